@@ -9,6 +9,9 @@
 //! - Support for query and update methods
 //! - Support for methods with and without arguments
 //! - Automatic type generation for Args and Response
+//! - Optional `_msgpack` twin stubs, so MessagePack endpoints appear in the .did file
+//! - Generation of many methods from a single list
+//! - Declaration of a canister's whole Candid surface with `candid_interface!`
 //!
 //! # Examples
 //! ```
@@ -19,12 +22,21 @@
 //!
 //! // Generate a method without arguments
 //! generate_candid_method_no_args!(my_canister, get_balance, query);
+//!
+//! // Generate a method and its `transfer_msgpack` twin
+//! generate_candid_method!(my_canister, transfer, update, msgpack);
 //! ```
 
 use proc_macro::TokenStream;
-use quote::{format_ident, quote};
+use proc_macro2::Span;
+use quote::{format_ident, quote, quote_spanned};
+use syn::parse::{Parse, ParseStream};
 use syn::punctuated::Punctuated;
-use syn::{parse_macro_input, Ident, Token};
+use syn::{bracketed, parse_macro_input, Ident, Token};
+
+const MSGPACK_FLAG: &str = "msgpack";
+const NO_ARGS_FLAG: &str = "no_args";
+const MSGPACK_SUFFIX: &str = "_msgpack";
 
 /// Represents the attributes needed to generate a Candid method.
 ///
@@ -37,6 +49,8 @@ struct MethodAttribute {
     method_name: String,
     /// The type of method ("query" or "update")
     method_type: String,
+    /// Whether to also emit the `<method_name>_msgpack` twin stub
+    msgpack: bool,
 }
 
 /// Generates a Candid method implementation with arguments.
@@ -46,10 +60,12 @@ struct MethodAttribute {
 /// appropriate Candid method attribute.
 ///
 /// # Arguments
-/// The macro takes three comma-separated identifiers:
+/// The macro takes three comma-separated identifiers, plus an optional flag:
 /// * `canister_name` - The name of the canister (without "_canister" suffix)
 /// * `method_name` - The name of the method to generate
 /// * `method_type` - The type of method ("query" or "update")
+/// * `msgpack` - (Optional) Also emit a `<method_name>_msgpack` stub taking and
+///   returning raw bytes
 ///
 /// # Returns
 /// A TokenStream containing the generated method implementation.
@@ -59,31 +75,21 @@ struct MethodAttribute {
 /// use bity_ic_candid_gen::generate_candid_method;
 ///
 /// generate_candid_method!(my_canister, transfer, update);
+/// generate_candid_method!(my_canister, transfer, update, msgpack);
 /// ```
 #[proc_macro]
 pub fn generate_candid_method(input: TokenStream) -> TokenStream {
-    let inputs = parse_macro_input!(input with Punctuated::<Ident, Token![,]>::parse_terminated)
-        .into_iter()
-        .map(|i| i.to_string())
-        .collect();
-
-    let attribute = get_method_attribute(inputs);
-
-    let canister_name = format_ident!("{}", attribute.canister_name);
-    let method_name = format_ident!("{}", attribute.method_name);
-    let method_type = format_ident!("{}", attribute.method_type);
-
-    let args_name = quote! { #canister_name::#method_name::Args };
-    let response_name = quote! { #canister_name::#method_name::Response };
+    let inputs: Vec<Ident> =
+        parse_macro_input!(input with Punctuated::<Ident, Token![,]>::parse_terminated)
+            .into_iter()
+            .collect();
 
-    let tokens = quote! {
-        #[candid::candid_method(#method_type)]
-        fn #method_name(_: #args_name) -> #response_name {
-            unimplemented!();
+    match get_method_attribute(&inputs) {
+        Ok(attribute) => {
+            TokenStream::from(expand_candid_method(&attribute, true, Span::call_site()))
         }
-    };
-
-    TokenStream::from(tokens)
+        Err(e) => e.to_compile_error().into(),
+    }
 }
 
 /// Generates a Candid method implementation without arguments.
@@ -93,10 +99,12 @@ pub fn generate_candid_method(input: TokenStream) -> TokenStream {
 /// appropriate Candid method attribute.
 ///
 /// # Arguments
-/// The macro takes three comma-separated identifiers:
+/// The macro takes three comma-separated identifiers, plus an optional flag:
 /// * `canister_name` - The name of the canister (without "_canister" suffix)
 /// * `method_name` - The name of the method to generate
 /// * `method_type` - The type of method ("query" or "update")
+/// * `msgpack` - (Optional) Also emit a `<method_name>_msgpack` stub taking and
+///   returning raw bytes
 ///
 /// # Returns
 /// A TokenStream containing the generated method implementation.
@@ -109,27 +117,337 @@ pub fn generate_candid_method(input: TokenStream) -> TokenStream {
 /// ```
 #[proc_macro]
 pub fn generate_candid_method_no_args(input: TokenStream) -> TokenStream {
-    let inputs = parse_macro_input!(input with Punctuated::<Ident, Token![,]>::parse_terminated)
-        .into_iter()
-        .map(|i| i.to_string())
-        .collect();
+    let inputs: Vec<Ident> =
+        parse_macro_input!(input with Punctuated::<Ident, Token![,]>::parse_terminated)
+            .into_iter()
+            .collect();
+
+    match get_method_attribute(&inputs) {
+        Ok(attribute) => {
+            TokenStream::from(expand_candid_method(&attribute, false, Span::call_site()))
+        }
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Generates Candid method implementations for a list of methods.
+///
+/// # Arguments
+/// The macro takes the canister name followed by a comma, then a list of
+/// `;`-terminated entries. Each entry is `method_name, method_type` optionally
+/// followed by the `msgpack` and/or `no_args` flags.
+///
+/// # Returns
+/// A TokenStream containing the generated method implementations.
+///
+/// # Example
+/// ```ignore
+/// use bity_ic_candid_gen::generate_candid_methods_from_list;
+///
+/// generate_candid_methods_from_list!(my_canister,
+///     transfer, update, msgpack;
+///     get_balance, query, no_args;
+/// );
+/// ```
+#[proc_macro]
+pub fn generate_candid_methods_from_list(input: TokenStream) -> TokenStream {
+    let list = parse_macro_input!(input as MethodList);
+
+    match expand_method_list(&list) {
+        Ok(methods) => TokenStream::from(methods),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// A list of methods of a canister, as taken by `generate_candid_methods_from_list!`.
+struct MethodList {
+    /// The name of the canister (without the "_canister" suffix)
+    canister_name: Ident,
+    /// One entry per method: the method name, its type and its flags
+    entries: Vec<Vec<Ident>>,
+}
+
+impl Parse for MethodList {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let canister_name: Ident = input.parse()?;
+        input.parse::<Token![,]>()?;
 
-    let attribute = get_method_attribute(inputs);
+        let mut entries = Vec::new();
+        while !input.is_empty() {
+            let mut entry = vec![input.parse::<Ident>()?];
+            while input.peek(Token![,]) {
+                input.parse::<Token![,]>()?;
+                entry.push(input.parse::<Ident>()?);
+            }
+            if !input.is_empty() {
+                input.parse::<Token![;]>()?;
+            }
+            entries.push(entry);
+        }
+
+        Ok(MethodList {
+            canister_name,
+            entries,
+        })
+    }
+}
+
+/// Expands every entry of a method list.
+///
+/// # Errors
+/// Returns an error on the first entry with a missing type, an unknown method
+/// type or an unknown flag.
+fn expand_method_list(list: &MethodList) -> syn::Result<proc_macro2::TokenStream> {
+    list.entries
+        .iter()
+        .map(|entry| {
+            if let Some(flag) = entry
+                .iter()
+                .skip(2)
+                .find(|flag| *flag != MSGPACK_FLAG && *flag != NO_ARGS_FLAG)
+            {
+                return Err(syn::Error::new_spanned(
+                    flag,
+                    format!(
+                        "unrecognised flag `{flag}`, expected `{MSGPACK_FLAG}` or `{NO_ARGS_FLAG}`"
+                    ),
+                ));
+            }
+            let mut inputs = vec![list.canister_name.clone()];
+            inputs.extend(entry.iter().filter(|e| *e != NO_ARGS_FLAG).cloned());
+            let with_args = !entry.iter().any(|e| e == NO_ARGS_FLAG);
+            Ok(expand_candid_method(
+                &get_method_attribute(&inputs)?,
+                with_args,
+                Span::call_site(),
+            ))
+        })
+        .collect()
+}
+
+/// Generates the Candid method stubs of every method of a canister.
+///
+/// Each listed method must have a module `<canister>_canister::<method>` defining
+/// the `Response` type, and the `Args` type unless it is listed as a no-arg method.
+/// A missing type is reported on the method name in the list.
+///
+/// # Arguments
+/// The macro takes `key = value` pairs separated by commas:
+/// * `canister` - The name of the canister (without "_canister" suffix), required
+/// * `queries`, `updates` - The methods taking `Args`, as a bracketed list
+/// * `composite_queries` - The composite query methods taking `Args`
+/// * `no_arg_queries`, `no_arg_updates` - The methods without arguments
+///
+/// A method listed twice in the same list is generated once. A method listed in
+/// two different lists is an error.
+///
+/// # Returns
+/// A TokenStream containing the generated method implementations.
+///
+/// # Example
+/// ```ignore
+/// use bity_ic_candid_gen::candid_interface;
+///
+/// candid_interface! {
+///     canister = my,
+///     queries = [get_fee, get_transactions],
+///     composite_queries = [find_block],
+///     updates = [transfer],
+///     no_arg_queries = [get_balance],
+/// }
+/// ```
+///
+/// The canister endpoints are then marked `hidden = true`, so that
+/// `export_candid!()` exports the declared interface only.
+#[proc_macro]
+pub fn candid_interface(input: TokenStream) -> TokenStream {
+    let interface = parse_macro_input!(input as InterfaceDecl);
+
+    TokenStream::from(expand_interface(&interface))
+}
+
+/// The kind of a method declared with `candid_interface!`, one per list key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MethodKind {
+    Query,
+    CompositeQuery,
+    Update,
+    NoArgQuery,
+    NoArgUpdate,
+}
+
+impl MethodKind {
+    const ALL: [MethodKind; 5] = [
+        MethodKind::Query,
+        MethodKind::CompositeQuery,
+        MethodKind::Update,
+        MethodKind::NoArgQuery,
+        MethodKind::NoArgUpdate,
+    ];
+
+    /// The key listing the methods of this kind.
+    fn key(self) -> &'static str {
+        match self {
+            MethodKind::Query => "queries",
+            MethodKind::CompositeQuery => "composite_queries",
+            MethodKind::Update => "updates",
+            MethodKind::NoArgQuery => "no_arg_queries",
+            MethodKind::NoArgUpdate => "no_arg_updates",
+        }
+    }
+
+    fn method_type(self) -> &'static str {
+        match self {
+            MethodKind::Query | MethodKind::NoArgQuery => "query",
+            MethodKind::CompositeQuery => "composite_query",
+            MethodKind::Update | MethodKind::NoArgUpdate => "update",
+        }
+    }
 
-    let canister_name = format_ident!("{}", attribute.canister_name);
-    let method_name = format_ident!("{}", attribute.method_name);
+    fn with_args(self) -> bool {
+        matches!(
+            self,
+            MethodKind::Query | MethodKind::CompositeQuery | MethodKind::Update
+        )
+    }
+}
+
+/// The methods of a canister, as taken by `candid_interface!`.
+struct InterfaceDecl {
+    /// The name of the canister (without the "_canister" suffix)
+    canister_name: Ident,
+    /// The methods in declaration order, without duplicates
+    methods: Vec<(Ident, MethodKind)>,
+}
+
+impl Parse for InterfaceDecl {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut canister_name: Option<Ident> = None;
+        let mut methods: Vec<(Ident, MethodKind)> = Vec::new();
+
+        while !input.is_empty() {
+            let key: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+
+            if key == "canister" {
+                if canister_name.is_some() {
+                    return Err(syn::Error::new(key.span(), "`canister` is given twice"));
+                }
+                canister_name = Some(input.parse()?);
+            } else {
+                let kind = MethodKind::ALL
+                    .into_iter()
+                    .find(|kind| key == kind.key())
+                    .ok_or_else(|| {
+                        syn::Error::new(
+                            key.span(),
+                            format!(
+                                "unknown key `{key}`, expected `canister`, `queries`, \
+                                 `composite_queries`, `updates`, `no_arg_queries` or \
+                                 `no_arg_updates`"
+                            ),
+                        )
+                    })?;
+
+                let content;
+                bracketed!(content in input);
+                for name in Punctuated::<Ident, Token![,]>::parse_terminated(&content)? {
+                    match methods.iter().find(|(listed, _)| *listed == name) {
+                        Some((_, listed_kind)) if *listed_kind == kind => {}
+                        Some((_, listed_kind)) => {
+                            return Err(syn::Error::new(
+                                name.span(),
+                                format!(
+                                    "method `{name}` is already listed in `{}`",
+                                    listed_kind.key()
+                                ),
+                            ));
+                        }
+                        None => methods.push((name, kind)),
+                    }
+                }
+            }
+
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        let canister_name = canister_name
+            .ok_or_else(|| syn::Error::new(Span::call_site(), "missing `canister = <name>`"))?;
+
+        Ok(InterfaceDecl {
+            canister_name,
+            methods,
+        })
+    }
+}
+
+/// Expands every method of an interface declaration.
+fn expand_interface(interface: &InterfaceDecl) -> proc_macro2::TokenStream {
+    interface
+        .methods
+        .iter()
+        .map(|(name, kind)| {
+            let attribute = MethodAttribute {
+                canister_name: format!("{}_canister", interface.canister_name),
+                method_name: name.to_string(),
+                method_type: kind.method_type().to_string(),
+                msgpack: false,
+            };
+            expand_candid_method(&attribute, kind.with_args(), name.span())
+        })
+        .collect()
+}
+
+/// Generates the Candid method stub, and its `_msgpack` twin if requested.
+///
+/// # Arguments
+/// * `attribute` - The method to generate
+/// * `with_args` - Whether the method takes the `Args` type as argument
+/// * `span` - Where errors about the `Args` and `Response` types are reported
+fn expand_candid_method(
+    attribute: &MethodAttribute,
+    with_args: bool,
+    span: Span,
+) -> proc_macro2::TokenStream {
+    let canister_name = Ident::new(&attribute.canister_name, span);
+    let method_name = Ident::new(&attribute.method_name, span);
     let method_type = format_ident!("{}", attribute.method_type);
 
-    let response_name = quote! { #canister_name::#method_name::Response };
+    let response_name = quote_spanned! {span=> #canister_name::#method_name::Response };
 
-    let tokens = quote! {
-        #[candid::candid_method(#method_type)]
-        fn #method_name() -> #response_name {
-            unimplemented!();
+    let method = if with_args {
+        let args_name = quote_spanned! {span=> #canister_name::#method_name::Args };
+        quote! {
+            #[candid::candid_method(#method_type)]
+            fn #method_name(_: #args_name) -> #response_name {
+                unimplemented!();
+            }
+        }
+    } else {
+        quote! {
+            #[candid::candid_method(#method_type)]
+            fn #method_name() -> #response_name {
+                unimplemented!();
+            }
         }
     };
 
-    TokenStream::from(tokens)
+    if !attribute.msgpack {
+        return method;
+    }
+
+    let msgpack_method_name = format_ident!("{}{}", attribute.method_name, MSGPACK_SUFFIX);
+
+    quote! {
+        #method
+
+        #[candid::candid_method(#method_type)]
+        fn #msgpack_method_name(_: Vec<u8>) -> Vec<u8> {
+            unimplemented!();
+        }
+    }
 }
 
 /// Extracts method attributes from the input tokens.
@@ -138,32 +456,184 @@ pub fn generate_candid_method_no_args(input: TokenStream) -> TokenStream {
 /// containing the canister name, method name, and method type.
 ///
 /// # Arguments
-/// * `inputs` - A vector of strings containing the input tokens
+/// * `inputs` - The canister name, method name, method type and optional flag
 ///
 /// # Returns
 /// A `MethodAttribute` struct with the processed information.
 ///
-/// # Panics
-/// Panics if:
-/// - The input vector has fewer than 3 elements
+/// # Errors
+/// Returns an error, reported on the offending token, if:
+/// - The method name or the method type is missing
 /// - The method type is not "query" or "update"
-fn get_method_attribute(inputs: Vec<String>) -> MethodAttribute {
-    let first_arg = inputs.get(0).unwrap();
-    let second_arg = inputs.get(1).unwrap();
-    let third_arg = inputs.get(2).unwrap();
+/// - The optional fourth element is not `msgpack`, or more elements are given
+fn get_method_attribute(inputs: &[Ident]) -> syn::Result<MethodAttribute> {
+    let [first_arg, second_arg, third_arg, ..] = inputs else {
+        let message = "expected `canister_name, method_name, method_type`";
+        return Err(match inputs.last() {
+            Some(last) => syn::Error::new_spanned(last, message),
+            None => syn::Error::new(Span::call_site(), message),
+        });
+    };
+
+    let msgpack = match inputs.get(3) {
+        None => false,
+        Some(flag) if flag == MSGPACK_FLAG => true,
+        Some(flag) => {
+            return Err(syn::Error::new_spanned(
+                flag,
+                format!("unrecognised flag `{flag}`, expected `{MSGPACK_FLAG}`"),
+            ))
+        }
+    };
+    if let Some(unexpected) = inputs.get(4) {
+        return Err(syn::Error::new_spanned(
+            unexpected,
+            format!("unexpected argument `{unexpected}`"),
+        ));
+    }
 
     let canister_name = format!("{first_arg}_canister");
 
     let method_name = second_arg.to_string();
 
-    let method_type = match third_arg.as_str() {
-        "query" | "update" => third_arg.to_string(),
-        _ => panic!("Unrecognised 'method_type' value: {third_arg}"),
+    let method_type = match third_arg.to_string().as_str() {
+        method_type @ ("query" | "update") => method_type.to_string(),
+        _ => {
+            return Err(syn::Error::new_spanned(
+                third_arg,
+                format!("unrecognised method type `{third_arg}`, expected `query` or `update`"),
+            ))
+        }
     };
 
-    MethodAttribute {
+    Ok(MethodAttribute {
         canister_name,
         method_name,
         method_type,
+        msgpack,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stub_names(tokens: proc_macro2::TokenStream) -> Vec<String> {
+        let file: syn::File = syn::parse2(tokens).unwrap();
+        file.items
+            .into_iter()
+            .filter_map(|item| match item {
+                syn::Item::Fn(f) => Some(f.sig.ident.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn idents(names: &[&str]) -> Vec<Ident> {
+        names
+            .iter()
+            .map(|name| Ident::new(name, Span::call_site()))
+            .collect()
+    }
+
+    #[test]
+    fn test_msgpack_flag_emits_twin_stub() {
+        let attribute =
+            get_method_attribute(&idents(&["my", "transfer", "update", "msgpack"])).unwrap();
+        let names = stub_names(expand_candid_method(&attribute, true, Span::call_site()));
+
+        assert_eq!(names, vec!["transfer", "transfer_msgpack"]);
+        assert!(names[1].ends_with(MSGPACK_SUFFIX));
+    }
+
+    #[test]
+    fn test_method_list_grammar() {
+        let list: MethodList = syn::parse_str(
+            "my,
+            transfer, update, msgpack;
+            get_balance, query, no_args;
+            get_fee, query",
+        )
+        .unwrap();
+        assert_eq!(list.canister_name, "my");
+        assert_eq!(list.entries.len(), 3);
+
+        let names = stub_names(expand_method_list(&list).unwrap());
+        assert_eq!(
+            names,
+            vec!["transfer", "transfer_msgpack", "get_balance", "get_fee"]
+        );
+        assert_eq!(
+            names.iter().filter(|n| n.ends_with(MSGPACK_SUFFIX)).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_unknown_flag_is_rejected() {
+        let error = get_method_attribute(&idents(&["my", "transfer", "update", "protobuf"]))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "unrecognised flag `protobuf`, expected `msgpack`"
+        );
+    }
+
+    #[test]
+    fn test_interface_deduplicates_methods() {
+        let interface: InterfaceDecl = syn::parse_str(
+            "canister = my,
+            queries = [get_fee, get_transactions, get_fee],
+            updates = [transfer,],
+            no_arg_queries = [get_balance],",
+        )
+        .unwrap();
+        assert_eq!(interface.canister_name, "my");
+
+        let names = stub_names(expand_interface(&interface));
+        assert_eq!(
+            names,
+            vec!["get_fee", "get_transactions", "transfer", "get_balance"]
+        );
+    }
+
+    #[test]
+    fn test_interface_composite_queries() {
+        let interface: InterfaceDecl =
+            syn::parse_str("canister = my, composite_queries = [find_block]").unwrap();
+
+        let tokens = expand_interface(&interface).to_string();
+        assert!(tokens.contains("candid_method (composite_query)"));
+        assert!(tokens.contains("find_block (_ : my_canister :: find_block :: Args)"));
+    }
+
+    #[test]
+    fn test_interface_rejects_method_in_two_lists() {
+        let error = syn::parse_str::<InterfaceDecl>(
+            "canister = my, queries = [transfer], updates = [transfer]",
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(
+            error.to_string(),
+            "method `transfer` is already listed in `queries`"
+        );
+    }
+
+    #[test]
+    fn test_interface_rejects_unknown_key() {
+        let error = syn::parse_str::<InterfaceDecl>("canister = my, oneway_updates = [x]")
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("unknown key `oneway_updates`"));
+
+        let error = syn::parse_str::<InterfaceDecl>("queries = [x]")
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "missing `canister = <name>`");
     }
 }
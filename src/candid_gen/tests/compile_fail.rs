@@ -0,0 +1,11 @@
+#[test]
+fn candid_interface_compile_errors() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
+
+#[test]
+fn msgpack_stubs_compile() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/pass/*.rs");
+}
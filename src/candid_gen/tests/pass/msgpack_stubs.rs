@@ -0,0 +1,23 @@
+// The `msgpack` flag adds a `<method>_msgpack` stub taking and returning bytes.
+mod my_canister {
+    pub mod transfer {
+        pub type Args = u64;
+        pub type Response = u64;
+    }
+
+    pub mod get_balance {
+        pub type Response = u64;
+    }
+}
+
+bity_ic_candid_gen::generate_candid_methods_from_list!(my,
+    transfer, update, msgpack;
+    get_balance, query, no_args, msgpack;
+);
+
+fn main() {
+    let _: fn(my_canister::transfer::Args) -> my_canister::transfer::Response = transfer;
+    let _: fn(Vec<u8>) -> Vec<u8> = transfer_msgpack;
+    let _: fn() -> my_canister::get_balance::Response = get_balance;
+    let _: fn(Vec<u8>) -> Vec<u8> = get_balance_msgpack;
+}
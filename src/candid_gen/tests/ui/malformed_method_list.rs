@@ -0,0 +1,13 @@
+// An entry of a method list missing the comma before its flag.
+mod my_canister {
+    pub mod transfer {
+        pub type Args = u64;
+        pub type Response = u64;
+    }
+}
+
+bity_ic_candid_gen::generate_candid_methods_from_list!(my,
+    transfer, update msgpack;
+);
+
+fn main() {}
@@ -0,0 +1,15 @@
+// A method listed both as a query and as an update.
+mod my_canister {
+    pub mod transfer {
+        pub type Args = u64;
+        pub type Response = u64;
+    }
+}
+
+bity_ic_candid_gen::candid_interface! {
+    canister = my,
+    queries = [transfer],
+    updates = [transfer],
+}
+
+fn main() {}
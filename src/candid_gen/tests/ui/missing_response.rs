@@ -0,0 +1,10 @@
+// A listed method whose module has no `Response` type.
+mod my_canister {
+    pub mod transfer {
+        pub type Args = u64;
+    }
+}
+
+bity_ic_candid_gen::candid_interface! { canister = my, updates = [transfer] }
+
+fn main() {}
@@ -0,0 +1,14 @@
+// A list under a key that is not a method kind.
+mod my_canister {
+    pub mod transfer {
+        pub type Args = u64;
+        pub type Response = u64;
+    }
+}
+
+bity_ic_candid_gen::candid_interface! {
+    canister = my,
+    oneway_updates = [transfer],
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+// A flag other than `msgpack` or `no_args` in a method list.
+mod my_canister {
+    pub mod transfer {
+        pub type Args = u64;
+        pub type Response = u64;
+    }
+}
+
+bity_ic_candid_gen::generate_candid_methods_from_list!(my,
+    transfer, update, protobuf;
+);
+
+fn main() {}
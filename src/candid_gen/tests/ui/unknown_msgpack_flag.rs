@@ -0,0 +1,11 @@
+// A flag other than `msgpack` after the method type.
+mod my_canister {
+    pub mod transfer {
+        pub type Args = u64;
+        pub type Response = u64;
+    }
+}
+
+bity_ic_candid_gen::generate_candid_method!(my, transfer, update, msgpak);
+
+fn main() {}
@@ -22,8 +22,10 @@
 //! - Update call generation with Candid serialization
 //! - Query call generation with Candid serialization
 //! - Cross-canister call generation with both Candid and MessagePack serialization
+//! - Cross-canister calls retried on a payload serialized once
 //! - Support for calls with and without arguments
 //! - Support for calls with cycle payments
+//! - Canister-side `_msgpack` endpoints mirroring Candid ones
 
 pub extern crate anyhow;
 // pub extern crate bity_ic_types;
@@ -163,6 +165,7 @@ macro_rules! generate_c2c_call {
                 |r| ::bity_ic_canister_client::msgpack::deserialize(r),
             )
             .await
+            .map_err(Into::into)
         }
     };
 }
@@ -213,6 +216,52 @@ macro_rules! generate_candid_c2c_call {
     };
 }
 
+/// Generates a function for making cross-canister calls using Candid serialization,
+/// retried according to a [`RetryPolicy`](crate::RetryPolicy).
+///
+/// The arguments are serialized once and every attempt sends the same payload, see
+/// [`make_c2c_call_with_retry`](crate::make_c2c_call_with_retry).
+///
+/// # Arguments
+/// * `function_name` - The name of the function to generate
+/// * `method_name` - The name of the method on the target canister, and of its API module
+///
+/// # Returns
+/// A function that takes a canister ID, arguments, and a retry policy, and returns a
+/// Result with the decoded response of the first successful attempt.
+///
+/// # Example
+/// ```
+/// use bity_ic_canister_client::generate_candid_c2c_call_with_retry;
+///
+/// generate_candid_c2c_call_with_retry!(transfer_with_retry, transfer);
+/// ```
+#[macro_export]
+macro_rules! generate_candid_c2c_call_with_retry {
+    ($function_name:ident, $method_name:ident) => {
+        pub async fn $function_name<A>(
+            canister_id: ::bity_ic_canister_client::canister_client_macros::bity_ic_types::CanisterId,
+            args: A,
+            policy: ::bity_ic_canister_client::RetryPolicy,
+        ) -> ::bity_ic_canister_client::Result<$method_name::Response>
+        where
+            A: std::borrow::Borrow<$method_name::Args>,
+        {
+            let method_name = stringify!($method_name);
+
+            ::bity_ic_canister_client::make_c2c_call_with_retry(
+                canister_id,
+                method_name,
+                args.borrow(),
+                ::bity_ic_canister_client::canister_client_macros::candid::encode_one,
+                |r| ::bity_ic_canister_client::canister_client_macros::candid::decode_one(r),
+                policy,
+            )
+            .await
+        }
+    };
+}
+
 /// Generates a function for making cross-canister calls with cycle payment.
 ///
 /// This macro creates an async function that handles cross-canister calls with
@@ -341,3 +390,54 @@ macro_rules! generate_candid_c2c_call_no_args {
         }
     };
 }
+
+/// Exposes a MessagePack variant of a canister endpoint.
+///
+/// This macro creates a hidden `update` or `query` method whose arguments and response
+/// are MessagePack encoded, following the `_msgpack` convention targeted by
+/// [`generate_c2c_call!`]. The method is excluded from the Candid interface and
+/// delegates to an existing handler, so the Candid and MessagePack endpoints share
+/// the same implementation.
+///
+/// The canister must depend on `bity-ic-canister-client` and `ic-cdk`.
+///
+/// # Arguments
+/// * `kind` - Either `update` or `query`
+/// * `endpoint_name` - The name of the exposed method, usually `<method>_msgpack`
+/// * `args` - The type of the arguments
+/// * `response` - The type of the response
+/// * `handler` - The function implementing the endpoint
+///
+/// # Example
+/// ```ignore
+/// use bity_ic_canister_client::expose_msgpack_endpoint;
+///
+/// fn transfer_impl(args: TransferArgs) -> TransferResponse {
+///     // ...
+/// }
+///
+/// expose_msgpack_endpoint!(update, transfer_msgpack, TransferArgs, TransferResponse, transfer_impl);
+/// ```
+#[macro_export]
+macro_rules! expose_msgpack_endpoint {
+    (update, $endpoint_name:ident, $args:ty, $response:ty, $handler:path) => {
+        #[::ic_cdk::update(
+            hidden = true,
+            decode_with = "::bity_ic_canister_client::msgpack::decode_args",
+            encode_with = "::bity_ic_canister_client::msgpack::encode_response"
+        )]
+        fn $endpoint_name(args: $args) -> $response {
+            $handler(args)
+        }
+    };
+    (query, $endpoint_name:ident, $args:ty, $response:ty, $handler:path) => {
+        #[::ic_cdk::query(
+            hidden = true,
+            decode_with = "::bity_ic_canister_client::msgpack::decode_args",
+            encode_with = "::bity_ic_canister_client::msgpack::encode_response"
+        )]
+        fn $endpoint_name(args: $args) -> $response {
+            $handler(args)
+        }
+    };
+}
@@ -0,0 +1,284 @@
+//! Coalescing of concurrent identical cross-canister queries.
+//!
+//! A composite flow may issue the same read call several times at once, e.g.
+//! `icrc3_get_blocks` on one archive with the same arguments. [`CoalescingClient`]
+//! makes a single call for all of them: concurrent calls to the same method of the
+//! same canister with the same encoded arguments share one in-flight future, and
+//! each caller receives a clone of its response.
+//!
+//! Only the methods declared as queries when creating the client are coalesced,
+//! other calls are made as is. The entry of a call is dropped as soon as it
+//! resolves, so responses are never cached: a call made afterwards is made again.
+
+use candid::Principal;
+use futures::future::{FutureExt, LocalBoxFuture, Shared};
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CallKey {
+    canister_id: Principal,
+    method_name: String,
+    args: Vec<u8>,
+}
+
+type SharedCall<R> = Shared<LocalBoxFuture<'static, R>>;
+
+/// Shares the in-flight calls of query methods among concurrent identical callers.
+///
+/// # Example
+/// ```ignore
+/// use bity_ic_canister_client::{fan_out_calls, CoalescingClient};
+///
+/// async fn get_blocks(
+///     archives: Vec<Principal>,
+///     args: GetBlocksArgs,
+/// ) -> Vec<(Principal, CallResult<GetBlocksResult>)> {
+///     let client = CoalescingClient::new(["icrc3_get_blocks"]);
+///     let encoded = candid::encode_one(&args).unwrap();
+///     fan_out_calls(archives, 5, |canister_id| {
+///         let (client, encoded, args) = (&client, &encoded, args.clone());
+///         async move {
+///             client
+///                 .call(canister_id, "icrc3_get_blocks", encoded, || {
+///                     icrc3_get_blocks(canister_id, args)
+///                 })
+///                 .await
+///         }
+///     })
+///     .await
+/// }
+/// ```
+#[derive(Default)]
+pub struct CoalescingClient {
+    query_methods: HashSet<String>,
+    in_flight: RefCell<HashMap<CallKey, Box<dyn Any>>>,
+}
+
+impl CoalescingClient {
+    /// Creates a client coalescing the calls to `query_methods`.
+    ///
+    /// Only methods without side effects should be listed: a coalesced caller
+    /// does not make its own call.
+    pub fn new<I, S>(query_methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            query_methods: query_methods.into_iter().map(Into::into).collect(),
+            in_flight: RefCell::default(),
+        }
+    }
+
+    /// Returns whether the calls to `method_name` are coalesced.
+    pub fn is_coalesced(&self, method_name: &str) -> bool {
+        self.query_methods.contains(method_name)
+    }
+
+    /// Returns the number of distinct calls currently in flight.
+    pub fn in_flight_count(&self) -> usize {
+        self.in_flight.borrow().len()
+    }
+
+    /// Makes a call, or joins the identical call already in flight.
+    ///
+    /// # Arguments
+    /// * `canister_id` - The canister called
+    /// * `method_name` - The method called, coalesced if declared as a query
+    /// * `args` - The encoded arguments of the call
+    /// * `call` - Makes the call, only invoked when no identical call is in flight
+    ///
+    /// # Returns
+    /// The response of the call, cloned for each caller sharing it.
+    pub async fn call<R, F, Fut>(
+        &self,
+        canister_id: Principal,
+        method_name: &str,
+        args: &[u8],
+        call: F,
+    ) -> R
+    where
+        R: Clone + 'static,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = R> + 'static,
+    {
+        if !self.is_coalesced(method_name) {
+            return call().await;
+        }
+
+        let key = CallKey {
+            canister_id,
+            method_name: method_name.to_string(),
+            args: args.to_vec(),
+        };
+
+        let in_flight = self
+            .in_flight
+            .borrow()
+            .get(&key)
+            .and_then(|shared| shared.downcast_ref::<SharedCall<R>>())
+            .cloned();
+        let shared = match in_flight {
+            Some(shared) => shared,
+            None if self.in_flight.borrow().contains_key(&key) => {
+                // The same call is in flight with another response type.
+                return call().await;
+            }
+            None => {
+                tracing::trace!(method_name, %canister_id, "Starting coalesced c2c call");
+                let shared = call().boxed_local().shared();
+                self.in_flight
+                    .borrow_mut()
+                    .insert(key.clone(), Box::new(shared.clone()));
+                shared
+            }
+        };
+
+        let response = shared.clone().await;
+
+        // The first caller to resolve drops the entry, unless it was replaced
+        // by a later call in the meantime.
+        let mut in_flight = self.in_flight.borrow_mut();
+        let is_same_call = in_flight
+            .get(&key)
+            .and_then(|entry| entry.downcast_ref::<SharedCall<R>>())
+            .is_some_and(|entry| entry.ptr_eq(&shared));
+        if is_same_call {
+            in_flight.remove(&key);
+        }
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fan_out_calls;
+    use futures::channel::oneshot;
+    use futures::executor::block_on;
+    use futures::future::join_all;
+    use std::cell::Cell;
+
+    type Gate = Shared<oneshot::Receiver<()>>;
+
+    /// A stub call counting how many times it was made, pending until the gate opens.
+    fn stub_call(calls: &Cell<usize>, args: &[u8], gate: &Gate) -> impl Future<Output = Vec<u8>> {
+        calls.set(calls.get() + 1);
+        let response = args.iter().rev().copied().collect();
+        let gate = gate.clone();
+        async move {
+            gate.await.unwrap();
+            response
+        }
+    }
+
+    /// Runs `calls` and opens the gate once they are all waiting on it.
+    fn run_gated<T>(calls: impl Future<Output = T>, open: oneshot::Sender<()>) -> T {
+        block_on(async {
+            let (output, _) = futures::join!(calls, async { open.send(()).unwrap() });
+            output
+        })
+    }
+
+    fn gate() -> (oneshot::Sender<()>, Gate) {
+        let (open, gate) = oneshot::channel();
+        (open, gate.shared())
+    }
+
+    fn archive() -> Principal {
+        Principal::from_slice(&[1])
+    }
+
+    #[test]
+    fn test_concurrent_identical_calls_are_coalesced() {
+        let client = CoalescingClient::new(["icrc3_get_blocks"]);
+        let calls = Cell::new(0);
+        let (open, gate) = gate();
+
+        let responses = run_gated(
+            join_all((0..10).map(|_| {
+                client.call(archive(), "icrc3_get_blocks", &[1, 2], || {
+                    stub_call(&calls, &[1, 2], &gate)
+                })
+            })),
+            open,
+        );
+
+        assert_eq!(calls.get(), 1);
+        assert_eq!(responses, vec![vec![2, 1]; 10]);
+        assert_eq!(client.in_flight_count(), 0);
+
+        // Nothing is cached once the call resolved.
+        block_on(client.call(archive(), "icrc3_get_blocks", &[1, 2], || {
+            stub_call(&calls, &[1, 2], &gate)
+        }));
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn test_different_calls_are_not_coalesced() {
+        let client = CoalescingClient::new(["icrc3_get_blocks"]);
+        let calls = Cell::new(0);
+        let (open, gate) = gate();
+
+        let (client, calls_ref, gate) = (&client, &calls, &gate);
+        let responses = run_gated(
+            join_all(
+                [
+                    (archive(), "icrc3_get_blocks", vec![1]),
+                    (archive(), "icrc3_get_blocks", vec![2]),
+                    (Principal::from_slice(&[2]), "icrc3_get_blocks", vec![1]),
+                    (archive(), "insert_blocks", vec![1]),
+                    (archive(), "insert_blocks", vec![1]),
+                ]
+                .into_iter()
+                .map(|(canister_id, method_name, args)| async move {
+                    client
+                        .call(canister_id, method_name, &args, || {
+                            stub_call(calls_ref, &args, gate)
+                        })
+                        .await
+                }),
+            ),
+            open,
+        );
+
+        assert_eq!(calls.get(), 5);
+        assert_eq!(responses, vec![vec![1], vec![2], vec![1], vec![1], vec![1]]);
+    }
+
+    #[test]
+    fn test_fan_out_with_duplicate_targets() {
+        let client = CoalescingClient::new(["icrc3_get_blocks"]);
+        let calls = Cell::new(0);
+        let (open, gate) = gate();
+        let targets = vec![archive(), Principal::from_slice(&[2]), archive(), archive()];
+
+        let results = run_gated(
+            fan_out_calls(targets.clone(), 4, |canister_id| {
+                let (client, calls, gate) = (&client, &calls, &gate);
+                async move {
+                    let args = canister_id.as_slice().to_vec();
+                    Ok::<_, String>(
+                        client
+                            .call(canister_id, "icrc3_get_blocks", &args, || {
+                                stub_call(calls, &args, gate)
+                            })
+                            .await,
+                    )
+                }
+            }),
+            open,
+        );
+
+        assert_eq!(calls.get(), 2);
+        for (target, (canister_id, response)) in targets.iter().zip(results) {
+            assert_eq!(canister_id, *target);
+            assert_eq!(response, Ok(target.as_slice().to_vec()));
+        }
+    }
+}
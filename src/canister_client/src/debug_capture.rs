@@ -0,0 +1,181 @@
+//! Capture of the responses that failed to deserialize.
+//!
+//! A c2c response that cannot be decoded only surfaces as a
+//! `Deserialization error: ...`, which says nothing about the bytes that were
+//! received, e.g. when two canister versions disagree on a candid or msgpack
+//! type. When enabled with [`set_c2c_debug_capture`], [`make_c2c_call`](crate::make_c2c_call)
+//! and [`make_c2c_call_with_payment`](crate::make_c2c_call_with_payment) keep the
+//! last [`C2C_DEBUG_CAPTURE_CAPACITY`] such failures along with the first
+//! [`C2C_DEBUG_RESPONSE_BYTES`] bytes of their response, see [`c2c_debug_records`].
+//!
+//! The capture is off by default, and the responses that were decoded are never kept.
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt::Debug;
+
+/// The number of failed calls kept, the oldest one is dropped first.
+pub const C2C_DEBUG_CAPTURE_CAPACITY: usize = 20;
+/// The number of response bytes kept for each failed call.
+pub const C2C_DEBUG_RESPONSE_BYTES: usize = 256;
+
+/// A call whose response failed to deserialize.
+///
+/// # Fields
+///
+/// * `method_name` - The method called
+/// * `canister_id` - The canister called
+/// * `arg_bytes_len` - The size of the serialized arguments, in bytes
+/// * `response_bytes_len` - The size of the response, in bytes
+/// * `response_hex` - The hex dump of the first [`C2C_DEBUG_RESPONSE_BYTES`] bytes of the response
+/// * `error` - The deserialization error
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct C2cDebugRecord {
+    pub method_name: String,
+    pub canister_id: Principal,
+    pub arg_bytes_len: u64,
+    pub response_bytes_len: u64,
+    pub response_hex: String,
+    pub error: String,
+}
+
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+    static RECORDS: RefCell<VecDeque<C2cDebugRecord>> = RefCell::default();
+}
+
+/// Starts or stops capturing the responses that fail to deserialize.
+///
+/// Stopping the capture drops the records kept so far.
+pub fn set_c2c_debug_capture(enabled: bool) {
+    ENABLED.with(|e| e.set(enabled));
+    if !enabled {
+        RECORDS.with(|records| records.borrow_mut().clear());
+    }
+}
+
+/// Returns whether the responses that fail to deserialize are captured.
+pub fn c2c_debug_capture_enabled() -> bool {
+    ENABLED.with(|e| e.get())
+}
+
+/// Returns the captured failures, oldest first.
+pub fn c2c_debug_records() -> Vec<C2cDebugRecord> {
+    RECORDS.with(|records| records.borrow().iter().cloned().collect())
+}
+
+/// Deserializes the response of a call, capturing it if that fails and the
+/// capture is enabled.
+pub(crate) fn deserialize_response<R, D, DError: Debug>(
+    canister_id: Principal,
+    method_name: &str,
+    arg_bytes_len: usize,
+    response_bytes: &[u8],
+    deserializer: D,
+) -> anyhow::Result<R>
+where
+    D: Fn(&[u8]) -> Result<R, DError>,
+{
+    deserializer(response_bytes).map_err(|e| {
+        let error = format!("{:?}", e);
+        if c2c_debug_capture_enabled() {
+            record(C2cDebugRecord {
+                method_name: method_name.to_string(),
+                canister_id,
+                arg_bytes_len: arg_bytes_len as u64,
+                response_bytes_len: response_bytes.len() as u64,
+                response_hex: hex_dump(response_bytes),
+                error: error.clone(),
+            });
+        }
+        anyhow::anyhow!("Deserialization error: {}", error)
+    })
+}
+
+fn record(record: C2cDebugRecord) {
+    RECORDS.with(|records| {
+        let mut records = records.borrow_mut();
+        if records.len() >= C2C_DEBUG_CAPTURE_CAPACITY {
+            records.pop_front();
+        }
+        records.push_back(record);
+    });
+}
+
+/// Returns the hex dump of the first [`C2C_DEBUG_RESPONSE_BYTES`] bytes.
+fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take(C2C_DEBUG_RESPONSE_BYTES)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> Result<u8, String> {
+        match bytes {
+            [byte] => Ok(*byte),
+            _ => Err(format!("expected 1 byte, got {}", bytes.len())),
+        }
+    }
+
+    fn call(response: &[u8]) -> anyhow::Result<u8> {
+        deserialize_response(Principal::anonymous(), "get_blocks", 3, response, decode)
+    }
+
+    #[test]
+    fn test_only_failures_are_captured_when_enabled() {
+        assert!(!c2c_debug_capture_enabled());
+        assert!(call(&[1, 2]).is_err());
+        assert!(c2c_debug_records().is_empty());
+
+        set_c2c_debug_capture(true);
+        assert_eq!(call(&[7]).unwrap(), 7);
+        assert!(c2c_debug_records().is_empty());
+
+        let error = call(&[0xab, 0xcd]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Deserialization error: \"expected 1 byte, got 2\""
+        );
+        assert_eq!(
+            c2c_debug_records(),
+            vec![C2cDebugRecord {
+                method_name: "get_blocks".to_string(),
+                canister_id: Principal::anonymous(),
+                arg_bytes_len: 3,
+                response_bytes_len: 2,
+                response_hex: "abcd".to_string(),
+                error: "\"expected 1 byte, got 2\"".to_string(),
+            }]
+        );
+
+        set_c2c_debug_capture(false);
+        assert!(c2c_debug_records().is_empty());
+    }
+
+    #[test]
+    fn test_records_are_bounded() {
+        set_c2c_debug_capture(true);
+        for i in 0..C2C_DEBUG_CAPTURE_CAPACITY + 5 {
+            let response = vec![i as u8; C2C_DEBUG_RESPONSE_BYTES + 10];
+            assert!(call(&response).is_err());
+        }
+
+        let records = c2c_debug_records();
+        assert_eq!(records.len(), C2C_DEBUG_CAPTURE_CAPACITY);
+        assert_eq!(
+            records[0].response_hex,
+            "05".repeat(C2C_DEBUG_RESPONSE_BYTES)
+        );
+        assert!(records.iter().all(|r| r.response_bytes_len
+            == (C2C_DEBUG_RESPONSE_BYTES + 10) as u64
+            && r.response_hex.len() == 2 * C2C_DEBUG_RESPONSE_BYTES));
+        set_c2c_debug_capture(false);
+    }
+}
@@ -0,0 +1,80 @@
+//! Errors of raw cross-canister calls.
+
+use ic_cdk::call::{CallErrorExt, CallFailed, CandidDecodeFailed};
+use std::fmt;
+
+/// Error returned by [`make_c2c_call_raw`](crate::make_c2c_call_raw).
+#[derive(Debug)]
+pub enum C2cError {
+    /// The payload exceeds the size limit, the call was not attempted
+    PayloadTooLarge { size: usize, limit: usize },
+    /// The call was made and failed
+    CallFailed(CallFailed),
+    /// The response could not be decoded
+    DecodeFailed(CandidDecodeFailed),
+    /// The call is not available outside of update calls, it was not attempted
+    NotInUpdate,
+}
+
+impl C2cError {
+    /// Returns whether the call may succeed if it is retried right away.
+    ///
+    /// Only `SYS_TRANSIENT` and `SYS_UNKNOWN` rejects are retryable: the other
+    /// errors would fail again, and a payload that is too large never succeeds.
+    pub fn is_immediately_retryable(&self) -> bool {
+        match self {
+            C2cError::CallFailed(e) => e.is_immediately_retryable(),
+            C2cError::DecodeFailed(e) => e.is_immediately_retryable(),
+            C2cError::PayloadTooLarge { .. } | C2cError::NotInUpdate => false,
+        }
+    }
+
+    /// Returns whether the call is known not to have been executed by the target.
+    ///
+    /// A `SYS_UNKNOWN` reject is not clean: the call may have been executed, so
+    /// retrying it is only safe if it is idempotent. A call that was not attempted
+    /// is clean.
+    pub fn is_clean_reject(&self) -> bool {
+        match self {
+            C2cError::CallFailed(e) => e.is_clean_reject(),
+            C2cError::DecodeFailed(e) => e.is_clean_reject(),
+            C2cError::PayloadTooLarge { .. } | C2cError::NotInUpdate => true,
+        }
+    }
+}
+
+impl fmt::Display for C2cError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            C2cError::PayloadTooLarge { size, limit } => write!(
+                f,
+                "payload of {size} bytes exceeds the limit of {limit} bytes"
+            ),
+            C2cError::CallFailed(e) => write!(f, "{e}"),
+            C2cError::DecodeFailed(e) => write!(f, "{e}"),
+            C2cError::NotInUpdate => write!(f, "the call can only be made from an update call"),
+        }
+    }
+}
+
+impl std::error::Error for C2cError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            C2cError::PayloadTooLarge { .. } | C2cError::NotInUpdate => None,
+            C2cError::CallFailed(e) => Some(e),
+            C2cError::DecodeFailed(e) => Some(e),
+        }
+    }
+}
+
+impl From<CallFailed> for C2cError {
+    fn from(error: CallFailed) -> Self {
+        C2cError::CallFailed(error)
+    }
+}
+
+impl From<CandidDecodeFailed> for C2cError {
+    fn from(error: CandidDecodeFailed) -> Self {
+        C2cError::DecodeFailed(error)
+    }
+}
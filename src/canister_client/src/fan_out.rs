@@ -0,0 +1,118 @@
+//! Concurrency-limited fan-out of cross-canister calls.
+//!
+//! Calling dozens of canisters with a naive `join_all` can exhaust the
+//! per-canister output queue. [`fan_out_calls`] keeps at most
+//! `max_concurrency` calls in flight at any time, collects every result
+//! (individual failures never abort the batch) and returns them in the
+//! same order as the input targets.
+//!
+//! When several targets may receive the same query, the calls can go through a
+//! [`CoalescingClient`](crate::CoalescingClient) so that each is only made once.
+
+use candid::Principal;
+use futures::stream::{self, StreamExt};
+use std::future::Future;
+
+/// Calls `f` for every target, driving at most `max_concurrency` futures at a time.
+///
+/// # Arguments
+/// * `targets` - The canisters to call
+/// * `max_concurrency` - Maximum number of calls in flight (a value of 0 is treated as 1)
+/// * `f` - Builds the call future for a given canister
+///
+/// # Returns
+/// One `(Principal, Result)` pair per target, in input order.
+///
+/// # Example
+/// ```ignore
+/// use bity_ic_canister_client::fan_out_calls;
+///
+/// async fn total_transactions(archives: Vec<Principal>) -> Vec<(Principal, anyhow::Result<u64>)> {
+///     fan_out_calls(archives, 5, |canister_id| async move {
+///         total_transactions_c2c(canister_id).await
+///     })
+///     .await
+/// }
+/// ```
+pub async fn fan_out_calls<I, F, Fut, R, E>(
+    targets: I,
+    max_concurrency: usize,
+    f: F,
+) -> Vec<(Principal, Result<R, E>)>
+where
+    I: IntoIterator<Item = Principal>,
+    F: Fn(Principal) -> Fut,
+    Fut: Future<Output = Result<R, E>>,
+{
+    stream::iter(targets)
+        .map(|canister_id| {
+            let call = f(canister_id);
+            async move { (canister_id, call.await) }
+        })
+        .buffered(max_concurrency.max(1))
+        .collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::cell::Cell;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// Future that stays pending for a number of polls before resolving.
+    struct YieldN(u8);
+
+    impl Future for YieldN {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 == 0 {
+                return Poll::Ready(());
+            }
+            self.0 -= 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    fn principal(id: u8) -> Principal {
+        Principal::from_slice(&[id])
+    }
+
+    #[test]
+    fn test_fan_out_preserves_order_and_caps_concurrency() {
+        let in_flight = Cell::new(0usize);
+        let max_seen = Cell::new(0usize);
+        let targets: Vec<Principal> = (0..10).map(principal).collect();
+
+        let results = block_on(fan_out_calls(targets.clone(), 3, |canister_id| {
+            let (in_flight, max_seen) = (&in_flight, &max_seen);
+            async move {
+                in_flight.set(in_flight.get() + 1);
+                max_seen.set(max_seen.get().max(in_flight.get()));
+                // Later targets finish first to check that ordering is preserved.
+                let id = canister_id.as_slice()[0];
+                YieldN(10 - id).await;
+                in_flight.set(in_flight.get() - 1);
+                if id % 4 == 0 {
+                    Err(anyhow::anyhow!("call to {} failed", id))
+                } else {
+                    Ok(id)
+                }
+            }
+        }));
+
+        assert_eq!(max_seen.get(), 3);
+        assert_eq!(results.len(), 10);
+        for (i, (canister_id, result)) in results.iter().enumerate() {
+            assert_eq!(*canister_id, targets[i]);
+            match result {
+                Ok(id) => assert_eq!(*id as usize, i),
+                Err(_) => assert_eq!(i % 4, 0),
+            }
+        }
+    }
+}
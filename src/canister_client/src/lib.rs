@@ -7,7 +7,14 @@
 //! - Cross-canister calls with custom serialization/deserialization
 //! - Support for cycle payments in C2C calls
 //! - Raw C2C call functionality with detailed error handling
+//! - Retries of transient failures, reusing the arguments serialized once
+//! - Payload size guards rejecting oversized calls before they are made
 //! - Integration with tracing for debugging and monitoring
+//! - Concurrency-limited fan-out of calls to many canisters
+//! - Coalescing of concurrent identical query calls
+//! - MessagePack encoding helpers for `_msgpack` endpoints
+//! - Pooled random bytes from the management canister's `raw_rand`
+//! - Opt-in capture of the responses that fail to deserialize
 //!
 //! # Examples
 //! ```
@@ -31,10 +38,32 @@
 
 pub use anyhow::{Context, Result};
 use candid::Principal;
-use ic_cdk::call::CallFailed;
 use std::fmt::Debug;
 
 pub mod canister_client_macros;
+pub mod coalesce;
+pub mod debug_capture;
+pub mod error;
+pub mod fan_out;
+pub mod msgpack;
+pub mod payload;
+pub mod random;
+pub mod retry;
+
+pub use bity_ic_types;
+pub use coalesce::CoalescingClient;
+pub use debug_capture::{
+    c2c_debug_capture_enabled, c2c_debug_records, set_c2c_debug_capture, C2cDebugRecord,
+};
+pub use error::C2cError;
+pub use fan_out::fan_out_calls;
+pub use payload::{
+    payload_limits, set_payload_limits, PayloadLimits, C2C_PAYLOAD_WARNING_BYTES,
+    C2C_RESPONSE_WARNING_BYTES, MAX_C2C_PAYLOAD_BYTES,
+};
+pub use random::{get_random_bytes, get_random_u64, random_pool_stats, set_test_seed};
+pub use retry::{make_c2c_call_with_retry, retry_c2c_call, RetryPolicy, SerializedArgs};
+
 /// Makes a cross-canister call with custom serialization and deserialization.
 ///
 /// This function handles the complete flow of a cross-canister call, including:
@@ -42,6 +71,10 @@ pub mod canister_client_macros;
 /// - Making the actual call
 /// - Deserialization of the response
 ///
+/// The serializer is called exactly once, so `args` can be a reference or a value
+/// that is not `Clone`. See [`make_c2c_call_with_retry`] to retry transient failures
+/// with the same serialized payload.
+///
 /// # Type Parameters
 /// * `A` - The type of the arguments
 /// * `R` - The type of the response
@@ -51,14 +84,15 @@ pub mod canister_client_macros;
 /// * `DError` - The error type of the deserializer
 ///
 /// # Arguments
-/// * `canister_id` - The ID of the target canister
+/// * `canister_id` - The ID of the target canister, a `Principal` or a `CanisterIdStrict`
 /// * `method_name` - The name of the method to call
 /// * `args` - The arguments to pass to the method
 /// * `serializer` - Function to serialize the arguments
 /// * `deserializer` - Function to deserialize the response
 ///
 /// # Returns
-/// A `CallResult` containing either the deserialized response or an error.
+/// A `CallResult` containing either the deserialized response or an error. A
+/// response that fails to deserialize is kept when [`set_c2c_debug_capture`] is enabled.
 ///
 /// # Example
 /// ```
@@ -77,30 +111,37 @@ pub mod canister_client_macros;
 /// }
 /// ```
 pub async fn make_c2c_call<A, R, S, D, SError: Debug, DError: Debug>(
-    canister_id: Principal,
+    canister_id: impl Into<Principal>,
     method_name: &str,
     args: A,
     serializer: S,
     deserializer: D,
 ) -> Result<R>
 where
-    S: Fn(A) -> Result<Vec<u8>, SError>,
+    S: FnOnce(A) -> Result<Vec<u8>, SError>,
     D: Fn(&[u8]) -> Result<R, DError>,
 {
-    let payload_bytes =
-        serializer(args).map_err(|e| anyhow::anyhow!("Serialization error: {:?}", e))?;
+    let canister_id = canister_id.into();
+    let payload = SerializedArgs::new(args, serializer)?;
 
-    let response_bytes = make_c2c_call_raw(canister_id, method_name, &payload_bytes, 0, None)
+    let response_bytes = make_c2c_call_raw(canister_id, method_name, payload.as_bytes(), 0, None)
         .await
         .context("Cross-canister call failed")?;
 
-    deserializer(&response_bytes).map_err(|e| anyhow::anyhow!("Deserialization error: {:?}", e))
+    debug_capture::deserialize_response(
+        canister_id,
+        method_name,
+        payload.len(),
+        &response_bytes,
+        deserializer,
+    )
 }
 
 /// Makes a cross-canister call with cycle payment and custom serialization.
 ///
 /// This function is similar to `make_c2c_call` but includes cycle payment support.
-/// It allows specifying the number of cycles to be transferred with the call.
+/// It allows specifying the number of cycles to be transferred with the call. As
+/// for `make_c2c_call`, the serializer is called exactly once.
 ///
 /// # Type Parameters
 /// * `A` - The type of the arguments
@@ -111,7 +152,7 @@ where
 /// * `DError` - The error type of the deserializer
 ///
 /// # Arguments
-/// * `canister_id` - The ID of the target canister
+/// * `canister_id` - The ID of the target canister, a `Principal` or a `CanisterIdStrict`
 /// * `method_name` - The name of the method to call
 /// * `args` - The arguments to pass to the method
 /// * `serializer` - Function to serialize the arguments
@@ -139,7 +180,7 @@ where
 /// }
 /// ```
 pub async fn make_c2c_call_with_payment<A, R, S, D, SError: Debug, DError: Debug>(
-    canister_id: Principal,
+    canister_id: impl Into<Principal>,
     method_name: &str,
     args: A,
     serializer: S,
@@ -147,17 +188,24 @@ pub async fn make_c2c_call_with_payment<A, R, S, D, SError: Debug, DError: Debug
     cycles: u128,
 ) -> Result<R>
 where
-    S: Fn(A) -> Result<Vec<u8>, SError>,
+    S: FnOnce(A) -> Result<Vec<u8>, SError>,
     D: Fn(&[u8]) -> Result<R, DError>,
 {
-    let payload_bytes =
-        serializer(args).map_err(|e| anyhow::anyhow!("Serialization error: {:?}", e))?;
+    let canister_id = canister_id.into();
+    let payload = SerializedArgs::new(args, serializer)?;
 
-    let response_bytes = make_c2c_call_raw(canister_id, method_name, &payload_bytes, cycles, None)
-        .await
-        .context("Cross-canister call with payment failed")?;
+    let response_bytes =
+        make_c2c_call_raw(canister_id, method_name, payload.as_bytes(), cycles, None)
+            .await
+            .context("Cross-canister call with payment failed")?;
 
-    deserializer(&response_bytes).map_err(|e| anyhow::anyhow!("Deserialization error: {:?}", e))
+    debug_capture::deserialize_response(
+        canister_id,
+        method_name,
+        payload.len(),
+        &response_bytes,
+        deserializer,
+    )
 }
 
 /// Makes a raw cross-canister call with byte-level control.
@@ -165,31 +213,39 @@ where
 /// This is the lowest-level function for making cross-canister calls. It handles
 /// the actual call to the Internet Computer and includes tracing for debugging.
 ///
+/// The payload is checked against the [`payload_limits`] first: an oversized
+/// payload is rejected with `C2cError::PayloadTooLarge` without making the call,
+/// and payloads or responses close to the limit are logged as warnings.
+///
 /// # Arguments
-/// * `canister_id` - The ID of the target canister
+/// * `canister_id` - The ID of the target canister, a `Principal` or a `CanisterIdStrict`
 /// * `method_name` - The name of the method to call
 /// * `payload_bytes` - The raw bytes to send as the payload
 /// * `cycles` - The number of cycles to transfer with the call
+/// * `timeout_seconds` - The timeout of a bounded-wait call, or `None` for an unbounded-wait call
 ///
 /// # Returns
-/// A `CallResult` containing either the raw response bytes or an error.
+/// A `Result` containing either the raw response bytes or a `C2cError`.
 ///
 /// # Example
 /// ```
 /// use bity_ic_canister_client::make_c2c_call_raw;
 ///
-/// async fn example(canister_id: Principal, payload: &[u8]) -> CallResult<Vec<u8>> {
-///     make_c2c_call_raw(canister_id, "my_method", payload, 0).await
+/// async fn example(canister_id: Principal, payload: &[u8]) -> Result<Vec<u8>, C2cError> {
+///     make_c2c_call_raw(canister_id, "my_method", payload, 0, None).await
 /// }
 /// ```
-
 pub async fn make_c2c_call_raw(
-    canister_id: Principal,
+    canister_id: impl Into<Principal>,
     method_name: &str,
     payload_bytes: &[u8],
     cycles: u128,
     timeout_seconds: Option<u32>,
-) -> Result<Vec<u8>, CallFailed> {
+) -> Result<Vec<u8>, C2cError> {
+    let canister_id = canister_id.into();
+    let limits = payload_limits();
+    payload::check_payload_size(&limits, method_name, payload_bytes.len())?;
+
     let call = if let Some(timeout_seconds) = timeout_seconds {
         ic_cdk::call::Call::bounded_wait(canister_id, method_name).change_timeout(timeout_seconds)
     } else {
@@ -201,8 +257,67 @@ pub async fn make_c2c_call_raw(
     match response {
         Ok(response_bytes) => {
             tracing::trace!(method_name, %canister_id, "Completed c2c call successfully");
-            Ok(response_bytes.into_bytes())
+            let response_bytes = response_bytes.into_bytes();
+            payload::check_response_size(&limits, method_name, response_bytes.len());
+            Ok(response_bytes)
         }
-        Err(error) => Err(error),
+        Err(error) => Err(error.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_oversized_payload_is_rejected_before_the_call() {
+        let canister_id = Principal::from_slice(&[1]);
+        let payload = vec![0u8; MAX_C2C_PAYLOAD_BYTES + 1];
+
+        // Natively the call itself would trap: reaching it would fail the test.
+        let error = block_on(make_c2c_call_raw(
+            canister_id,
+            "insert_blocks",
+            &payload,
+            0,
+            None,
+        ))
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            C2cError::PayloadTooLarge { size, limit }
+                if size == MAX_C2C_PAYLOAD_BYTES + 1 && limit == MAX_C2C_PAYLOAD_BYTES
+        ));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "payload of {} bytes exceeds the limit of {} bytes",
+                MAX_C2C_PAYLOAD_BYTES + 1,
+                MAX_C2C_PAYLOAD_BYTES
+            )
+        );
+
+        // The limit is configurable.
+        set_payload_limits(PayloadLimits {
+            max_payload_bytes: 10,
+            ..PayloadLimits::default()
+        });
+        let error = block_on(make_c2c_call_raw(
+            canister_id,
+            "insert_blocks",
+            &[0u8; 11],
+            0,
+            None,
+        ))
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            C2cError::PayloadTooLarge {
+                size: 11,
+                limit: 10
+            }
+        ));
+        set_payload_limits(PayloadLimits::default());
     }
 }
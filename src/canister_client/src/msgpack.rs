@@ -0,0 +1,78 @@
+//! MessagePack encoding for the `_msgpack` endpoint convention.
+//!
+//! Canisters expose `<method>_msgpack` variants of their endpoints that take and
+//! return MessagePack payloads instead of Candid. This module provides the
+//! encoding used on both sides: [`generate_c2c_call!`](crate::generate_c2c_call)
+//! on the caller side and [`expose_msgpack_endpoint!`](crate::expose_msgpack_endpoint)
+//! on the canister side.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::error::Error;
+
+/// Serializes a value to MessagePack bytes.
+///
+/// # Arguments
+/// * `value` - The value to serialize
+///
+/// # Returns
+/// A `Result` containing either the serialized bytes or an error.
+pub fn serialize<T>(value: T) -> Result<Vec<u8>, impl Error>
+where
+    T: Serialize,
+{
+    bity_ic_serializer::serialize_to_vec(value)
+}
+
+/// Deserializes a value from MessagePack bytes.
+///
+/// # Arguments
+/// * `bytes` - The serialized data
+///
+/// # Returns
+/// A `Result` containing either the deserialized value or an error.
+pub fn deserialize<T>(bytes: &[u8]) -> Result<T, impl Error>
+where
+    T: DeserializeOwned,
+{
+    bity_ic_serializer::deserialize_from_slice(bytes)
+}
+
+/// Decodes the arguments of a `_msgpack` endpoint, trapping on invalid input.
+///
+/// Meant to be used as the `decode_with` function of an endpoint.
+pub fn decode_args<T>(bytes: Vec<u8>) -> T
+where
+    T: DeserializeOwned,
+{
+    deserialize(&bytes)
+        .unwrap_or_else(|e| ic_cdk::trap(format!("Failed to decode msgpack args: {e}")))
+}
+
+/// Encodes the response of a `_msgpack` endpoint, trapping on failure.
+///
+/// Meant to be used as the `encode_with` function of an endpoint.
+pub fn encode_response<T>(value: T) -> Vec<u8>
+where
+    T: Serialize,
+{
+    serialize(value)
+        .unwrap_or_else(|e| ic_cdk::trap(format!("Failed to encode msgpack response: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Nat;
+
+    #[test]
+    fn test_msgpack_round_trip() {
+        let value = (Nat::from(42u64), vec!["a".to_string()], ());
+        let bytes = serialize(&value).unwrap();
+        let decoded: (Nat, Vec<String>, ()) = deserialize(&bytes).unwrap();
+        assert_eq!(decoded, value);
+
+        let nat: Nat = decode_args(encode_response(Nat::from(7u64)));
+        assert_eq!(nat, Nat::from(7u64));
+    }
+}
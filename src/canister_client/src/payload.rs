@@ -0,0 +1,90 @@
+//! Size guards for cross-canister call payloads.
+//!
+//! The system rejects inter-canister calls whose payload exceeds
+//! [`MAX_C2C_PAYLOAD_BYTES`] with an opaque error. [`make_c2c_call_raw`](crate::make_c2c_call_raw)
+//! checks the payload before calling and returns [`C2cError::PayloadTooLarge`]
+//! instead, and logs a warning for payloads and responses close to the limit.
+//! The limits can be changed with [`set_payload_limits`].
+
+use crate::C2cError;
+use std::cell::Cell;
+
+/// Maximum size of an inter-canister call payload, in bytes.
+pub const MAX_C2C_PAYLOAD_BYTES: usize = 2 * 1024 * 1024;
+/// Payload size above which a warning is logged before the call, in bytes.
+pub const C2C_PAYLOAD_WARNING_BYTES: usize = 1_800_000;
+/// Response size above which a warning is logged after the call, in bytes.
+pub const C2C_RESPONSE_WARNING_BYTES: usize = 1_800_000;
+
+/// Payload size limits applied by [`make_c2c_call_raw`](crate::make_c2c_call_raw).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PayloadLimits {
+    /// Payloads above this size are rejected without attempting the call
+    pub max_payload_bytes: usize,
+    /// Payloads above this size are logged as a warning
+    pub payload_warning_bytes: usize,
+    /// Responses above this size are logged as a warning
+    pub response_warning_bytes: usize,
+}
+
+impl Default for PayloadLimits {
+    fn default() -> Self {
+        Self {
+            max_payload_bytes: MAX_C2C_PAYLOAD_BYTES,
+            payload_warning_bytes: C2C_PAYLOAD_WARNING_BYTES,
+            response_warning_bytes: C2C_RESPONSE_WARNING_BYTES,
+        }
+    }
+}
+
+thread_local! {
+    static LIMITS: Cell<PayloadLimits> = Cell::new(PayloadLimits::default());
+}
+
+/// Replaces the payload size limits used for the calls of this canister.
+pub fn set_payload_limits(limits: PayloadLimits) {
+    LIMITS.with(|l| l.set(limits));
+}
+
+/// Returns the payload size limits used for the calls of this canister.
+pub fn payload_limits() -> PayloadLimits {
+    LIMITS.with(|l| l.get())
+}
+
+/// Checks the size of a payload before it is sent to `method_name`.
+///
+/// # Returns
+/// `C2cError::PayloadTooLarge` if the payload exceeds `max_payload_bytes`.
+pub fn check_payload_size(
+    limits: &PayloadLimits,
+    method_name: &str,
+    size: usize,
+) -> Result<(), C2cError> {
+    if size > limits.max_payload_bytes {
+        return Err(C2cError::PayloadTooLarge {
+            size,
+            limit: limits.max_payload_bytes,
+        });
+    }
+    if size > limits.payload_warning_bytes {
+        tracing::warn!(
+            method_name,
+            size,
+            limit = limits.max_payload_bytes,
+            "c2c payload is close to the size limit"
+        );
+    }
+    Ok(())
+}
+
+/// Logs a warning if the response of `method_name` exceeds `response_warning_bytes`.
+pub fn check_response_size(limits: &PayloadLimits, method_name: &str, size: usize) {
+    if size > limits.response_warning_bytes {
+        tracing::warn!(
+            method_name,
+            size,
+            threshold = limits.response_warning_bytes,
+            "c2c response is close to the size limit"
+        );
+    }
+}
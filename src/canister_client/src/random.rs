@@ -0,0 +1,197 @@
+//! Random bytes from the management canister's `raw_rand`.
+//!
+//! Each `raw_rand` call returns 32 bytes and takes a round trip to the management
+//! canister. [`get_random_bytes`] keeps the bytes it does not use in a pool local to
+//! the canister, so that several small draws, e.g. a retry jitter and a sampling
+//! seed, share one call. `raw_rand` traps when called from a query, so a call from
+//! a non-replicated query returns [`C2cError::NotInUpdate`] instead. A query run in
+//! replicated mode cannot be told apart from an update and is not detected.
+//!
+//! For reproducible tests, [`set_test_seed`] replaces `raw_rand` with a
+//! deterministic generator.
+
+use crate::C2cError;
+use candid::Principal;
+use ic_cdk::call::Call;
+use std::cell::RefCell;
+
+/// The number of bytes returned by a `raw_rand` call.
+pub const RAW_RAND_BYTES: usize = 32;
+
+/// The state of the pool of random bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RandomPoolStats {
+    /// The number of bytes left in the pool
+    pub available: usize,
+    /// The number of times the pool was refilled
+    pub refills: u64,
+}
+
+#[derive(Default)]
+struct Pool {
+    bytes: Vec<u8>,
+    refills: u64,
+    seed: Option<u64>,
+}
+
+impl Pool {
+    /// Removes up to `n` bytes from the pool.
+    fn take(&mut self, n: usize) -> Vec<u8> {
+        let start = self.bytes.len().saturating_sub(n);
+        self.bytes.split_off(start)
+    }
+
+    fn refill(&mut self, bytes: Vec<u8>) {
+        self.bytes.extend(bytes);
+        self.refills += 1;
+    }
+
+    /// Returns the next bytes of the seeded generator, if a seed is set.
+    fn seeded_bytes(&mut self) -> Option<Vec<u8>> {
+        let state = self.seed.as_mut()?;
+        Some(
+            (0..RAW_RAND_BYTES / 8)
+                .flat_map(|_| splitmix64(state).to_be_bytes())
+                .collect(),
+        )
+    }
+}
+
+thread_local! {
+    static POOL: RefCell<Pool> = RefCell::default();
+}
+
+/// Returns `n` random bytes, calling `raw_rand` only when the pool runs out.
+///
+/// # Errors
+///
+/// * `C2cError::NotInUpdate` if called from a query
+/// * `C2cError::CallFailed` or `C2cError::DecodeFailed` if `raw_rand` failed, the
+///   bytes already taken from the pool are then lost
+pub async fn get_random_bytes(n: usize) -> Result<Vec<u8>, C2cError> {
+    let mut bytes = POOL.with(|pool| pool.borrow_mut().take(n));
+    while bytes.len() < n {
+        let fresh = match POOL.with(|pool| pool.borrow_mut().seeded_bytes()) {
+            Some(fresh) => fresh,
+            None => raw_rand().await?,
+        };
+        POOL.with(|pool| {
+            let mut pool = pool.borrow_mut();
+            pool.refill(fresh);
+            bytes.extend(pool.take(n - bytes.len()));
+        });
+    }
+    Ok(bytes)
+}
+
+/// Returns a random `u64`, see [`get_random_bytes`].
+pub async fn get_random_u64() -> Result<u64, C2cError> {
+    let bytes = get_random_bytes(8).await?;
+    Ok(u64::from_be_bytes(
+        bytes.try_into().expect("8 bytes were requested"),
+    ))
+}
+
+/// Returns the state of the pool of random bytes.
+pub fn random_pool_stats() -> RandomPoolStats {
+    POOL.with(|pool| {
+        let pool = pool.borrow();
+        RandomPoolStats {
+            available: pool.bytes.len(),
+            refills: pool.refills,
+        }
+    })
+}
+
+/// Empties the pool and replaces `raw_rand` with a generator seeded with `seed`,
+/// or goes back to `raw_rand` with `None`.
+///
+/// The bytes are predictable once seeded: only use a seed in tests.
+pub fn set_test_seed(seed: Option<u64>) {
+    POOL.with(|pool| {
+        *pool.borrow_mut() = Pool {
+            seed,
+            ..Pool::default()
+        }
+    });
+}
+
+async fn raw_rand() -> Result<Vec<u8>, C2cError> {
+    if !ic_cdk::api::in_replicated_execution() {
+        return Err(C2cError::NotInUpdate);
+    }
+    let response = Call::bounded_wait(Principal::management_canister(), "raw_rand").await?;
+    Ok(response.candid::<Vec<u8>>()?)
+}
+
+/// The SplitMix64 generator, enough for reproducible test data.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_pool_refill_accounting() {
+        set_test_seed(Some(42));
+
+        let first = block_on(get_random_bytes(10)).unwrap();
+        assert_eq!(first.len(), 10);
+        assert_eq!(
+            random_pool_stats(),
+            RandomPoolStats {
+                available: 22,
+                refills: 1
+            }
+        );
+
+        // Served from the pool.
+        let second = block_on(get_random_bytes(20)).unwrap();
+        assert_ne!(first, second[..10]);
+        assert_eq!(
+            random_pool_stats(),
+            RandomPoolStats {
+                available: 2,
+                refills: 1
+            }
+        );
+
+        // Larger than one refill.
+        assert_eq!(block_on(get_random_bytes(70)).unwrap().len(), 70);
+        assert_eq!(
+            random_pool_stats(),
+            RandomPoolStats {
+                available: 28,
+                refills: 4
+            }
+        );
+
+        assert!(block_on(get_random_bytes(0)).unwrap().is_empty());
+        assert_eq!(random_pool_stats().refills, 4);
+    }
+
+    #[test]
+    fn test_seed_is_reproducible() {
+        set_test_seed(Some(7));
+        let draws: Vec<u64> = (0..8)
+            .map(|_| block_on(get_random_u64()).unwrap())
+            .collect();
+        assert_eq!(random_pool_stats().refills, 2);
+
+        set_test_seed(Some(7));
+        let again: Vec<u64> = (0..8)
+            .map(|_| block_on(get_random_u64()).unwrap())
+            .collect();
+        assert_eq!(draws, again);
+
+        set_test_seed(Some(8));
+        assert_ne!(block_on(get_random_u64()).unwrap(), draws[0]);
+    }
+}
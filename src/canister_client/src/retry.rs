@@ -0,0 +1,294 @@
+//! Retries of cross-canister calls on a payload serialized once.
+//!
+//! The arguments of a call are encoded once into a [`SerializedArgs`] buffer,
+//! and every attempt sends those same bytes: a retry neither pays for the
+//! encoding again nor needs the arguments to be `Clone`. A failed attempt is
+//! only retried when the error is immediately retryable (a `SYS_TRANSIENT` or
+//! `SYS_UNKNOWN` reject), see [`C2cError::is_immediately_retryable`]. A
+//! `SYS_UNKNOWN` reject leaves the outcome of the call unknown, so it is only
+//! retried for calls the policy marks as idempotent, see
+//! [`RetryPolicy::retry_unknown_outcomes`].
+
+use crate::{debug_capture, make_c2c_call_raw, C2cError, Context, Result};
+use candid::Principal;
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Arguments of a cross-canister call, serialized once.
+///
+/// Cloning only copies a reference to the bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SerializedArgs(Arc<[u8]>);
+
+impl SerializedArgs {
+    /// Serializes `args` with `serializer`, which is called exactly once.
+    pub fn new<A, S, SError: Debug>(args: A, serializer: S) -> Result<Self>
+    where
+        S: FnOnce(A) -> Result<Vec<u8>, SError>,
+    {
+        serializer(args)
+            .map(Self::from)
+            .map_err(|e| anyhow::anyhow!("Serialization error: {:?}", e))
+    }
+
+    /// Returns the serialized bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns the size of the payload in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns whether the payload is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<Vec<u8>> for SerializedArgs {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes.into())
+    }
+}
+
+/// How many times a call is attempted before its last error is returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one (a value of 0 is treated as 1)
+    pub max_attempts: u32,
+    /// Whether a call whose outcome is unknown (a `SYS_UNKNOWN` reject) is retried.
+    /// Only set it for idempotent calls, which may have been executed already.
+    pub retry_unknown_outcomes: bool,
+}
+
+impl RetryPolicy {
+    /// The default policy for an idempotent call, retried whatever its outcome.
+    pub fn idempotent() -> Self {
+        Self {
+            retry_unknown_outcomes: true,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            retry_unknown_outcomes: false,
+        }
+    }
+}
+
+/// Attempts a call on `payload` until it succeeds, fails with an error that is not
+/// immediately retryable, or `policy.max_attempts` is reached.
+///
+/// An error is retried if it is a clean reject, or if its outcome is unknown and
+/// `policy.retry_unknown_outcomes` is set.
+///
+/// # Arguments
+/// * `payload` - The serialized arguments, sent unchanged by every attempt
+/// * `policy` - The number of attempts and whether unknown outcomes are retried
+/// * `attempt` - Makes one attempt of the call with a handle to the payload
+///
+/// # Returns
+/// The response bytes of the first successful attempt, or the error of the last one.
+pub async fn retry_c2c_call<F, Fut>(
+    payload: &SerializedArgs,
+    policy: RetryPolicy,
+    mut attempt: F,
+) -> Result<Vec<u8>, C2cError>
+where
+    F: FnMut(SerializedArgs) -> Fut,
+    Fut: Future<Output = Result<Vec<u8>, C2cError>>,
+{
+    let max_attempts = policy.max_attempts.max(1);
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match attempt(payload.clone()).await {
+            Err(error)
+                if attempts < max_attempts
+                    && error.is_immediately_retryable()
+                    && (error.is_clean_reject() || policy.retry_unknown_outcomes) =>
+            {
+                tracing::debug!(attempts, %error, "Retrying c2c call");
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Makes a cross-canister call, retrying it according to `policy`.
+///
+/// The arguments are serialized once, before the first attempt, so `args` is
+/// taken by value without requiring `A: Clone`. Passing a reference works as for
+/// [`make_c2c_call`](crate::make_c2c_call).
+///
+/// # Arguments
+/// * `canister_id` - The ID of the target canister, a `Principal` or a `CanisterIdStrict`
+/// * `method_name` - The name of the method to call
+/// * `args` - The arguments to pass to the method
+/// * `serializer` - Function to serialize the arguments, called exactly once
+/// * `deserializer` - Function to deserialize the response
+/// * `policy` - The number of attempts and whether unknown outcomes are retried
+///
+/// # Example
+/// ```ignore
+/// use bity_ic_canister_client::{make_c2c_call_with_retry, RetryPolicy};
+/// use candid::{encode_one, decode_one};
+///
+/// async fn example(canister_id: Principal, args: MyArgs) -> anyhow::Result<MyResponse> {
+///     make_c2c_call_with_retry(
+///         canister_id,
+///         "my_method",
+///         args,
+///         encode_one,
+///         |r| decode_one(r),
+///         RetryPolicy::default(),
+///     )
+///     .await
+/// }
+/// ```
+pub async fn make_c2c_call_with_retry<A, R, S, D, SError: Debug, DError: Debug>(
+    canister_id: impl Into<Principal>,
+    method_name: &str,
+    args: A,
+    serializer: S,
+    deserializer: D,
+    policy: RetryPolicy,
+) -> Result<R>
+where
+    S: FnOnce(A) -> Result<Vec<u8>, SError>,
+    D: Fn(&[u8]) -> Result<R, DError>,
+{
+    let canister_id = canister_id.into();
+    let payload = SerializedArgs::new(args, serializer)?;
+
+    let response_bytes = retry_c2c_call(&payload, policy, |payload| async move {
+        make_c2c_call_raw(canister_id, method_name, payload.as_bytes(), 0, None).await
+    })
+    .await
+    .context("Cross-canister call failed")?;
+
+    debug_capture::deserialize_response(
+        canister_id,
+        method_name,
+        payload.len(),
+        &response_bytes,
+        deserializer,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use ic_cdk::call::{CallFailed, CallRejected, RejectCode};
+    use std::cell::Cell;
+
+    /// Arguments that cannot be cloned.
+    #[derive(candid::CandidType)]
+    struct NotClone {
+        value: u64,
+    }
+
+    fn reject(code: RejectCode) -> C2cError {
+        C2cError::CallFailed(CallFailed::CallRejected(CallRejected::with_rejection(
+            code as u32,
+            "rejected".to_string(),
+        )))
+    }
+
+    #[test]
+    fn test_retries_reuse_the_payload_serialized_once() {
+        let serializations = Cell::new(0);
+        let payload = SerializedArgs::new(NotClone { value: 7 }, |args: NotClone| {
+            serializations.set(serializations.get() + 1);
+            candid::encode_one(args.value)
+        })
+        .unwrap();
+
+        let mut sent = vec![];
+        let response = block_on(retry_c2c_call(
+            &payload,
+            RetryPolicy::default(),
+            |payload| {
+                sent.push(payload);
+                let attempt = sent.len();
+                async move {
+                    if attempt < 3 {
+                        Err(reject(RejectCode::SysTransient))
+                    } else {
+                        Ok(vec![1])
+                    }
+                }
+            },
+        ));
+
+        assert_eq!(response.unwrap(), vec![1]);
+        assert_eq!(serializations.get(), 1);
+        assert_eq!(sent.len(), 3);
+        assert!(sent
+            .iter()
+            .all(|sent| sent.as_bytes() == payload.as_bytes()));
+    }
+
+    #[test]
+    fn test_only_retryable_errors_are_retried() {
+        let payload = SerializedArgs::from(vec![0u8; 4]);
+
+        let attempts = Cell::new(0);
+        let error = block_on(retry_c2c_call(&payload, RetryPolicy::default(), |_| {
+            attempts.set(attempts.get() + 1);
+            async { Err::<Vec<u8>, _>(reject(RejectCode::CanisterError)) }
+        }))
+        .unwrap_err();
+        assert!(!error.is_immediately_retryable());
+        assert_eq!(attempts.get(), 1);
+
+        // An unknown outcome is not retried by default: the call may have been executed.
+        attempts.set(0);
+        let error = block_on(retry_c2c_call(&payload, RetryPolicy::default(), |_| {
+            attempts.set(attempts.get() + 1);
+            async { Err::<Vec<u8>, _>(reject(RejectCode::SysUnknown)) }
+        }))
+        .unwrap_err();
+        assert!(error.is_immediately_retryable());
+        assert!(!error.is_clean_reject());
+        assert_eq!(attempts.get(), 1);
+
+        // It is for idempotent calls, and the error of the last attempt is returned.
+        attempts.set(0);
+        let error = block_on(retry_c2c_call(
+            &payload,
+            RetryPolicy {
+                max_attempts: 2,
+                ..RetryPolicy::idempotent()
+            },
+            |_| {
+                attempts.set(attempts.get() + 1);
+                async { Err::<Vec<u8>, _>(reject(RejectCode::SysUnknown)) }
+            },
+        ))
+        .unwrap_err();
+        assert!(error.is_immediately_retryable());
+        assert_eq!(attempts.get(), 2);
+    }
+
+    // Only needs to compile: the call itself would trap natively.
+    #[allow(dead_code)]
+    fn make_c2c_call_with_retry_accepts_non_clone_args(canister_id: Principal) {
+        let _call = make_c2c_call_with_retry(
+            canister_id,
+            "my_method",
+            NotClone { value: 1 },
+            |args: NotClone| candid::encode_one(args.value),
+            |r| candid::decode_one::<u64>(r),
+            RetryPolicy::default(),
+        );
+    }
+}
@@ -0,0 +1,474 @@
+//! Export of the log and trace buffers over HTTP.
+//!
+//! [`handle_logs_http_request`] serves the `/logs` and `/traces` paths of a
+//! canister `http_request` query, so that the buffers can be read with a plain
+//! `curl https://<canister>.icp0.io/logs?tail=200` during an incident.
+//!
+//! The following query parameters are supported:
+//! - `tail`: only return the last `tail` entries
+//! - `since_ms`: only return the entries logged at or after this timestamp, in milliseconds
+//! - `after`: only return the entries following this [`LogKey`], given as `<timestamp>:<seq>`,
+//!   to page through the buffers without missing or repeating entries
+//! - `level`: only return the entries of this level or more severe (`error`, `warn`, `info`, ...)
+//! - `format`: `text` (the default) for one line per entry, or `json` for an array of [`LogEntry`]
+//!
+//! Responses are capped to [`MAX_LOGS_RESPONSE_BYTES`], keeping the most recent
+//! entries. A capped response has the `x-logs-truncated: true` header.
+//!
+//! The [`HttpRequest`] and [`HttpResponse`] types are those of the HTTP gateway, and
+//! can be used for other routes of the canister. Larger bodies can be streamed with
+//! a [`StreamingStrategy`]: the gateway passes its token to the callback query of the
+//! canister to get each following chunk.
+
+use crate::{export_all_logs, export_all_traces, LogEntry, LogKey};
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tracing::Level;
+
+/// The maximum size of the body of a logs response, in bytes.
+///
+/// Query responses are limited to 3MiB, the rest is left for the headers and
+/// the candid encoding.
+pub const MAX_LOGS_RESPONSE_BYTES: usize = 2 * 1024 * 1024;
+
+/// The header set on responses whose body was capped to [`MAX_LOGS_RESPONSE_BYTES`].
+pub const TRUNCATED_HEADER: &str = "x-logs-truncated";
+
+/// An HTTP request received by the `http_request` query of a canister.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    #[serde(with = "serde_bytes")]
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// Returns the path of the url, without the query string.
+    pub fn path(&self) -> &str {
+        self.url.split_once('?').map_or(&self.url, |(path, _)| path)
+    }
+
+    /// Returns the query string of the url, without the leading `?`.
+    pub fn query(&self) -> &str {
+        self.url.split_once('?').map_or("", |(_, query)| query)
+    }
+
+    /// Returns the value of the header `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// An HTTP response returned by the `http_request` query of a canister.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    #[serde(with = "serde_bytes")]
+    pub body: Vec<u8>,
+    /// How the gateway gets the rest of the body, if it is streamed
+    #[serde(default, skip_serializing)]
+    pub streaming_strategy: Option<StreamingStrategy>,
+}
+
+/// The token of a streamed response, passed back to the streaming callback to get
+/// the next chunk. Its key is up to the route that streams the response.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct StreamingToken {
+    pub key: String,
+}
+
+candid::define_function!(pub StreamingCallback : (StreamingToken) -> (StreamingCallbackHttpResponse) query);
+
+/// How the HTTP gateway gets the following chunks of a streamed response.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum StreamingStrategy {
+    /// The gateway calls `callback` with `token`, then with the token of each chunk
+    /// until a chunk has none
+    Callback {
+        callback: StreamingCallback,
+        token: StreamingToken,
+    },
+}
+
+impl StreamingStrategy {
+    /// Creates a strategy calling the `method` query of this canister.
+    ///
+    /// # Arguments
+    /// * `canister_id` - The canister serving the chunks, usually the caller's own
+    /// * `method` - The name of the streaming callback query, e.g. `http_request_streaming_callback`
+    /// * `key` - The key of the token of the first chunk
+    pub fn callback(canister_id: Principal, method: &str, key: String) -> Self {
+        StreamingStrategy::Callback {
+            callback: StreamingCallback::new(canister_id, method.to_string()),
+            token: StreamingToken { key },
+        }
+    }
+}
+
+/// A chunk of a streamed response, returned by the streaming callback.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StreamingCallbackHttpResponse {
+    #[serde(with = "serde_bytes")]
+    pub body: Vec<u8>,
+    /// The token of the next chunk, `None` for the last one
+    pub token: Option<StreamingToken>,
+}
+
+impl HttpResponse {
+    /// Creates a `text/plain` response.
+    pub fn text(status_code: u16, body: impl Into<String>) -> Self {
+        Self {
+            status_code,
+            headers: vec![(
+                "content-type".to_string(),
+                "text/plain; charset=utf-8".to_string(),
+            )],
+            body: body.into().into_bytes(),
+            streaming_strategy: None,
+        }
+    }
+
+    /// Creates a `404 Not Found` response.
+    pub fn not_found() -> Self {
+        Self::text(404, "Not found")
+    }
+
+    /// Returns the value of the header `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The format of a logs response.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// One line per entry
+    #[default]
+    Text,
+    /// A JSON array of [`LogEntry`]
+    Json,
+}
+
+/// The filters of a logs request, parsed from its query string.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LogQuery {
+    /// Only the last `tail` entries are returned
+    pub tail: Option<usize>,
+    /// Only the entries logged at or after this timestamp are returned, in milliseconds
+    pub since_ms: Option<u64>,
+    /// Only the entries whose key is greater than this one are returned
+    pub after: Option<LogKey>,
+    /// Only the entries of this level or more severe are returned
+    pub level: Option<Level>,
+    /// The format of the response
+    pub format: LogFormat,
+}
+
+impl FromStr for LogQuery {
+    type Err = String;
+
+    fn from_str(query: &str) -> Result<Self, Self::Err> {
+        let mut parsed = LogQuery::default();
+
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match key {
+                "tail" => {
+                    parsed.tail = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid tail: {value}"))?,
+                    )
+                }
+                "since_ms" => {
+                    parsed.since_ms = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid since_ms: {value}"))?,
+                    )
+                }
+                "after" => parsed.after = Some(value.parse()?),
+                "level" => {
+                    parsed.level = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid level: {value}"))?,
+                    )
+                }
+                "format" => {
+                    parsed.format = match value {
+                        "text" => LogFormat::Text,
+                        "json" => LogFormat::Json,
+                        _ => return Err(format!("Invalid format: {value}")),
+                    }
+                }
+                _ => return Err(format!("Unknown query parameter: {key}")),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// Returns a guard accepting the requests whose header `name` equals `token`.
+///
+/// # Arguments
+/// * `name` - The name of the header, compared case-insensitively
+/// * `token` - The shared token expected in the header
+pub fn require_header_token(name: String, token: String) -> impl Fn(&HttpRequest) -> bool {
+    move |request| request.header(&name) == Some(token.as_str())
+}
+
+/// Serves the `/logs` and `/traces` paths of an `http_request` query.
+///
+/// # Arguments
+/// * `request` - The request received by the canister
+/// * `guard` - Called before anything is read, the request is rejected with a
+///   `403 Forbidden` when it returns `false`
+///
+/// # Returns
+/// The logs response, `400 Bad Request` for invalid query parameters, or
+/// `404 Not Found` for any other path
+pub fn handle_logs_http_request(
+    request: &HttpRequest,
+    guard: impl Fn(&HttpRequest) -> bool,
+) -> HttpResponse {
+    let export = match request.path() {
+        "/logs" => export_all_logs,
+        "/traces" => export_all_traces,
+        _ => return HttpResponse::not_found(),
+    };
+
+    if !guard(request) {
+        return HttpResponse::text(403, "Forbidden");
+    }
+
+    let query = match request.query().parse::<LogQuery>() {
+        Ok(query) => query,
+        Err(e) => return HttpResponse::text(400, e),
+    };
+
+    render_logs(&export(), &query, MAX_LOGS_RESPONSE_BYTES)
+}
+
+/// Builds the response for the entries matching `query`, keeping the most
+/// recent ones when the body would exceed `max_bytes`.
+fn render_logs(entries: &[LogEntry], query: &LogQuery, max_bytes: usize) -> HttpResponse {
+    let matching: Vec<&LogEntry> = entries
+        .iter()
+        .filter(|entry| query.since_ms.is_none_or(|since| entry.timestamp >= since))
+        .filter(|entry| query.after.is_none_or(|after| entry.key() > after))
+        .filter(|entry| {
+            query
+                .level
+                .is_none_or(|level| entry_level(entry).is_some_and(|l| l <= level))
+        })
+        .collect();
+    let skipped = matching
+        .len()
+        .saturating_sub(query.tail.unwrap_or(usize::MAX));
+
+    let rendered: Vec<String> = matching[skipped..]
+        .iter()
+        .map(|entry| match query.format {
+            LogFormat::Text => format_text(entry),
+            LogFormat::Json => serde_json::to_string(entry).unwrap_or_default(),
+        })
+        .collect();
+
+    // Each entry is followed by a separator, and the json array adds 2 bytes.
+    let mut size = 2;
+    let kept = rendered
+        .iter()
+        .rev()
+        .take_while(|line| {
+            size += line.len() + 1;
+            size <= max_bytes
+        })
+        .count();
+    let truncated = kept < rendered.len();
+    let rendered = &rendered[rendered.len() - kept..];
+
+    let (content_type, body) = match query.format {
+        LogFormat::Text => (
+            "text/plain; charset=utf-8",
+            rendered.iter().map(|line| format!("{line}\n")).collect(),
+        ),
+        LogFormat::Json => ("application/json", format!("[{}]", rendered.join(","))),
+    };
+
+    let mut headers = vec![("content-type".to_string(), content_type.to_string())];
+    if truncated {
+        headers.push((TRUNCATED_HEADER.to_string(), "true".to_string()));
+    }
+
+    HttpResponse {
+        status_code: 200,
+        headers,
+        body: body.into_bytes(),
+        streaming_strategy: None,
+    }
+}
+
+/// Returns the level of an entry written by the JSON formatter of the logger.
+fn entry_level(entry: &LogEntry) -> Option<Level> {
+    let message: serde_json::Value = serde_json::from_str(&entry.message).ok()?;
+    message.get("level")?.as_str()?.parse().ok()
+}
+
+/// Formats an entry as `<timestamp> <LEVEL> <target>: <message> <field>=<value>...`,
+/// or `<timestamp> <message>` when the message is not from the JSON formatter.
+fn format_text(entry: &LogEntry) -> String {
+    let Ok(serde_json::Value::Object(message)) =
+        serde_json::from_str::<serde_json::Value>(&entry.message)
+    else {
+        return format!("{} {}", entry.timestamp, entry.message.trim_end());
+    };
+
+    let str_field = |name: &str| message.get(name).and_then(|v| v.as_str()).unwrap_or("");
+    let mut line = format!(
+        "{} {} {}:",
+        entry.timestamp,
+        str_field("level"),
+        str_field("target")
+    );
+
+    if let Some(serde_json::Value::Object(fields)) = message.get("fields") {
+        if let Some(text) = fields.get("message").and_then(|v| v.as_str()) {
+            line.push(' ');
+            line.push_str(text);
+        }
+        for (name, value) in fields.iter().filter(|(name, _)| *name != "message") {
+            let value = value
+                .as_str()
+                .map_or_else(|| value.to_string(), str::to_string);
+            line.push_str(&format!(" {name}={value}"));
+        }
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: u64, level: &str, message: &str) -> LogEntry {
+        LogEntry {
+            timestamp,
+            seq: 0,
+            message: format!(
+                r#"{{"timestamp":"{timestamp}","level":"{level}","fields":{{"message":"{message}","index":{timestamp}}},"target":"icrc3"}}"#
+            ),
+        }
+    }
+
+    fn entries() -> Vec<LogEntry> {
+        vec![
+            entry(1_000, "INFO", "started"),
+            entry(2_000, "WARN", "slow archive"),
+            entry(3_000, "INFO", "archived"),
+            entry(4_000, "ERROR", "archive failed"),
+        ]
+    }
+
+    fn body(response: &HttpResponse) -> String {
+        String::from_utf8(response.body.clone()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(
+            "tail=2&since_ms=10&level=warn&format=json".parse(),
+            Ok(LogQuery {
+                tail: Some(2),
+                since_ms: Some(10),
+                after: None,
+                level: Some(Level::WARN),
+                format: LogFormat::Json,
+            })
+        );
+        assert_eq!("".parse(), Ok(LogQuery::default()));
+        assert!("tail=-1".parse::<LogQuery>().is_err());
+        assert!("format=xml".parse::<LogQuery>().is_err());
+        assert!("limit=1".parse::<LogQuery>().is_err());
+        assert_eq!(
+            "after=10:2".parse::<LogQuery>().unwrap().after,
+            Some(LogKey {
+                timestamp: 10,
+                seq: 2
+            })
+        );
+        assert!("after=x".parse::<LogQuery>().is_err());
+    }
+
+    #[test]
+    fn test_render_filters_and_formats() {
+        let query = "level=warn".parse().unwrap();
+        let response = render_logs(&entries(), &query, MAX_LOGS_RESPONSE_BYTES);
+        assert_eq!(response.status_code, 200);
+        assert_eq!(
+            body(&response),
+            "2000 WARN icrc3: slow archive index=2000\n4000 ERROR icrc3: archive failed index=4000\n"
+        );
+
+        let query = "since_ms=2000&tail=2&format=json".parse().unwrap();
+        let response = render_logs(&entries(), &query, MAX_LOGS_RESPONSE_BYTES);
+        assert_eq!(response.header("content-type"), Some("application/json"));
+        let exported: Vec<serde_json::Value> = serde_json::from_slice(&response.body).unwrap();
+        let timestamps: Vec<_> = exported.iter().map(|e| e["timestamp"].clone()).collect();
+        assert_eq!(timestamps, vec![3_000, 4_000]);
+        assert_eq!(response.header(TRUNCATED_HEADER), None);
+
+        let query = "after=2000:0&format=json".parse().unwrap();
+        let response = render_logs(&entries(), &query, MAX_LOGS_RESPONSE_BYTES);
+        let exported: Vec<serde_json::Value> = serde_json::from_slice(&response.body).unwrap();
+        let timestamps: Vec<_> = exported.iter().map(|e| e["timestamp"].clone()).collect();
+        assert_eq!(timestamps, vec![3_000, 4_000]);
+    }
+
+    #[test]
+    fn test_render_keeps_most_recent_entries_within_cap() {
+        let line_len = format_text(&entries()[3]).len() + 1;
+        let response = render_logs(&entries(), &LogQuery::default(), line_len + 2);
+        assert_eq!(body(&response).lines().count(), 1);
+        assert!(body(&response).contains("archive failed"));
+        assert_eq!(response.header(TRUNCATED_HEADER), Some("true"));
+
+        let query = "format=json".parse().unwrap();
+        let response = render_logs(&entries(), &query, 10);
+        assert_eq!(body(&response), "[]");
+    }
+
+    #[test]
+    fn test_guard_and_routes() {
+        let request = |url: &str, headers: Vec<(String, String)>| HttpRequest {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers,
+            body: Vec::new(),
+        };
+        let guard = require_header_token("x-logs-token".to_string(), "secret".to_string());
+
+        let response = handle_logs_http_request(&request("/logs", Vec::new()), &guard);
+        assert_eq!(response.status_code, 403);
+
+        let headers = vec![("X-Logs-Token".to_string(), "secret".to_string())];
+        let response = handle_logs_http_request(&request("/logs?tail=1", headers.clone()), &guard);
+        assert_eq!(response.status_code, 200);
+        let response = handle_logs_http_request(&request("/logs?tail=x", headers.clone()), &guard);
+        assert_eq!(response.status_code, 400);
+        let response = handle_logs_http_request(&request("/metrics", headers), &guard);
+        assert_eq!(response.status_code, 404);
+    }
+}
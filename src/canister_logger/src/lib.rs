@@ -19,18 +19,44 @@
 //! // Export logs
 //! let logs = export_logs();
 //! ```
+//!
+//! The buffers can also be served over HTTP, see the [`http`] module, or read a
+//! page at a time with [`export_logs_page`], optionally along with a
+//! [`RuntimeSnapshot`] of the canister.
+//!
+//! Candid exports are capped to [`MAX_EXPORT_BYTES`] so that they stay below the
+//! response limit whatever the size of the buffers: a page then stops early and
+//! tells where to continue from, and [`export_logs`] keeps the newest entries.
+//! [`export_all_logs`] is not capped, for saving the buffers across upgrades.
+//!
+//! Each entry is identified by a [`LogKey`], its timestamp and a sequence number
+//! ordering the entries of the same millisecond. Keys are strictly increasing
+//! across both buffers, and the `timestamp` field of the JSON message is the key
+//! of its entry.
+
+pub mod http;
+pub mod snapshot;
 
+pub use http::{
+    handle_logs_http_request, HttpRequest, HttpResponse, StreamingCallbackHttpResponse,
+    StreamingStrategy, StreamingToken,
+};
+pub use snapshot::{runtime_snapshot, RuntimeSnapshot};
+
+use bity_ic_canister_time::Millis;
 use candid::CandidType;
 use serde::{Deserialize, Serialize};
 use std::cell::{Cell, RefCell};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::io::Write;
-use tracing::Level;
+use std::str::FromStr;
+use tracing::{Event, Level, Metadata, Subscriber};
 use tracing_subscriber::fmt::format::{FmtSpan, Writer};
 use tracing_subscriber::fmt::time::FormatTime;
 use tracing_subscriber::fmt::writer::MakeWriterExt;
 use tracing_subscriber::fmt::Layer;
-use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::layer::{Context, Filter, Layer as _, SubscriberExt};
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::Registry;
 
@@ -38,6 +64,9 @@ thread_local! {
     static INITIALIZED: Cell<bool> = Cell::default();
     static LOG: RefCell<LogBuffer> = RefCell::new(LogBuffer::default());
     static TRACE: RefCell<LogBuffer> = RefCell::new(LogBuffer::default());
+    static SAMPLING: RefCell<TraceSampling> = RefCell::new(TraceSampling::default());
+    static LAST_KEY: Cell<Option<LogKey>> = const { Cell::new(None) };
+    static PENDING_KEY: Cell<Option<LogKey>> = const { Cell::new(None) };
 }
 
 /// Initializes the logging system.
@@ -55,6 +84,12 @@ pub fn init(enable_trace: bool) {
         panic!("Logger already initialized");
     }
 
+    subscriber(enable_trace).init();
+}
+
+/// Builds the subscriber writing to the log buffer and, if `enable_trace` is
+/// set, to the trace buffer.
+fn subscriber(enable_trace: bool) -> Box<dyn Subscriber + Send + Sync> {
     let log_layer = Layer::default()
         .with_writer((|| LogWriter::new(false)).with_max_level(Level::INFO))
         .json()
@@ -72,17 +107,20 @@ pub fn init(enable_trace: bool) {
             .with_file(true)
             .with_line_number(true)
             .with_current_span(false)
-            .with_span_events(FmtSpan::ENTER);
+            .with_span_events(FmtSpan::ENTER)
+            .with_filter(SamplingFilter);
 
-        Registry::default().with(log_layer).with(trace_layer).init();
+        Box::new(Registry::default().with(log_layer).with(trace_layer))
     } else {
-        Registry::default().with(log_layer).init();
+        Box::new(Registry::default().with(log_layer))
     }
 }
 
 /// Initializes the logging system with pre-existing logs.
 ///
 /// This function initializes the logger and populates it with existing log entries.
+/// The entries keep their keys, those saved before keys had a sequence number get 0,
+/// and new entries are keyed after all of them.
 ///
 /// # Arguments
 /// * `enable_trace` - Whether to enable trace-level logging
@@ -90,6 +128,15 @@ pub fn init(enable_trace: bool) {
 /// * `traces` - Pre-existing trace entries to add
 pub fn init_with_logs(enable_trace: bool, logs: Vec<LogEntry>, traces: Vec<LogEntry>) {
     init(enable_trace);
+    restore_logs(logs, traces);
+}
+
+/// Adds restored entries to the buffers and keys new entries after them.
+fn restore_logs(logs: Vec<LogEntry>, traces: Vec<LogEntry>) {
+    let last_restored = logs.iter().chain(traces.iter()).map(LogEntry::key).max();
+    if let Some(last_restored) = last_restored {
+        LAST_KEY.with(|last| last.set(last.get().max(Some(last_restored))));
+    }
 
     for log in logs {
         LOG.with_borrow_mut(|l| l.append(log));
@@ -99,10 +146,32 @@ pub fn init_with_logs(enable_trace: bool, logs: Vec<LogEntry>, traces: Vec<LogEn
     }
 }
 
+/// Returns the key of a new entry: the current time in milliseconds, or the
+/// timestamp of the previous key if the clock has not moved past it, with the
+/// next sequence number.
+fn next_key() -> LogKey {
+    let now = Millis::from(bity_ic_canister_time::timestamp_millis()).get();
+    LAST_KEY.with(|last| {
+        let key = match last.get() {
+            Some(previous) if previous.timestamp >= now => LogKey {
+                timestamp: previous.timestamp,
+                seq: previous.seq + 1,
+            },
+            _ => LogKey {
+                timestamp: now,
+                seq: 0,
+            },
+        };
+        last.set(Some(key));
+        key
+    })
+}
+
 /// A circular buffer for storing log messages.
 ///
 /// This struct implements a fixed-size circular buffer that automatically
-/// evicts the oldest entries when full.
+/// evicts the oldest entries when full. The buffer can also be bounded by the
+/// total size of the messages it holds, see [`LogConfig::max_total_bytes`].
 ///
 /// # Examples
 /// ```
@@ -111,11 +180,14 @@ pub fn init_with_logs(enable_trace: bool, logs: Vec<LogEntry>, traces: Vec<LogEn
 /// let mut buffer = LogBuffer::with_capacity(10);
 /// buffer.append(LogEntry {
 ///     timestamp: 1000,
+///     seq: 0,
 ///     message: "Test message".to_string(),
 /// });
 /// ```
 pub struct LogBuffer {
     max_capacity: usize,
+    max_total_bytes: Option<usize>,
+    total_bytes: usize,
     entries: VecDeque<LogEntry>,
 }
 
@@ -130,21 +202,53 @@ impl LogBuffer {
     pub fn with_capacity(max_capacity: usize) -> Self {
         Self {
             max_capacity,
+            max_total_bytes: None,
+            total_bytes: 0,
             entries: VecDeque::with_capacity(max_capacity),
         }
     }
 
+    /// Creates a new buffer bounded by both limits of `config`.
+    ///
+    /// # Arguments
+    /// * `config` - The limits of the buffer
+    ///
+    /// # Returns
+    /// A new `LogBuffer` instance
+    pub fn with_config(config: &LogConfig) -> Self {
+        let mut buffer = Self::with_capacity(config.max_entries);
+        buffer.max_total_bytes = config.max_total_bytes;
+        buffer
+    }
+
+    /// Changes the limits of the buffer, evicting the oldest entries until the
+    /// new limits are satisfied.
+    ///
+    /// # Arguments
+    /// * `config` - The new limits of the buffer
+    pub fn set_config(&mut self, config: &LogConfig) {
+        self.max_capacity = config.max_entries;
+        self.max_total_bytes = config.max_total_bytes;
+        self.evict(0, 0);
+    }
+
     /// Adds a new entry to the buffer.
     ///
-    /// If the buffer is at capacity, the oldest entry is removed before adding
-    /// the new one.
+    /// The oldest entries are removed until both the entry count and the byte
+    /// limit leave room for the new one. An entry whose message alone exceeds
+    /// the byte limit is truncated to fit, with a `…truncated` marker.
     ///
     /// # Arguments
     /// * `entry` - The log entry to add
-    pub fn append(&mut self, entry: LogEntry) {
-        while self.entries.len() >= self.max_capacity {
-            self.entries.pop_front();
+    pub fn append(&mut self, mut entry: LogEntry) {
+        if let Some(max_total_bytes) = self.max_total_bytes {
+            if entry.message.len() > max_total_bytes {
+                truncate_message(&mut entry.message, max_total_bytes);
+            }
         }
+
+        self.evict(1, entry.message.len());
+        self.total_bytes += entry.message.len();
         self.entries.push_back(entry);
     }
 
@@ -155,45 +259,489 @@ impl LogBuffer {
     pub fn iter(&self) -> impl Iterator<Item = &LogEntry> {
         self.entries.iter()
     }
+
+    /// Returns the entries whose key is greater than `after`, sorted by key.
+    ///
+    /// Entries are appended in key order, except restored entries which may be
+    /// older than the ones already in the buffer.
+    pub fn export_after(&self, after: Option<LogKey>) -> Vec<LogEntry> {
+        let mut entries: Vec<LogEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| after.is_none_or(|after| entry.key() > after))
+            .cloned()
+            .collect();
+        entries.sort_by_key(LogEntry::key);
+        entries
+    }
+
+    /// Returns the oldest entries whose key is greater than `after` that fit in
+    /// `max_bytes`, sorted by key, and whether entries were left out.
+    ///
+    /// The size of an entry is estimated from the length of its message. At least
+    /// one entry is returned when there is one, its message truncated to fit if
+    /// needed, so that reading on from the key of the last entry always progresses.
+    pub fn export_page(&self, after: Option<LogKey>, max_bytes: usize) -> (Vec<LogEntry>, bool) {
+        let mut entries = self.export_after(after);
+        let mut size = 0;
+        let kept = entries
+            .iter()
+            .take_while(|entry| {
+                size += export_size(entry);
+                size <= max_bytes
+            })
+            .count();
+        let truncated = kept < entries.len();
+
+        if kept == 0 {
+            if let Some(first) = entries.first_mut() {
+                truncate_message(
+                    &mut first.message,
+                    max_bytes.saturating_sub(ENTRY_OVERHEAD_BYTES),
+                );
+            }
+            entries.truncate(1);
+        } else {
+            entries.truncate(kept);
+        }
+        (entries, truncated)
+    }
+
+    /// Returns the newest entries that fit in `max_bytes`, sorted by key, and
+    /// whether older entries were left out.
+    pub fn export_newest(&self, max_bytes: usize) -> (Vec<LogEntry>, bool) {
+        let mut entries = self.export_after(None);
+        let mut size = 0;
+        let kept = entries
+            .iter()
+            .rev()
+            .take_while(|entry| {
+                size += export_size(entry);
+                size <= max_bytes
+            })
+            .count();
+        let truncated = kept < entries.len();
+        (entries.split_off(entries.len() - kept), truncated)
+    }
+
+    /// Returns the number of entries in the buffer.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the buffer holds no entry.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the total size of the messages in the buffer, in bytes.
+    pub fn total_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    /// Removes the oldest entries until `entries` more entries of `bytes` bytes
+    /// fit in the buffer.
+    fn evict(&mut self, entries: usize, bytes: usize) {
+        while !self.entries.is_empty()
+            && (self.entries.len() + entries > self.max_capacity
+                || self
+                    .max_total_bytes
+                    .is_some_and(|max| self.total_bytes + bytes > max))
+        {
+            if let Some(evicted) = self.entries.pop_front() {
+                self.total_bytes -= evicted.message.len();
+            }
+        }
+    }
 }
 
 impl Default for LogBuffer {
     fn default() -> Self {
         LogBuffer {
-            max_capacity: 100,
+            max_capacity: DEFAULT_MAX_ENTRIES,
+            max_total_bytes: None,
+            total_bytes: 0,
             entries: VecDeque::new(),
         }
     }
 }
 
-/// Exports all current log entries.
+/// The marker appended to messages truncated to fit the byte limit of a buffer.
+const TRUNCATED_MARKER: &str = "…truncated";
+
+/// The default number of entries kept by a buffer.
+const DEFAULT_MAX_ENTRIES: usize = 100;
+
+/// The maximum size of the entries of a Candid export, in bytes, well below the
+/// 2MB limit of a response.
+pub const MAX_EXPORT_BYTES: usize = 1_500_000;
+
+/// The estimated size of the encoding of an entry besides its message.
+const ENTRY_OVERHEAD_BYTES: usize = 32;
+
+/// Returns the estimated size of the encoding of an entry.
+fn export_size(entry: &LogEntry) -> usize {
+    entry.message.len() + ENTRY_OVERHEAD_BYTES
+}
+
+/// Truncates `message` to at most `max_bytes` bytes, ending it with
+/// [`TRUNCATED_MARKER`] when the marker fits.
+fn truncate_message(message: &mut String, max_bytes: usize) {
+    let (keep, marker) = match max_bytes.checked_sub(TRUNCATED_MARKER.len()) {
+        Some(keep) => (keep, TRUNCATED_MARKER),
+        None => (max_bytes, ""),
+    };
+
+    let mut end = keep;
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    message.truncate(end);
+    message.push_str(marker);
+}
+
+/// The limits of the log and trace buffers.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LogConfig {
+    /// The maximum number of entries kept by each buffer
+    pub max_entries: usize,
+    /// The maximum total size of the messages kept by each buffer, in bytes
+    pub max_total_bytes: Option<usize>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_total_bytes: None,
+        }
+    }
+}
+
+/// Changes the limits of the log and trace buffers.
+///
+/// The oldest entries are evicted right away if the buffers exceed the new limits.
+///
+/// # Arguments
+/// * `config` - The new limits
+pub fn set_log_config(config: LogConfig) {
+    LOG.with_borrow_mut(|l| l.set_config(&config));
+    TRACE.with_borrow_mut(|t| t.set_config(&config));
+}
+
+/// Exports the current log entries, as many as fit in [`MAX_EXPORT_BYTES`].
+///
+/// When the buffer holds more, only the newest entries are returned and a warning
+/// is logged. Use [`export_logs_page`] to read them all, or [`export_all_logs`]
+/// outside of a Candid response.
 ///
 /// # Returns
-/// A vector containing all log entries
+/// A vector containing the newest log entries, sorted by key
 pub fn export_logs() -> Vec<LogEntry> {
-    LOG.with_borrow(|l| l.iter().cloned().collect())
+    let (entries, truncated) = LOG.with_borrow(|l| l.export_newest(MAX_EXPORT_BYTES));
+    if truncated {
+        warn_truncated_export("export_logs", entries.len());
+    }
+    entries
+}
+
+/// Exports the current trace entries, as many as fit in [`MAX_EXPORT_BYTES`], see
+/// [`export_logs`].
+pub fn export_traces() -> Vec<LogEntry> {
+    let (entries, truncated) = TRACE.with_borrow(|t| t.export_newest(MAX_EXPORT_BYTES));
+    if truncated {
+        warn_truncated_export("export_traces", entries.len());
+    }
+    entries
 }
 
-/// Exports all current trace entries.
+fn warn_truncated_export(export: &str, kept: usize) {
+    tracing::warn!(
+        "{export}: only the newest {kept} entries fit in {MAX_EXPORT_BYTES} bytes, read the older ones by page"
+    );
+}
+
+/// Exports all current log entries, whatever their size.
+///
+/// The result can exceed the Candid response limit, it is meant for saving the
+/// buffer across upgrades or serving it over HTTP.
 ///
 /// # Returns
-/// A vector containing all trace entries
-pub fn export_traces() -> Vec<LogEntry> {
-    TRACE.with_borrow(|t| t.iter().cloned().collect())
+/// A vector containing all log entries, sorted by key
+pub fn export_all_logs() -> Vec<LogEntry> {
+    LOG.with_borrow(|l| l.export_after(None))
+}
+
+/// Exports all current trace entries, whatever their size, see [`export_all_logs`].
+pub fn export_all_traces() -> Vec<LogEntry> {
+    TRACE.with_borrow(|t| t.export_after(None))
+}
+
+/// Exports the log entries following a cursor, as many as fit in [`MAX_EXPORT_BYTES`].
+///
+/// The oldest entries are returned first, so reading on from the key of the last
+/// entry returned until nothing is left yields every entry once.
+///
+/// # Arguments
+/// * `after` - The key of the last entry already read, `None` to start from the oldest
+///
+/// # Returns
+/// A vector containing the log entries whose key is greater than `after`, sorted by key
+pub fn export_logs_after(after: Option<LogKey>) -> Vec<LogEntry> {
+    LOG.with_borrow(|l| l.export_page(after, MAX_EXPORT_BYTES).0)
+}
+
+/// Exports the trace entries following a cursor, see [`export_logs_after`].
+pub fn export_traces_after(after: Option<LogKey>) -> Vec<LogEntry> {
+    TRACE.with_borrow(|t| t.export_page(after, MAX_EXPORT_BYTES).0)
+}
+
+/// Exports a page of log entries, see [`ExportLogsArgs`].
+///
+/// The page stops early when the entries would exceed [`MAX_EXPORT_BYTES`], it is
+/// then flagged as `truncated` and `next_after` is the cursor of the next page.
+///
+/// # Arguments
+/// * `args` - The cursor of the page and whether to attach a runtime snapshot
+///
+/// # Returns
+/// The log entries following the cursor, sorted by key, and the snapshot if requested
+pub fn export_logs_page(args: ExportLogsArgs) -> ExportLogsResponse {
+    let page = LOG.with_borrow(|l| l.export_page(args.after, MAX_EXPORT_BYTES));
+    ExportLogsResponse::new(page, args.include_snapshot)
+}
+
+/// Exports a page of trace entries, see [`export_logs_page`].
+pub fn export_traces_page(args: ExportLogsArgs) -> ExportLogsResponse {
+    let page = TRACE.with_borrow(|t| t.export_page(args.after, MAX_EXPORT_BYTES));
+    ExportLogsResponse::new(page, args.include_snapshot)
+}
+
+/// The arguments of [`export_logs_page`] and [`export_traces_page`].
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ExportLogsArgs {
+    /// The key of the last entry already read, `None` to read them all
+    pub after: Option<LogKey>,
+    /// Whether to attach a [`RuntimeSnapshot`], cheap pollers can leave it out
+    #[serde(default)]
+    pub include_snapshot: bool,
+}
+
+/// A page of exported entries.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ExportLogsResponse {
+    /// The entries following the cursor, sorted by key
+    pub entries: Vec<LogEntry>,
+    /// The cycle balance and memory usage of the canister at export time, if requested
+    pub snapshot: Option<RuntimeSnapshot>,
+    /// Whether entries following the page were left out to stay below [`MAX_EXPORT_BYTES`]
+    #[serde(default)]
+    pub truncated: bool,
+    /// The cursor of the next page, the key of the last entry, when `truncated`
+    #[serde(default)]
+    pub next_after: Option<LogKey>,
+}
+
+impl ExportLogsResponse {
+    fn new((entries, truncated): (Vec<LogEntry>, bool), include_snapshot: bool) -> Self {
+        let next_after = truncated
+            .then(|| entries.last().map(LogEntry::key))
+            .flatten();
+        Self {
+            entries,
+            snapshot: include_snapshot.then(runtime_snapshot),
+            truncated,
+            next_after,
+        }
+    }
+}
+
+/// Samples trace events whose target starts with `target_prefix`.
+///
+/// Only one event out of every `keep_one_in` is written to the trace buffer, the
+/// others are dropped and counted in [`LoggerStats::sampled_out_count`]. WARN and
+/// ERROR events are always kept. When several rules match a target, the one with
+/// the longest prefix applies.
+///
+/// The sampling can be changed at any time. Setting `keep_one_in` to 0 or 1
+/// removes the rule for `target_prefix`.
+///
+/// # Arguments
+/// * `target_prefix` - The prefix of the event targets to sample
+/// * `keep_one_in` - Keep one event out of this many
+pub fn set_trace_sampling(target_prefix: &str, keep_one_in: u32) {
+    SAMPLING.with_borrow_mut(|s| s.set_rule(target_prefix, keep_one_in));
+}
+
+/// Removes all trace sampling rules.
+pub fn clear_trace_sampling() {
+    SAMPLING.with_borrow_mut(|s| {
+        s.rules.clear();
+        s.counters.clear();
+    });
+}
+
+/// Returns the active trace sampling rules.
+///
+/// # Returns
+/// A vector containing all sampling rules, sorted by target prefix
+pub fn trace_sampling() -> Vec<TraceSamplingRule> {
+    SAMPLING.with_borrow(|s| s.rules.clone())
+}
+
+/// Returns statistics about the logging system.
+///
+/// # Returns
+/// The current `LoggerStats`
+pub fn logger_stats() -> LoggerStats {
+    SAMPLING.with_borrow(|s| LoggerStats {
+        sampled_out_count: s.sampled_out_count,
+        log_bytes: LOG.with_borrow(|l| l.total_bytes() as u64),
+        trace_bytes: TRACE.with_borrow(|t| t.total_bytes() as u64),
+    })
+}
+
+/// A trace sampling rule, see [`set_trace_sampling`].
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TraceSamplingRule {
+    /// The prefix of the event targets the rule applies to
+    pub target_prefix: String,
+    /// One event out of this many is kept
+    pub keep_one_in: u32,
+}
+
+/// Statistics about the logging system.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoggerStats {
+    /// The number of trace events dropped by sampling
+    pub sampled_out_count: u64,
+    /// The total size of the messages in the log buffer, in bytes
+    pub log_bytes: u64,
+    /// The total size of the messages in the trace buffer, in bytes
+    pub trace_bytes: u64,
+}
+
+/// The sampling rules and the per target event counters.
+#[derive(Default)]
+struct TraceSampling {
+    rules: Vec<TraceSamplingRule>,
+    counters: HashMap<String, u64>,
+    sampled_out_count: u64,
+}
+
+impl TraceSampling {
+    fn set_rule(&mut self, target_prefix: &str, keep_one_in: u32) {
+        self.rules.retain(|r| r.target_prefix != target_prefix);
+        if keep_one_in > 1 {
+            self.rules.push(TraceSamplingRule {
+                target_prefix: target_prefix.to_string(),
+                keep_one_in,
+            });
+            self.rules
+                .sort_by(|a, b| a.target_prefix.cmp(&b.target_prefix));
+        }
+        self.counters
+            .retain(|target, _| !target.starts_with(target_prefix));
+    }
+
+    /// Returns whether an event should be kept, updating the counters.
+    fn keep(&mut self, target: &str, level: &Level) -> bool {
+        if *level <= Level::WARN {
+            return true;
+        }
+
+        let Some(keep_one_in) = self
+            .rules
+            .iter()
+            .filter(|r| target.starts_with(&r.target_prefix))
+            .max_by_key(|r| r.target_prefix.len())
+            .map(|r| r.keep_one_in as u64)
+        else {
+            return true;
+        };
+
+        let counter = self.counters.entry(target.to_string()).or_default();
+        let keep = counter.is_multiple_of(keep_one_in);
+        *counter += 1;
+        if !keep {
+            self.sampled_out_count += 1;
+        }
+        keep
+    }
+}
+
+/// A per-layer filter applying the trace sampling rules.
+struct SamplingFilter;
+
+impl<S> Filter<S> for SamplingFilter {
+    fn enabled(&self, _meta: &Metadata<'_>, _cx: &Context<'_, S>) -> bool {
+        true
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, _cx: &Context<'_, S>) -> bool {
+        let meta = event.metadata();
+        SAMPLING.with_borrow_mut(|s| s.keep(meta.target(), meta.level()))
+    }
 }
 
 /// Represents a single log entry with timestamp and message.
 ///
 /// This struct is used to store individual log messages with their
 /// associated timestamps.
-#[derive(CandidType, Serialize, Deserialize, Clone)]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct LogEntry {
     /// The timestamp when the log entry was created (in milliseconds)
     pub timestamp: u64,
+    /// Orders the entries of the same millisecond, 0 for the entries saved before
+    /// it was recorded
+    #[serde(default)]
+    pub seq: u64,
     /// The log message content
     pub message: String,
 }
 
+impl LogEntry {
+    /// Returns the key ordering the entry.
+    pub fn key(&self) -> LogKey {
+        LogKey {
+            timestamp: self.timestamp,
+            seq: self.seq,
+        }
+    }
+}
+
+/// The key of a log entry: its timestamp and its sequence number within that
+/// millisecond, formatted as `<timestamp>:<seq>`.
+#[derive(
+    CandidType, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub struct LogKey {
+    /// The timestamp of the entry, in milliseconds
+    pub timestamp: u64,
+    /// The sequence number of the entry within its millisecond
+    pub seq: u64,
+}
+
+impl fmt::Display for LogKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.timestamp, self.seq)
+    }
+}
+
+impl FromStr for LogKey {
+    type Err = String;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        let (timestamp, seq) = key.split_once(':').unwrap_or((key, "0"));
+        Ok(LogKey {
+            timestamp: timestamp
+                .parse()
+                .map_err(|_| format!("Invalid log key: {key}"))?,
+            seq: seq.parse().map_err(|_| format!("Invalid log key: {key}"))?,
+        })
+    }
+}
+
 /// A writer implementation for the logging system.
 ///
 /// This struct handles the actual writing of log messages to the appropriate
@@ -229,8 +777,11 @@ impl Write for LogWriter {
         let buffer = std::mem::take(&mut self.buffer);
         let json = String::from_utf8(buffer).unwrap();
 
+        // The key formatted by the timer for this event, if any.
+        let key = PENDING_KEY.take().unwrap_or_else(next_key);
         let log_entry = LogEntry {
-            timestamp: bity_ic_canister_time::timestamp_millis(),
+            timestamp: key.timestamp,
+            seq: key.seq,
             message: json,
         };
 
@@ -246,12 +797,352 @@ impl Write for LogWriter {
 
 /// A timer implementation for log timestamps.
 ///
-/// This struct provides timestamp formatting for log entries.
+/// This struct formats the key of the entry being written, which is then used
+/// by [`LogWriter::flush`] so that the message and the entry agree.
 struct Timer;
 
 impl FormatTime for Timer {
     fn format_time(&self, w: &mut Writer) -> std::fmt::Result {
-        let now = bity_ic_canister_time::timestamp_millis();
-        w.write_str(&format!("{now}"))
+        let key = next_key();
+        PENDING_KEY.set(Some(key));
+        w.write_str(&key.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_trace_sampling() {
+        init(true);
+        set_trace_sampling("hot_path", 100);
+
+        assert_eq!(
+            trace_sampling(),
+            vec![TraceSamplingRule {
+                target_prefix: "hot_path".to_string(),
+                keep_one_in: 100,
+            }]
+        );
+
+        for i in 0..1000 {
+            tracing::trace!(target: "hot_path::is_throttling", "event {i}");
+        }
+        tracing::warn!(target: "hot_path::is_throttling", "kept warning");
+
+        let sampled = export_traces()
+            .iter()
+            .filter(|t| t.message.contains("hot_path::is_throttling"))
+            .count();
+        assert_eq!(sampled, 11);
+        assert_eq!(logger_stats().sampled_out_count, 990);
+
+        set_trace_sampling("hot_path", 1);
+        assert!(trace_sampling().is_empty());
+    }
+
+    #[test]
+    fn test_export_page_snapshot() {
+        bity_ic_canister_time::set_mock_time(42_000_000);
+        for (seq, message) in ["before the cursor", "after the cursor"].iter().enumerate() {
+            LOG.with_borrow_mut(|l| {
+                l.append(LogEntry {
+                    timestamp: 1,
+                    seq: seq as u64,
+                    message: message.to_string(),
+                })
+            });
+        }
+        let after = export_logs().first().map(LogEntry::key);
+
+        let page = export_logs_page(ExportLogsArgs {
+            after,
+            include_snapshot: false,
+        });
+        assert_eq!(page.entries.len(), 1);
+        assert!(page.entries[0].message.contains("after the cursor"));
+        assert!(page.snapshot.is_none());
+
+        let page = export_logs_page(ExportLogsArgs {
+            after,
+            include_snapshot: true,
+        });
+        let snapshot = page.snapshot.unwrap();
+        // Off-chain there is no stable memory to measure, so the figure is zero
+        // rather than a trap, as is the rest of the runtime.
+        assert_eq!(snapshot.stable_bytes, 0);
+        assert_eq!(snapshot.cycles_balance, 0);
+        assert_eq!(snapshot.heap_bytes, 0);
+        assert_eq!(snapshot.timestamp, 42);
+        assert_eq!(page.entries.len(), 1);
+    }
+
+    fn entry(message: &str) -> LogEntry {
+        LogEntry {
+            timestamp: 0,
+            seq: 0,
+            message: message.to_string(),
+        }
+    }
+
+    fn message_key(entry: &LogEntry) -> LogKey {
+        let message: serde_json::Value = serde_json::from_str(&entry.message).unwrap();
+        message["timestamp"].as_str().unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn test_keys_are_strictly_increasing() {
+        set_log_config(LogConfig {
+            max_entries: 1_000,
+            max_total_bytes: None,
+        });
+        // With the clock stopped, only the sequence numbers tell entries apart.
+        let start_ms = 1_700_000_000_000;
+        bity_ic_canister_time::set_mock_time(start_ms * 1_000_000);
+        tracing::subscriber::with_default(subscriber(true), || {
+            for i in 0..500 {
+                tracing::info!("event {i}");
+            }
+        });
+
+        let logs = export_logs();
+        let traces = export_traces();
+        assert_eq!(logs.len(), 500);
+        assert_eq!(traces.len(), 500);
+        assert!(logs.windows(2).all(|pair| pair[0].key() < pair[1].key()));
+        assert!(logs.iter().all(|log| log.timestamp == start_ms));
+        assert!(logs.iter().any(|log| log.seq > 0));
+
+        // The trace of an event is written after its log, with its own key.
+        let mut keys: Vec<LogKey> = logs.iter().chain(&traces).map(LogEntry::key).collect();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), 1_000);
+
+        for entry in logs.iter().chain(&traces) {
+            assert_eq!(message_key(entry), entry.key());
+        }
+
+        let cursor = logs[249].key();
+        let after = export_logs_after(Some(cursor));
+        assert_eq!(after.len(), 250);
+        assert!(after.iter().all(|log| log.key() > cursor));
+
+        // Once the clock moves on, the sequence starts over.
+        bity_ic_canister_time::advance_mock_time(Duration::from_millis(1));
+        assert_eq!(
+            next_key(),
+            LogKey {
+                timestamp: start_ms + 1,
+                seq: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_restored_entries_are_keyed_before_new_ones() {
+        let saved: Vec<LogEntry> = serde_json::from_str(
+            r#"[{"timestamp":5,"message":"old"},{"timestamp":4,"message":"older"}]"#,
+        )
+        .unwrap();
+        assert!(saved.iter().all(|entry| entry.seq == 0));
+        let future = LogEntry {
+            timestamp: u64::MAX / 2,
+            seq: 3,
+            message: "from a faster clock".to_string(),
+        };
+        restore_logs(saved, vec![future.clone()]);
+
+        let restored: Vec<_> = export_logs().iter().map(|e| e.message.clone()).collect();
+        assert_eq!(restored, vec!["older", "old"]);
+
+        let key = next_key();
+        assert_eq!(
+            key,
+            LogKey {
+                timestamp: u64::MAX / 2,
+                seq: 4
+            }
+        );
+        assert_eq!(
+            "12:3".parse(),
+            Ok(LogKey {
+                timestamp: 12,
+                seq: 3
+            })
+        );
+        assert_eq!(
+            "12".parse(),
+            Ok(LogKey {
+                timestamp: 12,
+                seq: 0
+            })
+        );
+        assert!("12:x".parse::<LogKey>().is_err());
+    }
+
+    #[test]
+    fn test_byte_limit_evicts_before_entry_limit() {
+        let mut buffer = LogBuffer::with_config(&LogConfig {
+            max_entries: 10,
+            max_total_bytes: Some(100),
+        });
+
+        for _ in 0..5 {
+            buffer.append(entry("small"));
+        }
+        assert_eq!(buffer.len(), 5);
+        assert_eq!(buffer.total_bytes(), 25);
+
+        // 25 + 80 > 100: the oldest small entries make room for the large one.
+        buffer.append(entry(&"x".repeat(80)));
+        assert_eq!(buffer.len(), 5);
+        assert_eq!(buffer.total_bytes(), 100);
+
+        buffer.append(entry(&"y".repeat(30)));
+        let messages: Vec<_> = buffer.iter().map(|e| e.message.len()).collect();
+        assert_eq!(messages, vec![30]);
+        assert_eq!(buffer.total_bytes(), 30);
+
+        // The entry count still applies to small entries.
+        for _ in 0..20 {
+            buffer.append(entry("a"));
+        }
+        assert_eq!(buffer.len(), 10);
+        assert_eq!(buffer.total_bytes(), 10);
+    }
+
+    #[test]
+    fn test_oversized_entry_is_truncated() {
+        let mut buffer = LogBuffer::with_config(&LogConfig {
+            max_entries: 10,
+            max_total_bytes: Some(20),
+        });
+        buffer.append(entry("kept?"));
+        buffer.append(entry(&"é".repeat(50)));
+
+        let messages: Vec<_> = buffer.iter().map(|e| e.message.clone()).collect();
+        assert_eq!(
+            messages,
+            vec![format!("{}{TRUNCATED_MARKER}", "é".repeat(4))]
+        );
+        assert_eq!(buffer.total_bytes(), 20);
+
+        let mut tiny = LogBuffer::with_config(&LogConfig {
+            max_entries: 10,
+            max_total_bytes: Some(4),
+        });
+        tiny.append(entry("abcdefgh"));
+        assert_eq!(tiny.iter().next().unwrap().message, "abcd");
+    }
+
+    #[test]
+    fn test_stats_track_bytes_after_evictions() {
+        set_log_config(LogConfig {
+            max_entries: 3,
+            max_total_bytes: Some(50),
+        });
+        for i in 0..10 {
+            LOG.with_borrow_mut(|l| l.append(entry(&format!("log {i}"))));
+        }
+        TRACE.with_borrow_mut(|t| t.append(entry(&"t".repeat(70))));
+
+        let stats = logger_stats();
+        assert_eq!(stats.log_bytes, 15);
+        assert_eq!(stats.trace_bytes, 50);
+
+        set_log_config(LogConfig {
+            max_entries: 1,
+            max_total_bytes: None,
+        });
+        let stats = logger_stats();
+        assert_eq!(stats.log_bytes, 5);
+        assert_eq!(export_logs()[0].message, "log 9");
+        assert_eq!(stats.trace_bytes, 50);
+    }
+
+    #[test]
+    fn test_exports_are_capped() {
+        let mut buffer = LogBuffer::with_capacity(1_000);
+        for i in 0..1_000u64 {
+            buffer.append(LogEntry {
+                timestamp: i / 3,
+                seq: i % 3,
+                message: format!("{i:04}{}", "m".repeat(2_000)),
+            });
+        }
+        let max_bytes = 50_000;
+        let page_size = |entries: &[LogEntry]| entries.iter().map(export_size).sum::<usize>();
+
+        // Paging from the oldest entry yields every entry once, in key order.
+        let mut read = vec![];
+        let mut after = None;
+        loop {
+            let (entries, truncated) = buffer.export_page(after, max_bytes);
+            assert!(page_size(&entries) <= max_bytes);
+            read.extend(entries.iter().map(|e| e.message[..4].to_string()));
+            if !truncated {
+                break;
+            }
+            after = entries.last().map(LogEntry::key);
+        }
+        let expected: Vec<String> = (0..1_000).map(|i| format!("{i:04}")).collect();
+        assert_eq!(read, expected);
+
+        // The legacy export keeps the newest entries.
+        let (newest, truncated) = buffer.export_newest(max_bytes);
+        assert!(truncated);
+        assert!(page_size(&newest) <= max_bytes);
+        assert!(newest.last().unwrap().message.starts_with("0999"));
+        assert!(newest.windows(2).all(|pair| pair[0].key() < pair[1].key()));
+        let (all, truncated) = buffer.export_newest(usize::MAX);
+        assert!(!truncated);
+        assert_eq!(all.len(), 1_000);
+
+        // An entry larger than a page is cut so that paging still progresses.
+        let (entries, truncated) = buffer.export_page(None, 1_000);
+        assert!(truncated);
+        assert_eq!(entries.len(), 1);
+        assert!(page_size(&entries) <= 1_000);
+        assert!(entries[0].message.ends_with(TRUNCATED_MARKER));
+    }
+
+    #[test]
+    fn test_export_page_flags_truncation() {
+        set_log_config(LogConfig {
+            max_entries: 1_000,
+            max_total_bytes: None,
+        });
+        for i in 0..500u64 {
+            LOG.with_borrow_mut(|l| {
+                l.append(LogEntry {
+                    timestamp: 10,
+                    seq: i,
+                    message: "x".repeat(5_000),
+                })
+            });
+        }
+
+        let page = export_logs_page(ExportLogsArgs::default());
+        assert!(page.truncated);
+        assert_eq!(page.next_after, page.entries.last().map(LogEntry::key));
+        let size: usize = page.entries.iter().map(export_size).sum();
+        assert!(size <= MAX_EXPORT_BYTES);
+        assert!(candid::encode_one(&page).unwrap().len() < 2 * 1024 * 1024);
+
+        let rest = export_logs_page(ExportLogsArgs {
+            after: page.next_after,
+            include_snapshot: false,
+        });
+        assert!(!rest.truncated);
+        assert_eq!(rest.next_after, None);
+        assert_eq!(page.entries.len() + rest.entries.len(), 500);
+
+        let newest = export_logs();
+        assert_eq!(newest.len(), page.entries.len());
+        assert_eq!(newest.last().unwrap().seq, 499);
+        assert_eq!(export_all_logs().len(), 500);
     }
 }
@@ -0,0 +1,66 @@
+//! Snapshot of the canister runtime, attached to the exported log pages.
+//!
+//! Cycles and memory usage are what is looked up next when reading the logs of
+//! an incident, [`runtime_snapshot`] captures them at the time of the export.
+//! Off-chain, e.g. in unit tests, the runtime figures are zeros.
+
+use bity_ic_canister_time::Millis;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// The cycle balance and memory usage of the canister at a given time.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RuntimeSnapshot {
+    /// The cycle balance of the canister
+    pub cycles_balance: u128,
+    /// The size of the wasm heap, in bytes
+    pub heap_bytes: u64,
+    /// The size of the stable memory, in bytes
+    pub stable_bytes: u64,
+    /// When the snapshot was captured, in milliseconds
+    pub timestamp: u64,
+}
+
+/// Captures the cycle balance and memory usage of the canister.
+///
+/// # Returns
+/// The current `RuntimeSnapshot`
+pub fn runtime_snapshot() -> RuntimeSnapshot {
+    RuntimeSnapshot {
+        cycles_balance: cycles_balance(),
+        heap_bytes: heap_bytes(),
+        stable_bytes: stable_bytes(),
+        timestamp: Millis::from(bity_ic_canister_time::timestamp_millis()).get(),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn cycles_balance() -> u128 {
+    ic_cdk::api::canister_cycle_balance()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn cycles_balance() -> u128 {
+    0
+}
+
+#[cfg(target_arch = "wasm32")]
+fn heap_bytes() -> u64 {
+    (core::arch::wasm32::memory_size(0) as u64) * 65536
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn heap_bytes() -> u64 {
+    0
+}
+
+#[cfg(target_arch = "wasm32")]
+fn stable_bytes() -> u64 {
+    bity_ic_stable_memory::used()
+}
+
+/// Returns 0, as there is no stable memory off-chain.
+#[cfg(not(target_arch = "wasm32"))]
+fn stable_bytes() -> u64 {
+    0
+}
@@ -3,6 +3,19 @@
 //! This module provides a macro for creating thread-safe state management in canisters,
 //! with functions for initialization, reading, and modifying the state.
 //!
+//! `canister_runtime_state!` builds on the same pattern for canisters split into a
+//! serializable `Data` struct and a `RuntimeState { env, data }` wrapper.
+//!
+//! `canister_state!(MyState; audit(fields = [...]))` also generates
+//! `mutate_state_audited`, which reports what a mutation changed in the listed
+//! fields as an [`AuditRecord`].
+//!
+//! `canister_state!(MyState; guards(...))` also generates the caller guards of the
+//! update and query methods from the principals stored in the state.
+//!
+//! `canister_lifecycle!` generates the `init` and `post_upgrade` entry points, which
+//! validate their arguments before handing them to the canister.
+//!
 //! # Example
 //! ```
 //! use bity_ic_canister_state_macros::canister_state;
@@ -22,6 +35,9 @@
 //! }
 //! ```
 
+use candid::Principal;
+use std::collections::{BTreeSet, HashSet};
+
 /// A macro that generates thread-safe state management functions for a canister.
 ///
 /// This macro creates a set of functions for managing the canister's state in a thread-safe manner.
@@ -56,8 +72,141 @@
 ///     read_state(|state| state.users.len())
 /// }
 /// ```
+///
+/// # Audited mutations
+///
+/// With `audit(fields = [...])`, the macro also generates:
+/// * `set_state_audit_sink<S: Fn(AuditRecord)>(sink: S)` - Registers the callback receiving the audit records
+/// * `mutate_state_audited<F, R>(action: &str, f: F) -> R` - Mutates the state like `mutate_state`,
+///   then passes the fields changed by `f` to the sink, with their old and new values
+///
+/// The audited fields must implement `Clone`, `PartialEq` and `serde::Serialize`. They are
+/// cloned before the mutation and compared after it, and only the changed ones are serialized.
+/// A mutation changing none of them produces no record, and the records are dropped until a
+/// sink is registered.
+///
+/// ```ignore
+/// canister_state!(AppState; audit(fields = [owners, config]));
+///
+/// set_state_audit_sink(|record| record_admin_block(record));
+///
+/// fn set_config(config: Config) {
+///     mutate_state_audited("set_config", |state| state.config = config);
+/// }
+/// ```
+///
+/// # Caller guards
+///
+/// With `guards(...)`, the macro also generates, for each listed role, a guard
+/// checking `ic_cdk::api::msg_caller()` against a field of the state, given by its
+/// path from the state:
+/// * `authorized = path` - `caller_is_authorized() -> Result<(), String>`
+/// * `controllers = path` - `caller_is_controller() -> Result<(), String>`
+///
+/// The field can be a `Principal` or a collection of principals, see [`PrincipalSet`].
+/// A path to a field that doesn't exist does not compile. The error names the caller,
+/// see [`check_caller`].
+///
+/// ```ignore
+/// canister_state!(RuntimeState; guards(
+///     authorized = data.permissions.authorized,
+///     controllers = data.controllers,
+/// ));
+///
+/// #[ic_cdk::update(guard = "caller_is_authorized")]
+/// fn set_config(config: Config) {
+///     mutate_state(|state| state.data.config = config);
+/// }
+/// ```
 #[macro_export]
 macro_rules! canister_state {
+    // Matched first, the other arms starting with a type.
+    (@guard authorized $($field:ident).+) => {
+        /// Checks that the caller is an authorized principal, as a method guard.
+        ///
+        /// # Panics
+        /// Panics if the state has not been initialized
+        pub fn caller_is_authorized() -> Result<(), String> {
+            let caller = ::ic_cdk::api::msg_caller();
+            read_state(|state| {
+                $crate::check_caller(caller, &state.$($field).+, "an authorized principal")
+            })
+        }
+    };
+    (@guard controllers $($field:ident).+) => {
+        /// Checks that the caller is a controller, as a method guard.
+        ///
+        /// # Panics
+        /// Panics if the state has not been initialized
+        pub fn caller_is_controller() -> Result<(), String> {
+            let caller = ::ic_cdk::api::msg_caller();
+            read_state(|state| $crate::check_caller(caller, &state.$($field).+, "a controller"))
+        }
+    };
+    ($type:ty; guards($($role:ident = $($field:ident).+),+ $(,)?)) => {
+        $crate::canister_state!($type);
+
+        $( $crate::canister_state!(@guard $role $($field).+); )+
+    };
+    ($type:ty; audit(fields = [$($field:ident),+ $(,)?])) => {
+        $crate::canister_state!($type);
+
+        thread_local! {
+            static __STATE_AUDIT_SINK: std::cell::RefCell<Option<Box<dyn Fn($crate::AuditRecord)>>> = std::cell::RefCell::default();
+        }
+
+        /// Registers the callback receiving the records of `mutate_state_audited`,
+        /// replacing the previous one.
+        pub fn set_state_audit_sink<S>(sink: S)
+        where
+            S: Fn($crate::AuditRecord) + 'static,
+        {
+            __STATE_AUDIT_SINK.set(Some(Box::new(sink)));
+        }
+
+        /// Mutates the state using a closure, and passes the audited fields it
+        /// changed to the audit sink.
+        ///
+        /// # Arguments
+        /// * `action` - The name of the mutation, e.g. `set_config`
+        /// * `f` - A closure that takes a mutable reference to the state and returns a value
+        ///
+        /// # Returns
+        /// The result of the closure
+        ///
+        /// # Panics
+        /// Panics if the state has not been initialized
+        pub fn mutate_state_audited<F, R>(action: &str, f: F) -> R
+        where
+            F: FnOnce(&mut $type) -> R,
+        {
+            let (result, changes) = mutate_state(|state| {
+                $( let $field = state.$field.clone(); )+
+                let result = f(state);
+                let mut changes = Vec::new();
+                $(
+                    if state.$field != $field {
+                        changes.push($crate::FieldChange::new(stringify!($field), &$field, &state.$field));
+                    }
+                )+
+                (result, changes)
+            });
+
+            // The sink is called once the state is released, so that it can read it.
+            if !changes.is_empty() {
+                let record = $crate::AuditRecord {
+                    action: action.to_string(),
+                    changes,
+                };
+                __STATE_AUDIT_SINK.with_borrow(|sink| {
+                    if let Some(sink) = sink {
+                        sink(record);
+                    }
+                });
+            }
+            result
+        }
+    };
     ($type:ty) => {
         thread_local! {
             static __STATE: std::cell::RefCell<Option<$type>> = std::cell::RefCell::default();
@@ -143,3 +292,669 @@ macro_rules! canister_state {
         }
     };
 }
+
+/// A macro that generates the `RuntimeState { env, data }` wrapper of a canister
+/// and its thread-safe state management functions.
+///
+/// The generated `RuntimeState` struct is serializable, so that it can be saved
+/// and restored across upgrades. The environment type defaults to
+/// `bity_ic_utils::env::CanisterEnv` and must implement
+/// `bity_ic_utils::env::Environment`.
+///
+/// # Arguments
+/// * `$data` - The type of the canister data
+/// * `$state` - The name of the runtime state struct to generate
+/// * `$env` - The type of the environment (optional)
+///
+/// # Generated Functions
+/// * `init_state(env: $env, data: $data)` - Initializes the state (panics if already initialized)
+/// * `replace_state(state: $state) -> $state` - Replaces the current state and returns the old one
+/// * `take_state() -> $state` - Takes ownership of the current state
+/// * `read_state<F, R>(f: F) -> R` - Reads the runtime state using a closure
+/// * `mutate_state<F, R>(f: F) -> R` - Mutates the runtime state using a closure
+/// * `with_data<F, R>(f: F) -> R` - Reads the data using a closure
+/// * `with_data_mut<F, R>(f: F) -> R` - Mutates the data using a closure
+/// * `is_caller_authorized<F>(principals: F) -> bool` - Checks the caller against a list of principals
+///
+/// # Example
+/// ```ignore
+/// use bity_ic_canister_state_macros::canister_runtime_state;
+///
+/// #[derive(Serialize, Deserialize)]
+/// pub struct Data {
+///     pub authorized_principals: Vec<Principal>,
+///     pub counter: u64,
+/// }
+///
+/// canister_runtime_state!(Data, RuntimeState);
+///
+/// fn increment() -> Result<u64, String> {
+///     if !is_caller_authorized(|data| &data.authorized_principals) {
+///         return Err("Unauthorized".to_string());
+///     }
+///     Ok(with_data_mut(|data| {
+///         data.counter += 1;
+///         data.counter
+///     }))
+/// }
+/// ```
+#[macro_export]
+macro_rules! canister_runtime_state {
+    ($data:ty, $state:ident) => {
+        $crate::canister_runtime_state!($data, $state, ::bity_ic_utils::env::CanisterEnv);
+    };
+    ($data:ty, $state:ident, $env:ty) => {
+        #[derive(::serde::Serialize, ::serde::Deserialize)]
+        pub struct $state {
+            pub env: $env,
+            pub data: $data,
+        }
+
+        impl $state {
+            pub fn new(env: $env, data: $data) -> Self {
+                Self { env, data }
+            }
+        }
+
+        thread_local! {
+            static __RUNTIME_STATE: std::cell::RefCell<Option<$state>> = std::cell::RefCell::default();
+        }
+
+        const __STATE_ALREADY_INITIALIZED: &str = "State has already been initialized";
+        const __STATE_NOT_INITIALIZED: &str = "State has not been initialized";
+
+        /// Initializes the canister state.
+        ///
+        /// # Arguments
+        /// * `env` - The canister environment
+        /// * `data` - The initial canister data
+        ///
+        /// # Panics
+        /// Panics if the state has already been initialized
+        pub fn init_state(env: $env, data: $data) {
+            __RUNTIME_STATE.with_borrow_mut(|s| {
+                if s.is_some() {
+                    panic!("{}", __STATE_ALREADY_INITIALIZED);
+                } else {
+                    *s = Some($state::new(env, data));
+                }
+            });
+        }
+
+        /// Replaces the current state with a new one.
+        ///
+        /// # Arguments
+        /// * `state` - The new state value
+        ///
+        /// # Returns
+        /// The previous state value
+        ///
+        /// # Panics
+        /// Panics if the state has not been initialized
+        pub fn replace_state(state: $state) -> $state {
+            __RUNTIME_STATE
+                .replace(Some(state))
+                .expect(__STATE_NOT_INITIALIZED)
+        }
+
+        /// Takes ownership of the current state.
+        ///
+        /// # Returns
+        /// The current state value
+        ///
+        /// # Panics
+        /// Panics if the state has not been initialized
+        pub fn take_state() -> $state {
+            __RUNTIME_STATE.take().expect(__STATE_NOT_INITIALIZED)
+        }
+
+        /// Reads the runtime state using a closure.
+        ///
+        /// # Panics
+        /// Panics if the state has not been initialized
+        pub fn read_state<F, R>(f: F) -> R
+        where
+            F: FnOnce(&$state) -> R,
+        {
+            __RUNTIME_STATE.with_borrow(|s| f(s.as_ref().expect(__STATE_NOT_INITIALIZED)))
+        }
+
+        /// Mutates the runtime state using a closure.
+        ///
+        /// # Panics
+        /// Panics if the state has not been initialized
+        pub fn mutate_state<F, R>(f: F) -> R
+        where
+            F: FnOnce(&mut $state) -> R,
+        {
+            __RUNTIME_STATE.with_borrow_mut(|s| f(s.as_mut().expect(__STATE_NOT_INITIALIZED)))
+        }
+
+        /// Reads the canister data using a closure.
+        ///
+        /// # Panics
+        /// Panics if the state has not been initialized
+        pub fn with_data<F, R>(f: F) -> R
+        where
+            F: FnOnce(&$data) -> R,
+        {
+            read_state(|s| f(&s.data))
+        }
+
+        /// Mutates the canister data using a closure.
+        ///
+        /// # Panics
+        /// Panics if the state has not been initialized
+        pub fn with_data_mut<F, R>(f: F) -> R
+        where
+            F: FnOnce(&mut $data) -> R,
+        {
+            mutate_state(|s| f(&mut s.data))
+        }
+
+        /// Checks whether the caller is in a list of principals taken from the data.
+        ///
+        /// # Arguments
+        /// * `principals` - A closure returning the authorized principals
+        ///
+        /// # Panics
+        /// Panics if the state has not been initialized
+        pub fn is_caller_authorized<C, F>(principals: F) -> bool
+        where
+            C: ?Sized,
+            F: for<'a> FnOnce(&'a $data) -> &'a C,
+            for<'a> &'a C: IntoIterator<Item = &'a ::candid::Principal>,
+        {
+            read_state(|s| {
+                let caller = ::bity_ic_utils::env::Environment::caller(&s.env);
+                principals(&s.data).into_iter().any(|p| *p == caller)
+            })
+        }
+    };
+}
+
+/// Principals a guard generated by `canister_state!(...; guards(...))` checks the
+/// caller against.
+pub trait PrincipalSet {
+    /// Returns whether `principal` is in the set.
+    fn contains_principal(&self, principal: &Principal) -> bool;
+}
+
+impl PrincipalSet for Principal {
+    fn contains_principal(&self, principal: &Principal) -> bool {
+        self == principal
+    }
+}
+
+impl PrincipalSet for Option<Principal> {
+    fn contains_principal(&self, principal: &Principal) -> bool {
+        self.as_ref() == Some(principal)
+    }
+}
+
+impl PrincipalSet for [Principal] {
+    fn contains_principal(&self, principal: &Principal) -> bool {
+        self.contains(principal)
+    }
+}
+
+impl PrincipalSet for Vec<Principal> {
+    fn contains_principal(&self, principal: &Principal) -> bool {
+        self.contains(principal)
+    }
+}
+
+impl<S: std::hash::BuildHasher> PrincipalSet for HashSet<Principal, S> {
+    fn contains_principal(&self, principal: &Principal) -> bool {
+        self.contains(principal)
+    }
+}
+
+impl PrincipalSet for BTreeSet<Principal> {
+    fn contains_principal(&self, principal: &Principal) -> bool {
+        self.contains(principal)
+    }
+}
+
+/// Checks that `caller` is in `principals`, for the guards generated by
+/// `canister_state!(...; guards(...))`.
+///
+/// # Example
+/// ```
+/// use bity_ic_canister_state_macros::check_caller;
+/// use candid::Principal;
+///
+/// let admin = Principal::from_slice(&[1]);
+/// assert_eq!(check_caller(admin, &vec![admin], "an admin"), Ok(()));
+/// assert_eq!(
+///     check_caller(Principal::anonymous(), &vec![admin], "an admin"),
+///     Err("Caller 2vxsx-fae is not an admin".to_string())
+/// );
+/// ```
+pub fn check_caller<P: PrincipalSet + ?Sized>(
+    caller: Principal,
+    principals: &P,
+    role: &str,
+) -> Result<(), String> {
+    if principals.contains_principal(&caller) {
+        Ok(())
+    } else {
+        Err(format!("Caller {caller} is not {role}"))
+    }
+}
+
+/// A field changed by a mutation made with `mutate_state_audited`.
+///
+/// # Fields
+/// * `field` - The name of the field
+/// * `old` - The value before the mutation, serialized with `bity_ic_serializer`
+/// * `new` - The value after the mutation, serialized with `bity_ic_serializer`
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+impl FieldChange {
+    /// Serializes the old and new values of a changed field.
+    ///
+    /// # Panics
+    /// Panics if a value cannot be serialized, which traps and rolls back the
+    /// mutation rather than leaving it unaudited.
+    pub fn new<T: serde::Serialize>(field: &str, old: &T, new: &T) -> Self {
+        let serialize = |value: &T| {
+            bity_ic_serializer::serialize_to_vec(value).unwrap_or_else(|e| {
+                panic!("Failed to serialize the audited field {}: {}", field, e)
+            })
+        };
+        Self {
+            field: field.to_string(),
+            old: serialize(old),
+            new: serialize(new),
+        }
+    }
+}
+
+/// What a mutation made with `mutate_state_audited` changed in the audited fields.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub action: String,
+    pub changes: Vec<FieldChange>,
+}
+
+/// The lifecycle entry point a canister's arguments are validated for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LifecycleStage {
+    Init,
+    PostUpgrade,
+}
+
+impl std::fmt::Display for LifecycleStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LifecycleStage::Init => write!(f, "init"),
+            LifecycleStage::PostUpgrade => write!(f, "post_upgrade"),
+        }
+    }
+}
+
+/// Formats the message a canister traps with when its lifecycle arguments are invalid.
+///
+/// # Example
+/// ```
+/// use bity_ic_canister_state_macros::{lifecycle_error, LifecycleStage};
+///
+/// assert_eq!(
+///     lifecycle_error("archive", "0.4.0", LifecycleStage::Init, "commit hash is empty"),
+///     "archive 0.4.0: invalid init arguments: commit hash is empty"
+/// );
+/// ```
+pub fn lifecycle_error(
+    canister_name: &str,
+    version: &str,
+    stage: LifecycleStage,
+    error: &str,
+) -> String {
+    format!("{canister_name} {version}: invalid {stage} arguments: {error}")
+}
+
+/// A macro that generates the `init` and `post_upgrade` entry points of a canister.
+///
+/// Both entry points decode the lifecycle arguments, validate them and trap with a
+/// message made by [`lifecycle_error`] if they are invalid, so that the install or
+/// upgrade fails cleanly and the canister is left as it was. The message names the
+/// canister crate and its version. Valid arguments are then handed to the hooks,
+/// which build or restore the state, and the canister logger is initialized with
+/// what the hooks return.
+///
+/// The caller needs `ic-cdk`, `tracing` and `bity-ic-canister-logger` as dependencies.
+///
+/// # Arguments
+/// * `$args` - The type of the lifecycle arguments, usually an `Args` enum
+/// * `validate` - A `fn(&$args, LifecycleStage) -> Result<(), String>`
+/// * `on_init` - A `fn($args) -> bool` initializing the state, returning the test mode
+/// * `on_post_upgrade` - A `fn($args) -> (bool, Vec<LogEntry>, Vec<LogEntry>)`
+///   restoring the state, returning the test mode with the restored logs and traces
+///
+/// The logger is initialized once the hook returns, so events logged by the hooks
+/// themselves are not kept.
+///
+/// # Example
+/// ```ignore
+/// use bity_ic_canister_state_macros::{canister_lifecycle, LifecycleStage};
+///
+/// canister_lifecycle!(
+///     Args,
+///     validate = validate_args,
+///     on_init = on_init,
+///     on_post_upgrade = on_post_upgrade
+/// );
+///
+/// fn validate_args(args: &Args, stage: LifecycleStage) -> Result<(), String> {
+///     match (args, stage) {
+///         (Args::Init(args), LifecycleStage::Init) if args.commit_hash.is_empty() => {
+///             Err("commit hash is empty".to_string())
+///         }
+///         (Args::Init(_), LifecycleStage::Init) => Ok(()),
+///         (Args::Upgrade(_), LifecycleStage::PostUpgrade) => Ok(()),
+///         _ => Err("wrong argument variant".to_string()),
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! canister_lifecycle {
+    (
+        $args:ty,
+        validate = $validate:path,
+        on_init = $on_init:path,
+        on_post_upgrade = $on_post_upgrade:path $(,)?
+    ) => {
+        fn __validate_lifecycle_args(args: &$args, stage: $crate::LifecycleStage) {
+            if let Err(error) = $validate(args, stage) {
+                ::ic_cdk::trap($crate::lifecycle_error(
+                    env!("CARGO_PKG_NAME"),
+                    env!("CARGO_PKG_VERSION"),
+                    stage,
+                    &error,
+                ));
+            }
+        }
+
+        #[::ic_cdk::init]
+        fn init(args: $args) {
+            __validate_lifecycle_args(&args, $crate::LifecycleStage::Init);
+            let test_mode = $on_init(args);
+            ::bity_ic_canister_logger::init(test_mode);
+            ::tracing::info!("Init complete.");
+        }
+
+        #[::ic_cdk::post_upgrade]
+        fn post_upgrade(args: $args) {
+            __validate_lifecycle_args(&args, $crate::LifecycleStage::PostUpgrade);
+            let (test_mode, logs, traces) = $on_post_upgrade(args);
+            ::bity_ic_canister_logger::init_with_logs(test_mode, logs, traces);
+            ::tracing::info!("Post-upgrade complete.");
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    mod reference_canister {
+        use bity_ic_stable_memory::{restore_state, save_state, RestoreOutcome};
+        use bity_ic_types::{CanisterId, Cycles, TimestampNanos};
+        use bity_ic_utils::env::Environment;
+        use candid::Principal;
+        use ic_stable_structures::DefaultMemoryImpl;
+        use serde::{Deserialize, Serialize};
+        use std::cell::RefCell;
+        use std::io;
+
+        #[derive(Serialize, Deserialize)]
+        pub struct TestEnv {
+            pub caller: Principal,
+        }
+
+        impl Environment for TestEnv {
+            fn now_nanos(&self) -> TimestampNanos {
+                0
+            }
+
+            fn caller(&self) -> Principal {
+                self.caller
+            }
+
+            fn canister_id(&self) -> CanisterId {
+                Principal::anonymous()
+            }
+
+            fn cycles_balance(&self) -> Cycles {
+                0
+            }
+        }
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Data {
+            pub authorized_principals: Vec<Principal>,
+            pub counter: u64,
+        }
+
+        canister_runtime_state!(Data, RuntimeState, TestEnv);
+
+        thread_local! {
+            static UPGRADES_MEMORY: RefCell<DefaultMemoryImpl> = RefCell::default();
+        }
+
+        pub fn init(caller: Principal, authorized_principals: Vec<Principal>) {
+            init_state(
+                TestEnv { caller },
+                Data {
+                    authorized_principals,
+                    counter: 0,
+                },
+            );
+        }
+
+        pub fn pre_upgrade() {
+            let state = take_state();
+            UPGRADES_MEMORY.with_borrow_mut(|memory| {
+                save_state(memory, |writer| {
+                    bity_ic_serializer::serialize(state, writer)
+                        .map_err(|e| io::Error::other(e.to_string()))
+                })
+                .unwrap();
+            });
+        }
+
+        pub fn post_upgrade(caller: Principal) -> Result<(), String> {
+            let outcome = UPGRADES_MEMORY.with_borrow(|memory| {
+                restore_state(memory, |reader| {
+                    bity_ic_serializer::deserialize::<RuntimeState, _>(reader)
+                        .map_err(|e| e.to_string())
+                })
+            })?;
+            match outcome {
+                RestoreOutcome::Restored(state) => {
+                    init_state(TestEnv { caller }, state.data);
+                    Ok(())
+                }
+                RestoreOutcome::FreshInstall => Err("No state to restore".to_string()),
+            }
+        }
+
+        // query
+        pub fn get_counter() -> u64 {
+            with_data(|data| data.counter)
+        }
+
+        // update
+        pub fn increment() -> Result<u64, String> {
+            if !is_caller_authorized(|data| &data.authorized_principals) {
+                return Err("Unauthorized".to_string());
+            }
+            Ok(with_data_mut(|data| {
+                data.counter += 1;
+                data.counter
+            }))
+        }
+    }
+
+    #[test]
+    fn test_runtime_state_reference_canister() {
+        use candid::Principal;
+        use reference_canister::*;
+
+        let admin = Principal::from_slice(&[1]);
+        let other = Principal::from_slice(&[2]);
+
+        // Upgrading from a canister that never saved its state fails loudly.
+        assert_eq!(post_upgrade(other), Err("No state to restore".to_string()));
+
+        init(admin, vec![admin]);
+        assert_eq!(increment(), Ok(1));
+        assert_eq!(increment(), Ok(2));
+        assert_eq!(get_counter(), 2);
+
+        pre_upgrade();
+        assert_eq!(post_upgrade(other), Ok(()));
+
+        assert_eq!(get_counter(), 2);
+        assert!(read_state(|s| s.env.caller == other));
+        assert_eq!(increment(), Err("Unauthorized".to_string()));
+
+        let previous = replace_state(RuntimeState::new(
+            TestEnv { caller: admin },
+            Data {
+                authorized_principals: vec![admin],
+                counter: 10,
+            },
+        ));
+        assert_eq!(previous.data.counter, 2);
+        assert_eq!(increment(), Ok(11));
+    }
+
+    #[allow(dead_code)]
+    mod audited_canister {
+        use serde::{Serialize, Serializer};
+        use std::cell::Cell;
+
+        thread_local! {
+            pub static SERIALIZED: Cell<u32> = const { Cell::new(0) };
+        }
+
+        // Counts its serializations, to check that unchanged fields are skipped.
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct Counted(pub u64);
+
+        impl Serialize for Counted {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                SERIALIZED.set(SERIALIZED.get() + 1);
+                self.0.serialize(serializer)
+            }
+        }
+
+        pub struct State {
+            pub owners: Vec<String>,
+            pub config: Counted,
+            pub counter: u64,
+        }
+
+        canister_state!(State; audit(fields = [owners, config]));
+    }
+
+    #[test]
+    fn test_audited_mutation_reports_only_the_changed_fields() {
+        use audited_canister::*;
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        init_state(State {
+            owners: vec!["alice".to_string()],
+            config: Counted(1),
+            counter: 0,
+        });
+        let records = Rc::new(RefCell::new(Vec::new()));
+        let sink = records.clone();
+        set_state_audit_sink(move |record| sink.borrow_mut().push(record));
+
+        let result = mutate_state_audited("add_owner", |state| {
+            state.owners.push("bob".to_string());
+            state.counter += 1;
+            state.owners.len()
+        });
+        assert_eq!(result, 2);
+
+        let reported = records.take();
+        assert_eq!(reported.len(), 1);
+        assert_eq!(reported[0].action, "add_owner");
+        let changes = &reported[0].changes;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].field, "owners");
+        let old: Vec<String> = bity_ic_serializer::deserialize_from_slice(&changes[0].old).unwrap();
+        let new: Vec<String> = bity_ic_serializer::deserialize_from_slice(&changes[0].new).unwrap();
+        assert_eq!(old, vec!["alice".to_string()]);
+        assert_eq!(new, vec!["alice".to_string(), "bob".to_string()]);
+        assert_eq!(SERIALIZED.get(), 0);
+
+        // A mutation of unaudited fields only is not reported.
+        mutate_state_audited("increment", |state| state.counter += 1);
+        assert_eq!(read_state(|state| state.counter), 2);
+        assert!(records.borrow().is_empty());
+
+        mutate_state_audited("set_config", |state| state.config = Counted(2));
+        assert_eq!(records.borrow()[0].changes[0].field, "config");
+        assert_eq!(SERIALIZED.get(), 2);
+    }
+
+    #[allow(dead_code)]
+    mod guarded_canister {
+        use candid::Principal;
+        use std::collections::BTreeSet;
+
+        pub struct Permissions {
+            pub authorized: Vec<Principal>,
+        }
+
+        pub struct Data {
+            pub permissions: Permissions,
+            pub controllers: BTreeSet<Principal>,
+        }
+
+        pub struct State {
+            pub data: Data,
+        }
+
+        canister_state!(State; guards(
+            authorized = data.permissions.authorized,
+            controllers = data.controllers,
+        ));
+    }
+
+    #[test]
+    fn test_caller_guards() {
+        use super::check_caller;
+        use candid::Principal;
+        use std::collections::{BTreeSet, HashSet};
+
+        // The guards only run on-chain, they can be used as method guards.
+        let _: fn() -> Result<(), String> = guarded_canister::caller_is_authorized;
+        let _: fn() -> Result<(), String> = guarded_canister::caller_is_controller;
+
+        let admin = Principal::from_slice(&[1]);
+        let other = Principal::from_slice(&[2]);
+        assert_eq!(check_caller(admin, &vec![admin], "authorized"), Ok(()));
+        assert_eq!(check_caller(admin, &admin, "the main canister"), Ok(()));
+        assert_eq!(check_caller(admin, &Some(admin), "the owner"), Ok(()));
+        assert_eq!(
+            check_caller(admin, &BTreeSet::from([admin]), "a controller"),
+            Ok(())
+        );
+        assert_eq!(
+            check_caller(other, &HashSet::from([admin]), "a controller"),
+            Err(format!("Caller {other} is not a controller"))
+        );
+        assert!(check_caller(other, &None, "the owner").is_err());
+        assert!(check_caller(other, [admin].as_slice(), "authorized").is_err());
+    }
+}
@@ -4,6 +4,14 @@
 //! timestamp functions, time constants, and timer scheduling functions. It supports
 //! both WASM and non-WASM environments.
 //!
+//! The timestamp functions return `u64` by default. With the `typed-time` feature
+//! they return the [`Seconds`], [`Millis`] and [`Nanos`] newtypes instead, which
+//! can't be mixed up across units. The feature is off by default for one release,
+//! so that dependents can move to [`SecondsTimestamp`], [`MillisTimestamp`] and
+//! [`NanosTimestamp`] before it becomes the default. A call site that needs a
+//! `u64` either way can go through the newtype, e.g.
+//! `Nanos::from(timestamp_nanos()).get()`.
+//!
 //! # Example
 //! ```
 //! use icrc7_nft::libraries::canister_time::{timestamp_millis, run_interval};
@@ -15,7 +23,12 @@
 //! });
 //! ```
 
+use candid::CandidType;
 use ic_cdk_timers::TimerId;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashSet};
+use std::rc::Rc;
 use std::time::Duration;
 
 use bity_ic_types::{Milliseconds, Second, TimestampMillis, TimestampNanos};
@@ -38,20 +51,52 @@ pub const DAY_IN_SECONDS: Second = 24 * 60 * 60;
 /// Number of nanoseconds in one millisecond
 pub const NANOS_PER_MILLISECOND: u64 = 1_000_000;
 
+/// Weekday of the Unix epoch, 1970-01-01 being a Thursday (0 = Monday)
+const EPOCH_WEEKDAY: u64 = 3;
+
+pub use bity_ic_types::{Millis, Nanos, Seconds};
+
+/// The timestamps in seconds returned by this crate: [`Seconds`] with the
+/// `typed-time` feature, `u64` otherwise.
+#[cfg(feature = "typed-time")]
+pub type SecondsTimestamp = Seconds;
+/// The timestamps in seconds returned by this crate: [`Seconds`] with the
+/// `typed-time` feature, `u64` otherwise.
+#[cfg(not(feature = "typed-time"))]
+pub type SecondsTimestamp = u64;
+
+/// The timestamps in milliseconds returned by this crate: [`Millis`] with the
+/// `typed-time` feature, [`TimestampMillis`] otherwise.
+#[cfg(feature = "typed-time")]
+pub type MillisTimestamp = Millis;
+/// The timestamps in milliseconds returned by this crate: [`Millis`] with the
+/// `typed-time` feature, [`TimestampMillis`] otherwise.
+#[cfg(not(feature = "typed-time"))]
+pub type MillisTimestamp = TimestampMillis;
+
+/// The timestamps in nanoseconds returned by this crate: [`Nanos`] with the
+/// `typed-time` feature, [`TimestampNanos`] otherwise.
+#[cfg(feature = "typed-time")]
+pub type NanosTimestamp = Nanos;
+/// The timestamps in nanoseconds returned by this crate: [`Nanos`] with the
+/// `typed-time` feature, [`TimestampNanos`] otherwise.
+#[cfg(not(feature = "typed-time"))]
+pub type NanosTimestamp = TimestampNanos;
+
 /// Returns the current timestamp in seconds.
 ///
 /// # Returns
 /// The current Unix timestamp in seconds
-pub fn timestamp_seconds() -> u64 {
-    timestamp_nanos() / 1_000_000_000
+pub fn timestamp_seconds() -> SecondsTimestamp {
+    Nanos(raw_timestamp_nanos()).to_seconds().into()
 }
 
 /// Returns the current timestamp in milliseconds.
 ///
 /// # Returns
 /// The current Unix timestamp in milliseconds
-pub fn timestamp_millis() -> u64 {
-    timestamp_nanos() / 1_000_000
+pub fn timestamp_millis() -> MillisTimestamp {
+    Nanos(raw_timestamp_nanos()).to_millis().into()
 }
 
 /// Returns the current timestamp in microseconds.
@@ -59,28 +104,75 @@ pub fn timestamp_millis() -> u64 {
 /// # Returns
 /// The current Unix timestamp in microseconds
 pub fn timestamp_micros() -> u64 {
-    timestamp_nanos() / 1_000
+    raw_timestamp_nanos() / 1_000
 }
 
-/// Returns the current timestamp in nanoseconds (WASM implementation).
-///
-/// This function is only available when targeting the WASM architecture.
+/// Returns the current timestamp in nanoseconds.
 ///
 /// # Returns
-/// The current timestamp in nanoseconds
+/// The current Unix timestamp in nanoseconds, from the mock clock off-chain, see
+/// [`set_mock_time`]
+pub fn timestamp_nanos() -> NanosTimestamp {
+    Nanos(raw_timestamp_nanos()).into()
+}
+
 #[cfg(target_arch = "wasm32")]
-pub fn timestamp_nanos() -> u64 {
+fn raw_timestamp_nanos() -> u64 {
     unsafe { ic0::time() as u64 }
 }
 
-/// Returns the current timestamp in nanoseconds (non-WASM implementation).
+#[cfg(not(target_arch = "wasm32"))]
+fn raw_timestamp_nanos() -> u64 {
+    mock_time_nanos()
+}
+
+/// Returns the current time in milliseconds.
 ///
-/// This function is only available when not targeting the WASM architecture.
+/// # Returns
+/// The current time in milliseconds since the Unix epoch
+pub fn now_millis() -> MillisTimestamp {
+    Millis(raw_now_millis()).into()
+}
+
+/// Returns the current time in nanoseconds.
 ///
 /// # Returns
-/// The current Unix timestamp in nanoseconds
+/// The current time in nanoseconds since the Unix epoch, from the mock clock
+/// off-chain, see [`set_mock_time`]
+pub fn now_nanos() -> NanosTimestamp {
+    Nanos(raw_now_nanos()).into()
+}
+
+fn raw_now_millis() -> TimestampMillis {
+    raw_now_nanos() / NANOS_PER_MILLISECOND
+}
+
+#[cfg(target_arch = "wasm32")]
+fn raw_now_nanos() -> TimestampNanos {
+    ic_cdk::api::time()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn raw_now_nanos() -> TimestampNanos {
+    mock_time_nanos()
+}
+
+// Mock clock used off-chain, local to the current thread. Until it is set, it
+// follows the system time, or stays at 0 with the `deterministic-time` feature.
 #[cfg(not(target_arch = "wasm32"))]
-pub fn timestamp_nanos() -> u64 {
+thread_local! {
+    static MOCK_NOW: std::cell::Cell<Option<TimestampNanos>> = const { std::cell::Cell::new(None) };
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn mock_time_nanos() -> TimestampNanos {
+    MOCK_NOW
+        .with(|now| now.get())
+        .unwrap_or_else(unset_mock_time_nanos)
+}
+
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "deterministic-time")))]
+fn unset_mock_time_nanos() -> TimestampNanos {
     use std::time::SystemTime;
 
     SystemTime::now()
@@ -89,34 +181,32 @@ pub fn timestamp_nanos() -> u64 {
         .as_nanos() as u64
 }
 
-/// Returns the current time in milliseconds.
-///
-/// # Returns
-/// The current time in milliseconds since the Unix epoch
-pub fn now_millis() -> TimestampMillis {
-    now_nanos() / NANOS_PER_MILLISECOND
+#[cfg(all(not(target_arch = "wasm32"), feature = "deterministic-time"))]
+fn unset_mock_time_nanos() -> TimestampNanos {
+    0
 }
 
-/// Returns the current time in nanoseconds (WASM implementation).
+/// Sets the time of the mock clock used off-chain.
 ///
-/// This function is only available when targeting the WASM architecture.
+/// The clock is local to the current thread. All the `timestamp_*` and `now_*`
+/// functions read it from then on.
 ///
-/// # Returns
-/// The current time in nanoseconds since the Unix epoch
-#[cfg(target_arch = "wasm32")]
-pub fn now_nanos() -> TimestampNanos {
-    ic_cdk::api::time()
+/// # Arguments
+/// * `nanos` - The time in nanoseconds since the Unix epoch
+#[cfg(not(target_arch = "wasm32"))]
+pub fn set_mock_time(nanos: TimestampNanos) {
+    MOCK_NOW.with(|now| now.set(Some(nanos)));
 }
 
-/// Returns the current time in nanoseconds (non-WASM implementation).
+/// Moves the mock clock used off-chain forward.
 ///
-/// This function is only available when not targeting the WASM architecture.
+/// An unset clock is first set to the time it currently reads.
 ///
-/// # Returns
-/// Always returns 0 in non-WASM environments
+/// # Arguments
+/// * `duration` - The time to add to the clock
 #[cfg(not(target_arch = "wasm32"))]
-pub fn now_nanos() -> TimestampNanos {
-    0
+pub fn advance_mock_time(duration: Duration) {
+    set_mock_time(mock_time_nanos() + duration.as_nanos() as u64);
 }
 
 /// Runs a function immediately and then at the specified interval.
@@ -149,9 +239,376 @@ pub fn run_once(func: fn()) {
     ic_cdk_timers::set_timer(Duration::ZERO, async move { func() });
 }
 
+/// How far in the past a timestamp passed to [`schedule_once_at_checked`] may be.
+/// The function of such a timestamp runs immediately.
+pub const SCHEDULE_GRACE_PERIOD_MS: Milliseconds = MINUTE_IN_MS;
+
+/// Default value of [`max_active_timers`].
+pub const DEFAULT_MAX_ACTIVE_TIMERS: u64 = 10_000;
+
+/// Why a timer was not scheduled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScheduleError {
+    /// The timestamp is more than [`SCHEDULE_GRACE_PERIOD_MS`] in the past
+    InThePast {
+        at_millis: TimestampMillis,
+        now_millis: TimestampMillis,
+    },
+    /// The timestamp is after the horizon of the caller
+    BeyondHorizon {
+        at_millis: TimestampMillis,
+        max_millis: TimestampMillis,
+    },
+    /// The timestamp cannot be expressed in nanoseconds
+    Overflow { at_millis: TimestampMillis },
+    /// The canister already has [`max_active_timers`] active timers
+    TooManyTimers { max: u64 },
+}
+
+impl std::fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScheduleError::InThePast {
+                at_millis,
+                now_millis,
+            } => write!(
+                f,
+                "Timestamp {} is in the past (now: {})",
+                at_millis, now_millis
+            ),
+            ScheduleError::BeyondHorizon {
+                at_millis,
+                max_millis,
+            } => write!(
+                f,
+                "Timestamp {} is beyond the scheduling horizon ({})",
+                at_millis, max_millis
+            ),
+            ScheduleError::Overflow { at_millis } => {
+                write!(f, "Timestamp {} overflows in nanoseconds", at_millis)
+            }
+            ScheduleError::TooManyTimers { max } => {
+                write!(f, "Too many active timers (max: {})", max)
+            }
+        }
+    }
+}
+
+thread_local! {
+    static ACTIVE_TIMERS: Cell<u64> = const { Cell::new(0) };
+    static MAX_ACTIVE_TIMERS: Cell<u64> =
+        const { Cell::new(DEFAULT_MAX_ACTIVE_TIMERS) };
+    static PENDING_ONCE_TIMERS: RefCell<HashSet<TimerId>> =
+        RefCell::new(HashSet::new());
+}
+
+/// Returns the number of timers counted against [`max_active_timers`].
+///
+/// Only the timers of the checked helpers, such as [`schedule_once_at_checked`] and
+/// the `TimerJobs` crate, are counted.
+pub fn active_timer_count() -> u64 {
+    ACTIVE_TIMERS.with(|count| count.get())
+}
+
+/// Returns the maximum number of active timers, [`DEFAULT_MAX_ACTIVE_TIMERS`] unless set.
+pub fn max_active_timers() -> u64 {
+    MAX_ACTIVE_TIMERS.with(|max| max.get())
+}
+
+/// Sets the maximum number of active timers.
+///
+/// Lowering it under [`active_timer_count`] does not clear any timer, new timers are
+/// rejected until enough of them have fired or been cleared.
+pub fn set_max_active_timers(max: u64) {
+    MAX_ACTIVE_TIMERS.with(|cell| cell.set(max));
+}
+
+/// Counts a new timer against [`max_active_timers`].
+///
+/// Helpers scheduling timers call this before setting one, and
+/// [`release_timer_slot`] once it has fired or been cleared.
+///
+/// # Returns
+/// * `Ok(())` if the timer can be set
+/// * `Err(ScheduleError::TooManyTimers)` if the limit is reached
+pub fn acquire_timer_slot() -> Result<(), ScheduleError> {
+    let max = max_active_timers();
+    ACTIVE_TIMERS.with(|count| {
+        if count.get() >= max {
+            return Err(ScheduleError::TooManyTimers { max });
+        }
+        count.set(count.get() + 1);
+        Ok(())
+    })
+}
+
+/// Releases a slot taken with [`acquire_timer_slot`].
+pub fn release_timer_slot() {
+    ACTIVE_TIMERS.with(|count| count.set(count.get().saturating_sub(1)));
+}
+
+/// Returns the delay until `at_millis`, checking it for [`schedule_once_at_checked`].
+///
+/// # Arguments
+/// * `at_millis` - The timestamp to run at, in milliseconds
+/// * `now_millis` - The current time in milliseconds
+/// * `max_horizon` - How far after `now_millis` the timestamp may be
+///
+/// # Returns
+/// * `Ok(Duration)` - The delay, zero for a timestamp within the grace period
+/// * `Err(ScheduleError)` if the timestamp overflows, is too old or beyond the horizon
+pub fn schedule_delay(
+    at_millis: TimestampMillis,
+    now_millis: TimestampMillis,
+    max_horizon: Milliseconds,
+) -> Result<Duration, ScheduleError> {
+    if at_millis.checked_mul(NANOS_PER_MILLISECOND).is_none() {
+        return Err(ScheduleError::Overflow { at_millis });
+    }
+    if at_millis < now_millis.saturating_sub(SCHEDULE_GRACE_PERIOD_MS) {
+        return Err(ScheduleError::InThePast {
+            at_millis,
+            now_millis,
+        });
+    }
+    let max_millis = now_millis.saturating_add(max_horizon);
+    if at_millis > max_millis {
+        return Err(ScheduleError::BeyondHorizon {
+            at_millis,
+            max_millis,
+        });
+    }
+    Ok(Duration::from_millis(at_millis.saturating_sub(now_millis)))
+}
+
+/// Runs a function once at a timestamp taken from user input.
+///
+/// The timestamp is checked with [`schedule_delay`] and the timer counted against
+/// [`max_active_timers`] until it fires or is cleared with [`cancel_checked_timer`].
+///
+/// # Arguments
+/// * `at_millis` - The timestamp to run at, in milliseconds
+/// * `max_horizon` - How far in the future the timestamp may be
+/// * `func` - The function to execute
+///
+/// # Returns
+/// * `Ok(TimerId)` of the scheduled timer
+/// * `Err(ScheduleError)` if the timestamp or the number of timers is rejected
+pub fn schedule_once_at_checked(
+    at_millis: TimestampMillis,
+    max_horizon: Milliseconds,
+    func: fn(),
+) -> Result<TimerId, ScheduleError> {
+    let delay = schedule_delay(at_millis, raw_now_millis(), max_horizon)?;
+    acquire_timer_slot()?;
+
+    let id = Rc::new(Cell::new(None::<TimerId>));
+    let fired_id = id.clone();
+    let timer_id = ic_cdk_timers::set_timer(delay, async move {
+        if let Some(timer_id) = fired_id.get() {
+            if PENDING_ONCE_TIMERS.with(|pending| pending.borrow_mut().remove(&timer_id)) {
+                release_timer_slot();
+            }
+        }
+        func()
+    });
+    id.set(Some(timer_id));
+    PENDING_ONCE_TIMERS.with(|pending| pending.borrow_mut().insert(timer_id));
+    Ok(timer_id)
+}
+
+/// Clears a timer set with [`schedule_once_at_checked`] and releases its slot.
+///
+/// Does nothing if the timer has already fired.
+pub fn cancel_checked_timer(timer_id: TimerId) {
+    if PENDING_ONCE_TIMERS.with(|pending| pending.borrow_mut().remove(&timer_id)) {
+        ic_cdk_timers::clear_timer(timer_id);
+        release_timer_slot();
+    }
+}
+
+/// Whether a named timer runs once or at an interval.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimerKind {
+    Once,
+    Interval,
+}
+
+/// What is known about a timer set with [`run_once_named`] or [`run_interval_named`].
+///
+/// # Fields
+/// * `name` - The name of the timer
+/// * `kind` - Whether the timer runs once or at an interval
+/// * `interval_ms` - The interval of an interval timer, the delay of a one-shot one
+/// * `created_at` - When the timer was set, in milliseconds
+/// * `fire_count` - How many times the function ran, including through [`fire_now`]
+/// * `last_fired_at` - When the function last ran, in milliseconds
+/// * `next_fire_at` - When the timer is expected to fire next, `None` once a one-shot
+///   timer has fired. Timers fire in the first round after this time.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TimerInfo {
+    pub name: String,
+    pub kind: TimerKind,
+    pub interval_ms: Milliseconds,
+    pub created_at: TimestampMillis,
+    pub fire_count: u64,
+    pub last_fired_at: Option<TimestampMillis>,
+    pub next_fire_at: Option<TimestampMillis>,
+}
+
+struct NamedTimer {
+    info: TimerInfo,
+    timer_id: TimerId,
+    func: fn(),
+}
+
+thread_local! {
+    static NAMED_TIMERS: RefCell<BTreeMap<String, NamedTimer>> =
+        const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Runs a function once after `delay`, under a name listed by [`list_timers`].
+///
+/// A timer already set under the same name is cleared and replaced.
+///
+/// # Arguments
+/// * `name` - The name of the timer
+/// * `delay` - The delay before the execution
+/// * `func` - The function to execute
+///
+/// # Returns
+/// The `TimerId` of the timer
+pub fn run_once_named(name: &str, delay: Duration, func: fn()) -> TimerId {
+    let fired_name = name.to_string();
+    let timer_id = ic_cdk_timers::set_timer(delay, async move {
+        record_fire(&fired_name, true);
+        func()
+    });
+    register_named_timer(name, TimerKind::Once, delay, timer_id, func);
+    timer_id
+}
+
+/// Runs a function at the specified interval, under a name listed by [`list_timers`].
+///
+/// A timer already set under the same name is cleared and replaced.
+///
+/// # Arguments
+/// * `name` - The name of the timer
+/// * `interval` - The duration between executions
+/// * `func` - The function to execute
+///
+/// # Returns
+/// The `TimerId` of the timer
+pub fn run_interval_named(name: &str, interval: Duration, func: fn()) -> TimerId {
+    let fired_name = name.to_string();
+    let timer_id = ic_cdk_timers::set_timer_interval(interval, move || {
+        let name = fired_name.clone();
+        async move {
+            record_fire(&name, true);
+            func()
+        }
+    });
+    register_named_timer(name, TimerKind::Interval, interval, timer_id, func);
+    timer_id
+}
+
+/// Clears a named timer.
+///
+/// # Returns
+/// Whether a timer was set under this name
+pub fn clear_named_timer(name: &str) -> bool {
+    match NAMED_TIMERS.with(|timers| timers.borrow_mut().remove(name)) {
+        Some(timer) => {
+            ic_cdk_timers::clear_timer(timer.timer_id);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns the named timers, sorted by name.
+///
+/// One-shot timers stay listed after firing, until they are cleared or replaced.
+pub fn list_timers() -> Vec<TimerInfo> {
+    NAMED_TIMERS.with(|timers| {
+        timers
+            .borrow()
+            .values()
+            .map(|timer| timer.info.clone())
+            .collect()
+    })
+}
+
+/// Returns whether a timer is set under `name`.
+pub fn timer_exists(name: &str) -> bool {
+    NAMED_TIMERS.with(|timers| timers.borrow().contains_key(name))
+}
+
+/// Runs the function of a named timer now, for manual triggering.
+///
+/// The timer keeps its schedule: only its fire count and last fire time change.
+///
+/// # Returns
+/// * `Ok(())` once the function has run
+/// * `Err(String)` if no timer is set under `name`
+pub fn fire_now(name: &str) -> Result<(), String> {
+    let func = NAMED_TIMERS
+        .with(|timers| timers.borrow().get(name).map(|timer| timer.func))
+        .ok_or_else(|| format!("No timer named {}", name))?;
+    record_fire(name, false);
+    func();
+    Ok(())
+}
+
+fn register_named_timer(
+    name: &str,
+    kind: TimerKind,
+    interval: Duration,
+    timer_id: TimerId,
+    func: fn(),
+) {
+    let created_at = raw_now_millis();
+    let interval_ms = interval.as_millis() as Milliseconds;
+    let timer = NamedTimer {
+        info: TimerInfo {
+            name: name.to_string(),
+            kind,
+            interval_ms,
+            created_at,
+            fire_count: 0,
+            last_fired_at: None,
+            next_fire_at: Some(created_at.saturating_add(interval_ms)),
+        },
+        timer_id,
+        func,
+    };
+    let replaced = NAMED_TIMERS.with(|timers| timers.borrow_mut().insert(name.to_string(), timer));
+    if let Some(replaced) = replaced {
+        ic_cdk_timers::clear_timer(replaced.timer_id);
+    }
+}
+
+/// Counts a run of a named timer, moving its next fire time if it was scheduled.
+fn record_fire(name: &str, scheduled: bool) {
+    let now = raw_now_millis();
+    NAMED_TIMERS.with(|timers| {
+        if let Some(timer) = timers.borrow_mut().get_mut(name) {
+            let info = &mut timer.info;
+            info.fire_count += 1;
+            info.last_fired_at = Some(now);
+            if scheduled {
+                info.next_fire_at = match info.kind {
+                    TimerKind::Once => None,
+                    TimerKind::Interval => Some(now.saturating_add(info.interval_ms)),
+                };
+            }
+        }
+    });
+}
+
 pub fn start_job_daily_at(hour: u8, func: fn()) {
     if let Some(next_timestamp) = calculate_next_timestamp(hour) {
-        let now_millis = now_millis();
+        let now_millis = raw_now_millis();
 
         if next_timestamp > now_millis {
             let delay = Duration::from_millis(next_timestamp - now_millis);
@@ -178,7 +635,7 @@ fn calculate_next_timestamp(hour: u8) -> Option<u64> {
         return None;
     }
 
-    let now = OffsetDateTime::from_unix_timestamp((now_millis() / 1000) as i64).ok()?;
+    let now = OffsetDateTime::from_unix_timestamp((raw_now_millis() / 1000) as i64).ok()?;
     let target_time = Time::from_hms(hour, 0, 0).ok()?;
 
     let next_occurrence = if now.time().hour() >= hour {
@@ -239,35 +696,323 @@ pub fn start_job_weekly_at(weekday: Weekday, hour: u8, func: fn(), now_fn: &impl
     }
 }
 
+/// Runs a function every week on the given UTC weekday and time.
+///
+/// The first run is at the next occurrence strictly after now, see [`next_weekday_time`].
+///
+/// # Arguments
+/// * `weekday` - The weekday, from 0 (Monday) to 6 (Sunday)
+/// * `hour` - The hour, from 0 to 23
+/// * `minute` - The minute, from 0 to 59
+/// * `func` - The function to execute
+pub fn run_weekly_at(weekday: u8, hour: u8, minute: u8, func: fn()) {
+    if weekday > 6 || hour > 23 || minute > 59 {
+        tracing::error!(
+            "Invalid time provided for weekly job scheduling: weekday {}, {}:{:02}",
+            weekday,
+            hour,
+            minute
+        );
+        return;
+    }
+
+    let now_millis = raw_now_millis();
+    let next_timestamp = next_weekday_time(now_millis, weekday, hour, minute);
+    let delay = Duration::from_millis(next_timestamp - now_millis);
+
+    ic_cdk_timers::set_timer(delay, async move {
+        run_now_then_interval(Duration::from_millis(WEEK_IN_MS), func);
+    });
+
+    tracing::info!(
+        "Job scheduled to start on weekday {} at {}:{:02}. (Timestamp: {})",
+        weekday,
+        hour,
+        minute,
+        next_timestamp
+    );
+}
+
+/// Returns the UTC weekday of a timestamp, from 0 (Monday) to 6 (Sunday).
+///
+/// # Arguments
+/// * `ts_millis` - The timestamp in milliseconds
+pub fn weekday(ts_millis: TimestampMillis) -> u8 {
+    ((ts_millis / DAY_IN_MS + EPOCH_WEEKDAY) % 7) as u8
+}
+
+/// Returns the first UTC time on the given weekday, hour and minute strictly after `after_ts`.
+///
+/// When `after_ts` is exactly such a time, the occurrence of the following week is returned.
+///
+/// # Arguments
+/// * `after_ts` - The timestamp in milliseconds
+/// * `weekday` - The weekday, from 0 (Monday) to 6 (Sunday)
+/// * `hour` - The hour, from 0 to 23
+/// * `minute` - The minute, from 0 to 59
+///
+/// # Panics
+/// Panics if `weekday`, `hour` or `minute` is out of range.
+pub fn next_weekday_time(
+    after_ts: TimestampMillis,
+    weekday: u8,
+    hour: u8,
+    minute: u8,
+) -> TimestampMillis {
+    assert!(weekday <= 6, "Invalid weekday: {weekday}");
+    assert!(hour <= 23, "Invalid hour: {hour}");
+    assert!(minute <= 59, "Invalid minute: {minute}");
+
+    let days_ahead = (weekday as u64 + 7 - self::weekday(after_ts) as u64) % 7;
+    let day_start = after_ts - after_ts % DAY_IN_MS;
+    let next = day_start
+        + days_ahead * DAY_IN_MS
+        + hour as u64 * HOUR_IN_MS
+        + minute as u64 * MINUTE_IN_MS;
+
+    if next <= after_ts {
+        next + WEEK_IN_MS
+    } else {
+        next
+    }
+}
+
+/// Returns whether two timestamps fall on the same UTC day.
+pub fn is_same_utc_day(a: TimestampMillis, b: TimestampMillis) -> bool {
+    a / DAY_IN_MS == b / DAY_IN_MS
+}
+
+fn offset_millis(utc_offset_minutes: i32) -> i64 {
+    utc_offset_minutes as i64 * MINUTE_IN_MS as i64
+}
+
+/// Returns the day bucket (days since the Unix epoch) of a timestamp in a fixed UTC offset.
+///
+/// Offsets are fixed: there is no DST database, callers pass the offset they want
+/// (e.g. `60` for CET, `120` for CEST). Timestamps falling before the first local day
+/// are placed in bucket 0.
+///
+/// # Arguments
+/// * `ts_millis` - The UTC timestamp in milliseconds
+/// * `utc_offset_minutes` - The UTC offset in minutes
+pub fn day_bucket(ts_millis: TimestampMillis, utc_offset_minutes: i32) -> u32 {
+    let local_millis = ts_millis as i64 + offset_millis(utc_offset_minutes);
+    local_millis.div_euclid(DAY_IN_MS as i64).max(0) as u32
+}
+
+/// Returns the UTC bounds `[start, end)` of a day bucket in a fixed UTC offset.
+///
+/// # Arguments
+/// * `bucket` - The day bucket, as returned by [`day_bucket`]
+/// * `utc_offset_minutes` - The UTC offset in minutes
+pub fn bucket_bounds(bucket: u32, utc_offset_minutes: i32) -> (TimestampMillis, TimestampMillis) {
+    let start = bucket as i64 * DAY_IN_MS as i64 - offset_millis(utc_offset_minutes);
+    let end = start + DAY_IN_MS as i64;
+    (
+        start.max(0) as TimestampMillis,
+        end.max(0) as TimestampMillis,
+    )
+}
+
+/// Re-expresses a local wall-clock timestamp from one fixed UTC offset to another.
+///
+/// # Arguments
+/// * `ts_millis` - The local timestamp in `from_offset_minutes`, in milliseconds
+/// * `from_offset_minutes` - The UTC offset of `ts_millis`, in minutes
+/// * `to_offset_minutes` - The target UTC offset, in minutes
+pub fn shift_offset(
+    ts_millis: TimestampMillis,
+    from_offset_minutes: i32,
+    to_offset_minutes: i32,
+) -> TimestampMillis {
+    let shifted =
+        ts_millis as i64 - offset_millis(from_offset_minutes) + offset_millis(to_offset_minutes);
+    shifted.max(0) as TimestampMillis
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use time::macros::datetime;
 
     #[test]
-    fn test_calculate_next_timestamp() {
-        // Mock current time: Sat Nov 23 2024 10:52:11 UTC
-        // 1732355531
-        // 1732362731
-        //       7128
-        let now = datetime!(2024-11-23 10:52:11 UTC);
+    fn test_schedule_delay() {
+        let now = 10 * DAY_IN_MS;
+
+        assert_eq!(
+            schedule_delay(now + HOUR_IN_MS, now, DAY_IN_MS),
+            Ok(Duration::from_millis(HOUR_IN_MS))
+        );
+        assert_eq!(
+            schedule_delay(now - SCHEDULE_GRACE_PERIOD_MS, now, DAY_IN_MS),
+            Ok(Duration::ZERO)
+        );
+        assert_eq!(
+            schedule_delay(now - SCHEDULE_GRACE_PERIOD_MS - 1, now, DAY_IN_MS),
+            Err(ScheduleError::InThePast {
+                at_millis: now - SCHEDULE_GRACE_PERIOD_MS - 1,
+                now_millis: now,
+            })
+        );
+        assert_eq!(
+            schedule_delay(now + DAY_IN_MS + 1, now, DAY_IN_MS),
+            Err(ScheduleError::BeyondHorizon {
+                at_millis: now + DAY_IN_MS + 1,
+                max_millis: now + DAY_IN_MS,
+            })
+        );
+        assert_eq!(
+            schedule_delay(u64::MAX, now, u64::MAX),
+            Err(ScheduleError::Overflow {
+                at_millis: u64::MAX
+            })
+        );
+        assert_eq!(
+            schedule_delay(u64::MAX / NANOS_PER_MILLISECOND, 0, u64::MAX),
+            Ok(Duration::from_millis(u64::MAX / NANOS_PER_MILLISECOND))
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "typed-time"))]
+    fn test_mock_clock_drives_the_timestamps() {
+        let start = 1_700_000_000 * 1_000 * NANOS_PER_MILLISECOND;
+        set_mock_time(start);
+        assert_eq!(timestamp_nanos(), start);
+        assert_eq!(now_nanos(), start);
+        assert_eq!(timestamp_seconds(), 1_700_000_000);
+
+        let millis = timestamp_millis();
+        let micros = timestamp_micros();
+        advance_mock_time(Duration::from_millis(1_500));
+        assert_eq!(timestamp_millis() - millis, 1_500);
+        assert_eq!(timestamp_micros() - micros, 1_500_000);
+        assert_eq!(now_millis(), millis + 1_500);
+        assert_eq!(timestamp_seconds(), 1_700_000_001);
+
+        // The delay of a schedule shrinks as the clock moves towards it.
+        let target = now_millis() + DAY_IN_MS;
+        advance_mock_time(Duration::from_millis(HOUR_IN_MS));
+        assert_eq!(target - now_millis(), DAY_IN_MS - HOUR_IN_MS);
+    }
+
+    #[test]
+    #[cfg(feature = "typed-time")]
+    fn test_timestamps_are_typed() {
+        let start = Seconds(1_700_000_000).to_nanos().unwrap();
+        set_mock_time(start.get());
+        assert_eq!(timestamp_nanos(), start);
+        assert_eq!(timestamp_seconds(), Seconds(1_700_000_000));
+
+        let millis = timestamp_millis();
+        advance_mock_time(Duration::from_millis(1_500));
+        assert_eq!(now_millis() - millis, Millis(1_500));
+        assert_eq!((now_nanos() - start).to_millis(), Millis(1_500));
+    }
+
+    #[test]
+    fn test_timer_slots_are_limited() {
+        assert_eq!(max_active_timers(), DEFAULT_MAX_ACTIVE_TIMERS);
+        set_max_active_timers(2);
 
-        let target_hour = 12; // Next target hour is 12 o'clock
+        assert_eq!(acquire_timer_slot(), Ok(()));
+        assert_eq!(acquire_timer_slot(), Ok(()));
+        assert_eq!(
+            acquire_timer_slot(),
+            Err(ScheduleError::TooManyTimers { max: 2 })
+        );
+        assert_eq!(
+            schedule_once_at_checked(raw_now_millis(), DAY_IN_MS, || {}),
+            Err(ScheduleError::TooManyTimers { max: 2 })
+        );
+        assert_eq!(active_timer_count(), 2);
+
+        release_timer_slot();
+        assert_eq!(active_timer_count(), 1);
+        assert_eq!(acquire_timer_slot(), Ok(()));
+
+        release_timer_slot();
+        release_timer_slot();
+        release_timer_slot();
+        assert_eq!(active_timer_count(), 0);
+    }
+
+    thread_local! {
+        static MANUAL_RUNS: Cell<u64> = const { Cell::new(0) };
+    }
+
+    #[test]
+    fn test_named_timers_are_listed_and_fired_now() {
+        set_mock_time(0);
+        register_named_timer(
+            "cleanup",
+            TimerKind::Interval,
+            Duration::from_millis(HOUR_IN_MS),
+            TimerId::default(),
+            || MANUAL_RUNS.with(|runs| runs.set(runs.get() + 1)),
+        );
+        register_named_timer(
+            "archive",
+            TimerKind::Once,
+            Duration::from_millis(MINUTE_IN_MS),
+            TimerId::default(),
+            || {},
+        );
+        assert!(timer_exists("cleanup"));
+        assert!(!timer_exists("verification"));
+
+        let timers = list_timers();
+        assert_eq!(
+            timers
+                .iter()
+                .map(|timer| timer.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["archive", "cleanup"]
+        );
+        assert_eq!(timers[1].interval_ms, HOUR_IN_MS);
+        assert_eq!(timers[1].fire_count, 0);
+        assert_eq!(timers[1].next_fire_at, Some(HOUR_IN_MS));
+
+        record_fire("cleanup", true);
+        record_fire("archive", true);
+        let timers = list_timers();
+        assert_eq!(timers[0].fire_count, 1);
+        assert_eq!(timers[0].next_fire_at, None);
+        assert_eq!(timers[1].fire_count, 1);
+        assert_eq!(timers[1].next_fire_at, Some(HOUR_IN_MS));
+
+        // Firing now runs the function without moving the schedule.
+        assert_eq!(fire_now("cleanup"), Ok(()));
+        assert_eq!(MANUAL_RUNS.with(|runs| runs.get()), 1);
+        let cleanup = &list_timers()[1];
+        assert_eq!(cleanup.fire_count, 2);
+        assert_eq!(cleanup.last_fired_at, Some(0));
+        assert_eq!(cleanup.next_fire_at, Some(HOUR_IN_MS));
+        assert!(fire_now("verification").is_err());
 
-        // Expected delay: 15 hours from 20:52:11 -> 12:00:00 next day
-        let expected_delay = 12 * 3600 * 1000;
+        assert!(clear_named_timer("archive"));
+        assert!(!clear_named_timer("archive"));
+        assert!(!timer_exists("archive"));
+    }
 
-        let calculated_delay =
-            calculate_next_timestamp(target_hour).expect("Failed to calculate next timestamp");
+    #[test]
+    fn test_calculate_next_timestamp() {
+        // Mock current time: Sat Nov 23 2024 10:52:11 UTC
+        let now = datetime!(2024-11-23 10:52:11 UTC);
+        set_mock_time(now.unix_timestamp_nanos() as u64);
 
-        println!("Calculated delay: {} milliseconds", calculated_delay);
+        let next = |hour| calculate_next_timestamp(hour).map(|ts| ts as i128 * 1_000_000);
 
-        // Verify the calculated delay matches the expected delay
+        // 12 o'clock is still ahead today, 8 o'clock has passed until tomorrow.
         assert_eq!(
-            calculated_delay, expected_delay,
-            "Expected delay: {}, Calculated delay: {}",
-            expected_delay, calculated_delay
+            next(12),
+            Some(datetime!(2024-11-23 12:00:00 UTC).unix_timestamp_nanos())
         );
+        assert_eq!(
+            next(8),
+            Some(datetime!(2024-11-24 08:00:00 UTC).unix_timestamp_nanos())
+        );
+        assert_eq!(next(24), None);
     }
 
     #[test]
@@ -312,4 +1057,223 @@ mod tests {
 
         assert_eq!(res, 1740754800000); // 28 Feb 2025, 15:00:00
     }
+
+    #[test]
+    fn test_day_bucket_around_midnight() {
+        // 2024-11-23 00:00:00 UTC, day 20050 since the epoch
+        let midnight = datetime!(2024-11-23 00:00:00 UTC).unix_timestamp() as u64 * 1000;
+        let day = 20050;
+
+        assert_eq!(day_bucket(midnight, 0), day);
+        assert_eq!(day_bucket(midnight - 1, 0), day - 1);
+
+        // CET (+01:00): local midnight is 23:00 UTC the day before
+        assert_eq!(day_bucket(midnight - HOUR_IN_MS, 60), day);
+        assert_eq!(day_bucket(midnight - HOUR_IN_MS - 1, 60), day - 1);
+
+        // -05:00: local midnight is 05:00 UTC
+        assert_eq!(day_bucket(midnight + 5 * HOUR_IN_MS, -300), day);
+        assert_eq!(day_bucket(midnight + 5 * HOUR_IN_MS - 1, -300), day - 1);
+
+        // +/-23h extremes
+        assert_eq!(day_bucket(midnight, 23 * 60), day);
+        assert_eq!(day_bucket(midnight + HOUR_IN_MS, 23 * 60), day + 1);
+        assert_eq!(day_bucket(midnight, -23 * 60), day - 1);
+        assert_eq!(day_bucket(midnight + 23 * HOUR_IN_MS, -23 * 60), day);
+
+        // Before the first local day
+        assert_eq!(day_bucket(0, -60), 0);
+    }
+
+    #[test]
+    fn test_bucket_bounds_and_shift_offset() {
+        let midnight = datetime!(2024-11-23 00:00:00 UTC).unix_timestamp() as u64 * 1000;
+        let day = 20050;
+
+        assert_eq!(bucket_bounds(day, 0), (midnight, midnight + DAY_IN_MS));
+        assert_eq!(
+            bucket_bounds(day, 60),
+            (midnight - HOUR_IN_MS, midnight + DAY_IN_MS - HOUR_IN_MS)
+        );
+        assert_eq!(
+            bucket_bounds(day, -23 * 60),
+            (
+                midnight + 23 * HOUR_IN_MS,
+                midnight + DAY_IN_MS + 23 * HOUR_IN_MS
+            )
+        );
+
+        for offset in [-23 * 60, -300, 0, 60, 23 * 60] {
+            let (start, end) = bucket_bounds(day, offset);
+            assert_eq!(day_bucket(start, offset), day);
+            assert_eq!(day_bucket(end - 1, offset), day);
+            assert_eq!(day_bucket(end, offset), day + 1);
+        }
+
+        // 12:00 in CET is 11:00 UTC and 06:00 in -05:00
+        let noon_cet = midnight + 12 * HOUR_IN_MS;
+        assert_eq!(shift_offset(noon_cet, 60, 0), midnight + 11 * HOUR_IN_MS);
+        assert_eq!(shift_offset(noon_cet, 60, -300), midnight + 6 * HOUR_IN_MS);
+        assert_eq!(
+            shift_offset(shift_offset(noon_cet, 60, -300), -300, 60),
+            noon_cet
+        );
+    }
+
+    fn millis(date_time: OffsetDateTime) -> TimestampMillis {
+        date_time.unix_timestamp() as u64 * 1000
+    }
+
+    #[test]
+    fn test_weekday_known_dates() {
+        let table = [
+            (datetime!(1970-01-01 00:00:00 UTC), 3),
+            (datetime!(1970-01-04 23:59:59.999 UTC), 6),
+            (datetime!(1970-01-05 00:00:00 UTC), 0),
+            (datetime!(1999-12-31 23:59:59 UTC), 4),
+            (datetime!(2000-01-01 00:00:00 UTC), 5),
+            (datetime!(2000-02-29 12:00:00 UTC), 1),
+            (datetime!(2024-11-23 10:52:11 UTC), 5),
+            (datetime!(2024-12-31 23:59:59 UTC), 1),
+            (datetime!(2025-01-01 00:00:00 UTC), 2),
+            (datetime!(2038-01-19 03:14:08 UTC), 1),
+        ];
+
+        for (date_time, expected) in table {
+            let ts = date_time.unix_timestamp_nanos() as u64 / NANOS_PER_MILLISECOND;
+            assert_eq!(weekday(ts), expected, "{date_time}");
+        }
+
+        for day in 0..40_000u64 {
+            let date_time =
+                OffsetDateTime::from_unix_timestamp((day * DAY_IN_SECONDS) as i64).unwrap();
+            let expected = date_time.weekday().number_days_from_monday();
+            assert_eq!(weekday(day * DAY_IN_MS), expected, "{date_time}");
+            assert_eq!(weekday(day * DAY_IN_MS + DAY_IN_MS - 1), expected);
+        }
+    }
+
+    #[test]
+    fn test_next_weekday_time_known_dates() {
+        let table = [
+            // At the epoch, exactly on the target: the next week
+            (
+                datetime!(1970-01-01 00:00 UTC),
+                3,
+                0,
+                0,
+                datetime!(1970-01-08 00:00 UTC),
+            ),
+            (
+                datetime!(1970-01-01 00:00 UTC),
+                3,
+                0,
+                1,
+                datetime!(1970-01-01 00:01 UTC),
+            ),
+            (
+                datetime!(1970-01-01 00:00 UTC),
+                0,
+                6,
+                0,
+                datetime!(1970-01-05 06:00 UTC),
+            ),
+            (
+                datetime!(1970-01-01 00:00 UTC),
+                2,
+                23,
+                59,
+                datetime!(1970-01-07 23:59 UTC),
+            ),
+            // Year boundaries
+            (
+                datetime!(1999-12-31 12:00 UTC),
+                5,
+                0,
+                0,
+                datetime!(2000-01-01 00:00 UTC),
+            ),
+            (
+                datetime!(2024-12-31 23:59 UTC),
+                2,
+                0,
+                0,
+                datetime!(2025-01-01 00:00 UTC),
+            ),
+            (
+                datetime!(2024-12-30 06:00 UTC),
+                0,
+                6,
+                0,
+                datetime!(2025-01-06 06:00 UTC),
+            ),
+            (
+                datetime!(2024-12-30 05:59 UTC),
+                0,
+                6,
+                0,
+                datetime!(2024-12-30 06:00 UTC),
+            ),
+            // Later on the same weekday, and earlier in the week
+            (
+                datetime!(2025-02-28 16:00 UTC),
+                4,
+                15,
+                0,
+                datetime!(2025-03-07 15:00 UTC),
+            ),
+            (
+                datetime!(2025-02-27 14:55 UTC),
+                4,
+                15,
+                0,
+                datetime!(2025-02-28 15:00 UTC),
+            ),
+            (
+                datetime!(2025-03-02 23:30 UTC),
+                6,
+                23,
+                45,
+                datetime!(2025-03-02 23:45 UTC),
+            ),
+        ];
+
+        for (after, weekday, hour, minute, expected) in table {
+            assert_eq!(
+                next_weekday_time(millis(after), weekday, hour, minute),
+                millis(expected),
+                "{after} -> {weekday} {hour}:{minute:02}"
+            );
+        }
+
+        // Exactly on the target, and one millisecond before
+        let target = millis(datetime!(2025-01-06 06:00:00 UTC));
+        assert_eq!(next_weekday_time(target, 0, 6, 0), target + WEEK_IN_MS);
+        assert_eq!(next_weekday_time(target - 1, 0, 6, 0), target);
+    }
+
+    #[test]
+    fn test_next_weekday_time_properties() {
+        let start = millis(datetime!(1999-12-20 00:00:00 UTC));
+        for step in 0..200u64 {
+            let after = start + step * (7 * HOUR_IN_MS + 13 * MINUTE_IN_MS + 1);
+            for target_weekday in 0..7 {
+                let next = next_weekday_time(after, target_weekday, 6, 30);
+
+                assert!(next > after && next - after <= WEEK_IN_MS);
+                assert_eq!(weekday(next), target_weekday);
+                assert_eq!(next % DAY_IN_MS, 6 * HOUR_IN_MS + 30 * MINUTE_IN_MS);
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_same_utc_day() {
+        let midnight = millis(datetime!(2025-01-01 00:00:00 UTC));
+
+        assert!(is_same_utc_day(midnight, midnight + DAY_IN_MS - 1));
+        assert!(!is_same_utc_day(midnight - 1, midnight));
+        assert!(!is_same_utc_day(midnight, midnight + DAY_IN_MS));
+        assert!(is_same_utc_day(0, DAY_IN_MS - 1));
+    }
 }
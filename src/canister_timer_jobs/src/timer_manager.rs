@@ -1,4 +1,5 @@
 use crate::Environment;
+use bity_ic_canister_time::{acquire_timer_slot, release_timer_slot, ScheduleError};
 use bity_ic_types::TimestampMillis;
 use ic_cdk_timers::TimerId;
 use std::any::type_name;
@@ -42,7 +43,13 @@ impl<J> TimerManager<J, Result<(), String>>
 where
     J: Fn() -> Result<(), String> + Clone + Sync + 'static,
 {
-    pub fn start_timer_sync(&mut self, env: &dyn Environment) {
+    /// Starts the timer, counted against `max_active_timers` until it is cancelled.
+    ///
+    /// A timer already started is cancelled first.
+    pub fn start_timer_sync(&mut self, env: &dyn Environment) -> Result<(), ScheduleError> {
+        self.cancel_timer();
+        acquire_timer_slot()?;
+
         let interval = self.interval;
         let job_function = self.job_function.clone();
         let max_attempts = self.max_attempts;
@@ -56,6 +63,7 @@ where
         }));
 
         self.last_run = Some(env.now());
+        Ok(())
     }
 }
 
@@ -65,7 +73,13 @@ where
     J: Fn() -> R + Clone + 'static + Sync + Send,
     R: std::future::Future<Output = Result<(), String>>,
 {
-    pub fn start_timer_async(&mut self, env: &dyn Environment) {
+    /// Starts the timer, counted against `max_active_timers` until it is cancelled.
+    ///
+    /// A timer already started is cancelled first.
+    pub fn start_timer_async(&mut self, env: &dyn Environment) -> Result<(), ScheduleError> {
+        self.cancel_timer();
+        acquire_timer_slot()?;
+
         let interval = self.interval;
         let job_function = self.job_function.clone();
         let max_attempts = self.max_attempts;
@@ -79,6 +93,7 @@ where
         }));
 
         self.last_run = Some(env.now());
+        Ok(())
     }
 }
 
@@ -109,6 +124,7 @@ where
     pub fn cancel_timer(&mut self) {
         if let Some(timer_id) = self.timer_id.take() {
             ic_cdk_timers::clear_timer(timer_id);
+            release_timer_slot();
         }
     }
 
@@ -119,6 +135,25 @@ where
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use bity_ic_canister_time::set_max_active_timers;
+    use bity_ic_types::BuildVersion;
+    use bity_ic_utils::env::CanisterEnv;
+
+    #[test]
+    fn test_start_is_rejected_over_the_timer_limit() {
+        let env = CanisterEnv::new(true, BuildVersion::min(), "test".to_string());
+        let mut timer = TimerManager::new(|| -> Result<(), String> { Ok(()) }, 2, None, None);
+        set_max_active_timers(0);
+
+        assert_eq!(
+            timer.start_timer_sync(&env),
+            Err(ScheduleError::TooManyTimers { max: 0 })
+        );
+        assert!(timer.timer_id.is_none());
+        assert!(timer.last_run.is_none());
+    }
+
     // use super::*;
     // use bity_ic_types::BuildVersion;
     // use bity_ic_utils::env::CanisterEnv;
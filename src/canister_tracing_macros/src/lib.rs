@@ -2,7 +2,10 @@
 //!
 //! This module provides macros that automatically add tracing instrumentation to functions,
 //! making it easier to debug and monitor canister behavior. It wraps functions with tracing
-//! capabilities while preserving their original functionality.
+//! capabilities while preserving their original functionality. The `Err` of a function
+//! returning a `Result` is logged at warn level, so that it is visible without trace
+//! logging. The span name and the target of the events can be set per function, or
+//! per module with [`macro@trace_defaults`].
 //!
 //! # Example
 //! ```
@@ -16,9 +19,14 @@
 //! ```
 
 use proc_macro::TokenStream;
-use proc_macro2::Ident;
+use proc_macro2::{Ident, TokenStream as TokenStream2};
 use quote::{format_ident, quote};
-use syn::{parse_macro_input, FnArg, ItemFn, Pat, PatIdent, PatType, Signature};
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::{
+    parse_macro_input, Attribute, Expr, ExprLit, FnArg, ImplItem, Item, ItemFn, ItemMod, Lit,
+    LitStr, Meta, Pat, PatIdent, PatType, ReturnType, Signature, Token, Type,
+};
 
 /// A procedural macro attribute that adds tracing capabilities to a function.
 ///
@@ -38,15 +46,49 @@ use syn::{parse_macro_input, FnArg, ItemFn, Pat, PatIdent, PatType, Signature};
 /// * Automatically traces function entry and exit
 /// * Logs all function arguments
 /// * Logs the return value
-/// * Works with both synchronous and asynchronous functions
-/// * Preserves the original function signature
+/// * Logs the `Err` of a `Result` at warn level, with the function name
+/// * Works with both synchronous and asynchronous functions, and with methods
+/// * Preserves the original function signature and visibility
+///
+/// # Results
+/// When the return type is written as `Result<_, E>` (or `io::Result<_>` and the
+/// like), the `Ok` value is logged at trace level and the error at warn level, or
+/// at the level given by `err_level`:
+/// ```ignore
+/// #[trace(err_level = "error")]
+/// fn parse(input: String) -> Result<u64, ParseIntError> {
+///     input.parse()
+/// }
+/// ```
+/// The error is logged with its `Debug` representation, or with its `Display`
+/// one if it doesn't implement `Debug`. Results behind a type alias with another
+/// name are logged as any other return value.
+///
+/// # Target and span name
+/// The span is named after the function and its events have the module path as
+/// target, unless `name` or `target` is given, in any combination with `err_level`:
+/// ```ignore
+/// #[trace(target = "icrc3::archive", name = "archive_batch", err_level = "error")]
+/// async fn archive_blocks(start: u64) -> Result<u64, String> {
+///     // ...
+/// }
+/// ```
 #[proc_macro_attribute]
-pub fn trace(_: TokenStream, item: TokenStream) -> TokenStream {
+pub fn trace(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let TraceArgs {
+        err_level,
+        target,
+        name,
+    } = match parse_trace_args(attr.into()) {
+        Ok(args) => args,
+        Err(e) => return e.to_compile_error().into(),
+    };
     let mut inner = parse_macro_input!(item as ItemFn);
 
     // We will wrap the original fn in a new fn whose signature matches the original fn
     #[allow(clippy::redundant_clone)] // clippy doesn't realise that this is used in the macro
     let wrapper_sig = inner.sig.clone();
+    let wrapper_vis = inner.vis.clone();
 
     // Change the name of the inner fn so that it doesn't clash with the wrapper fn
     let inner_method_name = format_ident!("{}_inner_", inner.sig.ident);
@@ -54,19 +96,41 @@ pub fn trace(_: TokenStream, item: TokenStream) -> TokenStream {
 
     let is_async = inner.sig.asyncness.is_some();
     let arg_names = get_arg_names(&inner.sig);
+    let function_name = wrapper_sig.ident.to_string();
+
+    // Methods call the renamed method through `Self`, and don't log `self`.
+    let has_receiver = matches!(inner.sig.inputs.first(), Some(FnArg::Receiver(_)));
+    let inner_path = if has_receiver {
+        quote! { Self::#inner_method_name }
+    } else {
+        quote! { #inner_method_name }
+    };
+    let skip = has_receiver.then(|| quote! { , skip(self) });
+    let span_target = target.as_ref().map(|target| quote! { , target = #target });
+    let span_name = name.as_ref().map(|name| quote! { , name = #name });
+    let instrument = quote! {
+        #[tracing::instrument(level = "trace" #span_target #span_name #skip)]
+    };
+    let event_target = target.as_ref().map(|target| quote! { target: #target, });
 
     let function_call = if is_async {
-        quote! { #inner_method_name ( #(#arg_names),* ) .await }
+        quote! { #inner_path ( #(#arg_names),* ) .await }
+    } else {
+        quote! { #inner_path ( #(#arg_names),* ) }
+    };
+
+    let log_result = if returns_result(&wrapper_sig) {
+        log_result(&function_name, &err_level, &event_target)
     } else {
-        quote! { #inner_method_name ( #(#arg_names),* ) }
+        quote! { tracing::trace!(#event_target ?result); }
     };
 
     let expanded = quote! {
         #[allow(unused_mut)]
-        #[tracing::instrument(level = "trace")]
-        #wrapper_sig {
+        #instrument
+        #wrapper_vis #wrapper_sig {
             let result = #function_call;
-            tracing::trace!(?result);
+            #log_result
             result
         }
         #inner
@@ -75,6 +139,268 @@ pub fn trace(_: TokenStream, item: TokenStream) -> TokenStream {
     TokenStream::from(expanded)
 }
 
+/// A procedural macro attribute that sets the target of the `#[trace]` functions
+/// of a module.
+///
+/// Every function of the module, of its `impl` blocks and of its nested modules
+/// that has a `#[trace]` attribute without a `target` gets this one, so that a
+/// whole module can be filtered in or out consistently. The module must be inline,
+/// and a nested module with its own `#[trace_defaults]` keeps its target.
+///
+/// # Usage
+/// ```ignore
+/// #[trace_defaults(target = "icrc3::archive")]
+/// mod archive {
+///     #[trace]
+///     pub fn archive_blocks(start: u64) -> Result<u64, String> {
+///         // ...
+///     }
+///
+///     // Keeps its own target.
+///     #[trace(target = "icrc3::archive::cleanup")]
+///     pub fn cleanup() {}
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn trace_defaults(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let target = match parse_trace_defaults_args(attr.into()) {
+        Ok(target) => target,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let mut module = parse_macro_input!(item as ItemMod);
+    let Some((_, items)) = module.content.as_mut() else {
+        return syn::Error::new_spanned(
+            &module,
+            "`#[trace_defaults]` only applies to inline modules",
+        )
+        .to_compile_error()
+        .into();
+    };
+    if let Err(e) = set_default_target(items, &target) {
+        return e.to_compile_error().into();
+    }
+    quote! { #module }.into()
+}
+
+/// The arguments of `#[trace]`.
+struct TraceArgs {
+    /// The `tracing::Level` constant `Err` results are logged at, `WARN` by default
+    err_level: Ident,
+    /// The target of the span and events, the module path by default
+    target: Option<LitStr>,
+    /// The name of the span, the function name by default
+    name: Option<LitStr>,
+}
+
+/// Parses the arguments of `#[trace]`: any of `err_level = "<level>"`,
+/// `target = "<target>"` and `name = "<name>"`.
+fn parse_trace_args(attr: TokenStream2) -> syn::Result<TraceArgs> {
+    let mut args = TraceArgs {
+        err_level: format_ident!("WARN"),
+        target: None,
+        name: None,
+    };
+    for (key, value) in parse_name_values(attr)? {
+        if key == "target" {
+            args.target = Some(value);
+        } else if key == "name" {
+            args.name = Some(value);
+        } else if key == "err_level" {
+            args.err_level = parse_level(&value)?;
+        } else {
+            return Err(syn::Error::new_spanned(
+                key,
+                "unknown argument, expected `err_level`, `target` or `name`",
+            ));
+        }
+    }
+    Ok(args)
+}
+
+/// Parses the arguments of `#[trace_defaults]`: `target = "<target>"`.
+fn parse_trace_defaults_args(attr: TokenStream2) -> syn::Result<LitStr> {
+    let mut target = None;
+    for (key, value) in parse_name_values(attr.clone())? {
+        if key != "target" {
+            return Err(syn::Error::new_spanned(
+                key,
+                "unknown argument, expected `target`",
+            ));
+        }
+        target = Some(value);
+    }
+    target.ok_or_else(|| syn::Error::new_spanned(attr, "expected `target = \"...\"`"))
+}
+
+/// Parses comma-separated `key = "value"` arguments.
+fn parse_name_values(attr: TokenStream2) -> syn::Result<Vec<(Ident, LitStr)>> {
+    let metas = Punctuated::<Meta, Token![,]>::parse_terminated.parse2(attr)?;
+    metas
+        .into_iter()
+        .map(|meta| {
+            let Meta::NameValue(name_value) = &meta else {
+                return Err(syn::Error::new_spanned(meta, "expected `name = \"...\"`"));
+            };
+            let Some(key) = name_value.path.get_ident() else {
+                return Err(syn::Error::new_spanned(
+                    &name_value.path,
+                    "expected an argument name",
+                ));
+            };
+            let Expr::Lit(ExprLit {
+                lit: Lit::Str(value),
+                ..
+            }) = &name_value.value
+            else {
+                return Err(syn::Error::new_spanned(
+                    &name_value.value,
+                    "expected a string literal",
+                ));
+            };
+            Ok((key.clone(), value.clone()))
+        })
+        .collect()
+}
+
+/// Adds `target` to the `#[trace]` attributes of the functions of `items` that
+/// have none, recursing into `impl` blocks and inline modules.
+fn set_default_target(items: &mut [Item], target: &LitStr) -> syn::Result<()> {
+    for item in items {
+        match item {
+            Item::Fn(function) => set_trace_target(&mut function.attrs, target)?,
+            Item::Impl(item_impl) => {
+                for impl_item in &mut item_impl.items {
+                    if let ImplItem::Fn(method) = impl_item {
+                        set_trace_target(&mut method.attrs, target)?;
+                    }
+                }
+            }
+            // A nested module with its own defaults keeps them.
+            Item::Mod(ItemMod {
+                attrs,
+                content: Some((_, items)),
+                ..
+            }) if !attrs
+                .iter()
+                .any(|attr| is_attribute(attr, "trace_defaults")) =>
+            {
+                set_default_target(items, target)?
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Adds `target` to a `#[trace]` attribute among `attrs` that has none.
+fn set_trace_target(attrs: &mut [Attribute], target: &LitStr) -> syn::Result<()> {
+    for attr in attrs {
+        if !is_attribute(attr, "trace") {
+            continue;
+        }
+        let path = attr.path().clone();
+        let args = match &attr.meta {
+            Meta::Path(_) => TokenStream2::new(),
+            Meta::List(list) => list.tokens.clone(),
+            Meta::NameValue(_) => continue,
+        };
+        let has_target = parse_name_values(args.clone())?
+            .iter()
+            .any(|(key, _)| key == "target");
+        if !has_target {
+            let separator = (!args.is_empty()).then(|| quote! { , });
+            *attr = syn::parse_quote! { #[#path(#args #separator target = #target)] };
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether an attribute is `name`, possibly through a path.
+fn is_attribute(attr: &Attribute, name: &str) -> bool {
+    attr.path()
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == name)
+}
+
+/// Parses an `err_level` value.
+fn parse_level(level: &LitStr) -> syn::Result<Ident> {
+    match level.value().as_str() {
+        "trace" => Ok(format_ident!("TRACE")),
+        "debug" => Ok(format_ident!("DEBUG")),
+        "info" => Ok(format_ident!("INFO")),
+        "warn" => Ok(format_ident!("WARN")),
+        "error" => Ok(format_ident!("ERROR")),
+        _ => Err(syn::Error::new_spanned(
+            level,
+            "expected one of \"trace\", \"debug\", \"info\", \"warn\" or \"error\"",
+        )),
+    }
+}
+
+/// Returns whether the return type of a function is written as a `Result`.
+fn returns_result(signature: &Signature) -> bool {
+    match &signature.output {
+        ReturnType::Type(_, ty) => match ty.as_ref() {
+            Type::Path(path) => path
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "Result"),
+            _ => false,
+        },
+        ReturnType::Default => false,
+    }
+}
+
+/// Generates the logging of a `Result`: `Ok` at trace level and `Err` at
+/// `err_level`.
+///
+/// The error is formatted with `Debug` if it implements it and with `Display`
+/// otherwise, through autoref specialization: the `Debug` formatting is
+/// implemented on the wrapper and the `Display` one on a reference to it, so that
+/// method resolution tries the former first.
+fn log_result(
+    function_name: &str,
+    err_level: &Ident,
+    event_target: &Option<TokenStream2>,
+) -> TokenStream2 {
+    quote! {
+        match &result {
+            Ok(ok) => tracing::trace!(#event_target result = ?ok),
+            Err(error) => {
+                struct TraceError<'a, E>(&'a E);
+                trait TraceErrorDebug {
+                    fn trace_error(&self) -> String;
+                }
+                impl<E: ::std::fmt::Debug> TraceErrorDebug for TraceError<'_, E> {
+                    fn trace_error(&self) -> String {
+                        format!("{:?}", self.0)
+                    }
+                }
+                trait TraceErrorDisplay {
+                    fn trace_error(&self) -> String;
+                }
+                impl<E: ::std::fmt::Display> TraceErrorDisplay for &TraceError<'_, E> {
+                    fn trace_error(&self) -> String {
+                        self.0.to_string()
+                    }
+                }
+                let error = (&TraceError(error)).trace_error();
+                tracing::event!(
+                    #event_target
+                    tracing::Level::#err_level,
+                    function = #function_name,
+                    error = %error,
+                    "{} returned an error: {}",
+                    #function_name,
+                    error
+                );
+            }
+        }
+    }
+}
+
 /// Extracts argument names from a function signature.
 ///
 /// This helper function processes a function signature to extract the names of all arguments,
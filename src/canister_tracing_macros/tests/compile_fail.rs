@@ -0,0 +1,5 @@
+#[test]
+fn invalid_arguments() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
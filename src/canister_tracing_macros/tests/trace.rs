@@ -0,0 +1,331 @@
+use bity_ic_canister_tracing_macros::{trace, trace_defaults};
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Collects the formatted events of a test subscriber.
+#[derive(Clone, Default)]
+struct Logs(Arc<Mutex<Vec<u8>>>);
+
+impl Logs {
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+impl Write for Logs {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Logs {
+    type Writer = Logs;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+/// Runs `f` with a subscriber logging every level, and returns the logged lines.
+fn capture_logs(f: impl FnOnce()) -> Vec<String> {
+    let logs = Logs::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::TRACE)
+        .with_ansi(false)
+        .without_time()
+        .with_writer(logs.clone())
+        .finish();
+    tracing::subscriber::with_default(subscriber, f);
+    logs.lines()
+}
+
+/// An error implementing `Display` only.
+struct DisplayOnlyError;
+
+impl fmt::Display for DisplayOnlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "display only")
+    }
+}
+
+#[derive(Debug)]
+struct BothError;
+
+impl fmt::Display for BothError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "display")
+    }
+}
+
+#[trace]
+fn checked_double(value: u64) -> Result<u64, String> {
+    value
+        .checked_mul(2)
+        .ok_or_else(|| format!("{} overflows", value))
+}
+
+#[trace(err_level = "error")]
+fn display_only(fail: bool) -> Result<u64, DisplayOnlyError> {
+    if fail {
+        Err(DisplayOnlyError)
+    } else {
+        Ok(1)
+    }
+}
+
+#[trace]
+fn both() -> Result<(), BothError> {
+    Err(BothError)
+}
+
+#[trace]
+fn double(value: u64) -> u64 {
+    value * 2
+}
+
+#[trace]
+async fn async_double(value: u64) -> Result<u64, String> {
+    checked_double(value)
+}
+
+struct Counter {
+    limit: u64,
+}
+
+impl Counter {
+    #[trace]
+    fn check(&self, value: u64) -> Result<u64, String> {
+        if value > self.limit {
+            Err(format!("{} is above {}", value, self.limit))
+        } else {
+            Ok(value)
+        }
+    }
+}
+
+#[trace(target = "icrc3::archive", name = "archive_batch")]
+fn archive_blocks(count: u64) -> Result<u64, String> {
+    if count == 0 {
+        Err("empty batch".to_string())
+    } else {
+        Ok(count)
+    }
+}
+
+#[trace(name = "renamed", err_level = "error")]
+async fn renamed_async(value: u64) -> Result<u64, String> {
+    Err(format!("{} rejected", value))
+}
+
+#[trace_defaults(target = "tests::archive")]
+mod archive {
+    use bity_ic_canister_tracing_macros::trace;
+
+    #[trace]
+    pub fn defaulted(value: u64) -> u64 {
+        value
+    }
+
+    #[trace(err_level = "error")]
+    pub fn defaulted_err() -> Result<(), String> {
+        Err("failed".to_string())
+    }
+
+    #[trace(target = "tests::own", name = "own")]
+    pub fn own_target() -> u64 {
+        1
+    }
+
+    pub struct Worker;
+
+    impl Worker {
+        #[trace]
+        pub fn work(&self) -> u64 {
+            2
+        }
+    }
+
+    pub mod nested {
+        use bity_ic_canister_tracing_macros::trace;
+
+        #[trace]
+        pub fn nested() -> u64 {
+            3
+        }
+    }
+
+    #[bity_ic_canister_tracing_macros::trace_defaults(target = "tests::retargeted")]
+    pub mod retargeted {
+        use bity_ic_canister_tracing_macros::trace;
+
+        #[trace]
+        pub fn retargeted() -> u64 {
+            4
+        }
+    }
+}
+
+fn errors(lines: &[String]) -> Vec<&String> {
+    lines
+        .iter()
+        .filter(|line| line.contains("returned an error"))
+        .collect()
+}
+
+#[test]
+fn test_ok_is_logged_at_trace_level() {
+    let lines = capture_logs(|| {
+        assert_eq!(checked_double(2), Ok(4));
+    });
+    assert!(errors(&lines).is_empty(), "{lines:?}");
+    assert!(
+        lines
+            .iter()
+            .any(|line| line.starts_with("TRACE") && line.contains("result=4")),
+        "{lines:?}"
+    );
+}
+
+#[test]
+fn test_err_is_logged_at_warn_level() {
+    let lines = capture_logs(|| {
+        assert!(checked_double(u64::MAX).is_err());
+    });
+    let errors = errors(&lines);
+    assert_eq!(errors.len(), 1, "{lines:?}");
+    assert!(errors[0].starts_with(" WARN"), "{}", errors[0]);
+    assert!(
+        errors[0].contains("checked_double returned an error: \"18446744073709551615 overflows\""),
+        "{}",
+        errors[0]
+    );
+    assert!(
+        errors[0].contains("function=\"checked_double\""),
+        "{}",
+        errors[0]
+    );
+}
+
+#[test]
+fn test_err_level_and_display_only_error() {
+    let lines = capture_logs(|| {
+        assert_eq!(display_only(false).ok(), Some(1));
+        assert!(display_only(true).is_err());
+    });
+    let errors = errors(&lines);
+    assert_eq!(errors.len(), 1, "{lines:?}");
+    assert!(errors[0].starts_with("ERROR"), "{}", errors[0]);
+    assert!(
+        errors[0].contains("display_only returned an error: display only"),
+        "{}",
+        errors[0]
+    );
+}
+
+#[test]
+fn test_debug_is_preferred_to_display() {
+    let lines = capture_logs(|| {
+        assert!(both().is_err());
+    });
+    let errors = errors(&lines);
+    assert_eq!(errors.len(), 1, "{lines:?}");
+    assert!(
+        errors[0].contains("both returned an error: BothError"),
+        "{}",
+        errors[0]
+    );
+}
+
+#[test]
+fn test_non_result_is_logged_at_trace_level() {
+    let lines = capture_logs(|| {
+        assert_eq!(double(3), 6);
+    });
+    assert!(errors(&lines).is_empty(), "{lines:?}");
+    assert!(
+        lines
+            .iter()
+            .any(|line| line.starts_with("TRACE") && line.contains("result=6")),
+        "{lines:?}"
+    );
+}
+
+#[test]
+fn test_async_fn_and_method() {
+    let lines = capture_logs(|| {
+        assert!(futures::executor::block_on(async_double(u64::MAX)).is_err());
+        let counter = Counter { limit: 10 };
+        assert_eq!(counter.check(5), Ok(5));
+        assert!(counter.check(11).is_err());
+    });
+    let errors = errors(&lines);
+    assert_eq!(errors.len(), 3, "{lines:?}");
+    assert!(errors[0].contains("checked_double returned an error"));
+    assert!(errors[1].contains("async_double returned an error"));
+    assert!(errors[2].contains("check returned an error: \"11 is above 10\""));
+}
+
+#[test]
+fn test_target_and_name() {
+    let lines = capture_logs(|| {
+        assert_eq!(archive_blocks(2), Ok(2));
+        assert!(archive_blocks(0).is_err());
+        assert!(futures::executor::block_on(renamed_async(1)).is_err());
+    });
+    assert!(
+        lines
+            .iter()
+            .any(|line| line.starts_with("TRACE archive_batch{count=2}: icrc3::archive: result=2")),
+        "{lines:?}"
+    );
+    let errors = errors(&lines);
+    assert_eq!(errors.len(), 2, "{lines:?}");
+    assert!(
+        errors[0].starts_with(
+            " WARN archive_batch{count=0}: icrc3::archive: archive_blocks returned an error"
+        ),
+        "{}",
+        errors[0]
+    );
+    assert!(
+        errors[1].starts_with("ERROR renamed{value=1}: trace: renamed_async returned an error"),
+        "{}",
+        errors[1]
+    );
+}
+
+#[test]
+fn test_trace_defaults_sets_the_target_of_a_module() {
+    let lines = capture_logs(|| {
+        assert_eq!(archive::defaulted(4), 4);
+        assert!(archive::defaulted_err().is_err());
+        assert_eq!(archive::own_target(), 1);
+        assert_eq!(archive::Worker.work(), 2);
+        assert_eq!(archive::nested::nested(), 3);
+        assert_eq!(archive::retargeted::retargeted(), 4);
+    });
+    for expected in [
+        "TRACE defaulted{value=4}: tests::archive: result=4",
+        "ERROR defaulted_err: tests::archive: defaulted_err returned an error",
+        "TRACE own: tests::own: result=1",
+        "TRACE work: tests::archive: result=2",
+        "TRACE nested: tests::archive: result=3",
+        "TRACE retargeted: tests::retargeted: result=4",
+    ] {
+        assert!(
+            lines.iter().any(|line| line.starts_with(expected)),
+            "{expected} not in {lines:?}"
+        );
+    }
+}
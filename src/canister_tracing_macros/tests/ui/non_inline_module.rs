@@ -0,0 +1,6 @@
+use bity_ic_canister_tracing_macros::trace_defaults;
+
+#[trace_defaults(target = "icrc3::archive")]
+mod archive;
+
+fn main() {}
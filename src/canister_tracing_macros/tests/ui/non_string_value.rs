@@ -0,0 +1,13 @@
+use bity_ic_canister_tracing_macros::{trace, trace_defaults};
+
+#[trace(err_level = error)]
+fn traced(value: u64) -> Result<u64, String> {
+    Ok(value)
+}
+
+#[trace_defaults(target = 42)]
+mod archive {}
+
+fn main() {
+    let _ = traced(1);
+}
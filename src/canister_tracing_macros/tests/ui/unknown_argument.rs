@@ -0,0 +1,13 @@
+use bity_ic_canister_tracing_macros::{trace, trace_defaults};
+
+#[trace(level = "debug")]
+fn traced(value: u64) -> u64 {
+    value
+}
+
+#[trace_defaults(name = "archive")]
+mod archive {}
+
+fn main() {
+    traced(1);
+}
@@ -0,0 +1,131 @@
+//! Cached storage stats of the archive canisters.
+//!
+//! Each archive reports the number and size of the blocks it stores with its
+//! `stats` query. The archive job fetches them again from the archives it inserted
+//! blocks into and from those whose stats are older than [`ARCHIVE_STATS_MAX_AGE`],
+//! so that they can be served from a query with when they were fetched. The cache
+//! is not kept across upgrades: the next archive runs rebuild it, the archives are
+//! listed without stats meanwhile.
+
+use bity_ic_icrc3_archive_api::queries::stats::StorageStats;
+use bity_ic_types::TimestampNanos;
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// The age after which the archive job fetches the stats of an archive again.
+pub const ARCHIVE_STATS_MAX_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// The cached stats of an archive canister.
+///
+/// # Fields
+///
+/// * `canister_id` - The archive canister
+/// * `stats` - What the archive reported, `None` until it is first fetched
+/// * `fetched_at` - When `stats` were fetched, in nanoseconds
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveStats {
+    pub canister_id: Principal,
+    pub stats: Option<StorageStats>,
+    pub fetched_at: Option<TimestampNanos>,
+}
+
+/// The last stats fetched from each archive canister, with when they were fetched.
+#[derive(Clone, Debug, Default)]
+pub struct ArchiveStatsCache {
+    entries: BTreeMap<Principal, (StorageStats, TimestampNanos)>,
+}
+
+impl ArchiveStatsCache {
+    /// Returns whether the stats of an archive were never fetched or are older
+    /// than [`ARCHIVE_STATS_MAX_AGE`] at `now`.
+    pub fn is_stale(&self, canister_id: &Principal, now: TimestampNanos) -> bool {
+        self.entries.get(canister_id).is_none_or(|(_, fetched_at)| {
+            now.saturating_sub(*fetched_at) >= ARCHIVE_STATS_MAX_AGE.as_nanos() as u64
+        })
+    }
+
+    /// Caches the stats of an archive fetched at `fetched_at`.
+    pub fn insert(
+        &mut self,
+        canister_id: Principal,
+        stats: StorageStats,
+        fetched_at: TimestampNanos,
+    ) {
+        self.entries.insert(canister_id, (stats, fetched_at));
+    }
+
+    /// Returns the cached stats of each archive of `canister_ids`, in that order.
+    pub fn list(&self, canister_ids: impl IntoIterator<Item = Principal>) -> Vec<ArchiveStats> {
+        canister_ids
+            .into_iter()
+            .map(|canister_id| {
+                let entry = self.entries.get(&canister_id);
+                ArchiveStats {
+                    canister_id,
+                    stats: entry.map(|(stats, _)| stats.clone()),
+                    fetched_at: entry.map(|(_, fetched_at)| *fetched_at),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Fetches the stats of an archive canister.
+///
+/// # Errors
+///
+/// Returns an error message if the archive can't be called, e.g. if it runs a
+/// version without the `stats` query.
+pub(crate) async fn fetch(canister_id: Principal) -> Result<StorageStats, String> {
+    bity_ic_icrc3_archive_c2c_client::stats(canister_id, &())
+        .await
+        .map_err(|e| format!("{:?}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Nat;
+
+    fn stats(blocks_stored: u64) -> StorageStats {
+        StorageStats {
+            blocks_stored,
+            total_bytes: 100 * blocks_stored,
+            last_insert_at: Some(1),
+            remaining_capacity: Nat::from(1_000u64),
+        }
+    }
+
+    #[test]
+    fn test_stale_entries_and_listing() {
+        let archive = Principal::from_slice(&[1]);
+        let other = Principal::from_slice(&[2]);
+        let max_age = ARCHIVE_STATS_MAX_AGE.as_nanos() as u64;
+
+        let mut cache = ArchiveStatsCache::default();
+        assert!(cache.is_stale(&archive, 0));
+
+        cache.insert(archive, stats(3), 10);
+        assert!(!cache.is_stale(&archive, 10 + max_age - 1));
+        assert!(cache.is_stale(&archive, 10 + max_age));
+        assert!(cache.is_stale(&other, 10));
+
+        assert_eq!(
+            cache.list([archive, other]),
+            vec![
+                ArchiveStats {
+                    canister_id: archive,
+                    stats: Some(stats(3)),
+                    fetched_at: Some(10),
+                },
+                ArchiveStats {
+                    canister_id: other,
+                    stats: None,
+                    fetched_at: None,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Audit blocks recording the administrative actions in the chain.
+//!
+//! When `audit_admin_actions` is set in the [`ICRC3Properties`], the `admin`
+//! block type is supported and the administrative endpoints generated by
+//! `icrc3_state!` call [`record_admin_action`] once an action succeeded: changing
+//! the recorders or the funding config, retiring an archive, managing its
+//! controllers or snapshots, and upgrading the canister. The action is then kept in
+//! the chain, with the principal that made it, instead of only in the log buffer.
+//!
+//! [`ICRC3Properties`]: crate::config::ICRC3Properties
+
+use crate::config::ICRC3Config;
+use crate::icrc3::ICRC3;
+use crate::interface::ICRC3Interface;
+use crate::runtime;
+use crate::schema::{FieldSchema, TransactionSchema, ValueKind};
+use crate::transaction::TransactionType;
+
+use bity_ic_types::TimestampNanos;
+use candid::{CandidType, Nat, Principal};
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use icrc_ledger_types::icrc3::blocks::SupportedBlockType;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::collections::BTreeMap;
+
+/// The block type of the audit blocks.
+pub const ADMIN_BLOCK_TYPE: &str = "admin";
+/// Where the audit blocks are described, as listed with the supported block types.
+pub const ADMIN_BLOCK_URL: &str =
+    "https://github.com/BitySA/dfinity-rust-libraries/blob/main/src/icrc3/src/audit.rs";
+
+/// An administrative action, recorded as an `admin` block.
+///
+/// The block is `{ btype: "admin", ts, tx: { action, caller, details } }`, with the
+/// caller as the blob of its principal.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct AuditTransaction {
+    pub action: String,
+    pub caller: Principal,
+    pub timestamp: TimestampNanos,
+    pub details: BTreeMap<String, ICRC3Value>,
+}
+
+impl AuditTransaction {
+    pub fn new(
+        action: String,
+        caller: Principal,
+        timestamp: TimestampNanos,
+        details: BTreeMap<String, ICRC3Value>,
+    ) -> Self {
+        Self {
+            action,
+            caller,
+            timestamp,
+            details,
+        }
+    }
+}
+
+impl TransactionType for AuditTransaction {
+    fn schemas() -> Vec<TransactionSchema> {
+        vec![TransactionSchema::new(
+            ADMIN_BLOCK_TYPE,
+            vec![
+                FieldSchema::required("btype", ValueKind::Text),
+                FieldSchema::required("ts", ValueKind::Nat),
+                FieldSchema::map(
+                    "tx",
+                    vec![
+                        FieldSchema::required("action", ValueKind::Text),
+                        FieldSchema::required("caller", ValueKind::Blob),
+                        // The details depend on the action.
+                        FieldSchema::map("details", vec![]),
+                    ],
+                ),
+            ],
+        )]
+    }
+
+    fn validate_transaction_fields(&self) -> Result<(), String> {
+        if self.action.is_empty() {
+            return Err("Action is required for admin".to_string());
+        }
+        Ok(())
+    }
+
+    fn timestamp(&self) -> Option<TimestampNanos> {
+        Some(self.timestamp)
+    }
+
+    fn tx(&self) -> ICRC3Value {
+        let mut tx = BTreeMap::new();
+        tx.insert("action".to_string(), ICRC3Value::Text(self.action.clone()));
+        tx.insert("caller".to_string(), principal_value(self.caller));
+        tx.insert("details".to_string(), ICRC3Value::Map(self.details.clone()));
+        ICRC3Value::Map(tx)
+    }
+
+    fn block_type(&self) -> String {
+        ADMIN_BLOCK_TYPE.to_string()
+    }
+}
+
+impl From<AuditTransaction> for ICRC3Value {
+    fn from(tx: AuditTransaction) -> Self {
+        let mut map = BTreeMap::new();
+        map.insert(
+            "btype".to_string(),
+            ICRC3Value::Text(ADMIN_BLOCK_TYPE.to_string()),
+        );
+        map.insert("ts".to_string(), ICRC3Value::Nat(Nat::from(tx.timestamp)));
+        map.insert("tx".to_string(), tx.tx());
+        ICRC3Value::Map(map)
+    }
+}
+
+/// Returns a principal as the blob value recorded in the blocks.
+pub fn principal_value(principal: Principal) -> ICRC3Value {
+    ICRC3Value::Blob(ByteBuf::from(principal.as_slice().to_vec()))
+}
+
+/// Adds the `admin` block type to the supported ones if `audit_admin_actions` is
+/// set and it is missing.
+pub(crate) fn support_admin_blocks(icrc3_config: &mut ICRC3Config) {
+    if icrc3_config.constants.audit_admin_actions
+        && !icrc3_config
+            .supported_blocks
+            .iter()
+            .any(|b| b.block_type == ADMIN_BLOCK_TYPE)
+    {
+        icrc3_config.supported_blocks.push(SupportedBlockType {
+            block_type: ADMIN_BLOCK_TYPE.to_string(),
+            url: ADMIN_BLOCK_URL.to_string(),
+        });
+    }
+}
+
+/// Records an administrative action made by the caller as an `admin` block, if
+/// `audit_admin_actions` is set.
+///
+/// The action itself must not depend on this: a failure to record it, e.g. when
+/// transactions are throttled, is logged as an error and otherwise ignored.
+///
+/// # Arguments
+///
+/// * `icrc3` - The ICRC3 instance
+/// * `action` - The name of the action, e.g. `add_recorder`
+/// * `details` - The arguments of the action
+///
+/// # Returns
+///
+/// The index of the block, or `None` if the action was not recorded.
+pub fn record_admin_action(
+    icrc3: &mut ICRC3,
+    action: &str,
+    details: Vec<(&str, ICRC3Value)>,
+) -> Option<u64> {
+    if !icrc3.icrc3_config.constants.audit_admin_actions {
+        return None;
+    }
+    let transaction = AuditTransaction::new(
+        action.to_string(),
+        runtime::caller(),
+        runtime::time(),
+        details
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    );
+    match icrc3.add_transaction(transaction) {
+        Ok(block_index) => Some(block_index),
+        Err(e) => {
+            tracing::error!(action, error = %e, "Failed to record the admin action");
+            None
+        }
+    }
+}
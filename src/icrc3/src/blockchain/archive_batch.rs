@@ -0,0 +1,163 @@
+//! Adaptive number of blocks per archive batch.
+//!
+//! The archive job sends the local blocks to the archive canisters in batches. A
+//! fixed number of blocks per batch wastes calls when the blocks are small and
+//! comes close to the payload and instruction limits when they are large, so the
+//! number of blocks is learned from the batches sent: it is doubled after a full
+//! batch that used less than [`GROW_BELOW_PERCENT`] of both budgets, and halved
+//! after a batch that used more than [`SHRINK_ABOVE_PERCENT`] of either budget or
+//! that failed. The learned size is stored with the ICRC3 state, so it survives
+//! upgrades, and stays within the `archive_batch_min_blocks` and
+//! `archive_batch_max_blocks` properties.
+
+use crate::blockchain::blockchain::{BATCH_MAX_BYTES_FOR_ARCHIVING, BATCH_SIZE_FOR_ARCHIVING};
+
+use serde::{Deserialize, Serialize};
+
+/// Instructions the archive job may spend on a batch, an eighth of the
+/// instruction limit of an update message.
+pub const ARCHIVE_BATCH_INSTRUCTION_BUDGET: u64 = 5_000_000_000;
+/// Share of a budget, in percent, above which the batch size is halved.
+pub const SHRINK_ABOVE_PERCENT: u64 = 70;
+/// Share of the budgets, in percent, below which a full batch doubles the batch size.
+pub const GROW_BELOW_PERCENT: u64 = 35;
+
+/// The bounds of the batch size, from `archive_batch_min_blocks` and
+/// `archive_batch_max_blocks`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchSizeLimits {
+    pub min_blocks: u64,
+    pub max_blocks: u64,
+}
+
+/// What a batch sent to an archive canister cost.
+///
+/// # Fields
+///
+/// * `blocks` - The number of blocks of the batch
+/// * `bytes` - The size of the blocks of the batch
+/// * `instructions` - The instructions spent on the batch by this canister
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchUsage {
+    pub blocks: u64,
+    pub bytes: u64,
+    pub instructions: u64,
+}
+
+impl BatchUsage {
+    /// Returns the share of the most used budget, in percent.
+    fn budget_percent(&self) -> u64 {
+        let bytes_percent = self.bytes.saturating_mul(100) / BATCH_MAX_BYTES_FOR_ARCHIVING as u64;
+        let instructions_percent =
+            self.instructions.saturating_mul(100) / ARCHIVE_BATCH_INSTRUCTION_BUDGET;
+        bytes_percent.max(instructions_percent)
+    }
+}
+
+/// The learned number of blocks per archive batch.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArchiveBatchSize {
+    blocks: u64,
+}
+
+impl Default for ArchiveBatchSize {
+    fn default() -> Self {
+        ArchiveBatchSize {
+            blocks: BATCH_SIZE_FOR_ARCHIVING as u64,
+        }
+    }
+}
+
+impl ArchiveBatchSize {
+    /// Returns the number of blocks of the next batch.
+    pub fn current(&self, limits: BatchSizeLimits) -> u64 {
+        self.blocks.clamp(limits.min_blocks, limits.max_blocks)
+    }
+
+    /// Adjusts the batch size after a batch was archived.
+    ///
+    /// A batch with fewer blocks than the batch size, e.g. the last one of a run,
+    /// only ever shrinks it: it says nothing about a larger batch.
+    pub fn record_success(&mut self, limits: BatchSizeLimits, usage: BatchUsage) {
+        let current = self.current(limits);
+        let percent = usage.budget_percent();
+        self.blocks = if percent > SHRINK_ABOVE_PERCENT {
+            current / 2
+        } else if percent < GROW_BELOW_PERCENT && usage.blocks >= current {
+            current.saturating_mul(2)
+        } else {
+            current
+        }
+        .clamp(limits.min_blocks, limits.max_blocks);
+    }
+
+    /// Halves the batch size after a batch failed to be archived.
+    pub fn record_failure(&mut self, limits: BatchSizeLimits) {
+        self.blocks = (self.current(limits) / 2).clamp(limits.min_blocks, limits.max_blocks);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: BatchSizeLimits = BatchSizeLimits {
+        min_blocks: 1,
+        max_blocks: 400,
+    };
+
+    #[test]
+    fn test_batch_size_grows_only_after_full_cheap_batches() {
+        let mut size = ArchiveBatchSize::default();
+        assert_eq!(size.current(LIMITS), 25);
+
+        let cheap = |blocks| BatchUsage {
+            blocks,
+            bytes: 100 * blocks,
+            instructions: 1_000_000 * blocks,
+        };
+        size.record_success(LIMITS, cheap(10));
+        assert_eq!(size.current(LIMITS), 25);
+        size.record_success(LIMITS, cheap(25));
+        assert_eq!(size.current(LIMITS), 50);
+        for _ in 0..10 {
+            let blocks = size.current(LIMITS);
+            size.record_success(LIMITS, cheap(blocks));
+        }
+        assert_eq!(size.current(LIMITS), LIMITS.max_blocks);
+    }
+
+    #[test]
+    fn test_batch_size_shrinks_over_either_budget_and_on_failure() {
+        let mut size = ArchiveBatchSize::default();
+        let over_bytes = BatchUsage {
+            blocks: 25,
+            bytes: BATCH_MAX_BYTES_FOR_ARCHIVING as u64,
+            instructions: 0,
+        };
+        size.record_success(LIMITS, over_bytes);
+        assert_eq!(size.current(LIMITS), 12);
+
+        let over_instructions = BatchUsage {
+            blocks: 12,
+            bytes: 1_000,
+            instructions: ARCHIVE_BATCH_INSTRUCTION_BUDGET,
+        };
+        size.record_success(LIMITS, over_instructions);
+        assert_eq!(size.current(LIMITS), 6);
+
+        size.record_failure(LIMITS);
+        size.record_failure(LIMITS);
+        size.record_failure(LIMITS);
+        assert_eq!(size.current(LIMITS), LIMITS.min_blocks);
+        size.record_failure(LIMITS);
+        assert_eq!(size.current(LIMITS), LIMITS.min_blocks);
+
+        // The learned size follows narrower limits.
+        let narrow = BatchSizeLimits {
+            min_blocks: 5,
+            max_blocks: 10,
+        };
+        assert_eq!(size.current(narrow), 5);
+    }
+}
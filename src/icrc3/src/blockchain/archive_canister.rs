@@ -1,4 +1,6 @@
 use crate::utils::trace;
+use bity_ic_canister_client::RetryPolicy;
+use bity_ic_icrc3_archive_api::insert_blocks::{InsertBlocksError, InsertBlocksSuccess};
 use bity_ic_icrc3_archive_api::types::encoded_blocks::EncodedBlock;
 use bity_ic_subcanister_manager::Canister;
 use bity_ic_utils::retry_async::retry_async;
@@ -18,60 +20,123 @@ pub struct ArchiveCanister {
     pub canister_param: bity_ic_icrc3_archive_api::Args,
     /// Information about the blocks stored in this archive
     pub archive_info: ICRC3ArchiveInfo,
+    /// The number of bytes the archive could still store at the last insert, if
+    /// known
+    #[serde(default)]
+    pub remaining_capacity: Option<u128>,
 }
 
 impl ArchiveCanister {
     /// Inserts a batch of blocks into the archive canister.
     ///
+    /// The archive skips the blocks it already stores, so a batch whose outcome
+    /// is unknown (e.g. after a timeout) can be sent again. The remaining capacity
+    /// it reports is cached, see [`ArchiveCanister::fits`].
+    ///
     /// # Arguments
     ///
+    /// * `first_block_id` - The ID of the first block of the batch
     /// * `blocks` - A vector of encoded blocks to insert
     ///
     /// # Returns
     ///
-    /// * `Ok(())` if the blocks were successfully inserted
+    /// * `Ok(InsertBlocksSuccess)` if the blocks are stored in the archive
     /// * `Err(String)` if the insertion failed
     ///
     /// # Errors
     ///
     /// Returns an error if:
     /// * The canister is not in the installed state
+    /// * The batch does not start at or before the end of the archive
     /// * The insertion operation fails
-    pub async fn insert_blocks(&mut self, blocks: Vec<EncodedBlock>) -> Result<(), String> {
+    pub async fn insert_blocks(
+        &mut self,
+        first_block_id: u64,
+        blocks: Vec<EncodedBlock>,
+    ) -> Result<InsertBlocksSuccess, String> {
         if self.state != bity_ic_subcanister_manager::CanisterState::Installed {
             return Err("Canister is not installed".to_string());
         }
 
-        let res = retry_async(
-            || bity_ic_icrc3_archive_c2c_client::insert_blocks(self.canister_id(), &blocks),
-            3,
+        let args = bity_ic_icrc3_archive_api::insert_blocks::Args {
+            first_block_id,
+            blocks,
+        };
+
+        // Inserting is idempotent, so a batch whose outcome is unknown is retried too.
+        let res = bity_ic_icrc3_archive_c2c_client::insert_blocks_with_retry(
+            self.canister_id(),
+            &args,
+            RetryPolicy::idempotent(),
         )
         .await;
 
         match res {
-            Ok(data_response) => match data_response {
-                bity_ic_icrc3_archive_api::insert_blocks::Response::Success => {
-                    // Update the archive info: increment the `end` by the number of blocks inserted
-                    let block_count = blocks.len() as u64;
-                    self.archive_info.end += block_count - 1;
-
-                    // Log the updated archive info for debugging
-                    ic_cdk::println!(
-                        "Updated archive info: start = {}, end = {}",
-                        self.archive_info.start,
-                        self.archive_info.end
-                    );
-
-                    Ok(())
+            Ok(Ok(success)) => {
+                if let Some(remaining_capacity) = &success.remaining_capacity {
+                    self.remaining_capacity = u128::try_from(remaining_capacity.0.clone()).ok();
                 }
-                bity_ic_icrc3_archive_api::insert_blocks::Response::Error(_) => {
-                    Err("Failed to insert data".to_string())
+
+                // Update the archive info: increment the `end` by the number of blocks inserted
+                if success.inserted > 0 {
+                    self.archive_info.end += success.inserted - 1;
                 }
-            },
+
+                // Log the updated archive info for debugging
+                ic_cdk::println!(
+                    "Updated archive info: start = {}, end = {}, already_present = {}",
+                    self.archive_info.start,
+                    self.archive_info.end,
+                    success.already_present
+                );
+
+                Ok(success)
+            }
+            Ok(Err(e)) => {
+                if let InsertBlocksError::NoSpaceLeft {
+                    remaining_capacity, ..
+                } = &e
+                {
+                    self.remaining_capacity = u128::try_from(remaining_capacity.0.clone()).ok();
+                }
+                Err(format!("Failed to insert data: {}", e))
+            }
             Err(e) => Err(format!("{e:?}")),
         }
     }
 
+    /// Creates the record of an external archive canister, installed by someone
+    /// else. Its range starts once blocks are inserted into it.
+    ///
+    /// # Arguments
+    ///
+    /// * `canister_id` - The principal ID of the canister
+    /// * `init_args` - The init arguments new archive canisters are installed with,
+    ///   kept for reference only
+    pub fn external(
+        canister_id: Principal,
+        init_args: bity_ic_icrc3_archive_api::init::InitArgs,
+    ) -> Self {
+        Self {
+            state: bity_ic_subcanister_manager::CanisterState::Installed,
+            canister_param: bity_ic_icrc3_archive_api::Args::Init(init_args),
+            archive_info: ICRC3ArchiveInfo {
+                canister_id,
+                start: 0u64.into(),
+                end: 0u64.into(),
+            },
+            remaining_capacity: None,
+        }
+    }
+
+    /// Returns whether a batch of `batch_bytes` fits in the archive, as far as its
+    /// cached remaining capacity tells. A batch is assumed to fit while the
+    /// capacity is unknown, the archive rejecting it otherwise.
+    pub fn fits(&self, batch_bytes: u128) -> bool {
+        self.remaining_capacity
+            .is_none_or(|remaining_capacity| batch_bytes <= remaining_capacity)
+    }
+
     /// Gets the available space in the archive canister.
     ///
     /// # Returns
@@ -132,6 +197,7 @@ impl bity_ic_subcanister_manager::Canister for ArchiveCanister {
                     start: init_args.archive_config.block_offset.into(),
                     end: init_args.archive_config.block_offset.into(),
                 },
+                remaining_capacity: Some(init_args.archive_config.max_memory_size_bytes),
             },
             bity_ic_icrc3_archive_api::Args::Upgrade(_) => {
                 panic!(
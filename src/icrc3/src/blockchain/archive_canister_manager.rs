@@ -1,17 +1,30 @@
 use crate::blockchain::archive_canister::ArchiveCanister;
+use crate::blockchain::block_transform::BlockTransformConfig;
+use crate::config::FundingConfig;
+use crate::rebuild::RebuiltArchive;
+use crate::runtime;
 use crate::utils::trace;
 
 use bity_ic_icrc3_archive_api::{
-    archive_config::ArchiveConfig, lifecycle::BlockType, types::encoded_blocks::EncodedBlock,
+    archive_config::ArchiveConfig,
+    lifecycle::BlockType,
+    types::{block_compression, encoded_blocks::EncodedBlock},
+};
+use bity_ic_subcanister_manager::{
+    Canister, CanisterHistory, ReinstallError, SubCanisterManager, MAX_CANISTERS_PAGE_SIZE,
+};
+pub use bity_ic_subcanister_manager::{
+    CyclesSample, Divergence, DivergenceKind, FundingAlert, FundingAlertReason,
+    ReconciliationReport, ReinstallConfirmation, SubCanisterCalls, SuggestedAction,
 };
-use bity_ic_subcanister_manager::{Canister, SubCanisterManager};
 use bity_ic_types::BuildVersion;
-use candid::Principal;
+use candid::{CandidType, Principal};
 use canfund::manager::options::{CyclesThreshold, FundManagerOptions, FundStrategy};
+use ic_cdk::management_canister::InstallCodeArgs;
 use ic_ledger_types::BlockIndex;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub const ARCHIVE_WASM: &[u8] = include_bytes!("../../wasm/icrc3_archive_canister.wasm.gz");
 const DEFAULT_INITIAL_CYCLES: u128 = 5_000_000_000_000;
@@ -20,6 +33,66 @@ const DEFAULT_MIN_CYCLES: u128 = 1_000_000_000_000;
 const DEFAULT_FUND_CYCLES: u128 = 2_000_000_000_000;
 const DEFAULT_INTERVAL_SECS: u64 = 60;
 
+fn fund_manager_options(
+    interval_secs: u64,
+    min_cycles: u128,
+    fund_cycles: u128,
+) -> FundManagerOptions {
+    FundManagerOptions::new()
+        .with_interval_secs(interval_secs)
+        .with_strategy(FundStrategy::BelowThreshold(
+            CyclesThreshold::new()
+                .with_min_cycles(min_cycles)
+                .with_fund_cycles(fund_cycles),
+        ))
+}
+
+/// Returns whether an `insert_blocks` error means the archive canister has no code,
+/// e.g. after it was uninstalled or ran out of cycles.
+fn is_archive_uninstalled(error: &str) -> bool {
+    error.contains("IC0537") || error.contains("contains no Wasm module")
+}
+
+/// Where a batch of blocks goes, see [`ArchiveCanisterManager::insert_plan`].
+#[derive(Debug, PartialEq, Eq)]
+enum InsertPlan {
+    /// Append the blocks to the active archive canister
+    Append(Principal),
+    /// Create a new archive canister starting at the first block, or move on to
+    /// the next external archive canister if any is configured
+    CreateArchive,
+}
+
+/// Creation and upgrade history of an archive canister, for metrics.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveCanisterHistory {
+    pub canister_id: Principal,
+    pub history: CanisterHistory,
+    /// Whether the archive canister is retired and no longer receives blocks
+    pub retired: bool,
+    /// Whether the blocks of the archive canister are marked as lost
+    #[serde(default)]
+    pub unrecoverable: bool,
+}
+
+/// A reinstall of an archive canister, made without holding the lock of the
+/// archive manager, see [`ArchiveCanisterManager::reinstall_plan`].
+#[derive(Clone)]
+pub struct ArchiveReinstall {
+    canister_id: Principal,
+    init_args: bity_ic_icrc3_archive_api::Args,
+    install_args: InstallCodeArgs,
+    calls: SubCanisterCalls,
+}
+
+impl ArchiveReinstall {
+    /// Stops the archive canister, reinstalls it and starts it again. The outcome
+    /// is recorded with [`ArchiveCanisterManager::record_reinstall`].
+    pub async fn run(&self) -> Result<(), ReinstallError> {
+        self.calls.reinstall(self.install_args.clone()).await
+    }
+}
+
 /// Manages multiple archive canisters for storing blockchain data.
 ///
 /// This struct handles the creation, management, and coordination of multiple
@@ -34,12 +107,24 @@ pub struct ArchiveCanisterManager {
     pub upgrade_args: bity_ic_icrc3_archive_api::post_upgrade::UpgradeArgs,
     /// Mapping of block IDs to canister IDs
     pub canisters_by_block_offset: Vec<(BlockIndex, Principal)>,
+    /// Transform applied to blocks before they are sent to archive canisters
+    #[serde(default)]
+    pub block_transform: BlockTransformConfig,
+    /// Archive canisters whose block range is lost, see
+    /// [`mark_archive_unrecoverable`](Self::mark_archive_unrecoverable)
+    #[serde(default)]
+    pub unrecoverable_archives: HashSet<Principal>,
+    /// Archive canisters created and funded by someone else, e.g. governance, used
+    /// in order instead of creating archive canisters, see
+    /// [`set_external_archives`](Self::set_external_archives)
+    #[serde(default)]
+    pub external_archives: Vec<ArchiveCanister>,
 }
 
 impl Default for ArchiveCanisterManager {
     /// Creates a default ArchiveCanisterManager with default settings.
     fn default() -> Self {
-        let this_canister_id = ic_cdk::api::canister_self();
+        let this_canister_id = runtime::canister_self();
         let version = bity_ic_icrc3_archive_api::VERSION.to_string();
         let mut hasher = Sha256::new();
         hasher.update(version.as_bytes());
@@ -47,22 +132,20 @@ impl Default for ArchiveCanisterManager {
 
         Self {
             sub_canister_manager: SubCanisterManager::new(
-                ic_cdk::api::canister_self(),
+                runtime::canister_self(),
                 HashMap::new(),
-                vec![ic_cdk::api::canister_self()],
-                vec![ic_cdk::api::canister_self()],
+                vec![runtime::canister_self()],
+                vec![runtime::canister_self()],
                 DEFAULT_INITIAL_CYCLES,
                 DEFAULT_RESERVED_CYCLES,
                 false,
                 commit_hash.clone(),
                 ARCHIVE_WASM.to_vec(),
-                FundManagerOptions::new()
-                    .with_interval_secs(DEFAULT_INTERVAL_SECS)
-                    .with_strategy(FundStrategy::BelowThreshold(
-                        CyclesThreshold::new()
-                            .with_min_cycles(DEFAULT_MIN_CYCLES)
-                            .with_fund_cycles(DEFAULT_FUND_CYCLES),
-                    )),
+                fund_manager_options(
+                    DEFAULT_INTERVAL_SECS,
+                    DEFAULT_MIN_CYCLES,
+                    DEFAULT_FUND_CYCLES,
+                ),
             ),
             init_args: bity_ic_icrc3_archive_api::init::InitArgs {
                 test_mode: false,
@@ -83,6 +166,9 @@ impl Default for ArchiveCanisterManager {
                 block_type: BlockType::Default,
             },
             canisters_by_block_offset: vec![],
+            block_transform: BlockTransformConfig::None,
+            unrecoverable_archives: HashSet::new(),
+            external_archives: vec![],
         }
     }
 }
@@ -92,7 +178,9 @@ impl ArchiveCanisterManager {
     ///
     /// # Arguments
     ///
-    /// * `init_args` - Arguments for initializing new canisters
+    /// * `init_args` - Arguments for initializing new canisters. With `test_mode` set,
+    ///   the management canister calls are also simulated, see
+    ///   [`SubCanisterManager::test_mode`]
     /// * `upgrade_args` - Arguments for upgrading existing canisters
     /// * `sub_canisters` - Initial set of sub-canisters
     /// * `controllers` - List of controller principals
@@ -119,7 +207,7 @@ impl ArchiveCanisterManager {
 
         Self {
             sub_canister_manager: SubCanisterManager::new(
-                ic_cdk::api::canister_self(),
+                runtime::canister_self(),
                 sub_canisters,
                 controllers,
                 authorized_principal,
@@ -128,34 +216,435 @@ impl ArchiveCanisterManager {
                 init_args.test_mode,
                 init_args.commit_hash.clone(),
                 wasm,
-                FundManagerOptions::new()
-                    .with_interval_secs(interval_secs)
-                    .with_strategy(FundStrategy::BelowThreshold(
-                        CyclesThreshold::new()
-                            .with_min_cycles(min_cycles)
-                            .with_fund_cycles(fund_cycles),
-                    )),
+                fund_manager_options(interval_secs, min_cycles, fund_cycles),
             ),
             init_args,
             upgrade_args,
             canisters_by_block_offset: vec![],
+            block_transform: BlockTransformConfig::None,
+            unrecoverable_archives: HashSet::new(),
+            external_archives: vec![],
+        }
+    }
+
+    /// Applies a new funding configuration to the archive canisters.
+    ///
+    /// The cycle thresholds apply to existing canisters immediately, while the
+    /// initial and reserved cycles only apply to canisters created afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `funding_config` - The new funding configuration
+    pub fn set_funding_config(&mut self, funding_config: &FundingConfig) {
+        self.sub_canister_manager.initial_cycles = funding_config.initial_cycles;
+        self.sub_canister_manager.reserved_cycles = funding_config.reserved_cycles;
+        self.sub_canister_manager
+            .set_funding_config(fund_manager_options(
+                funding_config.interval_secs,
+                funding_config.min_cycles,
+                funding_config.fund_cycles,
+            ));
+        self.sub_canister_manager
+            .set_retired_fund_strategy(funding_config.retired_min_cycles.map(|min_cycles| {
+                FundStrategy::BelowThreshold(
+                    CyclesThreshold::new()
+                        .with_min_cycles(min_cycles)
+                        .with_fund_cycles(funding_config.fund_cycles),
+                )
+            }));
+    }
+
+    /// Replaces the external archive canisters, used in order instead of creating
+    /// archive canisters when any is configured.
+    ///
+    /// External archive canisters are neither funded nor upgraded by the manager.
+    /// Each one must be installed with the archive wasm, authorize this canister,
+    /// and start at the block it is used from: the first one at the first block
+    /// archived from then on, e.g. 0, the next ones at the block the previous one
+    /// filled up at.
+    ///
+    /// # Arguments
+    ///
+    /// * `canister_ids` - The external archive canisters, in the order they are used
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a canister is listed twice or is an archive canister
+    /// created by the manager, or if the external archive canisters already holding
+    /// blocks are not listed first, in the same order.
+    pub fn set_external_archives(&mut self, canister_ids: Vec<Principal>) -> Result<(), String> {
+        for (i, canister_id) in canister_ids.iter().enumerate() {
+            if canister_ids[..i].contains(canister_id) {
+                return Err(format!(
+                    "External archive canister {} is listed more than once",
+                    canister_id
+                ));
+            }
+            if self
+                .sub_canister_manager
+                .sub_canisters
+                .contains_key(canister_id)
+            {
+                return Err(format!(
+                    "Archive canister {} was created by this canister and can't be external",
+                    canister_id
+                ));
+            }
+        }
+        let used: Vec<Principal> = self
+            .external_archives
+            .iter()
+            .map(|canister| canister.canister_id())
+            .filter(|canister_id| self.archive_block_offset(*canister_id).is_ok())
+            .collect();
+        if !canister_ids.starts_with(&used) {
+            return Err(format!(
+                "External archive canisters holding blocks must be listed first, in order: {:?}",
+                used
+            ));
+        }
+
+        let mut external_archives = std::mem::take(&mut self.external_archives);
+        self.external_archives = canister_ids
+            .into_iter()
+            .map(|canister_id| {
+                match external_archives
+                    .iter()
+                    .position(|canister| canister.canister_id() == canister_id)
+                {
+                    Some(position) => external_archives.swap_remove(position),
+                    None => ArchiveCanister::external(canister_id, self.init_args.clone()),
+                }
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Returns whether an archive canister is an external one, see
+    /// [`set_external_archives`](Self::set_external_archives).
+    pub fn is_external_archive(&self, canister_id: &Principal) -> bool {
+        self.external_archives
+            .iter()
+            .any(|canister| canister.canister_id() == *canister_id)
+    }
+
+    /// Returns the archive canister created by the manager or configured as
+    /// external with this id.
+    fn archive_canister(&self, canister_id: &Principal) -> Option<&ArchiveCanister> {
+        self.sub_canister_manager
+            .sub_canisters
+            .get(canister_id)
+            .map(|canister| canister.as_ref())
+            .or_else(|| {
+                self.external_archives
+                    .iter()
+                    .find(|canister| canister.canister_id() == *canister_id)
+            })
+    }
+
+    /// Returns the archive canister created by the manager or configured as
+    /// external with this id.
+    fn archive_canister_mut(&mut self, canister_id: &Principal) -> Option<&mut ArchiveCanister> {
+        match self.sub_canister_manager.sub_canisters.get_mut(canister_id) {
+            Some(canister) => Some(canister.as_mut()),
+            None => self
+                .external_archives
+                .iter_mut()
+                .find(|canister| canister.canister_id() == *canister_id),
+        }
+    }
+
+    /// Inserts blocks into the first external archive canister not holding blocks
+    /// yet, and registers it from `block_offset`.
+    async fn insert_into_next_external_archive(
+        &mut self,
+        blocks: Vec<EncodedBlock>,
+        block_offset: BlockIndex,
+    ) -> Result<(), String> {
+        let registered: Vec<Principal> = self
+            .canisters_by_block_offset
+            .iter()
+            .map(|(_, canister_id)| *canister_id)
+            .collect();
+        let Some(canister) = self
+            .external_archives
+            .iter_mut()
+            .find(|canister| !registered.contains(&canister.canister_id()))
+        else {
+            return Err(format!(
+                "All the external archive canisters are full, configure another one starting at block {}",
+                block_offset
+            ));
+        };
+
+        let canister_id = canister.canister_id();
+        trace(format!(
+            "Moving on to external archive canister {} from block {}",
+            canister_id, block_offset
+        ));
+        canister.archive_info.start = block_offset.into();
+        canister.archive_info.end = block_offset.into();
+        canister
+            .insert_blocks(block_offset, blocks)
+            .await
+            .map_err(|e| {
+                format!(
+                    "Failed to insert blocks into external archive canister {} from block {}: {}",
+                    canister_id, block_offset, e
+                )
+            })?;
+        self.canisters_by_block_offset
+            .push((block_offset, canister_id));
+        Ok(())
+    }
+
+    /// Takes over the archive canisters of a rebuilt chain, see
+    /// [`crate::rebuild`].
+    ///
+    /// The archive canisters configured as external are registered as such, the
+    /// others as archive canisters created by this canister, funded and upgraded
+    /// along with new ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `archives` - The archive canisters, ordered by their first block
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if archive canisters are already registered.
+    pub fn register_rebuilt_archives(&mut self, archives: &[RebuiltArchive]) -> Result<(), String> {
+        if !self.canisters_by_block_offset.is_empty() {
+            return Err(format!(
+                "Archive canisters are already registered: {:?}",
+                self.canisters_by_block_offset
+            ));
         }
+
+        for archive in archives {
+            let canister_id = archive.canister_id;
+            if !self.is_external_archive(&canister_id) {
+                let mut init_args = self.init_args.clone();
+                init_args.archive_config.block_offset = archive.start;
+                self.sub_canister_manager.sub_canisters.insert(
+                    canister_id,
+                    Box::new(ArchiveCanister::new(
+                        canister_id,
+                        bity_ic_subcanister_manager::CanisterState::Installed,
+                        bity_ic_icrc3_archive_api::Args::Init(init_args),
+                    )),
+                );
+            }
+            if let Some(canister) = self.archive_canister_mut(&canister_id) {
+                canister.archive_info.start = archive.start.into();
+                // The archive info holds the index of the last block, archives are never empty.
+                canister.archive_info.end = (archive.end - 1).into();
+            }
+            self.canisters_by_block_offset
+                .push((archive.start, canister_id));
+        }
+
+        let funding_config = self.sub_canister_manager.funding_config.clone();
+        self.sub_canister_manager.set_funding_config(funding_config);
+        Ok(())
     }
 
-    /// Inserts a block into an appropriate archive canister.
+    /// Retires an archive canister: no more blocks are inserted into it, even if
+    /// it has space left, but it stays funded and keeps serving its blocks. The
+    /// next blocks go to a new archive canister.
+    ///
+    /// # Arguments
+    ///
+    /// * `canister_id` - The archive canister to retire
+    pub fn retire_archive(&mut self, canister_id: Principal) -> Result<(), String> {
+        self.sub_canister_manager
+            .retire(canister_id)
+            .map_err(|e| format!("Failed to retire archive canister: {:?}", e))
+    }
+
+    /// Puts a retired archive canister back into service. Blocks are inserted
+    /// into it again once it is the last archive canister.
+    ///
+    /// An archive canister marked unrecoverable stays retired: its block range is
+    /// lost, so no blocks may be appended after it.
+    ///
+    /// # Arguments
+    ///
+    /// * `canister_id` - The archive canister to put back into service
+    pub fn unretire_archive(&mut self, canister_id: Principal) -> Result<(), String> {
+        if self.is_archive_unrecoverable(&canister_id) {
+            return Err(format!(
+                "Archive canister {} is marked unrecoverable and cannot be put back into service",
+                canister_id
+            ));
+        }
+        self.sub_canister_manager
+            .unretire(canister_id)
+            .map_err(|e| format!("Failed to unretire archive canister: {:?}", e))
+    }
+
+    /// Marks the block range of a retired archive canister as lost, e.g. when its
+    /// state is corrupted beyond repair. Its blocks are still looked up in it, and
+    /// it can then be reinstalled with [`reinstall_archive`](Self::reinstall_archive).
+    ///
+    /// # Arguments
+    ///
+    /// * `canister_id` - The archive canister whose blocks are lost
+    pub fn mark_archive_unrecoverable(&mut self, canister_id: Principal) -> Result<(), String> {
+        self.archive_block_offset(canister_id)?;
+        if !self.sub_canister_manager.is_retired(&canister_id) {
+            return Err(format!(
+                "Archive canister {} must be retired before being marked unrecoverable",
+                canister_id
+            ));
+        }
+        self.unrecoverable_archives.insert(canister_id);
+        Ok(())
+    }
+
+    /// Returns whether the block range of an archive canister is marked as lost.
+    pub fn is_archive_unrecoverable(&self, canister_id: &Principal) -> bool {
+        self.unrecoverable_archives.contains(canister_id)
+    }
+
+    /// Reinstalls an archive canister, wiping its state. It starts again empty at
+    /// the first block of its range, and stays retired and unrecoverable.
+    ///
+    /// # Arguments
+    ///
+    /// * `confirmation` - The confirmation of the data loss for the archive canister
+    ///
+    /// # Errors
+    ///
+    /// Returns an error unless the archive canister is retired and marked
+    /// unrecoverable, or if the confirmation is not valid.
+    pub async fn reinstall_archive(
+        &mut self,
+        confirmation: ReinstallConfirmation,
+    ) -> Result<(), String> {
+        let reinstall = self.reinstall_plan(&confirmation)?;
+        let result = reinstall.run().await;
+        self.record_reinstall(reinstall, result)
+    }
+
+    /// Checks that an archive canister can be reinstalled and returns the
+    /// reinstall, see [`reinstall_archive`](Self::reinstall_archive).
+    pub fn reinstall_plan(
+        &self,
+        confirmation: &ReinstallConfirmation,
+    ) -> Result<ArchiveReinstall, String> {
+        let canister_id = confirmation.canister_id();
+        let block_offset = self.archive_block_offset(canister_id)?;
+        if !self.sub_canister_manager.is_retired(&canister_id)
+            || !self.is_archive_unrecoverable(&canister_id)
+        {
+            return Err(format!(
+                "Archive canister {} must be retired and marked unrecoverable before being reinstalled",
+                canister_id
+            ));
+        }
+
+        let mut init_args = self.init_args.clone();
+        init_args.archive_config.block_offset = block_offset;
+        let init_args = bity_ic_icrc3_archive_api::Args::Init(init_args);
+        let install_args = self
+            .sub_canister_manager
+            .reinstall_args(canister_id, &init_args, confirmation)
+            .map_err(|e| format!("Failed to reinstall archive canister: {:?}", e))?;
+        Ok(ArchiveReinstall {
+            canister_id,
+            init_args,
+            install_args,
+            calls: self.sub_canister_manager.calls(),
+        })
+    }
+
+    /// Records the outcome of an [`ArchiveReinstall`].
+    pub fn record_reinstall(
+        &mut self,
+        reinstall: ArchiveReinstall,
+        result: Result<(), ReinstallError>,
+    ) -> Result<(), String> {
+        self.sub_canister_manager.record_reinstall(
+            reinstall.canister_id,
+            reinstall.init_args,
+            &result,
+        );
+        result.map_err(|e| format!("Failed to reinstall archive canister: {:?}", e))
+    }
+
+    /// Returns the first block of an archive canister, as registered.
+    fn archive_block_offset(&self, canister_id: Principal) -> Result<BlockIndex, String> {
+        self.canisters_by_block_offset
+            .iter()
+            .find(|(_, id)| *id == canister_id)
+            .map(|(block_offset, _)| *block_offset)
+            .ok_or_else(|| format!("Unknown archive canister: {}", canister_id))
+    }
+
+    /// Returns the active archive canister: the one holding the end of the chain,
+    /// the only one blocks can be appended to, unless there is none or it is
+    /// retired.
+    pub fn active_archive(&self) -> Option<Principal> {
+        self.canisters_by_block_offset
+            .last()
+            .map(|(_, canister_id)| *canister_id)
+            .filter(|canister_id| !self.sub_canister_manager.is_retired(canister_id))
+    }
+
+    /// Decides where a batch of `batch_bytes` starting at `block_offset` goes: the
+    /// active archive if it has room for the batch as far as its cached remaining
+    /// capacity tells, a new archive otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the batch would not fit in a new archive either, or if
+    /// it starts before the active archive.
+    fn insert_plan(
+        &self,
+        block_offset: BlockIndex,
+        batch_bytes: u128,
+    ) -> Result<InsertPlan, String> {
+        let max_memory_size_bytes = self.init_args.archive_config.max_memory_size_bytes;
+        if batch_bytes > max_memory_size_bytes {
+            return Err(format!(
+                "Blocks of {} bytes don't fit in an archive of {} bytes",
+                batch_bytes, max_memory_size_bytes
+            ));
+        }
+
+        let Some(canister_id) = self.active_archive() else {
+            return Ok(InsertPlan::CreateArchive);
+        };
+        if self.get_canister_id_by_block_id(block_offset) != Ok(canister_id) {
+            return Err(format!(
+                "Blocks from {} are behind the active archive canister {}",
+                block_offset, canister_id
+            ));
+        }
+        match self.archive_canister(&canister_id) {
+            Some(canister) if canister.fits(batch_bytes) => Ok(InsertPlan::Append(canister_id)),
+            _ => Ok(InsertPlan::CreateArchive),
+        }
+    }
+
+    /// Inserts blocks into the active archive canister.
     ///
     /// This method will:
-    /// 1. Try to insert the block into existing canisters
-    /// 2. Create a new canister if no existing canister has space
+    /// 1. Insert the blocks into the active archive canister if it has room for
+    ///    them, see [`Self::active_archive`]
+    /// 2. Create a new canister otherwise, or if the active one turns out to have no
+    ///    space left or its code was uninstalled. When external archive canisters
+    ///    are configured, the next one is used instead, and an error is returned
+    ///    once they are all full
     ///
     /// # Arguments
     ///
-    /// * `block_id` - The ID of the block to insert
-    /// * `block` - The encoded block to insert
+    /// * `blocks` - The encoded blocks to insert
+    /// * `block_offset` - The ID of the first block
     ///
     /// # Returns
     ///
-    /// * `Ok(())` if the block was successfully inserted
+    /// * `Ok(())` if the blocks were successfully inserted
     /// * `Err(String)` if the insertion failed
     pub async fn insert_blocks(
         &mut self,
@@ -165,30 +654,52 @@ impl ArchiveCanisterManager {
         trace(format!("Starting to insert blocks"));
         trace(format!("insert_blocks: blocks: {:?}", blocks));
 
-        for (_, canister) in self.sub_canister_manager.sub_canisters.iter_mut() {
-            trace(format!(
-                "Checking available space in canister {:?}...",
-                canister.canister_id()
-            ));
+        #[cfg(feature = "testing-hooks")]
+        crate::testing_hooks::before_archive_insert().await?;
 
-            match canister.insert_blocks(blocks.clone()).await {
-                Ok(_) => {
-                    return Ok(());
-                }
-                Err(e) => {
-                    if e.as_str().contains("no space left") {
-                        continue;
-                    } else {
-                        return Err(format!("Failed to insert block into canister: {}", e));
+        let blocks = self.seal_blocks(blocks)?;
+        let batch_bytes = blocks
+            .iter()
+            .map(|block| block.stored_size_bytes() as u128)
+            .sum();
+
+        // Blocks are only appended to the active archive, any other archive would
+        // reject them as non contiguous.
+        if let InsertPlan::Append(canister_id) = self.insert_plan(block_offset, batch_bytes)? {
+            if let Some(canister) = self.archive_canister_mut(&canister_id) {
+                trace(format!(
+                    "Inserting blocks from {} into canister {:?}...",
+                    block_offset, canister_id
+                ));
+
+                match canister.insert_blocks(block_offset, blocks.clone()).await {
+                    Ok(_) => {
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        if is_archive_uninstalled(&e) {
+                            trace(format!(
+                                "Archive canister {:?} has no code, replacing it from block {}",
+                                canister_id, block_offset
+                            ));
+                        } else if !e.as_str().contains("no space left") {
+                            return Err(format!("Failed to insert block into canister: {}", e));
+                        }
                     }
                 }
             }
         }
 
+        if !self.external_archives.is_empty() {
+            return self
+                .insert_into_next_external_archive(blocks, block_offset)
+                .await;
+        }
+
         let mut init_args = self.init_args.clone();
         init_args.archive_config.block_offset = block_offset;
 
-        // If no canister had enough space, create a new canister
+        // The active canister can't take the blocks, create a new one
         match self
             .sub_canister_manager
             .create_canister(bity_ic_icrc3_archive_api::Args::Init(init_args))
@@ -210,7 +721,10 @@ impl ArchiveCanisterManager {
                     .sub_canisters
                     .get_mut(&canister_id)
                 {
-                    if let Err(e) = canister_in_manager.insert_blocks(blocks.clone()).await {
+                    if let Err(e) = canister_in_manager
+                        .insert_blocks(block_offset, blocks.clone())
+                        .await
+                    {
                         trace(format!("Failed to insert block into new canister: {}", e));
                         return Err(format!("Failed to insert block into new canister: {}", e));
                     }
@@ -230,8 +744,15 @@ impl ArchiveCanisterManager {
         }
     }
 
-    /// Returns a list of all installed archive canisters.
+    /// Returns a list of all installed archive canisters, including the external
+    /// archive canisters holding blocks.
     pub fn get_subcanisters_installed(&self) -> Vec<ArchiveCanister> {
+        let external_archives = self
+            .external_archives
+            .iter()
+            .filter(|canister| self.archive_block_offset(canister.canister_id()).is_ok())
+            .cloned();
+
         self.sub_canister_manager
             .list_canisters()
             .into_iter()
@@ -242,9 +763,69 @@ impl ArchiveCanisterManager {
                     None
                 }
             })
+            .chain(external_archives)
             .collect()
     }
 
+    /// Returns the creation and upgrade history of each archive canister, ordered
+    /// by canister id.
+    ///
+    /// The archives are listed page by page from the records of the manager,
+    /// without calling them.
+    pub fn canister_histories(&self) -> Vec<ArchiveCanisterHistory> {
+        let mut histories = vec![];
+        let mut start_after = None;
+        loop {
+            let page = self
+                .sub_canister_manager
+                .list_canisters_page(start_after, MAX_CANISTERS_PAGE_SIZE);
+            histories.extend(page.canisters.into_iter().map(|canister| {
+                ArchiveCanisterHistory {
+                    canister_id: canister.canister_id,
+                    history: self
+                        .sub_canister_manager
+                        .canister_history(&canister.canister_id)
+                        .unwrap_or_default(),
+                    retired: canister.retired,
+                    unrecoverable: self.unrecoverable_archives.contains(&canister.canister_id),
+                }
+            }));
+            match page.next {
+                Some(next) => start_after = Some(next),
+                None => return histories,
+            }
+        }
+    }
+
+    /// Compresses, if enabled, and seals blocks before they are sent to an archive
+    /// canister. Blocks are compressed first as sealed bytes don't compress.
+    pub fn seal_blocks(&self, blocks: Vec<EncodedBlock>) -> Result<Vec<EncodedBlock>, String> {
+        let transform = self.block_transform.transform()?;
+        let compression = &self.init_args.archive_config.compression;
+        Ok(blocks
+            .into_iter()
+            .map(|block| match compression {
+                Some(compression) => transform.seal(compression.compress(block)),
+                None => transform.seal(block),
+            })
+            .collect())
+    }
+
+    /// Opens a block read back from an archive canister, and decompresses it if it
+    /// was stored compressed.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block as stored in the archive canister
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(EncodedBlock)` containing the plaintext, uncompressed block
+    /// * `Err(String)` if the block could not be opened
+    pub fn open_block(&self, block: EncodedBlock) -> Result<EncodedBlock, String> {
+        block_compression::decompress(self.block_transform.transform()?.open(block)?)
+    }
+
     /// Gets the canister ID for a specific block ID.
     ///
     /// # Arguments
@@ -276,3 +857,206 @@ impl ArchiveCanisterManager {
         }
     }
 }
+
+#[cfg(all(test, feature = "host-test"))]
+mod tests {
+    use super::*;
+    use crate::blockchain::block_transform::EncryptionKey;
+    use bity_ic_icrc3_archive_api::types::block_compression::CompressionAlgo;
+    use bity_ic_subcanister_manager::{CanisterState, SimulatedOperation};
+    use ic_cdk::management_canister::CanisterInstallMode;
+    use serde_bytes::ByteBuf;
+
+    fn add_archive(
+        manager: &mut ArchiveCanisterManager,
+        canister_id: Principal,
+        block_offset: BlockIndex,
+    ) {
+        let mut init_args = manager.init_args.clone();
+        init_args.archive_config.block_offset = block_offset;
+        let canister = ArchiveCanister::new(
+            canister_id,
+            CanisterState::Installed,
+            bity_ic_icrc3_archive_api::Args::Init(init_args),
+        );
+        manager
+            .sub_canister_manager
+            .sub_canisters
+            .insert(canister_id, Box::new(canister));
+        manager
+            .canisters_by_block_offset
+            .push((block_offset, canister_id));
+    }
+
+    #[test]
+    fn test_retired_archive_is_not_an_insert_target() {
+        let mut manager = ArchiveCanisterManager::default();
+        let canister_id = Principal::from_slice(&[1]);
+        add_archive(&mut manager, canister_id, 0);
+        assert_eq!(manager.active_archive(), Some(canister_id));
+        assert_eq!(
+            manager.insert_plan(5, 100),
+            Ok(InsertPlan::Append(canister_id))
+        );
+
+        manager.retire_archive(canister_id).unwrap();
+        assert_eq!(manager.active_archive(), None);
+        assert_eq!(manager.insert_plan(5, 100), Ok(InsertPlan::CreateArchive));
+        assert!(manager.canister_histories()[0].retired);
+        assert!(manager.retire_archive(Principal::from_slice(&[2])).is_err());
+
+        manager.unretire_archive(canister_id).unwrap();
+        assert_eq!(
+            manager.insert_plan(5, 100),
+            Ok(InsertPlan::Append(canister_id))
+        );
+        assert!(!manager.canister_histories()[0].retired);
+    }
+
+    #[test]
+    fn test_external_archives_are_used_in_order() {
+        let mut manager = ArchiveCanisterManager::default();
+        let created = Principal::from_slice(&[1]);
+        let first = Principal::from_slice(&[2]);
+        let second = Principal::from_slice(&[3]);
+        add_archive(&mut manager, created, 0);
+
+        assert!(manager.set_external_archives(vec![first, first]).is_err());
+        assert!(manager.set_external_archives(vec![created]).is_err());
+        manager.set_external_archives(vec![first, second]).unwrap();
+        assert!(manager.is_external_archive(&first));
+        assert!(!manager.is_external_archive(&created));
+        assert_eq!(manager.get_subcanisters_installed().len(), 1);
+
+        // Registered as it is in insert_into_next_external_archive
+        manager.canisters_by_block_offset.push((10, first));
+        assert_eq!(manager.insert_plan(12, 100), Ok(InsertPlan::Append(first)));
+        assert_eq!(manager.get_subcanisters_installed().len(), 2);
+
+        assert!(manager.set_external_archives(vec![second, first]).is_err());
+        assert!(manager.set_external_archives(vec![second]).is_err());
+        manager.set_external_archives(vec![first]).unwrap();
+        assert_eq!(manager.external_archives.len(), 1);
+    }
+
+    #[test]
+    fn test_reinstall_requires_a_retired_unrecoverable_archive() {
+        let mut manager = ArchiveCanisterManager::default();
+        manager.sub_canister_manager.test_mode = true;
+        let mut init_args = manager.init_args.clone();
+        init_args.archive_config.block_offset = 5;
+        let canister_id = futures::executor::block_on(
+            manager
+                .sub_canister_manager
+                .create_canister(bity_ic_icrc3_archive_api::Args::Init(init_args)),
+        )
+        .unwrap()
+        .canister_id();
+        manager.canisters_by_block_offset.push((5, canister_id));
+        let confirmation = ReinstallConfirmation::confirm_data_loss(canister_id);
+        let reinstall = |manager: &mut ArchiveCanisterManager, confirmation| {
+            futures::executor::block_on(manager.reinstall_archive(confirmation))
+        };
+
+        assert!(reinstall(&mut manager, confirmation.clone()).is_err());
+        assert!(manager.mark_archive_unrecoverable(canister_id).is_err());
+        manager.retire_archive(canister_id).unwrap();
+        assert!(reinstall(&mut manager, confirmation.clone()).is_err());
+        manager.mark_archive_unrecoverable(canister_id).unwrap();
+        assert!(manager.unretire_archive(canister_id).is_err());
+        assert!(manager.sub_canister_manager.is_retired(&canister_id));
+        assert!(manager
+            .mark_archive_unrecoverable(Principal::from_slice(&[2]))
+            .is_err());
+        assert!(reinstall(
+            &mut manager,
+            ReinstallConfirmation::confirm_data_loss(Principal::from_slice(&[2]))
+        )
+        .is_err());
+        assert_eq!(manager.canister_histories()[0].history.reinstall_count, 0);
+
+        reinstall(&mut manager, confirmation).unwrap();
+        assert!(matches!(
+            manager
+                .sub_canister_manager
+                .simulated_operations_log()
+                .as_slice(),
+            [
+                ..,
+                SimulatedOperation::InstallCode {
+                    mode: CanisterInstallMode::Reinstall,
+                    ..
+                },
+                SimulatedOperation::StartCanister(_),
+            ]
+        ));
+        let history = &manager.canister_histories()[0];
+        assert_eq!(history.history.reinstall_count, 1);
+        assert!(history.retired && history.unrecoverable);
+        // The archive starts again empty at the first block of its range.
+        let canister = &manager.sub_canister_manager.sub_canisters[&canister_id];
+        assert_eq!(canister.state(), CanisterState::Installed);
+        assert_eq!(canister.archive_info.start, 5u64);
+    }
+
+    #[test]
+    fn test_full_archive_rolls_over_without_probing() {
+        let mut manager = ArchiveCanisterManager::default();
+        manager.init_args.archive_config.max_memory_size_bytes = 1_000;
+        let archive_a = Principal::from_slice(&[1]);
+        let archive_b = Principal::from_slice(&[2]);
+        add_archive(&mut manager, archive_a, 0);
+        assert_eq!(
+            manager.insert_plan(0, 600),
+            Ok(InsertPlan::Append(archive_a))
+        );
+
+        // The archive reported 400 bytes left after storing the first batch.
+        manager
+            .sub_canister_manager
+            .sub_canisters
+            .get_mut(&archive_a)
+            .unwrap()
+            .remaining_capacity = Some(400);
+        assert_eq!(
+            manager.insert_plan(10, 400),
+            Ok(InsertPlan::Append(archive_a))
+        );
+        assert_eq!(manager.insert_plan(10, 401), Ok(InsertPlan::CreateArchive));
+
+        add_archive(&mut manager, archive_b, 10);
+        assert_eq!(manager.active_archive(), Some(archive_b));
+        assert_eq!(
+            manager.insert_plan(10, 401),
+            Ok(InsertPlan::Append(archive_b))
+        );
+        // Only the active archive is appended to.
+        assert!(manager.insert_plan(5, 10).is_err());
+
+        assert!(manager.insert_plan(10, 1_001).is_err());
+    }
+
+    #[test]
+    fn test_compressed_blocks_are_sealed_and_opened() {
+        let mut manager = ArchiveCanisterManager::default();
+        manager.block_transform = BlockTransformConfig::XChaCha20Poly1305 {
+            key: EncryptionKey(ByteBuf::from(vec![7u8; 32])),
+        };
+        let blocks: Vec<EncodedBlock> = (0u8..4)
+            .map(|i| {
+                EncodedBlock::from_vec([[i; 48].as_slice(), &[0x82, 0x01, 0x61, 0x61]].concat())
+            })
+            .collect();
+
+        // Blocks archived before compression was enabled stay readable.
+        let mut stored = manager.seal_blocks(blocks[..2].to_vec()).unwrap();
+        manager.init_args.archive_config.compression = Some(CompressionAlgo::Deflate { level: 6 });
+        stored.extend(manager.seal_blocks(blocks[2..].to_vec()).unwrap());
+
+        let opened: Vec<EncodedBlock> = stored
+            .into_iter()
+            .map(|block| manager.open_block(block).unwrap())
+            .collect();
+        assert_eq!(opened, blocks);
+    }
+}
@@ -0,0 +1,220 @@
+//! Optional transformation of blocks stored in archive canisters.
+//!
+//! Blocks are sealed just before they are sent to an archive canister and opened
+//! when the main canister reads them back. Block hashes and certification are always
+//! computed over the plaintext block, before sealing. Archive canisters only ever
+//! see sealed bytes, so direct archive queries return them as opaque blobs.
+
+use bity_ic_icrc3_archive_api::types::encoded_blocks::EncodedBlock;
+use candid::CandidType;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use sha2::Sha256;
+use std::fmt;
+
+const XCHACHA20_KEY_LEN: usize = 32;
+const XCHACHA20_NONCE_LEN: usize = 24;
+/// Label of the key the nonces are derived with, distinct from the encryption key.
+const NONCE_KEY_LABEL: &[u8] = b"bity-ic-icrc3/block-transform/nonce";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A transformation applied to blocks stored in archive canisters.
+pub trait BlockTransform {
+    /// Seals a plaintext block before it is sent to an archive canister.
+    fn seal(&self, block: EncodedBlock) -> EncodedBlock;
+
+    /// Opens a block read back from an archive canister.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the block cannot be opened (e.g. it was tampered with).
+    fn open(&self, block: EncodedBlock) -> Result<EncodedBlock, String>;
+}
+
+/// Transform that stores blocks as is.
+pub struct NoopTransform;
+
+impl BlockTransform for NoopTransform {
+    fn seal(&self, block: EncodedBlock) -> EncodedBlock {
+        block
+    }
+
+    fn open(&self, block: EncodedBlock) -> Result<EncodedBlock, String> {
+        Ok(block)
+    }
+}
+
+/// Reference XChaCha20-Poly1305 transform.
+///
+/// The nonce is an HMAC-SHA256 of the plaintext block, keyed with a key derived from
+/// the encryption key, and is prepended to the ciphertext. As it is keyed, the stored
+/// nonce reveals nothing about the block to whoever does not hold the key. Blocks are
+/// unique as they embed the hash of their parent, so nonces are never reused for
+/// different plaintexts.
+pub struct XChaCha20Poly1305Transform {
+    cipher: XChaCha20Poly1305,
+    nonce_key: [u8; 32],
+}
+
+impl XChaCha20Poly1305Transform {
+    pub fn new(key: &EncryptionKey) -> Result<Self, String> {
+        key.validate()?;
+        let cipher = XChaCha20Poly1305::new_from_slice(key.0.as_slice())
+            .map_err(|_| "Invalid encryption key".to_string())?;
+        let nonce_key = hmac_sha256(key.0.as_slice(), NONCE_KEY_LABEL);
+        Ok(Self { cipher, nonce_key })
+    }
+
+    fn nonce(&self, block: &EncodedBlock) -> XNonce {
+        let mac = hmac_sha256(&self.nonce_key, block.as_slice());
+        *XNonce::from_slice(&mac[..XCHACHA20_NONCE_LEN])
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac =
+        <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+impl BlockTransform for XChaCha20Poly1305Transform {
+    fn seal(&self, block: EncodedBlock) -> EncodedBlock {
+        let nonce = &self.nonce(&block);
+        let ciphertext = self
+            .cipher
+            .encrypt(nonce, block.as_slice())
+            .expect("XChaCha20-Poly1305 encryption failed");
+
+        let mut sealed = Vec::with_capacity(XCHACHA20_NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(nonce);
+        sealed.extend_from_slice(&ciphertext);
+        EncodedBlock::from_vec(sealed)
+    }
+
+    fn open(&self, block: EncodedBlock) -> Result<EncodedBlock, String> {
+        let bytes = block.as_slice();
+        if bytes.len() < XCHACHA20_NONCE_LEN {
+            return Err("Sealed block is too short".to_string());
+        }
+        let (nonce, ciphertext) = bytes.split_at(XCHACHA20_NONCE_LEN);
+        self.cipher
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map(EncodedBlock::from_vec)
+            .map_err(|_| "Failed to open sealed block".to_string())
+    }
+}
+
+/// Key material for block encryption. Never printed by `Debug`.
+#[derive(CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct EncryptionKey(pub ByteBuf);
+
+impl EncryptionKey {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.0.len() != XCHACHA20_KEY_LEN {
+            return Err(format!(
+                "Encryption key must be {} bytes long",
+                XCHACHA20_KEY_LEN
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EncryptionKey(<redacted>)")
+    }
+}
+
+/// Configuration of the transform applied to archived blocks.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub enum BlockTransformConfig {
+    #[default]
+    None,
+    XChaCha20Poly1305 {
+        key: EncryptionKey,
+    },
+}
+
+impl BlockTransformConfig {
+    /// Validates the configuration.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            BlockTransformConfig::None => Ok(()),
+            BlockTransformConfig::XChaCha20Poly1305 { key } => key.validate(),
+        }
+    }
+
+    /// Builds the transform described by this configuration.
+    pub fn transform(&self) -> Result<Box<dyn BlockTransform>, String> {
+        match self {
+            BlockTransformConfig::None => Ok(Box::new(NoopTransform)),
+            BlockTransformConfig::XChaCha20Poly1305 { key } => {
+                Ok(Box::new(XChaCha20Poly1305Transform::new(key)?))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xchacha_config() -> BlockTransformConfig {
+        BlockTransformConfig::XChaCha20Poly1305 {
+            key: EncryptionKey(ByteBuf::from(vec![42u8; 32])),
+        }
+    }
+
+    #[test]
+    fn test_xchacha_seal_open_round_trip() {
+        let transform = xchacha_config().transform().unwrap();
+        let block = EncodedBlock::from_vec(b"plaintext block".to_vec());
+
+        let sealed = transform.seal(block.clone());
+        assert_ne!(sealed, block);
+        assert_eq!(transform.open(sealed.clone()).unwrap(), block);
+
+        let mut tampered = sealed.into_vec();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(transform.open(EncodedBlock::from_vec(tampered)).is_err());
+    }
+
+    #[test]
+    fn test_xchacha_nonce_requires_the_key() {
+        use sha2::Digest;
+
+        let block = EncodedBlock::from_vec(b"plaintext block".to_vec());
+        let sealed = xchacha_config().transform().unwrap().seal(block.clone());
+        let nonce = &sealed.as_slice()[..XCHACHA20_NONCE_LEN];
+
+        // Neither an unkeyed hash of the block nor another key gives the nonce.
+        assert_ne!(
+            nonce,
+            &Sha256::digest(block.as_slice())[..XCHACHA20_NONCE_LEN]
+        );
+        let other_key = BlockTransformConfig::XChaCha20Poly1305 {
+            key: EncryptionKey(ByteBuf::from(vec![7u8; 32])),
+        };
+        let resealed = other_key.transform().unwrap().seal(block);
+        assert_ne!(nonce, &resealed.as_slice()[..XCHACHA20_NONCE_LEN]);
+    }
+
+    #[test]
+    fn test_noop_transform_and_key_handling() {
+        let transform = BlockTransformConfig::None.transform().unwrap();
+        let block = EncodedBlock::from_vec(vec![1, 2, 3]);
+        assert_eq!(transform.seal(block.clone()), block);
+
+        let short_key = BlockTransformConfig::XChaCha20Poly1305 {
+            key: EncryptionKey(ByteBuf::from(vec![1u8; 16])),
+        };
+        assert!(short_key.validate().is_err());
+        assert!(!format!("{:?}", xchacha_config()).contains("42"));
+    }
+}
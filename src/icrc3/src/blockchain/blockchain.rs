@@ -1,5 +1,7 @@
+use crate::blockchain::archive_batch::{ArchiveBatchSize, BatchSizeLimits, BatchUsage};
 use crate::blockchain::archive_canister_manager::ArchiveCanisterManager;
 use crate::memory::{get_block_log_data_memory, VM};
+use crate::runtime;
 use crate::utils::trace;
 
 use bity_ic_icrc3_archive_api::types::{
@@ -16,7 +18,13 @@ use std::time::Duration;
 /// The default maximum size of local stable memory for transactions before archiving.
 const DEFAULT_MAX_TX_LOCAL_STABLE_MEMORY_SIZE_BYTES: u128 = 100 * 1024 * 1024 * 1024; // 100GB
 const TRESHOLD_FOR_ARCHIVING: usize = 100_000;
-const BATCH_SIZE_FOR_ARCHIVING: usize = 25;
+/// The number of blocks of the first archive batches, before the batch size is
+/// adapted to their cost, see [`crate::blockchain::archive_batch`].
+pub(crate) const BATCH_SIZE_FOR_ARCHIVING: usize = 25;
+/// The maximum size of the blocks of an archive batch. Staying below the warning
+/// threshold leaves room for the encoding of the `insert_blocks` arguments.
+pub(crate) const BATCH_MAX_BYTES_FOR_ARCHIVING: usize =
+    bity_ic_canister_client::C2C_PAYLOAD_WARNING_BYTES;
 
 fn init_archive_map() -> StableBTreeMap<BlockIndex, EncodedBlock, VM> {
     let memory = get_block_log_data_memory();
@@ -75,6 +83,59 @@ impl Blockchain {
     }
 }
 
+/// Collects the next batch of blocks to archive, from `start` up to `end` excluded.
+///
+/// A batch holds at most `max_blocks` blocks and, unless it has a
+/// single block, at most `BATCH_MAX_BYTES_FOR_ARCHIVING` bytes of blocks, so the
+/// `insert_blocks` call stays below the inter-canister payload limit.
+///
+/// # Returns
+///
+/// * `Ok(Vec<EncodedBlock>)` with at least one block if `start < end`
+/// * `Err(String)` if a block of the range is missing locally
+fn collect_archive_batch(
+    local_archive: &StableBTreeMap<BlockIndex, EncodedBlock, VM>,
+    start: usize,
+    end: usize,
+    max_blocks: usize,
+) -> Result<Vec<EncodedBlock>, String> {
+    let mut batch_blocks = Vec::new();
+    let mut batch_bytes = 0usize;
+
+    for local_index in start..end.min(start + max_blocks.max(1)) {
+        let Some(block) = local_archive.get(&(local_index as u64)) else {
+            return Err(format!("Block at local_index {} not found", local_index));
+        };
+        if !batch_blocks.is_empty()
+            && batch_bytes + block.size_bytes() > BATCH_MAX_BYTES_FOR_ARCHIVING
+        {
+            break;
+        }
+        batch_bytes += block.size_bytes();
+        batch_blocks.push(block);
+    }
+
+    Ok(batch_blocks)
+}
+
+/// Returns the local copy of a block.
+///
+/// Blocks are removed from the local archive only once they are stored in an
+/// archive canister, so while an archive run is in progress a block can be both
+/// archived and still present locally. The local copy is served in that case,
+/// whatever `archived_chain_length` says.
+fn get_local_block(
+    local_archive: &StableBTreeMap<BlockIndex, EncodedBlock, VM>,
+    block_id: BlockIndex,
+) -> Option<EncodedBlock> {
+    match local_archive.first_key_value() {
+        Some((lowest_local_index, _)) if block_id >= lowest_local_index => {
+            local_archive.get(&block_id)
+        }
+        _ => None,
+    }
+}
+
 impl Default for Blockchain {
     /// Creates a new Blockchain with default settings.
     fn default() -> Self {
@@ -170,7 +231,8 @@ impl Blockchain {
     ///
     /// # Returns
     ///
-    /// * `Ok(BlockIndex)` containing the new block's index
+    /// * `Ok(BlockIndex)` containing the id of the new block. Block ids are 0-based,
+    ///   the first block of the chain has id 0.
     /// * `Err(String)` if the block could not be added
     pub fn add_block<B>(&mut self, block: B) -> Result<BlockIndex, String>
     where
@@ -193,9 +255,9 @@ impl Blockchain {
         }
 
         let encoded_block: EncodedBlock = block_clone.clone().encode();
-        let max_tx_local_stable_memory_size_bytes = self
-            .max_tx_local_stable_memory_size_bytes
-            .unwrap_or(DEFAULT_MAX_TX_LOCAL_STABLE_MEMORY_SIZE_BYTES);
+        #[cfg(feature = "testing-hooks")]
+        let encoded_block = crate::testing_hooks::corrupt_next_block(encoded_block);
+        let max_tx_local_stable_memory_size_bytes = self.local_archive_limit();
 
         if (self.local_archive_size as u128) + (encoded_block.size_bytes() as u128)
             > max_tx_local_stable_memory_size_bytes
@@ -209,17 +271,51 @@ impl Blockchain {
         self.last_timestamp = block_clone.timestamp();
         self.last_hash = Some(B::block_hash(&encoded_block));
 
-        self.local_archive.insert(
-            self.archived_chain_length as u64 + self.local_archive.len() as u64,
-            encoded_block.clone(),
-        );
+        let block_index = self.chain_length();
+        self.local_archive
+            .insert(block_index, encoded_block.clone());
 
         self.local_archive_size += encoded_block.size_bytes();
 
-        Ok(self.archived_chain_length as u64 + self.local_archive.len() as u64)
+        Ok(block_index)
+    }
+
+    /// Returns the number of blocks in the chain, archived and local.
+    pub fn chain_length(&self) -> u64 {
+        self.archived_chain_length as u64 + self.local_archive.len()
     }
 
-    pub async fn archive_blocks_jobs(&mut self) -> Result<u128, String> {
+    /// Returns the maximum number of bytes of blocks stored in the local archive.
+    pub fn local_archive_limit(&self) -> u128 {
+        self.max_tx_local_stable_memory_size_bytes
+            .unwrap_or(DEFAULT_MAX_TX_LOCAL_STABLE_MEMORY_SIZE_BYTES)
+    }
+
+    /// Returns the number of bytes of blocks the local archive can still store.
+    pub fn remaining_local_capacity(&self) -> u128 {
+        self.local_archive_limit()
+            .saturating_sub(self.local_archive_size as u128)
+    }
+
+    /// Moves half of the local blocks to the archive canisters.
+    ///
+    /// Nothing is archived while the local archive holds fewer blocks than
+    /// `threshold_for_archiving_to_external_archive`, unless `force` is set to
+    /// free local capacity.
+    ///
+    /// The number of blocks per batch is `adaptive_batch_size`, adjusted after each batch
+    /// to its size and instruction cost within `limits`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u128)` with the number of archived blocks
+    /// * `Err(String)` if a batch could not be archived, the previous batches stay archived
+    pub async fn archive_blocks_jobs(
+        &mut self,
+        force: bool,
+        adaptive_batch_size: &mut ArchiveBatchSize,
+        limits: BatchSizeLimits,
+    ) -> Result<u128, String> {
         trace("archive_blocks_jobs");
 
         trace(format!(
@@ -231,7 +327,7 @@ impl Blockchain {
             .threshold_for_archiving_to_external_archive
             .unwrap_or(TRESHOLD_FOR_ARCHIVING);
 
-        if self.local_archive.len() < threshold_for_archiving_to_external_archive as u64 {
+        if !force && self.local_archive.len() < threshold_for_archiving_to_external_archive as u64 {
             // no need to archive blocks on external canister
             return Ok(0);
         }
@@ -241,9 +337,13 @@ impl Blockchain {
             return Ok(0);
         }
 
-        // Calculate half of the blocks to archive
+        // Calculate half of the blocks to archive, at least one when forced
         let total_blocks = self.local_archive.len() as usize;
-        let num_to_archive = total_blocks / 2;
+        let num_to_archive = if force {
+            total_blocks.div_ceil(2)
+        } else {
+            total_blocks / 2
+        };
 
         if num_to_archive == 0 {
             return Ok(0);
@@ -255,17 +355,25 @@ impl Blockchain {
         ));
 
         let mut archived_count = 0u128;
-        let mut total_size_decreased = 0usize;
         let batch_start_block_id: usize = self.archived_chain_length;
 
         // Archive blocks in batches
-        for batch_start in (batch_start_block_id..num_to_archive + batch_start_block_id)
-            .step_by(BATCH_SIZE_FOR_ARCHIVING)
-        {
-            let batch_end =
-                (batch_start + BATCH_SIZE_FOR_ARCHIVING).min(num_to_archive + batch_start_block_id);
-            let batch_size = batch_end - batch_start;
-            let first_block_id = self.archived_chain_length as u64 + batch_start as u64;
+        let archive_end = num_to_archive + batch_start_block_id;
+        let mut batch_end = batch_start_block_id;
+        while batch_end < archive_end {
+            let batch_start = batch_end;
+            let instructions_before = runtime::instruction_counter();
+            let batch_blocks = collect_archive_batch(
+                &self.local_archive,
+                batch_start,
+                archive_end,
+                adaptive_batch_size.current(limits) as usize,
+            )
+            .inspect_err(|e| trace(format!("archive_blocks_jobs: {}", e)))?;
+            let batch_size = batch_blocks.len();
+            batch_end = batch_start + batch_size;
+            let first_block_id = batch_start as u64;
+            let batch_size_bytes = batch_blocks.iter().map(|b| b.size_bytes()).sum::<usize>();
 
             trace(format!(
                 "archive_blocks_jobs: Processing batch from {} to {} (block_id: {} to {})",
@@ -275,21 +383,6 @@ impl Blockchain {
                 first_block_id + batch_size as u64 - 1
             ));
 
-            // Collect all blocks in the batch
-            let mut batch_blocks = Vec::with_capacity(batch_size);
-            for local_index in batch_start..batch_end {
-                if let Some(block) = self.local_archive.get(&(local_index as u64)) {
-                    total_size_decreased += block.size_bytes();
-                    batch_blocks.push(block);
-                } else {
-                    trace(format!(
-                        "archive_blocks_jobs: Block at local_index {} not found",
-                        local_index
-                    ));
-                    return Err(format!("Block at local_index {} not found", local_index));
-                }
-            }
-
             // we still have transaction in local_archive, and might have it duplicated in archive canister,
             // but it's fine as when we get the blocks we first check the local_archive and then the archive canister.
 
@@ -316,12 +409,27 @@ impl Blockchain {
                                     trace(format!("archive_info: {:?}", canister.archive_info));
                                 });
 
-                            // remove the batch from local_archive
+                            // remove the batch from local_archive, counting it as archived
+                            // right away so that a later failed batch keeps the chain length
                             for index in batch_start..batch_end {
                                 self.local_archive.remove(&(index as u64));
                             }
+                            self.archived_chain_length += batch_size;
+                            self.local_archive_size =
+                                self.local_archive_size.saturating_sub(batch_size_bytes);
+
+                            adaptive_batch_size.record_success(
+                                limits,
+                                BatchUsage {
+                                    blocks: batch_size as u64,
+                                    bytes: batch_size_bytes as u64,
+                                    instructions: runtime::instruction_counter()
+                                        .saturating_sub(instructions_before),
+                                },
+                            );
                         }
                         Err(e) => {
+                            adaptive_batch_size.record_failure(limits);
                             trace(format!(
                                 "archive_blocks_jobs: Failed to archive batch (block_id: {} to {}): {}",
                                 first_block_id, first_block_id + batch_size as u64 - 1, e
@@ -344,10 +452,6 @@ impl Blockchain {
             }
         }
 
-        // Update the blockchain state after successful archiving
-        self.archived_chain_length += num_to_archive;
-        self.local_archive_size = self.local_archive_size.saturating_sub(total_size_decreased);
-
         trace(format!(
             "archive_blocks_jobs: Successfully archived {} blocks. Updated archived_chain_length: {}, local_archive_size: {}",
             archived_count, self.archived_chain_length, self.local_archive_size
@@ -364,19 +468,34 @@ impl Blockchain {
     ///
     /// # Returns
     ///
-    /// * `Some(EncodedBlock)` if the block exists
-    /// * `None` if the block doesn't exist
+    /// * `Some(EncodedBlock)` if the block is stored locally
+    /// * `None` if the block doesn't exist or is only stored in an archive canister
     pub fn get_block(&self, block_id: BlockIndex) -> Option<EncodedBlock> {
-        if block_id as usize > self.archived_chain_length + self.local_archive.len() as usize {
-            return None;
-        }
+        get_local_block(&self.local_archive, block_id)
+    }
 
-        if block_id as usize >= self.archived_chain_length {
-            self.local_archive.get(&(block_id as u64))
-        } else {
-            // This is an archived block, we don't have it locally
-            None
+    /// Checks whether a block is stored locally or registered in an archive canister.
+    ///
+    /// Only the local archive keys and the archive registry are looked up, the
+    /// block itself is not read. The archive registry has no upper bound, so the
+    /// caller must check `block_id` against the chain length.
+    ///
+    /// # Arguments
+    ///
+    /// * `block_id` - The index of the block
+    pub fn has_block(&self, block_id: BlockIndex) -> bool {
+        if self.local_archive.contains_key(&block_id) {
+            return true;
         }
+
+        self.archive_canister_manager
+            .read()
+            .map(|archive_manager| {
+                archive_manager
+                    .get_canister_id_by_block_id(block_id)
+                    .is_ok()
+            })
+            .unwrap_or(false)
     }
 
     /// Gets the canister ID that stores a specific block.
@@ -408,3 +527,151 @@ impl Blockchain {
             .get_canister_id_by_block_id(block_id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_stable_structures::memory_manager::{MemoryId, MemoryManager};
+    use ic_stable_structures::DefaultMemoryImpl;
+
+    #[test]
+    fn test_local_blocks_are_served_during_archiving() {
+        let memory_manager = MemoryManager::init(DefaultMemoryImpl::default());
+        let mut local_archive: StableBTreeMap<BlockIndex, EncodedBlock, VM> =
+            StableBTreeMap::init(memory_manager.get(MemoryId::new(0)));
+        for block_id in 0..6 {
+            local_archive.insert(block_id, EncodedBlock::from_vec(vec![block_id as u8]));
+        }
+
+        // Blocks 0..3 were inserted in an archive canister but are still present
+        // locally: they are read from the local archive.
+        for block_id in 0..6 {
+            assert_eq!(
+                get_local_block(&local_archive, block_id),
+                Some(EncodedBlock::from_vec(vec![block_id as u8]))
+            );
+        }
+
+        // Once removed locally, reads of archived blocks fall back to the archives.
+        for block_id in 0..3 {
+            local_archive.remove(&block_id);
+        }
+        for block_id in 0..3 {
+            assert_eq!(get_local_block(&local_archive, block_id), None);
+        }
+        for block_id in 3..6 {
+            assert!(get_local_block(&local_archive, block_id).is_some());
+        }
+        assert_eq!(get_local_block(&local_archive, 6), None);
+    }
+
+    #[test]
+    fn test_archive_batches_stay_below_the_payload_limit() {
+        let memory_manager = MemoryManager::init(DefaultMemoryImpl::default());
+        let mut local_archive: StableBTreeMap<BlockIndex, EncodedBlock, VM> =
+            StableBTreeMap::init(memory_manager.get(MemoryId::new(0)));
+        // Small blocks, blocks filling a batch in a few entries and a single block
+        // close to the limit.
+        let sizes: Vec<usize> = (0..40)
+            .map(|i| match i % 10 {
+                0..=5 => 100,
+                6..=8 => 700_000,
+                _ => 1_900_000,
+            })
+            .collect();
+        for (block_id, size) in sizes.iter().enumerate() {
+            local_archive.insert(block_id as u64, EncodedBlock::from_vec(vec![0; *size]));
+        }
+
+        let mut start = 0;
+        while start < sizes.len() {
+            let blocks =
+                collect_archive_batch(&local_archive, start, sizes.len(), BATCH_SIZE_FOR_ARCHIVING)
+                    .unwrap();
+            assert!(!blocks.is_empty());
+            assert!(blocks.len() <= BATCH_SIZE_FOR_ARCHIVING);
+            let batch_size = blocks.len();
+
+            let payload = candid::encode_one(bity_ic_icrc3_archive_api::insert_blocks::Args {
+                first_block_id: start as u64,
+                blocks,
+            })
+            .unwrap();
+            assert!(
+                payload.len() <= bity_ic_canister_client::MAX_C2C_PAYLOAD_BYTES,
+                "batch at {start} has a payload of {} bytes",
+                payload.len()
+            );
+            start += batch_size;
+        }
+
+        local_archive.remove(&3);
+        assert_eq!(
+            collect_archive_batch(&local_archive, 0, sizes.len(), BATCH_SIZE_FOR_ARCHIVING),
+            Err("Block at local_index 3 not found".to_string())
+        );
+    }
+
+    #[cfg(feature = "host-test")]
+    #[test]
+    fn test_batch_size_converges_to_the_block_sizes() {
+        let memory_manager = MemoryManager::init(DefaultMemoryImpl::default());
+        let mut local_archive: StableBTreeMap<BlockIndex, EncodedBlock, VM> =
+            StableBTreeMap::init(memory_manager.get(MemoryId::new(0)));
+        let small_blocks = 3_000;
+        let large_blocks = 100;
+        for block_id in 0..small_blocks + large_blocks {
+            let size = if block_id < small_blocks {
+                100
+            } else {
+                300_000
+            };
+            local_archive.insert(block_id as u64, EncodedBlock::from_vec(vec![0; size]));
+        }
+
+        // Archives the blocks as the archive job does, each block costing 100k
+        // instructions, and returns the batch sizes used.
+        let limits = crate::config::ICRC3Properties::default().archive_batch_limits();
+        let mut adaptive_batch_size = ArchiveBatchSize::default();
+        let mut archive = |start: usize, end: usize| {
+            let mut sizes = vec![];
+            let mut batch_start = start;
+            while batch_start < end {
+                let instructions_before = runtime::instruction_counter();
+                let blocks = collect_archive_batch(
+                    &local_archive,
+                    batch_start,
+                    end,
+                    adaptive_batch_size.current(limits) as usize,
+                )
+                .unwrap();
+                runtime::host::advance_instruction_counter(100_000 * blocks.len() as u64);
+                let bytes = blocks.iter().map(|b| b.size_bytes()).sum::<usize>();
+                assert!(bytes <= BATCH_MAX_BYTES_FOR_ARCHIVING);
+
+                adaptive_batch_size.record_success(
+                    limits,
+                    BatchUsage {
+                        blocks: blocks.len() as u64,
+                        bytes: bytes as u64,
+                        instructions: runtime::instruction_counter() - instructions_before,
+                    },
+                );
+                sizes.push(adaptive_batch_size.current(limits));
+                batch_start += blocks.len();
+            }
+            sizes
+        };
+
+        // Small blocks: the batch size doubles up to the maximum.
+        let sizes = archive(0, small_blocks);
+        assert_eq!(sizes[..4], [50, 100, 200, 400]);
+        assert_eq!(*sizes.last().unwrap(), limits.max_blocks);
+
+        // Large blocks: the batch size halves until batches use less than 70% of
+        // the payload budget, 4 blocks of 300 KB.
+        let sizes = archive(small_blocks, small_blocks + large_blocks);
+        assert!(sizes.windows(2).all(|w| w[1] <= w[0]));
+        assert_eq!(*sizes.last().unwrap(), 3);
+    }
+}
@@ -5,10 +5,14 @@
 //!
 //! # Components
 //!
+//! * `archive_batch` - Adaptive number of blocks per archive batch
 //! * `archive_canister` - Manages individual archive canisters
 //! * `archive_canister_manager` - Manages multiple archive canisters
+//! * `block_transform` - Optional sealing of blocks stored in archive canisters
 //! * `blockchain` - Core blockchain implementation
 
+pub mod archive_batch;
 pub mod archive_canister;
 pub mod archive_canister_manager;
+pub mod block_transform;
 pub mod blockchain;
@@ -1,6 +1,14 @@
-use candid::CandidType;
+use crate::blockchain::archive_batch::BatchSizeLimits;
+use crate::blockchain::block_transform::BlockTransformConfig;
+use crate::costs::ArchiveCostEstimate;
+use crate::timestamp_ordering::TimestampOrdering;
+use crate::validation::ValidationMode;
+
+use bity_ic_icrc3_archive_api::types::block_compression::CompressionAlgo;
+use candid::{CandidType, Principal};
 use icrc_ledger_types::icrc3::blocks::SupportedBlockType;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::time::Duration;
 
 /// Configuration for the ICRC3 implementation.
@@ -12,11 +20,21 @@ use std::time::Duration;
 ///
 /// ```rust
 /// use icrc3_library::config::{ICRC3Config, ICRC3Properties};
+/// use std::collections::BTreeMap;
 /// use std::time::Duration;
 ///
 /// let config = ICRC3Config {
 ///     supported_blocks: vec![],
 ///     constants: ICRC3Properties::default(),
+///     funding_config: None,
+///     block_transform: None,
+///     commit_hash: None,
+///     archive_test_mode: false,
+///     custom_block_types: vec![],
+///     external_archives: vec![],
+///     genesis_parent_hash: None,
+///     genesis_parent_ref: None,
+///     validation_modes: BTreeMap::new(),
 /// };
 /// ```
 #[derive(CandidType, Serialize, Deserialize, Debug, Default)]
@@ -25,6 +43,65 @@ pub struct ICRC3Config {
     pub supported_blocks: Vec<SupportedBlockType>,
     /// System constants and limits
     pub constants: ICRC3Properties,
+    /// Cycle top-up configuration for the archive canisters.
+    /// If None, the archive manager defaults are used with the cycles from `constants`.
+    pub funding_config: Option<FundingConfig>,
+    /// Transform applied to blocks stored in archive canisters (e.g. encryption at rest).
+    /// If None, blocks are stored as is.
+    pub block_transform: Option<BlockTransformConfig>,
+    /// Commit hash of the canister, embedded in the blocks when
+    /// `constants.embed_version_metadata` is set.
+    #[serde(default)]
+    pub commit_hash: Option<String>,
+    /// Whether new archive canisters are installed in test mode, which enables their
+    /// test-only endpoints. Only meant for tests.
+    #[serde(default)]
+    pub archive_test_mode: bool,
+    /// Block types of `supported_blocks` numbered like a known standard but not
+    /// defined by it, e.g. `7gldt_swap`. Other such block types are rejected, as
+    /// they are most likely typos.
+    #[serde(default)]
+    pub custom_block_types: Vec<String>,
+    /// Archive canisters installed and funded by someone else, e.g. governance,
+    /// used in order instead of creating archive canisters. The first one must be
+    /// installed with the archive wasm starting at the first block to archive,
+    /// with this canister authorized.
+    #[serde(default)]
+    pub external_archives: Vec<Principal>,
+    /// Hash of the tip of a parent chain this chain continues, e.g. the ledger of a
+    /// migrated collection. If set, block 0 has it as parent hash and a `genesis`
+    /// block recording it is appended at init.
+    #[serde(default)]
+    pub genesis_parent_hash: Option<[u8; 32]>,
+    /// Reference of the parent chain, e.g. `<canister id>:<block index>`, recorded in
+    /// the `genesis` block. Requires `genesis_parent_hash`.
+    #[serde(default)]
+    pub genesis_parent_ref: Option<String>,
+    /// How strictly the transactions of each block type are validated, see
+    /// [`crate::validation`]. Block types not listed are validated strictly.
+    #[serde(default)]
+    pub validation_modes: BTreeMap<String, ValidationMode>,
+}
+
+impl ICRC3Config {
+    /// Returns the validation mode of a block type.
+    pub fn validation_mode(&self, btype: &str) -> ValidationMode {
+        self.validation_modes
+            .get(btype)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the funding configuration that applies to the archive canisters.
+    pub fn funding_config(&self) -> FundingConfig {
+        self.funding_config
+            .clone()
+            .unwrap_or_else(|| FundingConfig {
+                initial_cycles: self.constants.initial_cycles,
+                reserved_cycles: self.constants.reserved_cycles,
+                ..FundingConfig::default()
+            })
+    }
 }
 
 impl Clone for ICRC3Config {
@@ -39,6 +116,15 @@ impl Clone for ICRC3Config {
                 })
                 .collect(),
             constants: self.constants.clone(),
+            funding_config: self.funding_config.clone(),
+            block_transform: self.block_transform.clone(),
+            commit_hash: self.commit_hash.clone(),
+            archive_test_mode: self.archive_test_mode,
+            custom_block_types: self.custom_block_types.clone(),
+            external_archives: self.external_archives.clone(),
+            genesis_parent_hash: self.genesis_parent_hash,
+            genesis_parent_ref: self.genesis_parent_ref.clone(),
+            validation_modes: self.validation_modes.clone(),
         }
     }
 }
@@ -54,7 +140,8 @@ pub struct ICRC3Properties {
     pub tx_window: Duration,
     /// Maximum number of transactions allowed in the window
     pub max_transactions_in_window: u128,
-    /// Maximum memory size in bytes
+    /// Maximum number of bytes of blocks stored by each archive canister, the next
+    /// blocks go to a new archive canister. Applies to archives created afterwards
     pub max_memory_size_bytes: u128,
     /// Maximum number of blocks per response
     pub max_blocks_per_response: u128,
@@ -69,9 +156,158 @@ pub struct ICRC3Properties {
     pub max_tx_local_stable_memory_size_bytes: Option<u128>,
     /// Threshold for archiving blocks to the external archive canister
     pub threshold_for_archiving_to_external_archive: Option<usize>,
+    /// Maximum size of an incoming transaction in bytes
+    #[serde(default = "default_max_transaction_size_bytes")]
+    pub max_transaction_size_bytes: u128,
+    /// Maximum size of the memo of an incoming transaction in bytes.
+    /// If None, memos are only bounded by `max_transaction_size_bytes`.
+    #[serde(default)]
+    pub max_memo_size_bytes: Option<u128>,
+    /// Maximum size in bytes of the transactions kept for deduplication.
+    /// When exceeded, the oldest transactions that are already outside `tx_window`
+    /// are purged early. If None, the window is only bounded by `tx_window`.
+    #[serde(default)]
+    pub max_dedup_window_bytes: Option<u128>,
+    /// Whether transactions still inside `tx_window` may be purged to honour
+    /// `max_dedup_window_bytes`. This bounds the memory used by the window at the
+    /// cost of the deduplication guarantee: a duplicate of a purged transaction is
+    /// accepted. Each such purge is counted in the dedup window metrics.
+    #[serde(default)]
+    pub allow_early_purge: bool,
+    /// Maximum number of committed transactions kept for deduplication. When set,
+    /// transactions are kept until this count is exceeded rather than purged once
+    /// outside `tx_window`, so duplicate protection outlives the throttling window.
+    /// Must not be below `max_transactions_in_window`. Transactions still inside
+    /// `tx_window` are only purged for it when `allow_early_purge` is set.
+    #[serde(default)]
+    pub max_dedup_entries: Option<u128>,
+    /// Principals allowed to record transactions, in addition to the controllers
+    /// and the canister itself. If None, any caller may record transactions.
+    #[serde(default)]
+    pub recorders: Option<Vec<Principal>>,
+    /// Whether blocks embed the principal that recorded them in a `rec` field.
+    #[serde(default)]
+    pub record_recorder: bool,
+    /// Whether blocks embed the versions that produced them in a `ver` field: a
+    /// map with the `bity-ic-icrc3` version as `lib` and, if configured, the
+    /// commit hash of the canister as `commit`.
+    ///
+    /// Like `record_recorder`, this adds a field to the blocks and so changes their
+    /// hashes. It is off by default and should only be enabled on a new chain or
+    /// knowingly, as the blocks recorded before keep their format.
+    #[serde(default)]
+    pub embed_version_metadata: bool,
+    /// Whether the memos of the local blocks are indexed for `find_blocks_by_memo`.
+    /// Blocks stored in archive canisters are not indexed.
+    #[serde(default)]
+    pub index_memos: bool,
+    /// Whether archive canisters index their blocks by transaction hash for
+    /// `find_block_by_thash`. Only archives created after it is set index their
+    /// blocks. It is rejected along with a block transform, as the archives can't
+    /// open sealed blocks to hash them.
+    #[serde(default)]
+    pub index_thashes: bool,
+    /// Compression of the blocks sent to the archive canisters. Hashes and
+    /// certification are computed over the uncompressed blocks, and blocks
+    /// archived before a change keep their format.
+    #[serde(default)]
+    pub compression: Option<CompressionAlgo>,
+    /// Whether the administrative actions made through the endpoints of
+    /// `icrc3_state!` are recorded as `admin` blocks, see [`crate::audit`]. The
+    /// `admin` block type is then supported.
+    #[serde(default)]
+    pub audit_admin_actions: bool,
+    /// How long a prepared transaction can be committed. Expired prepared
+    /// transactions are removed by the cleanup job.
+    #[serde(default = "default_prepared_transaction_ttl")]
+    pub prepared_transaction_ttl: Duration,
+    /// Share of the local archive, in percent, under which the capacity left
+    /// triggers an archive run right away instead of at the next archive job tick.
+    #[serde(default = "default_local_archive_low_water_mark_percent")]
+    pub local_archive_low_water_mark_percent: u8,
+    /// Whether ICRC-1 and ICRC-2 blocks are also served in the legacy
+    /// `get_transactions` format of the ICRC-1 index canisters, see [`crate::legacy`].
+    #[serde(default)]
+    pub legacy_transactions: bool,
+    /// Number of pending prepared transactions above which a warning is traced,
+    /// when callers prepare transactions without committing them. If None, no
+    /// warning is traced.
+    #[serde(default)]
+    pub prepared_transactions_warning_threshold: Option<u64>,
+    /// Minimum number of blocks sent to an archive canister in a batch. The
+    /// archive job adapts the number of blocks per batch to their cost, see
+    /// [`crate::blockchain::archive_batch`].
+    #[serde(default = "default_archive_batch_min_blocks")]
+    pub archive_batch_min_blocks: u64,
+    /// Maximum number of blocks sent to an archive canister in a batch.
+    #[serde(default = "default_archive_batch_max_blocks")]
+    pub archive_batch_max_blocks: u64,
+    /// What happens to a new block whose timestamp is older than the tip, e.g.
+    /// when the clocks of several recorders differ, see [`crate::timestamp_ordering`].
+    #[serde(default)]
+    pub timestamp_ordering: TimestampOrdering,
+}
+
+fn default_max_transaction_size_bytes() -> u128 {
+    32 * 1024
+}
+
+fn default_prepared_transaction_ttl() -> Duration {
+    Duration::from_secs(24 * 60 * 60)
+}
+
+fn default_local_archive_low_water_mark_percent() -> u8 {
+    10
+}
+
+fn default_archive_batch_min_blocks() -> u64 {
+    1
+}
+
+fn default_archive_batch_max_blocks() -> u64 {
+    1_000
 }
 
 impl ICRC3Properties {
+    /// Validates the properties.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if `max_dedup_entries` is below
+    /// `max_transactions_in_window`: the deduplication window would then be
+    /// smaller than what throttling lets in. Also returns an error message if
+    /// `archive_batch_min_blocks` is zero or above `archive_batch_max_blocks`.
+    pub fn validate(&self) -> Result<(), String> {
+        if self
+            .max_dedup_entries
+            .is_some_and(|max| max < self.max_transactions_in_window)
+        {
+            return Err(
+                "max_dedup_entries must not be lower than max_transactions_in_window".to_string(),
+            );
+        }
+        if self.archive_batch_min_blocks == 0 {
+            return Err("archive_batch_min_blocks must be greater than 0".to_string());
+        }
+        if self.archive_batch_min_blocks > self.archive_batch_max_blocks {
+            return Err(
+                "archive_batch_min_blocks must not be greater than archive_batch_max_blocks"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the bounds of the number of blocks per archive batch.
+    pub fn archive_batch_limits(&self) -> BatchSizeLimits {
+        BatchSizeLimits {
+            min_blocks: self.archive_batch_min_blocks,
+            max_blocks: self.archive_batch_max_blocks,
+        }
+    }
+
+    /// The properties that are not passed take their default value and can be
+    /// set with struct update syntax, e.g. `ICRC3Properties { max_memo_size_bytes: Some(32), ..properties }`.
     pub fn new(
         tx_window: Duration,
         max_transactions_in_window: u128,
@@ -82,6 +318,23 @@ impl ICRC3Properties {
         max_transactions_to_purge: u128,
         max_tx_local_stable_memory_size_bytes: Option<u128>,
         threshold_for_archiving_to_external_archive: Option<usize>,
+        max_dedup_window_bytes: Option<u128>,
+        allow_early_purge: bool,
+        max_dedup_entries: Option<u128>,
+        recorders: Option<Vec<Principal>>,
+        record_recorder: bool,
+        embed_version_metadata: bool,
+        index_memos: bool,
+        index_thashes: bool,
+        compression: Option<CompressionAlgo>,
+        audit_admin_actions: bool,
+        prepared_transaction_ttl: Duration,
+        local_archive_low_water_mark_percent: u8,
+        legacy_transactions: bool,
+        prepared_transactions_warning_threshold: Option<u64>,
+        archive_batch_min_blocks: u64,
+        archive_batch_max_blocks: u64,
+        timestamp_ordering: TimestampOrdering,
     ) -> Self {
         Self {
             tx_window,
@@ -93,6 +346,25 @@ impl ICRC3Properties {
             max_transactions_to_purge,
             max_tx_local_stable_memory_size_bytes,
             threshold_for_archiving_to_external_archive,
+            max_transaction_size_bytes: default_max_transaction_size_bytes(),
+            max_memo_size_bytes: None,
+            max_dedup_window_bytes,
+            allow_early_purge,
+            max_dedup_entries,
+            recorders,
+            record_recorder,
+            embed_version_metadata,
+            index_memos,
+            index_thashes,
+            compression,
+            audit_admin_actions,
+            prepared_transaction_ttl,
+            local_archive_low_water_mark_percent,
+            legacy_transactions,
+            prepared_transactions_warning_threshold,
+            archive_batch_min_blocks,
+            archive_batch_max_blocks,
+            timestamp_ordering,
         }
     }
 }
@@ -109,6 +381,160 @@ impl Default for ICRC3Properties {
             max_transactions_to_purge: 0_u64.into(),
             max_tx_local_stable_memory_size_bytes: None,
             threshold_for_archiving_to_external_archive: None,
+            max_transaction_size_bytes: default_max_transaction_size_bytes(),
+            max_memo_size_bytes: None,
+            max_dedup_window_bytes: None,
+            allow_early_purge: false,
+            max_dedup_entries: None,
+            recorders: None,
+            record_recorder: false,
+            embed_version_metadata: false,
+            index_memos: false,
+            index_thashes: false,
+            compression: None,
+            audit_admin_actions: false,
+            prepared_transaction_ttl: default_prepared_transaction_ttl(),
+            local_archive_low_water_mark_percent: default_local_archive_low_water_mark_percent(),
+            legacy_transactions: false,
+            prepared_transactions_warning_threshold: None,
+            archive_batch_min_blocks: default_archive_batch_min_blocks(),
+            archive_batch_max_blocks: default_archive_batch_max_blocks(),
+            timestamp_ordering: TimestampOrdering::default(),
         }
     }
 }
+
+/// Cycle top-up configuration for the archive canisters.
+///
+/// Archive canisters are registered with a fund manager which tops them up
+/// with `fund_cycles` whenever their balance drops below `min_cycles`, or below
+/// `retired_min_cycles` for the retired ones when it is set.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FundingConfig {
+    /// Interval in seconds between two balance checks
+    pub interval_secs: u64,
+    /// Balance below which an archive canister is topped up
+    pub min_cycles: u128,
+    /// Number of cycles sent on each top-up
+    pub fund_cycles: u128,
+    /// Number of cycles a new archive canister is created with
+    pub initial_cycles: u128,
+    /// Reserved cycles limit of new archive canisters
+    pub reserved_cycles: u128,
+    /// Balance below which a retired archive canister is topped up, `min_cycles`
+    /// applies when unset
+    #[serde(default)]
+    pub retired_min_cycles: Option<u128>,
+}
+
+impl FundingConfig {
+    /// Validates the funding configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the interval or the top-up amounts are zero, or
+    /// if the threshold of the retired archive canisters is above `min_cycles`.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.interval_secs == 0 {
+            return Err("interval_secs must be greater than 0".to_string());
+        }
+        if self.min_cycles == 0 {
+            return Err("min_cycles must be greater than 0".to_string());
+        }
+        if self.fund_cycles == 0 {
+            return Err("fund_cycles must be greater than 0".to_string());
+        }
+        if self.initial_cycles == 0 {
+            return Err("initial_cycles must be greater than 0".to_string());
+        }
+        if self
+            .retired_min_cycles
+            .is_some_and(|min| min > self.min_cycles)
+        {
+            return Err("retired_min_cycles must not be greater than min_cycles".to_string());
+        }
+        Ok(())
+    }
+
+    /// Returns the funding recommended by a cost estimate, with the default interval
+    /// and reserved cycles.
+    pub fn from_estimate(estimate: &ArchiveCostEstimate) -> Self {
+        FundingConfig {
+            min_cycles: estimate.recommended_min_cycles,
+            fund_cycles: estimate.recommended_fund_cycles,
+            initial_cycles: estimate.recommended_initial_cycles,
+            ..FundingConfig::default()
+        }
+    }
+}
+
+impl Default for FundingConfig {
+    fn default() -> Self {
+        FundingConfig {
+            interval_secs: 60,
+            min_cycles: 1_000_000_000_000,
+            fund_cycles: 2_000_000_000_000,
+            initial_cycles: 5_000_000_000_000,
+            reserved_cycles: 5_000_000_000_000,
+            retired_min_cycles: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_funding_config_falls_back_to_constants() {
+        let mut config = ICRC3Config::default();
+        config.constants.initial_cycles = 3_000_000_000_000;
+
+        let funding = config.funding_config();
+        assert_eq!(funding.initial_cycles, 3_000_000_000_000);
+        assert_eq!(funding.min_cycles, FundingConfig::default().min_cycles);
+        assert!(funding.validate().is_ok());
+    }
+
+    #[test]
+    fn test_funding_config_validation() {
+        let funding = FundingConfig {
+            fund_cycles: 0,
+            ..FundingConfig::default()
+        };
+        assert!(funding.validate().is_err());
+
+        let funding = FundingConfig {
+            retired_min_cycles: Some(FundingConfig::default().min_cycles + 1),
+            ..FundingConfig::default()
+        };
+        assert!(funding.validate().is_err());
+    }
+
+    #[test]
+    fn test_dedup_entries_cover_the_throttling_window() {
+        let mut properties = ICRC3Properties::default();
+        assert!(properties.validate().is_ok());
+
+        properties.max_dedup_entries = Some(properties.max_transactions_in_window - 1);
+        assert!(properties.validate().is_err());
+
+        properties.max_dedup_entries = Some(properties.max_transactions_in_window);
+        assert!(properties.validate().is_ok());
+    }
+
+    #[test]
+    fn test_archive_batch_limits_are_ordered() {
+        let mut properties = ICRC3Properties {
+            archive_batch_min_blocks: 0,
+            ..ICRC3Properties::default()
+        };
+        assert!(properties.validate().is_err());
+
+        properties.archive_batch_min_blocks = properties.archive_batch_max_blocks + 1;
+        assert!(properties.validate().is_err());
+
+        properties.archive_batch_min_blocks = properties.archive_batch_max_blocks;
+        assert!(properties.validate().is_ok());
+    }
+}
@@ -0,0 +1,210 @@
+//! Approximate cycle costs of an archive canister, to size its funding.
+//!
+//! [`estimate_archive_costs`] is a pure function: it can be called off-chain when
+//! preparing a deployment, or exposed by a canister as a query. The IC prices it
+//! relies on are the constants of this module, for a 13-node application subnet as
+//! listed in the "Cycles costs" page of the IC documentation. Update them here when
+//! the pricing changes; nothing else hardcodes a price.
+//!
+//! The estimate assumes the chain has reached its steady state, with
+//! `retention_days` of blocks stored in the archive, and is meant to err on the
+//! side of overfunding by a small factor rather than by orders of magnitude.
+
+use crate::blockchain::blockchain::{BATCH_MAX_BYTES_FOR_ARCHIVING, BATCH_SIZE_FOR_ARCHIVING};
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// Cycles charged for storing one GiB for one second.
+pub const STORAGE_CYCLES_PER_GIB_SECOND: u128 = 127_000;
+/// Cycles charged for executing an update message, on top of its instructions.
+pub const UPDATE_MESSAGE_CYCLES: u128 = 5_000_000;
+/// Cycles charged for ten executed instructions.
+pub const CYCLES_PER_TEN_INSTRUCTIONS: u128 = 4;
+/// Cycles charged to the caller for an inter-canister call.
+pub const XNET_CALL_CYCLES: u128 = 260_000;
+/// Cycles charged to the caller for each byte of an inter-canister call.
+pub const XNET_BYTE_CYCLES: u128 = 1_000;
+/// Cycles charged for creating a canister, taken from its initial cycles.
+pub const CANISTER_CREATION_CYCLES: u128 = 500_000_000_000;
+/// Default freezing threshold of a canister, in days.
+pub const FREEZING_THRESHOLD_DAYS: u128 = 30;
+
+/// Instructions spent by the archive on an `insert_blocks` call, whatever its size.
+/// A rough figure, to be refined from the instruction counters of the archive.
+pub const INSERT_INSTRUCTIONS_PER_BATCH: u128 = 1_000_000;
+/// Instructions spent by the archive per byte of inserted blocks (decoding and
+/// stable memory writes). A rough figure, like `INSERT_INSTRUCTIONS_PER_BATCH`.
+pub const INSERT_INSTRUCTIONS_PER_BYTE: u128 = 500;
+/// Bytes stored by the archive for each block on top of the block itself: the key
+/// and the bookkeeping of the stable map.
+pub const STORED_BLOCK_OVERHEAD_BYTES: u128 = 32;
+
+/// Days of burn above the freezing threshold at which an archive is topped up.
+pub const TOP_UP_MARGIN_DAYS: u128 = 7;
+/// Days of burn covered by each top-up.
+pub const FUNDED_DAYS: u128 = 30;
+
+const SECONDS_PER_DAY: u128 = 86_400;
+const BYTES_PER_GIB: u128 = 1 << 30;
+
+/// The expected workload of the archive canisters.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveCostArgs {
+    /// Number of blocks added to the chain per day
+    pub blocks_per_day: u64,
+    /// Average size of a block as sent to the archive, after compression if any
+    pub avg_block_bytes: u32,
+    /// Number of days of blocks kept in the archive
+    pub retention_days: u32,
+}
+
+/// The approximate costs of an archive canister for a workload, see
+/// [`estimate_archive_costs`].
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveCostEstimate {
+    /// Bytes stored once `retention_days` of blocks are kept
+    pub stored_bytes: u128,
+    /// Storage cycles burnt per day once `retention_days` of blocks are kept
+    pub storage_cycles_per_day: u128,
+    /// Number of blocks in an insert batch
+    pub blocks_per_batch: u64,
+    /// Number of insert batches per day
+    pub batches_per_day: u128,
+    /// Cycles burnt by the archive to execute an insert batch
+    pub insert_cycles_per_batch: u128,
+    /// Cycles paid by the ledger canister to send an insert batch, not part of the
+    /// archive's burn
+    pub call_cycles_per_batch: u128,
+    /// Cycles burnt by the archive per day, storage and inserts
+    pub cycles_per_day: u128,
+    /// Recommended balance below which the archive is topped up
+    pub recommended_min_cycles: u128,
+    /// Recommended number of cycles sent on each top-up
+    pub recommended_fund_cycles: u128,
+    /// Recommended number of cycles a new archive is created with
+    pub recommended_initial_cycles: u128,
+}
+
+/// Estimates the cycles burnt by an archive canister for a workload, and the
+/// funding that keeps it clear of its freezing threshold.
+///
+/// The archive is topped up `TOP_UP_MARGIN_DAYS` of burn above its freezing
+/// threshold, each top-up covers `FUNDED_DAYS` of burn, and a new archive is
+/// created with the creation fee, the top-up threshold and one top-up. An empty
+/// workload gives zero recommendations, which [`crate::config::FundingConfig::validate`] rejects.
+pub fn estimate_archive_costs(args: &ArchiveCostArgs) -> ArchiveCostEstimate {
+    let blocks_per_day = args.blocks_per_day as u128;
+    let avg_block_bytes = args.avg_block_bytes as u128;
+
+    let stored_bytes = blocks_per_day
+        * args.retention_days as u128
+        * (avg_block_bytes + STORED_BLOCK_OVERHEAD_BYTES);
+    let storage_cycles_per_day =
+        stored_bytes * STORAGE_CYCLES_PER_GIB_SECOND * SECONDS_PER_DAY / BYTES_PER_GIB;
+
+    // The archive job fills batches up to the block count or the byte limit.
+    let blocks_per_batch = (BATCH_MAX_BYTES_FOR_ARCHIVING as u128)
+        .checked_div(avg_block_bytes)
+        .unwrap_or(u128::MAX)
+        .clamp(1, BATCH_SIZE_FOR_ARCHIVING as u128);
+    let batches_per_day = blocks_per_day.div_ceil(blocks_per_batch);
+    let batch_bytes = blocks_per_batch * avg_block_bytes;
+    let insert_cycles_per_batch = UPDATE_MESSAGE_CYCLES
+        + (INSERT_INSTRUCTIONS_PER_BATCH + INSERT_INSTRUCTIONS_PER_BYTE * batch_bytes)
+            * CYCLES_PER_TEN_INSTRUCTIONS
+            / 10;
+    let call_cycles_per_batch = XNET_CALL_CYCLES + XNET_BYTE_CYCLES * batch_bytes;
+
+    let cycles_per_day = storage_cycles_per_day + batches_per_day * insert_cycles_per_batch;
+    let recommended_min_cycles = cycles_per_day * (FREEZING_THRESHOLD_DAYS + TOP_UP_MARGIN_DAYS);
+    let recommended_fund_cycles = cycles_per_day * FUNDED_DAYS;
+
+    ArchiveCostEstimate {
+        stored_bytes,
+        storage_cycles_per_day,
+        blocks_per_batch: blocks_per_batch as u64,
+        batches_per_day,
+        insert_cycles_per_batch,
+        call_cycles_per_batch,
+        cycles_per_day,
+        recommended_min_cycles,
+        recommended_fund_cycles,
+        recommended_initial_cycles: CANISTER_CREATION_CYCLES
+            + recommended_min_cycles
+            + recommended_fund_cycles,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FundingConfig;
+
+    #[test]
+    fn test_estimate_small_blocks() {
+        let estimate = estimate_archive_costs(&ArchiveCostArgs {
+            blocks_per_day: 100_000,
+            avg_block_bytes: 224,
+            retention_days: 365,
+        });
+
+        // 100_000 * 365 * (224 + 32)
+        assert_eq!(estimate.stored_bytes, 9_344_000_000);
+        // 9_344_000_000 * 127_000 * 86_400 / 2^30
+        assert_eq!(estimate.storage_cycles_per_day, 95_488_357_543);
+        // 1_800_000 / 224 is above 25 blocks
+        assert_eq!(estimate.blocks_per_batch, 25);
+        assert_eq!(estimate.batches_per_day, 4_000);
+        // 5_000_000 + (1_000_000 + 500 * 5_600) * 4 / 10
+        assert_eq!(estimate.insert_cycles_per_batch, 6_520_000);
+        // 260_000 + 1_000 * 5_600
+        assert_eq!(estimate.call_cycles_per_batch, 5_860_000);
+        // 95_488_357_543 + 4_000 * 6_520_000
+        assert_eq!(estimate.cycles_per_day, 121_568_357_543);
+        assert_eq!(estimate.recommended_min_cycles, 121_568_357_543 * 37);
+        assert_eq!(estimate.recommended_fund_cycles, 121_568_357_543 * 30);
+        assert_eq!(estimate.recommended_initial_cycles, 8_645_079_955_381);
+    }
+
+    #[test]
+    fn test_estimate_large_blocks() {
+        let estimate = estimate_archive_costs(&ArchiveCostArgs {
+            blocks_per_day: 1_000,
+            avg_block_bytes: 100_000,
+            retention_days: 30,
+        });
+
+        // 1_000 * 30 * (100_000 + 32)
+        assert_eq!(estimate.stored_bytes, 3_000_960_000);
+        assert_eq!(estimate.storage_cycles_per_day, 30_667_459_487);
+        // Limited by the batch bytes: 1_800_000 / 100_000
+        assert_eq!(estimate.blocks_per_batch, 18);
+        // 1_000 / 18 rounded up
+        assert_eq!(estimate.batches_per_day, 56);
+        // 5_000_000 + (1_000_000 + 500 * 1_800_000) * 4 / 10
+        assert_eq!(estimate.insert_cycles_per_batch, 365_400_000);
+        assert_eq!(estimate.call_cycles_per_batch, 1_800_260_000);
+        // 30_667_459_487 + 56 * 365_400_000
+        assert_eq!(estimate.cycles_per_day, 51_129_859_487);
+        assert_eq!(estimate.recommended_initial_cycles, 3_925_700_585_629);
+
+        let funding = FundingConfig::from_estimate(&estimate);
+        assert_eq!(funding.min_cycles, 1_891_804_801_019);
+        assert_eq!(funding.fund_cycles, 1_533_895_784_610);
+        assert_eq!(funding.initial_cycles, 3_925_700_585_629);
+        assert!(funding.validate().is_ok());
+    }
+
+    #[test]
+    fn test_estimate_empty_workload_is_rejected() {
+        let estimate = estimate_archive_costs(&ArchiveCostArgs {
+            blocks_per_day: 0,
+            avg_block_bytes: 0,
+            retention_days: 0,
+        });
+        assert_eq!(estimate.cycles_per_day, 0);
+        assert_eq!(estimate.blocks_per_batch, 25);
+        assert!(FundingConfig::from_estimate(&estimate).validate().is_err());
+    }
+}
@@ -0,0 +1,304 @@
+//! Size accounting of the transaction deduplication window.
+//!
+//! Every transaction accepted during the last `tx_window` is kept in the ledger so
+//! that duplicates can be rejected. With large memos this window can grow large, so
+//! its size in bytes is tracked and can be capped with `max_dedup_window_bytes`.
+//! With `max_dedup_entries`, the window is instead bounded by its number of
+//! transactions, independently of the `tx_window` used for throttling.
+
+use crate::config::ICRC3Properties;
+use crate::utils::{get_transaction_size, trace};
+
+use bity_ic_types::TimestampNanos;
+use candid::CandidType;
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Size of the deduplication window and early purge events, for metrics.
+///
+/// `dedup_entries` counts every transaction kept for deduplication, while
+/// `throttle_window_entries` only counts those inside `tx_window`, which
+/// throttling is based on.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DedupWindowMetrics {
+    pub dedup_window_bytes: u128,
+    pub max_dedup_window_bytes: Option<u128>,
+    pub dedup_entries: u64,
+    pub max_dedup_entries: Option<u128>,
+    pub throttle_window_entries: u64,
+    pub max_transactions_in_window: u128,
+    pub early_purge_count: u64,
+    pub last_early_purge: Option<TimestampNanos>,
+    pub entries_limit_purge_count: u64,
+}
+
+/// Running totals of the deduplication window.
+///
+/// # Fields
+///
+/// * `bytes` - Sum of the transaction sizes of the entries in the window
+/// * `early_purge_count` - Number of entries purged while still inside `tx_window`
+/// * `last_early_purge` - When an entry was last purged early, in nanoseconds
+/// * `entries_limit_purge_count` - Number of entries purged to honour `max_dedup_entries`
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct DedupWindowStats {
+    pub bytes: u128,
+    pub early_purge_count: u64,
+    pub last_early_purge: Option<TimestampNanos>,
+    #[serde(default)]
+    pub entries_limit_purge_count: u64,
+}
+
+impl DedupWindowStats {
+    /// Returns the number of bytes an entry accounts for in the window.
+    pub fn entry_size(transaction: &ICRC3Value) -> u128 {
+        get_transaction_size(transaction).unwrap_or(0)
+    }
+
+    /// Accounts for an entry added to the window.
+    pub fn on_insert(&mut self, transaction: &ICRC3Value) {
+        self.bytes += Self::entry_size(transaction);
+    }
+
+    /// Accounts for an entry removed from the window.
+    pub fn on_remove(&mut self, transaction: &ICRC3Value) {
+        self.bytes = self.bytes.saturating_sub(Self::entry_size(transaction));
+    }
+
+    /// Recomputes the size of the window from its entries.
+    pub fn recompute(&mut self, ledger: &VecDeque<ICRC3Value>) {
+        self.bytes = ledger.iter().map(Self::entry_size).sum();
+    }
+
+    /// Purges the oldest entries until the window fits in `max_bytes`.
+    ///
+    /// Prepared entries are never purged, and entries still inside `tx_window` are
+    /// only purged when `allow_early_purge` is set. Purging such an entry is recorded
+    /// as an early purge, since a duplicate of it would no longer be detected.
+    ///
+    /// # Arguments
+    ///
+    /// * `ledger` - The entries of the window, oldest first
+    /// * `max_bytes` - The maximum size of the window in bytes
+    /// * `allow_early_purge` - Whether entries inside `tx_window` may be purged
+    /// * `now` - The current timestamp in nanoseconds
+    /// * `is_expired` - Whether an entry is outside `tx_window`
+    /// * `is_prepared` - Whether an entry belongs to a prepared transaction
+    ///
+    /// # Returns
+    ///
+    /// The number of entries that were purged
+    pub fn purge_to_limit(
+        &mut self,
+        ledger: &mut VecDeque<ICRC3Value>,
+        max_bytes: u128,
+        allow_early_purge: bool,
+        now: TimestampNanos,
+        is_expired: impl Fn(&ICRC3Value) -> bool,
+        is_prepared: impl Fn(&ICRC3Value) -> bool,
+    ) -> u128 {
+        self.purge_while(
+            ledger,
+            |stats, _| stats.bytes > max_bytes,
+            allow_early_purge,
+            now,
+            is_expired,
+            is_prepared,
+        )
+    }
+
+    /// Purges the oldest entries until the window holds at most `max_entries`.
+    ///
+    /// Entries are kept as for [`DedupWindowStats::purge_to_limit`], and each purged
+    /// entry is counted in `entries_limit_purge_count`.
+    ///
+    /// # Returns
+    ///
+    /// The number of entries that were purged
+    pub fn purge_to_entries(
+        &mut self,
+        ledger: &mut VecDeque<ICRC3Value>,
+        max_entries: u128,
+        allow_early_purge: bool,
+        now: TimestampNanos,
+        is_expired: impl Fn(&ICRC3Value) -> bool,
+        is_prepared: impl Fn(&ICRC3Value) -> bool,
+    ) -> u128 {
+        let num_purged = self.purge_while(
+            ledger,
+            |_, ledger| ledger.len() as u128 > max_entries,
+            allow_early_purge,
+            now,
+            is_expired,
+            is_prepared,
+        );
+        self.entries_limit_purge_count += num_purged as u64;
+        num_purged
+    }
+
+    /// Purges the oldest entries while `over_limit` holds, see `purge_to_limit`.
+    fn purge_while(
+        &mut self,
+        ledger: &mut VecDeque<ICRC3Value>,
+        over_limit: impl Fn(&Self, &VecDeque<ICRC3Value>) -> bool,
+        allow_early_purge: bool,
+        now: TimestampNanos,
+        is_expired: impl Fn(&ICRC3Value) -> bool,
+        is_prepared: impl Fn(&ICRC3Value) -> bool,
+    ) -> u128 {
+        let mut num_purged = 0;
+
+        while over_limit(self, ledger) {
+            let Some(front) = ledger.front() else {
+                break;
+            };
+
+            if is_prepared(front) {
+                break;
+            }
+
+            let early = !is_expired(front);
+            if early && !allow_early_purge {
+                break;
+            }
+
+            let transaction = ledger.pop_front().unwrap();
+            self.on_remove(&transaction);
+            num_purged += 1;
+
+            if early {
+                self.early_purge_count += 1;
+                self.last_early_purge = Some(now);
+                trace(
+                    "WARNING: dedup window above its limit, purged a transaction still inside tx_window",
+                );
+            }
+        }
+
+        num_purged
+    }
+
+    /// Returns the metrics of the window.
+    ///
+    /// # Arguments
+    ///
+    /// * `properties` - The limits of the window
+    /// * `dedup_entries` - The number of entries in the window
+    /// * `throttle_window_entries` - The number of those entries inside `tx_window`
+    pub fn metrics(
+        &self,
+        properties: &ICRC3Properties,
+        dedup_entries: u64,
+        throttle_window_entries: u64,
+    ) -> DedupWindowMetrics {
+        DedupWindowMetrics {
+            dedup_window_bytes: self.bytes,
+            max_dedup_window_bytes: properties.max_dedup_window_bytes,
+            dedup_entries,
+            max_dedup_entries: properties.max_dedup_entries,
+            throttle_window_entries,
+            max_transactions_in_window: properties.max_transactions_in_window,
+            early_purge_count: self.early_purge_count,
+            last_early_purge: self.last_early_purge,
+            entries_limit_purge_count: self.entries_limit_purge_count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Nat;
+    use serde_bytes::ByteBuf;
+    use std::collections::BTreeMap;
+
+    fn transaction(timestamp: u64, memo_len: usize) -> ICRC3Value {
+        let mut map = BTreeMap::new();
+        map.insert(
+            "timestamp".to_string(),
+            ICRC3Value::Nat(Nat::from(timestamp)),
+        );
+        map.insert(
+            "memo".to_string(),
+            ICRC3Value::Blob(ByteBuf::from(vec![0u8; memo_len])),
+        );
+        ICRC3Value::Map(map)
+    }
+
+    fn window(stats: &mut DedupWindowStats) -> VecDeque<ICRC3Value> {
+        let ledger: VecDeque<ICRC3Value> = (0..4)
+            .map(|timestamp| transaction(timestamp, 1_000))
+            .collect();
+        stats.recompute(&ledger);
+        ledger
+    }
+
+    fn timestamp(transaction: &ICRC3Value) -> u64 {
+        crate::utils::get_timestamp(transaction)
+            .unwrap()
+            .0
+            .try_into()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_byte_limit_purges_only_expired_entries() {
+        let mut stats = DedupWindowStats::default();
+        let mut ledger = window(&mut stats);
+        let entry_size = DedupWindowStats::entry_size(&ledger[0]);
+        assert_eq!(stats.bytes, 4 * entry_size);
+
+        // Entries 0 and 1 are outside tx_window, 2 and 3 are not.
+        let purged = stats.purge_to_limit(
+            &mut ledger,
+            entry_size,
+            false,
+            100,
+            |tx| timestamp(tx) < 2,
+            |_| false,
+        );
+
+        assert_eq!(purged, 2);
+        assert_eq!(ledger.len(), 2);
+        assert_eq!(stats.bytes, 2 * entry_size);
+        assert_eq!(stats.early_purge_count, 0);
+        assert_eq!(stats.last_early_purge, None);
+    }
+
+    #[test]
+    fn test_early_purge_flag_allows_purging_inside_tx_window() {
+        let mut stats = DedupWindowStats::default();
+        let mut ledger = window(&mut stats);
+        let entry_size = DedupWindowStats::entry_size(&ledger[0]);
+
+        let purged = stats.purge_to_limit(
+            &mut ledger,
+            entry_size,
+            true,
+            100,
+            |tx| timestamp(tx) < 2,
+            |_| false,
+        );
+
+        assert_eq!(purged, 3);
+        assert_eq!(ledger.len(), 1);
+        assert_eq!(stats.bytes, entry_size);
+        assert_eq!(stats.early_purge_count, 1);
+        assert_eq!(stats.last_early_purge, Some(100));
+
+        // Prepared entries are kept even with early purge allowed.
+        let mut stats = DedupWindowStats::default();
+        let mut ledger = window(&mut stats);
+        let purged = stats.purge_to_limit(
+            &mut ledger,
+            0,
+            true,
+            100,
+            |_| false,
+            |tx| timestamp(tx) == 1,
+        );
+        assert_eq!(purged, 1);
+        assert_eq!(ledger.len(), 3);
+    }
+}
@@ -0,0 +1,128 @@
+//! Anchoring of a new chain to the tip of a parent chain.
+//!
+//! When the history of a product moves to a new ICRC3 canister, e.g. when an NFT
+//! collection is migrated, the new chain can commit to the tip of the old one:
+//! with `genesis_parent_hash` set in the [`ICRC3Config`], block 0 has that hash as
+//! parent hash and `phash` instead of none, and a `genesis` block recording the
+//! anchor is appended at init. The verification of the chain then takes the
+//! anchor as the parent of block 0, see [`crate::verification::verify_linkage`]
+//! and `bity_ic_icrc3_verifier::verify_anchored_block_witness`.
+//!
+//! [`ICRC3Config`]: crate::config::ICRC3Config
+
+use crate::config::ICRC3Config;
+use crate::schema::{FieldSchema, TransactionSchema, ValueKind};
+use crate::transaction::TransactionType;
+
+use bity_ic_types::TimestampNanos;
+use candid::{CandidType, Nat};
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use icrc_ledger_types::icrc3::blocks::SupportedBlockType;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::collections::BTreeMap;
+
+/// The block type of the genesis block.
+pub const GENESIS_BLOCK_TYPE: &str = "genesis";
+/// Where the genesis block is described, as listed with the supported block types.
+pub const GENESIS_BLOCK_URL: &str =
+    "https://github.com/BitySA/dfinity-rust-libraries/blob/main/src/icrc3/src/genesis.rs";
+
+/// The anchor of a chain, recorded as its `genesis` block.
+///
+/// The block is `{ btype: "genesis", ts, tx: { parent_hash, parent_ref } }`, with
+/// `parent_ref` only when it is configured.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct GenesisTransaction {
+    pub parent_hash: [u8; 32],
+    pub parent_ref: Option<String>,
+    pub timestamp: TimestampNanos,
+}
+
+impl TransactionType for GenesisTransaction {
+    fn schemas() -> Vec<TransactionSchema> {
+        vec![TransactionSchema::new(
+            GENESIS_BLOCK_TYPE,
+            vec![
+                FieldSchema::required("btype", ValueKind::Text),
+                FieldSchema::required("ts", ValueKind::Nat),
+                FieldSchema::map(
+                    "tx",
+                    vec![
+                        FieldSchema::required("parent_hash", ValueKind::Blob),
+                        FieldSchema::optional("parent_ref", ValueKind::Text),
+                    ],
+                ),
+            ],
+        )]
+    }
+
+    fn validate_transaction_fields(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn timestamp(&self) -> Option<TimestampNanos> {
+        Some(self.timestamp)
+    }
+
+    fn tx(&self) -> ICRC3Value {
+        let mut tx = BTreeMap::new();
+        tx.insert(
+            "parent_hash".to_string(),
+            ICRC3Value::Blob(ByteBuf::from(self.parent_hash.to_vec())),
+        );
+        if let Some(parent_ref) = &self.parent_ref {
+            tx.insert(
+                "parent_ref".to_string(),
+                ICRC3Value::Text(parent_ref.clone()),
+            );
+        }
+        ICRC3Value::Map(tx)
+    }
+
+    fn block_type(&self) -> String {
+        GENESIS_BLOCK_TYPE.to_string()
+    }
+}
+
+impl From<GenesisTransaction> for ICRC3Value {
+    fn from(tx: GenesisTransaction) -> Self {
+        let mut map = BTreeMap::new();
+        map.insert(
+            "btype".to_string(),
+            ICRC3Value::Text(GENESIS_BLOCK_TYPE.to_string()),
+        );
+        map.insert("ts".to_string(), ICRC3Value::Nat(Nat::from(tx.timestamp)));
+        map.insert("tx".to_string(), tx.tx());
+        ICRC3Value::Map(map)
+    }
+}
+
+/// Validates the anchor of the configuration.
+///
+/// # Errors
+///
+/// Returns an error message if `genesis_parent_ref` is set without
+/// `genesis_parent_hash`: the reference alone commits to nothing.
+pub fn validate_anchor(icrc3_config: &ICRC3Config) -> Result<(), String> {
+    if icrc3_config.genesis_parent_ref.is_some() && icrc3_config.genesis_parent_hash.is_none() {
+        return Err("genesis_parent_ref requires genesis_parent_hash".to_string());
+    }
+    Ok(())
+}
+
+/// Adds the `genesis` block type to the supported ones if the chain is anchored
+/// and it is missing.
+pub(crate) fn support_genesis_blocks(icrc3_config: &mut ICRC3Config) {
+    if icrc3_config.genesis_parent_hash.is_some()
+        && !icrc3_config
+            .supported_blocks
+            .iter()
+            .any(|b| b.block_type == GENESIS_BLOCK_TYPE)
+    {
+        icrc3_config.supported_blocks.push(SupportedBlockType {
+            block_type: GENESIS_BLOCK_TYPE.to_string(),
+            url: GENESIS_BLOCK_URL.to_string(),
+        });
+    }
+}
@@ -1,25 +1,135 @@
-use crate::blockchain::archive_canister_manager::{ArchiveCanisterManager, ARCHIVE_WASM};
+use crate::archive_stats::{self, ArchiveStats, ArchiveStatsCache};
+use crate::audit;
+use crate::blockchain::archive_batch::ArchiveBatchSize;
+use crate::blockchain::archive_canister_manager::{
+    ArchiveCanisterHistory, ArchiveCanisterManager, ArchiveReinstall, CyclesSample, FundingAlert,
+    ReconciliationReport, ReinstallConfirmation, SubCanisterCalls, ARCHIVE_WASM,
+};
 use crate::blockchain::blockchain::Blockchain;
 use crate::config::ICRC3Config;
-use crate::utils::{get_timestamp, last_block_hash_tree, trace};
+use crate::dedup_window::{DedupWindowMetrics, DedupWindowStats};
+use crate::genesis::{self, GenesisTransaction};
+use crate::interface::ICRC3Interface;
+use crate::job_history::{JobHistory, JobHistoryMetrics, JobKind};
+use crate::memo_index::MemoIndex;
+use crate::notifications::{
+    BlockNotification, DeliveryResult, NotificationDelivery, Notifications,
+};
+use crate::prepared::PreparedTransactionsMetrics;
+use crate::rebuild::{self, ArchiveEnds, RebuildError, RebuildPlan, RebuildReport};
+use crate::runtime;
+use crate::schema::{self, BlockSchema, TransactionSchema};
+use crate::standards;
+use crate::thash_lookup::{validate_thash_indexing, ThashIndex, ThashLookup};
+use crate::throttle::{should_throttle, ThrottleParams};
+use crate::timestamp_ordering::TimestampOrderingMetrics;
+use crate::transaction::TransactionType;
+use crate::types::Icrc3Error;
+use crate::utils::{get_timestamp, trace};
+use crate::validation::{ValidationMetrics, ValidationMode};
+use crate::verification::{VerificationJobConfig, VerificationPlan};
 
+use bity_ic_canister_time::Nanos;
+use bity_ic_icrc3_archive_api::blocks_http::{render_blocks_chunk, BlocksChunk};
+use bity_ic_icrc3_archive_api::types::hashing::tip_hash_tree;
+use bity_ic_icrc3_archive_api::types::{
+    block_compression::CompressionAlgo, block_interface::Block, defaultblock::DefaultBlock,
+    thash::transaction_hash,
+};
 use bity_ic_icrc3_archive_api::{
     archive_config::ArchiveConfig, lifecycle::BlockType, types::hash::HashOf,
 };
+use bity_ic_subcanister_manager::{
+    CanisterStatusSummary, ControllerError, ReinstallError, SnapshotError,
+};
 use bity_ic_types::BuildVersion;
 use bity_ic_types::TimestampNanos;
-use candid::Nat;
-use ic_certification::AsHashTree;
+use bity_ic_utils::rate::RateTracker;
+use candid::{Nat, Principal};
+use ic_certification::{AsHashTree, RbTree};
 use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use icrc_ledger_types::icrc3::archive::ICRC3ArchiveInfo;
+use icrc_ledger_types::icrc3::blocks::BlockWithId;
 use serde::{Deserialize, Serialize};
 use serde_bytes::ByteBuf;
 use sha2::{Digest, Sha256};
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::time::Duration;
 
 /// The maximum allowed time drift for transaction timestamps
 pub const PERMITTED_DRIFT: Duration = Duration::from_millis(100);
 
+/// The width of the buckets the transaction rate is counted in
+pub const TRANSACTION_RATE_BUCKET: Duration = Duration::from_secs(10);
+
+/// The period the transaction rate is measured over
+pub const TRANSACTION_RATE_HORIZON: Duration = Duration::from_secs(5 * 60);
+
+/// How long a caller is advised to wait before retrying a transaction rejected
+/// because the local archive is full, the time for the requested archive run
+pub const LOCAL_ARCHIVE_FULL_RETRY_AFTER: Duration = Duration::from_secs(2);
+
+fn default_transaction_rate() -> RateTracker {
+    RateTracker::new(
+        TRANSACTION_RATE_BUCKET.as_nanos() as u64,
+        TRANSACTION_RATE_HORIZON.as_nanos() as u64,
+    )
+}
+
+/// Checks the `created_at_time` of a transaction against the ledger time, as
+/// ICRC-1 ledgers do.
+///
+/// # Arguments
+///
+/// * `created_at_time` - The `created_at_time` of the transaction
+/// * `now` - The current time
+/// * `tx_window` - The transaction window
+///
+/// # Errors
+///
+/// * `Icrc3Error::TooOld` if `created_at_time` is before `now - tx_window`
+/// * `Icrc3Error::CreatedInFuture` if `created_at_time` is after `now + PERMITTED_DRIFT`
+pub fn validate_created_at_time(
+    created_at_time: Nanos,
+    now: Nanos,
+    tx_window: Duration,
+) -> Result<(), Icrc3Error> {
+    if created_at_time.saturating_add(duration_nanos(tx_window)) < now {
+        return Err(Icrc3Error::TooOld);
+    }
+    if created_at_time > now.saturating_add(duration_nanos(PERMITTED_DRIFT)) {
+        return Err(Icrc3Error::CreatedInFuture {
+            ledger_time: now.get(),
+        });
+    }
+    Ok(())
+}
+
+/// Returns the timestamp a new transaction is recorded with: its own timestamp if
+/// it has one, `now` otherwise.
+///
+/// The timestamps and `created_at_time` of the transactions are in nanoseconds.
+/// They are wrapped in [`Nanos`] here, where they enter the ledger, so that they
+/// can't be compared with a timestamp in another unit.
+///
+/// # Errors
+///
+/// As [`validate_created_at_time`], if the transaction has a `created_at_time`
+pub fn ingestion_timestamp<T: TransactionType>(
+    transaction: &T,
+    now: Nanos,
+    tx_window: Duration,
+) -> Result<Nanos, Icrc3Error> {
+    if let Some(created_at_time) = transaction.created_at_time() {
+        validate_created_at_time(Nanos(created_at_time), now, tx_window)?;
+    }
+    Ok(transaction.timestamp().map_or(now, Nanos))
+}
+
+fn duration_nanos(duration: Duration) -> Nanos {
+    Nanos(u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX))
+}
+
 /// The main ICRC3 implementation struct.
 ///
 /// This struct represents the core of the ICRC3 implementation, managing
@@ -29,17 +139,85 @@ pub const PERMITTED_DRIFT: Duration = Duration::from_millis(100);
 ///
 /// * `blockchain` - The blockchain implementation
 /// * `ledger` - A queue of recent transactions
-/// * `prepared_transactions` - A FIFO queue of prepared transaction hashes
-/// * `next_index` - The index of the next transaction
+/// * `ledger_block_indices` - The block index of each ledger entry, see [`ICRC3::ledger_block_index`]
+/// * `prepared_transactions` - A FIFO queue of prepared transaction hashes and their timestamps
+/// * `prepared_at` - When each prepared transaction was prepared, by hash, see
+///   [`ICRC3::prepared_transaction_prepared_at`]
+/// * `expired_prepared_transactions` - The hashes of the prepared transactions removed by the cleanup
+///   and when they expired, kept for one more `prepared_transaction_ttl`
 /// * `icrc3_config` - Configuration parameters
+/// * `job_history` - The most recent archive and cleanup job runs
+/// * `dedup_window` - The size of the ledger and its early purges
+/// * `archive_job_interval_ms` - The interval the archive job was started with, restarted after upgrades
+/// * `cleanup_job_interval_ms` - The interval the cleanup job was started with, restarted after upgrades
+/// * `verification_job` - The interval and sample size the verification job was started with, restarted after upgrades
+/// * `funding_health_job_interval_ms` - The interval the funding health job was started with, restarted after upgrades
+/// * `registered_schemas` - The schemas registered for custom block types, see [`ICRC3::register_block_schema`]
+/// * `memo_index` - The local blocks by memo, maintained when `index_memos` is set
+/// * `thash_index` - The local blocks by transaction hash, see [`ICRC3::thash_lookup`]
+/// * `transaction_rate` - The blocks added over the last [`TRANSACTION_RATE_HORIZON`]
+/// * `notifications` - The subscribers to the appended blocks and their queued notifications
+/// * `notification_job_interval_ms` - The interval the notification job was started with, restarted after upgrades
+/// * `archive_batch_size` - The number of blocks per archive batch learned by the archive job
+/// * `validation_violations` - The transactions of each block type accepted despite violations
+/// * `timestamp_clamps` - The blocks whose timestamp was raised to the tip's, see [`crate::timestamp_ordering`]
+/// * `archive_stats` - The last stats fetched from each archive canister, rebuilt after upgrades
 #[derive(Serialize, Deserialize)]
 pub struct ICRC3 {
     pub blockchain: Blockchain,
     pub ledger: VecDeque<ICRC3Value>,
+    #[serde(default)]
+    pub ledger_block_indices: VecDeque<Option<u64>>,
     pub prepared_transactions: VecDeque<(String, TimestampNanos)>,
-    pub next_index: u64,
+    #[serde(default)]
+    pub prepared_at: BTreeMap<String, TimestampNanos>,
+    #[serde(default)]
+    pub expired_prepared_transactions: VecDeque<(String, TimestampNanos)>,
     pub last_phash: Option<ByteBuf>,
     pub icrc3_config: ICRC3Config,
+    #[serde(default)]
+    pub job_history: JobHistory,
+    #[serde(default)]
+    pub dedup_window: DedupWindowStats,
+    #[serde(default)]
+    pub archive_job_interval_ms: Option<u64>,
+    #[serde(default)]
+    pub cleanup_job_interval_ms: Option<u64>,
+    #[serde(default)]
+    pub verification_job: Option<VerificationJobConfig>,
+    #[serde(default)]
+    pub funding_health_job_interval_ms: Option<u64>,
+    #[serde(default)]
+    pub registered_schemas: BTreeMap<String, TransactionSchema>,
+    #[serde(default)]
+    pub memo_index: MemoIndex,
+    #[serde(default)]
+    pub thash_index: ThashIndex,
+    #[serde(default = "default_transaction_rate")]
+    pub transaction_rate: RateTracker,
+    #[serde(default)]
+    pub notifications: Notifications,
+    #[serde(default)]
+    pub notification_job_interval_ms: Option<u64>,
+    #[serde(default)]
+    pub archive_batch_size: ArchiveBatchSize,
+    /// Number of transactions of each block type accepted despite violations
+    #[serde(default)]
+    pub validation_violations: BTreeMap<String, u64>,
+    /// Blocks whose timestamp was raised to the tip's
+    #[serde(default)]
+    pub timestamp_clamps: TimestampOrderingMetrics,
+    /// Whether the add path requested an archive run, see [`ICRC3::take_archive_request`]
+    #[serde(skip)]
+    pub archive_requested: bool,
+    /// Whether a requested archive run is scheduled or running
+    #[serde(skip)]
+    pub archive_in_progress: bool,
+    /// Whether a verification run is waiting on the archives, see [`ICRC3::verification_plan`]
+    #[serde(skip)]
+    pub verification_in_progress: bool,
+    #[serde(skip)]
+    pub archive_stats: ArchiveStatsCache,
 }
 
 unsafe impl Send for ICRC3 {}
@@ -55,44 +233,101 @@ impl ICRC3 {
     /// # Returns
     ///
     /// A new ICRC3 instance with an empty blockchain and ledger
-    pub fn new(icrc3_config: ICRC3Config) -> Self {
-        let this_canister_id = ic_cdk::api::canister_self();
+    pub fn new(mut icrc3_config: ICRC3Config) -> Self {
+        audit::support_admin_blocks(&mut icrc3_config);
+        genesis::support_genesis_blocks(&mut icrc3_config);
+        let this_canister_id = runtime::canister_self();
         let version = bity_ic_icrc3_archive_api::VERSION.to_string();
         let mut hasher = Sha256::new();
         hasher.update(version.as_bytes());
         let commit_hash = format!("{:x}", hasher.finalize());
 
-        Self {
+        if let Err(e) = standards::validate_supported_blocks(&icrc3_config) {
+            runtime::trap(format!("Invalid ICRC3 supported blocks: {}", e));
+        }
+
+        if let Err(e) = icrc3_config.constants.validate() {
+            runtime::trap(format!("Invalid ICRC3 properties: {}", e));
+        }
+
+        if let Err(e) = genesis::validate_anchor(&icrc3_config) {
+            runtime::trap(format!("Invalid ICRC3 genesis anchor: {}", e));
+        }
+
+        let funding_config = icrc3_config.funding_config();
+        if let Err(e) = funding_config.validate() {
+            runtime::trap(format!("Invalid ICRC3 funding config: {}", e));
+        }
+
+        let block_transform = icrc3_config.block_transform.clone().unwrap_or_default();
+        if let Err(e) = block_transform.validate() {
+            runtime::trap(format!("Invalid ICRC3 block transform: {}", e));
+        }
+        if let Err(e) =
+            validate_thash_indexing(icrc3_config.constants.index_thashes, &block_transform)
+        {
+            runtime::trap(format!("Invalid ICRC3 properties: {}", e));
+        }
+
+        let compression = icrc3_config.constants.compression.clone();
+        if let Some(Err(e)) = compression.as_ref().map(CompressionAlgo::validate) {
+            runtime::trap(format!("Invalid ICRC3 block compression: {}", e));
+        }
+
+        let mut archive_canister_manager = ArchiveCanisterManager::new(
+            bity_ic_icrc3_archive_api::init::InitArgs {
+                test_mode: false,
+                version: bity_ic_icrc3_archive_api::VERSION
+                    .parse::<BuildVersion>()
+                    .unwrap(),
+                commit_hash: commit_hash.clone(),
+                authorized_principals: vec![this_canister_id],
+                archive_config: ArchiveConfig::default(),
+                master_canister_id: this_canister_id,
+                block_type: BlockType::Default,
+            },
+            bity_ic_icrc3_archive_api::post_upgrade::UpgradeArgs {
+                version: bity_ic_icrc3_archive_api::VERSION
+                    .parse::<BuildVersion>()
+                    .unwrap(),
+                commit_hash,
+                block_type: BlockType::Default,
+            },
+            HashMap::new(),
+            vec![this_canister_id],
+            vec![this_canister_id],
+            funding_config.initial_cycles,
+            funding_config.reserved_cycles,
+            ARCHIVE_WASM.to_vec(),
+            Some(funding_config.interval_secs),
+            Some(funding_config.min_cycles),
+            Some(funding_config.fund_cycles),
+        );
+        archive_canister_manager.block_transform = block_transform;
+        // Set after the manager is built, so that the archives in test mode are
+        // still real canisters rather than simulated ones.
+        archive_canister_manager.init_args.test_mode = icrc3_config.archive_test_mode;
+        if let Err(e) =
+            archive_canister_manager.set_external_archives(icrc3_config.external_archives.clone())
+        {
+            runtime::trap(format!("Invalid ICRC3 external archives: {}", e));
+        }
+        archive_canister_manager
+            .init_args
+            .archive_config
+            .compression = compression;
+        archive_canister_manager
+            .init_args
+            .archive_config
+            .index_thashes = icrc3_config.constants.index_thashes;
+        archive_canister_manager
+            .init_args
+            .archive_config
+            .max_memory_size_bytes = icrc3_config.constants.max_memory_size_bytes;
+
+        let mut icrc3 = Self {
             blockchain: Blockchain::new(
-                ArchiveCanisterManager::new(
-                    bity_ic_icrc3_archive_api::init::InitArgs {
-                        test_mode: false,
-                        version: bity_ic_icrc3_archive_api::VERSION
-                            .parse::<BuildVersion>()
-                            .unwrap(),
-                        commit_hash: commit_hash.clone(),
-                        authorized_principals: vec![this_canister_id],
-                        archive_config: ArchiveConfig::default(),
-                        master_canister_id: this_canister_id,
-                        block_type: BlockType::Default,
-                    },
-                    bity_ic_icrc3_archive_api::post_upgrade::UpgradeArgs {
-                        version: bity_ic_icrc3_archive_api::VERSION
-                            .parse::<BuildVersion>()
-                            .unwrap(),
-                        commit_hash,
-                        block_type: BlockType::Default,
-                    },
-                    HashMap::new(),
-                    vec![this_canister_id],
-                    vec![this_canister_id],
-                    icrc3_config.constants.initial_cycles,
-                    icrc3_config.constants.reserved_cycles,
-                    ARCHIVE_WASM.to_vec(),
-                    None,
-                    None,
-                    None,
-                ),
+                archive_canister_manager,
                 None,
                 0,
                 Duration::from_secs(120),
@@ -103,11 +338,136 @@ impl ICRC3 {
             ),
 
             ledger: VecDeque::new(),
+            ledger_block_indices: VecDeque::new(),
             prepared_transactions: VecDeque::new(),
-            next_index: 0,
+            prepared_at: BTreeMap::new(),
+            expired_prepared_transactions: VecDeque::new(),
             last_phash: None,
             icrc3_config,
+            job_history: JobHistory::default(),
+            dedup_window: DedupWindowStats::default(),
+            archive_job_interval_ms: None,
+            cleanup_job_interval_ms: None,
+            verification_job: None,
+            funding_health_job_interval_ms: None,
+            registered_schemas: BTreeMap::new(),
+            memo_index: MemoIndex::default(),
+            thash_index: ThashIndex::default(),
+            transaction_rate: default_transaction_rate(),
+            notifications: Notifications::default(),
+            notification_job_interval_ms: None,
+            archive_batch_size: ArchiveBatchSize::default(),
+            validation_violations: BTreeMap::new(),
+            timestamp_clamps: TimestampOrderingMetrics::default(),
+            archive_requested: false,
+            archive_in_progress: false,
+            verification_in_progress: false,
+            archive_stats: ArchiveStatsCache::default(),
+        };
+
+        if let Some(parent_hash) = icrc3.icrc3_config.genesis_parent_hash {
+            icrc3.blockchain.last_hash = Some(HashOf::new(parent_hash));
+            icrc3.last_phash = Some(ByteBuf::from(parent_hash.to_vec()));
+            let genesis = GenesisTransaction {
+                parent_hash,
+                parent_ref: icrc3.icrc3_config.genesis_parent_ref.clone(),
+                timestamp: runtime::time(),
+            };
+            if let Err(e) = icrc3.add_transaction(genesis) {
+                runtime::trap(format!("Failed to add the ICRC3 genesis block: {:?}", e));
+            }
+        }
+
+        icrc3
+    }
+
+    /// Replaces the configuration of a restored instance, e.g. after an upgrade.
+    ///
+    /// The funding config, block transform, block compression and external archive
+    /// canisters are validated as in [`ICRC3::new`] and applied to the archive
+    /// manager, along with the archiving constants.
+    ///
+    /// # Arguments
+    ///
+    /// * `icrc3_config` - The new configuration
+    pub fn apply_config(&mut self, mut icrc3_config: ICRC3Config) -> Result<(), String> {
+        audit::support_admin_blocks(&mut icrc3_config);
+        // The anchor is part of block 0: a config without one keeps it, another one is rejected.
+        if icrc3_config.genesis_parent_hash.is_none() {
+            icrc3_config.genesis_parent_hash = self.icrc3_config.genesis_parent_hash;
+            icrc3_config.genesis_parent_ref = self.icrc3_config.genesis_parent_ref.clone();
+        } else if icrc3_config.genesis_parent_hash != self.icrc3_config.genesis_parent_hash {
+            return Err("The genesis parent hash cannot change after init".to_string());
+        }
+        genesis::validate_anchor(&icrc3_config)
+            .map_err(|e| format!("Invalid ICRC3 genesis anchor: {}", e))?;
+        genesis::support_genesis_blocks(&mut icrc3_config);
+        standards::validate_supported_blocks(&icrc3_config)
+            .map_err(|e| format!("Invalid ICRC3 supported blocks: {}", e))?;
+        icrc3_config
+            .constants
+            .validate()
+            .map_err(|e| format!("Invalid ICRC3 properties: {}", e))?;
+
+        let funding_config = icrc3_config.funding_config();
+        funding_config
+            .validate()
+            .map_err(|e| format!("Invalid ICRC3 funding config: {}", e))?;
+
+        let block_transform = icrc3_config.block_transform.clone().unwrap_or_default();
+        block_transform
+            .validate()
+            .map_err(|e| format!("Invalid ICRC3 block transform: {}", e))?;
+        validate_thash_indexing(icrc3_config.constants.index_thashes, &block_transform)
+            .map_err(|e| format!("Invalid ICRC3 properties: {}", e))?;
+
+        let compression = icrc3_config.constants.compression.clone();
+        if let Some(compression) = &compression {
+            compression
+                .validate()
+                .map_err(|e| format!("Invalid ICRC3 block compression: {}", e))?;
+        }
+
+        {
+            let mut archive_canister_manager = self
+                .blockchain
+                .archive_canister_manager
+                .write()
+                .map_err(|e| format!("Failed to acquire archive manager lock: {}", e))?;
+            archive_canister_manager
+                .set_external_archives(icrc3_config.external_archives.clone())
+                .map_err(|e| format!("Invalid ICRC3 external archives: {}", e))?;
+            archive_canister_manager.set_funding_config(&funding_config);
+            archive_canister_manager.block_transform = block_transform;
+            archive_canister_manager.init_args.test_mode = icrc3_config.archive_test_mode;
+            archive_canister_manager
+                .init_args
+                .archive_config
+                .compression = compression;
+            archive_canister_manager
+                .init_args
+                .archive_config
+                .index_thashes = icrc3_config.constants.index_thashes;
+            archive_canister_manager
+                .init_args
+                .archive_config
+                .max_memory_size_bytes = icrc3_config.constants.max_memory_size_bytes;
+        }
+
+        self.blockchain.max_tx_local_stable_memory_size_bytes =
+            icrc3_config.constants.max_tx_local_stable_memory_size_bytes;
+        self.blockchain.threshold_for_archiving_to_external_archive = icrc3_config
+            .constants
+            .threshold_for_archiving_to_external_archive;
+        let index_memos = icrc3_config.constants.index_memos;
+        let was_indexing_memos = self.icrc3_config.constants.index_memos;
+        self.icrc3_config = icrc3_config;
+        if !index_memos {
+            self.memo_index.clear();
+        } else if !was_indexing_memos {
+            self.rebuild_memo_index();
         }
+        Ok(())
     }
 
     /// Checks if the system should throttle new transactions.
@@ -116,10 +476,17 @@ impl ICRC3 {
     /// 1. Allows the first half of max_transactions_in_window freely
     /// 2. After that, throttles on a per-second basis
     ///
+    /// See [`should_throttle`] for the exact rule.
+    ///
     /// # Returns
     ///
     /// `true` if the system should throttle new transactions, `false` otherwise
     pub fn is_throttling(&self) -> bool {
+        #[cfg(feature = "testing-hooks")]
+        if crate::testing_hooks::force_throttle() {
+            return true;
+        }
+
         let num_in_window = self.ledger_len();
 
         trace(format!(
@@ -129,36 +496,40 @@ impl ICRC3 {
             self.transaction_window().as_secs()
         ));
 
-        if num_in_window >= self.max_transactions_in_window() / 2 {
-            let max_rate = (0.5 * self.max_transactions_in_window() as f64
-                / self.transaction_window().as_secs_f64())
-            .ceil() as u128;
-
-            trace(format!(
-                "is_throttling: num_in_window: {}, max_rate: {}",
-                num_in_window, max_rate
-            ));
-
-            if self
-                .ledger
-                .get(num_in_window.saturating_sub(max_rate).try_into().unwrap())
-                .map(|tx| get_timestamp(tx).unwrap_or(Nat::from(0_u64)))
-                .unwrap_or_else(|| Nat::from(0_u64))
-                + Nat::from(1_u64)
-                > Duration::from_nanos(ic_cdk::api::time()).as_secs()
-            {
-                return true;
-            }
-        }
+        let now = runtime::time() as u128;
+        should_throttle(
+            &self.throttle_window(now),
+            now,
+            &ThrottleParams::from(&self.icrc3_config.constants),
+        )
+    }
 
-        false
+    /// Returns the timestamps of the ledger entries inside the transaction window,
+    /// oldest first.
+    ///
+    /// Throttling only counts these entries: with `max_dedup_entries`, the ledger
+    /// also keeps older ones for deduplication.
+    fn throttle_window(&self, now: u128) -> Vec<u128> {
+        let window = self.transaction_window().as_nanos() + PERMITTED_DRIFT.as_nanos();
+        self.ledger
+            .iter()
+            .map(|tx| {
+                get_timestamp(tx)
+                    .ok()
+                    .and_then(|timestamp| u128::try_from(timestamp.0).ok())
+                    .unwrap_or(0)
+            })
+            .filter(|timestamp| timestamp + window >= now)
+            .collect()
     }
 
     /// Purges old transactions from the ledger.
     ///
     /// Removes transactions older than `now - transaction_window` up to
     /// the maximum number of transactions specified in the configuration.
-    /// Only removes committed transactions, not prepared ones.
+    /// Only removes committed transactions, not prepared ones. Nothing is removed
+    /// when `max_dedup_entries` is set, the ledger is then bounded by
+    /// [`ICRC3::enforce_dedup_entries_limit`] instead.
     ///
     /// # Arguments
     ///
@@ -168,6 +539,9 @@ impl ICRC3 {
     ///
     /// The number of transactions that were purged
     pub fn purge_old_transactions(&mut self, now: u128) -> u128 {
+        if self.icrc3_config.constants.max_dedup_entries.is_some() {
+            return 0;
+        }
         let max_tx_to_purge = self.icrc3_config.constants.max_transactions_to_purge;
         let mut num_tx_purged = 0;
         trace("purge_old_transactions");
@@ -188,13 +562,16 @@ impl ICRC3 {
                 break;
             }
 
-            self.ledger.pop_front();
+            if let Some(transaction) = self.ledger.pop_front() {
+                self.dedup_window.on_remove(&transaction);
+            }
             num_tx_purged += 1;
 
             if num_tx_purged >= max_tx_to_purge {
                 break;
             }
         }
+        self.trim_ledger_block_indices();
         trace(format!(
             "purge_old_transactions done, num_tx_purged: {}",
             num_tx_purged
@@ -203,10 +580,211 @@ impl ICRC3 {
         num_tx_purged
     }
 
+    /// Adds a transaction to the ledger, accounting for its size.
+    ///
+    /// The block index of the entry is unknown until its block is added, see
+    /// [`ICRC3::set_ledger_block_index`].
+    pub(crate) fn push_to_ledger(&mut self, transaction: ICRC3Value) {
+        self.dedup_window.on_insert(&transaction);
+        self.ledger.push_back(transaction);
+        self.ledger_block_indices.push_back(None);
+    }
+
+    /// Replaces the ledger entry at `position`, accounting for the size difference.
+    pub(crate) fn replace_ledger_entry(&mut self, position: usize, transaction: ICRC3Value) {
+        self.dedup_window.on_insert(&transaction);
+        let previous = std::mem::replace(&mut self.ledger[position], transaction);
+        self.dedup_window.on_remove(&previous);
+    }
+
+    /// Drops the block indices of entries purged from the front of the ledger.
+    fn trim_ledger_block_indices(&mut self) {
+        while self.ledger_block_indices.len() > self.ledger.len() {
+            self.ledger_block_indices.pop_front();
+        }
+    }
+
+    /// Returns the block index of the ledger entry at `position`.
+    ///
+    /// `ledger_block_indices` is aligned with the end of `ledger`: entries restored
+    /// from a state saved before the indices were tracked have none, and neither do
+    /// prepared transactions that are not committed yet.
+    pub fn ledger_block_index(&self, position: usize) -> Option<u64> {
+        let offset = self.ledger.len() - self.ledger_block_indices.len();
+        position
+            .checked_sub(offset)
+            .and_then(|i| self.ledger_block_indices.get(i).copied().flatten())
+    }
+
+    /// Records the block index of the ledger entry at `position`.
+    pub(crate) fn set_ledger_block_index(&mut self, position: usize, block_index: u64) {
+        let offset = self.ledger.len() - self.ledger_block_indices.len();
+        if let Some(i) = position.checked_sub(offset) {
+            if let Some(entry) = self.ledger_block_indices.get_mut(i) {
+                *entry = Some(block_index);
+            }
+        }
+    }
+
+    /// Returns the index reported as `duplicate_of` for the ledger entry at `position`.
+    ///
+    /// Falls back to an estimate from the position of the entry when its block
+    /// index is not known.
+    pub(crate) fn duplicate_of(&self, position: usize) -> Nat {
+        let block_index = self.ledger_block_index(position).unwrap_or_else(|| {
+            self.chain_length()
+                .saturating_sub((self.ledger.len() - position) as u64)
+        });
+        Nat::from(block_index)
+    }
+
+    /// Purges the oldest transactions while the ledger is above `max_dedup_window_bytes`.
+    ///
+    /// Only transactions outside the transaction window are purged, unless
+    /// `allow_early_purge` is set. Prepared transactions are never purged.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current timestamp in nanoseconds
+    ///
+    /// # Returns
+    ///
+    /// The number of transactions that were purged
+    pub fn enforce_dedup_window_limit(&mut self, now: u128) -> u128 {
+        let Some(max_bytes) = self.icrc3_config.constants.max_dedup_window_bytes else {
+            return 0;
+        };
+        if self.dedup_window.bytes <= max_bytes {
+            return 0;
+        }
+
+        let allow_early_purge = self.icrc3_config.constants.allow_early_purge;
+        self.purge_dedup_window(now, |stats, ledger, is_expired, is_prepared| {
+            stats.purge_to_limit(
+                ledger,
+                max_bytes,
+                allow_early_purge,
+                now as TimestampNanos,
+                is_expired,
+                is_prepared,
+            )
+        })
+    }
+
+    /// Purges the oldest transactions while the ledger holds more than
+    /// `max_dedup_entries`, with the same rules as
+    /// [`ICRC3::enforce_dedup_window_limit`].
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current timestamp in nanoseconds
+    ///
+    /// # Returns
+    ///
+    /// The number of transactions that were purged
+    pub fn enforce_dedup_entries_limit(&mut self, now: u128) -> u128 {
+        let Some(max_entries) = self.icrc3_config.constants.max_dedup_entries else {
+            return 0;
+        };
+        if self.ledger.len() as u128 <= max_entries {
+            return 0;
+        }
+
+        let allow_early_purge = self.icrc3_config.constants.allow_early_purge;
+        self.purge_dedup_window(now, |stats, ledger, is_expired, is_prepared| {
+            stats.purge_to_entries(
+                ledger,
+                max_entries,
+                allow_early_purge,
+                now as TimestampNanos,
+                is_expired,
+                is_prepared,
+            )
+        })
+    }
+
+    /// Runs a purge of the ledger with the transaction window and the prepared
+    /// transactions it must respect.
+    fn purge_dedup_window(
+        &mut self,
+        now: u128,
+        purge: impl FnOnce(
+            &mut DedupWindowStats,
+            &mut VecDeque<ICRC3Value>,
+            &dyn Fn(&ICRC3Value) -> bool,
+            &dyn Fn(&ICRC3Value) -> bool,
+        ) -> u128,
+    ) -> u128 {
+        let window = self.transaction_window().as_nanos() + PERMITTED_DRIFT.as_nanos();
+        let prepared_transactions = &self.prepared_transactions;
+
+        let num_purged = purge(
+            &mut self.dedup_window,
+            &mut self.ledger,
+            &|tx| {
+                let timestamp = get_timestamp(tx).unwrap_or(Nat::from(0_u64));
+                u128::try_from(timestamp.0).unwrap_or(u128::MAX) + window < now
+            },
+            &|tx| {
+                let mut tx = tx.clone();
+                if let ICRC3Value::Map(ref mut map) = tx {
+                    map.remove("phash");
+                }
+                let hash = hex::encode(tx.hash());
+                prepared_transactions.iter().any(|(h, _)| *h == hash)
+            },
+        );
+        self.trim_ledger_block_indices();
+
+        num_purged
+    }
+
+    /// Recomputes the size of the ledger, e.g. after restoring a state saved
+    /// before the size was tracked.
+    pub fn recompute_dedup_window_bytes(&mut self) {
+        self.dedup_window.recompute(&self.ledger);
+    }
+
+    /// Returns the size of the deduplication window, the number of its entries
+    /// inside the throttling window, and its purges.
+    pub fn dedup_window_metrics(&self) -> DedupWindowMetrics {
+        self.dedup_window.metrics(
+            &self.icrc3_config.constants,
+            self.ledger.len() as u64,
+            self.throttle_window(runtime::time() as u128).len() as u64,
+        )
+    }
+
+    /// Returns the number of pending prepared transactions and the age of the oldest one.
+    pub fn prepared_transactions_metrics(&self) -> PreparedTransactionsMetrics {
+        let now = runtime::time();
+        PreparedTransactionsMetrics {
+            count: self.prepared_transactions.len() as u64,
+            oldest_age_ns: self
+                .prepared_transactions
+                .iter()
+                .map(|(hash, timestamp)| self.prepared_transaction_prepared_at(hash, *timestamp))
+                .min()
+                .map(|prepared_at| now.saturating_sub(prepared_at)),
+            warning_threshold: self
+                .icrc3_config
+                .constants
+                .prepared_transactions_warning_threshold,
+        }
+    }
+
+    /// Returns the number of blocks added per second over the last
+    /// [`TRANSACTION_RATE_HORIZON`].
+    pub fn transactions_per_sec(&self) -> f64 {
+        self.transaction_rate.rate_per_sec(runtime::time())
+    }
+
     /// Cleans up expired prepared transactions.
     ///
-    /// Removes prepared transactions that have been in the ledger for more than 24 hours.
+    /// Removes prepared transactions that were not committed within `prepared_transaction_ttl`.
     /// This is a separate cleanup mechanism for prepared transactions that were never committed.
+    /// Their hashes are kept for one more `prepared_transaction_ttl`, so that committing them
+    /// returns `Icrc3Error::PreparedTransactionExpired` rather than a not found error.
     ///
     /// # Arguments
     ///
@@ -216,20 +794,35 @@ impl ICRC3 {
     ///
     /// The number of expired prepared transactions that were removed
     pub fn cleanup_expired_prepared_transactions(&mut self, now: u128) -> usize {
-        let one_day_nanos = 24 * 60 * 60 * 1_000_000_000u128;
-        let expired_threshold = now.saturating_sub(one_day_nanos);
+        let now = now as TimestampNanos;
         let mut removed_count = 0;
 
-        // Remove expired prepared transactions from the front of the ledger
-        while let Some((_, tx_timestamp)) = self.prepared_transactions.front() {
-            if *tx_timestamp >= expired_threshold as u64 {
+        // Transactions are queued in the order they were prepared, so the expired
+        // ones are at the front.
+        while let Some((transaction_hash, tx_timestamp)) = self.prepared_transactions.front() {
+            let prepared_at =
+                self.prepared_transaction_prepared_at(transaction_hash, *tx_timestamp);
+            let expired_at = self.prepared_transaction_expires_at(prepared_at);
+            if expired_at > now {
                 break;
             }
 
-            self.prepared_transactions.pop_front();
+            if let Some((transaction_hash, _)) = self.prepared_transactions.pop_front() {
+                self.prepared_at.remove(&transaction_hash);
+                self.expired_prepared_transactions
+                    .push_back((transaction_hash, expired_at));
+            }
             removed_count += 1;
         }
 
+        let ttl = self.prepared_transaction_ttl().as_nanos() as TimestampNanos;
+        while let Some((_, expired_at)) = self.expired_prepared_transactions.front() {
+            if expired_at.saturating_add(ttl) > now {
+                break;
+            }
+            self.expired_prepared_transactions.pop_front();
+        }
+
         if removed_count > 0 {
             trace(format!(
                 "cleanup_expired_prepared_transactions: removed {} expired prepared transactions",
@@ -240,12 +833,36 @@ impl ICRC3 {
         removed_count
     }
 
+    /// Runs the cleanup job and records the run in the job history.
     pub fn cleanup_job(&mut self) -> Result<(), String> {
-        self.cleanup_expired_prepared_transactions(ic_cdk::api::time() as u128);
+        let started_at = runtime::time();
+        let removed = self.cleanup_expired_prepared_transactions(started_at as u128);
+        self.job_history.record(
+            JobKind::Cleanup,
+            started_at,
+            runtime::time(),
+            Ok(removed as u128),
+        );
         Ok(())
     }
 
-    /// Returns the current size of the blockchain.
+    /// Returns the number of blocks in the chain, archived and local.
+    ///
+    /// This is the `log_length` of `icrc3_get_blocks` and the index of the next
+    /// block. Prepared transactions are not counted until they are committed.
+    pub fn chain_length(&self) -> u64 {
+        self.blockchain.chain_length()
+    }
+
+    /// Returns the id of the last block, or `None` while the chain is empty.
+    ///
+    /// Block ids are 0-based: this is the id `add_transaction` returned for the
+    /// last block, and the index certified with the tip.
+    pub fn last_block_index(&self) -> Option<u64> {
+        self.chain_length().checked_sub(1)
+    }
+
+    /// Returns the number of blocks stored in archive canisters.
     pub fn archived_chain_length(&self) -> usize {
         self.blockchain.archived_chain_length
     }
@@ -265,23 +882,35 @@ impl ICRC3 {
         self.icrc3_config.constants.max_transactions_in_window
     }
 
+    /// Returns the hash tree of the tip, holding the index and hash of the last block.
+    ///
+    /// The tree is empty while the chain is. Its root hash is the certified data.
+    pub fn tip_hash_tree(&self) -> RbTree<&'static str, Vec<u8>> {
+        tip_hash_tree(
+            self.blockchain
+                .last_hash
+                .zip(self.last_block_index())
+                .map(|(last_hash, last_block_index)| (last_block_index, last_hash.into_bytes())),
+        )
+    }
+
     /// Generates a hash tree for the current state.
     ///
     /// This is used for data certification in the Internet Computer.
     ///
     /// # Returns
     ///
-    /// A vector containing the root hash of the certification tree
+    /// A vector containing the root hash of [`ICRC3::tip_hash_tree`]
     pub fn get_hash_tree(&self) -> Vec<u8> {
-        let hash_tree_root = last_block_hash_tree(
-            self.next_index,
-            self.blockchain
-                .last_hash
-                .unwrap_or(HashOf::new([0; 32]))
-                .into_bytes(),
-        )
-        .root_hash();
-        hash_tree_root.to_vec()
+        self.tip_hash_tree().root_hash().to_vec()
+    }
+
+    /// Sets the certified data of the canister to the root hash of [`ICRC3::get_hash_tree`].
+    ///
+    /// The certified data does not survive upgrades, this must be called once the
+    /// instance is restored so that `icrc3_get_tip_certificate` covers the tip.
+    pub fn refresh_certified_data(&self) {
+        runtime::certified_data_set(self.get_hash_tree());
     }
 
     pub fn add_phash(&mut self, icrc3_transaction: &mut ICRC3Value) {
@@ -300,22 +929,1035 @@ impl ICRC3 {
         }
     }
 
+    /// Adds the principal of the caller to a block in a `rec` field, if `record_recorder` is set.
+    pub fn add_rec(&self, icrc3_transaction: &mut ICRC3Value) {
+        if !self.icrc3_config.constants.record_recorder {
+            return;
+        }
+        if let ICRC3Value::Map(map) = icrc3_transaction {
+            map.insert(
+                "rec".to_string(),
+                ICRC3Value::Blob(ByteBuf::from(runtime::caller().as_slice().to_vec())),
+            );
+        }
+    }
+
+    /// Adds the versions that produced a block in a `ver` field, if
+    /// `embed_version_metadata` is set.
+    pub fn add_ver(&self, icrc3_transaction: &mut ICRC3Value) {
+        if !self.icrc3_config.constants.embed_version_metadata {
+            return;
+        }
+        if let ICRC3Value::Map(map) = icrc3_transaction {
+            let mut ver = BTreeMap::new();
+            ver.insert(
+                "lib".to_string(),
+                ICRC3Value::Text(env!("CARGO_PKG_VERSION").to_string()),
+            );
+            if let Some(commit_hash) = &self.icrc3_config.commit_hash {
+                ver.insert("commit".to_string(), ICRC3Value::Text(commit_hash.clone()));
+            }
+            map.insert("ver".to_string(), ICRC3Value::Map(ver));
+        }
+    }
+
+    /// Orders the timestamp of a new block after the tip, as configured by
+    /// `timestamp_ordering`, see [`crate::timestamp_ordering`].
+    ///
+    /// Returns the timestamp of the block. When it is raised to the tip's, the
+    /// `ts` field of the block is raised too and the original timestamp is
+    /// recorded in an `orig_ts` field.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the block is older than the tip by more than the
+    /// allowed skew
+    pub fn order_timestamp(
+        &self,
+        icrc3_transaction: &mut ICRC3Value,
+        timestamp: u128,
+    ) -> Result<u128, Icrc3Error> {
+        let ordered = self
+            .icrc3_config
+            .constants
+            .timestamp_ordering
+            .order(self.blockchain.last_timestamp, timestamp)
+            .map_err(Icrc3Error::Icrc3Error)?;
+        if ordered == timestamp {
+            return Ok(timestamp);
+        }
+        if let ICRC3Value::Map(map) = icrc3_transaction {
+            if map.contains_key("ts") {
+                map.insert("ts".to_string(), ICRC3Value::Nat(Nat::from(ordered)));
+            }
+            map.insert("orig_ts".to_string(), ICRC3Value::Nat(Nat::from(timestamp)));
+        }
+        Ok(ordered)
+    }
+
+    /// Returns whether a principal may record transactions.
+    ///
+    /// Any principal may when no recorders are configured. Otherwise only the
+    /// recorders, the controllers and the canister itself may.
+    pub fn is_recorder(&self, principal: &Principal) -> bool {
+        match &self.icrc3_config.constants.recorders {
+            None => true,
+            Some(recorders) => {
+                recorders.contains(principal)
+                    || runtime::is_controller(principal)
+                    || *principal == runtime::canister_self()
+            }
+        }
+    }
+
+    /// Checks that the caller may record transactions, see [`ICRC3::is_recorder`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `Icrc3Error::Unauthorized` if it may not.
+    pub fn authorize_recorder(&self) -> Result<(), Icrc3Error> {
+        let caller = runtime::caller();
+        if self.is_recorder(&caller) {
+            Ok(())
+        } else {
+            Err(Icrc3Error::Unauthorized { caller })
+        }
+    }
+
+    /// Allows a principal to record transactions.
+    ///
+    /// This restricts recording to the recorders if it was open to any caller.
+    pub fn add_recorder(&mut self, recorder: Principal) {
+        let recorders = self
+            .icrc3_config
+            .constants
+            .recorders
+            .get_or_insert_with(Vec::new);
+        if !recorders.contains(&recorder) {
+            recorders.push(recorder);
+        }
+        trace(format!("add_recorder: {}", recorder));
+    }
+
+    /// Stops a principal from recording transactions.
+    ///
+    /// Recording stays restricted to the remaining recorders, the controllers and
+    /// the canister itself, even when no recorder is left.
+    pub fn remove_recorder(&mut self, recorder: Principal) {
+        if let Some(recorders) = self.icrc3_config.constants.recorders.as_mut() {
+            recorders.retain(|r| *r != recorder);
+        }
+        trace(format!("remove_recorder: {}", recorder));
+    }
+
+    /// Runs the archive job and records the run in the job history.
+    ///
+    /// The local blocks are archived whatever their number when an archive run was
+    /// requested or the local capacity is low, see [`ICRC3::local_capacity_low`].
+    /// The stats of the archives that received blocks or whose stats are stale are
+    /// then fetched, see [`crate::archive_stats`].
     pub async fn archive_job(&mut self) -> Result<u128, String> {
-        self.blockchain.archive_blocks_jobs().await
+        let started_at = runtime::time();
+        let force = self.archive_in_progress || self.local_capacity_low();
+        let limits = self.icrc3_config.constants.archive_batch_limits();
+        let archives_before = self.icrc3_get_archives();
+        let result = self
+            .blockchain
+            .archive_blocks_jobs(force, &mut self.archive_batch_size, limits)
+            .await;
+        self.archive_in_progress = false;
+        self.prune_memo_index();
+        self.prune_thash_index();
+        self.refresh_archive_stats(&archives_before).await;
+        self.job_history.record_archive(
+            started_at,
+            runtime::time(),
+            result.clone(),
+            self.current_archive_batch_size(),
+        );
+        result
     }
 
-    /// Adds a transaction hash to the prepared transactions queue.
+    /// Fetches the stats of the archives whose range changed since
+    /// `archives_before` was listed, or whose cached stats are stale. An archive
+    /// that can't be called keeps its previous stats.
+    async fn refresh_archive_stats(&mut self, archives_before: &[ICRC3ArchiveInfo]) {
+        for archive in self.icrc3_get_archives() {
+            let received_blocks = !archives_before.contains(&archive);
+            if !received_blocks
+                && !self
+                    .archive_stats
+                    .is_stale(&archive.canister_id, runtime::time())
+            {
+                continue;
+            }
+            match archive_stats::fetch(archive.canister_id).await {
+                Ok(stats) => self
+                    .archive_stats
+                    .insert(archive.canister_id, stats, runtime::time()),
+                Err(e) => trace(format!(
+                    "Failed to fetch the stats of archive {}: {}",
+                    archive.canister_id, e
+                )),
+            }
+        }
+    }
+
+    /// Returns the cached stats of each archive canister, in the order of
+    /// `icrc3_get_archives`, see [`crate::archive_stats`].
+    pub fn archive_stats(&self) -> Vec<ArchiveStats> {
+        self.archive_stats.list(
+            self.icrc3_get_archives()
+                .into_iter()
+                .map(|archive| archive.canister_id),
+        )
+    }
+
+    /// Returns the number of blocks of the next archive batch.
+    pub fn current_archive_batch_size(&self) -> u64 {
+        self.archive_batch_size
+            .current(self.icrc3_config.constants.archive_batch_limits())
+    }
+
+    /// Returns the last success and failure timestamps of each job, along with
+    /// the number of blocks of the next archive batch.
+    pub fn job_history_metrics(&self) -> JobHistoryMetrics {
+        JobHistoryMetrics {
+            archive_batch_size: self.current_archive_batch_size(),
+            ..self.job_history.metrics()
+        }
+    }
+
+    /// Returns whether the capacity left in the local archive is below
+    /// `local_archive_low_water_mark_percent` of its size.
+    pub fn local_capacity_low(&self) -> bool {
+        let low_water_mark = self.blockchain.local_archive_limit().saturating_mul(
+            self.icrc3_config
+                .constants
+                .local_archive_low_water_mark_percent
+                .min(100) as u128,
+        ) / 100;
+        self.blockchain.remaining_local_capacity() < low_water_mark
+    }
+
+    /// Requests an archive run, unless one is already scheduled or running.
+    fn request_archive(&mut self) {
+        if !self.archive_in_progress {
+            self.archive_requested = true;
+        }
+    }
+
+    /// Takes the archive run requested by the add path, if any.
+    ///
+    /// The run is then counted as in progress until the archive job completes, so
+    /// that it is scheduled only once.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the caller should schedule an archive run right away
+    pub fn take_archive_request(&mut self) -> bool {
+        let requested = std::mem::take(&mut self.archive_requested);
+        self.archive_in_progress |= requested;
+        requested
+    }
+
+    /// Appends a block to the chain, requesting an archive run when the local
+    /// capacity gets low, and queues its notification for the subscribers.
+    ///
+    /// # Errors
+    ///
+    /// * `Icrc3Error::LocalArchiveFull` if the block does not fit in the local archive
+    /// * `Icrc3Error::Icrc3Error` if the block is rejected by the chain
+    pub(crate) fn append_block(&mut self, block: DefaultBlock) -> Result<u64, Icrc3Error> {
+        let size = block.clone().encode().size_bytes() as u128;
+        if size > self.blockchain.remaining_local_capacity() {
+            self.request_archive();
+            return Err(Icrc3Error::LocalArchiveFull {
+                retry_after_hint_ms: LOCAL_ARCHIVE_FULL_RETRY_AFTER.as_millis() as u64,
+            });
+        }
+
+        let transaction = (!self.notifications.is_empty()).then(|| block.transaction.clone());
+        let thash = transaction_hash(&block.transaction);
+        let block_index = self
+            .blockchain
+            .add_block(block)
+            .map_err(Icrc3Error::Icrc3Error)?;
+        if let Some(thash) = thash {
+            self.thash_index
+                .insert(block_index, ByteBuf::from(thash.to_vec()));
+        }
+        if self.local_capacity_low() {
+            self.request_archive();
+        }
+        if let Some(transaction) = transaction {
+            self.notifications
+                .enqueue(&BlockNotification::of(block_index, &transaction));
+        }
+        Ok(block_index)
+    }
+
+    /// Starts a verification run of the archived blocks and returns what it
+    /// needs, see [`VerificationPlan::run`]. The run ends with
+    /// [`ICRC3::record_verification`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a previous run has not ended yet, so that a slow run
+    /// does not overlap with the next one.
+    pub fn verification_plan(&mut self) -> Result<VerificationPlan, String> {
+        if self.verification_in_progress {
+            return Err("A verification run is already in progress".to_string());
+        }
+        self.verification_in_progress = true;
+        let archive_canister_manager = self.blockchain.archive_canister_manager.read().unwrap();
+        Ok(VerificationPlan {
+            archived_chain_length: self.blockchain.archived_chain_length as u64,
+            canisters_by_block_offset: archive_canister_manager.canisters_by_block_offset.clone(),
+            block_transform: archive_canister_manager.block_transform.clone(),
+            genesis_parent_hash: self.icrc3_config.genesis_parent_hash,
+        })
+    }
+
+    /// Records a verification run in the job history. A failure is also reported
+    /// as an error trace.
+    ///
+    /// # Arguments
+    ///
+    /// * `started_at` - When the run started, in nanoseconds
+    /// * `result` - The number of verified blocks, or the failures
+    pub fn record_verification(
+        &mut self,
+        started_at: TimestampNanos,
+        result: Result<u128, String>,
+    ) {
+        if let Err(e) = &result {
+            tracing::error!(error = %e, "Archived blocks verification failed");
+        }
+        self.verification_in_progress = false;
+        self.job_history
+            .record(JobKind::Verification, started_at, runtime::time(), result);
+    }
+
+    /// Registers the schema of a block type, replacing its built-in or previously
+    /// registered schema.
+    ///
+    /// The schema is returned by [`block_schemas`](Self::block_schemas) and the
+    /// transactions added with its block type are checked against it.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema` - The schema of the block type
+    pub fn register_block_schema(&mut self, schema: TransactionSchema) {
+        self.registered_schemas.insert(schema.btype.clone(), schema);
+    }
+
+    /// Registers the schemas of the block types of a transaction type, see
+    /// [`TransactionType::schemas`].
+    pub fn register_transaction_type<T: TransactionType>(&mut self) {
+        for schema in T::schemas() {
+            self.register_block_schema(schema);
+        }
+    }
+
+    /// Returns the schema transactions of type `T` with a block type are checked
+    /// against: the registered one, or else the one of `T`, see
+    /// [`schema::schema_for`].
+    pub fn block_schema<T: TransactionType>(&self, btype: &str) -> Option<TransactionSchema> {
+        schema::schema_for::<T>(btype, &self.registered_schemas)
+    }
+
+    /// Returns the documentation of each supported block type.
+    pub fn block_schemas(&self) -> Vec<BlockSchema> {
+        schema::block_schemas(
+            &self.icrc3_config.supported_blocks,
+            &self.registered_schemas,
+        )
+    }
+
+    /// Checks a transaction of type `T` against the schema of its block type, if
+    /// any, see [`ICRC3::block_schema`].
+    ///
+    /// # Arguments
+    ///
+    /// * `btype` - The block type of the transaction
+    /// * `transaction` - The transaction converted to an `ICRC3Value`
+    pub fn validate_against_schema<T: TransactionType>(
+        &self,
+        btype: &str,
+        transaction: &ICRC3Value,
+    ) -> Result<(), String> {
+        match self.block_schema::<T>(btype) {
+            Some(schema) => schema.validate(transaction),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks a new transaction with its own `validate_transaction_fields` and
+    /// against the schema of its block type, as strictly as the validation mode of
+    /// the block type, see [`crate::validation`].
+    ///
+    /// In lenient mode, a transaction failing the checks is accepted and counted
+    /// in [`ICRC3::validation_metrics`], with a warning listing the violations.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first violation in strict mode
+    pub fn check_transaction_fields<T: TransactionType>(
+        &mut self,
+        transaction: &T,
+        transaction_as_icrc3: &ICRC3Value,
+    ) -> Result<(), String> {
+        let btype = transaction.block_type();
+        let mode = self.icrc3_config.validation_mode(&btype);
+        if mode == ValidationMode::Off {
+            return Ok(());
+        }
+
+        let violations: Vec<String> = [
+            transaction.validate_transaction_fields(),
+            self.validate_against_schema::<T>(&btype, transaction_as_icrc3),
+        ]
+        .into_iter()
+        .filter_map(Result::err)
+        .collect();
+        let Some(first_violation) = violations.first() else {
+            return Ok(());
+        };
+
+        if mode == ValidationMode::Strict {
+            return Err(first_violation.clone());
+        }
+        trace(format!(
+            "accepted a {} transaction despite violations: {:?}",
+            btype, violations
+        ));
+        tracing::warn!(
+            btype = %btype,
+            violations = ?violations,
+            "Accepted a transaction failing validation in lenient mode"
+        );
+        *self.validation_violations.entry(btype).or_default() += 1;
+        Ok(())
+    }
+
+    /// Returns the number of transactions of each block type accepted despite
+    /// violations, see [`ICRC3::check_transaction_fields`].
+    pub fn validation_metrics(&self) -> ValidationMetrics {
+        ValidationMetrics {
+            lenient_violations: self.validation_violations.clone(),
+        }
+    }
+
+    /// Returns the number of blocks whose timestamp was raised to the tip's, see
+    /// [`ICRC3::order_timestamp`].
+    pub fn timestamp_ordering_metrics(&self) -> TimestampOrderingMetrics {
+        self.timestamp_clamps.clone()
+    }
+
+    /// Indexes the memo of a new local block, if `index_memos` is set.
+    pub(crate) fn index_memo(&mut self, block_index: u64, memo: Option<ByteBuf>) {
+        if !self.icrc3_config.constants.index_memos {
+            return;
+        }
+        if let Some(memo) = memo {
+            self.memo_index.insert(block_index, memo);
+        }
+    }
+
+    /// Removes the memos of the blocks that are no longer stored locally.
+    pub fn prune_memo_index(&mut self) {
+        let first_local_index = self.first_local_block_index();
+        self.memo_index.prune_before(first_local_index);
+    }
+
+    /// Removes the transaction hashes of the blocks that are no longer stored locally.
+    pub fn prune_thash_index(&mut self) {
+        let first_local_index = self.first_local_block_index();
+        self.thash_index.prune_before(first_local_index);
+    }
+
+    /// Returns the index of the oldest local block, or the chain length when no
+    /// block is stored locally.
+    fn first_local_block_index(&self) -> u64 {
+        self.blockchain
+            .local_archive
+            .first_key_value()
+            .map(|(index, _)| index)
+            .unwrap_or(
+                self.blockchain.archived_chain_length as u64 + self.blockchain.local_archive.len(),
+            )
+    }
+
+    /// Indexes the memos of all the local blocks, e.g. when `index_memos` is enabled
+    /// on an existing chain.
+    pub fn rebuild_memo_index(&mut self) {
+        self.memo_index.clear();
+        for entry in self.blockchain.local_archive.iter() {
+            let (block_index, encoded) = entry.into_pair();
+            match DefaultBlock::decode(encoded) {
+                Ok(block) => {
+                    if let Some(memo) = MemoIndex::memo_of(&block.transaction) {
+                        self.memo_index.insert(block_index, memo.clone());
+                    }
+                }
+                Err(e) => trace(format!(
+                    "rebuild_memo_index: failed to decode block {}: {}",
+                    block_index, e
+                )),
+            }
+        }
+    }
+
+    /// Indexes the transaction hashes of all the local blocks, unless each of them
+    /// is already indexed, e.g. for a state saved before the index existed.
+    ///
+    /// Blocks that don't record a map have no transaction hash, so the index of a
+    /// chain with such blocks is rebuilt each time.
+    pub fn rebuild_thash_index(&mut self) {
+        if self.thash_index.len() as u64 == self.blockchain.local_archive.len() {
+            return;
+        }
+        self.thash_index.clear();
+        for entry in self.blockchain.local_archive.iter() {
+            let (block_index, encoded) = entry.into_pair();
+            match DefaultBlock::decode(encoded) {
+                Ok(block) => {
+                    if let Some(thash) = transaction_hash(&block.transaction) {
+                        self.thash_index
+                            .insert(block_index, ByteBuf::from(thash.to_vec()));
+                    }
+                }
+                Err(e) => trace(format!(
+                    "rebuild_thash_index: failed to decode block {}: {}",
+                    block_index, e
+                )),
+            }
+        }
+    }
+
+    /// Returns the indices of the local blocks with a memo, in increasing order.
+    ///
+    /// Blocks stored in archive canisters are not searched, and nothing is found
+    /// unless `index_memos` is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `memo` - The memo to look up
+    /// * `max` - The maximum number of indices returned
+    pub fn find_blocks_by_memo(&self, memo: &[u8], max: u32) -> Vec<Nat> {
+        self.memo_index
+            .find(memo, max)
+            .into_iter()
+            .map(Nat::from)
+            .collect()
+    }
+
+    /// Prepares the lookup of the block recording the transaction with hash
+    /// `thash`, see [`ThashLookup`].
+    ///
+    /// The local blocks are found through the `thash_index`, the most recent one
+    /// when several record the transaction. The archive canisters are only queried
+    /// by [`ThashLookup::run`] if none does.
+    pub fn thash_lookup(&self, thash: ByteBuf) -> ThashLookup {
+        let local_block = self.thash_index.find(&thash).and_then(|block_index| {
+            let encoded = self.blockchain.local_archive.get(&block_index)?;
+            DefaultBlock::decode(encoded).ok().map(|block| BlockWithId {
+                id: Nat::from(block_index),
+                block: block.transaction,
+            })
+        });
+        let archives = self
+            .blockchain
+            .archive_canister_manager
+            .read()
+            .unwrap()
+            .canisters_by_block_offset
+            .iter()
+            .map(|(_, canister_id)| *canister_id)
+            .collect();
+        ThashLookup {
+            thash,
+            local_block,
+            archives,
+        }
+    }
+
+    /// Renders a chunk of a `/blocks` HTTP export from the local blocks, see
+    /// [`blocks_http`](bity_ic_icrc3_archive_api::blocks_http).
+    ///
+    /// The chunk stops at the first block that isn't stored locally.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The index of the first block of the chunk
+    /// * `end` - The index following the last block of the export
+    /// * `chunk_bytes` - The maximum size of the chunk
+    /// * `first` - Whether this is the first chunk of the export
+    ///
+    /// # Returns
+    ///
+    /// * `Result<BlocksChunk, String>` - An error naming the archive canister if the
+    ///   first block has been archived and removed from the local blocks
+    pub fn blocks_http_chunk(
+        &self,
+        start: u64,
+        end: u64,
+        chunk_bytes: usize,
+        first: bool,
+    ) -> Result<BlocksChunk, String> {
+        if start < end && self.blockchain.get_block(start).is_none() {
+            return Err(match self.blockchain.get_block_canister_id(start) {
+                Ok(canister_id) => format!(
+                    "Block {} is archived, it is served by the archive canister {}",
+                    start, canister_id
+                ),
+                Err(e) => format!("Block {} is not available: {}", start, e),
+            });
+        }
+
+        Ok(render_blocks_chunk(
+            start,
+            end,
+            chunk_bytes,
+            first,
+            |block_id| {
+                let block = self.blockchain.get_block(block_id)?;
+                match DefaultBlock::decode(block) {
+                    Ok(block) => Some(block.transaction),
+                    Err(e) => {
+                        trace(format!(
+                            "blocks_http_chunk: failed to decode block {}: {}",
+                            block_id, e
+                        ));
+                        None
+                    }
+                }
+            },
+        ))
+    }
+
+    /// Returns the creation and upgrade history of each archive canister.
+    pub fn archive_canister_histories(&self) -> Vec<ArchiveCanisterHistory> {
+        self.blockchain
+            .archive_canister_manager
+            .read()
+            .unwrap()
+            .canister_histories()
+    }
+
+    /// Retires an archive canister: it keeps serving its blocks and being funded,
+    /// but the next blocks are inserted into a new archive canister.
+    ///
+    /// # Arguments
+    ///
+    /// * `canister_id` - The archive canister to retire
+    pub fn retire_archive(&mut self, canister_id: Principal) -> Result<(), String> {
+        self.blockchain
+            .archive_canister_manager
+            .write()
+            .map_err(|e| format!("Failed to acquire archive manager lock: {}", e))?
+            .retire_archive(canister_id)
+    }
+
+    /// Puts a retired archive canister back into service.
+    ///
+    /// # Arguments
+    ///
+    /// * `canister_id` - The archive canister to put back into service
+    pub fn unretire_archive(&mut self, canister_id: Principal) -> Result<(), String> {
+        self.blockchain
+            .archive_canister_manager
+            .write()
+            .map_err(|e| format!("Failed to acquire archive manager lock: {}", e))?
+            .unretire_archive(canister_id)
+    }
+
+    /// Marks the blocks of a retired archive canister as lost, which allows
+    /// reinstalling it with [`reinstall_archive`](Self::reinstall_archive).
+    ///
+    /// # Arguments
+    ///
+    /// * `canister_id` - The archive canister whose blocks are lost
+    pub fn mark_archive_unrecoverable(&mut self, canister_id: Principal) -> Result<(), String> {
+        self.blockchain
+            .archive_canister_manager
+            .write()
+            .map_err(|e| format!("Failed to acquire archive manager lock: {}", e))?
+            .mark_archive_unrecoverable(canister_id)
+    }
+
+    /// Starts the reinstall of a retired archive canister marked unrecoverable,
+    /// wiping its state. The reinstall runs without holding the ICRC3 lock, see
+    /// [`ArchiveReinstall::run`], and ends with
+    /// [`record_archive_reinstall`](Self::record_archive_reinstall).
+    ///
+    /// # Arguments
+    ///
+    /// * `confirmation` - The confirmation of the data loss for the archive canister
+    pub fn archive_reinstall(
+        &self,
+        confirmation: &ReinstallConfirmation,
+    ) -> Result<ArchiveReinstall, String> {
+        self.blockchain
+            .archive_canister_manager
+            .read()
+            .map_err(|e| format!("Failed to acquire archive manager lock: {}", e))?
+            .reinstall_plan(confirmation)
+    }
+
+    /// Records the outcome of the reinstall of an archive canister.
+    ///
+    /// # Arguments
+    ///
+    /// * `reinstall` - The reinstall returned by [`archive_reinstall`](Self::archive_reinstall)
+    /// * `result` - The outcome of [`ArchiveReinstall::run`]
+    pub fn record_archive_reinstall(
+        &mut self,
+        reinstall: ArchiveReinstall,
+        result: Result<(), ReinstallError>,
+    ) -> Result<(), String> {
+        self.blockchain
+            .archive_canister_manager
+            .write()
+            .map_err(|e| format!("Failed to acquire archive manager lock: {}", e))?
+            .record_reinstall(reinstall, result)
+    }
+
+    /// Returns a handle making the management canister calls on the archive
+    /// canisters, e.g. to snapshot them, without holding the archive manager lock.
+    ///
+    /// The lock of the ICRC3 instance should be released as well while the calls
+    /// await, so that other messages don't trap on it.
+    pub fn archive_calls(&self) -> Result<SubCanisterCalls, String> {
+        Ok(self
+            .blockchain
+            .archive_canister_manager
+            .read()
+            .map_err(|e| format!("Failed to acquire archive manager lock: {}", e))?
+            .sub_canister_manager
+            .calls())
+    }
+
+    /// Caches the controllers of an archive canister after updating them with
+    /// [`SubCanisterCalls::add_controller`] or [`SubCanisterCalls::remove_controller`].
+    ///
+    /// The ICRC3 canister itself cannot be removed from the controllers.
+    ///
+    /// # Arguments
+    ///
+    /// * `canister_id` - The updated archive canister
+    /// * `result` - The controllers of the archive canister, as verified after the update
+    pub fn record_archive_controllers(
+        &mut self,
+        canister_id: Principal,
+        result: &Result<Vec<Principal>, ControllerError>,
+    ) -> Result<(), String> {
+        self.blockchain
+            .archive_canister_manager
+            .write()
+            .map_err(|e| format!("Failed to acquire archive manager lock: {}", e))?
+            .sub_canister_manager
+            .record_controllers(canister_id, result);
+        Ok(())
+    }
+
+    /// Records the state of an archive canister after restoring it from one of
+    /// its snapshots with [`SubCanisterCalls::restore_snapshot`].
+    ///
+    /// # Arguments
+    ///
+    /// * `canister_id` - The restored archive canister
+    /// * `result` - The outcome of the restore
+    pub fn record_archive_snapshot_restore(
+        &mut self,
+        canister_id: Principal,
+        result: &Result<(), SnapshotError>,
+    ) -> Result<(), String> {
+        self.blockchain
+            .archive_canister_manager
+            .write()
+            .map_err(|e| format!("Failed to acquire archive manager lock: {}", e))?
+            .sub_canister_manager
+            .record_snapshot_restore(canister_id, result);
+        Ok(())
+    }
+
+    /// Compares the statuses of the archive canisters, fetched with
+    /// [`SubCanisterCalls::canister_statuses`], with the records of the archive manager.
+    ///
+    /// Reports the archive canisters whose module or running status differs from
+    /// the recorded one, e.g. after a manual upgrade with dfx.
+    ///
+    /// # Arguments
+    ///
+    /// * `statuses` - The status of each archive canister
+    /// * `auto_adopt` - Whether to update the records to match the archive canisters
+    pub fn apply_archive_reconciliation(
+        &mut self,
+        statuses: Vec<(Principal, Result<CanisterStatusSummary, String>)>,
+        auto_adopt: bool,
+    ) -> Result<ReconciliationReport, String> {
+        Ok(self
+            .blockchain
+            .archive_canister_manager
+            .write()
+            .map_err(|e| format!("Failed to acquire archive manager lock: {}", e))?
+            .sub_canister_manager
+            .apply_reconciliation(statuses, auto_adopt))
+    }
+
+    /// Starts a rebuild of an empty chain from its archive canisters, after the
+    /// state of this canister was lost, see [`crate::rebuild`].
+    ///
+    /// The archive canisters are read with [`RebuildPlan::read_archive_ends`], and
+    /// the rebuild ends with [`apply_rebuild`](Self::apply_rebuild).
+    ///
+    /// # Arguments
+    ///
+    /// * `archives` - The archive canisters storing the chain, in any order
+    pub fn rebuild_plan(&self, archives: Vec<Principal>) -> Result<RebuildPlan, RebuildError> {
+        self.check_rebuildable()?;
+        if archives.is_empty() {
+            return Err(RebuildError::NoArchives);
+        }
+
+        let block_transform = self
+            .blockchain
+            .archive_canister_manager
+            .read()
+            .map_err(|e| {
+                RebuildError::Icrc3Error(format!("Failed to acquire archive manager lock: {}", e))
+            })?
+            .block_transform
+            .clone();
+        Ok(RebuildPlan {
+            archives,
+            block_transform,
+        })
+    }
+
+    /// Rebuilds the chain from the ends of its archive canisters.
+    ///
+    /// The archive canisters are registered, and the chain length and the tip are
+    /// restored from the last archived block. The local archive stays empty, ready
+    /// for new blocks. Nothing is changed when the rebuild is aborted, e.g. when
+    /// blocks were added while the archives were read.
+    ///
+    /// # Arguments
+    ///
+    /// * `ends` - The ends read with [`RebuildPlan::read_archive_ends`]
+    pub fn apply_rebuild(&mut self, ends: Vec<ArchiveEnds>) -> Result<RebuildReport, RebuildError> {
+        self.check_rebuildable()?;
+        let report = rebuild::check_archive_ends(ends, self.icrc3_config.genesis_parent_hash)?;
+
+        self.blockchain
+            .archive_canister_manager
+            .write()
+            .map_err(|e| {
+                RebuildError::Icrc3Error(format!("Failed to acquire archive manager lock: {}", e))
+            })?
+            .register_rebuilt_archives(&report.archives)
+            .map_err(RebuildError::Icrc3Error)?;
+
+        let last_hash: [u8; 32] = report
+            .last_hash
+            .as_slice()
+            .try_into()
+            .expect("block hashes are 32 bytes");
+        self.blockchain.archived_chain_length = report.archived_chain_length as usize;
+        self.blockchain.last_hash = Some(HashOf::new(last_hash));
+        self.blockchain.last_timestamp = report.last_timestamp as u128;
+        self.last_phash = Some(report.last_hash.clone());
+        self.refresh_certified_data();
+
+        trace(format!("Rebuilt the chain from archives: {:?}", report));
+        Ok(report)
+    }
+
+    fn check_rebuildable(&self) -> Result<(), RebuildError> {
+        let chain_length = self.chain_length();
+        if chain_length > 0 {
+            return Err(RebuildError::ChainNotEmpty { chain_length });
+        }
+        Ok(())
+    }
+
+    /// Takes the notification batches due now, see
+    /// [`notifications::deliver`](crate::notifications::deliver).
+    pub fn take_notification_deliveries(&mut self) -> Vec<NotificationDelivery> {
+        self.notifications.take_due_deliveries(runtime::time())
+    }
+
+    /// Records the outcome of the notification deliveries in the subscribers
+    /// and in the job history, with the number of delivered notifications.
+    /// A failed delivery is reported as an error trace.
+    ///
+    /// # Arguments
+    ///
+    /// * `started_at` - When the deliveries started, in nanoseconds
+    /// * `results` - The outcome of each delivery
+    pub fn record_notification_deliveries(
+        &mut self,
+        started_at: TimestampNanos,
+        results: Vec<DeliveryResult>,
+    ) {
+        let now = runtime::time();
+        let mut delivered = 0;
+        for (canister_id, last_index, result) in results {
+            if let Err(e) = &result {
+                trace(format!(
+                    "Failed to notify subscriber {}: {}",
+                    canister_id, e
+                ));
+            }
+            delivered += self
+                .notifications
+                .record_delivery(canister_id, last_index, result, now);
+        }
+        self.job_history.record(
+            JobKind::Notifications,
+            started_at,
+            now,
+            Ok(delivered as u128),
+        );
+    }
+
+    /// Retries the pending funding registrations of the archive canisters, and
+    /// returns the calls sampling their cycle balances and the one of this canister.
+    ///
+    /// The calls are made without holding the ICRC3 lock, and their samples are
+    /// recorded with [`record_archive_funding_check`](Self::record_archive_funding_check).
+    pub fn archive_funding_check_calls(&mut self) -> Result<SubCanisterCalls, String> {
+        let mut archive_manager = self
+            .blockchain
+            .archive_canister_manager
+            .write()
+            .map_err(|e| format!("Failed to acquire archive manager lock: {}", e))?;
+
+        archive_manager.sub_canister_manager.retry_pending_funding();
+        Ok(archive_manager.sub_canister_manager.calls())
+    }
+
+    /// Records the cycle balances sampled by a funding check, and returns the
+    /// canisters trending towards freezing.
+    ///
+    /// Each alert is logged as a warning, and the run is recorded in the job
+    /// history with the number of alerts.
+    ///
+    /// # Arguments
+    ///
+    /// * `started_at` - When the check started, in nanoseconds
+    /// * `sample` - The balances sampled by the calls of [`archive_funding_check_calls`](Self::archive_funding_check_calls)
+    pub fn record_archive_funding_check(
+        &mut self,
+        started_at: TimestampNanos,
+        sample: CyclesSample,
+    ) -> Result<Vec<FundingAlert>, String> {
+        let mut archive_manager = self
+            .blockchain
+            .archive_canister_manager
+            .write()
+            .map_err(|e| format!("Failed to acquire archive manager lock: {}", e))?;
+
+        archive_manager
+            .sub_canister_manager
+            .record_cycles_sample(sample);
+        let alerts = archive_manager.sub_canister_manager.report_funding_health();
+        drop(archive_manager);
+
+        self.job_history.record(
+            JobKind::FundingHealth,
+            started_at,
+            runtime::time(),
+            Ok(alerts.len() as u128),
+        );
+        Ok(alerts)
+    }
+
+    /// Returns the funding alerts of the last funding check, see
+    /// [`record_archive_funding_check`](Self::record_archive_funding_check).
+    pub fn archive_funding_alerts(&self) -> Vec<FundingAlert> {
+        self.blockchain
+            .archive_canister_manager
+            .read()
+            .unwrap()
+            .sub_canister_manager
+            .check_funding_health()
+    }
+
+    /// Adds a transaction hash to the prepared transactions queue, recording that
+    /// it was prepared now.
     ///
     /// # Arguments
     ///
     /// * `transaction_hash` - The hash of the prepared transaction
+    /// * `timestamp` - The timestamp of the transaction, to commit it with
     pub fn add_prepared_transaction(
         &mut self,
         transaction_hash: String,
         timestamp: TimestampNanos,
     ) {
+        self.expired_prepared_transactions
+            .retain(|(hash, _)| *hash != transaction_hash);
+        self.prepared_at
+            .insert(transaction_hash.clone(), runtime::time());
         self.prepared_transactions
             .push_back((transaction_hash, timestamp));
+
+        // Only traced when the threshold is crossed, not for every transaction above it.
+        let count = self.prepared_transactions.len() as u64;
+        if self
+            .icrc3_config
+            .constants
+            .prepared_transactions_warning_threshold
+            .is_some_and(|threshold| count == threshold.saturating_add(1))
+        {
+            trace(format!(
+                "WARNING: {} prepared transactions are pending, callers may not be committing them",
+                count
+            ));
+        }
+    }
+
+    /// Returns how long a prepared transaction can be committed.
+    pub fn prepared_transaction_ttl(&self) -> Duration {
+        self.icrc3_config.constants.prepared_transaction_ttl
+    }
+
+    /// Returns when a transaction prepared at `prepared_at` expires, in nanoseconds.
+    pub fn prepared_transaction_expires_at(&self, prepared_at: TimestampNanos) -> TimestampNanos {
+        prepared_at.saturating_add(self.prepared_transaction_ttl().as_nanos() as TimestampNanos)
+    }
+
+    /// Returns when a pending transaction was prepared, in nanoseconds.
+    ///
+    /// The timestamp of the transaction is set by the caller and can be far in the
+    /// past, so it only stands for the prepare time of the transactions prepared
+    /// before that time was recorded.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction_hash` - The hash of the prepared transaction, hex encoded
+    /// * `timestamp` - The timestamp of the transaction
+    pub fn prepared_transaction_prepared_at(
+        &self,
+        transaction_hash: &str,
+        timestamp: TimestampNanos,
+    ) -> TimestampNanos {
+        self.prepared_at
+            .get(transaction_hash)
+            .copied()
+            .unwrap_or(timestamp)
+    }
+
+    /// Returns when a prepared transaction removed by the cleanup expired, if its
+    /// hash is still kept.
+    pub fn expired_prepared_transaction(&self, transaction_hash: &str) -> Option<TimestampNanos> {
+        self.expired_prepared_transactions
+            .iter()
+            .find(|(hash, _)| hash == transaction_hash)
+            .map(|(_, expired_at)| *expired_at)
     }
 
     pub fn prepared_transactions_count(&self) -> usize {
@@ -328,7 +1970,9 @@ use ic_certification::hash_tree::leaf;
 use ic_certification::Certificate;
 use leb128;
 
-impl From<ICRC3> for Certificate {
+impl TryFrom<ICRC3> for Certificate {
+    type Error = String;
+
     /// Converts an ICRC3 instance into a Certificate.
     ///
     /// This implementation creates a certification tree containing:
@@ -336,8 +1980,12 @@ impl From<ICRC3> for Certificate {
     /// * The last block hash
     ///
     /// The certificate is then set as the certified data for the canister.
-    fn from(val: ICRC3) -> Self {
-        let last_block_index = val.next_index - 1;
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if no data certificate is available, i.e. outside a query.
+    fn try_from(val: ICRC3) -> Result<Self, Self::Error> {
+        let last_block_index = val.last_block_index().unwrap_or(0);
         let last_block_hash = val.blockchain.last_hash.unwrap_or(HashOf::new([0; 32]));
 
         // Encode last_block_index as LEB128
@@ -349,12 +1997,13 @@ impl From<ICRC3> for Certificate {
         let leaf2 = leaf(last_block_hash.as_slice());
 
         let hash_tree = fork(leaf1, leaf2);
-        ic_cdk::api::certified_data_set(hash_tree.digest());
-        let certificate = ic_cdk::api::data_certificate().expect("No data certificate available");
-        Certificate {
+        runtime::certified_data_set(hash_tree.digest());
+        let certificate = runtime::data_certificate()
+            .ok_or_else(|| "No data certificate available outside a query".to_string())?;
+        Ok(Certificate {
             tree: hash_tree,
             signature: certificate,
             delegation: None,
-        }
+        })
     }
 }
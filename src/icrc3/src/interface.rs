@@ -1,17 +1,22 @@
-use crate::icrc3::ICRC3;
+use crate::config::FundingConfig;
+use crate::icrc3::{ingestion_timestamp, ICRC3};
+use crate::memo_index::MemoIndex;
+use crate::prepared::{ListPreparedTransactionsArgs, PreparedTransactionInfo};
+use crate::runtime;
 use crate::transaction::{GlobalTransaction, TransactionType};
-use crate::types::{commit_transaction, prepare_transaction, Icrc3Error};
-use crate::utils::trace;
+use crate::types::{commit_transaction, icrc3_get_tip::TipInfo, prepare_transaction, Icrc3Error};
+use crate::utils::{check_transaction_limits, push_archived_range, requested_block_range, trace};
 
+use bity_ic_canister_time::Nanos;
 use bity_ic_icrc3_archive_api::types::{block_interface::Block, defaultblock::DefaultBlock};
 use candid::Nat;
 use hex;
+use ic_certification::AsHashTree;
 use icrc_ledger_types::{
     icrc::generic_value::ICRC3Value,
     icrc3::archive::ICRC3ArchiveInfo,
     icrc3::blocks::{BlockWithId, ICRC3DataCertificate},
     icrc3::blocks::{GetBlocksRequest, GetBlocksResult, SupportedBlockType},
-    icrc3::{archive::QueryArchiveFn, blocks::ArchivedBlocks},
 };
 use serde_bytes::ByteBuf;
 
@@ -56,14 +61,17 @@ pub trait ICRC3Interface {
     ///
     /// # Returns
     ///
-    /// * `Result<u64, Icrc3Error>` - The index of the added transaction or an error
+    /// * `Result<u64, Icrc3Error>` - The id of the block created for the transaction, as
+    ///   used by `icrc3_get_blocks` (block ids are 0-based), or an error
     ///
     /// # Errors
     ///
     /// Returns an error if:
     /// * The transaction is invalid
+    /// * The `created_at_time` of the transaction is too old or in the future
     /// * The transaction is a duplicate
     /// * The system is throttling transactions
+    /// * The local archive is full, with `Icrc3Error::LocalArchiveFull`
     fn add_transaction<T: TransactionType>(&mut self, transaction: T) -> Result<u64, Icrc3Error>;
 
     /// Prepares a transaction for later commit without adding it to the ledger.
@@ -78,12 +86,14 @@ pub trait ICRC3Interface {
     ///
     /// # Returns
     ///
-    /// * `Result<PreparedTransaction, Icrc3Error>` - The prepared transaction or an error
+    /// * `Result<PreparedTransaction, Icrc3Error>` - The prepared transaction, to commit
+    ///   before its `expires_at_ns`, or an error
     ///
     /// # Errors
     ///
     /// Returns an error if:
     /// * The transaction is invalid
+    /// * The `created_at_time` of the transaction is too old or in the future
     /// * The transaction is a duplicate
     /// * The system is throttling transactions
     fn prepare_transaction<T: TransactionType>(
@@ -95,6 +105,8 @@ pub trait ICRC3Interface {
     ///
     /// This method adds the prepared transaction to the ledger and updates
     /// the blockchain. It should be called after any async operations are completed.
+    /// As with [`add_transaction`](Self::add_transaction), the committed transaction
+    /// is then rejected as a duplicate of its block and counted for throttling.
     ///
     /// # Arguments
     ///
@@ -102,7 +114,8 @@ pub trait ICRC3Interface {
     ///
     /// # Returns
     ///
-    /// * `Result<u64, Icrc3Error>` - The index of the committed transaction or an error
+    /// * `Result<u64, Icrc3Error>` - The id of the block created for the committed
+    ///   transaction (block ids are 0-based), or an error
     ///
     /// # Errors
     ///
@@ -110,6 +123,8 @@ pub trait ICRC3Interface {
     /// * The prepared transaction is invalid
     /// * The transaction has become a duplicate since preparation
     /// * The system is now throttling transactions
+    /// * The prepared transaction expired, with `Icrc3Error::PreparedTransactionExpired`
+    /// * The local archive is full, with `Icrc3Error::LocalArchiveFull`
     fn commit_prepared_transaction<T: TransactionType>(
         &mut self,
         transaction: T,
@@ -125,6 +140,9 @@ pub trait ICRC3Interface {
 
     /// Retrieves blocks from the blockchain.
     ///
+    /// Requests never trap: each range is clamped to the chain tip and to
+    /// `max_blocks_per_response` blocks, and empty ranges are skipped.
+    ///
     /// # Arguments
     ///
     /// * `args` - A vector of `GetBlocksRequest` specifying which blocks to retrieve
@@ -148,8 +166,10 @@ pub trait ICRC3Interface {
     ///
     /// # Returns
     ///
-    /// An `ICRC3DataCertificate` containing the current tip certificate.
-    fn icrc3_get_tip_certificate(&self) -> ICRC3DataCertificate;
+    /// An `ICRC3DataCertificate` containing the current tip certificate, or `None` when
+    /// no data certificate is available, e.g. when called as an update or from another
+    /// canister.
+    fn icrc3_get_tip_certificate(&self) -> Option<ICRC3DataCertificate>;
 
     /// Lists the supported block types.
     ///
@@ -158,27 +178,72 @@ pub trait ICRC3Interface {
     /// A vector of `SupportedBlockType` containing information about supported block types.
     fn icrc3_supported_block_types(&self) -> Vec<SupportedBlockType>;
 
+    /// Returns the index, hash and timestamp of the last block.
+    ///
+    /// Answered from the in-memory tip, no block is read. The hash is the one
+    /// certified by the tip certificate.
+    ///
+    /// # Returns
+    ///
+    /// The tip of the chain, or `None` if the chain is empty.
+    fn icrc3_get_tip(&self) -> Option<TipInfo>;
+
+    /// Returns the number of blocks in the chain, archived blocks included.
+    ///
+    /// Answered from the in-memory block counter, no block is read.
+    fn icrc3_chain_length(&self) -> Nat;
+
+    /// Returns whether the block at `index` exists, locally or in an archive.
+    ///
+    /// Answered from the local archive keys and the archive registry, no block is
+    /// decoded. Indices beyond the tip return `false`.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the block
+    fn icrc3_has_block(&self, index: Nat) -> bool;
+
     /// Cleans up expired prepared transactions.
     ///
-    /// Removes prepared transactions that have been in the ledger for more than 24 hours.
+    /// Removes prepared transactions that were not committed within `prepared_transaction_ttl`.
     /// This is automatically called during transaction preparation, but can also be called manually.
     ///
     /// # Returns
     ///
     /// The number of expired prepared transactions that were removed
     fn cleanup_expired_prepared_transactions(&mut self) -> usize;
+
+    /// Lists the pending prepared transactions, oldest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The number of prepared transactions to skip and to return
+    fn list_prepared_transactions(
+        &self,
+        args: ListPreparedTransactionsArgs,
+    ) -> Vec<PreparedTransactionInfo>;
+
+    /// Updates the cycle top-up configuration of the archive canisters.
+    ///
+    /// # Arguments
+    ///
+    /// * `funding_config` - The new funding configuration
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Icrc3Error>` - An error if the configuration is invalid
+    fn update_funding_config(&mut self, funding_config: FundingConfig) -> Result<(), Icrc3Error>;
 }
 
 impl ICRC3Interface for ICRC3 {
     fn add_transaction<T: TransactionType>(&mut self, transaction: T) -> Result<u64, Icrc3Error> {
-        let now = ic_cdk::api::time() as u128;
+        let mut transaction_as_icrc3: ICRC3Value = transaction.clone().into();
+        check_transaction_limits(&transaction_as_icrc3, &self.icrc3_config.constants)?;
 
-        let timestamp: u128 = if let Some(timestamp) = transaction.timestamp() {
-            let ts = timestamp as u128;
-            ts
-        } else {
-            now
-        };
+        let now = runtime::time() as u128;
+        let timestamp =
+            ingestion_timestamp(&transaction, Nanos(now as u64), self.transaction_window())?.get()
+                as u128;
 
         let num_pruned = self.purge_old_transactions(now);
 
@@ -188,14 +253,8 @@ impl ICRC3Interface for ICRC3 {
             return Err(Icrc3Error::Icrc3Error("Transaction throttled".to_string()));
         }
 
-        match transaction.validate_transaction_fields() {
-            Ok(_) => {}
-            Err(e) => {
-                return Err(Icrc3Error::Icrc3Error(e));
-            }
-        }
-
-        let mut transaction_as_icrc3: ICRC3Value = transaction.clone().into();
+        self.check_transaction_fields(&transaction, &transaction_as_icrc3)
+            .map_err(Icrc3Error::Icrc3Error)?;
 
         self.add_phash(&mut transaction_as_icrc3);
 
@@ -232,52 +291,54 @@ impl ICRC3Interface for ICRC3 {
             }
             if existing_tx_clone.clone().hash().as_slice() == transaction_hash.as_slice() {
                 return Err(Icrc3Error::DuplicateTransaction {
-                    duplicate_of: self.next_index - i as u64 - 1,
+                    duplicate_of: self.duplicate_of(i),
                 });
             }
         }
 
-        let original_ledger_length = self.ledger.len();
-        let original_next_index = self.next_index;
-        let original_last_phash = self.last_phash.clone();
-
-        self.ledger.push_back(checked_transaction.clone());
-        self.next_index += 1;
+        let mut block_transaction = checked_transaction.clone();
+        self.add_rec(&mut block_transaction);
+        self.add_ver(&mut block_transaction);
+        let block_timestamp = self.order_timestamp(&mut block_transaction, timestamp)?;
 
         let block = DefaultBlock::from_transaction(
             self.blockchain.last_hash,
-            checked_transaction,
-            timestamp,
+            block_transaction,
+            block_timestamp,
         );
 
         let block_hash = DefaultBlock::block_hash(&block.clone().encode());
-        self.last_phash = Some(ByteBuf::from(block_hash.into_bytes()));
+        let memo = MemoIndex::memo_of(&block.transaction).cloned();
 
-        match self.blockchain.add_block(block) {
-            Ok(_) => (),
-            Err(e) => {
-                self.ledger.truncate(original_ledger_length);
-                self.next_index = original_next_index;
-                self.last_phash = original_last_phash;
+        // The block is appended before anything else is updated, so that a rejected
+        // block leaves no trace in the ledger, the counters or the last hash.
+        let block_index = self.append_block(block)?;
+        self.timestamp_clamps.record(timestamp, block_timestamp);
 
-                return Err(Icrc3Error::Icrc3Error(e));
-            }
-        }
+        self.push_to_ledger(checked_transaction);
+        self.last_phash = Some(ByteBuf::from(block_hash.into_bytes()));
+        self.set_ledger_block_index(self.ledger.len() - 1, block_index);
+        self.index_memo(block_index, memo);
+        self.refresh_certified_data();
+        self.transaction_rate.record(now as u64, 1);
+
+        self.enforce_dedup_window_limit(now);
+        self.enforce_dedup_entries_limit(now);
 
-        Ok(self.next_index)
+        Ok(block_index)
     }
 
     fn prepare_transaction<T: TransactionType>(
         &mut self,
         transaction: T,
     ) -> prepare_transaction::Response {
-        let now = ic_cdk::api::time() as u128;
+        let mut transaction_as_icrc3: ICRC3Value = transaction.clone().into();
+        check_transaction_limits(&transaction_as_icrc3, &self.icrc3_config.constants)?;
 
-        let timestamp: u128 = if let Some(timestamp) = transaction.timestamp() {
-            timestamp.into()
-        } else {
-            now
-        };
+        let now = runtime::time() as u128;
+        let timestamp =
+            ingestion_timestamp(&transaction, Nanos(now as u64), self.transaction_window())?.get()
+                as u128;
 
         let num_pruned = self.purge_old_transactions(now);
 
@@ -287,14 +348,8 @@ impl ICRC3Interface for ICRC3 {
             return Err(Icrc3Error::Icrc3Error("Transaction throttled".to_string()));
         }
 
-        match transaction.validate_transaction_fields() {
-            Ok(_) => {}
-            Err(e) => {
-                return Err(Icrc3Error::Icrc3Error(e));
-            }
-        }
-
-        let mut transaction_as_icrc3: ICRC3Value = transaction.clone().into();
+        self.check_transaction_fields(&transaction, &transaction_as_icrc3)
+            .map_err(Icrc3Error::Icrc3Error)?;
 
         self.add_phash(&mut transaction_as_icrc3);
 
@@ -326,18 +381,21 @@ impl ICRC3Interface for ICRC3 {
             }
             if existing_tx_clone.clone().hash().as_slice() == transaction_hash.as_slice() {
                 return Err(Icrc3Error::DuplicateTransaction {
-                    duplicate_of: self.next_index - i as u64 - 1,
+                    duplicate_of: self.duplicate_of(i),
                 });
             }
         }
 
-        self.ledger.push_back(checked_transaction.clone());
+        self.push_to_ledger(checked_transaction.clone());
         let transaction_hash_string = hex::encode(&transaction_hash);
         self.add_prepared_transaction(transaction_hash_string, timestamp as u64);
+        self.enforce_dedup_window_limit(now);
+        self.enforce_dedup_entries_limit(now);
 
         Ok(prepare_transaction::PreparedTransaction {
             transaction_hash,
             timestamp,
+            expires_at_ns: self.prepared_transaction_expires_at(now as u64),
         })
     }
 
@@ -352,7 +410,10 @@ impl ICRC3Interface for ICRC3 {
 
         let basic_transaction = GlobalTransaction::new(transaction_as_icrc3);
 
-        let icrc3_transaction = ICRC3Value::from(basic_transaction);
+        let checked_transaction = ICRC3Value::from(basic_transaction);
+        let mut icrc3_transaction = checked_transaction.clone();
+        self.add_rec(&mut icrc3_transaction);
+        self.add_ver(&mut icrc3_transaction);
 
         let transaction_hash = transaction.tx().hash().to_vec();
 
@@ -363,35 +424,75 @@ impl ICRC3Interface for ICRC3 {
             .iter()
             .position(|(hash, _)| hash == &transaction_hash_string);
 
-        if let Some(index) = prepared_transaction {
-            let (_, prepared_timestamp) = self.prepared_transactions[index];
-            if prepared_timestamp != timestamp as u64 {
-                return Err(Icrc3Error::Icrc3Error(
-                    "Transaction timestamp mismatch".to_string(),
-                ));
+        let Some(index) = prepared_transaction else {
+            if let Some(expired_at) = self.expired_prepared_transaction(&transaction_hash_string) {
+                return Err(Icrc3Error::PreparedTransactionExpired { expired_at });
             }
-
-            self.prepared_transactions.remove(index);
-        } else {
             return Err(Icrc3Error::Icrc3Error(
                 "Transaction not found in prepared transactions".to_string(),
             ));
+        };
+        let (_, prepared_timestamp) = self.prepared_transactions[index];
+        // The cleanup job may not have run since the transaction expired.
+        let prepared_at =
+            self.prepared_transaction_prepared_at(&transaction_hash_string, prepared_timestamp);
+        let expired_at = self.prepared_transaction_expires_at(prepared_at);
+        if expired_at <= runtime::time() {
+            return Err(Icrc3Error::PreparedTransactionExpired { expired_at });
+        }
+        if prepared_timestamp != timestamp as u64 {
+            return Err(Icrc3Error::Icrc3Error(
+                "Transaction timestamp mismatch".to_string(),
+            ));
         }
 
         // Add block to blockchain
-        let block =
-            DefaultBlock::from_transaction(self.blockchain.last_hash, icrc3_transaction, timestamp);
+        let block_timestamp = self.order_timestamp(&mut icrc3_transaction, timestamp)?;
+        let block = DefaultBlock::from_transaction(
+            self.blockchain.last_hash,
+            icrc3_transaction,
+            block_timestamp,
+        );
 
         let block_hash = DefaultBlock::block_hash(&block.clone().encode());
+        let memo = MemoIndex::memo_of(&block.transaction).cloned();
+
+        // The transaction stays prepared until its block is appended, so that it
+        // can be committed again when the block is rejected.
+        let block_index = self.append_block(block)?;
+        self.timestamp_clamps.record(timestamp, block_timestamp);
+        self.prepared_transactions.remove(index);
+        self.prepared_at.remove(&transaction_hash_string);
         self.last_phash = Some(ByteBuf::from(block_hash.into_bytes()));
-
-        return match self.blockchain.add_block(block) {
-            Ok(index) => {
-                self.next_index = index + 1;
-                Ok(index)
+        self.index_memo(block_index, memo);
+        self.refresh_certified_data();
+        self.transaction_rate.record(runtime::time(), 1);
+
+        // The committed transaction replaces the provisional entry pushed by
+        // prepare_transaction, or is pushed as add_transaction does when that
+        // entry is gone, so that it is deduplicated and counted for throttling.
+        let position = self.ledger.iter().position(|existing_tx| {
+            let mut existing_tx = existing_tx.clone();
+            if let ICRC3Value::Map(ref mut existing_map) = existing_tx {
+                existing_map.remove("phash");
+            }
+            existing_tx.hash().as_slice() == transaction_hash.as_slice()
+        });
+        let position = match position {
+            Some(position) => {
+                self.replace_ledger_entry(position, checked_transaction);
+                position
+            }
+            None => {
+                self.push_to_ledger(checked_transaction);
+                self.ledger.len() - 1
             }
-            Err(e) => Err(Icrc3Error::Icrc3Error(e)),
         };
+        self.set_ledger_block_index(position, block_index);
+        let now = runtime::time() as u128;
+        self.enforce_dedup_window_limit(now);
+        self.enforce_dedup_entries_limit(now);
+        Ok(block_index)
     }
 
     fn icrc3_get_archives(&self) -> Vec<ICRC3ArchiveInfo> {
@@ -416,31 +517,33 @@ impl ICRC3Interface for ICRC3 {
         args: Vec<GetBlocksRequest>,
     ) -> crate::types::icrc3_get_blocks::Response {
         let mut response = GetBlocksResult {
-            log_length: Nat::from(self.next_index),
+            log_length: Nat::from(self.chain_length()),
             blocks: vec![],
             archived_blocks: vec![],
         };
 
+        let max_length =
+            u64::try_from(self.icrc3_config.constants.max_blocks_per_response).unwrap_or(u64::MAX);
+
         for arg in args {
-            let start: u64 = arg.start.0.try_into().unwrap();
-            let length: u64 = arg.length.0.try_into().unwrap();
+            let range = requested_block_range(&arg, self.chain_length(), max_length);
+            if range.is_empty() {
+                continue;
+            }
 
-            let mut current_start = start;
+            let mut current_start = range.start;
             let mut current_length = 0u64;
             let mut current_canister = None;
 
-            for i in start..start + length {
-                if i >= self.archived_chain_length() as u64 {
-                    let block = self.blockchain.get_block(i);
-
-                    if let Some(block) = block {
-                        let default_block = DefaultBlock::decode(block).unwrap();
-                        response.blocks.push(BlockWithId {
-                            id: Nat::from(i),
-                            block: default_block.transaction,
-                        });
-                        continue;
-                    }
+            for i in range {
+                // Prefer the local copy, which may still exist for an archived block.
+                if let Some(block) = self.blockchain.get_block(i) {
+                    let default_block = DefaultBlock::decode(block).unwrap();
+                    response.blocks.push(BlockWithId {
+                        id: Nat::from(i),
+                        block: default_block.transaction,
+                    });
+                    continue;
                 }
 
                 let block_canister_id = self.blockchain.get_block_canister_id(i);
@@ -454,16 +557,12 @@ impl ICRC3Interface for ICRC3 {
                         }
                         _ => {
                             if let Some(current_id) = current_canister {
-                                response.archived_blocks.push(ArchivedBlocks {
-                                    args: vec![GetBlocksRequest {
-                                        start: Nat::from(current_start),
-                                        length: Nat::from(current_length),
-                                    }],
-                                    callback: QueryArchiveFn::new(
-                                        current_id,
-                                        "icrc3_get_blocks".to_string(),
-                                    ),
-                                });
+                                push_archived_range(
+                                    &mut response.archived_blocks,
+                                    current_id,
+                                    current_start,
+                                    current_length,
+                                );
                             }
 
                             current_start = i;
@@ -479,13 +578,12 @@ impl ICRC3Interface for ICRC3 {
             }
 
             if let Some(current_id) = current_canister {
-                response.archived_blocks.push(ArchivedBlocks {
-                    args: vec![GetBlocksRequest {
-                        start: Nat::from(current_start),
-                        length: Nat::from(current_length),
-                    }],
-                    callback: QueryArchiveFn::new(current_id, "icrc3_get_blocks".to_string()),
-                });
+                push_archived_range(
+                    &mut response.archived_blocks,
+                    current_id,
+                    current_start,
+                    current_length,
+                );
             }
         }
 
@@ -496,13 +594,15 @@ impl ICRC3Interface for ICRC3 {
         self.icrc3_config.constants.clone()
     }
 
-    fn icrc3_get_tip_certificate(&self) -> ICRC3DataCertificate {
-        let certificate = ic_cdk::api::data_certificate().expect("No data certificate available");
+    fn icrc3_get_tip_certificate(&self) -> Option<ICRC3DataCertificate> {
+        let certificate = runtime::data_certificate()?;
 
-        ICRC3DataCertificate {
+        Some(ICRC3DataCertificate {
             certificate: certificate.into(),
-            hash_tree: self.get_hash_tree().into(),
-        }
+            hash_tree: serde_cbor::to_vec(&self.tip_hash_tree().as_hash_tree())
+                .expect("Failed to encode the hash tree")
+                .into(),
+        })
     }
 
     fn icrc3_supported_block_types(&self) -> Vec<SupportedBlockType> {
@@ -516,8 +616,1685 @@ impl ICRC3Interface for ICRC3 {
             .collect()
     }
 
+    fn icrc3_get_tip(&self) -> Option<TipInfo> {
+        let last_hash = self.blockchain.last_hash?;
+        Some(TipInfo {
+            index: Nat::from(self.last_block_index()?),
+            block_hash: ByteBuf::from(last_hash.as_slice().to_vec()),
+            timestamp_ns: Nat::from(self.blockchain.last_timestamp),
+        })
+    }
+
+    fn icrc3_chain_length(&self) -> Nat {
+        Nat::from(self.chain_length())
+    }
+
+    fn icrc3_has_block(&self, index: Nat) -> bool {
+        match u64::try_from(index.0) {
+            Ok(index) => index < self.chain_length() && self.blockchain.has_block(index),
+            Err(_) => false,
+        }
+    }
+
     fn cleanup_expired_prepared_transactions(&mut self) -> usize {
-        let now = ic_cdk::api::time() as u128;
+        let now = runtime::time() as u128;
         self.cleanup_expired_prepared_transactions(now)
     }
+
+    fn list_prepared_transactions(
+        &self,
+        args: ListPreparedTransactionsArgs,
+    ) -> Vec<PreparedTransactionInfo> {
+        self.prepared_transactions
+            .iter()
+            .skip(args.offset as usize)
+            .take(args.limit as usize)
+            .map(|(hash_hex, timestamp)| {
+                let prepared_at = self.prepared_transaction_prepared_at(hash_hex, *timestamp);
+                PreparedTransactionInfo {
+                    hash_hex: hash_hex.clone(),
+                    prepared_at_ns: prepared_at,
+                    expires_at_ns: self.prepared_transaction_expires_at(prepared_at),
+                }
+            })
+            .collect()
+    }
+
+    fn update_funding_config(&mut self, funding_config: FundingConfig) -> Result<(), Icrc3Error> {
+        funding_config.validate().map_err(Icrc3Error::Icrc3Error)?;
+
+        self.blockchain
+            .archive_canister_manager
+            .write()
+            .map_err(|e| {
+                Icrc3Error::Icrc3Error(format!("Failed to acquire archive manager lock: {}", e))
+            })?
+            .set_funding_config(&funding_config);
+
+        trace(format!("update_funding_config: {:?}", funding_config));
+        self.icrc3_config.funding_config = Some(funding_config);
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "host-test"))]
+mod host_tests {
+    use super::*;
+    use crate::config::{ICRC3Config, ICRC3Properties};
+    use crate::icrc3::{LOCAL_ARCHIVE_FULL_RETRY_AFTER, PERMITTED_DRIFT, TRANSACTION_RATE_HORIZON};
+    use crate::runtime::host;
+    use crate::timestamp_ordering::{TimestampOrdering, TimestampOrderingMetrics};
+    use ic_certification::Certificate;
+    use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    const START_TIME_NANOS: u64 = 1_700_000_000_000_000_000;
+
+    #[derive(Clone)]
+    struct TestTransaction {
+        timestamp: u64,
+        sender: String,
+        created_at_time: Option<u64>,
+        memo: Option<ByteBuf>,
+    }
+
+    impl TestTransaction {
+        fn now(sender: &str) -> Self {
+            Self {
+                timestamp: runtime::time(),
+                sender: sender.to_string(),
+                created_at_time: None,
+                memo: None,
+            }
+        }
+
+        fn with_memo(sender: &str, memo: &[u8]) -> Self {
+            Self {
+                memo: Some(ByteBuf::from(memo.to_vec())),
+                ..Self::now(sender)
+            }
+        }
+
+        fn created_at(sender: &str, created_at_time: u64) -> Self {
+            Self {
+                created_at_time: Some(created_at_time),
+                ..Self::now(sender)
+            }
+        }
+    }
+
+    impl TransactionType for TestTransaction {
+        fn validate_transaction_fields(&self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn timestamp(&self) -> Option<u64> {
+            Some(self.timestamp)
+        }
+
+        fn created_at_time(&self) -> Option<u64> {
+            self.created_at_time
+        }
+
+        fn tx(&self) -> ICRC3Value {
+            self.clone().into()
+        }
+
+        fn block_type(&self) -> String {
+            "btype_test".to_string()
+        }
+    }
+
+    impl From<TestTransaction> for ICRC3Value {
+        fn from(tx: TestTransaction) -> Self {
+            let mut map = BTreeMap::new();
+            map.insert(
+                "btype".to_string(),
+                ICRC3Value::Text("btype_test".to_string()),
+            );
+            map.insert(
+                "timestamp".to_string(),
+                ICRC3Value::Nat(Nat::from(tx.timestamp)),
+            );
+            map.insert("sender".to_string(), ICRC3Value::Text(tx.sender));
+            if let Some(created_at_time) = tx.created_at_time {
+                map.insert(
+                    "ts".to_string(),
+                    ICRC3Value::Nat(Nat::from(created_at_time)),
+                );
+            }
+            if let Some(memo) = tx.memo {
+                map.insert("memo".to_string(), ICRC3Value::Blob(memo));
+            }
+            ICRC3Value::Map(map)
+        }
+    }
+
+    fn setup(constants: ICRC3Properties) -> ICRC3 {
+        host::set_mock_time(START_TIME_NANOS);
+        host::set_canister_self(candid::Principal::from_slice(&[1]));
+        host::set_caller(candid::Principal::anonymous());
+        host::set_controllers(vec![]);
+        host::set_data_certificate(None);
+
+        ICRC3::new(ICRC3Config {
+            supported_blocks: vec![SupportedBlockType {
+                block_type: "btype_test".to_string(),
+                url: "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-3/README.md".to_string(),
+            }],
+            constants,
+            funding_config: None,
+            block_transform: None,
+            commit_hash: Some("commit_hash".to_string()),
+            archive_test_mode: false,
+            custom_block_types: vec![],
+            external_archives: vec![],
+            genesis_parent_hash: None,
+            genesis_parent_ref: None,
+            validation_modes: BTreeMap::new(),
+        })
+    }
+
+    fn get_blocks(icrc3: &ICRC3, start: u64, length: u64) -> GetBlocksResult {
+        icrc3.icrc3_get_blocks(vec![GetBlocksRequest {
+            start: Nat::from(start),
+            length: Nat::from(length),
+        }])
+    }
+
+    // Ported from test_insert_transaction::test_multiple_transactions.
+    #[test]
+    fn test_multiple_transactions() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+
+        for i in 0..10 {
+            icrc3
+                .add_transaction(TestTransaction::now(&format!("sender-{i}")))
+                .unwrap();
+            host::advance_mock_time(Duration::from_secs(2));
+        }
+
+        let result = get_blocks(&icrc3, 0, 100);
+        assert_eq!(result.log_length, 10u64);
+        assert_eq!(result.blocks.len(), 10);
+        assert!(result.archived_blocks.is_empty());
+        for (i, block) in result.blocks.iter().enumerate() {
+            assert_eq!(block.id, i as u64);
+        }
+    }
+
+    #[test]
+    fn test_get_blocks_bounds_pathological_requests() {
+        let mut icrc3 = setup(ICRC3Properties {
+            max_blocks_per_response: 3,
+            ..ICRC3Properties::default()
+        });
+
+        for i in 0..5 {
+            icrc3
+                .add_transaction(TestTransaction::now(&format!("sender-{i}")))
+                .unwrap();
+            host::advance_mock_time(Duration::from_secs(2));
+        }
+
+        let huge = Nat::from(1u128 << 80);
+        let ids = |args: Vec<(Nat, Nat)>| -> Vec<u64> {
+            let result = icrc3.icrc3_get_blocks(
+                args.into_iter()
+                    .map(|(start, length)| GetBlocksRequest { start, length })
+                    .collect(),
+            );
+            assert_eq!(result.log_length, 5u64);
+            assert!(result.archived_blocks.is_empty());
+            result
+                .blocks
+                .iter()
+                .map(|block| u64::try_from(block.id.0.clone()).unwrap())
+                .collect()
+        };
+
+        // Lengths beyond u64::MAX or overflowing start + length are capped.
+        assert_eq!(ids(vec![(Nat::from(0u64), huge.clone())]), vec![0, 1, 2]);
+        assert_eq!(
+            ids(vec![(Nat::from(2u64), Nat::from(u64::MAX))]),
+            vec![2, 3, 4]
+        );
+        // Starts beyond the tip and empty ranges are skipped.
+        assert!(ids(vec![(huge.clone(), huge.clone())]).is_empty());
+        assert!(ids(vec![(Nat::from(u64::MAX), Nat::from(u64::MAX))]).is_empty());
+        assert!(ids(vec![(Nat::from(1u64), Nat::from(0u64))]).is_empty());
+        // The cap applies to each request.
+        assert_eq!(
+            ids(vec![
+                (huge.clone(), Nat::from(1u64)),
+                (Nat::from(0u64), Nat::from(0u64)),
+                (Nat::from(3u64), huge),
+                (Nat::from(0u64), Nat::from(u64::MAX)),
+            ]),
+            vec![3, 4, 0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn test_chain_length_and_has_block() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+        assert_eq!(icrc3.icrc3_chain_length(), 0u64);
+        assert!(!icrc3.icrc3_has_block(Nat::from(0u64)));
+
+        for i in 0..4 {
+            icrc3
+                .add_transaction(TestTransaction::now(&format!("sender-{i}")))
+                .unwrap();
+            host::advance_mock_time(Duration::from_secs(2));
+        }
+
+        assert_eq!(icrc3.icrc3_chain_length(), 4u64);
+        for index in 0..4u64 {
+            assert!(icrc3.icrc3_has_block(Nat::from(index)));
+        }
+        assert!(!icrc3.icrc3_has_block(Nat::from(4u64)));
+        assert!(!icrc3.icrc3_has_block(Nat::from(u128::MAX) * 2u64));
+
+        // Blocks 0 and 1 moved to an archive canister: they are found through the
+        // registry once removed from the local archive.
+        for index in 0..2u64 {
+            icrc3.blockchain.local_archive.remove(&index);
+        }
+        assert!(!icrc3.icrc3_has_block(Nat::from(0u64)));
+        icrc3
+            .blockchain
+            .archive_canister_manager
+            .write()
+            .unwrap()
+            .canisters_by_block_offset
+            .push((0, candid::Principal::from_slice(&[2])));
+        icrc3.blockchain.archived_chain_length = 2;
+
+        assert_eq!(icrc3.icrc3_chain_length(), 4u64);
+        for index in 0..4u64 {
+            assert!(icrc3.icrc3_has_block(Nat::from(index)));
+        }
+        // The registry has no upper bound, the tip still applies.
+        assert!(!icrc3.icrc3_has_block(Nat::from(4u64)));
+    }
+
+    #[test]
+    fn test_memo_index_finds_local_blocks() {
+        let mut icrc3 = setup(ICRC3Properties {
+            index_memos: true,
+            ..ICRC3Properties::default()
+        });
+
+        for (i, memo) in [Some(b"a"), Some(b"b"), None, Some(b"a")]
+            .into_iter()
+            .enumerate()
+        {
+            let sender = format!("sender-{i}");
+            let transaction = match memo {
+                Some(memo) => TestTransaction::with_memo(&sender, memo),
+                None => TestTransaction::now(&sender),
+            };
+            icrc3.add_transaction(transaction).unwrap();
+            host::advance_mock_time(Duration::from_millis(10));
+        }
+        let prepared = icrc3
+            .prepare_transaction(TestTransaction::with_memo("sender-4", b"a"))
+            .unwrap();
+        icrc3
+            .commit_prepared_transaction(
+                TestTransaction::with_memo("sender-4", b"a"),
+                prepared.timestamp,
+            )
+            .unwrap();
+
+        let find = |icrc3: &ICRC3, memo: &[u8], max| -> Vec<u64> {
+            icrc3
+                .find_blocks_by_memo(memo, max)
+                .into_iter()
+                .map(|index| index.0.try_into().unwrap())
+                .collect()
+        };
+        assert_eq!(find(&icrc3, b"a", 10), vec![0, 3, 4]);
+        assert_eq!(find(&icrc3, b"a", 2), vec![0, 3]);
+        assert_eq!(find(&icrc3, b"b", 10), vec![1]);
+        assert!(find(&icrc3, b"c", 10).is_empty());
+
+        // The index is not part of the blocks.
+        let blocks = get_block_maps(&icrc3, 5);
+        assert!(blocks.iter().all(|block| !block.contains_key("memo_index")));
+
+        // Blocks 0 and 1 moved to an archive canister.
+        for index in 0..2u64 {
+            icrc3.blockchain.local_archive.remove(&index);
+        }
+        icrc3.blockchain.archived_chain_length = 2;
+        icrc3.prune_memo_index();
+        assert_eq!(find(&icrc3, b"a", 10), vec![3, 4]);
+        assert!(find(&icrc3, b"b", 10).is_empty());
+        assert_eq!(icrc3.memo_index.len(), 2);
+
+        // Disabling the index drops it, enabling it again indexes the local blocks.
+        let mut config = icrc3.icrc3_config.clone();
+        config.constants.index_memos = false;
+        icrc3.apply_config(config.clone()).unwrap();
+        assert!(find(&icrc3, b"a", 10).is_empty());
+        config.constants.index_memos = true;
+        icrc3.apply_config(config).unwrap();
+        assert_eq!(find(&icrc3, b"a", 10), vec![3, 4]);
+    }
+
+    #[test]
+    fn test_memos_are_not_indexed_by_default() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+        icrc3
+            .add_transaction(TestTransaction::with_memo("a", b"a"))
+            .unwrap();
+        assert!(icrc3.find_blocks_by_memo(b"a", 10).is_empty());
+        assert!(icrc3.memo_index.is_empty());
+    }
+
+    #[test]
+    fn test_thash_lookup_finds_local_blocks() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+
+        let mut hashes = vec![];
+        for i in 0..3 {
+            let sender = format!("sender-{i}");
+            let prepared = icrc3
+                .prepare_transaction(TestTransaction::now(&sender))
+                .unwrap();
+            icrc3
+                .commit_prepared_transaction(TestTransaction::now(&sender), prepared.timestamp)
+                .unwrap();
+            hashes.push(prepared.transaction_hash);
+            host::advance_mock_time(Duration::from_millis(10));
+        }
+
+        let blocks = get_blocks(&icrc3, 0, 3).blocks;
+        for (block_index, hash) in hashes.iter().enumerate() {
+            let lookup = icrc3.thash_lookup(ByteBuf::from(hash.clone()));
+            assert_eq!(lookup.local_block, Some(blocks[block_index].clone()));
+            assert!(lookup.archives.is_empty());
+        }
+        assert_eq!(
+            icrc3.thash_lookup(ByteBuf::from(vec![0; 32])).local_block,
+            None
+        );
+        assert_eq!(icrc3.thash_index.len(), 3);
+
+        // Block 0 moved to an archive canister.
+        icrc3.blockchain.local_archive.remove(&0);
+        icrc3.blockchain.archived_chain_length = 1;
+        icrc3.prune_thash_index();
+        assert_eq!(icrc3.thash_index.len(), 2);
+        let lookup = |icrc3: &ICRC3, hash: &Vec<u8>| {
+            icrc3.thash_lookup(ByteBuf::from(hash.clone())).local_block
+        };
+        assert_eq!(lookup(&icrc3, &hashes[0]), None);
+        assert_eq!(lookup(&icrc3, &hashes[2]), Some(blocks[2].clone()));
+
+        // A state saved before the index existed indexes its local blocks again.
+        icrc3.thash_index.clear();
+        icrc3.rebuild_thash_index();
+        assert_eq!(icrc3.thash_index.len(), 2);
+        assert_eq!(lookup(&icrc3, &hashes[1]), Some(blocks[1].clone()));
+    }
+
+    // Ported from test_insert_transaction::test_throttling.
+    #[test]
+    fn test_throttling() {
+        let mut constants = ICRC3Properties::default();
+        constants.max_transactions_in_window = 10_u64.into();
+        let mut icrc3 = setup(constants);
+
+        let mut throttled = 0;
+        for i in 0..15 {
+            match icrc3.add_transaction(TestTransaction::now(&format!("sender-{i}"))) {
+                Ok(_) => {}
+                Err(Icrc3Error::Icrc3Error(e)) if e == "Transaction throttled" => throttled += 1,
+                Err(e) => panic!("unexpected error: {e}"),
+            }
+            host::advance_mock_time(Duration::from_millis(10));
+        }
+
+        assert_eq!(throttled, 10);
+        assert_eq!(get_blocks(&icrc3, 0, 100).blocks.len(), 5);
+    }
+
+    #[test]
+    fn test_dedup_entries_outlive_an_aggressive_throttling_window() {
+        let constants = ICRC3Properties {
+            tx_window: Duration::from_secs(1),
+            max_transactions_in_window: 4_u64.into(),
+            max_dedup_entries: Some(5),
+            ..ICRC3Properties::default()
+        };
+        let mut icrc3 = setup(constants.clone());
+        let original = TestTransaction::now("sender-0");
+        icrc3.add_transaction(original.clone()).unwrap();
+
+        // Throttling only sees the last second.
+        icrc3
+            .add_transaction(TestTransaction::now("sender-1"))
+            .unwrap();
+        assert!(matches!(
+            icrc3.add_transaction(TestTransaction::now("sender-2")),
+            Err(Icrc3Error::Icrc3Error(e)) if e == "Transaction throttled"
+        ));
+
+        // Long after the throttling window, the duplicate is still rejected.
+        host::advance_mock_time(Duration::from_secs(60));
+        assert!(matches!(
+            icrc3.add_transaction(original.clone()),
+            Err(Icrc3Error::DuplicateTransaction { duplicate_of }) if duplicate_of == 0u64
+        ));
+        let metrics = icrc3.dedup_window_metrics();
+        assert_eq!(metrics.dedup_entries, 2);
+        assert_eq!(metrics.throttle_window_entries, 0);
+        assert_eq!(metrics.max_dedup_entries, Some(5));
+
+        // Past max_dedup_entries, the oldest entries are purged.
+        for i in 3..7 {
+            host::advance_mock_time(Duration::from_secs(2));
+            icrc3
+                .add_transaction(TestTransaction::now(&format!("sender-{i}")))
+                .unwrap();
+        }
+        let metrics = icrc3.dedup_window_metrics();
+        assert_eq!(metrics.dedup_entries, 5);
+        assert_eq!(metrics.throttle_window_entries, 1);
+        assert_eq!(metrics.entries_limit_purge_count, 1);
+        assert_eq!(metrics.early_purge_count, 0);
+        // Its block timestamp is now behind the tip, but it is no duplicate anymore.
+        assert!(!matches!(
+            icrc3.add_transaction(original.clone()),
+            Err(Icrc3Error::DuplicateTransaction { .. })
+        ));
+
+        // Without max_dedup_entries, it left the window with the throttling one.
+        let mut icrc3_by_time = setup(ICRC3Properties {
+            max_dedup_entries: None,
+            ..constants
+        });
+        icrc3_by_time.add_transaction(original.clone()).unwrap();
+        host::advance_mock_time(Duration::from_secs(60));
+        assert!(icrc3_by_time.add_transaction(original).is_ok());
+    }
+
+    // Ported from test_insert_transaction::test_add_same_transaction_with_delay.
+    #[test]
+    fn test_add_same_transaction_with_delay() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+        let transaction = TestTransaction::now("sender");
+
+        assert!(icrc3.add_transaction(transaction.clone()).is_ok());
+
+        host::advance_mock_time(Duration::from_millis(1));
+        assert!(matches!(
+            icrc3.add_transaction(transaction.clone()),
+            Err(Icrc3Error::DuplicateTransaction { duplicate_of }) if duplicate_of == 0u64
+        ));
+
+        // The first transaction has left the deduplication window.
+        host::advance_mock_time(Duration::from_secs(5 * 60));
+        assert!(icrc3.add_transaction(transaction).is_ok());
+
+        assert_eq!(get_blocks(&icrc3, 0, 10).blocks.len(), 2);
+    }
+
+    #[test]
+    fn test_duplicate_of_is_the_original_block_index_after_purges() {
+        let mut icrc3 = setup(ICRC3Properties {
+            tx_window: Duration::from_secs(60),
+            max_transactions_to_purge: 10_u64.into(),
+            ..ICRC3Properties::default()
+        });
+
+        let transactions: Vec<_> = (0..5)
+            .map(|i| {
+                let transaction = TestTransaction::now(&format!("sender-{i}"));
+                icrc3.add_transaction(transaction.clone()).unwrap();
+                host::advance_mock_time(Duration::from_secs(30));
+                transaction
+            })
+            .collect();
+
+        // Blocks 0 to 2 have left the window and are purged by the next insert.
+        icrc3
+            .add_transaction(TestTransaction::now("sender-5"))
+            .unwrap();
+        assert_eq!(icrc3.ledger.len(), 3);
+
+        for (index, transaction) in transactions.into_iter().enumerate().skip(3) {
+            assert!(matches!(
+                icrc3.add_transaction(transaction),
+                Err(Icrc3Error::DuplicateTransaction { duplicate_of }) if duplicate_of == index as u64
+            ));
+        }
+    }
+
+    // Ported from test_insert_transaction::test_prepare_transaction_duplicate_immediate
+    // and test_prepare_transaction_cleanup_after_long_delay.
+    #[test]
+    fn test_prepare_transaction_duplicate_and_cleanup() {
+        let ttl = Duration::from_secs(60 * 60);
+        let mut icrc3 = setup(ICRC3Properties {
+            prepared_transaction_ttl: ttl,
+            ..ICRC3Properties::default()
+        });
+        let transaction = TestTransaction::now("sender");
+
+        let first = icrc3.prepare_transaction(transaction.clone()).unwrap();
+        assert_eq!(
+            first.expires_at_ns as u128,
+            first.timestamp + ttl.as_nanos()
+        );
+        assert!(matches!(
+            icrc3.prepare_transaction(transaction.clone()),
+            Err(Icrc3Error::DuplicateTransaction { .. })
+        ));
+
+        // 1.1 TTL later the prepared transaction has expired.
+        host::advance_mock_time(ttl.mul_f64(1.1));
+        assert_eq!(
+            ICRC3Interface::cleanup_expired_prepared_transactions(&mut icrc3),
+            1
+        );
+
+        let second = icrc3.prepare_transaction(transaction).unwrap();
+        assert_eq!(first.transaction_hash, second.transaction_hash);
+        assert_eq!(first.timestamp, second.timestamp);
+    }
+
+    #[test]
+    fn test_prepared_transaction_expires_after_its_prepare_time() {
+        let ttl = Duration::from_secs(60 * 60);
+        let mut icrc3 = setup(ICRC3Properties {
+            prepared_transaction_ttl: ttl,
+            ..ICRC3Properties::default()
+        });
+        // The caller sets a timestamp older than the TTL.
+        host::advance_mock_time(ttl * 2);
+        let transaction = TestTransaction {
+            timestamp: runtime::time() - (ttl * 2).as_nanos() as u64,
+            ..TestTransaction::now("sender")
+        };
+
+        let prepared = icrc3.prepare_transaction(transaction.clone()).unwrap();
+        assert_eq!(
+            prepared.expires_at_ns,
+            runtime::time() + ttl.as_nanos() as u64
+        );
+        assert_eq!(
+            ICRC3Interface::cleanup_expired_prepared_transactions(&mut icrc3),
+            0
+        );
+
+        host::advance_mock_time(ttl / 2);
+        assert!(icrc3
+            .commit_prepared_transaction(transaction, prepared.timestamp)
+            .is_ok());
+        assert!(icrc3.prepared_at.is_empty());
+    }
+
+    // Ported from test_insert_transaction::test_prepare_and_commit_workflow.
+    #[test]
+    fn test_prepare_and_commit_workflow() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+        let transaction = TestTransaction::now("sender");
+
+        let prepared = icrc3.prepare_transaction(transaction.clone()).unwrap();
+        assert!(matches!(
+            icrc3.commit_prepared_transaction(transaction.clone(), prepared.timestamp + 1),
+            Err(Icrc3Error::Icrc3Error(e)) if e == "Transaction timestamp mismatch"
+        ));
+
+        assert!(matches!(
+            icrc3.commit_prepared_transaction(transaction.clone(), prepared.timestamp),
+            Ok(0)
+        ));
+        assert_eq!(icrc3.prepared_transactions_count(), 0);
+        assert_eq!(get_blocks(&icrc3, 0, 10).blocks.len(), 1);
+
+        assert!(matches!(
+            icrc3.commit_prepared_transaction(transaction, prepared.timestamp),
+            Err(Icrc3Error::Icrc3Error(e)) if e == "Transaction not found in prepared transactions"
+        ));
+    }
+
+    #[test]
+    fn test_list_prepared_transactions() {
+        let mut icrc3 = setup(ICRC3Properties {
+            prepared_transactions_warning_threshold: Some(2),
+            ..ICRC3Properties::default()
+        });
+        let transactions: Vec<TestTransaction> = ["alice", "bob", "carol"]
+            .into_iter()
+            .map(TestTransaction::now)
+            .collect();
+        // The transactions are prepared a minute after their timestamp.
+        host::advance_mock_time(Duration::from_secs(60));
+        let prepared_at = runtime::time();
+        let mut prepared = vec![];
+        for transaction in &transactions {
+            prepared.push(icrc3.prepare_transaction(transaction.clone()).unwrap());
+        }
+        host::advance_mock_time(Duration::from_secs(1));
+
+        let listed = icrc3.list_prepared_transactions(ListPreparedTransactionsArgs {
+            limit: 10,
+            offset: 0,
+        });
+        assert_eq!(listed.len(), 3);
+        assert_eq!(
+            listed[0].hash_hex,
+            hex::encode(&prepared[0].transaction_hash)
+        );
+        assert_eq!(listed[0].prepared_at_ns, prepared_at);
+        assert_eq!(listed[0].expires_at_ns, prepared[0].expires_at_ns);
+
+        let page = icrc3.list_prepared_transactions(ListPreparedTransactionsArgs {
+            limit: 1,
+            offset: 1,
+        });
+        assert_eq!(page, listed[1..2].to_vec());
+
+        let metrics = icrc3.prepared_transactions_metrics();
+        assert_eq!(metrics.count, 3);
+        assert_eq!(metrics.oldest_age_ns, Some(1_000_000_000));
+        assert_eq!(metrics.warning_threshold, Some(2));
+
+        icrc3
+            .commit_prepared_transaction(transactions[0].clone(), prepared[0].timestamp)
+            .unwrap();
+        let listed_after_commit = icrc3.list_prepared_transactions(ListPreparedTransactionsArgs {
+            limit: 10,
+            offset: 0,
+        });
+        assert_eq!(listed_after_commit, listed[1..].to_vec());
+        assert_eq!(icrc3.prepared_transactions_metrics().count, 2);
+    }
+
+    #[test]
+    fn test_commit_after_expiry() {
+        let ttl = Duration::from_secs(60 * 60);
+        let mut icrc3 = setup(ICRC3Properties {
+            prepared_transaction_ttl: ttl,
+            ..ICRC3Properties::default()
+        });
+        let expired = TestTransaction::now("expired");
+        let prepared = icrc3.prepare_transaction(expired.clone()).unwrap();
+
+        // Expired, before and after the cleanup.
+        host::advance_mock_time(ttl);
+        for cleaned_up in [false, true] {
+            if cleaned_up {
+                ICRC3Interface::cleanup_expired_prepared_transactions(&mut icrc3);
+                assert_eq!(icrc3.prepared_transactions_count(), 0);
+            }
+            assert!(matches!(
+                icrc3.commit_prepared_transaction(expired.clone(), prepared.timestamp),
+                Err(Icrc3Error::PreparedTransactionExpired { expired_at })
+                    if expired_at == prepared.expires_at_ns
+            ));
+        }
+
+        // Distinguishable from a transaction that was never prepared.
+        let never_prepared = TestTransaction::now("never_prepared");
+        assert!(matches!(
+            icrc3.commit_prepared_transaction(never_prepared, prepared.timestamp),
+            Err(Icrc3Error::Icrc3Error(e)) if e == "Transaction not found in prepared transactions"
+        ));
+
+        // The expired hashes are forgotten one more TTL later.
+        host::advance_mock_time(ttl);
+        ICRC3Interface::cleanup_expired_prepared_transactions(&mut icrc3);
+        assert!(matches!(
+            icrc3.commit_prepared_transaction(expired, prepared.timestamp),
+            Err(Icrc3Error::Icrc3Error(_))
+        ));
+    }
+
+    #[test]
+    fn test_committed_transaction_is_deduplicated() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+        let transaction = TestTransaction::now("sender");
+
+        let prepared = icrc3.prepare_transaction(transaction.clone()).unwrap();
+        icrc3
+            .add_transaction(TestTransaction::now("other"))
+            .unwrap();
+        assert!(matches!(
+            icrc3.commit_prepared_transaction(transaction.clone(), prepared.timestamp),
+            Ok(1)
+        ));
+        assert_eq!(icrc3.ledger_len(), 2);
+        assert!(matches!(
+            icrc3.add_transaction(transaction.clone()),
+            Err(Icrc3Error::DuplicateTransaction { duplicate_of }) if duplicate_of == 1u64
+        ));
+
+        // The provisional entry may be gone by the time the transaction is
+        // committed, e.g. in a state saved by an older version.
+        let transaction = TestTransaction::now("late");
+        let prepared = icrc3.prepare_transaction(transaction.clone()).unwrap();
+        icrc3.ledger.pop_back();
+        icrc3.ledger_block_indices.pop_back();
+        icrc3.recompute_dedup_window_bytes();
+        icrc3
+            .add_transaction(TestTransaction::now("later"))
+            .unwrap();
+
+        assert!(matches!(
+            icrc3.commit_prepared_transaction(transaction.clone(), prepared.timestamp),
+            Ok(3)
+        ));
+        assert_eq!(icrc3.ledger_len(), 4);
+        assert!(matches!(
+            icrc3.add_transaction(transaction),
+            Err(Icrc3Error::DuplicateTransaction { duplicate_of }) if duplicate_of == 3u64
+        ));
+    }
+
+    #[test]
+    fn test_log_length_counts_committed_blocks_only() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+        for i in 0..2 {
+            icrc3
+                .add_transaction(TestTransaction::now(&format!("sender-{i}")))
+                .unwrap();
+        }
+        let transaction = TestTransaction::now("prepared");
+        let prepared = icrc3.prepare_transaction(transaction.clone()).unwrap();
+
+        let result = get_blocks(&icrc3, 0, 10);
+        assert_eq!(result.log_length, 2u64);
+        assert_eq!(result.blocks.len(), 2);
+        assert_eq!(icrc3.icrc3_chain_length(), 2u64);
+        assert_eq!(icrc3.icrc3_get_tip().unwrap().index, 1u64);
+        assert!(!icrc3.icrc3_has_block(Nat::from(2u64)));
+
+        icrc3
+            .commit_prepared_transaction(transaction, prepared.timestamp)
+            .unwrap();
+        let result = get_blocks(&icrc3, 0, 10);
+        assert_eq!(result.log_length, 3u64);
+        assert_eq!(result.blocks.len(), 3);
+        assert_eq!(icrc3.icrc3_get_tip().unwrap().index, 2u64);
+    }
+
+    #[test]
+    fn test_returned_ids_are_block_ids() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+        assert_eq!(icrc3.last_block_index(), None);
+
+        let mut transactions = vec![];
+        for i in 0..4u64 {
+            let transaction = TestTransaction::now(&format!("sender-{i}"));
+            let block_index = if i % 2 == 0 {
+                icrc3.add_transaction(transaction.clone()).unwrap()
+            } else {
+                let prepared = icrc3.prepare_transaction(transaction.clone()).unwrap();
+                icrc3
+                    .commit_prepared_transaction(transaction.clone(), prepared.timestamp)
+                    .unwrap()
+            };
+            assert_eq!(block_index, i);
+            assert_eq!(icrc3.last_block_index(), Some(block_index));
+            assert_eq!(icrc3.icrc3_get_tip().unwrap().index, block_index);
+            transactions.push((block_index, transaction));
+        }
+
+        for (block_index, transaction) in transactions {
+            let blocks = get_blocks(&icrc3, block_index, 1).blocks;
+            assert_eq!(blocks[0].id, block_index);
+            let ICRC3Value::Map(block) = &blocks[0].block else {
+                panic!("a block is a map");
+            };
+            assert_eq!(
+                block.get("sender"),
+                Some(&ICRC3Value::Text(transaction.sender.clone()))
+            );
+            assert!(matches!(
+                icrc3.add_transaction(transaction),
+                Err(Icrc3Error::DuplicateTransaction { duplicate_of }) if duplicate_of == block_index
+            ));
+        }
+    }
+
+    #[test]
+    fn test_transactions_per_sec() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+        assert_eq!(icrc3.transactions_per_sec(), 0.0);
+
+        for i in 0..2 {
+            icrc3
+                .add_transaction(TestTransaction::now(&format!("sender-{i}")))
+                .unwrap();
+        }
+        let transaction = TestTransaction::now("prepared");
+        let prepared = icrc3.prepare_transaction(transaction.clone()).unwrap();
+        icrc3
+            .commit_prepared_transaction(transaction, prepared.timestamp)
+            .unwrap();
+        assert_eq!(icrc3.transactions_per_sec(), 3.0 / 300.0);
+
+        host::set_mock_time(START_TIME_NANOS + TRANSACTION_RATE_HORIZON.as_nanos() as u64);
+        assert_eq!(icrc3.transactions_per_sec(), 0.0);
+    }
+
+    #[test]
+    fn test_rejected_block_leaves_state_untouched() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+        icrc3
+            .add_transaction(TestTransaction::now("sender-0"))
+            .unwrap();
+
+        // The local archive is full, the next block is rejected.
+        icrc3.blockchain.max_tx_local_stable_memory_size_bytes =
+            Some(icrc3.blockchain.local_archive_size as u128);
+        let ledger = icrc3.ledger.clone();
+        let chain_length = icrc3.chain_length();
+        let last_phash = icrc3.last_phash.clone();
+        let dedup_bytes = icrc3.dedup_window.bytes;
+        let certified_data = host::certified_data();
+
+        let transaction = TestTransaction::now("sender-1");
+        assert!(matches!(
+            icrc3.add_transaction(transaction.clone()),
+            Err(Icrc3Error::LocalArchiveFull { .. })
+        ));
+        assert_eq!(icrc3.ledger, ledger);
+        assert_eq!(icrc3.ledger_block_indices.len(), ledger.len());
+        assert_eq!(icrc3.chain_length(), chain_length);
+        assert_eq!(icrc3.last_phash, last_phash);
+        assert_eq!(icrc3.dedup_window.bytes, dedup_bytes);
+        assert_eq!(host::certified_data(), certified_data);
+
+        // The transaction is not taken for a duplicate once its block fits.
+        icrc3.blockchain.max_tx_local_stable_memory_size_bytes = None;
+        assert!(matches!(icrc3.add_transaction(transaction), Ok(1)));
+        let blocks = get_blocks(&icrc3, 0, 10).blocks;
+        assert_eq!(blocks.len(), 2);
+        let ICRC3Value::Map(block) = &blocks[1].block else {
+            panic!("a block is a map");
+        };
+        assert_eq!(
+            block.get("phash"),
+            last_phash.map(ICRC3Value::Blob).as_ref()
+        );
+    }
+
+    #[test]
+    fn test_admin_actions_are_recorded() {
+        use crate::audit::{principal_value, record_admin_action, ADMIN_BLOCK_TYPE};
+
+        let mut icrc3 = setup(ICRC3Properties::default());
+        assert_eq!(
+            record_admin_action(&mut icrc3, "add_recorder", vec![]),
+            None
+        );
+        assert_eq!(icrc3.chain_length(), 0);
+
+        let mut icrc3 = setup(ICRC3Properties {
+            audit_admin_actions: true,
+            ..ICRC3Properties::default()
+        });
+        assert!(icrc3
+            .icrc3_supported_block_types()
+            .iter()
+            .any(|b| b.block_type == ADMIN_BLOCK_TYPE));
+        assert!(icrc3
+            .block_schemas()
+            .iter()
+            .any(|s| s.btype == ADMIN_BLOCK_TYPE && !s.fields.is_empty()));
+
+        let admin = candid::Principal::from_slice(&[7]);
+        let recorder = candid::Principal::from_slice(&[8]);
+        host::set_caller(admin);
+        let index = record_admin_action(
+            &mut icrc3,
+            "add_recorder",
+            vec![("recorder", principal_value(recorder))],
+        );
+        assert_eq!(index, Some(0));
+
+        let blocks = get_blocks(&icrc3, 0, 10).blocks;
+        let ICRC3Value::Map(block) = &blocks[0].block else {
+            panic!("a block is a map");
+        };
+        assert_eq!(
+            block.get("btype"),
+            Some(&ICRC3Value::Text(ADMIN_BLOCK_TYPE.to_string()))
+        );
+        let Some(ICRC3Value::Map(tx)) = block.get("tx") else {
+            panic!("an admin block has a tx map");
+        };
+        assert_eq!(
+            tx.get("action"),
+            Some(&ICRC3Value::Text("add_recorder".to_string()))
+        );
+        assert_eq!(tx.get("caller"), Some(&principal_value(admin)));
+        assert_eq!(
+            tx.get("details"),
+            Some(&ICRC3Value::Map(BTreeMap::from([(
+                "recorder".to_string(),
+                principal_value(recorder)
+            )])))
+        );
+
+        // A failure to record is reported, not raised.
+        assert_eq!(record_admin_action(&mut icrc3, "", vec![]), None);
+        assert_eq!(icrc3.chain_length(), 1);
+    }
+
+    #[test]
+    fn test_anchored_chain_starts_with_a_genesis_block() {
+        use crate::genesis::GENESIS_BLOCK_TYPE;
+        use crate::verification::verify_linkage;
+
+        let anchor = [9; 32];
+        host::set_mock_time(START_TIME_NANOS);
+        let mut icrc3 = ICRC3::new(ICRC3Config {
+            supported_blocks: vec![SupportedBlockType {
+                block_type: "btype_test".to_string(),
+                url: "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-3/README.md".to_string(),
+            }],
+            genesis_parent_hash: Some(anchor),
+            genesis_parent_ref: Some("ryjl3-tyaaa-aaaaa-aaaba-cai:41".to_string()),
+            ..ICRC3Config::default()
+        });
+        icrc3
+            .add_transaction(TestTransaction::now("sender"))
+            .unwrap();
+        assert_eq!(icrc3.chain_length(), 2);
+
+        let blocks = get_blocks(&icrc3, 0, 10).blocks;
+        let ICRC3Value::Map(genesis) = &blocks[0].block else {
+            panic!("a block is a map");
+        };
+        assert_eq!(
+            genesis.get("btype"),
+            Some(&ICRC3Value::Text(GENESIS_BLOCK_TYPE.to_string()))
+        );
+        assert_eq!(
+            genesis.get("phash"),
+            Some(&ICRC3Value::Blob(ByteBuf::from(anchor.to_vec())))
+        );
+        let Some(ICRC3Value::Map(tx)) = genesis.get("tx") else {
+            panic!("a genesis block has a tx map");
+        };
+        assert_eq!(
+            tx.get("parent_ref"),
+            Some(&ICRC3Value::Text(
+                "ryjl3-tyaaa-aaaaa-aaaba-cai:41".to_string()
+            ))
+        );
+
+        let encoded: Vec<_> = (0..2)
+            .map(|id| (id, icrc3.blockchain.get_block(id).unwrap()))
+            .collect();
+        assert_eq!(
+            icrc3
+                .blockchain
+                .get_block(0)
+                .map(|block| DefaultBlock::decode(block)
+                    .unwrap()
+                    .parent_hash()
+                    .map(|hash| hash.into_bytes())),
+            Some(Some(anchor))
+        );
+        assert_eq!(verify_linkage(&encoded, Some(anchor)), Ok(()));
+        assert_eq!(
+            verify_linkage(&encoded, None),
+            Err("block 0: the first block has a parent hash".to_string())
+        );
+
+        // The anchor is kept by a config without one and cannot be replaced.
+        let mut config = icrc3.icrc3_config.clone();
+        config.genesis_parent_hash = None;
+        assert_eq!(icrc3.apply_config(config.clone()), Ok(()));
+        assert_eq!(icrc3.icrc3_config.genesis_parent_hash, Some(anchor));
+        config.genesis_parent_hash = Some([1; 32]);
+        assert!(icrc3.apply_config(config).is_err());
+    }
+
+    #[test]
+    fn test_validation_modes() {
+        use crate::transaction::{ICRC1Transaction, ICRC1TransactionData};
+        use crate::validation::ValidationMode;
+        use icrc_ledger_types::icrc1::account::Account;
+
+        // A mint with a `from` account.
+        let account = Account {
+            owner: candid::Principal::from_slice(&[3]),
+            subaccount: None,
+        };
+        let malformed_mint = ICRC1Transaction::new(
+            "1mint".to_string(),
+            START_TIME_NANOS,
+            Nat::from(0u64),
+            ICRC1TransactionData {
+                op: Some("mint".to_string()),
+                amount: Nat::from(1_000u64),
+                from: Some(account),
+                to: Some(account),
+                memo: None,
+                created_at_time: None,
+                fee: None,
+            },
+        );
+        let setup_with_mode = |mode| {
+            let mut icrc3 = setup(ICRC3Properties::default());
+            let mut config = icrc3.icrc3_config.clone();
+            config.supported_blocks.push(SupportedBlockType {
+                block_type: "1mint".to_string(),
+                url: "https://github.com/dfinity/ICRC-1/tree/main/standards/ICRC-3".to_string(),
+            });
+            config.validation_modes = BTreeMap::from([("1mint".to_string(), mode)]);
+            icrc3.apply_config(config).unwrap();
+            icrc3
+        };
+
+        let mut icrc3 = setup_with_mode(ValidationMode::Strict);
+        assert!(icrc3.add_transaction(malformed_mint.clone()).is_err());
+        assert_eq!(icrc3.chain_length(), 0);
+
+        let mut icrc3 = setup_with_mode(ValidationMode::Lenient);
+        assert!(icrc3.add_transaction(malformed_mint.clone()).is_ok());
+        assert_eq!(
+            icrc3.validation_metrics().lenient_violations,
+            BTreeMap::from([("1mint".to_string(), 1)])
+        );
+
+        let mut icrc3 = setup_with_mode(ValidationMode::Off);
+        assert!(icrc3.add_transaction(malformed_mint).is_ok());
+        assert!(icrc3.validation_metrics().lenient_violations.is_empty());
+    }
+
+    fn setup_with_timestamp_ordering(timestamp_ordering: TimestampOrdering) -> ICRC3 {
+        let mut icrc3 = setup(ICRC3Properties {
+            timestamp_ordering,
+            ..ICRC3Properties::default()
+        });
+        icrc3
+            .add_transaction(TestTransaction::now("ahead"))
+            .unwrap();
+        icrc3
+    }
+
+    /// A transaction stamped by a recorder whose clock is `lag` ns behind.
+    fn behind(lag: u64) -> TestTransaction {
+        TestTransaction {
+            timestamp: START_TIME_NANOS - lag,
+            ..TestTransaction::now("behind")
+        }
+    }
+
+    fn block_timestamp(icrc3: &ICRC3, id: u64) -> u128 {
+        DefaultBlock::decode(icrc3.blockchain.get_block(id).unwrap())
+            .unwrap()
+            .timestamp()
+    }
+
+    #[test]
+    fn test_strict_timestamp_ordering_rejects_blocks_older_than_the_tip() {
+        let mut icrc3 = setup_with_timestamp_ordering(TimestampOrdering::Strict);
+        assert!(icrc3.add_transaction(behind(0)).is_ok());
+        assert!(icrc3.add_transaction(behind(1)).is_err());
+        assert_eq!(icrc3.chain_length(), 2);
+        assert_eq!(
+            icrc3.timestamp_ordering_metrics(),
+            TimestampOrderingMetrics::default()
+        );
+    }
+
+    #[test]
+    fn test_clamp_to_tip_raises_older_blocks() {
+        let mut icrc3 = setup_with_timestamp_ordering(TimestampOrdering::ClampToTip);
+        assert!(matches!(icrc3.add_transaction(behind(1)), Ok(1)));
+        assert_eq!(block_timestamp(&icrc3, 1), START_TIME_NANOS as u128);
+        let blocks = get_blocks(&icrc3, 1, 1).blocks;
+        let ICRC3Value::Map(block) = &blocks[0].block else {
+            panic!("a block is a map");
+        };
+        assert_eq!(
+            block.get("orig_ts"),
+            Some(&ICRC3Value::Nat(Nat::from(START_TIME_NANOS - 1)))
+        );
+
+        // A prepared transaction is ordered when it is committed.
+        let prepared = icrc3.prepare_transaction(behind(5)).unwrap();
+        assert!(matches!(
+            icrc3.commit_prepared_transaction(behind(5), prepared.timestamp),
+            Ok(2)
+        ));
+        assert_eq!(block_timestamp(&icrc3, 2), START_TIME_NANOS as u128);
+        assert_eq!(
+            icrc3.timestamp_ordering_metrics(),
+            TimestampOrderingMetrics {
+                clamped_blocks: 2,
+                max_clamp_ns: 5,
+            }
+        );
+    }
+
+    #[test]
+    fn test_reject_beyond_skew_clamps_up_to_the_skew() {
+        let mut icrc3 = setup_with_timestamp_ordering(TimestampOrdering::RejectBeyondSkew(
+            Duration::from_nanos(10),
+        ));
+        assert!(matches!(icrc3.add_transaction(behind(10)), Ok(1)));
+        assert_eq!(block_timestamp(&icrc3, 1), START_TIME_NANOS as u128);
+        assert!(icrc3.add_transaction(behind(11)).is_err());
+        assert_eq!(icrc3.chain_length(), 2);
+        assert_eq!(icrc3.timestamp_ordering_metrics().clamped_blocks, 1);
+    }
+
+    #[test]
+    fn test_clamped_blocks_are_found_by_their_thash() {
+        let mut icrc3 = setup_with_timestamp_ordering(TimestampOrdering::ClampToTip);
+        assert!(matches!(icrc3.add_transaction(behind(1)), Ok(1)));
+        let thash = ByteBuf::from(behind(1).tx().hash().to_vec());
+
+        let block = get_blocks(&icrc3, 1, 1).blocks.pop();
+        assert_eq!(icrc3.thash_lookup(thash.clone()).local_block, block);
+
+        // The rebuilt index finds it too.
+        icrc3.thash_index.clear();
+        icrc3.rebuild_thash_index();
+        assert_eq!(icrc3.thash_lookup(thash).local_block, block);
+    }
+
+    #[test]
+    fn test_rejected_commit_keeps_the_prepared_transaction() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+        let transaction = TestTransaction::now("sender");
+        let prepared = icrc3.prepare_transaction(transaction.clone()).unwrap();
+
+        icrc3.blockchain.max_tx_local_stable_memory_size_bytes = Some(0);
+        assert!(icrc3
+            .commit_prepared_transaction(transaction.clone(), prepared.timestamp)
+            .is_err());
+        assert_eq!(icrc3.prepared_transactions_count(), 1);
+        assert_eq!(icrc3.chain_length(), 0);
+        assert_eq!(icrc3.last_phash, None);
+
+        icrc3.blockchain.max_tx_local_stable_memory_size_bytes = None;
+        assert!(matches!(
+            icrc3.commit_prepared_transaction(transaction, prepared.timestamp),
+            Ok(0)
+        ));
+        assert_eq!(icrc3.prepared_transactions_count(), 0);
+        assert_eq!(get_blocks(&icrc3, 0, 10).blocks.len(), 1);
+    }
+
+    #[test]
+    fn test_low_local_capacity_requests_an_archive_run() {
+        let mut icrc3 = setup(ICRC3Properties {
+            local_archive_low_water_mark_percent: 30,
+            ..ICRC3Properties::default()
+        });
+        icrc3
+            .add_transaction(TestTransaction::now("sender-0"))
+            .unwrap();
+        assert!(!icrc3.take_archive_request());
+
+        // The blocks have the same size, the third one crosses the mark and
+        // there is no room for a fourth one.
+        let block_size = icrc3.blockchain.local_archive_size as u128;
+        icrc3.blockchain.max_tx_local_stable_memory_size_bytes = Some(block_size * 7 / 2);
+        icrc3
+            .add_transaction(TestTransaction::now("sender-1"))
+            .unwrap();
+        assert!(!icrc3.local_capacity_low());
+        assert!(!icrc3.take_archive_request());
+        icrc3
+            .add_transaction(TestTransaction::now("sender-2"))
+            .unwrap();
+        assert!(icrc3.local_capacity_low());
+        assert!(icrc3.take_archive_request());
+        assert!(!icrc3.take_archive_request());
+
+        // The run is in progress, a rejected block doesn't request another one.
+        let transaction = TestTransaction::now("sender-3");
+        assert!(matches!(
+            icrc3.add_transaction(transaction.clone()),
+            Err(Icrc3Error::LocalArchiveFull { retry_after_hint_ms })
+                if retry_after_hint_ms == LOCAL_ARCHIVE_FULL_RETRY_AFTER.as_millis() as u64
+        ));
+        assert!(!icrc3.take_archive_request());
+
+        icrc3.archive_in_progress = false;
+        assert!(icrc3.add_transaction(transaction.clone()).is_err());
+        assert!(icrc3.take_archive_request());
+        assert_eq!(icrc3.chain_length(), 3);
+
+        // The transaction goes through once the archive run freed some room.
+        icrc3.blockchain.max_tx_local_stable_memory_size_bytes = None;
+        assert!(matches!(icrc3.add_transaction(transaction), Ok(3)));
+    }
+
+    #[test]
+    fn test_verification_runs_do_not_overlap() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+
+        assert!(icrc3.verification_plan().is_ok());
+        assert!(icrc3.verification_plan().is_err());
+
+        icrc3.record_verification(runtime::time(), Ok(0));
+        assert!(icrc3.verification_plan().is_ok());
+    }
+
+    #[test]
+    fn test_appended_blocks_are_queued_for_the_subscribers() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+        icrc3
+            .add_transaction(TestTransaction::now("sender-0"))
+            .unwrap();
+
+        let subscriber = candid::Principal::from_slice(&[7]);
+        icrc3
+            .notifications
+            .subscribe(subscriber, "on_blocks".to_string(), None)
+            .unwrap();
+        icrc3
+            .add_transaction(TestTransaction::now("sender-1"))
+            .unwrap();
+
+        // A rejected block is not notified.
+        icrc3.blockchain.max_tx_local_stable_memory_size_bytes = Some(0);
+        assert!(icrc3
+            .add_transaction(TestTransaction::now("sender-2"))
+            .is_err());
+
+        let deliveries = icrc3.take_notification_deliveries();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].canister_id, subscriber);
+        let notifications = &deliveries[0].notifications;
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].index, 1);
+        assert_eq!(notifications[0].btype, "btype_test");
+        assert_eq!(notifications[0].thash.len(), 32);
+
+        icrc3.record_notification_deliveries(START_TIME_NANOS, vec![(subscriber, 1, Ok(()))]);
+        assert_eq!(icrc3.notifications.metrics()[0].delivered, 1);
+        assert_eq!(icrc3.job_history.records().last().unwrap().outcome, Ok(1));
+    }
+
+    #[test]
+    fn test_legacy_transactions_serve_the_local_blocks() {
+        let request = |start: u64, length: u64| GetBlocksRequest {
+            start: Nat::from(start),
+            length: Nat::from(length),
+        };
+        let mut icrc3 = setup(ICRC3Properties::default());
+        icrc3
+            .add_transaction(TestTransaction::now("sender-0"))
+            .unwrap();
+        assert!(crate::legacy::get_transactions(&icrc3, request(0, 1)).is_err());
+
+        icrc3.icrc3_config.constants.legacy_transactions = true;
+        for i in 1..4 {
+            icrc3
+                .add_transaction(TestTransaction::now(&format!("sender-{i}")))
+                .unwrap();
+        }
+
+        let response = crate::legacy::get_transactions(&icrc3, request(1, 10)).unwrap();
+        assert_eq!(response.log_length, Nat::from(4u64));
+        assert_eq!(response.first_index, Nat::from(1u64));
+        assert!(response.archived_transactions.is_empty());
+        // Blocks without a legacy equivalent keep their position.
+        assert_eq!(response.transactions.len(), 3);
+        assert!(response
+            .transactions
+            .iter()
+            .all(|transaction| transaction.kind == "btype_test"
+                && transaction.timestamp == START_TIME_NANOS));
+
+        let response = crate::legacy::get_transactions(&icrc3, request(10, 1)).unwrap();
+        assert_eq!(response.first_index, Nat::from(4u64));
+        assert!(response.transactions.is_empty());
+    }
+
+    // Ported from test_insert_transaction::test_certificate.
+    #[test]
+    fn test_certificate() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+
+        for i in 0..10 {
+            icrc3
+                .add_transaction(TestTransaction::now(&format!("sender-{i}")))
+                .unwrap();
+            host::advance_mock_time(Duration::from_secs(2));
+        }
+
+        // Each block refreshes the certified data.
+        assert_eq!(host::certified_data(), icrc3.get_hash_tree());
+
+        // No certificate is available outside a query.
+        assert!(icrc3.icrc3_get_tip_certificate().is_none());
+
+        host::set_data_certificate(Some(vec![1, 2, 3]));
+        let tip = icrc3.icrc3_get_tip_certificate().unwrap();
+        assert_eq!(tip.certificate.as_slice(), &[1, 2, 3]);
+        let hash_tree: ic_certification::HashTree =
+            serde_cbor::from_slice(tip.hash_tree.as_slice()).unwrap();
+        assert_eq!(hash_tree.digest().to_vec(), icrc3.get_hash_tree());
+        assert_eq!(
+            hash_tree.lookup_path([b"last_block_index"]),
+            ic_certification::LookupResult::Found(&[9])
+        );
+
+        let certificate = Certificate::try_from(icrc3).unwrap();
+        assert_eq!(host::certified_data(), certificate.tree.digest().to_vec());
+        assert_eq!(certificate.signature, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_get_tip() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+        assert_eq!(icrc3.icrc3_get_tip(), None);
+
+        for i in 0..5 {
+            icrc3
+                .add_transaction(TestTransaction::now(&format!("sender-{i}")))
+                .unwrap();
+            host::advance_mock_time(Duration::from_secs(2));
+        }
+
+        let tip = icrc3.icrc3_get_tip().unwrap();
+        assert_eq!(tip.index, 4u64);
+        assert_eq!(
+            tip.timestamp_ns,
+            Nat::from(START_TIME_NANOS + 4 * 2_000_000_000)
+        );
+        assert_eq!(tip.block_hash.len(), 32);
+
+        // The certificate covers the same index and hash.
+        host::set_data_certificate(Some(vec![1, 2, 3]));
+        let mut index = Vec::new();
+        leb128::write::unsigned(&mut index, 4).unwrap();
+        let expected = ic_certification::fork(
+            ic_certification::hash_tree::leaf(index),
+            ic_certification::hash_tree::leaf(tip.block_hash.to_vec()),
+        );
+        let certificate = Certificate::try_from(icrc3).unwrap();
+        assert_eq!(certificate.tree.digest(), expected.digest());
+    }
+
+    #[test]
+    fn test_restore_refreshes_certified_data_and_applies_config() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+        for i in 0..3 {
+            icrc3
+                .add_transaction(TestTransaction::now(&format!("sender-{i}")))
+                .unwrap();
+        }
+        // The certified data does not survive upgrades.
+        runtime::certified_data_set([]);
+        assert!(host::certified_data().is_empty());
+
+        icrc3.refresh_certified_data();
+        assert_eq!(host::certified_data(), icrc3.get_hash_tree());
+
+        let mut config = icrc3.icrc3_config.clone();
+        config.constants.threshold_for_archiving_to_external_archive = Some(7);
+        config.funding_config = Some(FundingConfig {
+            interval_secs: 0,
+            ..FundingConfig::default()
+        });
+        assert!(icrc3.apply_config(config.clone()).is_err());
+        assert_eq!(icrc3.icrc3_config.funding_config, None);
+
+        config.funding_config = Some(FundingConfig {
+            interval_secs: 30,
+            ..FundingConfig::default()
+        });
+        icrc3.apply_config(config).unwrap();
+        assert_eq!(icrc3.icrc3_config.funding_config().interval_secs, 30);
+        assert_eq!(
+            icrc3.blockchain.threshold_for_archiving_to_external_archive,
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn test_apply_config_rejects_invalid_supported_blocks() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+
+        let mut config = icrc3.icrc3_config.clone();
+        config.supported_blocks.push(SupportedBlockType {
+            block_type: "7transfer".to_string(),
+            url: "https://example.com".to_string(),
+        });
+        let error = icrc3.apply_config(config.clone()).unwrap_err();
+        assert!(error.contains("7transfer"), "{error}");
+        assert_eq!(icrc3.icrc3_config.supported_blocks.len(), 1);
+
+        config.custom_block_types = vec!["7transfer".to_string()];
+        icrc3.apply_config(config).unwrap();
+        assert_eq!(icrc3.icrc3_config.supported_blocks.len(), 2);
+    }
+
+    #[test]
+    fn test_recorders() {
+        let satellite = candid::Principal::from_slice(&[2]);
+        let controller = candid::Principal::from_slice(&[3]);
+
+        // Without recorders, any caller may record.
+        let mut icrc3 = setup(ICRC3Properties::default());
+        host::set_caller(satellite);
+        assert!(icrc3.authorize_recorder().is_ok());
+
+        let mut icrc3 = setup(ICRC3Properties {
+            recorders: Some(vec![]),
+            ..ICRC3Properties::default()
+        });
+        host::set_controllers(vec![controller]);
+
+        host::set_caller(satellite);
+        assert!(matches!(
+            icrc3.authorize_recorder(),
+            Err(Icrc3Error::Unauthorized { caller }) if caller == satellite
+        ));
+        host::set_caller(controller);
+        assert!(icrc3.authorize_recorder().is_ok());
+        host::set_caller(runtime::canister_self());
+        assert!(icrc3.authorize_recorder().is_ok());
+
+        icrc3.add_recorder(satellite);
+        icrc3.add_recorder(satellite);
+        assert_eq!(
+            icrc3.icrc3_get_properties().recorders,
+            Some(vec![satellite])
+        );
+        host::set_caller(satellite);
+        assert!(icrc3.authorize_recorder().is_ok());
+
+        icrc3.remove_recorder(satellite);
+        assert_eq!(icrc3.icrc3_get_properties().recorders, Some(vec![]));
+        assert!(icrc3.authorize_recorder().is_err());
+    }
+
+    #[test]
+    fn test_record_recorder_adds_rec_field() {
+        let satellite = candid::Principal::from_slice(&[2]);
+        let mut icrc3 = setup(ICRC3Properties {
+            record_recorder: true,
+            ..ICRC3Properties::default()
+        });
+        host::set_caller(satellite);
+
+        let transaction = TestTransaction::now("a");
+        icrc3.add_transaction(transaction.clone()).unwrap();
+
+        // Duplicates are still detected with the `rec` field in the block.
+        assert!(matches!(
+            icrc3.add_transaction(transaction),
+            Err(Icrc3Error::DuplicateTransaction { .. })
+        ));
+        host::advance_mock_time(Duration::from_secs(2));
+
+        let prepared = icrc3
+            .prepare_transaction(TestTransaction::now("b"))
+            .unwrap();
+        icrc3
+            .commit_prepared_transaction(TestTransaction::now("b"), prepared.timestamp)
+            .unwrap();
+
+        let blocks = get_blocks(&icrc3, 0, 2).blocks;
+        assert_eq!(blocks.len(), 2);
+        for block in blocks {
+            let ICRC3Value::Map(map) = block.block else {
+                panic!("block is not a map");
+            };
+            assert_eq!(
+                map.get("rec"),
+                Some(&ICRC3Value::Blob(ByteBuf::from(
+                    satellite.as_slice().to_vec()
+                )))
+            );
+        }
+    }
+
+    fn get_block_maps(icrc3: &ICRC3, length: u64) -> Vec<BTreeMap<String, ICRC3Value>> {
+        get_blocks(icrc3, 0, length)
+            .blocks
+            .into_iter()
+            .map(|block| match block.block {
+                ICRC3Value::Map(map) => map,
+                _ => panic!("block is not a map"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_embed_version_metadata_adds_ver_field() {
+        let mut icrc3 = setup(ICRC3Properties {
+            embed_version_metadata: true,
+            ..ICRC3Properties::default()
+        });
+        assert!(icrc3.icrc3_get_properties().embed_version_metadata);
+
+        icrc3.add_transaction(TestTransaction::now("a")).unwrap();
+        host::advance_mock_time(Duration::from_secs(2));
+        let prepared = icrc3
+            .prepare_transaction(TestTransaction::now("b"))
+            .unwrap();
+        icrc3
+            .commit_prepared_transaction(TestTransaction::now("b"), prepared.timestamp)
+            .unwrap();
+
+        let blocks = get_block_maps(&icrc3, 2);
+        assert_eq!(blocks.len(), 2);
+        let expected = ICRC3Value::Map(BTreeMap::from([
+            (
+                "lib".to_string(),
+                ICRC3Value::Text(env!("CARGO_PKG_VERSION").to_string()),
+            ),
+            (
+                "commit".to_string(),
+                ICRC3Value::Text("commit_hash".to_string()),
+            ),
+        ]));
+        for block in &blocks {
+            assert_eq!(block.get("ver"), Some(&expected));
+        }
+
+        // The blocks still validate with the `ver` field.
+        for block in blocks {
+            GlobalTransaction::new(ICRC3Value::Map(block))
+                .validate_transaction_fields()
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_block_hash_unchanged_without_version_metadata() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+        assert!(!icrc3.icrc3_get_properties().embed_version_metadata);
+
+        icrc3.add_transaction(TestTransaction::now("a")).unwrap();
+
+        let blocks = get_block_maps(&icrc3, 1);
+        assert!(!blocks[0].contains_key("ver"));
+        assert_eq!(
+            hex::encode(ICRC3Value::Map(blocks[0].clone()).hash()),
+            "56ab29e793dc72be056147c2be9a748c504c47f612e8220c15091c24949b1b0f"
+        );
+    }
+
+    #[test]
+    fn test_created_at_time_boundaries() {
+        let mut icrc3 = setup(ICRC3Properties::default());
+        let now = START_TIME_NANOS;
+        let window = icrc3.transaction_window().as_nanos() as u64;
+        let drift = PERMITTED_DRIFT.as_nanos() as u64;
+
+        icrc3
+            .add_transaction(TestTransaction::created_at("a", now - window))
+            .unwrap();
+        assert!(matches!(
+            icrc3.add_transaction(TestTransaction::created_at("b", now - window - 1)),
+            Err(Icrc3Error::TooOld)
+        ));
+        icrc3
+            .add_transaction(TestTransaction::created_at("c", now + drift))
+            .unwrap();
+        assert!(matches!(
+            icrc3.add_transaction(TestTransaction::created_at("d", now + drift + 1)),
+            Err(Icrc3Error::CreatedInFuture { ledger_time }) if ledger_time == now
+        ));
+
+        // Prepared transactions are checked the same way.
+        icrc3
+            .prepare_transaction(TestTransaction::created_at("e", now - window))
+            .unwrap();
+        assert!(matches!(
+            icrc3.prepare_transaction(TestTransaction::created_at("f", now - window - 1)),
+            Err(Icrc3Error::TooOld)
+        ));
+        assert!(matches!(
+            icrc3.prepare_transaction(TestTransaction::created_at("g", now + drift + 1)),
+            Err(Icrc3Error::CreatedInFuture { .. })
+        ));
+
+        // Transactions without created_at_time are exempt.
+        host::advance_mock_time(Duration::from_secs(3600));
+        icrc3.add_transaction(TestTransaction::now("h")).unwrap();
+    }
+
+    #[test]
+    fn test_registered_schema_documents_and_validates() {
+        use crate::schema::{FieldSchema, TransactionSchema, ValueKind};
+
+        let mut icrc3 = setup(ICRC3Properties::default());
+        assert!(icrc3.block_schemas()[0].fields.is_empty());
+        icrc3.add_transaction(TestTransaction::now("a")).unwrap();
+
+        let fields = vec![
+            FieldSchema::required("btype", ValueKind::Text),
+            FieldSchema::required("timestamp", ValueKind::Nat),
+            FieldSchema::required("sender", ValueKind::Text),
+            FieldSchema::optional("ts", ValueKind::Nat),
+        ];
+        icrc3.register_block_schema(TransactionSchema::new("btype_test", fields.clone()));
+        assert_eq!(icrc3.block_schemas()[0].fields, fields);
+        icrc3.add_transaction(TestTransaction::now("b")).unwrap();
+
+        // The schema now requires a field the transactions do not have.
+        let mut stricter = fields;
+        stricter.push(FieldSchema::required("recipient", ValueKind::Text));
+        icrc3.register_block_schema(TransactionSchema::new("btype_test", stricter));
+        assert!(matches!(
+            icrc3.add_transaction(TestTransaction::now("c")),
+            Err(Icrc3Error::Icrc3Error(e)) if e == "recipient is required for btype_test"
+        ));
+        assert!(icrc3
+            .prepare_transaction(TestTransaction::now("d"))
+            .is_err());
+    }
 }
@@ -0,0 +1,227 @@
+//! History of the background archive, cleanup and verification job runs.
+//!
+//! Job failures are otherwise only visible through `trace`, which is compiled out
+//! without the `debug-logs` feature. The most recent runs are kept in a ring
+//! buffer that is stored with the ICRC3 state and therefore survives upgrades.
+
+use bity_ic_types::TimestampNanos;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Maximum number of job runs kept in the history.
+pub const JOB_HISTORY_CAPACITY: usize = 50;
+
+/// The kind of background job.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobKind {
+    Archive,
+    Cleanup,
+    Verification,
+    FundingHealth,
+    Notifications,
+}
+
+impl JobKind {
+    /// Every kind of background job.
+    pub const ALL: [JobKind; 5] = [
+        JobKind::Archive,
+        JobKind::Cleanup,
+        JobKind::Verification,
+        JobKind::FundingHealth,
+        JobKind::Notifications,
+    ];
+
+    /// Returns the name of the `bity_ic_canister_time` timer running the job.
+    pub fn timer_name(&self) -> &'static str {
+        match self {
+            JobKind::Archive => "icrc3_archive_job",
+            JobKind::Cleanup => "icrc3_cleanup_job",
+            JobKind::Verification => "icrc3_verification_job",
+            JobKind::FundingHealth => "icrc3_funding_health_job",
+            JobKind::Notifications => "icrc3_notification_job",
+        }
+    }
+}
+
+/// The record of a single job run.
+///
+/// # Fields
+///
+/// * `job` - The kind of job
+/// * `started_at` - When the run started, in nanoseconds
+/// * `finished_at` - When the run finished, in nanoseconds
+/// * `outcome` - The number of processed items, or the error message
+/// * `archive_batch_size` - For archive runs, the number of blocks per batch
+///   learned by the run, see [`crate::blockchain::archive_batch`]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct JobRunRecord {
+    pub job: JobKind,
+    pub started_at: TimestampNanos,
+    pub finished_at: TimestampNanos,
+    pub outcome: Result<u128, String>,
+    #[serde(default)]
+    pub archive_batch_size: Option<u64>,
+}
+
+/// Last success and failure timestamps of each job, for metrics.
+///
+/// `verification_failures` counts all the failed verification runs, including
+/// those evicted from the history. `archive_batch_size` is the number of blocks of
+/// the next archive batch, set by `ICRC3::job_history_metrics`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct JobHistoryMetrics {
+    pub last_archive_success: Option<TimestampNanos>,
+    pub last_archive_failure: Option<TimestampNanos>,
+    pub last_cleanup_success: Option<TimestampNanos>,
+    pub last_cleanup_failure: Option<TimestampNanos>,
+    pub last_verification_success: Option<TimestampNanos>,
+    pub last_verification_failure: Option<TimestampNanos>,
+    pub verification_failures: u64,
+    pub archive_batch_size: u64,
+}
+
+/// Ring buffer of the most recent job runs.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct JobHistory {
+    records: VecDeque<JobRunRecord>,
+    #[serde(default)]
+    verification_failures: u64,
+}
+
+impl JobHistory {
+    /// Records a job run, evicting the oldest record when the buffer is full.
+    ///
+    /// # Arguments
+    ///
+    /// * `job` - The kind of job
+    /// * `started_at` - When the run started, in nanoseconds
+    /// * `finished_at` - When the run finished, in nanoseconds
+    /// * `outcome` - The result of the run
+    pub fn record(
+        &mut self,
+        job: JobKind,
+        started_at: TimestampNanos,
+        finished_at: TimestampNanos,
+        outcome: Result<u128, String>,
+    ) {
+        if job == JobKind::Verification && outcome.is_err() {
+            self.verification_failures += 1;
+        }
+        self.push(JobRunRecord {
+            job,
+            started_at,
+            finished_at,
+            outcome,
+            archive_batch_size: None,
+        });
+    }
+
+    /// Records an archive job run along with the batch size it learned.
+    ///
+    /// # Arguments
+    ///
+    /// * `started_at` - When the run started, in nanoseconds
+    /// * `finished_at` - When the run finished, in nanoseconds
+    /// * `outcome` - The result of the run
+    /// * `archive_batch_size` - The number of blocks of the next archive batch
+    pub fn record_archive(
+        &mut self,
+        started_at: TimestampNanos,
+        finished_at: TimestampNanos,
+        outcome: Result<u128, String>,
+        archive_batch_size: u64,
+    ) {
+        self.push(JobRunRecord {
+            job: JobKind::Archive,
+            started_at,
+            finished_at,
+            outcome,
+            archive_batch_size: Some(archive_batch_size),
+        });
+    }
+
+    /// Appends a record, evicting the oldest record when the buffer is full.
+    fn push(&mut self, record: JobRunRecord) {
+        if self.records.len() >= JOB_HISTORY_CAPACITY {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    /// Returns the recorded job runs, oldest first.
+    pub fn records(&self) -> Vec<JobRunRecord> {
+        self.records.iter().cloned().collect()
+    }
+
+    /// Returns the finish time of the last successful or failed run of a job.
+    fn last_finished(&self, job: JobKind, success: bool) -> Option<TimestampNanos> {
+        self.records
+            .iter()
+            .rev()
+            .find(|r| r.job == job && r.outcome.is_ok() == success)
+            .map(|r| r.finished_at)
+    }
+
+    /// Returns the last success and failure timestamps of each job.
+    pub fn metrics(&self) -> JobHistoryMetrics {
+        JobHistoryMetrics {
+            last_archive_success: self.last_finished(JobKind::Archive, true),
+            last_archive_failure: self.last_finished(JobKind::Archive, false),
+            last_cleanup_success: self.last_finished(JobKind::Cleanup, true),
+            last_cleanup_failure: self.last_finished(JobKind::Cleanup, false),
+            last_verification_success: self.last_finished(JobKind::Verification, true),
+            last_verification_failure: self.last_finished(JobKind::Verification, false),
+            verification_failures: self.verification_failures,
+            archive_batch_size: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_history_is_bounded_and_tracks_last_outcomes() {
+        let mut history = JobHistory::default();
+
+        for i in 0..(JOB_HISTORY_CAPACITY as u64 + 10) {
+            history.record(JobKind::Cleanup, i, i + 1, Ok(0));
+        }
+        history.record(JobKind::Archive, 100, 101, Err("stopped".to_string()));
+        history.record_archive(200, 201, Ok(5), 50);
+
+        let records = history.records();
+        assert_eq!(records.len(), JOB_HISTORY_CAPACITY);
+        assert_eq!(records.last().unwrap().outcome, Ok(5));
+        assert_eq!(records.last().unwrap().archive_batch_size, Some(50));
+
+        let metrics = history.metrics();
+        assert_eq!(metrics.last_archive_success, Some(201));
+        assert_eq!(metrics.last_archive_failure, Some(101));
+        assert_eq!(
+            metrics.last_cleanup_success,
+            Some(JOB_HISTORY_CAPACITY as u64 + 10)
+        );
+        assert_eq!(metrics.last_cleanup_failure, None);
+        assert_eq!(metrics.verification_failures, 0);
+    }
+
+    #[test]
+    fn test_verification_failures_outlive_the_history() {
+        let mut history = JobHistory::default();
+        history.record(JobKind::Verification, 1, 2, Err("block 3".to_string()));
+        for i in 0..JOB_HISTORY_CAPACITY as u64 {
+            history.record(JobKind::Verification, 10 + i, 11 + i, Ok(3));
+        }
+
+        let metrics = history.metrics();
+        assert_eq!(metrics.verification_failures, 1);
+        assert_eq!(metrics.last_verification_failure, None);
+        assert_eq!(
+            metrics.last_verification_success,
+            Some(10 + JOB_HISTORY_CAPACITY as u64)
+        );
+    }
+}
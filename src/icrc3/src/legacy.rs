@@ -0,0 +1,93 @@
+//! Legacy `get_transactions` endpoint of the ICRC-1 ledgers.
+//!
+//! Wallets and explorers that predate ICRC-3 read the chain through
+//! `get_transactions`, which returns the ICRC-1 and ICRC-2 blocks as
+//! `Transaction` records. When `legacy_transactions` is set, the local blocks are
+//! served in that format, and the archived ones are redirected to the
+//! `get_transactions` query of their archive canister. See
+//! `bity_ic_icrc3_archive_api::types::legacy_transactions` for the mapping.
+
+use crate::icrc3::ICRC3;
+use crate::utils::{requested_block_range, trace};
+
+use bity_ic_icrc3_archive_api::types::legacy_transactions::{
+    legacy_transaction, ARCHIVE_GET_TRANSACTIONS_METHOD,
+};
+use bity_ic_icrc3_archive_api::types::{block_interface::Block, defaultblock::DefaultBlock};
+use candid::{Nat, Principal};
+use icrc_ledger_types::icrc3::archive::{ArchivedRange, QueryTxArchiveFn};
+use icrc_ledger_types::icrc3::transactions::{GetTransactionsRequest, GetTransactionsResponse};
+
+/// Returns the transactions of the requested range, in the format of the
+/// `get_transactions` endpoint of the ICRC-1 ledgers.
+///
+/// The local blocks at the end of the range are returned from `first_index`, the
+/// blocks before them as ranges of their archive canisters. At most
+/// `max_blocks_per_response` blocks are covered.
+///
+/// # Errors
+///
+/// Returns an error if `legacy_transactions` is not set.
+pub fn get_transactions(
+    icrc3: &ICRC3,
+    request: GetTransactionsRequest,
+) -> Result<GetTransactionsResponse, String> {
+    if !icrc3.icrc3_config.constants.legacy_transactions {
+        return Err("get_transactions is disabled, set legacy_transactions".to_string());
+    }
+
+    let chain_length = icrc3.chain_length();
+    let max_length =
+        u64::try_from(icrc3.icrc3_config.constants.max_blocks_per_response).unwrap_or(u64::MAX);
+    let range = requested_block_range(&request, chain_length, max_length);
+
+    // Local transactions must be consecutive, so only the local blocks ending the
+    // range are served here, even if older blocks still have a local copy.
+    let mut first_index = range.end;
+    let mut transactions = vec![];
+    while first_index > range.start {
+        let Some(block) = icrc3.blockchain.get_block(first_index - 1) else {
+            break;
+        };
+        let block = DefaultBlock::decode(block).map_err(|e| e.to_string())?;
+        transactions.push(legacy_transaction(&block.transaction));
+        first_index -= 1;
+    }
+    transactions.reverse();
+
+    let mut archived_transactions: Vec<ArchivedRange<QueryTxArchiveFn>> = vec![];
+    for index in range.start..first_index {
+        match icrc3.blockchain.get_block_canister_id(index) {
+            Ok(canister_id) => push_archived_range(&mut archived_transactions, canister_id, index),
+            Err(e) => trace(format!("get_transactions error: {:?}", e)),
+        }
+    }
+
+    Ok(GetTransactionsResponse {
+        log_length: Nat::from(chain_length),
+        first_index: Nat::from(first_index),
+        transactions,
+        archived_transactions,
+    })
+}
+
+/// Adds a block to the last archived range if it extends it, or starts a new one.
+fn push_archived_range(
+    archived_transactions: &mut Vec<ArchivedRange<QueryTxArchiveFn>>,
+    canister_id: Principal,
+    index: u64,
+) {
+    if let Some(last) = archived_transactions.last_mut() {
+        if last.callback.canister_id == canister_id
+            && last.start.clone() + last.length.clone() == index
+        {
+            last.length += Nat::from(1u64);
+            return;
+        }
+    }
+    archived_transactions.push(ArchivedRange {
+        start: Nat::from(index),
+        length: Nat::from(1u64),
+        callback: QueryTxArchiveFn::new(canister_id, ARCHIVE_GET_TRANSACTIONS_METHOD.to_string()),
+    });
+}
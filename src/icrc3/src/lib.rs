@@ -29,13 +29,31 @@
 //!
 //! ## Modules
 //!
+//! - `audit`: Audit blocks recording the administrative actions, with `audit_admin_actions`
 //! - `blockchain`: Core blockchain implementation
 //! - `config`: Configuration management
+//! - `costs`: Cycle cost estimation of the archive canisters, to size their funding
+//! - `dedup_window`: Size accounting of the deduplication window
+//! - `genesis`: Anchoring of the chain to a parent chain, with `genesis_parent_hash`
 //! - `icrc3`: Main ICRC3 implementation
 //! - `interface`: Public interfaces
+//! - `job_history`: History of the background job runs
+//! - `legacy`: Legacy `get_transactions` endpoint of the ICRC-1 ledgers, with `legacy_transactions`
+//! - `memo_index`: Index of the local blocks by memo
+//! - `notifications`: Block-added notifications published to subscriber canisters
+//! - `prepared`: Listing and metrics of the pending prepared transactions
+//! - `rebuild`: Rebuild of the state from the archive canisters, for disaster recovery
+//! - `runtime`: System API, replaceable by a test shim with the `host-test` feature
+//! - `schema`: Fields of each block type, documented and checked on new transactions
+//! - `standards`: Standards supported according to the configured block types
+//! - `testing_hooks`: Fault injection for integration tests, with the `testing-hooks` feature
+//! - `throttle`: Throttling decision for new transactions
+//! - `timestamp_ordering`: Ordering of the block timestamps across recorders, with `timestamp_ordering`
 //! - `transaction`: Transaction handling
 //! - `types`: Custom types
 //! - `utils`: Utility functions
+//! - `validation`: Validation strictness of each block type, with `validation_modes`
+//! - `verification`: Re-verification of the blocks stored in archive canisters
 //!
 //! ## Security
 //!
@@ -52,11 +70,35 @@
 //! - `serde_bytes`
 //! - `bity_ic_subcanister_manager`
 
+pub mod archive_stats;
+pub mod audit;
 pub mod blockchain;
 pub mod config;
+pub mod costs;
+pub mod dedup_window;
+pub mod genesis;
 pub mod icrc3;
 pub mod interface;
+pub mod job_history;
+pub mod legacy;
+pub mod memo_index;
 pub mod memory;
+pub mod notifications;
+pub mod prepared;
+pub mod rebuild;
+pub mod runtime;
+pub mod schema;
+pub mod standards;
+#[cfg(feature = "testing-hooks")]
+pub mod testing_hooks;
+pub mod thash_lookup;
+pub mod throttle;
+pub mod timestamp_ordering;
 pub mod transaction;
 pub mod types;
 pub mod utils;
+pub mod validation;
+pub mod verification;
+
+/// The `/blocks` HTTP export, shared with the archive canisters.
+pub use bity_ic_icrc3_archive_api::blocks_http;
@@ -0,0 +1,158 @@
+//! Secondary index of the local blocks by memo.
+//!
+//! With `index_memos` set, the memo of each block added to the local archive is
+//! indexed, so that the blocks with a given memo are found without scanning the
+//! chain. Entries are pruned once their block leaves the local archive: blocks
+//! stored in archive canisters are not searched.
+//!
+//! The index is kept next to the blocks. It is not part of the blocks, their
+//! hashes or the certified data.
+
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Memos longer than this are not indexed, as for ICRC-1 ledgers.
+pub const MAX_INDEXED_MEMO_BYTES: usize = 32;
+
+/// The indices of the local blocks by memo.
+///
+/// # Fields
+///
+/// * `by_memo` - The indices of the blocks of each memo
+/// * `by_block` - The memo of each indexed block, used for pruning
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MemoIndex {
+    by_memo: BTreeMap<ByteBuf, BTreeSet<u64>>,
+    by_block: BTreeMap<u64, ByteBuf>,
+}
+
+impl MemoIndex {
+    /// Returns the memo of a transaction, from its `tx` map or else its top level.
+    pub fn memo_of(transaction: &ICRC3Value) -> Option<&ByteBuf> {
+        let ICRC3Value::Map(map) = transaction else {
+            return None;
+        };
+        let memo = match map.get("tx") {
+            Some(ICRC3Value::Map(tx)) => tx.get("memo").or_else(|| map.get("memo")),
+            _ => map.get("memo"),
+        };
+        match memo {
+            Some(ICRC3Value::Blob(memo)) => Some(memo),
+            _ => None,
+        }
+    }
+
+    /// Indexes the memo of a block, unless it is longer than [`MAX_INDEXED_MEMO_BYTES`].
+    ///
+    /// # Arguments
+    ///
+    /// * `block_index` - The index of the block
+    /// * `memo` - The memo of the block, see [`MemoIndex::memo_of`]
+    pub fn insert(&mut self, block_index: u64, memo: ByteBuf) {
+        if memo.len() > MAX_INDEXED_MEMO_BYTES {
+            return;
+        }
+        self.by_memo
+            .entry(memo.clone())
+            .or_default()
+            .insert(block_index);
+        self.by_block.insert(block_index, memo);
+    }
+
+    /// Returns the indices of the blocks with a memo, in increasing order.
+    ///
+    /// # Arguments
+    ///
+    /// * `memo` - The memo to look up
+    /// * `max` - The maximum number of indices returned
+    pub fn find(&self, memo: &[u8], max: u32) -> Vec<u64> {
+        self.by_memo
+            .get(serde_bytes::Bytes::new(memo))
+            .map(|indices| indices.iter().take(max as usize).copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Removes the entries of the blocks before `block_index`.
+    ///
+    /// # Returns
+    ///
+    /// The number of removed entries
+    pub fn prune_before(&mut self, block_index: u64) -> usize {
+        let kept = self.by_block.split_off(&block_index);
+        let pruned = std::mem::replace(&mut self.by_block, kept);
+        for (index, memo) in &pruned {
+            if let Some(indices) = self.by_memo.get_mut(memo) {
+                indices.remove(index);
+                if indices.is_empty() {
+                    self.by_memo.remove(memo);
+                }
+            }
+        }
+        pruned.len()
+    }
+
+    /// Removes all the entries.
+    pub fn clear(&mut self) {
+        self.by_memo.clear();
+        self.by_block.clear();
+    }
+
+    /// Returns the number of indexed blocks.
+    pub fn len(&self) -> usize {
+        self.by_block.len()
+    }
+
+    /// Returns whether no block is indexed.
+    pub fn is_empty(&self) -> bool {
+        self.by_block.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memo(bytes: &[u8]) -> ByteBuf {
+        ByteBuf::from(bytes.to_vec())
+    }
+
+    #[test]
+    fn test_memo_of() {
+        let blob = ICRC3Value::Blob(memo(b"a"));
+        let nested = ICRC3Value::Map(BTreeMap::from([(
+            "tx".to_string(),
+            ICRC3Value::Map(BTreeMap::from([("memo".to_string(), blob.clone())])),
+        )]));
+        let top_level = ICRC3Value::Map(BTreeMap::from([("memo".to_string(), blob)]));
+        let text = ICRC3Value::Map(BTreeMap::from([(
+            "memo".to_string(),
+            ICRC3Value::Text("a".to_string()),
+        )]));
+
+        assert_eq!(MemoIndex::memo_of(&nested), Some(&memo(b"a")));
+        assert_eq!(MemoIndex::memo_of(&top_level), Some(&memo(b"a")));
+        assert_eq!(MemoIndex::memo_of(&text), None);
+    }
+
+    #[test]
+    fn test_duplicate_memos_are_found_in_order_and_pruned() {
+        let mut index = MemoIndex::default();
+        index.insert(0, memo(b"a"));
+        index.insert(1, memo(b"b"));
+        index.insert(3, memo(b"a"));
+        index.insert(4, memo(&[1; MAX_INDEXED_MEMO_BYTES + 1]));
+        index.insert(5, memo(b"a"));
+
+        assert_eq!(index.len(), 4);
+        assert_eq!(index.find(b"a", 10), vec![0, 3, 5]);
+        assert_eq!(index.find(b"a", 2), vec![0, 3]);
+        assert!(index.find(&[1; MAX_INDEXED_MEMO_BYTES + 1], 10).is_empty());
+
+        assert_eq!(index.prune_before(4), 3);
+        assert_eq!(index.find(b"a", 10), vec![5]);
+        assert!(index.find(b"b", 10).is_empty());
+        assert_eq!(index.len(), 1);
+    }
+}
@@ -0,0 +1,529 @@
+//! Block-added notifications published to subscriber canisters.
+//!
+//! Canisters following the chain otherwise have to poll `icrc3_get_blocks`. A
+//! subscriber registers a method, optionally with the block types it cares
+//! about, and every appended block is queued for it as a [`BlockNotification`].
+//! The notification job delivers the queued notifications in batches, calling
+//! the method with a `vec BlockNotification` argument.
+//!
+//! Notifications are best-effort: queuing never fails the add path, each queue
+//! is bounded and drops its oldest notifications when full, and a subscriber
+//! that keeps failing is retried with an exponential backoff, then suspended
+//! after [`MAX_CONSECUTIVE_FAILURES`] failures. A suspended subscriber is only
+//! probed every [`SUSPENDED_RETRY_INTERVAL`], and resumes on the first
+//! successful delivery.
+
+use bity_ic_icrc3_archive_api::types::thash::transaction_hash;
+use bity_ic_types::TimestampNanos;
+use candid::{CandidType, Principal};
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+/// Maximum number of subscribers.
+pub const MAX_SUBSCRIBERS: usize = 20;
+
+/// Maximum number of notifications queued for a single subscriber.
+pub const SUBSCRIBER_QUEUE_CAPACITY: usize = 1_000;
+
+/// Maximum number of notifications delivered in a single call.
+pub const NOTIFICATION_BATCH_SIZE: usize = 100;
+
+/// Number of consecutive failed deliveries after which a subscriber is suspended.
+pub const MAX_CONSECUTIVE_FAILURES: u32 = 5;
+
+/// The delay before the first retry of a failed delivery, doubled on each failure.
+pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest delay between two retries of a subscriber which is not suspended.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// How often a suspended subscriber is probed.
+pub const SUSPENDED_RETRY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// The timeout of the bounded-wait delivery calls, in seconds.
+pub const NOTIFICATION_CALL_TIMEOUT_SECONDS: u32 = 10;
+
+/// Maximum number of delivery calls in flight.
+const DELIVERY_CONCURRENCY: usize = 10;
+
+/// The notification of an appended block.
+///
+/// # Fields
+///
+/// * `index` - The index of the block
+/// * `btype` - The block type, empty if the block has none
+/// * `thash` - The hash of the transaction, see `bity_ic_icrc3_archive_api::types::thash`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BlockNotification {
+    pub index: u64,
+    pub btype: String,
+    pub thash: ByteBuf,
+}
+
+impl BlockNotification {
+    /// Builds the notification of the block at `index` holding `transaction`.
+    pub fn of(index: u64, transaction: &ICRC3Value) -> Self {
+        let btype = match transaction {
+            ICRC3Value::Map(fields) => match fields.get("btype") {
+                Some(ICRC3Value::Text(btype)) => btype.clone(),
+                _ => String::new(),
+            },
+            _ => String::new(),
+        };
+        let thash = transaction_hash(transaction)
+            .map(|hash| hash.to_vec())
+            .unwrap_or_default();
+        Self {
+            index,
+            btype,
+            thash: ByteBuf::from(thash),
+        }
+    }
+}
+
+/// A subscriber and its queued notifications.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Subscription {
+    method_name: String,
+    filter: Option<Vec<String>>,
+    queue: VecDeque<BlockNotification>,
+    consecutive_failures: u32,
+    next_attempt_at: TimestampNanos,
+    suspended: bool,
+    delivered: u64,
+    dropped: u64,
+    last_error: Option<String>,
+    /// Whether a delivery is in flight, so that its batch is not sent twice
+    #[serde(skip)]
+    in_flight: bool,
+}
+
+impl Subscription {
+    fn accepts(&self, notification: &BlockNotification) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|btypes| btypes.contains(&notification.btype))
+    }
+}
+
+/// The delivery state of a subscriber, for metrics.
+///
+/// # Fields
+///
+/// * `canister_id` - The subscriber
+/// * `method_name` - The method called with the notifications
+/// * `filter` - The block types notified, or `None` for all of them
+/// * `queued` - The number of notifications waiting for delivery
+/// * `delivered` - The number of notifications delivered
+/// * `dropped` - The number of notifications dropped because the queue was full
+/// * `consecutive_failures` - The number of failed deliveries since the last success
+/// * `suspended` - Whether the subscriber is suspended, see [`MAX_CONSECUTIVE_FAILURES`]
+/// * `next_attempt_at` - When the next delivery may be attempted, in nanoseconds
+/// * `last_error` - The error of the last failed delivery, cleared on success
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SubscriberMetrics {
+    pub canister_id: Principal,
+    pub method_name: String,
+    pub filter: Option<Vec<String>>,
+    pub queued: u64,
+    pub delivered: u64,
+    pub dropped: u64,
+    pub consecutive_failures: u32,
+    pub suspended: bool,
+    pub next_attempt_at: TimestampNanos,
+    pub last_error: Option<String>,
+}
+
+/// A batch of notifications to deliver, taken from the state so that the
+/// subscribers are called without holding the ICRC3 lock.
+#[derive(Clone, Debug)]
+pub struct NotificationDelivery {
+    pub canister_id: Principal,
+    pub method_name: String,
+    pub notifications: Vec<BlockNotification>,
+}
+
+impl NotificationDelivery {
+    /// The index of the last notification of the batch.
+    pub fn last_index(&self) -> u64 {
+        self.notifications.last().map_or(0, |n| n.index)
+    }
+
+    /// Calls the subscriber with the batch, with a bounded-wait call.
+    pub async fn send(&self) -> Result<(), String> {
+        let payload = candid::encode_one(&self.notifications)
+            .map_err(|e| format!("Failed to encode the notifications: {}", e))?;
+        bity_ic_canister_client::make_c2c_call_raw(
+            self.canister_id,
+            &self.method_name,
+            &payload,
+            0,
+            Some(NOTIFICATION_CALL_TIMEOUT_SECONDS),
+        )
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    }
+}
+
+/// The outcome of a delivery: the subscriber, the index of the last
+/// notification sent, and the result of the call.
+pub type DeliveryResult = (Principal, u64, Result<(), String>);
+
+/// Sends every delivery, at most [`DELIVERY_CONCURRENCY`] at a time.
+pub async fn deliver(deliveries: Vec<NotificationDelivery>) -> Vec<DeliveryResult> {
+    let by_canister: BTreeMap<Principal, NotificationDelivery> = deliveries
+        .into_iter()
+        .map(|delivery| (delivery.canister_id, delivery))
+        .collect();
+    let results = bity_ic_canister_client::fan_out_calls(
+        by_canister.keys().copied().collect::<Vec<_>>(),
+        DELIVERY_CONCURRENCY,
+        |canister_id| {
+            let delivery = &by_canister[&canister_id];
+            delivery.send()
+        },
+    )
+    .await;
+    results
+        .into_iter()
+        .map(|(canister_id, result)| (canister_id, by_canister[&canister_id].last_index(), result))
+        .collect()
+}
+
+/// The subscribers and their queued notifications.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Notifications {
+    subscriptions: BTreeMap<Principal, Subscription>,
+}
+
+impl Notifications {
+    /// Subscribes `canister_id` to the notifications of the blocks whose type is
+    /// in `filter`, or of every block. Subscribing again updates the method and
+    /// the filter, and resumes a suspended subscriber.
+    ///
+    /// # Errors
+    ///
+    /// * The method name or the filter is empty
+    /// * There are already [`MAX_SUBSCRIBERS`] subscribers
+    pub fn subscribe(
+        &mut self,
+        canister_id: Principal,
+        method_name: String,
+        filter: Option<Vec<String>>,
+    ) -> Result<(), String> {
+        if method_name.is_empty() {
+            return Err("The method name must not be empty".to_string());
+        }
+        if filter.as_ref().is_some_and(|btypes| btypes.is_empty()) {
+            return Err("The filter must contain at least one block type".to_string());
+        }
+
+        if let Some(subscription) = self.subscriptions.get_mut(&canister_id) {
+            subscription.method_name = method_name;
+            subscription.filter = filter;
+            subscription.consecutive_failures = 0;
+            subscription.suspended = false;
+            subscription.next_attempt_at = 0;
+            return Ok(());
+        }
+        if self.subscriptions.len() >= MAX_SUBSCRIBERS {
+            return Err(format!(
+                "The maximum of {} subscribers is reached",
+                MAX_SUBSCRIBERS
+            ));
+        }
+        self.subscriptions.insert(
+            canister_id,
+            Subscription {
+                method_name,
+                filter,
+                queue: VecDeque::new(),
+                consecutive_failures: 0,
+                next_attempt_at: 0,
+                suspended: false,
+                delivered: 0,
+                dropped: 0,
+                last_error: None,
+                in_flight: false,
+            },
+        );
+        Ok(())
+    }
+
+    /// Whether there are no subscribers.
+    pub fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty()
+    }
+
+    /// Removes a subscriber along with its queued notifications.
+    ///
+    /// # Returns
+    ///
+    /// Whether `canister_id` was subscribed.
+    pub fn unsubscribe(&mut self, canister_id: Principal) -> bool {
+        self.subscriptions.remove(&canister_id).is_some()
+    }
+
+    /// Queues a notification for every subscriber accepting its block type.
+    pub fn enqueue(&mut self, notification: &BlockNotification) {
+        for subscription in self.subscriptions.values_mut() {
+            if !subscription.accepts(notification) {
+                continue;
+            }
+            if subscription.queue.len() >= SUBSCRIBER_QUEUE_CAPACITY {
+                subscription.queue.pop_front();
+                subscription.dropped += 1;
+            }
+            subscription.queue.push_back(notification.clone());
+        }
+    }
+
+    /// Takes the batches due at `now`, marking them in flight until their
+    /// delivery is recorded.
+    pub fn take_due_deliveries(&mut self, now: TimestampNanos) -> Vec<NotificationDelivery> {
+        self.subscriptions
+            .iter_mut()
+            .filter(|(_, s)| !s.in_flight && !s.queue.is_empty() && s.next_attempt_at <= now)
+            .map(|(canister_id, subscription)| {
+                subscription.in_flight = true;
+                NotificationDelivery {
+                    canister_id: *canister_id,
+                    method_name: subscription.method_name.clone(),
+                    notifications: subscription
+                        .queue
+                        .iter()
+                        .take(NOTIFICATION_BATCH_SIZE)
+                        .cloned()
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// Records the outcome of a delivery to `canister_id`.
+    ///
+    /// On success, the notifications up to `last_index` are removed from the
+    /// queue and the subscriber resumes. On failure, the next attempt is
+    /// delayed with an exponential backoff, and the subscriber is suspended
+    /// after [`MAX_CONSECUTIVE_FAILURES`] failures.
+    ///
+    /// # Returns
+    ///
+    /// The number of delivered notifications.
+    pub fn record_delivery(
+        &mut self,
+        canister_id: Principal,
+        last_index: u64,
+        result: Result<(), String>,
+        now: TimestampNanos,
+    ) -> u64 {
+        // The subscriber may have unsubscribed during the delivery.
+        let Some(subscription) = self.subscriptions.get_mut(&canister_id) else {
+            return 0;
+        };
+        subscription.in_flight = false;
+
+        match result {
+            Ok(()) => {
+                let mut delivered = 0;
+                // Notifications dropped during the delivery may already be gone.
+                while subscription
+                    .queue
+                    .front()
+                    .is_some_and(|n| n.index <= last_index)
+                {
+                    subscription.queue.pop_front();
+                    delivered += 1;
+                }
+                subscription.delivered += delivered;
+                subscription.consecutive_failures = 0;
+                subscription.suspended = false;
+                subscription.next_attempt_at = 0;
+                subscription.last_error = None;
+                delivered
+            }
+            Err(e) => {
+                subscription.consecutive_failures += 1;
+                subscription.last_error = Some(e);
+                let delay = if subscription.consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+                    subscription.suspended = true;
+                    SUSPENDED_RETRY_INTERVAL
+                } else {
+                    INITIAL_BACKOFF
+                        .saturating_mul(1 << (subscription.consecutive_failures - 1))
+                        .min(MAX_BACKOFF)
+                };
+                subscription.next_attempt_at = now.saturating_add(delay.as_nanos() as u64);
+                0
+            }
+        }
+    }
+
+    /// Returns the delivery state of every subscriber.
+    pub fn metrics(&self) -> Vec<SubscriberMetrics> {
+        self.subscriptions
+            .iter()
+            .map(|(canister_id, s)| SubscriberMetrics {
+                canister_id: *canister_id,
+                method_name: s.method_name.clone(),
+                filter: s.filter.clone(),
+                queued: s.queue.len() as u64,
+                delivered: s.delivered,
+                dropped: s.dropped,
+                consecutive_failures: s.consecutive_failures,
+                suspended: s.suspended,
+                next_attempt_at: s.next_attempt_at,
+                last_error: s.last_error.clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notification(index: u64, btype: &str) -> BlockNotification {
+        BlockNotification {
+            index,
+            btype: btype.to_string(),
+            thash: ByteBuf::from(vec![index as u8; 32]),
+        }
+    }
+
+    fn subscriber(id: u8) -> Principal {
+        Principal::from_slice(&[id])
+    }
+
+    #[test]
+    fn test_notifications_are_filtered_batched_and_delivered_once() {
+        let mut notifications = Notifications::default();
+        notifications
+            .subscribe(subscriber(1), "on_blocks".to_string(), None)
+            .unwrap();
+        notifications
+            .subscribe(
+                subscriber(2),
+                "on_blocks".to_string(),
+                Some(vec!["1mint".to_string()]),
+            )
+            .unwrap();
+
+        for index in 0..150 {
+            let btype = if index % 2 == 0 { "1mint" } else { "1xfer" };
+            notifications.enqueue(&notification(index, btype));
+        }
+
+        let deliveries = notifications.take_due_deliveries(0);
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0].notifications.len(), NOTIFICATION_BATCH_SIZE);
+        assert_eq!(deliveries[1].notifications.len(), 75);
+        assert!(deliveries[1]
+            .notifications
+            .iter()
+            .all(|n| n.btype == "1mint"));
+
+        // A batch in flight is not taken again.
+        assert!(notifications.take_due_deliveries(0).is_empty());
+
+        let delivered =
+            notifications.record_delivery(subscriber(1), deliveries[0].last_index(), Ok(()), 0);
+        assert_eq!(delivered, 100);
+        let remaining = notifications.take_due_deliveries(0);
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].notifications[0].index, 100);
+        assert_eq!(notifications.metrics()[0].delivered, 100);
+        assert_eq!(notifications.metrics()[0].queued, 50);
+    }
+
+    #[test]
+    fn test_failing_subscriber_is_suspended_then_resumes() {
+        let mut notifications = Notifications::default();
+        notifications
+            .subscribe(subscriber(1), "on_blocks".to_string(), None)
+            .unwrap();
+        notifications.enqueue(&notification(0, "1xfer"));
+
+        let mut now = 0;
+        let mut delay = 0;
+        for failure in 1..=MAX_CONSECUTIVE_FAILURES {
+            let deliveries = notifications.take_due_deliveries(now);
+            assert_eq!(deliveries.len(), 1, "attempt {}", failure);
+            notifications.record_delivery(subscriber(1), 0, Err("stopped".to_string()), now);
+
+            let metrics = &notifications.metrics()[0];
+            assert_eq!(metrics.consecutive_failures, failure);
+            assert_eq!(metrics.suspended, failure == MAX_CONSECUTIVE_FAILURES);
+            // Nothing is due before the backoff elapses.
+            assert!(notifications
+                .take_due_deliveries(metrics.next_attempt_at - 1)
+                .is_empty());
+            delay = metrics.next_attempt_at - now;
+            now = metrics.next_attempt_at;
+        }
+        // The backoff doubles, then a suspended subscriber is only probed rarely.
+        assert_eq!(delay, SUSPENDED_RETRY_INTERVAL.as_nanos() as u64);
+
+        // New blocks are still queued while suspended, and delivered on resumption.
+        notifications.enqueue(&notification(1, "1xfer"));
+        let deliveries = notifications.take_due_deliveries(now);
+        assert_eq!(deliveries[0].notifications.len(), 2);
+        notifications.record_delivery(subscriber(1), 1, Ok(()), now);
+
+        let metrics = &notifications.metrics()[0];
+        assert!(!metrics.suspended);
+        assert_eq!(metrics.consecutive_failures, 0);
+        assert_eq!(metrics.delivered, 2);
+        assert_eq!(metrics.last_error, None);
+    }
+
+    #[test]
+    fn test_full_queue_drops_the_oldest_notifications() {
+        let mut notifications = Notifications::default();
+        notifications
+            .subscribe(subscriber(1), "on_blocks".to_string(), None)
+            .unwrap();
+        for index in 0..SUBSCRIBER_QUEUE_CAPACITY as u64 + 5 {
+            notifications.enqueue(&notification(index, "1xfer"));
+        }
+
+        let metrics = &notifications.metrics()[0];
+        assert_eq!(metrics.queued, SUBSCRIBER_QUEUE_CAPACITY as u64);
+        assert_eq!(metrics.dropped, 5);
+        assert_eq!(
+            notifications.take_due_deliveries(0)[0].notifications[0].index,
+            5
+        );
+    }
+
+    #[test]
+    fn test_subscribe_validates_its_arguments() {
+        let mut notifications = Notifications::default();
+        assert!(notifications
+            .subscribe(subscriber(1), String::new(), None)
+            .is_err());
+        assert!(notifications
+            .subscribe(subscriber(1), "on_blocks".to_string(), Some(vec![]))
+            .is_err());
+
+        for id in 0..MAX_SUBSCRIBERS as u8 {
+            notifications
+                .subscribe(subscriber(id), "on_blocks".to_string(), None)
+                .unwrap();
+        }
+        assert!(notifications
+            .subscribe(subscriber(200), "on_blocks".to_string(), None)
+            .is_err());
+        // Subscribing again is still possible.
+        notifications
+            .subscribe(subscriber(0), "on_other_blocks".to_string(), None)
+            .unwrap();
+
+        assert!(notifications.unsubscribe(subscriber(0)));
+        assert!(!notifications.unsubscribe(subscriber(0)));
+    }
+}
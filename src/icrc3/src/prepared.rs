@@ -0,0 +1,40 @@
+//! Visibility into the prepared transactions that are not committed yet.
+//!
+//! A prepared transaction stays pending until it is committed or expires after
+//! `prepared_transaction_ttl`. When callers prepare transactions without
+//! committing them, the queue backs up: it can be listed with
+//! `list_prepared_transactions`, its size and the age of its oldest entry are
+//! reported by [`PreparedTransactionsMetrics`], and a warning is traced once it
+//! grows above `prepared_transactions_warning_threshold`.
+
+use bity_ic_types::TimestampNanos;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// A page of the pending prepared transactions, oldest first.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ListPreparedTransactionsArgs {
+    /// Maximum number of prepared transactions to return
+    pub limit: u16,
+    /// Number of prepared transactions to skip
+    pub offset: u32,
+}
+
+/// A pending prepared transaction.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PreparedTransactionInfo {
+    /// The hash of the transaction, hex encoded
+    pub hash_hex: String,
+    /// When the transaction was prepared, in nanoseconds
+    pub prepared_at_ns: TimestampNanos,
+    /// When the transaction can no longer be committed, in nanoseconds
+    pub expires_at_ns: TimestampNanos,
+}
+
+/// Size of the prepared transactions queue, for metrics.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PreparedTransactionsMetrics {
+    pub count: u64,
+    pub oldest_age_ns: Option<u64>,
+    pub warning_threshold: Option<u64>,
+}
@@ -0,0 +1,389 @@
+//! Rebuild of the ICRC3 state from its archive canisters, for disaster recovery.
+//!
+//! When the stable state of the main canister is lost but its archive canisters
+//! survive, a freshly installed canister can take the chain over from them. The
+//! range of each archive is read from the archive itself: the index of its first
+//! block and its number of blocks. The ranges must cover the chain from block 0
+//! without gaps or overlaps, and each archive must start with a block linked to
+//! the last block of the previous one. The blocks within an archive are not read,
+//! the verification job re-checks them once the chain is rebuilt.
+
+use crate::blockchain::block_transform::BlockTransformConfig;
+use crate::verification::verify_linkage;
+use bity_ic_icrc3_archive_api::get_encoded_blocks;
+use bity_ic_icrc3_archive_api::types::{
+    block_compression,
+    block_interface::{Block, BlockIndex},
+    defaultblock::DefaultBlock,
+    encoded_blocks::EncodedBlock,
+};
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+
+/// An archive canister taken over by a rebuild, storing the blocks `start..end`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RebuiltArchive {
+    pub canister_id: Principal,
+    pub start: u64,
+    pub end: u64,
+}
+
+/// The outcome of a rebuild.
+///
+/// # Fields
+///
+/// * `archives` - The archive canisters, ordered by their first block
+/// * `archived_chain_length` - The number of blocks stored in the archive canisters
+/// * `last_index` - The index of the last block of the chain
+/// * `last_hash` - The hash of the last block, the parent hash of the next one
+/// * `last_timestamp` - The timestamp of the last block, in nanoseconds
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RebuildReport {
+    pub archives: Vec<RebuiltArchive>,
+    pub archived_chain_length: u64,
+    pub last_index: u64,
+    pub last_hash: ByteBuf,
+    pub last_timestamp: u64,
+}
+
+/// Why a rebuild was aborted. Nothing is changed when a rebuild is aborted.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum RebuildError {
+    /// The chain already has blocks, only an empty chain can be rebuilt
+    ChainNotEmpty { chain_length: u64 },
+    /// No archive canister was given
+    NoArchives,
+    /// An archive canister was given more than once
+    DuplicateArchive { canister_id: Principal },
+    /// An archive canister could not be read
+    ArchiveUnavailable {
+        canister_id: Principal,
+        error: String,
+    },
+    /// An archive canister stores no block
+    EmptyArchive { canister_id: Principal },
+    /// An archive canister starts after the end of the previous one, or the
+    /// first one after block 0
+    Gap {
+        canister_id: Principal,
+        expected_start: u64,
+        start: u64,
+    },
+    /// An archive canister starts before the end of the previous one
+    Overlap {
+        canister_id: Principal,
+        start: u64,
+        previous_canister_id: Principal,
+        previous_end: u64,
+    },
+    /// The first block of an archive canister is not linked to the previous block
+    BrokenLink {
+        canister_id: Principal,
+        block_id: u64,
+        error: String,
+    },
+    /// The state could not be updated
+    Icrc3Error(String),
+}
+
+impl std::fmt::Display for RebuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for RebuildError {}
+
+/// The range of an archive canister along with its first and last blocks, opened.
+#[derive(Clone, Debug)]
+pub struct ArchiveEnds {
+    pub canister_id: Principal,
+    pub start: BlockIndex,
+    pub end: BlockIndex,
+    pub first_block: EncodedBlock,
+    pub last_block: EncodedBlock,
+}
+
+/// The archive canisters to read for a rebuild, taken from the ICRC3 instance so
+/// that they are read without holding its lock.
+///
+/// # Fields
+///
+/// * `archives` - The archive canisters, in any order
+/// * `block_transform` - The transform opening the blocks read from the archives
+#[derive(Clone, Debug)]
+pub struct RebuildPlan {
+    pub archives: Vec<Principal>,
+    pub block_transform: BlockTransformConfig,
+}
+
+impl RebuildPlan {
+    /// Reads the ends of the archive canisters, see [`read_archive_ends`].
+    pub async fn read_archive_ends(&self) -> Result<Vec<ArchiveEnds>, RebuildError> {
+        read_archive_ends(&self.archives, &self.block_transform).await
+    }
+}
+
+/// Reads the range and the first and last blocks of each archive canister.
+///
+/// # Arguments
+///
+/// * `archives` - The archive canisters, in any order
+/// * `block_transform` - The transform opening the blocks read from the archives
+pub async fn read_archive_ends(
+    archives: &[Principal],
+    block_transform: &BlockTransformConfig,
+) -> Result<Vec<ArchiveEnds>, RebuildError> {
+    let transform = block_transform
+        .transform()
+        .map_err(RebuildError::Icrc3Error)?;
+
+    let mut ends = vec![];
+    for (i, &canister_id) in archives.iter().enumerate() {
+        if archives[..i].contains(&canister_id) {
+            return Err(RebuildError::DuplicateArchive { canister_id });
+        }
+        let unavailable = |error: String| RebuildError::ArchiveUnavailable { canister_id, error };
+
+        let length = bity_ic_icrc3_archive_c2c_client::total_transactions(canister_id, &())
+            .await
+            .map_err(|e| unavailable(format!("{:?}", e)))? as u64;
+        if length == 0 {
+            return Err(RebuildError::EmptyArchive { canister_id });
+        }
+
+        // An archive returns its blocks from its first one, whatever the start.
+        let (start, first_block) = read_block(canister_id, 0).await?;
+        let (_, last_block) = if length == 1 {
+            (start, first_block.clone())
+        } else {
+            read_block(canister_id, start + length - 1).await?
+        };
+
+        let open = |block: EncodedBlock| {
+            transform
+                .open(block)
+                .and_then(block_compression::decompress)
+                .map_err(unavailable)
+        };
+        ends.push(ArchiveEnds {
+            canister_id,
+            start,
+            end: start + length,
+            first_block: open(first_block)?,
+            last_block: open(last_block)?,
+        });
+    }
+    Ok(ends)
+}
+
+/// Reads the first block stored by an archive canister from `start` on, with its index.
+async fn read_block(
+    canister_id: Principal,
+    start: BlockIndex,
+) -> Result<(BlockIndex, EncodedBlock), RebuildError> {
+    let args = get_encoded_blocks::Args { start, length: 1 };
+    bity_ic_icrc3_archive_c2c_client::get_encoded_blocks(canister_id, &args)
+        .await
+        .map_err(|e| format!("{:?}", e))
+        .and_then(|blocks| {
+            blocks
+                .into_iter()
+                .next()
+                .ok_or_else(|| format!("block {} is missing", start))
+        })
+        .map_err(|error| RebuildError::ArchiveUnavailable { canister_id, error })
+}
+
+/// Orders the archive canisters by their first block and checks that they cover
+/// the chain from block 0, each one linked to the previous one and the first one
+/// to `genesis_parent_hash`, see [`crate::genesis`].
+///
+/// # Returns
+///
+/// * `Ok(RebuildReport)` describing the rebuilt chain
+/// * `Err(RebuildError)` for the first gap, overlap or broken link
+pub fn check_archive_ends(
+    mut ends: Vec<ArchiveEnds>,
+    genesis_parent_hash: Option<[u8; 32]>,
+) -> Result<RebuildReport, RebuildError> {
+    ends.sort_by_key(|archive| archive.start);
+
+    let mut previous: Option<&ArchiveEnds> = None;
+    for archive in &ends {
+        let expected_start = previous.map_or(0, |previous| previous.end);
+        if archive.start > expected_start {
+            return Err(RebuildError::Gap {
+                canister_id: archive.canister_id,
+                expected_start,
+                start: archive.start,
+            });
+        }
+        if let Some(previous) = previous.filter(|_| archive.start < expected_start) {
+            return Err(RebuildError::Overlap {
+                canister_id: archive.canister_id,
+                start: archive.start,
+                previous_canister_id: previous.canister_id,
+                previous_end: previous.end,
+            });
+        }
+
+        let mut boundary = vec![];
+        if let Some(previous) = previous {
+            boundary.push((previous.end - 1, previous.last_block.clone()));
+        }
+        boundary.push((archive.start, archive.first_block.clone()));
+        verify_linkage(&boundary, genesis_parent_hash).map_err(|error| {
+            RebuildError::BrokenLink {
+                canister_id: archive.canister_id,
+                block_id: archive.start,
+                error,
+            }
+        })?;
+        previous = Some(archive);
+    }
+
+    let last = previous.ok_or(RebuildError::NoArchives)?;
+    let last_block = DefaultBlock::decode(last.last_block.clone()).map_err(|error| {
+        RebuildError::BrokenLink {
+            canister_id: last.canister_id,
+            block_id: last.end - 1,
+            error,
+        }
+    })?;
+    Ok(RebuildReport {
+        archives: ends
+            .iter()
+            .map(|archive| RebuiltArchive {
+                canister_id: archive.canister_id,
+                start: archive.start,
+                end: archive.end,
+            })
+            .collect(),
+        archived_chain_length: last.end,
+        last_index: last.end - 1,
+        last_hash: ByteBuf::from(
+            DefaultBlock::block_hash(&last.last_block)
+                .into_bytes()
+                .to_vec(),
+        ),
+        last_timestamp: last_block.timestamp() as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bity_ic_icrc3_archive_api::types::hash::HashOf;
+    use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+    use std::collections::BTreeMap;
+
+    fn chain(length: u64, salt: u64) -> Vec<EncodedBlock> {
+        let mut blocks = vec![];
+        let mut parent_hash: Option<HashOf<EncodedBlock>> = None;
+        for block_id in 0..length {
+            let transaction = ICRC3Value::Map(BTreeMap::from([
+                ("id".to_string(), ICRC3Value::Nat(block_id.into())),
+                ("salt".to_string(), ICRC3Value::Nat(salt.into())),
+            ]));
+            let encoded =
+                DefaultBlock::from_transaction(parent_hash, transaction, block_id as u128).encode();
+            parent_hash = Some(DefaultBlock::block_hash(&encoded));
+            blocks.push(encoded);
+        }
+        blocks
+    }
+
+    fn archive(id: u8, blocks: &[EncodedBlock], start: u64, end: u64) -> ArchiveEnds {
+        ArchiveEnds {
+            canister_id: Principal::from_slice(&[id]),
+            start,
+            end,
+            first_block: blocks[start as usize].clone(),
+            last_block: blocks[end as usize - 1].clone(),
+        }
+    }
+
+    #[test]
+    fn test_contiguous_archives_are_rebuilt() {
+        let blocks = chain(10, 0);
+        let report = check_archive_ends(
+            vec![archive(2, &blocks, 6, 10), archive(1, &blocks, 0, 6)],
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            report.archives,
+            vec![
+                RebuiltArchive {
+                    canister_id: Principal::from_slice(&[1]),
+                    start: 0,
+                    end: 6,
+                },
+                RebuiltArchive {
+                    canister_id: Principal::from_slice(&[2]),
+                    start: 6,
+                    end: 10,
+                },
+            ]
+        );
+        assert_eq!(report.archived_chain_length, 10);
+        assert_eq!(report.last_index, 9);
+        assert_eq!(
+            report.last_hash.as_slice(),
+            DefaultBlock::block_hash(&blocks[9]).as_slice()
+        );
+        assert_eq!(report.last_timestamp, 9);
+    }
+
+    #[test]
+    fn test_gaps_overlaps_and_broken_links_abort() {
+        let blocks = chain(10, 0);
+        assert_eq!(
+            check_archive_ends(
+                vec![archive(1, &blocks, 0, 5), archive(2, &blocks, 6, 10)],
+                None
+            ),
+            Err(RebuildError::Gap {
+                canister_id: Principal::from_slice(&[2]),
+                expected_start: 5,
+                start: 6,
+            })
+        );
+        assert!(matches!(
+            check_archive_ends(vec![archive(1, &blocks, 2, 10)], None),
+            Err(RebuildError::Gap {
+                expected_start: 0,
+                ..
+            })
+        ));
+        assert_eq!(
+            check_archive_ends(
+                vec![archive(1, &blocks, 0, 6), archive(2, &blocks, 4, 10)],
+                None
+            ),
+            Err(RebuildError::Overlap {
+                canister_id: Principal::from_slice(&[2]),
+                start: 4,
+                previous_canister_id: Principal::from_slice(&[1]),
+                previous_end: 6,
+            })
+        );
+
+        // The second archive holds the blocks of another chain.
+        let other = chain(10, 1);
+        assert!(matches!(
+            check_archive_ends(
+                vec![archive(1, &blocks, 0, 5), archive(2, &other, 5, 10)],
+                None
+            ),
+            Err(RebuildError::BrokenLink { block_id: 5, .. })
+        ));
+        assert_eq!(
+            check_archive_ends(vec![], None),
+            Err(RebuildError::NoArchives)
+        );
+    }
+}
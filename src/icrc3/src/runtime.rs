@@ -0,0 +1,171 @@
+//! System API used by the ICRC3 implementation.
+//!
+//! On-chain these functions forward to `ic_cdk`. With the `host-test` feature they
+//! read from a shim set up by the tests instead, so the library can be exercised
+//! with `cargo test`: the time comes from the `bity_ic_canister_time` mock clock and
+//! the canister id, caller, controllers, data certificate and instruction counter
+//! are set with the [`host`] functions.
+
+use candid::Principal;
+
+/// Returns the current time in nanoseconds.
+pub fn time() -> u64 {
+    #[cfg(feature = "host-test")]
+    {
+        bity_ic_canister_time::Nanos::from(bity_ic_canister_time::timestamp_nanos()).get()
+    }
+    #[cfg(not(feature = "host-test"))]
+    {
+        ic_cdk::api::time()
+    }
+}
+
+/// Returns the id of the current canister.
+pub fn canister_self() -> Principal {
+    #[cfg(feature = "host-test")]
+    {
+        host::SHIM.with(|shim| shim.borrow().canister_self)
+    }
+    #[cfg(not(feature = "host-test"))]
+    {
+        ic_cdk::api::canister_self()
+    }
+}
+
+/// Returns the caller of the current call.
+pub fn caller() -> Principal {
+    #[cfg(feature = "host-test")]
+    {
+        host::SHIM.with(|shim| shim.borrow().caller)
+    }
+    #[cfg(not(feature = "host-test"))]
+    {
+        ic_cdk::api::msg_caller()
+    }
+}
+
+/// Returns whether a principal is a controller of the current canister.
+pub fn is_controller(principal: &Principal) -> bool {
+    #[cfg(feature = "host-test")]
+    {
+        host::SHIM.with(|shim| shim.borrow().controllers.contains(principal))
+    }
+    #[cfg(not(feature = "host-test"))]
+    {
+        ic_cdk::api::is_controller(principal)
+    }
+}
+
+/// Sets the certified data of the canister.
+pub fn certified_data_set(data: impl AsRef<[u8]>) {
+    #[cfg(feature = "host-test")]
+    {
+        host::SHIM.with(|shim| shim.borrow_mut().certified_data = data.as_ref().to_vec());
+    }
+    #[cfg(not(feature = "host-test"))]
+    {
+        ic_cdk::api::certified_data_set(data);
+    }
+}
+
+/// Returns the data certificate, only available in query calls.
+pub fn data_certificate() -> Option<Vec<u8>> {
+    #[cfg(feature = "host-test")]
+    {
+        host::SHIM.with(|shim| shim.borrow().data_certificate.clone())
+    }
+    #[cfg(not(feature = "host-test"))]
+    {
+        ic_cdk::api::data_certificate()
+    }
+}
+
+/// Returns the number of instructions executed in the current call context,
+/// including the messages before its last await.
+pub fn instruction_counter() -> u64 {
+    #[cfg(feature = "host-test")]
+    {
+        host::SHIM.with(|shim| shim.borrow().instructions)
+    }
+    #[cfg(not(feature = "host-test"))]
+    {
+        ic_cdk::api::call_context_instruction_counter()
+    }
+}
+
+/// Aborts the current call with the given message.
+pub fn trap(message: impl AsRef<str>) -> ! {
+    #[cfg(feature = "host-test")]
+    {
+        panic!("{}", message.as_ref())
+    }
+    #[cfg(not(feature = "host-test"))]
+    {
+        ic_cdk::trap(message)
+    }
+}
+
+/// Shim replacing the system API in host tests.
+///
+/// The shim is local to the current thread. The canister id and caller default to
+/// the anonymous principal, and there are no controllers and no data certificate
+/// until they are set.
+#[cfg(feature = "host-test")]
+pub mod host {
+    use candid::Principal;
+    use std::cell::RefCell;
+
+    pub(super) struct Shim {
+        pub(super) canister_self: Principal,
+        pub(super) caller: Principal,
+        pub(super) controllers: Vec<Principal>,
+        pub(super) certified_data: Vec<u8>,
+        pub(super) data_certificate: Option<Vec<u8>>,
+        pub(super) instructions: u64,
+    }
+
+    thread_local! {
+        pub(super) static SHIM: RefCell<Shim> = const {
+            RefCell::new(Shim {
+                canister_self: Principal::anonymous(),
+                caller: Principal::anonymous(),
+                controllers: Vec::new(),
+                certified_data: Vec::new(),
+                data_certificate: None,
+                instructions: 0,
+            })
+        };
+    }
+
+    pub use bity_ic_canister_time::{advance_mock_time, set_mock_time};
+
+    /// Sets the id returned by `canister_self`.
+    pub fn set_canister_self(canister_id: Principal) {
+        SHIM.with(|shim| shim.borrow_mut().canister_self = canister_id);
+    }
+
+    /// Sets the principal returned by `caller`.
+    pub fn set_caller(caller: Principal) {
+        SHIM.with(|shim| shim.borrow_mut().caller = caller);
+    }
+
+    /// Sets the principals for which `is_controller` returns true.
+    pub fn set_controllers(controllers: Vec<Principal>) {
+        SHIM.with(|shim| shim.borrow_mut().controllers = controllers);
+    }
+
+    /// Sets the certificate returned by `data_certificate`.
+    pub fn set_data_certificate(certificate: Option<Vec<u8>>) {
+        SHIM.with(|shim| shim.borrow_mut().data_certificate = certificate);
+    }
+
+    /// Adds to the count returned by `instruction_counter`.
+    pub fn advance_instruction_counter(instructions: u64) {
+        SHIM.with(|shim| shim.borrow_mut().instructions += instructions);
+    }
+
+    /// Returns the data last passed to `certified_data_set`.
+    pub fn certified_data() -> Vec<u8> {
+        SHIM.with(|shim| shim.borrow().certified_data.clone())
+    }
+}
@@ -0,0 +1,442 @@
+//! Machine-readable description of the blocks recorded by ICRC3.
+//!
+//! A [`TransactionSchema`] lists the fields of the transactions of a block type,
+//! with the kind of their value and whether they are required. The built-in
+//! transaction types describe their block types with [`TransactionType::schemas`],
+//! custom types register theirs on the ICRC3 instance.
+//!
+//! The same schema is returned by `icrc3_block_schemas` and checked against each
+//! transaction added with its block type, so the documentation cannot drift from
+//! what is accepted: a field missing from the schema is rejected. The built-in
+//! schemas are only checked against the built-in transaction types, a custom type
+//! reusing one of their block types registers its own schema.
+
+use crate::audit::AuditTransaction;
+use crate::transaction::{
+    ICRC1Transaction, ICRC2Transaction, ICRC37Transaction, ICRC7Transaction, TransactionType,
+};
+use candid::CandidType;
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use icrc_ledger_types::icrc3::blocks::SupportedBlockType;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The kind of an [`ICRC3Value`].
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueKind {
+    Nat,
+    Int,
+    Text,
+    Blob,
+    Array,
+    Map,
+    /// Any kind of value
+    Any,
+}
+
+impl ValueKind {
+    /// Returns whether a value is of this kind.
+    pub fn matches(&self, value: &ICRC3Value) -> bool {
+        matches!(
+            (self, value),
+            (ValueKind::Any, _)
+                | (ValueKind::Nat, ICRC3Value::Nat(_))
+                | (ValueKind::Int, ICRC3Value::Int(_))
+                | (ValueKind::Text, ICRC3Value::Text(_))
+                | (ValueKind::Blob, ICRC3Value::Blob(_))
+                | (ValueKind::Array, ICRC3Value::Array(_))
+                | (ValueKind::Map, ICRC3Value::Map(_))
+        )
+    }
+}
+
+/// A field of a transaction.
+///
+/// # Fields
+///
+/// * `name` - The key of the field in its map
+/// * `kind` - The kind of its value
+/// * `required` - Whether the field must be present
+/// * `fields` - The fields of a `Map` value, left unchecked when empty
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FieldSchema {
+    pub name: String,
+    pub kind: ValueKind,
+    pub required: bool,
+    pub fields: Vec<FieldSchema>,
+}
+
+impl FieldSchema {
+    /// Creates a field that must be present.
+    pub fn required(name: impl Into<String>, kind: ValueKind) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+            required: true,
+            fields: vec![],
+        }
+    }
+
+    /// Creates a field that may be left out.
+    pub fn optional(name: impl Into<String>, kind: ValueKind) -> Self {
+        Self {
+            required: false,
+            ..Self::required(name, kind)
+        }
+    }
+
+    /// Creates a required map field with the given fields.
+    pub fn map(name: impl Into<String>, fields: Vec<FieldSchema>) -> Self {
+        Self {
+            fields,
+            ..Self::required(name, ValueKind::Map)
+        }
+    }
+}
+
+/// The fields of the transactions of a block type, as converted to an [`ICRC3Value`].
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TransactionSchema {
+    pub btype: String,
+    pub fields: Vec<FieldSchema>,
+}
+
+impl TransactionSchema {
+    /// Creates the schema of the block type `btype`.
+    pub fn new(btype: impl Into<String>, fields: Vec<FieldSchema>) -> Self {
+        Self {
+            btype: btype.into(),
+            fields,
+        }
+    }
+
+    /// Checks a transaction against the schema.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction converted to an `ICRC3Value`, before
+    ///   ICRC3 adds its own fields such as `phash`
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the transaction has the required fields, of the right kind,
+    ///   and no other field
+    /// * `Err(String)` describing the first mismatch
+    pub fn validate(&self, transaction: &ICRC3Value) -> Result<(), String> {
+        validate_fields(&self.btype, &self.fields, transaction, "")
+    }
+}
+
+fn validate_fields(
+    btype: &str,
+    fields: &[FieldSchema],
+    value: &ICRC3Value,
+    path: &str,
+) -> Result<(), String> {
+    let ICRC3Value::Map(map) = value else {
+        return Err(match path {
+            "" => "Transaction is supposed to be a map".to_string(),
+            _ => format!("{} is supposed to be a map", path.trim_end_matches('.')),
+        });
+    };
+
+    for field in fields {
+        match map.get(&field.name) {
+            None if field.required => {
+                return Err(format!("{}{} is required for {}", path, field.name, btype));
+            }
+            None => {}
+            Some(value) if !field.kind.matches(value) => {
+                return Err(format!(
+                    "{}{} is supposed to be a {:?}",
+                    path, field.name, field.kind
+                ));
+            }
+            Some(value) if field.kind == ValueKind::Map && !field.fields.is_empty() => {
+                let path = format!("{}{}.", path, field.name);
+                validate_fields(btype, &field.fields, value, &path)?;
+            }
+            Some(_) => {}
+        }
+    }
+
+    if let Some(key) = map
+        .keys()
+        .find(|key| !fields.iter().any(|field| &field.name == *key))
+    {
+        return Err(format!("{}{} is not allowed for {}", path, key, btype));
+    }
+    Ok(())
+}
+
+/// The documentation of a supported block type, as returned by `icrc3_block_schemas`.
+///
+/// # Fields
+///
+/// * `btype` - The block type
+/// * `url` - The URL of its specification
+/// * `fields` - Its fields, empty when no schema is known for the block type
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BlockSchema {
+    pub btype: String,
+    pub url: String,
+    pub fields: Vec<FieldSchema>,
+}
+
+/// Returns the schemas of the block types of the built-in transaction types.
+pub fn builtin_schemas() -> Vec<TransactionSchema> {
+    let mut schemas = ICRC1Transaction::schemas();
+    schemas.extend(ICRC2Transaction::schemas());
+    schemas.extend(ICRC7Transaction::schemas());
+    schemas.extend(ICRC37Transaction::schemas());
+    schemas.extend(AuditTransaction::schemas());
+    schemas
+}
+
+/// Returns the schema transactions of type `T` with a block type are checked
+/// against: the registered one, or else the one of `T`, see
+/// [`TransactionType::schemas`].
+///
+/// The built-in schemas are only applied to the built-in transaction types, a
+/// custom type reusing one of their block types is only checked against the
+/// schema registered for it.
+///
+/// # Arguments
+///
+/// * `btype` - The block type
+/// * `registered` - The schemas registered by the canister, by block type
+pub fn schema_for<T: TransactionType>(
+    btype: &str,
+    registered: &BTreeMap<String, TransactionSchema>,
+) -> Option<TransactionSchema> {
+    registered.get(btype).cloned().or_else(|| {
+        T::schemas()
+            .into_iter()
+            .find(|schema| schema.btype == btype)
+    })
+}
+
+/// Documents the supported block types, in the order they are configured, with
+/// their registered schema or else their built-in one.
+///
+/// # Arguments
+///
+/// * `supported_blocks` - The block types of the configuration
+/// * `registered` - The schemas registered by the canister, by block type
+pub fn block_schemas(
+    supported_blocks: &[SupportedBlockType],
+    registered: &BTreeMap<String, TransactionSchema>,
+) -> Vec<BlockSchema> {
+    supported_blocks
+        .iter()
+        .map(|supported| BlockSchema {
+            btype: supported.block_type.clone(),
+            url: supported.url.clone(),
+            fields: registered
+                .get(&supported.block_type)
+                .cloned()
+                .or_else(|| {
+                    builtin_schemas()
+                        .into_iter()
+                        .find(|schema| schema.btype == supported.block_type)
+                })
+                .map(|schema| schema.fields)
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Renders block schemas as a JSON array, e.g. for an `http_request` route.
+pub fn block_schemas_json(schemas: &[BlockSchema]) -> String {
+    serde_json::to_string(schemas).unwrap_or_else(|_| "[]".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::ICRC1TransactionData;
+    use bity_ic_types::TimestampNanos;
+    use candid::{Nat, Principal};
+    use icrc_ledger_types::icrc1::account::Account;
+    use serde_bytes::ByteBuf;
+
+    fn account(byte: u8) -> Option<Account> {
+        Some(Account {
+            owner: Principal::from_slice(&[byte]),
+            subaccount: None,
+        })
+    }
+
+    fn keys(value: &ICRC3Value) -> Vec<String> {
+        match value {
+            ICRC3Value::Map(map) => map.keys().cloned().collect(),
+            _ => panic!("value is not a map"),
+        }
+    }
+
+    fn names(fields: &[FieldSchema], required_only: bool) -> Vec<String> {
+        let mut names: Vec<String> = fields
+            .iter()
+            .filter(|field| field.required || !required_only)
+            .map(|field| field.name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn xfer(tx: ICRC1TransactionData) -> ICRC1Transaction {
+        ICRC1Transaction::new("1xfer".to_string(), 1_000, Nat::from(0u64), tx)
+    }
+
+    #[test]
+    fn test_icrc1_xfer_schema_matches_emitted_fields() {
+        let schema = schema_for::<ICRC1Transaction>("1xfer", &BTreeMap::new()).unwrap();
+        let tx_fields = &schema
+            .fields
+            .iter()
+            .find(|field| field.name == "tx")
+            .unwrap()
+            .fields;
+
+        let full = ICRC1TransactionData {
+            op: Some("xfer".to_string()),
+            amount: Nat::from(5u64),
+            from: account(1),
+            to: account(2),
+            memo: Some(ByteBuf::from(vec![1])),
+            created_at_time: Some(Nat::from(10u64)),
+            fee: Some(Nat::from(1u64)),
+        };
+        assert_eq!(keys(&full.clone().into()), names(tx_fields, false));
+
+        let minimal = ICRC1TransactionData {
+            op: None,
+            memo: None,
+            created_at_time: None,
+            fee: None,
+            ..full.clone()
+        };
+        assert_eq!(keys(&minimal.clone().into()), names(tx_fields, true));
+
+        let transaction: ICRC3Value = xfer(full).into();
+        assert_eq!(keys(&transaction), names(&schema.fields, false));
+        assert_eq!(schema.validate(&transaction), Ok(()));
+        assert_eq!(schema.validate(&xfer(minimal).into()), Ok(()));
+    }
+
+    #[test]
+    fn test_validation_reports_mismatches() {
+        let schema = schema_for::<ICRC1Transaction>("1mint", &BTreeMap::new()).unwrap();
+        let mint = |from, to| -> ICRC3Value {
+            ICRC1Transaction::new(
+                "1mint".to_string(),
+                1_000,
+                Nat::from(0u64),
+                ICRC1TransactionData {
+                    op: None,
+                    amount: Nat::from(5u64),
+                    from,
+                    to,
+                    memo: None,
+                    created_at_time: None,
+                    fee: None,
+                },
+            )
+            .into()
+        };
+
+        assert_eq!(schema.validate(&mint(None, account(2))), Ok(()));
+        assert_eq!(
+            schema.validate(&mint(None, None)),
+            Err("tx.to is required for 1mint".to_string())
+        );
+        assert_eq!(
+            schema.validate(&mint(account(1), account(2))),
+            Err("tx.from is not allowed for 1mint".to_string())
+        );
+
+        let mut transaction = mint(None, account(2));
+        if let ICRC3Value::Map(map) = &mut transaction {
+            map.insert("ts".to_string(), ICRC3Value::Text("now".to_string()));
+        }
+        assert_eq!(
+            schema.validate(&transaction),
+            Err("ts is supposed to be a Nat".to_string())
+        );
+    }
+
+    /// A custom transaction type without schemas.
+    #[derive(Clone)]
+    struct CustomTransaction;
+
+    impl From<CustomTransaction> for ICRC3Value {
+        fn from(_: CustomTransaction) -> Self {
+            ICRC3Value::Map(BTreeMap::new())
+        }
+    }
+
+    impl TransactionType for CustomTransaction {
+        fn validate_transaction_fields(&self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn timestamp(&self) -> Option<TimestampNanos> {
+            None
+        }
+
+        fn tx(&self) -> ICRC3Value {
+            ICRC3Value::Map(BTreeMap::new())
+        }
+
+        fn block_type(&self) -> String {
+            "1xfer".to_string()
+        }
+    }
+
+    #[test]
+    fn test_builtin_schemas_only_apply_to_builtin_types() {
+        assert!(schema_for::<ICRC1Transaction>("1xfer", &BTreeMap::new()).is_some());
+        assert!(schema_for::<CustomTransaction>("1xfer", &BTreeMap::new()).is_none());
+
+        let custom = TransactionSchema::new(
+            "1xfer",
+            vec![FieldSchema::required("btype", ValueKind::Text)],
+        );
+        let registered = BTreeMap::from([("1xfer".to_string(), custom.clone())]);
+        assert_eq!(
+            schema_for::<CustomTransaction>("1xfer", &registered),
+            Some(custom)
+        );
+    }
+
+    #[test]
+    fn test_registered_schemas_take_precedence() {
+        let supported = |btype: &str| SupportedBlockType {
+            block_type: btype.to_string(),
+            url: format!("https://example.com/{btype}"),
+        };
+        let custom = TransactionSchema::new(
+            "1xfer",
+            vec![FieldSchema::required("btype", ValueKind::Text)],
+        );
+        let registered = BTreeMap::from([("1xfer".to_string(), custom.clone())]);
+
+        let schemas = block_schemas(
+            &[supported("1xfer"), supported("1burn"), supported("custom")],
+            &registered,
+        );
+        assert_eq!(schemas[0].fields, custom.fields);
+        assert_eq!(schemas[0].url, "https://example.com/1xfer");
+        assert_eq!(
+            schemas[1].fields,
+            schema_for::<ICRC1Transaction>("1burn", &BTreeMap::new())
+                .unwrap()
+                .fields
+        );
+        assert!(schemas[2].fields.is_empty());
+
+        let json: serde_json::Value = serde_json::from_str(&block_schemas_json(&schemas)).unwrap();
+        assert_eq!(json[0]["btype"], "1xfer");
+        assert_eq!(json[0]["fields"][0]["kind"], "Text");
+    }
+}
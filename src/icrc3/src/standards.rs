@@ -0,0 +1,296 @@
+//! Supported standards, as returned by `icrc10_supported_standards`.
+//!
+//! The standards implemented by a ledger built on ICRC3 follow from the block
+//! types it records: [`icrc3_standard_records`] derives them from the
+//! configuration, and [`merge_standard_records`] adds the standards that the
+//! canister implements on its own.
+//!
+//! [`validate_supported_blocks`] checks the configured block types against the
+//! operations of the standards they claim to belong to.
+
+use crate::config::ICRC3Config;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// A standard supported by a canister, as defined by ICRC-10.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct StandardRecord {
+    /// The name of the standard, e.g. `ICRC-3`
+    pub name: String,
+    /// The URL of the specification of the standard
+    pub url: String,
+}
+
+impl StandardRecord {
+    /// Creates a record for the standard `name` specified at `url`.
+    pub fn new(name: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            url: url.into(),
+        }
+    }
+}
+
+/// The standard number, name and specification URL of the standards whose
+/// blocks can be recorded, in increasing number.
+const BLOCK_STANDARDS: [(u32, &str, &str); 5] = [
+    (
+        1,
+        "ICRC-1",
+        "https://github.com/dfinity/ICRC-1/tree/main/standards/ICRC-1",
+    ),
+    (
+        2,
+        "ICRC-2",
+        "https://github.com/dfinity/ICRC-1/tree/main/standards/ICRC-2",
+    ),
+    (
+        3,
+        "ICRC-3",
+        "https://github.com/dfinity/ICRC-1/tree/main/standards/ICRC-3",
+    ),
+    (
+        7,
+        "ICRC-7",
+        "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-7/ICRC-7.md",
+    ),
+    (
+        37,
+        "ICRC-37",
+        "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-37/ICRC-37.md",
+    ),
+];
+
+/// The block types defined by the standards whose operations are checked by
+/// [`validate_supported_blocks`].
+const STANDARD_BLOCK_TYPES: [(u32, &[&str]); 4] = [
+    (1, &["1burn", "1mint", "1xfer"]),
+    (2, &["2approve", "2xfer"]),
+    (7, &["7burn", "7mint", "7update_token", "7xfer"]),
+    (
+        37,
+        &[
+            "37approve",
+            "37approve_coll",
+            "37revoke",
+            "37revoke_coll",
+            "37xfer",
+        ],
+    ),
+];
+
+/// The ICRC-10 standard, supported by any canister answering `icrc10_supported_standards`.
+pub fn icrc10_standard_record() -> StandardRecord {
+    StandardRecord::new(
+        "ICRC-10",
+        "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-10/ICRC-10.md",
+    )
+}
+
+/// Returns the standard number a block type belongs to, e.g. 37 for `37approve_coll`.
+fn block_type_standard(block_type: &str) -> Option<u32> {
+    let digits = block_type
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(block_type.len());
+    block_type[..digits].parse().ok()
+}
+
+/// Returns the standards implemented according to the configured block types.
+///
+/// ICRC-3 is always included. ICRC-1, ICRC-2, ICRC-7 and ICRC-37 are included
+/// when one of their block types is supported. ICRC-2 extends ICRC-1 and ICRC-37
+/// extends ICRC-7, so the extended standard is included along with them.
+///
+/// # Arguments
+/// * `config` - The configuration of the ICRC3 instance
+pub fn icrc3_standard_records(config: &ICRC3Config) -> Vec<StandardRecord> {
+    let mut numbers = vec![3];
+    for supported in &config.supported_blocks {
+        match block_type_standard(&supported.block_type) {
+            Some(2) => numbers.extend([1, 2]),
+            Some(37) => numbers.extend([7, 37]),
+            Some(number) => numbers.push(number),
+            None => {}
+        }
+    }
+
+    BLOCK_STANDARDS
+        .iter()
+        .filter(|(number, _, _)| numbers.contains(number))
+        .map(|(_, name, url)| StandardRecord::new(*name, *url))
+        .collect()
+}
+
+/// Checks the block types of the configuration.
+///
+/// A block type may only be listed once. A block type whose number is one of
+/// ICRC-1, ICRC-2, ICRC-7 or ICRC-37, e.g. `7transfer`, must be one of the
+/// operations of that standard unless it is listed in `custom_block_types`.
+///
+/// # Arguments
+/// * `config` - The configuration of the ICRC3 instance
+///
+/// # Errors
+///
+/// Returns an error message naming the first offending block type.
+pub fn validate_supported_blocks(config: &ICRC3Config) -> Result<(), String> {
+    for (i, supported) in config.supported_blocks.iter().enumerate() {
+        let block_type = &supported.block_type;
+        if config.supported_blocks[..i]
+            .iter()
+            .any(|b| &b.block_type == block_type)
+        {
+            return Err(format!(
+                "Block type {} is listed more than once",
+                block_type
+            ));
+        }
+        if config.custom_block_types.contains(block_type) {
+            continue;
+        }
+        let Some(number) = block_type_standard(block_type) else {
+            continue;
+        };
+        if let Some((_, block_types)) = STANDARD_BLOCK_TYPES.iter().find(|(n, _)| *n == number) {
+            if !block_types.contains(&block_type.as_str()) {
+                return Err(format!(
+                    "Block type {} is not an operation of ICRC-{} (expected one of: {}). \
+                     List it in custom_block_types if it is a custom block type",
+                    block_type,
+                    number,
+                    block_types.join(", ")
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Merges the standards derived by ICRC3 with the standards the canister
+/// implements on its own, keeping one record per name.
+///
+/// The records of `extra` come first and take precedence, so a canister can
+/// override the URL of a derived standard.
+///
+/// # Arguments
+/// * `derived` - The standards returned by [`icrc3_standard_records`]
+/// * `extra` - The `(name, url)` of the other standards of the canister
+pub fn merge_standard_records(
+    derived: Vec<StandardRecord>,
+    extra: &[(&str, &str)],
+) -> Vec<StandardRecord> {
+    let mut records: Vec<StandardRecord> = Vec::with_capacity(extra.len() + derived.len());
+    let extra = extra
+        .iter()
+        .map(|(name, url)| StandardRecord::new(*name, *url));
+
+    for record in extra.chain(derived) {
+        if !records.iter().any(|r| r.name == record.name) {
+            records.push(record);
+        }
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use icrc_ledger_types::icrc3::blocks::SupportedBlockType;
+
+    fn config(block_types: &[&str]) -> ICRC3Config {
+        ICRC3Config {
+            supported_blocks: block_types
+                .iter()
+                .map(|block_type| SupportedBlockType {
+                    block_type: block_type.to_string(),
+                    url: "https://example.com".to_string(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn names(records: &[StandardRecord]) -> Vec<&str> {
+        records.iter().map(|r| r.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_icrc7_block_types() {
+        let records =
+            icrc3_standard_records(&config(&["7mint", "7burn", "7xfer", "7update_token"]));
+        assert_eq!(names(&records), vec!["ICRC-3", "ICRC-7"]);
+
+        assert_eq!(names(&icrc3_standard_records(&config(&[]))), vec!["ICRC-3"]);
+    }
+
+    #[test]
+    fn test_mixed_block_types() {
+        let records = icrc3_standard_records(&config(&["1xfer", "37approve_coll", "unknown"]));
+        assert_eq!(
+            names(&records),
+            vec!["ICRC-1", "ICRC-3", "ICRC-7", "ICRC-37"]
+        );
+
+        let records = icrc3_standard_records(&config(&["2approve", "1mint", "1burn"]));
+        assert_eq!(names(&records), vec!["ICRC-1", "ICRC-2", "ICRC-3"]);
+    }
+
+    #[test]
+    fn test_merge_deduplicates_by_name() {
+        let derived = icrc3_standard_records(&config(&["7xfer"]));
+        let merged = merge_standard_records(
+            derived,
+            &[
+                ("ICRC-7", "https://example.com/icrc7"),
+                ("ICRC-61", "https://example.com/icrc61"),
+            ],
+        );
+
+        assert_eq!(names(&merged), vec!["ICRC-7", "ICRC-61", "ICRC-3"]);
+        assert_eq!(merged[0].url, "https://example.com/icrc7");
+    }
+
+    #[test]
+    fn test_duplicate_block_types_are_rejected() {
+        assert_eq!(
+            validate_supported_blocks(&config(&["1mint", "1xfer", "1xfer"])),
+            Err("Block type 1xfer is listed more than once".to_string())
+        );
+        assert_eq!(
+            validate_supported_blocks(&config(&["1mint", "1burn", "1xfer", "2approve"])),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_unknown_standard_operations_are_rejected() {
+        let error = validate_supported_blocks(&config(&["7mint", "7transfer"])).unwrap_err();
+        assert!(error.contains("7transfer"), "{error}");
+        assert!(error.contains("ICRC-7"), "{error}");
+        assert!(error.contains("7xfer"), "{error}");
+
+        assert!(validate_supported_blocks(&config(&["37approve_coll", "37revoke_all"])).is_err());
+        // Only the numbers of the checked standards are checked.
+        assert_eq!(
+            validate_supported_blocks(&config(&["3xfer", "107feecol"])),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_custom_block_types_are_accepted() {
+        assert_eq!(
+            validate_supported_blocks(&config(&["gldt_swap", "7xfer"])),
+            Ok(())
+        );
+
+        let mut custom = config(&["7xfer", "7gldt_swap"]);
+        assert!(validate_supported_blocks(&custom).is_err());
+        custom.custom_block_types = vec!["7gldt_swap".to_string()];
+        assert_eq!(validate_supported_blocks(&custom), Ok(()));
+
+        // A custom block type is still listed only once.
+        custom.supported_blocks = config(&["7xfer", "7gldt_swap", "7gldt_swap"]).supported_blocks;
+        assert!(validate_supported_blocks(&custom).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! Fault injection, to reach the error paths of the library from integration tests.
+//!
+//! Only compiled with the `testing-hooks` feature: without it, the module and every
+//! hook calling into it are left out of the build. The faults are kept in a thread
+//! local, so they apply to the whole canister and are lost on upgrade.
+
+use crate::runtime;
+use bity_ic_icrc3_archive_api::types::encoded_blocks::EncodedBlock;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::time::Duration;
+
+/// The length of the parent hash at the start of an encoded block.
+const PARENT_HASH_LEN: usize = 32;
+
+/// A fault that can be injected with [`set_fault`].
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum FaultKind {
+    /// The next call inserting blocks into an archive canister fails
+    RejectNextArchiveInsert,
+    /// Each insertion into an archive canister waits `ms` milliseconds first
+    SlowArchiveInsert { ms: u64 },
+    /// New transactions are throttled as if the window were full
+    ForceThrottle,
+    /// The next block is added with a wrong parent hash, breaking the chain
+    CorruptNextBlockHash,
+}
+
+#[derive(Default)]
+struct Faults {
+    reject_next_archive_insert: bool,
+    slow_archive_insert_ms: Option<u64>,
+    force_throttle: bool,
+    corrupt_next_block_hash: bool,
+}
+
+thread_local! {
+    static FAULTS: RefCell<Faults> = RefCell::new(Faults::default());
+}
+
+/// Enables or disables a fault.
+///
+/// Disabling `SlowArchiveInsert` removes the delay whatever its `ms`.
+pub fn set_fault(fault: FaultKind, enabled: bool) {
+    FAULTS.with(|faults| {
+        let mut faults = faults.borrow_mut();
+        match fault {
+            FaultKind::RejectNextArchiveInsert => faults.reject_next_archive_insert = enabled,
+            FaultKind::SlowArchiveInsert { ms } => {
+                faults.slow_archive_insert_ms = enabled.then_some(ms)
+            }
+            FaultKind::ForceThrottle => faults.force_throttle = enabled,
+            FaultKind::CorruptNextBlockHash => faults.corrupt_next_block_hash = enabled,
+        }
+    });
+}
+
+/// Disables every fault.
+pub fn clear_faults() {
+    FAULTS.with(|faults| *faults.borrow_mut() = Faults::default());
+}
+
+/// Applies the archive insertion faults: waits for the configured delay, then
+/// fails if the next insertion was set to be rejected.
+pub(crate) async fn before_archive_insert() -> Result<(), String> {
+    if let Some(ms) = FAULTS.with(|faults| faults.borrow().slow_archive_insert_ms) {
+        wait(Duration::from_millis(ms)).await;
+    }
+    let reject =
+        FAULTS.with(|faults| std::mem::take(&mut faults.borrow_mut().reject_next_archive_insert));
+    if reject {
+        return Err("Injected fault: archive insert rejected".to_string());
+    }
+    Ok(())
+}
+
+/// Returns whether new transactions must be throttled regardless of the window.
+pub(crate) fn force_throttle() -> bool {
+    FAULTS.with(|faults| faults.borrow().force_throttle)
+}
+
+/// Corrupts the parent hash stored in the next block once, if the fault is set.
+///
+/// The block is corrupted after it passed the chain checks, so that it is stored
+/// and archived as is and the chain is broken.
+pub(crate) fn corrupt_next_block(block: EncodedBlock) -> EncodedBlock {
+    let corrupt =
+        FAULTS.with(|faults| std::mem::take(&mut faults.borrow_mut().corrupt_next_block_hash));
+    if !corrupt {
+        return block;
+    }
+    let mut bytes = block.into_vec();
+    bytes
+        .iter_mut()
+        .take(PARENT_HASH_LEN)
+        .for_each(|byte| *byte = !*byte);
+    EncodedBlock::from_vec(bytes)
+}
+
+/// Lets `delay` elapse, yielding to other calls meanwhile.
+async fn wait(delay: Duration) {
+    #[cfg(feature = "host-test")]
+    {
+        runtime::host::advance_mock_time(delay);
+    }
+    #[cfg(not(feature = "host-test"))]
+    {
+        let deadline = runtime::time().saturating_add(delay.as_nanos() as u64);
+        // Each call completes in a later round, so the time moves on.
+        while runtime::time() < deadline {
+            let _ = ic_cdk::management_canister::raw_rand().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_shot_faults_are_consumed() {
+        clear_faults();
+        let block = EncodedBlock::from_vec([[1; 32], [2; 32]].concat());
+
+        set_fault(FaultKind::CorruptNextBlockHash, true);
+        assert_eq!(
+            corrupt_next_block(block.clone()).into_vec(),
+            [[0xfe; 32], [2; 32]].concat()
+        );
+        assert_eq!(corrupt_next_block(block.clone()), block);
+
+        set_fault(FaultKind::RejectNextArchiveInsert, true);
+        assert!(futures::executor::block_on(before_archive_insert()).is_err());
+        assert!(futures::executor::block_on(before_archive_insert()).is_ok());
+
+        set_fault(FaultKind::ForceThrottle, true);
+        assert!(force_throttle());
+        clear_faults();
+        assert!(!force_throttle());
+    }
+}
@@ -0,0 +1,208 @@
+//! Lookup of blocks by transaction hash.
+//!
+//! A duplicate transaction is rejected with the index of the original block, but
+//! support staff investigating it often only have the hash of the transaction,
+//! and the original block may already be archived. The local blocks are searched
+//! first through a [`ThashIndex`], then the archive canisters, which index their
+//! blocks by transaction hash when `index_thashes` is set.
+//!
+//! The archive canisters hash the blocks they receive, which they can decompress
+//! but not open. `index_thashes` is therefore rejected along with a block
+//! transform, see [`validate_thash_indexing`].
+
+use crate::blockchain::block_transform::BlockTransformConfig;
+use bity_ic_canister_client::fan_out_calls;
+use bity_ic_icrc3_archive_api::types::hash::HASH_LENGTH;
+use candid::Principal;
+use icrc_ledger_types::icrc3::blocks::BlockWithId;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::collections::BTreeMap;
+
+/// The maximum number of archive canisters queried at the same time.
+pub const MAX_CONCURRENT_THASH_LOOKUPS: usize = 5;
+
+/// Checks that the archive canisters can index their blocks by transaction hash,
+/// which they can't when the blocks are sealed by a block transform.
+///
+/// # Arguments
+///
+/// * `index_thashes` - Whether the archive canisters index their blocks
+/// * `block_transform` - The transform sealing the archived blocks
+pub fn validate_thash_indexing(
+    index_thashes: bool,
+    block_transform: &BlockTransformConfig,
+) -> Result<(), String> {
+    if index_thashes && *block_transform != BlockTransformConfig::None {
+        return Err(
+            "index_thashes cannot be set along with a block transform, as archive canisters can't open the blocks they store"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// The indices of the local blocks by transaction hash.
+///
+/// Entries are added when a block is appended and pruned once their block leaves
+/// the local archive, as for the [`MemoIndex`](crate::memo_index::MemoIndex).
+/// When several local blocks record the same transaction, the most recent one is
+/// found.
+///
+/// # Fields
+///
+/// * `by_thash` - The index of the most recent block of each transaction hash
+/// * `by_block` - The transaction hash of each indexed block, used for pruning
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ThashIndex {
+    by_thash: BTreeMap<ByteBuf, u64>,
+    by_block: BTreeMap<u64, ByteBuf>,
+}
+
+impl ThashIndex {
+    /// Indexes the transaction hash of a block.
+    ///
+    /// # Arguments
+    ///
+    /// * `block_index` - The index of the block
+    /// * `thash` - The hash of the transaction recorded in the block
+    pub fn insert(&mut self, block_index: u64, thash: ByteBuf) {
+        self.by_thash.insert(thash.clone(), block_index);
+        self.by_block.insert(block_index, thash);
+    }
+
+    /// Returns the index of the most recent block recording a transaction.
+    pub fn find(&self, thash: &[u8]) -> Option<u64> {
+        self.by_thash.get(serde_bytes::Bytes::new(thash)).copied()
+    }
+
+    /// Removes the entries of the blocks before `block_index`.
+    ///
+    /// # Returns
+    ///
+    /// The number of removed entries
+    pub fn prune_before(&mut self, block_index: u64) -> usize {
+        let kept = self.by_block.split_off(&block_index);
+        let pruned = std::mem::replace(&mut self.by_block, kept);
+        for (index, thash) in &pruned {
+            if self.by_thash.get(thash) == Some(index) {
+                self.by_thash.remove(thash);
+            }
+        }
+        pruned.len()
+    }
+
+    /// Removes all the entries.
+    pub fn clear(&mut self) {
+        self.by_thash.clear();
+        self.by_block.clear();
+    }
+
+    /// Returns the number of indexed blocks.
+    pub fn len(&self) -> usize {
+        self.by_block.len()
+    }
+
+    /// Returns whether no block is indexed.
+    pub fn is_empty(&self) -> bool {
+        self.by_block.is_empty()
+    }
+}
+
+/// What a lookup needs from the ICRC3 state, taken beforehand so that the
+/// archive canisters are called without holding the ICRC3 lock.
+///
+/// # Fields
+///
+/// * `thash` - The transaction hash looked up
+/// * `local_block` - The local block recording the transaction, if any
+/// * `archives` - The archive canisters to query when no local block does
+#[derive(Clone, Debug)]
+pub struct ThashLookup {
+    pub thash: ByteBuf,
+    pub local_block: Option<BlockWithId>,
+    pub archives: Vec<Principal>,
+}
+
+impl ThashLookup {
+    /// Returns the block recording the transaction, querying the archive
+    /// canisters if it is not local.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(None)` if no block records the transaction, or the hash is not
+    ///   `HASH_LENGTH` bytes long
+    /// * `Err(String)` naming the archive canisters that could not be queried,
+    ///   when no other one found the block
+    pub async fn run(self) -> Result<Option<BlockWithId>, String> {
+        if self.local_block.is_some() || self.thash.len() != HASH_LENGTH {
+            return Ok(self.local_block);
+        }
+
+        let thash = &self.thash;
+        let results = fan_out_calls(
+            self.archives,
+            MAX_CONCURRENT_THASH_LOOKUPS,
+            |canister_id| async move {
+                bity_ic_icrc3_archive_c2c_client::find_block_by_thash(canister_id, thash).await
+            },
+        )
+        .await;
+
+        let mut failures = vec![];
+        for (canister_id, result) in results {
+            match result {
+                Ok(Some(block)) => return Ok(Some(block)),
+                Ok(None) => {}
+                Err(e) => failures.push(format!("{}: {:?}", canister_id, e)),
+            }
+        }
+        if failures.is_empty() {
+            Ok(None)
+        } else {
+            Err(format!(
+                "Failed to query archive canisters: {}",
+                failures.join("; ")
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thash(byte: u8) -> ByteBuf {
+        ByteBuf::from(vec![byte; HASH_LENGTH])
+    }
+
+    #[test]
+    fn test_most_recent_block_is_found_and_pruned() {
+        let mut index = ThashIndex::default();
+        index.insert(0, thash(1));
+        index.insert(1, thash(2));
+        index.insert(2, thash(1));
+
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.find(&thash(1)), Some(2));
+        assert_eq!(index.find(&thash(2)), Some(1));
+        assert_eq!(index.find(&thash(3)), None);
+
+        // Pruning an older block of a transaction keeps its most recent one.
+        assert_eq!(index.prune_before(2), 2);
+        assert_eq!(index.find(&thash(1)), Some(2));
+        assert_eq!(index.find(&thash(2)), None);
+        assert_eq!(index.len(), 1);
+    }
+
+    #[test]
+    fn test_thash_indexing_is_rejected_with_a_block_transform() {
+        let sealed = BlockTransformConfig::XChaCha20Poly1305 {
+            key: crate::blockchain::block_transform::EncryptionKey(ByteBuf::from(vec![42u8; 32])),
+        };
+
+        assert!(validate_thash_indexing(true, &BlockTransformConfig::None).is_ok());
+        assert!(validate_thash_indexing(false, &sealed).is_ok());
+        assert!(validate_thash_indexing(true, &sealed).is_err());
+    }
+}
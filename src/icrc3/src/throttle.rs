@@ -0,0 +1,157 @@
+//! Throttling decision for new transactions.
+//!
+//! The first half of `max_transactions_in_window` is accepted freely. Past that,
+//! transactions are accepted at a rate of at most `max_rate` per second, where
+//! `max_rate` would fill the second half of the window over one `tx_window`.
+//! The decision only depends on the timestamps of the transactions in the
+//! deduplication window, so it is kept as a pure function.
+
+use crate::config::ICRC3Properties;
+use std::time::Duration;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+/// The configuration used by [`should_throttle`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ThrottleParams {
+    /// The maximum number of transactions in the window, zero disables throttling
+    pub max_transactions_in_window: u128,
+    /// The duration of the deduplication window
+    pub tx_window: Duration,
+}
+
+impl ThrottleParams {
+    /// Returns the number of transactions per second accepted once the first
+    /// half of the window is used, rounded up.
+    ///
+    /// A zero `tx_window` has no rate limit besides the window itself.
+    pub fn max_rate(&self) -> u128 {
+        let window_nanos = self.tx_window.as_nanos();
+        if window_nanos == 0 {
+            return u128::MAX;
+        }
+        self.max_transactions_in_window
+            .saturating_mul(NANOS_PER_SECOND)
+            .div_ceil(2 * window_nanos)
+    }
+}
+
+impl From<&ICRC3Properties> for ThrottleParams {
+    fn from(properties: &ICRC3Properties) -> Self {
+        Self {
+            max_transactions_in_window: properties.max_transactions_in_window,
+            tx_window: properties.tx_window,
+        }
+    }
+}
+
+/// Returns whether a new transaction should be throttled.
+///
+/// Once half of the window is used, the transaction is throttled if the
+/// `max_rate` most recent transactions (or all of them, if there are fewer)
+/// happened during the last second.
+///
+/// # Arguments
+///
+/// * `window` - The timestamps of the transactions in the window in nanoseconds, oldest first
+/// * `now` - The current time in nanoseconds
+/// * `params` - The throttling configuration
+pub fn should_throttle(window: &[u128], now: u128, params: &ThrottleParams) -> bool {
+    let num_in_window = window.len() as u128;
+    if params.max_transactions_in_window == 0
+        || num_in_window < params.max_transactions_in_window / 2
+    {
+        return false;
+    }
+
+    let index = num_in_window.saturating_sub(params.max_rate()) as usize;
+    window
+        .get(index)
+        .is_some_and(|timestamp| timestamp.saturating_add(NANOS_PER_SECOND) > now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const NOW: u128 = 1_700_000_000 * NANOS_PER_SECOND;
+
+    /// The intended policy, stated on counts: past the first half of the window,
+    /// throttle once the last second holds `max_rate` transactions, or every
+    /// transaction of the window if there are fewer.
+    fn model(window: &[u128], now: u128, params: &ThrottleParams) -> bool {
+        let len = window.len() as u128;
+        let max = params.max_transactions_in_window;
+        if max == 0 || len == 0 || len < max / 2 {
+            return false;
+        }
+
+        let last_second = window
+            .iter()
+            .filter(|&&timestamp| timestamp + NANOS_PER_SECOND > now)
+            .count() as u128;
+        // last_second >= ceil(max / (2 * tx_window)), without rounding.
+        let rate_reached = last_second * 2 * params.tx_window.as_nanos() >= max * NANOS_PER_SECOND;
+
+        rate_reached || last_second == len
+    }
+
+    fn params() -> impl Strategy<Value = ThrottleParams> {
+        (
+            prop_oneof![1 => Just(0u128), 20 => 1u128..400],
+            prop_oneof![1 => Just(0u64), 20 => 0u64..3_000_000_000],
+        )
+            .prop_map(
+                |(max_transactions_in_window, window_nanos)| ThrottleParams {
+                    max_transactions_in_window,
+                    tx_window: Duration::from_nanos(window_nanos),
+                },
+            )
+    }
+
+    /// Sorted timestamps from 3s in the past up to the permitted drift in the future.
+    fn window() -> impl Strategy<Value = Vec<u128>> {
+        prop::collection::vec(NOW - 3 * NANOS_PER_SECOND..NOW + 100_000_000, 0..600).prop_map(
+            |mut window| {
+                window.sort_unstable();
+                window
+            },
+        )
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(5_000))]
+
+        #[test]
+        fn test_should_throttle_matches_model(window in window(), params in params()) {
+            prop_assert_eq!(
+                should_throttle(&window, NOW, &params),
+                model(&window, NOW, &params)
+            );
+        }
+    }
+
+    #[test]
+    fn test_max_rate_is_exact() {
+        // 1.5 / 0.3 is 5.000000000000001 in floating point.
+        let params = ThrottleParams {
+            max_transactions_in_window: 3,
+            tx_window: Duration::from_millis(300),
+        };
+        assert_eq!(params.max_rate(), 5);
+
+        // Timestamps are compared in nanoseconds: a transaction two seconds old
+        // is outside of the last second.
+        let params = ThrottleParams {
+            max_transactions_in_window: 2,
+            tx_window: Duration::from_secs(1),
+        };
+        assert!(!should_throttle(
+            &[NOW - 2 * NANOS_PER_SECOND],
+            NOW,
+            &params
+        ));
+        assert!(should_throttle(&[NOW - 1], NOW, &params));
+    }
+}
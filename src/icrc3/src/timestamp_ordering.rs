@@ -0,0 +1,144 @@
+//! Ordering of the block timestamps across recorders.
+//!
+//! The chain rejects a block whose timestamp is older than the tip. With several
+//! recorders, a transaction stamped by a canister whose clock lags behind can
+//! arrive right after one stamped by a canister whose clock is ahead, so the
+//! `timestamp_ordering` of the [`ICRC3Properties`] sets what happens to such a
+//! block: it is rejected ([`TimestampOrdering::Strict`], the default), its
+//! timestamp is raised to the tip's ([`TimestampOrdering::ClampToTip`]), or it is
+//! raised as long as it is older than the tip by at most a skew and rejected
+//! beyond ([`TimestampOrdering::RejectBeyondSkew`]). A clamped block records its
+//! original timestamp in an `orig_ts` field, and the clamps are counted in
+//! [`TimestampOrderingMetrics`].
+//!
+//! [`ICRC3Properties`]: crate::config::ICRC3Properties
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// What happens to a new block whose timestamp is older than the tip.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimestampOrdering {
+    /// The block is rejected
+    #[default]
+    Strict,
+    /// The timestamp of the block is raised to the tip's
+    ClampToTip,
+    /// The timestamp of the block is raised to the tip's if it is older by at
+    /// most this skew, and the block is rejected otherwise
+    RejectBeyondSkew(Duration),
+}
+
+impl TimestampOrdering {
+    /// Returns the timestamp of a new block after a tip with timestamp `tip`.
+    ///
+    /// In strict mode, the timestamp is returned as is and the chain rejects the
+    /// block if it is older than the tip.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the timestamp is older than the tip by more
+    /// than the skew of [`TimestampOrdering::RejectBeyondSkew`].
+    pub fn order(&self, tip: u128, timestamp: u128) -> Result<u128, String> {
+        if timestamp >= tip {
+            return Ok(timestamp);
+        }
+        match self {
+            TimestampOrdering::Strict => Ok(timestamp),
+            TimestampOrdering::ClampToTip => Ok(tip),
+            TimestampOrdering::RejectBeyondSkew(max_skew) => {
+                if tip - timestamp > max_skew.as_nanos() {
+                    return Err(format!(
+                        "Cannot apply block because its timestamp is older than the previous tip by {} ns, more than the allowed skew of {} ns.",
+                        tip - timestamp,
+                        max_skew.as_nanos()
+                    ));
+                }
+                Ok(tip)
+            }
+        }
+    }
+}
+
+/// Blocks whose timestamp was raised to the tip's, for metrics.
+///
+/// # Fields
+///
+/// * `clamped_blocks` - The number of such blocks
+/// * `max_clamp_ns` - The largest amount a timestamp was raised by, in nanoseconds
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TimestampOrderingMetrics {
+    pub clamped_blocks: u64,
+    pub max_clamp_ns: u64,
+}
+
+impl TimestampOrderingMetrics {
+    /// Counts a new block whose timestamp `timestamp` was ordered as `ordered`.
+    pub fn record(&mut self, timestamp: u128, ordered: u128) {
+        if ordered <= timestamp {
+            return;
+        }
+        self.clamped_blocks += 1;
+        self.max_clamp_ns = self
+            .max_clamp_ns
+            .max(u64::try_from(ordered - timestamp).unwrap_or(u64::MAX));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIP: u128 = 1_700_000_000_000_000_000;
+
+    #[test]
+    fn newer_or_equal_timestamps_are_kept_in_every_mode() {
+        for mode in [
+            TimestampOrdering::Strict,
+            TimestampOrdering::ClampToTip,
+            TimestampOrdering::RejectBeyondSkew(Duration::ZERO),
+        ] {
+            assert_eq!(mode.order(TIP, TIP), Ok(TIP));
+            assert_eq!(mode.order(TIP, TIP + 1), Ok(TIP + 1));
+        }
+    }
+
+    #[test]
+    fn strict_keeps_older_timestamps_for_the_chain_to_reject() {
+        assert_eq!(TimestampOrdering::Strict.order(TIP, TIP - 1), Ok(TIP - 1));
+    }
+
+    #[test]
+    fn clamp_to_tip_raises_any_older_timestamp() {
+        assert_eq!(TimestampOrdering::ClampToTip.order(TIP, TIP - 1), Ok(TIP));
+        assert_eq!(TimestampOrdering::ClampToTip.order(TIP, 0), Ok(TIP));
+    }
+
+    #[test]
+    fn reject_beyond_skew_clamps_up_to_the_skew() {
+        let mode = TimestampOrdering::RejectBeyondSkew(Duration::from_secs(5));
+        let skew = Duration::from_secs(5).as_nanos();
+
+        assert_eq!(mode.order(TIP, TIP - 1), Ok(TIP));
+        assert_eq!(mode.order(TIP, TIP - skew), Ok(TIP));
+        assert!(mode.order(TIP, TIP - skew - 1).is_err());
+    }
+
+    #[test]
+    fn metrics_only_count_raised_timestamps() {
+        let mut metrics = TimestampOrderingMetrics::default();
+        metrics.record(TIP, TIP);
+        assert_eq!(metrics, TimestampOrderingMetrics::default());
+
+        metrics.record(TIP - 10, TIP);
+        metrics.record(TIP - 3, TIP);
+        assert_eq!(
+            metrics,
+            TimestampOrderingMetrics {
+                clamped_blocks: 2,
+                max_clamp_ns: 10,
+            }
+        );
+    }
+}
@@ -6,8 +6,34 @@ use serde::{Deserialize, Serialize};
 use serde_bytes::ByteBuf;
 use std::collections::BTreeMap;
 
+use crate::schema::{FieldSchema, TransactionSchema, ValueKind};
 use crate::utils::trace;
 
+/// Converts a `created_at_time` to nanoseconds, saturating the times that do not
+/// fit in a `u64` so they are rejected as created in the future.
+fn created_at_time_nanos(created_at_time: &Option<Nat>) -> Option<TimestampNanos> {
+    created_at_time
+        .as_ref()
+        .map(|time| u64::try_from(time.0.clone()).unwrap_or(u64::MAX))
+}
+
+/// Whether a field appears in the transactions of a block type.
+#[derive(Clone, Copy)]
+enum Presence {
+    Required,
+    Optional,
+    Absent,
+}
+
+/// Returns the schema of a field, or `None` when it is not allowed.
+fn field(name: &str, kind: ValueKind, presence: Presence) -> Option<FieldSchema> {
+    match presence {
+        Presence::Required => Some(FieldSchema::required(name, kind)),
+        Presence::Optional => Some(FieldSchema::optional(name, kind)),
+        Presence::Absent => None,
+    }
+}
+
 /// The length of transaction hashes in bytes
 pub const HASH_LENGTH: usize = 32;
 /// The type representing a transaction hash
@@ -55,10 +81,27 @@ pub trait TransactionType: Sized + Clone + Into<ICRC3Value> {
     /// Returns the timestamp of the transaction if available.
     fn timestamp(&self) -> Option<TimestampNanos>;
 
+    /// Returns the `created_at_time` set by the caller of the transaction, if any.
+    ///
+    /// Transactions with a `created_at_time` are rejected when it is outside of the
+    /// transaction window or too far in the future, as ICRC-1 ledgers do.
+    fn created_at_time(&self) -> Option<TimestampNanos> {
+        None
+    }
+
     /// Returns the transaction data.
     fn tx(&self) -> ICRC3Value;
 
     fn block_type(&self) -> String;
+
+    /// Returns the schemas of the block types of this transaction type.
+    ///
+    /// Transactions of a block type with a schema are checked against it when
+    /// they are added, see [`crate::schema`]. Custom types can leave it empty and
+    /// register their schemas on the ICRC3 instance instead.
+    fn schemas() -> Vec<TransactionSchema> {
+        vec![]
+    }
 }
 
 /// A basic transaction type that wraps ICRC3Value.
@@ -143,6 +186,37 @@ impl ICRC1Transaction {
 }
 
 impl TransactionType for ICRC1Transaction {
+    fn schemas() -> Vec<TransactionSchema> {
+        use Presence::*;
+
+        [
+            ("1mint", Absent, Required),
+            ("1burn", Required, Absent),
+            ("1xfer", Required, Required),
+        ]
+        .into_iter()
+        .map(|(btype, from, to)| {
+            let tx = [
+                field("fee", ValueKind::Nat, Optional),
+                field("op", ValueKind::Text, Optional),
+                field("amt", ValueKind::Nat, Required),
+                field("from", ValueKind::Text, from),
+                field("to", ValueKind::Text, to),
+                field("memo", ValueKind::Blob, Optional),
+                field("ts", ValueKind::Nat, Optional),
+            ];
+            TransactionSchema::new(
+                btype,
+                vec![
+                    FieldSchema::required("btype", ValueKind::Text),
+                    FieldSchema::required("ts", ValueKind::Nat),
+                    FieldSchema::map("tx", tx.into_iter().flatten().collect()),
+                ],
+            )
+        })
+        .collect()
+    }
+
     fn validate_transaction_fields(&self) -> Result<(), String> {
         let validate_mint = || -> Result<(), String> {
             if self.tx.to.is_none() {
@@ -194,6 +268,10 @@ impl TransactionType for ICRC1Transaction {
         Some(self.timestamp)
     }
 
+    fn created_at_time(&self) -> Option<TimestampNanos> {
+        created_at_time_nanos(&self.tx.created_at_time)
+    }
+
     fn tx(&self) -> ICRC3Value {
         self.tx.clone().into()
     }
@@ -260,6 +338,8 @@ pub struct ICRC2TransactionData {
     pub memo: Option<ByteBuf>,
     pub expected_allowance: Option<Nat>,
     pub expires_at: Option<Nat>,
+    #[serde(default)]
+    pub created_at_time: Option<Nat>,
 }
 
 impl ICRC2Transaction {
@@ -274,6 +354,39 @@ impl ICRC2Transaction {
 }
 
 impl TransactionType for ICRC2Transaction {
+    fn schemas() -> Vec<TransactionSchema> {
+        use Presence::*;
+
+        [
+            ("2xfer", Required, Optional),
+            ("2approve", Absent, Required),
+        ]
+        .into_iter()
+        .map(|(btype, to, spender)| {
+            let tx = [
+                field("op", ValueKind::Text, Optional),
+                field("amt", ValueKind::Nat, Required),
+                field("from", ValueKind::Text, Required),
+                field("to", ValueKind::Text, to),
+                field("spender", ValueKind::Text, spender),
+                field("memo", ValueKind::Blob, Optional),
+                field("expected_allowance", ValueKind::Nat, Optional),
+                field("expires_at", ValueKind::Nat, Optional),
+                field("ts", ValueKind::Nat, Optional),
+            ];
+            TransactionSchema::new(
+                btype,
+                vec![
+                    FieldSchema::required("btype", ValueKind::Text),
+                    FieldSchema::required("timestamp", ValueKind::Nat),
+                    FieldSchema::optional("fee", ValueKind::Nat),
+                    FieldSchema::map("tx", tx.into_iter().flatten().collect()),
+                ],
+            )
+        })
+        .collect()
+    }
+
     fn validate_transaction_fields(&self) -> Result<(), String> {
         let validate_transfer = || -> Result<(), String> {
             if self.tx.from.is_none() {
@@ -316,6 +429,10 @@ impl TransactionType for ICRC2Transaction {
         Some(self.timestamp)
     }
 
+    fn created_at_time(&self) -> Option<TimestampNanos> {
+        created_at_time_nanos(&self.tx.created_at_time)
+    }
+
     fn tx(&self) -> ICRC3Value {
         self.tx.clone().into()
     }
@@ -356,6 +473,9 @@ impl From<ICRC2TransactionData> for ICRC3Value {
         if let Some(expires_at) = tx.expires_at {
             tx_map.insert("expires_at".to_string(), ICRC3Value::Nat(expires_at));
         }
+        if let Some(time) = tx.created_at_time {
+            tx_map.insert("ts".to_string(), ICRC3Value::Nat(time));
+        }
         ICRC3Value::Map(tx_map)
     }
 }
@@ -383,6 +503,7 @@ impl From<ICRC2Transaction> for ICRC3Value {
 pub struct ICRC7Transaction {
     pub btype: String,
     pub timestamp: u64,
+    pub fee: Option<Nat>, // charged by the ledger, not allowed for mint and burn
     pub tx: ICRC7TransactionData,
 }
 
@@ -402,12 +523,50 @@ impl ICRC7Transaction {
         Self {
             btype,
             timestamp,
+            fee: None,
             tx,
         }
     }
+
+    /// Sets the fee charged by the ledger, recorded as the top-level `fee` field.
+    pub fn with_fee(mut self, fee: Nat) -> Self {
+        self.fee = Some(fee);
+        self
+    }
 }
 
 impl TransactionType for ICRC7Transaction {
+    fn schemas() -> Vec<TransactionSchema> {
+        use Presence::*;
+
+        [
+            ("7mint", Absent, Required, Absent, Absent),
+            ("7burn", Required, Absent, Absent, Absent),
+            ("7xfer", Required, Required, Absent, Optional),
+            ("7update_token", Optional, Absent, Required, Optional),
+        ]
+        .into_iter()
+        .map(|(btype, from, to, meta, fee)| {
+            let tx = [
+                field("op", ValueKind::Text, Required),
+                field("tid", ValueKind::Nat, Required),
+                field("from", ValueKind::Text, from),
+                field("to", ValueKind::Text, to),
+                field("meta", ValueKind::Any, meta),
+                field("memo", ValueKind::Blob, Optional),
+                field("created_at_time", ValueKind::Nat, Optional),
+            ];
+            let fields = [
+                field("btype", ValueKind::Text, Required),
+                field("timestamp", ValueKind::Nat, Required),
+                field("fee", ValueKind::Nat, fee),
+                Some(FieldSchema::map("tx", tx.into_iter().flatten().collect())),
+            ];
+            TransactionSchema::new(btype, fields.into_iter().flatten().collect())
+        })
+        .collect()
+    }
+
     fn validate_transaction_fields(&self) -> Result<(), String> {
         if self.btype != self.tx.op {
             return Err("btype and op must be the same".to_string());
@@ -426,6 +585,9 @@ impl TransactionType for ICRC7Transaction {
                 if self.tx.meta.is_some() {
                     return Err("Meta is not allowed for mint".to_string());
                 }
+                if self.fee.is_some() {
+                    return Err("Fee is not allowed for mint".to_string());
+                }
             }
             "7burn" => {
                 if self.tx.tid.is_none() {
@@ -440,6 +602,9 @@ impl TransactionType for ICRC7Transaction {
                 if self.tx.meta.is_some() {
                     return Err("Meta is not allowed for burn".to_string());
                 }
+                if self.fee.is_some() {
+                    return Err("Fee is not allowed for burn".to_string());
+                }
             }
             "7xfer" => {
                 if self.tx.tid.is_none() {
@@ -475,6 +640,10 @@ impl TransactionType for ICRC7Transaction {
         Some(self.timestamp)
     }
 
+    fn created_at_time(&self) -> Option<TimestampNanos> {
+        created_at_time_nanos(&self.tx.created_at_time)
+    }
+
     fn block_type(&self) -> String {
         self.btype.clone()
     }
@@ -518,6 +687,9 @@ impl From<ICRC7Transaction> for ICRC3Value {
             "timestamp".to_string(),
             ICRC3Value::Nat(Nat::from(tx.timestamp)),
         );
+        if let Some(fee) = tx.fee {
+            map.insert("fee".to_string(), ICRC3Value::Nat(fee));
+        }
 
         let tx_value = tx.tx.into();
         map.insert("tx".to_string(), tx_value);
@@ -530,6 +702,7 @@ impl From<ICRC7Transaction> for ICRC3Value {
 pub struct ICRC37Transaction {
     pub btype: String,
     pub timestamp: u64,
+    pub fee: Option<Nat>,
     pub tx: ICRC37TransactionData,
 }
 
@@ -550,12 +723,54 @@ impl ICRC37Transaction {
         Self {
             btype,
             timestamp,
+            fee: None,
             tx,
         }
     }
+
+    /// Sets the fee charged by the ledger, recorded as the top-level `fee` field.
+    pub fn with_fee(mut self, fee: Nat) -> Self {
+        self.fee = Some(fee);
+        self
+    }
 }
 
 impl TransactionType for ICRC37Transaction {
+    fn schemas() -> Vec<TransactionSchema> {
+        use Presence::*;
+
+        [
+            ("37approve", Required, Absent, Required, Optional),
+            ("37approve_coll", Absent, Absent, Required, Optional),
+            ("37revoke", Required, Absent, Optional, Absent),
+            ("37revoke_coll", Absent, Absent, Optional, Absent),
+            ("37xfer", Required, Required, Required, Absent),
+        ]
+        .into_iter()
+        .map(|(btype, tid, to, spender, exp)| {
+            let tx = [
+                field("op", ValueKind::Text, Required),
+                field("tid", ValueKind::Nat, tid),
+                field("from", ValueKind::Text, Required),
+                field("spender", ValueKind::Text, spender),
+                field("exp", ValueKind::Nat, exp),
+                field("to", ValueKind::Text, to),
+                field("memo", ValueKind::Blob, Optional),
+                field("created_at_time", ValueKind::Nat, Optional),
+            ];
+            TransactionSchema::new(
+                btype,
+                vec![
+                    FieldSchema::required("btype", ValueKind::Text),
+                    FieldSchema::required("timestamp", ValueKind::Nat),
+                    FieldSchema::optional("fee", ValueKind::Nat),
+                    FieldSchema::map("tx", tx.into_iter().flatten().collect()),
+                ],
+            )
+        })
+        .collect()
+    }
+
     fn validate_transaction_fields(&self) -> Result<(), String> {
         if self.btype != self.tx.op {
             return Err("btype and op must be the same".to_string());
@@ -643,6 +858,10 @@ impl TransactionType for ICRC37Transaction {
         Some(self.timestamp)
     }
 
+    fn created_at_time(&self) -> Option<TimestampNanos> {
+        created_at_time_nanos(&self.tx.created_at_time)
+    }
+
     fn block_type(&self) -> String {
         self.btype.clone()
     }
@@ -692,6 +911,9 @@ impl From<ICRC37Transaction> for ICRC3Value {
             "timestamp".to_string(),
             ICRC3Value::Nat(Nat::from(tx.timestamp)),
         );
+        if let Some(fee) = tx.fee {
+            map.insert("fee".to_string(), ICRC3Value::Nat(fee));
+        }
 
         let tx_value = tx.tx.into();
         map.insert("tx".to_string(), tx_value);
@@ -699,3 +921,161 @@ impl From<ICRC37Transaction> for ICRC3Value {
         ICRC3Value::Map(map)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+
+    fn account(byte: u8) -> Option<Account> {
+        Some(Account {
+            owner: Principal::from_slice(&[byte]),
+            subaccount: None,
+        })
+    }
+
+    fn schema(btype: &str) -> TransactionSchema {
+        crate::schema::builtin_schemas()
+            .into_iter()
+            .find(|schema| schema.btype == btype)
+            .unwrap()
+    }
+
+    fn top_level_fee(value: ICRC3Value) -> Option<ICRC3Value> {
+        match value {
+            ICRC3Value::Map(map) => {
+                if let Some(ICRC3Value::Map(tx)) = map.get("tx") {
+                    assert!(!tx.contains_key("fee"));
+                }
+                map.get("fee").cloned()
+            }
+            _ => panic!("transaction is not a map"),
+        }
+    }
+
+    fn icrc7(btype: &str, fee: Option<Nat>) -> ICRC7Transaction {
+        let (from, to, meta) = match btype {
+            "7mint" => (None, account(2), None),
+            "7burn" => (account(1), None, None),
+            "7xfer" => (account(1), account(2), None),
+            _ => (account(1), None, Some(ICRC3Value::Text("meta".to_string()))),
+        };
+        let transaction = ICRC7Transaction::new(
+            btype.to_string(),
+            1_000,
+            ICRC7TransactionData {
+                op: btype.to_string(),
+                tid: Some(Nat::from(1u64)),
+                from,
+                to,
+                meta,
+                memo: None,
+                created_at_time: None,
+            },
+        );
+        match fee {
+            Some(fee) => transaction.with_fee(fee),
+            None => transaction,
+        }
+    }
+
+    fn icrc37(btype: &str, fee: Option<Nat>) -> ICRC37Transaction {
+        let collection = btype.ends_with("_coll");
+        let (to, exp) = match btype {
+            "37xfer" => (account(3), None),
+            "37approve" | "37approve_coll" => (None, Some(Nat::from(10u64))),
+            _ => (None, None),
+        };
+        let transaction = ICRC37Transaction::new(
+            btype.to_string(),
+            1_000,
+            ICRC37TransactionData {
+                op: btype.to_string(),
+                tid: (!collection).then(|| Nat::from(1u64)),
+                from: account(1),
+                to,
+                memo: None,
+                created_at_time: None,
+                spender: account(2),
+                exp,
+            },
+        );
+        match fee {
+            Some(fee) => transaction.with_fee(fee),
+            None => transaction,
+        }
+    }
+
+    #[test]
+    fn test_icrc7_fee_is_a_top_level_field() {
+        let fee = Nat::from(10_000u64);
+
+        for btype in ["7mint", "7burn", "7xfer", "7update_token"] {
+            let tx = icrc7(btype, None);
+            assert!(tx.validate_transaction_fields().is_ok(), "{btype}");
+            assert_eq!(schema(btype).validate(&tx.clone().into()), Ok(()));
+            assert_eq!(top_level_fee(tx.into()), None, "{btype}");
+        }
+
+        for btype in ["7xfer", "7update_token"] {
+            let tx = icrc7(btype, Some(fee.clone()));
+            assert!(tx.validate_transaction_fields().is_ok(), "{btype}");
+            assert_eq!(
+                top_level_fee(tx.into()),
+                Some(ICRC3Value::Nat(fee.clone())),
+                "{btype}"
+            );
+        }
+
+        assert_eq!(
+            icrc7("7mint", Some(fee.clone())).validate_transaction_fields(),
+            Err("Fee is not allowed for mint".to_string())
+        );
+        assert_eq!(
+            icrc7("7burn", Some(fee)).validate_transaction_fields(),
+            Err("Fee is not allowed for burn".to_string())
+        );
+    }
+
+    #[test]
+    fn test_icrc37_fee_is_a_top_level_field() {
+        let fee = Nat::from(10_000u64);
+
+        for btype in [
+            "37approve",
+            "37approve_coll",
+            "37revoke",
+            "37revoke_coll",
+            "37xfer",
+        ] {
+            let tx = icrc37(btype, None);
+            assert!(tx.validate_transaction_fields().is_ok(), "{btype}");
+            assert_eq!(schema(btype).validate(&tx.clone().into()), Ok(()));
+            assert_eq!(top_level_fee(tx.into()), None, "{btype}");
+
+            let tx = icrc37(btype, Some(fee.clone()));
+            assert!(tx.validate_transaction_fields().is_ok(), "{btype}");
+            assert_eq!(
+                top_level_fee(tx.into()),
+                Some(ICRC3Value::Nat(fee.clone())),
+                "{btype}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_created_at_time_is_exposed() {
+        let mut transaction = icrc7("7xfer", None);
+        assert_eq!(transaction.created_at_time(), None);
+
+        transaction.tx.created_at_time = Some(Nat::from(1_700_000_000_000_000_000u64));
+        assert_eq!(
+            transaction.created_at_time(),
+            Some(1_700_000_000_000_000_000)
+        );
+
+        // Times past u64::MAX are in the future of any ledger time.
+        transaction.tx.created_at_time = Some(Nat::from(u128::MAX));
+        assert_eq!(transaction.created_at_time(), Some(u64::MAX));
+    }
+}
@@ -1,4 +1,4 @@
-use candid::CandidType;
+use candid::{CandidType, Nat, Principal};
 use serde::{Deserialize, Serialize};
 
 /// Error types for the ICRC3 implementation.
@@ -13,8 +13,26 @@ pub enum Icrc3Error {
     Icrc3Error(String),
     /// An error occurred during block creation
     BlockCreationError(String),
-    /// A duplicate transaction occurred
-    DuplicateTransaction { duplicate_of: u64 },
+    /// A duplicate transaction occurred, `duplicate_of` is the block index of the original
+    DuplicateTransaction { duplicate_of: Nat },
+    /// The transaction is larger than `max_transaction_size_bytes`
+    TransactionTooLarge { size: u128, limit: u128 },
+    /// The memo of the transaction is larger than `max_memo_size_bytes`
+    MemoTooLarge { size: u128, limit: u128 },
+    /// The transaction nests maps or arrays deeper than the limit
+    TransactionTooDeep { limit: u32 },
+    /// The caller is neither a recorder nor a controller of the canister
+    Unauthorized { caller: Principal },
+    /// The `created_at_time` of the transaction is before the transaction window
+    TooOld,
+    /// The `created_at_time` of the transaction is after the ledger time plus the
+    /// permitted drift
+    CreatedInFuture { ledger_time: u64 },
+    /// The prepared transaction was not committed within `prepared_transaction_ttl`
+    PreparedTransactionExpired { expired_at: u64 },
+    /// The local archive has no room left for the block. An archive run was
+    /// requested to free some, the transaction can be retried after the hint
+    LocalArchiveFull { retry_after_hint_ms: u64 },
 }
 
 impl std::fmt::Display for Icrc3Error {
@@ -61,8 +79,8 @@ pub mod icrc3_get_tip_certificate {
 
     /// Arguments for the `icrc3_get_tip_certificate` endpoint
     pub type Args = ();
-    /// Response type for the `icrc3_get_tip_certificate` endpoint
-    pub type Response = ICRC3DataCertificate;
+    /// Response type for the `icrc3_get_tip_certificate` endpoint, `None` outside a query
+    pub type Response = Option<ICRC3DataCertificate>;
 }
 
 /// Module containing types for the `icrc3_supported_block_types` endpoint.
@@ -75,6 +93,59 @@ pub mod icrc3_supported_block_types {
     pub type Response = Vec<SupportedBlockType>;
 }
 
+/// Module containing types for the `icrc10_supported_standards` endpoint.
+pub mod icrc10_supported_standards {
+    use crate::standards::StandardRecord;
+
+    /// Arguments for the `icrc10_supported_standards` endpoint
+    pub type Args = ();
+    /// Response type for the `icrc10_supported_standards` endpoint
+    pub type Response = Vec<StandardRecord>;
+}
+
+/// Module containing types for the `icrc3_get_tip` endpoint.
+pub mod icrc3_get_tip {
+    use candid::{CandidType, Nat};
+    use serde::{Deserialize, Serialize};
+    use serde_bytes::ByteBuf;
+
+    /// The last block of the chain.
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub struct TipInfo {
+        /// The index of the last block
+        pub index: Nat,
+        /// The hash of the last block, as certified by the tip certificate
+        pub block_hash: ByteBuf,
+        /// The timestamp of the last block, in nanoseconds
+        pub timestamp_ns: Nat,
+    }
+
+    /// Arguments for the `icrc3_get_tip` endpoint
+    pub type Args = ();
+    /// Response type for the `icrc3_get_tip` endpoint, `None` on an empty chain
+    pub type Response = Option<TipInfo>;
+}
+
+/// Module containing types for the `icrc3_chain_length` endpoint.
+pub mod icrc3_chain_length {
+    use candid::Nat;
+
+    /// Arguments for the `icrc3_chain_length` endpoint
+    pub type Args = ();
+    /// Response type for the `icrc3_chain_length` endpoint
+    pub type Response = Nat;
+}
+
+/// Module containing types for the `icrc3_has_block` endpoint.
+pub mod icrc3_has_block {
+    use candid::Nat;
+
+    /// Arguments for the `icrc3_has_block` endpoint
+    pub type Args = Nat;
+    /// Response type for the `icrc3_has_block` endpoint
+    pub type Response = bool;
+}
+
 /// Module containing types for the `add_transaction` endpoint.
 pub mod add_transaction {
     use crate::types::Icrc3Error;
@@ -98,10 +169,12 @@ pub mod prepare_transaction {
     use icrc_ledger_types::icrc::generic_value::ICRC3Value;
     use serde::{Deserialize, Serialize};
 
+    /// A prepared transaction, to commit with its `timestamp` before `expires_at_ns`.
     #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
     pub struct PreparedTransaction {
         pub transaction_hash: Vec<u8>,
         pub timestamp: u128,
+        pub expires_at_ns: u64,
     }
 
     /// Arguments for the `prepare_transaction` endpoint
@@ -1,7 +1,13 @@
+use crate::config::ICRC3Properties;
+use crate::types::Icrc3Error;
+
+use bity_ic_icrc3_archive_api::types::hashing::tip_hash_tree;
+use candid::Principal;
 use ic_certification::Hash;
 use ic_certification::RbTree;
-
-const MAX_U64_ENCODING_BYTES: usize = 10;
+use icrc_ledger_types::icrc3::archive::QueryArchiveFn;
+use icrc_ledger_types::icrc3::blocks::{ArchivedBlocks, GetBlocksRequest};
+use std::ops::Range;
 
 /// Creates a hash tree for the last block in the chain.
 ///
@@ -32,19 +38,7 @@ where
     I: Into<u64>,
     H: Into<Hash>,
 {
-    let last_block_index: u64 = last_block_index.into();
-    let last_block_hash: Hash = last_block_hash.into();
-
-    let mut hash_tree = RbTree::new();
-    let mut last_block_index_buf = Vec::with_capacity(MAX_U64_ENCODING_BYTES);
-    leb128::write::unsigned(&mut last_block_index_buf, last_block_index).unwrap();
-
-    hash_tree.insert("last_block_index", last_block_index_buf);
-    hash_tree.insert("last_block_hash", last_block_hash.to_vec());
-
-    // FIXME: write the tree somewhere while block update
-    // ic_cdk::api::set_certified_data(&hash_tree.root_hash());
-    hash_tree
+    tip_hash_tree(Some((last_block_index.into(), last_block_hash.into())))
 }
 
 /// Prints a debug message to both the IC debug output and standard output.
@@ -187,3 +181,264 @@ pub fn get_transaction_size(transaction: &ICRC3Value) -> Result<u128, String> {
     };
     Ok(map.iter().map(|(_, v)| get_value_size(v.clone())).sum())
 }
+
+/// Maximum nesting depth of maps and arrays in a transaction.
+///
+/// Bounds the recursion of size computation and hashing on incoming transactions.
+pub const MAX_TRANSACTION_DEPTH: u32 = 16;
+
+/// Returns whether a value nests maps and arrays deeper than `max_depth` levels.
+///
+/// The recursion stops as soon as the limit is reached, so the cost is bounded
+/// by the limit rather than by the depth of the value.
+fn exceeds_depth(value: &ICRC3Value, max_depth: u32) -> bool {
+    match value {
+        ICRC3Value::Array(array) => {
+            max_depth == 0 || array.iter().any(|v| exceeds_depth(v, max_depth - 1))
+        }
+        ICRC3Value::Map(map) => {
+            max_depth == 0 || map.values().any(|v| exceeds_depth(v, max_depth - 1))
+        }
+        _ => false,
+    }
+}
+
+/// Calculates the size of the `memo` field of a transaction in bytes.
+///
+/// The memo is looked up at the top level of the transaction and in its `tx` map,
+/// where the ICRC-1 block schema puts it.
+///
+/// # Returns
+///
+/// The size of the memo, or `None` if the transaction has no memo
+pub fn get_memo_size(transaction: &ICRC3Value) -> Option<u128> {
+    let ICRC3Value::Map(map) = transaction else {
+        return None;
+    };
+    let memo = map.get("memo").or_else(|| match map.get("tx") {
+        Some(ICRC3Value::Map(tx)) => tx.get("memo"),
+        _ => None,
+    })?;
+    Some(get_value_size(memo.clone()))
+}
+
+/// Checks an incoming transaction against the size and depth limits.
+///
+/// # Arguments
+///
+/// * `transaction` - The transaction to check
+/// * `constants` - The limits to enforce
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The transaction nests maps or arrays deeper than `MAX_TRANSACTION_DEPTH`
+/// * The transaction is larger than `max_transaction_size_bytes`
+/// * The memo is larger than `max_memo_size_bytes`, when set
+pub fn check_transaction_limits(
+    transaction: &ICRC3Value,
+    constants: &ICRC3Properties,
+) -> Result<(), Icrc3Error> {
+    if exceeds_depth(transaction, MAX_TRANSACTION_DEPTH) {
+        return Err(Icrc3Error::TransactionTooDeep {
+            limit: MAX_TRANSACTION_DEPTH,
+        });
+    }
+
+    let size = get_transaction_size(transaction).map_err(Icrc3Error::Icrc3Error)?;
+    if size > constants.max_transaction_size_bytes {
+        return Err(Icrc3Error::TransactionTooLarge {
+            size,
+            limit: constants.max_transaction_size_bytes,
+        });
+    }
+
+    if let (Some(size), Some(limit)) = (get_memo_size(transaction), constants.max_memo_size_bytes) {
+        if size > limit {
+            return Err(Icrc3Error::MemoTooLarge { size, limit });
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds an archived block range to a `get_blocks` response.
+///
+/// Ranges held by the same archive canister are grouped into a single
+/// `ArchivedBlocks` entry, so clients make one follow-up call per canister.
+/// Entries and ranges keep the order in which they were added, and a range
+/// directly following the previous range of its canister extends it.
+///
+/// # Arguments
+///
+/// * `archived_blocks` - The archived blocks of the response
+/// * `canister_id` - The archive canister holding the range
+/// * `start` - The index of the first block of the range
+/// * `length` - The number of blocks in the range
+pub fn push_archived_range(
+    archived_blocks: &mut Vec<ArchivedBlocks>,
+    canister_id: Principal,
+    start: u64,
+    length: u64,
+) {
+    let Some(entry) = archived_blocks
+        .iter_mut()
+        .find(|entry| entry.callback.canister_id == canister_id)
+    else {
+        archived_blocks.push(ArchivedBlocks {
+            args: vec![GetBlocksRequest {
+                start: Nat::from(start),
+                length: Nat::from(length),
+            }],
+            callback: QueryArchiveFn::new(canister_id, "icrc3_get_blocks".to_string()),
+        });
+        return;
+    };
+
+    if let Some(last) = entry.args.last_mut() {
+        if last.start.clone() + last.length.clone() == start {
+            last.length += Nat::from(length);
+            return;
+        }
+    }
+    entry.args.push(GetBlocksRequest {
+        start: Nat::from(start),
+        length: Nat::from(length),
+    });
+}
+
+/// Returns the block indices served for a `get_blocks` request.
+///
+/// The request is bounded without trapping: a start or length above `u64::MAX`
+/// is clamped, the range ends at the chain tip, and it covers at most
+/// `max_length` blocks. The range is empty if nothing is to be served.
+///
+/// # Arguments
+///
+/// * `request` - The requested range
+/// * `log_length` - The number of blocks in the chain
+/// * `max_length` - The maximum number of blocks served for the request
+pub fn requested_block_range(
+    request: &GetBlocksRequest,
+    log_length: u64,
+    max_length: u64,
+) -> Range<u64> {
+    let start = u64::try_from(&request.start.0).unwrap_or(u64::MAX);
+    let length = u64::try_from(&request.length.0).unwrap_or(u64::MAX);
+    let end = start.saturating_add(length.min(max_length)).min(log_length);
+
+    start.min(end)..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_bytes::ByteBuf;
+    use std::collections::BTreeMap;
+
+    fn transaction_with_memo(memo_len: usize) -> ICRC3Value {
+        let mut tx = BTreeMap::new();
+        tx.insert(
+            "memo".to_string(),
+            ICRC3Value::Blob(ByteBuf::from(vec![0u8; memo_len])),
+        );
+        let mut map = BTreeMap::new();
+        map.insert("tx".to_string(), ICRC3Value::Map(tx));
+        ICRC3Value::Map(map)
+    }
+
+    fn nested(depth: u32) -> ICRC3Value {
+        let mut value = ICRC3Value::Text("leaf".to_string());
+        for _ in 0..depth {
+            value = ICRC3Value::Array(vec![value]);
+        }
+        let mut map = BTreeMap::new();
+        map.insert("tx".to_string(), value);
+        ICRC3Value::Map(map)
+    }
+
+    #[test]
+    fn test_memo_and_transaction_size_limits() {
+        let constants = ICRC3Properties {
+            max_memo_size_bytes: Some(32),
+            max_transaction_size_bytes: 40,
+            ..ICRC3Properties::default()
+        };
+
+        // "memo" key (4 bytes) + 32 bytes of memo
+        assert_eq!(get_memo_size(&transaction_with_memo(32)), Some(32));
+        assert!(check_transaction_limits(&transaction_with_memo(32), &constants).is_ok());
+
+        assert!(matches!(
+            check_transaction_limits(&transaction_with_memo(33), &constants),
+            Err(Icrc3Error::MemoTooLarge {
+                size: 33,
+                limit: 32
+            })
+        ));
+
+        let constants = ICRC3Properties {
+            max_transaction_size_bytes: 36,
+            ..constants
+        };
+        assert!(check_transaction_limits(&transaction_with_memo(32), &constants).is_ok());
+        assert!(matches!(
+            check_transaction_limits(&transaction_with_memo(33), &constants),
+            Err(Icrc3Error::TransactionTooLarge {
+                size: 37,
+                limit: 36
+            })
+        ));
+
+        let constants = ICRC3Properties {
+            max_memo_size_bytes: None,
+            max_transaction_size_bytes: 1024,
+            ..constants
+        };
+        assert!(check_transaction_limits(&transaction_with_memo(512), &constants).is_ok());
+    }
+
+    #[test]
+    fn test_archived_ranges_are_grouped_per_canister() {
+        let archive_a = Principal::from_slice(&[1]);
+        let archive_b = Principal::from_slice(&[2]);
+        let mut archived_blocks = vec![];
+
+        push_archived_range(&mut archived_blocks, archive_a, 0, 5);
+        push_archived_range(&mut archived_blocks, archive_b, 20, 5);
+        push_archived_range(&mut archived_blocks, archive_a, 10, 2);
+        push_archived_range(&mut archived_blocks, archive_a, 12, 3);
+        push_archived_range(&mut archived_blocks, archive_a, 2, 1);
+
+        let ranges = |entry: &ArchivedBlocks| -> Vec<(u64, u64)> {
+            entry
+                .args
+                .iter()
+                .map(|r| {
+                    (
+                        r.start.0.clone().try_into().unwrap(),
+                        r.length.0.clone().try_into().unwrap(),
+                    )
+                })
+                .collect()
+        };
+
+        assert_eq!(archived_blocks.len(), 2);
+        assert_eq!(archived_blocks[0].callback.canister_id, archive_a);
+        assert_eq!(ranges(&archived_blocks[0]), vec![(0, 5), (10, 5), (2, 1)]);
+        assert_eq!(archived_blocks[1].callback.canister_id, archive_b);
+        assert_eq!(ranges(&archived_blocks[1]), vec![(20, 5)]);
+    }
+
+    #[test]
+    fn test_transaction_depth_limit() {
+        let constants = ICRC3Properties::default();
+
+        // The top-level map counts as one level.
+        assert!(check_transaction_limits(&nested(MAX_TRANSACTION_DEPTH - 1), &constants).is_ok());
+        assert!(matches!(
+            check_transaction_limits(&nested(MAX_TRANSACTION_DEPTH), &constants),
+            Err(Icrc3Error::TransactionTooDeep { .. })
+        ));
+    }
+}
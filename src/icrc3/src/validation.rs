@@ -0,0 +1,39 @@
+//! Validation strictness of each block type.
+//!
+//! A new transaction is checked by its own `validate_transaction_fields` and by
+//! the schema registered for its block type. Some integrators send blocks with
+//! minor deviations from the standards, e.g. an ICRC1 mint with an `op` that does
+//! not match its `btype`, so the `validation_modes` of the [`ICRC3Config`] set,
+//! per block type, what happens when these checks fail: the transaction is
+//! rejected ([`ValidationMode::Strict`], the default), accepted with a warning
+//! listing the violations ([`ValidationMode::Lenient`]), or the checks are
+//! skipped ([`ValidationMode::Off`]). The envelope is checked in every mode: the
+//! block type must be supported and the block must have a `phash`.
+//!
+//! [`ICRC3Config`]: crate::config::ICRC3Config
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// What happens when a transaction fails the checks of its block type.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ValidationMode {
+    /// The transaction is rejected
+    #[default]
+    Strict,
+    /// The transaction is accepted, and the violations are counted and logged as a warning
+    Lenient,
+    /// The checks are skipped, only the envelope is checked
+    Off,
+}
+
+/// Transactions accepted despite violations, for metrics.
+///
+/// # Fields
+///
+/// * `lenient_violations` - The number of such transactions of each block type
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationMetrics {
+    pub lenient_violations: BTreeMap<String, u64>,
+}
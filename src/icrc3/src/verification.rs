@@ -0,0 +1,340 @@
+//! Re-verification of the blocks stored in archive canisters.
+//!
+//! Archive canisters run their own wasm and may be operated by others, so a block
+//! corrupted there would only be noticed when a client reads it. A verification run
+//! samples archived blocks at random, reads them back as stored with
+//! `get_encoded_blocks` and checks that they still link to their neighbours: each
+//! block must decode and carry the hash of the previous block, both as parent hash
+//! and in its `phash` field when it has one.
+
+use crate::blockchain::block_transform::BlockTransformConfig;
+use bity_ic_icrc3_archive_api::get_encoded_blocks;
+use bity_ic_icrc3_archive_api::types::{
+    block_compression,
+    block_interface::{Block, BlockIndex},
+    defaultblock::DefaultBlock,
+    encoded_blocks::EncodedBlock,
+};
+use candid::{CandidType, Principal};
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+
+/// The length of the parent hash and timestamp preceding the transaction of an
+/// encoded block.
+const BLOCK_HEADER_LEN: usize = 32 + 16;
+
+/// The interval and sample size of the periodic verification job.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VerificationJobConfig {
+    pub interval_ms: u64,
+    pub sample_size: u32,
+}
+
+/// What a verification run needs from the ICRC3 state, taken beforehand so that
+/// the archive canisters are called without holding the ICRC3 lock.
+///
+/// # Fields
+///
+/// * `archived_chain_length` - The number of blocks stored in archive canisters
+/// * `canisters_by_block_offset` - The archive canisters with the index of their first block
+/// * `block_transform` - The transform opening the blocks read from the archives
+/// * `genesis_parent_hash` - The parent hash of block 0, if the chain is anchored
+#[derive(Clone, Debug)]
+pub struct VerificationPlan {
+    pub archived_chain_length: u64,
+    pub canisters_by_block_offset: Vec<(BlockIndex, Principal)>,
+    pub block_transform: BlockTransformConfig,
+    pub genesis_parent_hash: Option<[u8; 32]>,
+}
+
+impl VerificationPlan {
+    /// Verifies `sample_size` archived blocks picked with a random seed.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u128)` containing the number of verified blocks
+    /// * `Err(String)` describing each sampled block that failed verification
+    pub async fn run(&self, sample_size: u32) -> Result<u128, String> {
+        let seed = bity_ic_canister_client::get_random_bytes(32)
+            .await
+            .map_err(|e| format!("Failed to get a random seed: {}", e))?;
+        self.verify_sample(&seed, sample_size).await
+    }
+
+    /// Verifies the archived blocks sampled with `seed`, along with the links to
+    /// their neighbours.
+    pub async fn verify_sample(&self, seed: &[u8], sample_size: u32) -> Result<u128, String> {
+        let indices = sample_indices(seed, self.archived_chain_length, sample_size);
+
+        let mut failures = vec![];
+        for &index in &indices {
+            let start = index.saturating_sub(1);
+            let end = (index + 2).min(self.archived_chain_length);
+            let result = match self.fetch_blocks(start, end).await {
+                Ok(window) => verify_linkage(&window, self.genesis_parent_hash),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                failures.push(e);
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(indices.len() as u128)
+        } else {
+            Err(format!(
+                "{} of {} sampled blocks failed verification: {}",
+                failures.len(),
+                indices.len(),
+                failures.join("; ")
+            ))
+        }
+    }
+
+    /// Returns the archive canister storing a block and the index following its
+    /// last block.
+    fn archive_range(&self, block_id: BlockIndex) -> Option<(Principal, BlockIndex)> {
+        let position = self
+            .canisters_by_block_offset
+            .iter()
+            .rposition(|(offset, _)| *offset <= block_id)?;
+        let end = self
+            .canisters_by_block_offset
+            .get(position + 1)
+            .map(|(offset, _)| *offset)
+            .unwrap_or(self.archived_chain_length);
+        Some((self.canisters_by_block_offset[position].1, end))
+    }
+
+    /// Reads the blocks `start..end` from the archive canisters, opens them and
+    /// decompresses the compressed ones.
+    async fn fetch_blocks(
+        &self,
+        start: BlockIndex,
+        end: BlockIndex,
+    ) -> Result<Vec<(BlockIndex, EncodedBlock)>, String> {
+        let transform = self.block_transform.transform()?;
+
+        let mut blocks = vec![];
+        let mut next = start;
+        while next < end {
+            let (canister_id, archive_end) = self
+                .archive_range(next)
+                .filter(|(_, archive_end)| *archive_end > next)
+                .ok_or_else(|| format!("block {}: no archive canister stores it", next))?;
+            let segment_end = end.min(archive_end);
+
+            let args = get_encoded_blocks::Args {
+                start: next,
+                length: segment_end - next,
+            };
+            let response = bity_ic_icrc3_archive_c2c_client::get_encoded_blocks(canister_id, &args)
+                .await
+                .map_err(|e| {
+                    format!(
+                        "Failed to read blocks from archive {}: {:?}",
+                        canister_id, e
+                    )
+                })?;
+
+            for block_id in next..segment_end {
+                let (_, block) =
+                    response
+                        .iter()
+                        .find(|(id, _)| *id == block_id)
+                        .ok_or_else(|| {
+                            format!("block {}: missing from archive {}", block_id, canister_id)
+                        })?;
+                let block = transform
+                    .open(block.clone())
+                    .and_then(block_compression::decompress)
+                    .map_err(|e| format!("block {}: {}", block_id, e))?;
+                blocks.push((block_id, block));
+            }
+            next = segment_end;
+        }
+        Ok(blocks)
+    }
+}
+
+/// Picks the indices of the archived blocks to verify.
+///
+/// All the blocks are picked when `sample_size` covers the archived chain.
+/// Otherwise the indices are drawn from the SHA-256 of the seed and a counter,
+/// without repetition.
+///
+/// # Arguments
+///
+/// * `seed` - Random bytes, e.g. from `raw_rand`
+/// * `archived_chain_length` - The number of archived blocks
+/// * `sample_size` - The number of blocks to pick
+///
+/// # Returns
+///
+/// The picked indices, in increasing order.
+pub fn sample_indices(seed: &[u8], archived_chain_length: u64, sample_size: u32) -> Vec<u64> {
+    if sample_size as u64 >= archived_chain_length {
+        return (0..archived_chain_length).collect();
+    }
+
+    let mut indices = BTreeSet::new();
+    let mut counter = 0u64;
+    while indices.len() < sample_size as usize {
+        let mut hasher = Sha256::new();
+        hasher.update(seed);
+        hasher.update(counter.to_be_bytes());
+        let digest = hasher.finalize();
+        let value = u64::from_be_bytes(digest[..8].try_into().unwrap());
+        indices.insert(value % archived_chain_length);
+        counter += 1;
+    }
+    indices.into_iter().collect()
+}
+
+/// Checks that consecutive opened blocks are linked to one another.
+///
+/// Each block must decode, the first block of the chain must have the genesis
+/// parent hash as parent, or no parent if the chain is not anchored, and the
+/// `phash` field of a block, if any, must be its parent hash. A block following
+/// another one in `blocks` must have the hash of that block as parent hash.
+///
+/// # Arguments
+///
+/// * `blocks` - Opened blocks with their index, in increasing order
+/// * `genesis_parent_hash` - The `genesis_parent_hash` of the chain, see [`crate::genesis`]
+///
+/// # Returns
+///
+/// * `Ok(())` if the blocks are linked
+/// * `Err(String)` describing the first broken block
+pub fn verify_linkage(
+    blocks: &[(BlockIndex, EncodedBlock)],
+    genesis_parent_hash: Option<[u8; 32]>,
+) -> Result<(), String> {
+    let mut previous: Option<(BlockIndex, [u8; 32])> = None;
+
+    for (block_id, encoded) in blocks {
+        if encoded.as_slice().len() < BLOCK_HEADER_LEN {
+            return Err(format!("block {}: too short to be a block", block_id));
+        }
+        let block = DefaultBlock::decode(encoded.clone())
+            .map_err(|e| format!("block {}: {}", block_id, e))?;
+        let parent_hash = block.parent_hash().map(|hash| hash.into_bytes());
+
+        if *block_id == 0 && parent_hash != genesis_parent_hash {
+            return Err(match genesis_parent_hash {
+                None => "block 0: the first block has a parent hash".to_string(),
+                Some(_) => "block 0: the parent hash is not the genesis parent hash".to_string(),
+            });
+        }
+
+        if let ICRC3Value::Map(map) = &block.transaction {
+            if let Some(ICRC3Value::Blob(phash)) = map.get("phash") {
+                if phash.as_slice() != parent_hash.unwrap_or([0; 32]) {
+                    return Err(format!(
+                        "block {}: phash does not match its parent hash",
+                        block_id
+                    ));
+                }
+            }
+        }
+
+        if let Some((previous_id, previous_hash)) = previous {
+            if previous_id + 1 == *block_id && parent_hash != Some(previous_hash) {
+                return Err(format!(
+                    "block {}: parent hash does not match the hash of block {}",
+                    block_id, previous_id
+                ));
+            }
+        }
+        previous = Some((*block_id, DefaultBlock::block_hash(encoded).into_bytes()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bity_ic_icrc3_archive_api::types::hash::HashOf;
+    use serde_bytes::ByteBuf;
+    use std::collections::BTreeMap;
+
+    fn chain(length: u64) -> Vec<(BlockIndex, EncodedBlock)> {
+        let mut blocks = vec![];
+        let mut parent_hash: Option<HashOf<EncodedBlock>> = None;
+        for block_id in 0..length {
+            let phash = parent_hash.map_or([0; 32], |hash| hash.into_bytes());
+            let transaction = ICRC3Value::Map(BTreeMap::from([
+                ("phash".to_string(), ICRC3Value::Blob(ByteBuf::from(phash))),
+                ("id".to_string(), ICRC3Value::Nat(block_id.into())),
+            ]));
+            let encoded =
+                DefaultBlock::from_transaction(parent_hash, transaction, block_id as u128).encode();
+            parent_hash = Some(DefaultBlock::block_hash(&encoded));
+            blocks.push((block_id, encoded));
+        }
+        blocks
+    }
+
+    fn flip_byte(block: &EncodedBlock, position: usize) -> EncodedBlock {
+        let mut bytes = block.clone().into_vec();
+        bytes[position] ^= 0xff;
+        EncodedBlock::from_vec(bytes)
+    }
+
+    #[test]
+    fn test_sample_indices() {
+        assert_eq!(sample_indices(&[1], 5, 10), vec![0, 1, 2, 3, 4]);
+        assert!(sample_indices(&[1], 0, 10).is_empty());
+
+        let indices = sample_indices(&[7; 32], 1_000, 20);
+        assert_eq!(indices.len(), 20);
+        assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(indices.iter().all(|index| *index < 1_000));
+        assert_eq!(indices, sample_indices(&[7; 32], 1_000, 20));
+        assert_ne!(indices, sample_indices(&[8; 32], 1_000, 20));
+    }
+
+    #[test]
+    fn test_linked_blocks_are_verified() {
+        let blocks = chain(4);
+        assert_eq!(verify_linkage(&blocks, None), Ok(()));
+        assert_eq!(verify_linkage(&blocks[1..3], None), Ok(()));
+    }
+
+    #[test]
+    fn test_corrupted_blocks_are_reported() {
+        let blocks = chain(4);
+
+        // A corrupted parent hash breaks the link to the previous block.
+        let mut corrupted = blocks.clone();
+        corrupted[2].1 = flip_byte(&blocks[2].1, 0);
+        assert_eq!(
+            verify_linkage(&corrupted[1..4], None),
+            Err("block 2: phash does not match its parent hash".to_string())
+        );
+
+        // A corrupted timestamp changes the hash linked by the next block.
+        let mut corrupted = blocks.clone();
+        corrupted[1].1 = flip_byte(&blocks[1].1, 40);
+        assert_eq!(
+            verify_linkage(&corrupted[0..3], None),
+            Err("block 2: parent hash does not match the hash of block 1".to_string())
+        );
+
+        let mut corrupted = blocks.clone();
+        corrupted[0].1 = flip_byte(&blocks[0].1, 0);
+        assert_eq!(
+            verify_linkage(&corrupted[0..2], None),
+            Err("block 0: the first block has a parent hash".to_string())
+        );
+
+        let truncated = vec![(3, EncodedBlock::from_vec(vec![0; 10]))];
+        assert_eq!(
+            verify_linkage(&truncated, None),
+            Err("block 3: too short to be a block".to_string())
+        );
+    }
+}
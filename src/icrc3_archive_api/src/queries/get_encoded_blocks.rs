@@ -0,0 +1,18 @@
+use crate::types::encoded_blocks::EncodedBlock;
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// A range of blocks to read as stored, addressed by their index in the chain.
+///
+/// Unlike `icrc3_get_blocks`, the blocks are returned without being decoded, so
+/// the main canister can open them and recompute their hashes.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Args {
+    pub start: u64,
+    pub length: u64,
+}
+
+/// The stored blocks of the range with their index in the chain, capped at
+/// `max_blocks_per_response`.
+pub type Response = Vec<(u64, EncodedBlock)>;
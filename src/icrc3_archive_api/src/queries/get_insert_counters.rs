@@ -0,0 +1,15 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// The number of `insert_blocks` calls the archive accepted and rejected since it
+/// was installed. Calls that trapped are not counted.
+///
+/// Only available in test mode.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InsertCounters {
+    pub accepted: u64,
+    pub rejected: u64,
+}
+
+pub type Args = ();
+pub type Response = Result<InsertCounters, String>;
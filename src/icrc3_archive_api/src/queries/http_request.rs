@@ -0,0 +1,4 @@
+use bity_ic_canister_logger::{HttpRequest, HttpResponse};
+
+pub type Args = HttpRequest;
+pub type Response = HttpResponse;
@@ -1,4 +1,11 @@
+pub mod find_block_by_thash;
+pub mod get_encoded_blocks;
+pub mod get_insert_counters;
+pub mod get_transactions;
 pub mod get_version;
+pub mod http_request;
+pub mod http_request_streaming_callback;
 pub mod icrc3_get_blocks;
 pub mod remaining_capacity;
+pub mod stats;
 pub mod total_transactions;
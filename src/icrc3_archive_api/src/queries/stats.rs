@@ -0,0 +1,22 @@
+use bity_ic_types::TimestampNanos;
+use candid::{CandidType, Nat};
+use serde::{Deserialize, Serialize};
+
+/// What an archive stores, for capacity planning.
+///
+/// # Fields
+///
+/// * `blocks_stored` - The number of blocks stored
+/// * `total_bytes` - The number of bytes of the stored blocks
+/// * `last_insert_at` - When blocks were last inserted, in nanoseconds, `None` if none were
+/// * `remaining_capacity` - The number of bytes of blocks that can still be stored
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct StorageStats {
+    pub blocks_stored: u64,
+    pub total_bytes: u64,
+    pub last_insert_at: Option<TimestampNanos>,
+    pub remaining_capacity: Nat,
+}
+
+pub type Args = ();
+pub type Response = StorageStats;
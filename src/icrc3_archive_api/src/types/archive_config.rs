@@ -1,3 +1,4 @@
+use crate::types::block_compression::CompressionAlgo;
 use candid::CandidType;
 use serde::{Deserialize, Serialize};
 
@@ -9,6 +10,16 @@ pub struct ArchiveConfig {
     pub max_blocks_per_response: u64,
     /// The offset of the first block in the archive.
     pub block_offset: u64,
+    /// The compression of the blocks sent to the archive, if any. Each stored
+    /// block names its own compression, so this can change over time.
+    #[serde(default)]
+    pub compression: Option<CompressionAlgo>,
+    /// Whether the blocks are indexed by transaction hash as they are inserted,
+    /// for `find_block_by_thash`. The index takes about 40 bytes of stable memory
+    /// per block. Blocks sealed by the main canister can't be indexed, so the main
+    /// canister doesn't set it along with a block transform.
+    #[serde(default)]
+    pub index_thashes: bool,
 }
 
 const MAX_MEMORY_SIZE_BYTES: u128 = 1024 * 1024 * 1024; // 1GB
@@ -20,6 +31,8 @@ impl Default for ArchiveConfig {
             max_memory_size_bytes: MAX_MEMORY_SIZE_BYTES,
             max_blocks_per_response: MAX_BLOCKS_PER_RESPONSE,
             block_offset: 0,
+            compression: None,
+            index_thashes: false,
         }
     }
 }
@@ -34,6 +47,8 @@ impl ArchiveConfig {
             max_memory_size_bytes,
             max_blocks_per_response,
             block_offset,
+            compression: None,
+            index_thashes: false,
         }
     }
 
@@ -0,0 +1,214 @@
+//! Optional compression of the blocks stored in archive canisters.
+//!
+//! A compressed block starts with a small header naming its algorithm, so that
+//! archives holding blocks written before compression was enabled, or with another
+//! algorithm, stay readable. Blocks without the header are returned as is. Block
+//! hashes are always computed over the uncompressed encoding.
+
+use crate::types::encoded_blocks::EncodedBlock;
+use candid::CandidType;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// Marks a compressed block. An uncompressed block starts with the hash of its
+/// parent, which has a 2^-40 chance of matching it.
+const COMPRESSED_BLOCK_MAGIC: &[u8] = b"\xffCBLK";
+
+/// Identifies DEFLATE in the header, as a zlib stream.
+const DEFLATE_ID: u8 = 1;
+
+const MAX_DEFLATE_LEVEL: u32 = 9;
+
+/// Compression algorithm of the blocks sent to archive canisters.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum CompressionAlgo {
+    /// DEFLATE in a zlib stream, with a level from 0 (none) to 9 (best)
+    Deflate { level: u32 },
+}
+
+impl CompressionAlgo {
+    /// Validates the configuration.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            CompressionAlgo::Deflate { level } if *level > MAX_DEFLATE_LEVEL => Err(format!(
+                "Deflate level must be at most {}",
+                MAX_DEFLATE_LEVEL
+            )),
+            CompressionAlgo::Deflate { .. } => Ok(()),
+        }
+    }
+
+    /// Compresses a block and prepends the header naming the algorithm.
+    pub fn compress(&self, block: EncodedBlock) -> EncodedBlock {
+        match self {
+            CompressionAlgo::Deflate { level } => {
+                let mut header = COMPRESSED_BLOCK_MAGIC.to_vec();
+                header.push(DEFLATE_ID);
+                let mut encoder =
+                    ZlibEncoder::new(header, Compression::new((*level).min(MAX_DEFLATE_LEVEL)));
+                encoder
+                    .write_all(block.as_slice())
+                    .expect("writing to a Vec cannot fail");
+                EncodedBlock::from_vec(encoder.finish().expect("writing to a Vec cannot fail"))
+            }
+        }
+    }
+}
+
+/// Returns whether a stored block carries the compression header.
+pub fn is_compressed(block: &EncodedBlock) -> bool {
+    block.as_slice().starts_with(COMPRESSED_BLOCK_MAGIC)
+}
+
+/// Decompresses a block read from an archive canister.
+///
+/// Blocks without the compression header are returned as is.
+///
+/// # Errors
+///
+/// Returns an error if the algorithm is unknown or the compressed bytes are corrupt.
+pub fn decompress(block: EncodedBlock) -> Result<EncodedBlock, String> {
+    let Some(rest) = block.as_slice().strip_prefix(COMPRESSED_BLOCK_MAGIC) else {
+        return Ok(block);
+    };
+    match rest.split_first() {
+        Some((&DEFLATE_ID, compressed)) => {
+            let mut decompressed = Vec::new();
+            ZlibDecoder::new(compressed)
+                .read_to_end(&mut decompressed)
+                .map_err(|e| format!("Failed to decompress block: {}", e))?;
+            Ok(EncodedBlock::from_vec(decompressed))
+        }
+        Some((id, _)) => Err(format!("Unknown block compression algorithm {}", id)),
+        None => Err("Compressed block has no algorithm".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::block_interface::Block;
+    use crate::types::defaultblock::DefaultBlock;
+    use crate::types::hash::HashOf;
+    use candid::Nat;
+    use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+    use serde_bytes::ByteBuf;
+    use std::collections::BTreeMap;
+
+    const DEFLATE: CompressionAlgo = CompressionAlgo::Deflate { level: 6 };
+
+    /// An ICRC-1 transfer block, as recorded by a ledger.
+    fn transfer_block(i: u64, parent_hash: Option<HashOf<EncodedBlock>>) -> EncodedBlock {
+        let account =
+            |owner: u8| ICRC3Value::Array(vec![ICRC3Value::Blob(ByteBuf::from(vec![owner; 29]))]);
+        let tx = ICRC3Value::Map(BTreeMap::from([
+            ("amt".to_string(), ICRC3Value::Nat(Nat::from(1_000_000 + i))),
+            ("from".to_string(), account(1)),
+            ("to".to_string(), account(2)),
+            ("op".to_string(), ICRC3Value::Text("xfer".to_string())),
+            (
+                "memo".to_string(),
+                ICRC3Value::Blob(ByteBuf::from(i.to_be_bytes().to_vec())),
+            ),
+            (
+                "ts".to_string(),
+                ICRC3Value::Nat(Nat::from(1_700_000_000_000_000_000u64 + i)),
+            ),
+        ]));
+        let block = ICRC3Value::Map(BTreeMap::from([
+            ("btype".to_string(), ICRC3Value::Text("1xfer".to_string())),
+            ("fee".to_string(), ICRC3Value::Nat(Nat::from(10_000u64))),
+            (
+                "phash".to_string(),
+                ICRC3Value::Blob(ByteBuf::from(
+                    parent_hash
+                        .map(|h| h.as_slice().to_vec())
+                        .unwrap_or(vec![0; 32]),
+                )),
+            ),
+            ("tx".to_string(), tx),
+        ]));
+        DefaultBlock::from_transaction(parent_hash, block, 1_700_000_000_000_000_000 + i as u128)
+            .encode()
+    }
+
+    fn chain(length: u64) -> Vec<EncodedBlock> {
+        let mut parent_hash = None;
+        (0..length)
+            .map(|i| {
+                let block = transfer_block(i, parent_hash);
+                parent_hash = Some(DefaultBlock::block_hash(&block));
+                block
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_compression_reduces_realistic_blocks() {
+        let blocks = chain(100);
+        let plain: usize = blocks.iter().map(|b| b.size_bytes()).sum();
+        let compressed: usize = blocks
+            .iter()
+            .map(|b| DEFLATE.compress(b.clone()).size_bytes())
+            .sum();
+        // The parent hash is stored twice per block and does not compress.
+        assert!(
+            compressed * 5 < plain * 4,
+            "compressed {} bytes into {}",
+            plain,
+            compressed
+        );
+    }
+
+    #[test]
+    fn test_round_trip_keeps_hashes() {
+        for block in chain(10) {
+            let compressed = DEFLATE.compress(block.clone());
+            assert!(is_compressed(&compressed));
+            let decompressed = decompress(compressed).unwrap();
+            assert_eq!(decompressed, block);
+            assert_eq!(
+                DefaultBlock::block_hash(&decompressed),
+                DefaultBlock::block_hash(&block)
+            );
+        }
+    }
+
+    #[test]
+    fn test_mixed_blocks_are_readable() {
+        // Blocks stored before compression was enabled, then compressed ones.
+        let blocks = chain(10);
+        let stored: Vec<EncodedBlock> = blocks
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                if i < 5 {
+                    b.clone()
+                } else {
+                    DEFLATE.compress(b.clone())
+                }
+            })
+            .collect();
+        assert_eq!(stored.iter().filter(|b| is_compressed(b)).count(), 5);
+
+        let read: Vec<EncodedBlock> = stored.into_iter().map(|b| decompress(b).unwrap()).collect();
+        assert_eq!(read, blocks);
+    }
+
+    #[test]
+    fn test_corrupt_blocks_are_rejected() {
+        let mut bytes = DEFLATE.compress(chain(1).remove(0)).into_vec();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        assert!(decompress(EncodedBlock::from_vec(bytes)).is_err());
+
+        let mut unknown = COMPRESSED_BLOCK_MAGIC.to_vec();
+        unknown.push(0x7f);
+        assert!(decompress(EncodedBlock::from_vec(unknown)).is_err());
+
+        assert!(CompressionAlgo::Deflate { level: 10 }.validate().is_err());
+    }
+}
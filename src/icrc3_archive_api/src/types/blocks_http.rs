@@ -0,0 +1,348 @@
+//! Export of a range of blocks as JSON over HTTP, for explorers pulling large
+//! ranges without agent code: `GET /blocks?start=<index>&length=<count>`.
+//!
+//! The body is a JSON array of `{"id": <index>, "block": <value>}`, the values being
+//! rendered by [`icrc3_value_json`]. It is streamed in chunks of at most
+//! `chunk_bytes` bytes (at least one block per chunk): the first response carries
+//! the first chunk and a [`BlocksToken`], which the HTTP gateway passes to the
+//! `http_request_streaming_callback` query of the canister to get the next chunk,
+//! until the range is exhausted. The chunks concatenated are the whole array.
+//!
+//! The token holds the whole state of the stream, so the canister keeps nothing
+//! between chunks. A token is only a position in the public chain: a malformed one
+//! is rejected, and an edited one serves another range of blocks.
+
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use serde_json::{json, Map, Value};
+
+/// The path of the blocks export.
+pub const BLOCKS_PATH: &str = "/blocks";
+/// The query the HTTP gateway calls to get the next chunk of a streamed response.
+pub const STREAMING_CALLBACK_METHOD: &str = "http_request_streaming_callback";
+/// The maximum size of a chunk, and the default one. Query responses are limited
+/// to 3MiB, the rest is left for the headers and the candid encoding.
+pub const MAX_BLOCKS_CHUNK_BYTES: usize = 2 * 1024 * 1024;
+
+const TOKEN_PREFIX: &str = "blocks:v1";
+
+/// The range of a blocks request, parsed from its query string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlocksRequest {
+    /// The index of the first block, 0 by default
+    pub start: u64,
+    /// The index following the last block, capped at the length of the chain
+    pub end: u64,
+    /// The maximum size of a chunk, [`MAX_BLOCKS_CHUNK_BYTES`] by default
+    pub chunk_bytes: usize,
+}
+
+impl BlocksRequest {
+    /// Parses the query string of a blocks request: `start`, `length` (up to the end
+    /// of the chain by default) and `chunk_bytes`.
+    ///
+    /// # Arguments
+    /// * `query` - The query string, without the leading `?`
+    /// * `chain_length` - The number of blocks of the chain
+    pub fn from_query(query: &str, chain_length: u64) -> Result<Self, String> {
+        let mut start = 0;
+        let mut length = None;
+        let mut chunk_bytes = MAX_BLOCKS_CHUNK_BYTES;
+
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match key {
+                "start" => {
+                    start = value
+                        .parse()
+                        .map_err(|_| format!("Invalid start: {value}"))?
+                }
+                "length" => {
+                    length = Some(
+                        value
+                            .parse::<u64>()
+                            .map_err(|_| format!("Invalid length: {value}"))?,
+                    )
+                }
+                "chunk_bytes" => chunk_bytes = parse_chunk_bytes(value)?,
+                _ => return Err(format!("Unknown query parameter: {key}")),
+            }
+        }
+
+        let start = start.min(chain_length);
+        let end = length.map_or(chain_length, |length| {
+            start.saturating_add(length).min(chain_length)
+        });
+        Ok(BlocksRequest {
+            start,
+            end,
+            chunk_bytes,
+        })
+    }
+}
+
+/// The position of a streamed blocks response, see the module documentation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlocksToken {
+    /// The index of the first block of the next chunk
+    pub next: u64,
+    /// The index following the last block of the range
+    pub end: u64,
+    /// The maximum size of a chunk
+    pub chunk_bytes: usize,
+}
+
+impl BlocksToken {
+    /// Returns the key of the streaming token.
+    pub fn to_key(&self) -> String {
+        format!(
+            "{TOKEN_PREFIX}:{}:{}:{}",
+            self.next, self.end, self.chunk_bytes
+        )
+    }
+
+    /// Parses the key of a streaming token.
+    ///
+    /// # Errors
+    /// Returns an error if the key is not a token of this version, or its range is
+    /// empty or its chunk size out of bounds.
+    pub fn from_key(key: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid blocks token: {key}");
+        let fields = key
+            .strip_prefix(TOKEN_PREFIX)
+            .and_then(|fields| fields.strip_prefix(':'))
+            .ok_or_else(invalid)?;
+        let mut fields = fields.split(':');
+        let (Some(next), Some(end), Some(chunk_bytes), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(invalid());
+        };
+        let token = BlocksToken {
+            next: next.parse().map_err(|_| invalid())?,
+            end: end.parse().map_err(|_| invalid())?,
+            chunk_bytes: parse_chunk_bytes(chunk_bytes).map_err(|_| invalid())?,
+        };
+        if token.next >= token.end {
+            return Err(invalid());
+        }
+        Ok(token)
+    }
+}
+
+/// A chunk of a blocks response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlocksChunk {
+    pub body: Vec<u8>,
+    /// The position of the next chunk, `None` for the last one
+    pub next: Option<BlocksToken>,
+}
+
+/// Renders the chunk of a blocks response starting at block `start`.
+///
+/// Blocks are added until the next one would exceed `chunk_bytes`, there is no
+/// block left before `end`, or `get_block` has none. The first chunk opens the
+/// JSON array and the last one closes it.
+///
+/// # Arguments
+/// * `start` - The index of the first block of the chunk
+/// * `end` - The index following the last block of the range
+/// * `chunk_bytes` - The maximum size of the chunk, exceeded by a single block only
+/// * `first` - Whether this is the first chunk of the response
+/// * `get_block` - Returns the block at an index, if it is served by the canister
+pub fn render_blocks_chunk(
+    start: u64,
+    end: u64,
+    chunk_bytes: usize,
+    first: bool,
+    mut get_block: impl FnMut(u64) -> Option<ICRC3Value>,
+) -> BlocksChunk {
+    let mut body = if first { b"[".to_vec() } else { vec![] };
+    let mut next = start;
+
+    while next < end {
+        let Some(block) = get_block(next) else {
+            break;
+        };
+        let separator: &[u8] = if first && next == start { b"" } else { b"," };
+        let item = block_json(next, &block).to_string();
+        // A chunk holds at least one block, and room is kept for the closing bracket.
+        if next > start && body.len() + separator.len() + item.len() + 1 > chunk_bytes {
+            return BlocksChunk {
+                body,
+                next: Some(BlocksToken {
+                    next,
+                    end,
+                    chunk_bytes,
+                }),
+            };
+        }
+        body.extend_from_slice(separator);
+        body.extend_from_slice(item.as_bytes());
+        next += 1;
+    }
+
+    body.push(b']');
+    BlocksChunk { body, next: None }
+}
+
+/// Returns a block as rendered in a blocks response: `{"id": <index>, "block": <value>}`.
+pub fn block_json(id: u64, block: &ICRC3Value) -> Value {
+    json!({ "id": id, "block": icrc3_value_json(block) })
+}
+
+/// Returns the JSON of a value, tagged with its variant as in candid so that it can
+/// be read back unambiguously: numbers are rendered as decimal strings and blobs as
+/// hex strings, e.g. `{"Map": {"amt": {"Nat": "100"}, "memo": {"Blob": "0a0b"}}}`.
+pub fn icrc3_value_json(value: &ICRC3Value) -> Value {
+    match value {
+        ICRC3Value::Nat(n) => json!({ "Nat": n.0.to_string() }),
+        ICRC3Value::Int(i) => json!({ "Int": i.0.to_string() }),
+        ICRC3Value::Text(text) => json!({ "Text": text }),
+        ICRC3Value::Blob(blob) => json!({ "Blob": hex::encode(blob) }),
+        ICRC3Value::Array(values) => {
+            json!({ "Array": values.iter().map(icrc3_value_json).collect::<Vec<Value>>() })
+        }
+        ICRC3Value::Map(map) => json!({
+            "Map": map
+                .iter()
+                .map(|(key, value)| (key.clone(), icrc3_value_json(value)))
+                .collect::<Map<String, Value>>()
+        }),
+    }
+}
+
+fn parse_chunk_bytes(value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|chunk_bytes| (1..=MAX_BLOCKS_CHUNK_BYTES).contains(chunk_bytes))
+        .ok_or_else(|| {
+            format!("Invalid chunk_bytes: {value}, expected 1 to {MAX_BLOCKS_CHUNK_BYTES}")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::{Int, Nat};
+    use serde_bytes::ByteBuf;
+    use std::collections::BTreeMap;
+
+    fn block(i: u64) -> ICRC3Value {
+        ICRC3Value::Map(BTreeMap::from([
+            ("amt".to_string(), ICRC3Value::Nat(Nat::from(i))),
+            ("delta".to_string(), ICRC3Value::Int(Int::from(-(i as i64)))),
+            (
+                "memo".to_string(),
+                ICRC3Value::Blob(ByteBuf::from(vec![i as u8, 0xff])),
+            ),
+            (
+                "tags".to_string(),
+                ICRC3Value::Array(vec![ICRC3Value::Text("a\"b".to_string())]),
+            ),
+        ]))
+    }
+
+    #[test]
+    fn test_value_json() {
+        assert_eq!(
+            block_json(2, &block(2)),
+            json!({
+                "id": 2,
+                "block": {"Map": {
+                    "amt": {"Nat": "2"},
+                    "delta": {"Int": "-2"},
+                    "memo": {"Blob": "02ff"},
+                    "tags": {"Array": [{"Text": "a\"b"}]},
+                }}
+            })
+        );
+    }
+
+    #[test]
+    fn test_streamed_chunks_reassemble_the_range() {
+        let request = BlocksRequest::from_query("start=3&length=50&chunk_bytes=600", 40).unwrap();
+        assert_eq!(
+            request,
+            BlocksRequest {
+                start: 3,
+                end: 40,
+                chunk_bytes: 600
+            }
+        );
+
+        let mut chunk =
+            render_blocks_chunk(request.start, request.end, request.chunk_bytes, true, |i| {
+                Some(block(i))
+            });
+        let mut body = chunk.body.clone();
+        let mut chunks = 1;
+        while let Some(token) = chunk.next {
+            assert!(chunk.body.len() <= 600);
+            let token = BlocksToken::from_key(&token.to_key()).unwrap();
+            chunk = render_blocks_chunk(token.next, token.end, token.chunk_bytes, false, |i| {
+                Some(block(i))
+            });
+            body.extend(&chunk.body);
+            chunks += 1;
+        }
+        assert!(chunks > 5, "{chunks}");
+
+        let expected: Vec<Value> = (3..40).map(|i| block_json(i, &block(i))).collect();
+        let served: Vec<Value> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(served, expected);
+    }
+
+    #[test]
+    fn test_chunk_stops_at_missing_block_and_oversized_block() {
+        // A block larger than the chunk is sent alone.
+        let chunk = render_blocks_chunk(0, 10, 10, true, |i| Some(block(i)));
+        assert_eq!(chunk.next.map(|token| token.next), Some(1));
+
+        let chunk = render_blocks_chunk(0, 10, MAX_BLOCKS_CHUNK_BYTES, true, |i| {
+            (i < 4).then(|| block(i))
+        });
+        assert_eq!(chunk.next, None);
+        let served: Vec<Value> = serde_json::from_slice(&chunk.body).unwrap();
+        assert_eq!(served.len(), 4);
+
+        let empty = render_blocks_chunk(5, 5, MAX_BLOCKS_CHUNK_BYTES, true, |i| Some(block(i)));
+        assert_eq!(empty.body, b"[]");
+    }
+
+    #[test]
+    fn test_invalid_requests_and_tokens_are_rejected() {
+        assert_eq!(
+            BlocksRequest::from_query("", 7).unwrap(),
+            BlocksRequest {
+                start: 0,
+                end: 7,
+                chunk_bytes: MAX_BLOCKS_CHUNK_BYTES
+            }
+        );
+        assert!(BlocksRequest::from_query("start=-1", 7).is_err());
+        assert!(BlocksRequest::from_query("chunk_bytes=0", 7).is_err());
+        assert!(BlocksRequest::from_query("chunk_bytes=99999999", 7).is_err());
+        assert!(BlocksRequest::from_query("limit=2", 7).is_err());
+
+        let token = BlocksToken {
+            next: 5,
+            end: 9,
+            chunk_bytes: 100,
+        };
+        assert_eq!(BlocksToken::from_key(&token.to_key()), Ok(token));
+        for key in [
+            "",
+            "blocks:v1",
+            "blocks:v1:5:9",
+            "blocks:v1:5:9:100:1",
+            "blocks:v2:5:9:100",
+            "blocks:v1:9:5:100",
+            "blocks:v1:5:9:0",
+            "blocks:v1:x:9:100",
+            "blocks:v1:5:9:100\u{0}",
+        ] {
+            assert!(BlocksToken::from_key(key).is_err(), "{key}");
+        }
+    }
+}
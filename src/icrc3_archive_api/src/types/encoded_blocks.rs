@@ -65,4 +65,10 @@ impl EncodedBlock {
     pub fn size_bytes(&self) -> usize {
         self.block.len()
     }
+
+    /// Returns the number of bytes the block takes once stored by an archive,
+    /// encoding included.
+    pub fn stored_size_bytes(&self) -> usize {
+        self.to_bytes().len()
+    }
 }
@@ -0,0 +1,153 @@
+//! The hash rules of the ICRC3 blocks and of the tip certificate.
+//!
+//! `bity-ic-icrc3` hashes its blocks and builds its certified data with these
+//! functions, and `bity-ic-icrc3-verifier` checks them with the same ones, so
+//! that the ledger and the verifier cannot disagree on them. They live here so
+//! that the ledger doesn't pull the BLS dependencies of the verifier.
+
+use crate::types::{block_interface::Block, defaultblock::DefaultBlock, hash::HashOf};
+use ic_certification::{Hash, RbTree};
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+
+/// The label of the index of the last block in the tip hash tree.
+pub const LAST_BLOCK_INDEX_LABEL: &str = "last_block_index";
+
+/// The label of the hash of the last block in the tip hash tree.
+pub const LAST_BLOCK_HASH_LABEL: &str = "last_block_hash";
+
+const MAX_U64_ENCODING_BYTES: usize = 10;
+
+/// Builds the hash tree certified by the ledger.
+///
+/// The tree holds the LEB128 encoded index of the last block and its hash, or
+/// nothing while the chain is empty. Its root hash is the certified data.
+///
+/// # Arguments
+///
+/// * `tip` - The index and hash of the last block, `None` for an empty chain
+pub fn tip_hash_tree(tip: Option<(u64, Hash)>) -> RbTree<&'static str, Vec<u8>> {
+    let mut hash_tree = RbTree::new();
+    if let Some((last_block_index, last_block_hash)) = tip {
+        hash_tree.insert(LAST_BLOCK_INDEX_LABEL, encode_block_index(last_block_index));
+        hash_tree.insert(LAST_BLOCK_HASH_LABEL, last_block_hash.to_vec());
+    }
+    hash_tree
+}
+
+/// Encodes a block index as in the tip hash tree, in unsigned LEB128.
+pub fn encode_block_index(block_index: u64) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(MAX_U64_ENCODING_BYTES);
+    leb128::write::unsigned(&mut buf, block_index).expect("writing to a Vec cannot fail");
+    buf
+}
+
+/// Decodes a block index encoded by [`encode_block_index`].
+///
+/// # Returns
+///
+/// The index, or `None` if `bytes` is not exactly one LEB128 encoded `u64`.
+pub fn decode_block_index(bytes: &[u8]) -> Option<u64> {
+    let mut reader = bytes;
+    let block_index = leb128::read::unsigned(&mut reader).ok()?;
+    reader.is_empty().then_some(block_index)
+}
+
+/// Returns the hash of a block, as computed by the ledger when adding it.
+///
+/// # Arguments
+///
+/// * `parent_hash` - The hash of the previous block, `None` for the first block
+/// * `timestamp` - The timestamp of the block
+/// * `transaction` - The block as returned by `icrc3_get_blocks`, `phash` included
+///
+/// # Returns
+///
+/// The hash, or `None` if the block holds a number that the block encoding
+/// cannot represent, in which case the ledger cannot have produced it.
+pub fn block_hash(
+    parent_hash: Option<Hash>,
+    timestamp: u128,
+    transaction: &ICRC3Value,
+) -> Option<Hash> {
+    if !is_encodable(transaction) {
+        return None;
+    }
+    let block = DefaultBlock::from_transaction(
+        parent_hash.map(HashOf::new),
+        transaction.clone(),
+        timestamp,
+    );
+    Some(DefaultBlock::block_hash(&block.encode()).into_bytes())
+}
+
+/// Returns the `phash` field of a block.
+///
+/// The first block of a chain carries a zero hash, returned as is.
+pub fn parent_hash(transaction: &ICRC3Value) -> Option<Hash> {
+    match transaction {
+        ICRC3Value::Map(map) => match map.get("phash") {
+            Some(ICRC3Value::Blob(phash)) => phash.as_slice().try_into().ok(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns whether every number of a value fits the 64 bits of the block encoding.
+fn is_encodable(value: &ICRC3Value) -> bool {
+    match value {
+        ICRC3Value::Nat(n) => u64::try_from(n.0.clone()).is_ok(),
+        ICRC3Value::Int(i) => i64::try_from(i.0.clone()).is_ok(),
+        ICRC3Value::Array(values) => values.iter().all(is_encodable),
+        ICRC3Value::Map(map) => map.values().all(is_encodable),
+        ICRC3Value::Blob(_) | ICRC3Value::Text(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Nat;
+    use ic_certification::AsHashTree;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_block_index_round_trip() {
+        for block_index in [0, 1, 127, 128, 300, u64::MAX] {
+            assert_eq!(
+                decode_block_index(&encode_block_index(block_index)),
+                Some(block_index)
+            );
+        }
+        let mut trailing = encode_block_index(5);
+        trailing.push(0);
+        assert_eq!(decode_block_index(&trailing), None);
+        assert_eq!(decode_block_index(&[0x80]), None);
+    }
+
+    #[test]
+    fn test_tip_hash_tree() {
+        let tree = tip_hash_tree(Some((4, [7; 32]))).as_hash_tree();
+        assert_eq!(
+            tree.lookup_path([LAST_BLOCK_INDEX_LABEL.as_bytes()]),
+            ic_certification::LookupResult::Found(&[4])
+        );
+        assert_eq!(
+            tree.lookup_path([LAST_BLOCK_HASH_LABEL.as_bytes()]),
+            ic_certification::LookupResult::Found(&[7; 32])
+        );
+        assert_eq!(
+            tip_hash_tree(None).root_hash(),
+            ic_certification::empty().digest()
+        );
+    }
+
+    #[test]
+    fn test_block_hash_rejects_unencodable_numbers() {
+        let block = ICRC3Value::Map(BTreeMap::from([(
+            "amount".to_string(),
+            ICRC3Value::Nat(Nat::from(u128::MAX)),
+        )]));
+        assert_eq!(block_hash(None, 0, &block), None);
+    }
+}
@@ -0,0 +1,294 @@
+//! Legacy `get_transactions` view of ICRC-1 and ICRC-2 blocks.
+//!
+//! Wallets integrated before ICRC-3 read the chain through the `get_transactions`
+//! endpoint of the reference ledger and index canisters, which returns
+//! `Transaction` records of kind `mint`, `burn`, `transfer` or `approve`. This
+//! module maps the blocks of the `1mint`, `1burn`, `1xfer`, `2xfer` and
+//! `2approve` types to that view, for the main canister and the archives alike.
+//!
+//! The legacy view has no block index: the transactions of a response are
+//! consecutive from its first index. Other blocks, and blocks missing a field the
+//! legacy record requires, are therefore not dropped but returned with their
+//! `btype` as `kind` and no operation record, which legacy clients skip.
+
+use candid::{Nat, Principal};
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
+use icrc_ledger_types::icrc1::transfer::Memo;
+use icrc_ledger_types::icrc3::transactions::{Approve, Burn, Mint, Transaction, Transfer};
+use std::collections::BTreeMap;
+
+/// The name of the archive query serving the legacy view of its blocks.
+pub const ARCHIVE_GET_TRANSACTIONS_METHOD: &str = "get_transactions";
+
+/// Returns the legacy view of a block value, see the module documentation for
+/// the blocks that are not ICRC-1 or ICRC-2 blocks.
+pub fn legacy_transaction(block: &ICRC3Value) -> Transaction {
+    match decode(block) {
+        Ok(transaction) => transaction,
+        Err(kind) => Transaction {
+            kind,
+            mint: None,
+            burn: None,
+            transfer: None,
+            approve: None,
+            timestamp: timestamp(block),
+        },
+    }
+}
+
+/// Maps an ICRC-1 or ICRC-2 block, or returns the kind of its placeholder.
+fn decode(block: &ICRC3Value) -> Result<Transaction, String> {
+    let ICRC3Value::Map(fields) = block else {
+        return Err(String::new());
+    };
+    let tx = match fields.get("tx") {
+        Some(ICRC3Value::Map(tx)) => tx,
+        _ => return Err(text(fields, "btype").unwrap_or_default()),
+    };
+    // Blocks without a btype are typed by their `op`, as in the ICRC-3 standard.
+    let btype = match (text(fields, "btype"), text(tx, "op")) {
+        (Some(btype), _) => btype,
+        (None, Some(op)) if op == "approve" => "2approve".to_string(),
+        (None, Some(op)) => format!("1{}", op),
+        (None, None) => return Err(String::new()),
+    };
+
+    let placeholder = || btype.clone();
+    let timestamp = timestamp(block);
+    let amount = nat(tx, "amt").ok_or_else(placeholder)?;
+    let memo = blob(tx, "memo").map(Memo::from);
+    let created_at_time = u64_field(tx, "ts");
+    // ICRC-1 blocks carry the fee in the transaction, ICRC-2 blocks in the block.
+    let fee = nat(tx, "fee").or_else(|| nat(fields, "fee"));
+
+    let transaction = match btype.as_str() {
+        "1mint" => Transaction::mint(
+            Mint {
+                amount,
+                to: account(tx, "to").ok_or_else(placeholder)?,
+                memo,
+                created_at_time,
+                fee,
+            },
+            timestamp,
+        ),
+        "1burn" => Transaction::burn(
+            Burn {
+                amount,
+                from: account(tx, "from").ok_or_else(placeholder)?,
+                spender: account(tx, "spender"),
+                memo,
+                created_at_time,
+                fee,
+            },
+            timestamp,
+        ),
+        "1xfer" | "2xfer" => Transaction::transfer(
+            Transfer {
+                amount,
+                from: account(tx, "from").ok_or_else(placeholder)?,
+                to: account(tx, "to").ok_or_else(placeholder)?,
+                spender: account(tx, "spender"),
+                memo,
+                fee,
+                created_at_time,
+            },
+            timestamp,
+        ),
+        "2approve" => Transaction::approve(
+            Approve {
+                from: account(tx, "from").ok_or_else(placeholder)?,
+                spender: account(tx, "spender").ok_or_else(placeholder)?,
+                amount,
+                expected_allowance: nat(tx, "expected_allowance"),
+                expires_at: u64_field(tx, "expires_at"),
+                memo,
+                fee,
+                created_at_time,
+            },
+            timestamp,
+        ),
+        _ => return Err(btype),
+    };
+    Ok(transaction)
+}
+
+/// The timestamp of a block: `ts` for ICRC-1 blocks, `timestamp` for ICRC-2 blocks.
+fn timestamp(block: &ICRC3Value) -> u64 {
+    let ICRC3Value::Map(fields) = block else {
+        return 0;
+    };
+    u64_field(fields, "ts")
+        .or_else(|| u64_field(fields, "timestamp"))
+        .unwrap_or(0)
+}
+
+fn text(fields: &BTreeMap<String, ICRC3Value>, name: &str) -> Option<String> {
+    match fields.get(name) {
+        Some(ICRC3Value::Text(text)) => Some(text.clone()),
+        _ => None,
+    }
+}
+
+fn nat(fields: &BTreeMap<String, ICRC3Value>, name: &str) -> Option<Nat> {
+    match fields.get(name) {
+        Some(ICRC3Value::Nat(nat)) => Some(nat.clone()),
+        _ => None,
+    }
+}
+
+fn u64_field(fields: &BTreeMap<String, ICRC3Value>, name: &str) -> Option<u64> {
+    nat(fields, name).and_then(|nat| u64::try_from(nat.0).ok())
+}
+
+fn blob(fields: &BTreeMap<String, ICRC3Value>, name: &str) -> Option<Vec<u8>> {
+    match fields.get(name) {
+        Some(ICRC3Value::Blob(blob)) => Some(blob.to_vec()),
+        _ => None,
+    }
+}
+
+/// Decodes an account, stored as the text of its owner, or as the ICRC-3 array
+/// of its owner and subaccount.
+fn account(fields: &BTreeMap<String, ICRC3Value>, name: &str) -> Option<Account> {
+    match fields.get(name)? {
+        ICRC3Value::Text(owner) => Some(Account {
+            owner: Principal::from_text(owner).ok()?,
+            subaccount: None,
+        }),
+        ICRC3Value::Array(parts) => {
+            let owner = match parts.first()? {
+                ICRC3Value::Blob(owner) => Principal::try_from_slice(owner).ok()?,
+                _ => return None,
+            };
+            let subaccount = match parts.get(1) {
+                Some(ICRC3Value::Blob(subaccount)) => {
+                    Some(Subaccount::try_from(subaccount.as_slice()).ok()?)
+                }
+                Some(_) => return None,
+                None => None,
+            };
+            Some(Account { owner, subaccount })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_bytes::ByteBuf;
+
+    fn block(btype: &str, top: Vec<(&str, ICRC3Value)>, tx: Vec<(&str, ICRC3Value)>) -> ICRC3Value {
+        let mut fields: BTreeMap<String, ICRC3Value> = top
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        fields.insert("btype".to_string(), ICRC3Value::Text(btype.to_string()));
+        fields.insert(
+            "tx".to_string(),
+            ICRC3Value::Map(
+                tx.into_iter()
+                    .map(|(name, value)| (name.to_string(), value))
+                    .collect(),
+            ),
+        );
+        ICRC3Value::Map(fields)
+    }
+
+    fn owner(byte: u8) -> Principal {
+        Principal::from_slice(&[byte])
+    }
+
+    fn nat_value(n: u64) -> ICRC3Value {
+        ICRC3Value::Nat(Nat::from(n))
+    }
+
+    #[test]
+    fn test_icrc1_transfer_maps_every_field() {
+        let transfer = block(
+            "1xfer",
+            vec![("ts", nat_value(100))],
+            vec![
+                ("amt", nat_value(500)),
+                ("fee", nat_value(10)),
+                ("from", ICRC3Value::Text(owner(1).to_text())),
+                (
+                    "to",
+                    ICRC3Value::Array(vec![
+                        ICRC3Value::Blob(ByteBuf::from(owner(2).as_slice().to_vec())),
+                        ICRC3Value::Blob(ByteBuf::from(vec![7; 32])),
+                    ]),
+                ),
+                ("memo", ICRC3Value::Blob(ByteBuf::from(vec![1, 2, 3]))),
+                ("ts", nat_value(90)),
+            ],
+        );
+
+        assert_eq!(
+            legacy_transaction(&transfer),
+            Transaction::transfer(
+                Transfer {
+                    amount: Nat::from(500u64),
+                    from: Account {
+                        owner: owner(1),
+                        subaccount: None
+                    },
+                    to: Account {
+                        owner: owner(2),
+                        subaccount: Some([7; 32])
+                    },
+                    spender: None,
+                    memo: Some(Memo::from(vec![1, 2, 3])),
+                    fee: Some(Nat::from(10u64)),
+                    created_at_time: Some(90),
+                },
+                100
+            )
+        );
+    }
+
+    #[test]
+    fn test_icrc2_approve_takes_the_block_fee() {
+        let approve = block(
+            "2approve",
+            vec![("timestamp", nat_value(100)), ("fee", nat_value(10))],
+            vec![
+                ("amt", nat_value(500)),
+                ("from", ICRC3Value::Text(owner(1).to_text())),
+                ("spender", ICRC3Value::Text(owner(2).to_text())),
+                ("expected_allowance", nat_value(20)),
+                ("expires_at", nat_value(200)),
+            ],
+        );
+
+        let transaction = legacy_transaction(&approve);
+        assert_eq!(transaction.kind, "approve");
+        assert_eq!(transaction.timestamp, 100);
+        let approve = transaction.approve.unwrap();
+        assert_eq!(approve.fee, Some(Nat::from(10u64)));
+        assert_eq!(approve.spender.owner, owner(2));
+        assert_eq!(approve.expected_allowance, Some(Nat::from(20u64)));
+        assert_eq!(approve.expires_at, Some(200));
+    }
+
+    #[test]
+    fn test_other_blocks_become_placeholders() {
+        let nft = block("7mint", vec![("ts", nat_value(100))], vec![]);
+        let transaction = legacy_transaction(&nft);
+        assert_eq!(transaction.kind, "7mint");
+        assert_eq!(transaction.timestamp, 100);
+        assert!(transaction.mint.is_none() && transaction.transfer.is_none());
+
+        // A mint without its recipient can't be represented either.
+        let mint = block("1mint", vec![], vec![("amt", nat_value(1))]);
+        assert_eq!(legacy_transaction(&mint).kind, "1mint");
+        assert!(legacy_transaction(&mint).mint.is_none());
+
+        assert_eq!(
+            legacy_transaction(&ICRC3Value::Blob(ByteBuf::from(vec![1]))).kind,
+            ""
+        );
+    }
+}
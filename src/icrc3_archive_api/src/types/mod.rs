@@ -1,6 +1,11 @@
 pub mod archive_config;
+pub mod block_compression;
 pub mod block_interface;
+pub mod blocks_http;
 pub mod defaultblock;
 pub mod encoded_blocks;
 pub mod hash;
+pub mod hashing;
+pub mod legacy_transactions;
 pub mod sha256;
+pub mod thash;
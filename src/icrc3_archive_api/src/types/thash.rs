@@ -0,0 +1,89 @@
+//! Transaction hashes of stored blocks.
+//!
+//! The main canister rejects a duplicate transaction with the index of the block
+//! recording the original one, while clients only know the hash of the
+//! transaction they sent. That hash is the hash of the block value without the
+//! fields the main canister adds when the block is created, and with the
+//! timestamp the transaction was sent with.
+
+use crate::types::hash::HASH_LENGTH;
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+
+/// The fields added to a transaction when its block is created: the hash of the
+/// previous block, the recorder, the versions and the original timestamp of a
+/// block whose timestamp was raised to the tip's.
+pub const BLOCK_FIELDS: [&str; 4] = ["phash", "rec", "ver", "orig_ts"];
+
+/// Returns the hash of the transaction recorded in a block value, or `None` if
+/// the value is not a map, e.g. a block sealed by the main canister.
+///
+/// The `ts` of a block with an `orig_ts` field is replaced by it, as the
+/// transaction was sent with that timestamp.
+pub fn transaction_hash(block: &ICRC3Value) -> Option<[u8; HASH_LENGTH]> {
+    let ICRC3Value::Map(fields) = block else {
+        return None;
+    };
+    let mut transaction = fields.clone();
+    let orig_ts = transaction.get("orig_ts").cloned();
+    for field in BLOCK_FIELDS {
+        transaction.remove(field);
+    }
+    if let (Some(orig_ts), Some(ts)) = (orig_ts, transaction.get_mut("ts")) {
+        *ts = orig_ts;
+    }
+    Some(ICRC3Value::Map(transaction).hash())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Nat;
+    use serde_bytes::ByteBuf;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_transaction_hash_ignores_block_fields() {
+        let mut transaction = BTreeMap::new();
+        transaction.insert("btype".to_string(), ICRC3Value::Text("1xfer".to_string()));
+        transaction.insert("amt".to_string(), ICRC3Value::Nat(Nat::from(10u64)));
+        let thash = ICRC3Value::Map(transaction.clone()).hash();
+
+        let mut block = transaction;
+        block.insert(
+            "phash".to_string(),
+            ICRC3Value::Blob(ByteBuf::from(vec![1; 32])),
+        );
+        block.insert("rec".to_string(), ICRC3Value::Blob(ByteBuf::from(vec![2])));
+        assert_eq!(
+            transaction_hash(&ICRC3Value::Map(block.clone())),
+            Some(thash)
+        );
+
+        block.insert("memo".to_string(), ICRC3Value::Blob(ByteBuf::from(vec![3])));
+        assert_ne!(transaction_hash(&ICRC3Value::Map(block)), Some(thash));
+        assert_eq!(
+            transaction_hash(&ICRC3Value::Blob(ByteBuf::from(vec![4]))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_transaction_hash_restores_the_original_timestamp() {
+        let mut transaction = BTreeMap::new();
+        transaction.insert("btype".to_string(), ICRC3Value::Text("1xfer".to_string()));
+        transaction.insert("ts".to_string(), ICRC3Value::Nat(Nat::from(10u64)));
+        let thash = ICRC3Value::Map(transaction.clone()).hash();
+
+        // The timestamp of a clamped block is raised to the tip's.
+        let mut block = transaction;
+        block.insert("ts".to_string(), ICRC3Value::Nat(Nat::from(12u64)));
+        block.insert("orig_ts".to_string(), ICRC3Value::Nat(Nat::from(10u64)));
+        assert_eq!(
+            transaction_hash(&ICRC3Value::Map(block.clone())),
+            Some(thash)
+        );
+
+        block.remove("orig_ts");
+        assert_ne!(transaction_hash(&ICRC3Value::Map(block)), Some(thash));
+    }
+}
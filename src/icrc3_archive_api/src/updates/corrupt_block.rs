@@ -0,0 +1,6 @@
+/// The index in the chain of the block to corrupt.
+///
+/// Only available in test mode: the first byte of the stored block is flipped so
+/// that tests can check that the corruption is detected.
+pub type Args = u64;
+pub type Response = Result<(), String>;
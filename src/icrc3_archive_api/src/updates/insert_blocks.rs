@@ -1,12 +1,66 @@
 use crate::types::encoded_blocks::EncodedBlock;
 
-use candid::CandidType;
-use serde::Deserialize;
+use candid::{CandidType, Nat};
+use serde::{Deserialize, Serialize};
 
-pub type Args = Vec<EncodedBlock>;
+/// Blocks to append to the archive, starting at block `first_block_id`.
+///
+/// `first_block_id` must not be past the end of the archive. Blocks that are
+/// already stored are skipped, which makes retrying a batch safe.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Args {
+    pub first_block_id: u64,
+    pub blocks: Vec<EncodedBlock>,
+}
+
+pub type Response = Result<InsertBlocksSuccess, InsertBlocksError>;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct InsertBlocksSuccess {
+    /// Whether all the blocks were already stored
+    pub already_present: bool,
+    /// The number of blocks appended by this call
+    pub inserted: u64,
+    /// The id of the block following the last stored block
+    pub next_block_id: u64,
+    /// The number of bytes the archive can still store, `None` from archives
+    /// predating it
+    #[serde(default)]
+    pub remaining_capacity: Option<Nat>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum InsertBlocksError {
+    /// The blocks do not start at or before the end of the archive
+    NonContiguous {
+        expected: u64,
+        got: u64,
+    },
+    /// The blocks to append do not fit in the remaining capacity of the archive
+    NoSpaceLeft {
+        needed: Nat,
+        remaining_capacity: Nat,
+    },
+    InsertFailed(String),
+}
 
-#[derive(CandidType, Deserialize, Debug)]
-pub enum Response {
-    Success,
-    Error(String),
+impl std::fmt::Display for InsertBlocksError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InsertBlocksError::NonContiguous { expected, got } => write!(
+                f,
+                "Non contiguous blocks: expected first block {}, got {}",
+                expected, got
+            ),
+            InsertBlocksError::NoSpaceLeft {
+                needed,
+                remaining_capacity,
+            } => write!(
+                f,
+                "no space left: the blocks need {} bytes, {} remaining",
+                needed, remaining_capacity
+            ),
+            InsertBlocksError::InsertFailed(e) => write!(f, "Failed to insert blocks: {}", e),
+        }
+    }
 }
@@ -1 +1,2 @@
+pub mod corrupt_block;
 pub mod insert_blocks;
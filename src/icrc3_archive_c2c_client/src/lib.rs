@@ -1,11 +1,15 @@
-use bity_ic_canister_client::generate_candid_c2c_call;
+use bity_ic_canister_client::{generate_candid_c2c_call, generate_candid_c2c_call_with_retry};
 use bity_ic_icrc3_archive_api::*;
 
 // Queries
+generate_candid_c2c_call!(find_block_by_thash);
+generate_candid_c2c_call!(get_encoded_blocks);
 generate_candid_c2c_call!(icrc3_get_blocks);
 generate_candid_c2c_call!(get_version);
 generate_candid_c2c_call!(remaining_capacity);
+generate_candid_c2c_call!(stats);
 generate_candid_c2c_call!(total_transactions);
 
 // Updates
 generate_candid_c2c_call!(insert_blocks);
+generate_candid_c2c_call_with_retry!(insert_blocks_with_retry, insert_blocks);
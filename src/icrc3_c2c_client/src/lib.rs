@@ -6,3 +6,6 @@ generate_candid_c2c_call!(icrc3_get_properties);
 generate_candid_c2c_call!(icrc3_supported_block_types);
 generate_candid_c2c_call!(icrc3_get_archives);
 generate_candid_c2c_call!(icrc3_get_tip_certificate);
+generate_candid_c2c_call!(icrc3_get_tip);
+generate_candid_c2c_call!(icrc3_chain_length);
+generate_candid_c2c_call!(icrc3_has_block);
@@ -0,0 +1,38 @@
+// The Candid interface of the canister, as exported by `export_candid!()`.
+//
+// The endpoints are marked `hidden = true` so that the interface is the one
+// declared here from the `bity_ic_icrc3_archive_api` types: a new endpoint
+// must be listed here too.
+
+use crate::icrc3_archive_canister;
+
+bity_ic_candid_gen::candid_interface! {
+    canister = icrc3_archive,
+    queries = [
+        find_block_by_thash,
+        get_encoded_blocks,
+        get_insert_counters,
+        get_transactions,
+        get_version,
+        http_request,
+        http_request_streaming_callback,
+        icrc3_get_blocks,
+        remaining_capacity,
+        stats,
+        total_transactions,
+    ],
+    updates = [corrupt_block, insert_blocks],
+}
+
+#[cfg(test)]
+mod tests {
+    use bity_ic_utils::did::service_methods;
+
+    #[test]
+    fn test_candid_interface_matches_did() {
+        assert_eq!(
+            service_methods(&crate::__export_service()),
+            service_methods(include_str!("../../../../../icrc3_archive_api/can.did"))
+        );
+    }
+}
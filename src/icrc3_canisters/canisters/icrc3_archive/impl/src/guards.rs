@@ -1,13 +1,6 @@
+pub use crate::state::caller_is_authorized;
 use crate::state::read_state;
 
-pub fn caller_is_authorized() -> Result<(), String> {
-    if read_state(|state| state.is_caller_authorized()) {
-        Ok(())
-    } else {
-        Err("Caller is not an authorized principal".to_string())
-    }
-}
-
 pub fn caller_is_main_canister() -> Result<(), String> {
     if read_state(|state| state.is_caller_main_canister()) {
         Ok(())
@@ -17,9 +10,6 @@ pub fn caller_is_main_canister() -> Result<(), String> {
 }
 
 pub fn caller_is_main_canister_or_authorized() -> Result<(), String> {
-    if read_state(|state| state.is_caller_main_canister() || state.is_caller_authorized()) {
-        Ok(())
-    } else {
-        Err("Caller is not an admin principal".to_string())
-    }
+    // Rejected callers get the error of `caller_is_main_canister`, as before.
+    caller_is_main_canister().or_else(|error| caller_is_authorized().map_err(|_| error))
 }
@@ -1,5 +1,7 @@
 use ic_cdk::export_candid;
 // use gldt_swap_api_canister::types::swap::*;
+#[allow(dead_code)]
+mod candid_interface;
 mod guards;
 mod lifecycle;
 mod memory;
@@ -11,7 +13,7 @@ mod utils;
 // use ::types::{ HttpRequest, HttpResponse };
 
 use lifecycle::*;
-use queries::*;
-use updates::*;
+// `export_candid!()` resolves the types of the declared interface from here.
+use bity_ic_icrc3_archive_api as icrc3_archive_canister;
 
 export_candid!();
@@ -1,11 +1,50 @@
-pub mod init;
+mod init;
 mod post_upgrade;
 mod pre_upgrade;
 
-pub use init::*;
+use bity_ic_canister_state_macros::{canister_lifecycle, LifecycleStage};
+pub use bity_ic_icrc3_archive_api::lifecycle::Args;
 
 use crate::state::{init_state, RuntimeState};
+use init::on_init;
+use post_upgrade::on_post_upgrade;
+
+canister_lifecycle!(
+    Args,
+    validate = validate_args,
+    on_init = on_init,
+    on_post_upgrade = on_post_upgrade
+);
 
 pub fn init_canister(runtime_state: RuntimeState) {
     init_state(runtime_state);
 }
+
+fn validate_args(args: &Args, stage: LifecycleStage) -> Result<(), String> {
+    match (args, stage) {
+        (Args::Init(init_args), LifecycleStage::Init) => {
+            if init_args.commit_hash.is_empty() {
+                return Err("commit_hash is empty".to_string());
+            }
+            // In test mode the installer is added to the authorized principals.
+            if init_args.authorized_principals.is_empty() && !init_args.test_mode {
+                return Err("authorized_principals is empty".to_string());
+            }
+            Ok(())
+        }
+        (Args::Upgrade(upgrade_args), LifecycleStage::PostUpgrade) => {
+            if upgrade_args.commit_hash.is_empty() {
+                return Err("commit_hash is empty".to_string());
+            }
+            Ok(())
+        }
+        (Args::Upgrade(_), LifecycleStage::Init) => Err(
+            "Cannot initialize the canister with an Upgrade argument. Please provide an Init argument."
+                .to_string(),
+        ),
+        (Args::Init(_), LifecycleStage::PostUpgrade) => Err(
+            "Cannot upgrade the canister with an Init argument. Please provide an Upgrade argument."
+                .to_string(),
+        ),
+    }
+}
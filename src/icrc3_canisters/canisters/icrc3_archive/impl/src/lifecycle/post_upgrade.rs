@@ -1,36 +1,27 @@
 use crate::{memory::get_upgrades_memory, state::RuntimeState};
 use bity_ic_canister_logger::LogEntry;
-use bity_ic_canister_tracing_macros::trace;
-pub use bity_ic_icrc3_archive_api::lifecycle::Args;
 use bity_ic_stable_memory::get_reader;
-use ic_cdk_macros::post_upgrade;
-use tracing::info;
 
-use super::init_canister;
+use super::{init_canister, Args};
 
-#[post_upgrade]
-#[trace]
-fn post_upgrade(args: Args) {
-    match args {
-        Args::Init(_) =>
-            panic!(
-                "Cannot upgrade the canister with an Init argument. Please provide an Upgrade argument."
-            ),
-        Args::Upgrade(upgrade_args) => {
-            let memory = get_upgrades_memory();
-            let reader = get_reader(&memory);
+/// Restores the state saved before the upgrade, applies the validated upgrade
+/// arguments and returns the test mode with the saved logs and traces.
+pub(super) fn on_post_upgrade(args: Args) -> (bool, Vec<LogEntry>, Vec<LogEntry>) {
+    let Args::Upgrade(upgrade_args) = args else {
+        unreachable!("validated as upgrade arguments");
+    };
 
-            let (mut state, logs, traces): (RuntimeState, Vec<LogEntry>, Vec<LogEntry>) = bity_ic_serializer
-                ::deserialize(reader)
-                .unwrap();
+    let memory = get_upgrades_memory();
+    let reader = get_reader(&memory);
 
-            state.env.set_version(upgrade_args.version);
-            state.env.set_commit_hash(upgrade_args.commit_hash);
+    let (mut state, logs, traces): (RuntimeState, Vec<LogEntry>, Vec<LogEntry>) =
+        bity_ic_serializer::deserialize(reader).unwrap();
 
-            bity_ic_canister_logger::init_with_logs(state.env.is_test_mode(), logs, traces);
-            init_canister(state);
+    state.env.set_version(upgrade_args.version);
+    state.env.set_commit_hash(upgrade_args.commit_hash);
 
-            info!(version = %upgrade_args.version, "Post-upgrade complete");
-        }
-    }
+    let test_mode = state.env.is_test_mode();
+    init_canister(state);
+
+    (test_mode, logs, traces)
 }
@@ -7,6 +7,7 @@ pub type VM = VirtualMemory<DefaultMemoryImpl>;
 const UPGRADES: MemoryId = MemoryId::new(0);
 const BLOCK_LOG_INDEX_MEMORY_ID: MemoryId = MemoryId::new(1);
 const BLOCK_LOG_DATA_MEMORY_ID: MemoryId = MemoryId::new(2);
+const THASH_INDEX_MEMORY_ID: MemoryId = MemoryId::new(3);
 
 thread_local! {
     static MEMORY_MANAGER: MemoryManager<DefaultMemoryImpl> = MemoryManager::init(
@@ -29,3 +30,7 @@ pub fn get_block_log_index_memory() -> VM {
 pub fn get_block_log_data_memory() -> VM {
     get_memory(BLOCK_LOG_DATA_MEMORY_ID)
 }
+
+pub fn get_thash_index_memory() -> VM {
+    get_memory(THASH_INDEX_MEMORY_ID)
+}
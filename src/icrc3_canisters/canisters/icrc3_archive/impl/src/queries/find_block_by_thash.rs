@@ -0,0 +1,23 @@
+use crate::queries::icrc3_get_blocks::decode_block;
+use crate::state::read_state;
+
+pub use bity_ic_icrc3_archive_api::queries::find_block_by_thash::{
+    Args as FindBlockByThashArgs, Response as FindBlockByThashResponse,
+};
+use candid::Nat;
+use ic_cdk::query;
+use icrc_ledger_types::icrc3::blocks::BlockWithId;
+
+/// Returns the block recording the transaction with hash `thash`, or `None` if
+/// no indexed block does. Nothing is indexed unless `index_thashes` is set.
+#[query(hidden = true)]
+fn find_block_by_thash(thash: FindBlockByThashArgs) -> FindBlockByThashResponse {
+    read_state(|s| {
+        let block_id = s.data.archive.find_block_by_thash(&thash)?;
+        let block = s.data.archive.get_block(block_id)?;
+        Some(BlockWithId {
+            id: Nat::from(block_id),
+            block: decode_block(&s.data.block_type, block)?,
+        })
+    })
+}
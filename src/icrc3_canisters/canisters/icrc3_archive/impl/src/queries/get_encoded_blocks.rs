@@ -0,0 +1,11 @@
+use crate::state::read_state;
+
+pub use bity_ic_icrc3_archive_api::get_encoded_blocks::{
+    Args as GetEncodedBlocksArgs, Response as GetEncodedBlocksResponse,
+};
+use ic_cdk::query;
+
+#[query(hidden = true)]
+fn get_encoded_blocks(args: GetEncodedBlocksArgs) -> GetEncodedBlocksResponse {
+    read_state(|s| s.data.archive.get_encoded_blocks(args.start, args.length))
+}
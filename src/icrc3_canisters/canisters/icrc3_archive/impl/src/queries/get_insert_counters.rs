@@ -0,0 +1,15 @@
+use crate::state::read_state;
+pub use bity_ic_icrc3_archive_api::queries::get_insert_counters::{
+    Args as GetInsertCountersArgs, Response as GetInsertCountersResponse,
+};
+use ic_cdk::query;
+
+#[query(hidden = true)]
+fn get_insert_counters(_: GetInsertCountersArgs) -> GetInsertCountersResponse {
+    read_state(|s| {
+        if !s.env.is_test_mode() {
+            return Err("Insert counters are only available in test mode".to_string());
+        }
+        Ok(s.data.archive.insert_counters)
+    })
+}
@@ -0,0 +1,38 @@
+use crate::queries::icrc3_get_blocks::decode_block;
+use crate::state::read_state;
+pub use bity_ic_icrc3_archive_api::queries::get_transactions::{
+    Args as GetTransactionsArgs, Response as GetTransactionsResponse,
+};
+use bity_ic_icrc3_archive_api::types::legacy_transactions::legacy_transaction;
+use ic_cdk::query;
+
+/// Serves the blocks of the archive in the legacy `get_transactions` format, see
+/// `legacy_transactions` for how blocks are mapped.
+#[query(hidden = true)]
+fn get_transactions(req: GetTransactionsArgs) -> GetTransactionsResponse {
+    let block_type = read_state(|s| s.data.block_type.clone());
+    let range = (
+        u64::try_from(&req.start.0).unwrap_or(u64::MAX),
+        u64::try_from(&req.length.0).unwrap_or(u64::MAX),
+    );
+    let blocks = if range.1 > 0 {
+        read_state(|s| s.data.archive.get_blocks_ranges(&[range]))
+    } else {
+        vec![]
+    };
+
+    // The transactions are consecutive from the requested start, so a missing or
+    // undecodable block ends the range rather than shifting the ones after it.
+    let transactions = blocks
+        .into_iter()
+        .zip(range.0..)
+        .map_while(|((block_id, block), expected)| {
+            (block_id == expected)
+                .then(|| decode_block(&block_type, block))
+                .flatten()
+        })
+        .map(|block| legacy_transaction(&block))
+        .collect();
+
+    GetTransactionsResponse { transactions }
+}
@@ -4,7 +4,7 @@ pub use bity_ic_icrc3_archive_api::get_version::{
 };
 use ic_cdk::query;
 
-#[query]
+#[query(hidden = true)]
 async fn get_version(_: GetVersionArg) -> GetVersionResponse {
     read_state(|s| s.env.version())
 }
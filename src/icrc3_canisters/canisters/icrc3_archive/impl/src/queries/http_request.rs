@@ -1,21 +1,62 @@
-// #[query(hidden = true)]
-// fn http_request(request: HttpRequest) -> HttpResponse {
-//     fn get_logs_impl(since: Option<TimestampMillis>) -> HttpResponse {
-//         encode_logs(canister_logger::export_logs(), since.unwrap_or(0))
-//     }
+use crate::queries::icrc3_get_blocks::decode_block;
+use crate::state::{read_state, RuntimeState};
+use bity_ic_canister_logger::{HttpResponse, StreamingStrategy};
+use bity_ic_icrc3_archive_api::blocks_http::{
+    render_blocks_chunk, BlocksRequest, BLOCKS_PATH, STREAMING_CALLBACK_METHOD,
+};
+pub use bity_ic_icrc3_archive_api::http_request::{
+    Args as HttpRequestArgs, Response as HttpRequestResponse,
+};
+use ic_cdk::query;
 
-//     fn get_traces_impl(since: Option<TimestampMillis>) -> HttpResponse {
-//         encode_logs(canister_logger::export_traces(), since.unwrap_or(0))
-//     }
+/// Serves the stored blocks as JSON on `/blocks?start=&length=`, streamed in chunks
+/// through `http_request_streaming_callback`.
+#[query(hidden = true)]
+fn http_request(request: HttpRequestArgs) -> HttpRequestResponse {
+    match request.path() {
+        BLOCKS_PATH => read_state(|s| blocks_response(s, request.query())),
+        _ => HttpResponse::not_found(),
+    }
+}
 
-//     fn get_metrics_impl(state: &RuntimeState) -> HttpResponse {
-//         build_json_response(&state.metrics())
-//     }
+fn blocks_response(state: &RuntimeState, query: &str) -> HttpResponse {
+    let archive = &state.data.archive;
+    let request = match BlocksRequest::from_query(query, archive.chain_length()) {
+        Ok(request) => request,
+        Err(e) => return HttpResponse::text(400, e),
+    };
+    let block_offset = archive.archive_config.block_offset;
+    if request.start < block_offset {
+        return HttpResponse::text(
+            404,
+            format!(
+                "Block {} is not stored in this archive, which starts at block {}",
+                request.start, block_offset
+            ),
+        );
+    }
 
-//     match extract_route(&request.url) {
-//         Route::Logs(since) => get_logs_impl(since),
-//         Route::Traces(since) => get_traces_impl(since),
-//         Route::Metrics => read_state(get_metrics_impl),
-//         _ => HttpResponse::not_found(),
-//     }
-// }
+    let chunk = render_blocks_chunk(
+        request.start,
+        request.end,
+        request.chunk_bytes,
+        true,
+        |block_id| {
+            archive
+                .get_block(block_id)
+                .and_then(|block| decode_block(&state.data.block_type, block))
+        },
+    );
+    HttpResponse {
+        status_code: 200,
+        headers: vec![("content-type".to_string(), "application/json".to_string())],
+        body: chunk.body,
+        streaming_strategy: chunk.next.map(|token| {
+            StreamingStrategy::callback(
+                ic_cdk::api::canister_self(),
+                STREAMING_CALLBACK_METHOD,
+                token.to_key(),
+            )
+        }),
+    }
+}
@@ -0,0 +1,38 @@
+use crate::queries::icrc3_get_blocks::decode_block;
+use crate::state::read_state;
+use bity_ic_canister_logger::StreamingToken;
+use bity_ic_icrc3_archive_api::blocks_http::{render_blocks_chunk, BlocksToken};
+pub use bity_ic_icrc3_archive_api::http_request_streaming_callback::{
+    Args as HttpRequestStreamingCallbackArgs, Response as HttpRequestStreamingCallbackResponse,
+};
+use ic_cdk::query;
+
+/// Returns the next chunk of a `/blocks` response.
+#[query(hidden = true)]
+fn http_request_streaming_callback(
+    token: HttpRequestStreamingCallbackArgs,
+) -> HttpRequestStreamingCallbackResponse {
+    let token = match BlocksToken::from_key(&token.key) {
+        Ok(token) => token,
+        Err(e) => ic_cdk::trap(format!("Invalid streaming token: {}", e)),
+    };
+
+    read_state(|s| {
+        let chunk = render_blocks_chunk(
+            token.next,
+            token.end,
+            token.chunk_bytes,
+            false,
+            |block_id| {
+                s.data
+                    .archive
+                    .get_block(block_id)
+                    .and_then(|block| decode_block(&s.data.block_type, block))
+            },
+        );
+        HttpRequestStreamingCallbackResponse {
+            body: chunk.body,
+            token: chunk.next.map(|next| StreamingToken { key: next.to_key() }),
+        }
+    })
+}
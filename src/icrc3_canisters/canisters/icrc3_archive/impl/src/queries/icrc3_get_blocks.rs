@@ -7,48 +7,45 @@ pub use bity_ic_icrc3_archive_api::icrc3_get_blocks::{
 };
 use bity_ic_icrc3_archive_api::{
     lifecycle::BlockType,
-    types::{block_interface::Block, defaultblock::DefaultBlock, encoded_blocks::EncodedBlock},
+    types::{
+        block_compression, block_interface::Block, defaultblock::DefaultBlock,
+        encoded_blocks::EncodedBlock,
+    },
 };
 use candid::Nat;
 use ic_cdk::query;
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
 use icrc_ledger_types::icrc3::blocks::BlockWithId;
+use serde_bytes::ByteBuf;
 
 // #[query(guard = "caller_is_main_canister")]
-#[query]
+#[query(hidden = true)]
 fn icrc3_get_blocks(req: GetBlocksArg) -> GetBlockseResponse {
-    let log_length = read_state(|s| s.data.archive.get_len());
+    let log_length = read_state(|s| s.data.archive.chain_length());
     let block_type = read_state(|s| s.data.block_type.clone());
     let mut blocks = vec![];
 
-    for arg in req {
-        let start = arg.start.clone().0.try_into().unwrap();
-        let length = arg.length.clone().0.try_into().unwrap();
+    // Values beyond u64::MAX can't address a block: clamp them instead of trapping,
+    // `get_blocks_ranges` then bounds each range by the chain length.
+    let ranges: Vec<(u64, u64)> = req
+        .iter()
+        .map(|arg| {
+            (
+                u64::try_from(&arg.start.0).unwrap_or(u64::MAX),
+                u64::try_from(&arg.length.0).unwrap_or(u64::MAX),
+            )
+        })
+        .filter(|&(_, length)| length > 0)
+        .collect();
 
-        let response = read_state(|s| s.data.archive.get_blocks_range(start, length));
+    let response = read_state(|s| s.data.archive.get_blocks_ranges(&ranges));
 
-        for (idx, block) in response.into_iter().enumerate() {
-            let block_id = idx + arg.start.clone();
-            match block_type {
-                BlockType::Default => {
-                    let encoded_block = EncodedBlock::from_vec(block.into_vec());
-                    // decode block
-                    match DefaultBlock::decode(encoded_block) {
-                        Ok(block) => {
-                            blocks.push(BlockWithId {
-                                id: Nat::from(block_id),
-                                block: block.transaction,
-                            });
-                        }
-                        Err(e) => {
-                            trace(format!("Error decoding block: {}", e));
-                        }
-                    }
-                }
-                _ => {
-                    // TODO: handle other block types
-                    trace(format!("TODO: handle other block types"));
-                }
-            }
+    for (block_id, block) in response {
+        if let Some(block) = decode_block(&block_type, block) {
+            blocks.push(BlockWithId {
+                id: Nat::from(block_id),
+                block,
+            });
         }
     }
 
@@ -58,3 +55,36 @@ fn icrc3_get_blocks(req: GetBlocksArg) -> GetBlockseResponse {
         archived_blocks: vec![],
     }
 }
+
+/// Returns the value of a stored block, as served by `icrc3_get_blocks`, or `None`
+/// for block types that can't be decoded yet.
+pub(crate) fn decode_block(block_type: &BlockType, block: EncodedBlock) -> Option<ICRC3Value> {
+    match block_type {
+        BlockType::Default => {
+            // Blocks compressed by the main canister are decompressed here, those
+            // stored before compression was enabled are served as they are.
+            let encoded_block = match block_compression::decompress(block.clone()) {
+                Ok(block) => block,
+                Err(e) => {
+                    trace(format!("Error decompressing block: {}", e));
+                    block
+                }
+            };
+            // decode block
+            match DefaultBlock::decode(encoded_block.clone()) {
+                Ok(block) => Some(block.transaction),
+                Err(e) => {
+                    // Blocks sealed by the main canister (e.g. encrypted at rest)
+                    // can't be decoded here, return them as opaque bytes.
+                    trace(format!("Error decoding block: {}", e));
+                    Some(ICRC3Value::Blob(ByteBuf::from(encoded_block.into_vec())))
+                }
+            }
+        }
+        _ => {
+            // TODO: handle other block types
+            trace(format!("TODO: handle other block types"));
+            None
+        }
+    }
+}
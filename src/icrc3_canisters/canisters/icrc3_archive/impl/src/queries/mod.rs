@@ -1,11 +1,23 @@
+pub mod find_block_by_thash;
+pub mod get_encoded_blocks;
+pub mod get_insert_counters;
+pub mod get_transactions;
 pub mod get_version;
 pub mod http_request;
+pub mod http_request_streaming_callback;
 pub mod icrc3_get_blocks;
 pub mod remaining_capacity;
+pub mod stats;
 pub mod total_transactions;
 
+pub use find_block_by_thash::*;
+pub use get_encoded_blocks::*;
+pub use get_insert_counters::*;
+pub use get_transactions::*;
 pub use get_version::*;
 pub use http_request::*;
+pub use http_request_streaming_callback::*;
 pub use icrc3_get_blocks::*;
 pub use remaining_capacity::*;
+pub use stats::*;
 pub use total_transactions::*;
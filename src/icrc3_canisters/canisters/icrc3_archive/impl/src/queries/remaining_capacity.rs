@@ -4,7 +4,7 @@ pub use bity_ic_icrc3_archive_api::queries::remaining_capacity::{
 };
 use ic_cdk::query;
 
-#[query]
+#[query(hidden = true)]
 async fn remaining_capacity(_: GetArchiveSizeArg) -> GetArchiveSizeResponse {
     read_state(|s| s.data.archive.remaining_capacity())
 }
@@ -0,0 +1,10 @@
+use crate::state::read_state;
+pub use bity_ic_icrc3_archive_api::queries::stats::{
+    Args as GetStatsArgs, Response as GetStatsResponse,
+};
+use ic_cdk::query;
+
+#[query(hidden = true)]
+fn stats(_: GetStatsArgs) -> GetStatsResponse {
+    read_state(|s| s.data.archive.stats())
+}
@@ -4,7 +4,7 @@ pub use bity_ic_icrc3_archive_api::queries::total_transactions::{
 };
 use ic_cdk::query;
 
-#[query]
+#[query(hidden = true)]
 async fn total_transactions(_: GetTotalTransactionsArg) -> GetTotalTransactionsResponse {
     read_state(|s| s.data.archive.get_len() as usize)
 }
@@ -9,7 +9,7 @@ use bity_ic_utils::{
 use candid::{CandidType, Principal};
 use serde::{Deserialize, Serialize};
 
-canister_state!(RuntimeState);
+canister_state!(RuntimeState; guards(authorized = data.authorized_principals));
 
 #[derive(Default, Serialize, Deserialize)]
 pub struct RuntimeState {
@@ -35,11 +35,6 @@ impl RuntimeState {
         }
     }
 
-    pub fn is_caller_authorized(&self) -> bool {
-        let caller = self.env.caller();
-        self.data.authorized_principals.contains(&caller)
-    }
-
     pub fn is_caller_main_canister(&self) -> bool {
         let caller = self.env.caller();
         self.data.master_canister_id == caller
@@ -1,13 +1,20 @@
-use crate::memory::{get_block_log_data_memory, get_block_log_index_memory};
+use crate::memory::{
+    get_block_log_data_memory, get_block_log_index_memory, get_thash_index_memory,
+};
 
 use crate::memory::VM;
 use bity_ic_icrc3_archive_api::{
-    archive_config::ArchiveConfig, types::encoded_blocks::EncodedBlock,
+    archive_config::ArchiveConfig,
+    get_insert_counters::InsertCounters,
+    insert_blocks::{InsertBlocksError, InsertBlocksSuccess},
+    stats::StorageStats,
+    types::{encoded_blocks::EncodedBlock, hash::HASH_LENGTH},
 };
+use bity_ic_types::TimestampNanos;
 use candid::Nat;
 use ic_cdk::stable::stable_size;
 use ic_cdk::stable::WASM_PAGE_SIZE_IN_BYTES;
-use ic_stable_structures::{StableLog, Storable};
+use ic_stable_structures::{StableBTreeMap, StableLog};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
@@ -15,6 +22,15 @@ pub struct Archive {
     #[serde(skip, default = "init_archive_map")]
     pub archive: StableLog<EncodedBlock, VM, VM>,
     pub archive_config: ArchiveConfig,
+    #[serde(default)]
+    pub insert_counters: InsertCounters,
+    /// When blocks were last appended, in nanoseconds.
+    #[serde(default)]
+    pub last_insert_at: Option<TimestampNanos>,
+    /// The index of each block by transaction hash, filled when
+    /// `archive_config.index_thashes` is set.
+    #[serde(skip, default = "init_thash_index")]
+    pub thash_index: StableBTreeMap<[u8; HASH_LENGTH], u64, VM>,
 }
 
 impl Default for Archive {
@@ -22,6 +38,9 @@ impl Default for Archive {
         Self {
             archive: init_archive_map(),
             archive_config: ArchiveConfig::default(),
+            insert_counters: InsertCounters::default(),
+            last_insert_at: None,
+            thash_index: init_thash_index(),
         }
     }
 }
@@ -31,6 +50,9 @@ impl Archive {
         Self {
             archive: init_archive_map(),
             archive_config,
+            insert_counters: InsertCounters::default(),
+            last_insert_at: None,
+            thash_index: init_thash_index(),
         }
     }
 }
@@ -39,6 +61,10 @@ fn init_archive_map() -> StableLog<EncodedBlock, VM, VM> {
     StableLog::init(get_block_log_index_memory(), get_block_log_data_memory())
 }
 
+fn init_thash_index() -> StableBTreeMap<[u8; HASH_LENGTH], u64, VM> {
+    StableBTreeMap::init(get_thash_index_memory())
+}
+
 impl Archive {
     pub fn get_archive_size_bytes(&self) -> usize {
         let num_pages = stable_size();
@@ -46,37 +72,211 @@ impl Archive {
     }
 
     pub fn remaining_capacity(&self) -> Nat {
-        let current_archive_size: u128 = self
-            .archive
-            .iter()
-            .map(|entry| entry.to_bytes().len() as u128)
-            .sum();
+        self.remaining_capacity_bytes().into()
+    }
+
+    /// Returns the number of bytes of blocks that can still be stored, out of
+    /// `max_memory_size_bytes`.
+    fn remaining_capacity_bytes(&self) -> u128 {
         self.archive_config
             .max_memory_size_bytes
-            .saturating_sub(current_archive_size)
-            .into()
+            .saturating_sub(self.archive.log_size_bytes() as u128)
+    }
+
+    /// Returns the number and size of the stored blocks, when they were last
+    /// appended and the remaining capacity.
+    pub fn stats(&self) -> StorageStats {
+        StorageStats {
+            blocks_stored: self.archive.len(),
+            total_bytes: self.archive.log_size_bytes(),
+            last_insert_at: self.last_insert_at,
+            remaining_capacity: self.remaining_capacity(),
+        }
     }
 
     pub fn get_len(&self) -> u64 {
         self.archive.len()
     }
 
-    pub fn insert_blocks(&mut self, new_blocks: Vec<EncodedBlock>) -> Result<(), String> {
-        for block in new_blocks {
+    /// Returns the index following the last stored block, the length of the chain
+    /// as far as this archive knows. This is the `log_length` of `icrc3_get_blocks`.
+    pub fn chain_length(&self) -> u64 {
+        self.archive_config.block_offset + self.archive.len()
+    }
+
+    /// Appends blocks starting at `first_block_id` at time `now`, counting the
+    /// accepted and rejected calls.
+    ///
+    /// The only legal append point is `block_offset + len`. Blocks before it are
+    /// already stored and are skipped, so that a retried batch is not stored twice.
+    /// The blocks to append are rejected as a whole if they don't fit in the
+    /// remaining capacity.
+    pub fn insert_blocks(
+        &mut self,
+        first_block_id: u64,
+        new_blocks: Vec<EncodedBlock>,
+        now: TimestampNanos,
+    ) -> Result<InsertBlocksSuccess, InsertBlocksError> {
+        let result = self.append_blocks(first_block_id, new_blocks);
+        match &result {
+            Ok(success) => {
+                self.insert_counters.accepted += 1;
+                if success.inserted > 0 {
+                    self.last_insert_at = Some(now);
+                }
+            }
+            Err(_) => self.insert_counters.rejected += 1,
+        }
+        result
+    }
+
+    fn append_blocks(
+        &mut self,
+        first_block_id: u64,
+        new_blocks: Vec<EncodedBlock>,
+    ) -> Result<InsertBlocksSuccess, InsertBlocksError> {
+        let block_offset = self.archive_config.block_offset;
+        let expected = block_offset + self.archive.len();
+
+        if first_block_id < block_offset || first_block_id > expected {
+            return Err(InsertBlocksError::NonContiguous {
+                expected,
+                got: first_block_id,
+            });
+        }
+
+        let already_stored = (expected - first_block_id) as usize;
+        if already_stored >= new_blocks.len() {
+            return Ok(InsertBlocksSuccess {
+                already_present: true,
+                inserted: 0,
+                next_block_id: expected,
+                remaining_capacity: Some(self.remaining_capacity()),
+            });
+        }
+
+        let needed: u128 = new_blocks
+            .iter()
+            .skip(already_stored)
+            .map(|block| block.stored_size_bytes() as u128)
+            .sum();
+        let remaining_capacity = self.remaining_capacity_bytes();
+        if needed > remaining_capacity {
+            return Err(InsertBlocksError::NoSpaceLeft {
+                needed: needed.into(),
+                remaining_capacity: remaining_capacity.into(),
+            });
+        }
+
+        let mut inserted = 0;
+        for block in new_blocks.into_iter().skip(already_stored) {
             self.archive
                 .append(&block)
                 .unwrap_or_else(|_| ic_cdk::api::trap("no space left"));
+            inserted += 1;
         }
 
-        Ok(())
+        Ok(InsertBlocksSuccess {
+            already_present: false,
+            inserted,
+            next_block_id: expected + inserted,
+            remaining_capacity: Some(self.remaining_capacity()),
+        })
     }
 
-    pub fn get_blocks_range(&self, start: u64, length: u64) -> Vec<EncodedBlock> {
-        let length = length.min(self.archive_config.get_max_blocks_per_response());
-        self.archive
-            .iter()
-            .skip(start as usize)
-            .take(length as usize)
+    /// Returns the stored blocks of a range addressed by their index in the chain,
+    /// each with that index. The range is capped at `max_blocks_per_response`.
+    pub fn get_encoded_blocks(&self, start: u64, length: u64) -> Vec<(u64, EncodedBlock)> {
+        let block_offset = self.archive_config.block_offset;
+        let start = start.max(block_offset);
+        let end = start
+            .saturating_add(length.min(self.archive_config.get_max_blocks_per_response()))
+            .min(self.chain_length());
+
+        (start..end)
+            .filter_map(|block_id| {
+                self.archive
+                    .get(block_id - block_offset)
+                    .map(|block| (block_id, block))
+            })
             .collect()
     }
+
+    /// Returns the stored block at an index in the chain.
+    pub fn get_block(&self, block_id: u64) -> Option<EncodedBlock> {
+        block_id
+            .checked_sub(self.archive_config.block_offset)
+            .and_then(|position| self.archive.get(position))
+    }
+
+    /// Indexes the blocks `start..end` by the transaction hash `thash` returns
+    /// for them. Blocks without a hash are skipped.
+    pub fn index_thashes(
+        &mut self,
+        start: u64,
+        end: u64,
+        thash: impl Fn(EncodedBlock) -> Option<[u8; HASH_LENGTH]>,
+    ) {
+        for block_id in start..end {
+            if let Some(hash) = self.get_block(block_id).and_then(&thash) {
+                self.thash_index.insert(hash, block_id);
+            }
+        }
+    }
+
+    /// Returns the index of the block recording the transaction with hash `thash`.
+    pub fn find_block_by_thash(&self, thash: &[u8]) -> Option<u64> {
+        let thash: [u8; HASH_LENGTH] = thash.try_into().ok()?;
+        self.thash_index.get(&thash)
+    }
+
+    /// Flips the first byte of a stored block, for tests of the verification job.
+    ///
+    /// The log can't be updated in place, so it is rebuilt with the corrupted block.
+    pub fn corrupt_block(&mut self, block_id: u64) -> Result<(), String> {
+        let position = block_id
+            .checked_sub(self.archive_config.block_offset)
+            .filter(|position| *position < self.archive.len())
+            .ok_or_else(|| format!("Block {} is not stored in this archive", block_id))?;
+
+        let mut blocks: Vec<EncodedBlock> = self.archive.iter().collect();
+        let mut corrupted = blocks[position as usize].clone().into_vec();
+        if let Some(byte) = corrupted.first_mut() {
+            *byte ^= 0xff;
+        }
+        blocks[position as usize] = EncodedBlock::from_vec(corrupted);
+
+        self.archive = StableLog::new(get_block_log_index_memory(), get_block_log_data_memory());
+        for block in blocks {
+            self.archive
+                .append(&block)
+                .map_err(|e| format!("Failed to rewrite the block log: {:?}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Reads several block ranges addressed by their index in the chain, returning
+    /// each block with that index. Ranges are served in request order and the total
+    /// number of blocks is capped at `max_blocks_per_response`.
+    pub fn get_blocks_ranges(&self, ranges: &[(u64, u64)]) -> Vec<(u64, EncodedBlock)> {
+        let mut remaining = self.archive_config.get_max_blocks_per_response();
+        let block_offset = self.archive_config.block_offset;
+        let chain_length = self.chain_length();
+        let mut blocks = vec![];
+
+        for &(start, length) in ranges {
+            let end = start.saturating_add(length).min(chain_length);
+            for block_id in start.max(block_offset)..end {
+                if remaining == 0 {
+                    return blocks;
+                }
+                if let Some(block) = self.archive.get(block_id - block_offset) {
+                    blocks.push((block_id, block));
+                    remaining -= 1;
+                }
+            }
+        }
+
+        blocks
+    }
 }
@@ -0,0 +1,16 @@
+use crate::guards::caller_is_main_canister_or_authorized;
+use crate::state::mutate_state;
+pub use bity_ic_icrc3_archive_api::corrupt_block::{
+    Args as CorruptBlockArgs, Response as CorruptBlockResponse,
+};
+use ic_cdk::update;
+
+#[update(guard = "caller_is_main_canister_or_authorized", hidden = true)]
+fn corrupt_block(block_id: CorruptBlockArgs) -> CorruptBlockResponse {
+    mutate_state(|s| {
+        if !s.env.is_test_mode() {
+            return Err("Blocks can only be corrupted in test mode".to_string());
+        }
+        s.data.archive.corrupt_block(block_id)
+    })
+}
@@ -1,16 +1,19 @@
 use crate::guards::caller_is_authorized;
+use crate::queries::icrc3_get_blocks::decode_block;
 use crate::state::mutate_state;
 pub use bity_ic_icrc3_archive_api::insert_blocks::{
     Args as AppendTransactionsArgs, Response as AppendTransactionsResponse,
 };
+use bity_ic_icrc3_archive_api::types::thash::transaction_hash;
+use bity_ic_utils::env::Environment;
 use ic_cdk::update;
 
-#[update(guard = "caller_is_authorized")]
-async fn insert_blocks(new_blocks: AppendTransactionsArgs) -> AppendTransactionsResponse {
+#[update(guard = "caller_is_authorized", hidden = true)]
+async fn insert_blocks(args: AppendTransactionsArgs) -> AppendTransactionsResponse {
     let max_memory_size_bytes =
         mutate_state(|s| s.data.archive.archive_config.get_max_memory_size_bytes());
 
-    if max_memory_size_bytes < new_blocks.len() as u128 {
+    if max_memory_size_bytes < args.blocks.len() as u128 {
         ic_cdk::api::trap(
             format!(
                 "New blocks size is too big, limit is: {}",
@@ -20,11 +23,26 @@ async fn insert_blocks(new_blocks: AppendTransactionsArgs) -> AppendTransactions
         );
     }
 
-    // Insert Blocks trap in case of no space left. Rolling back the transaction.
-    let result = mutate_state(|s| s.data.archive.insert_blocks(new_blocks));
-
-    match result {
-        Ok(_) => AppendTransactionsResponse::Success,
-        Err(e) => AppendTransactionsResponse::Error(e),
-    }
+    // Blocks that don't fit in the remaining capacity are rejected, insert_blocks
+    // only traps, rolling back the call, if the stable memory can't grow.
+    mutate_state(|s| {
+        let now = s.env.now_nanos();
+        let result = s
+            .data
+            .archive
+            .insert_blocks(args.first_block_id, args.blocks, now);
+        if let Ok(success) = &result {
+            if s.data.archive.archive_config.index_thashes {
+                let block_type = s.data.block_type.clone();
+                s.data.archive.index_thashes(
+                    success.next_block_id - success.inserted,
+                    success.next_block_id,
+                    |block| {
+                        decode_block(&block_type, block).and_then(|block| transaction_hash(&block))
+                    },
+                );
+            }
+        }
+        result
+    })
 }
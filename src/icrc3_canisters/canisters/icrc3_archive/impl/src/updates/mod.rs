@@ -1,3 +1,5 @@
+pub mod corrupt_block;
 pub mod insert_blocks;
 
+pub use corrupt_block::*;
 pub use insert_blocks::*;
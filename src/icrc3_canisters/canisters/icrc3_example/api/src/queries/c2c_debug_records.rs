@@ -0,0 +1,5 @@
+use bity_ic_canister_client::C2cDebugRecord;
+
+pub type Args = ();
+/// The last c2c calls whose response failed to deserialize, oldest first.
+pub type Response = Vec<C2cDebugRecord>;
@@ -0,0 +1,5 @@
+use icrc_ledger_types::icrc3::blocks::BlockWithId;
+use serde_bytes::ByteBuf;
+
+pub type Args = ByteBuf;
+pub type Response = Result<Option<BlockWithId>, String>;
@@ -0,0 +1,11 @@
+use candid::{CandidType, Nat};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Args {
+    pub memo: ByteBuf,
+    pub max: u32,
+}
+
+pub type Response = Vec<Nat>;
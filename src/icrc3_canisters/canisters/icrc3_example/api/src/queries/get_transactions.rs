@@ -0,0 +1,4 @@
+use icrc_ledger_types::icrc3::transactions::{GetTransactionsRequest, GetTransactionsResponse};
+
+pub type Args = GetTransactionsRequest;
+pub type Response = GetTransactionsResponse;
@@ -1,4 +1,4 @@
-// use bity_ic_types::{HttpRequest, HttpResponse};
+use bity_ic_canister_logger::{HttpRequest, HttpResponse};
 
-// pub type Args = HttpRequest;
-// pub type Response = HttpResponse;
+pub type Args = HttpRequest;
+pub type Response = HttpResponse;
@@ -0,0 +1,4 @@
+use bity_ic_canister_logger::{StreamingCallbackHttpResponse, StreamingToken};
+
+pub type Args = StreamingToken;
+pub type Response = StreamingCallbackHttpResponse;
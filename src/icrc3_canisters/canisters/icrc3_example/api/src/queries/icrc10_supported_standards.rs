@@ -0,0 +1 @@
+pub use bity_ic_icrc3::types::icrc10_supported_standards::{Args, Response};
@@ -0,0 +1 @@
+pub use bity_ic_icrc3::types::icrc3_chain_length::{Args, Response};
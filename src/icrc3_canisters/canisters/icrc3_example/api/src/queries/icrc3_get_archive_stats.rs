@@ -0,0 +1,4 @@
+use bity_ic_icrc3::archive_stats::ArchiveStats;
+
+pub type Args = ();
+pub type Response = Vec<ArchiveStats>;
@@ -0,0 +1 @@
+pub use bity_ic_icrc3::types::icrc3_get_tip::{Args, Response};
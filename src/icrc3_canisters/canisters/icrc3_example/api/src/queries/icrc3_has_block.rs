@@ -0,0 +1 @@
+pub use bity_ic_icrc3::types::icrc3_has_block::{Args, Response};
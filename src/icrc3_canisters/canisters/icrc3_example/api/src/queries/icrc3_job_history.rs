@@ -0,0 +1,4 @@
+use bity_ic_icrc3::job_history::JobRunRecord;
+
+pub type Args = ();
+pub type Response = Vec<JobRunRecord>;
@@ -0,0 +1,5 @@
+use bity_ic_icrc3::prepared::{ListPreparedTransactionsArgs, PreparedTransactionInfo};
+
+pub type Args = ListPreparedTransactionsArgs;
+/// The pending prepared transactions of the page, oldest first.
+pub type Response = Vec<PreparedTransactionInfo>;
@@ -0,0 +1,4 @@
+use bity_ic_icrc3::notifications::SubscriberMetrics;
+
+pub type Args = ();
+pub type Response = Vec<SubscriberMetrics>;
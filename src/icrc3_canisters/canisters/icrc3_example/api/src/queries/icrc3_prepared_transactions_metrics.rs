@@ -0,0 +1,4 @@
+use bity_ic_icrc3::prepared::PreparedTransactionsMetrics;
+
+pub type Args = ();
+pub type Response = PreparedTransactionsMetrics;
@@ -0,0 +1,4 @@
+use bity_ic_canister_time::TimerInfo;
+
+pub type Args = ();
+pub type Response = Vec<TimerInfo>;
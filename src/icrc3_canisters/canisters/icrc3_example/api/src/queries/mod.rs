@@ -1,6 +1,23 @@
-// pub mod http_request;
+pub mod c2c_debug_records;
+pub mod find_block_by_thash;
+pub mod find_blocks_by_memo;
+pub mod get_transactions;
+pub mod http_request;
+pub mod http_request_streaming_callback;
+pub mod icrc10_supported_standards;
+pub mod icrc3_block_schemas;
+pub mod icrc3_chain_length;
+pub mod icrc3_get_archive_stats;
 pub mod icrc3_get_archives;
 pub mod icrc3_get_blocks;
 pub mod icrc3_get_properties;
+pub mod icrc3_get_tip;
 pub mod icrc3_get_tip_certificate;
+pub mod icrc3_has_block;
+pub mod icrc3_job_history;
+pub mod icrc3_list_prepared_transactions;
+pub mod icrc3_notification_metrics;
+pub mod icrc3_prepared_transactions_metrics;
 pub mod icrc3_supported_block_types;
+pub mod icrc3_timers;
+pub mod received_block_notifications;
@@ -0,0 +1,4 @@
+use bity_ic_icrc3::notifications::BlockNotification;
+
+pub type Args = ();
+pub type Response = Vec<BlockNotification>;
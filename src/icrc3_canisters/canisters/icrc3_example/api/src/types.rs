@@ -1,15 +1,25 @@
+use bity_ic_icrc3::schema::{FieldSchema, TransactionSchema, ValueKind};
 use bity_ic_icrc3::transaction::TransactionType;
-use bity_ic_types::TimestampSeconds;
+use bity_ic_types::{TimestampNanos, TimestampSeconds};
 
 use candid::{CandidType, Nat, Principal};
 use icrc_ledger_types::icrc::generic_value::ICRC3Value;
 use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::cell::Cell;
 use std::collections::BTreeMap;
 
+thread_local! {
+    /// Whether the next random transaction is charged a fee.
+    static CHARGE_FEE: Cell<bool> = const { Cell::new(false) };
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct FakeTransaction {
     pub btype: String,
     pub timestamp: u64,
+    #[serde(default)]
+    pub fee: Option<Nat>,
     pub tx: FakeTransactionData,
 }
 
@@ -17,6 +27,10 @@ pub struct FakeTransaction {
 pub struct FakeTransactionData {
     pub sender: Principal,
     pub recipient: Principal,
+    #[serde(default)]
+    pub memo: Option<ByteBuf>,
+    #[serde(default)]
+    pub created_at_time: Option<u64>,
 }
 
 impl Default for FakeTransaction {
@@ -24,9 +38,12 @@ impl Default for FakeTransaction {
         Self {
             btype: "".to_string(),
             timestamp: 0,
+            fee: None,
             tx: FakeTransactionData {
                 sender: Principal::anonymous(),
                 recipient: Principal::anonymous(),
+                memo: None,
+                created_at_time: None,
             },
         }
     }
@@ -42,9 +59,15 @@ impl FakeTransaction {
         Self {
             btype: "btype_test".to_string(),
             timestamp: now,
+            // Charge a fee on every other transaction.
+            fee: CHARGE_FEE
+                .with(|charge| charge.replace(!charge.get()))
+                .then(|| Nat::from(10_000u64)),
             tx: FakeTransactionData {
                 sender: Principal::anonymous(),
                 recipient: Principal::anonymous(),
+                memo: None,
+                created_at_time: None,
             },
         }
     }
@@ -59,6 +82,10 @@ impl TransactionType for FakeTransaction {
         Some(self.timestamp)
     }
 
+    fn created_at_time(&self) -> Option<TimestampNanos> {
+        self.tx.created_at_time
+    }
+
     fn block_type(&self) -> String {
         self.btype.clone()
     }
@@ -66,6 +93,26 @@ impl TransactionType for FakeTransaction {
     fn tx(&self) -> ICRC3Value {
         self.clone().into()
     }
+
+    fn schemas() -> Vec<TransactionSchema> {
+        vec![TransactionSchema::new(
+            "btype_test",
+            vec![
+                FieldSchema::required("btype", ValueKind::Text),
+                FieldSchema::required("timestamp", ValueKind::Nat),
+                FieldSchema::optional("fee", ValueKind::Nat),
+                FieldSchema::map(
+                    "tx",
+                    vec![
+                        FieldSchema::required("sender", ValueKind::Text),
+                        FieldSchema::required("recipient", ValueKind::Text),
+                        FieldSchema::optional("memo", ValueKind::Blob),
+                        FieldSchema::optional("ts", ValueKind::Nat),
+                    ],
+                ),
+            ],
+        )]
+    }
 }
 
 impl From<FakeTransactionData> for ICRC3Value {
@@ -79,6 +126,15 @@ impl From<FakeTransactionData> for ICRC3Value {
             "recipient".to_string(),
             ICRC3Value::Text(tx.recipient.to_string()),
         );
+        if let Some(memo) = tx.memo {
+            map.insert("memo".to_string(), ICRC3Value::Blob(memo));
+        }
+        if let Some(created_at_time) = tx.created_at_time {
+            map.insert(
+                "ts".to_string(),
+                ICRC3Value::Nat(Nat::from(created_at_time)),
+            );
+        }
         ICRC3Value::Map(map)
     }
 }
@@ -91,6 +147,9 @@ impl From<FakeTransaction> for ICRC3Value {
             "timestamp".to_string(),
             ICRC3Value::Nat(Nat::from(tx.timestamp)),
         );
+        if let Some(fee) = tx.fee {
+            map.insert("fee".to_string(), ICRC3Value::Nat(fee));
+        }
         map.insert("tx".to_string(), tx.tx.into());
         ICRC3Value::Map(map)
     }
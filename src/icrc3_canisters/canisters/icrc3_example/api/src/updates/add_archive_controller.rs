@@ -0,0 +1,10 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Args {
+    pub canister_id: Principal,
+    pub controller: Principal,
+}
+
+pub type Response = Result<Vec<Principal>, String>;
@@ -0,0 +1,4 @@
+use bity_ic_icrc3::transaction::ICRC1Transaction;
+
+pub type Args = ICRC1Transaction;
+pub type Response = Result<(), String>;
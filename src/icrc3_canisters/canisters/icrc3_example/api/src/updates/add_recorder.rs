@@ -0,0 +1,4 @@
+use candid::Principal;
+
+pub type Args = Principal;
+pub type Response = ();
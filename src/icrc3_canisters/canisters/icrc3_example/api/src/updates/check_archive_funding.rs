@@ -0,0 +1,5 @@
+use bity_ic_icrc3::blockchain::archive_canister_manager::FundingAlert;
+
+pub type Args = ();
+/// The archive canisters, and this canister, trending towards freezing.
+pub type Response = Result<Vec<FundingAlert>, String>;
@@ -0,0 +1,15 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+
+/// The length of each draw.
+pub type Args = Vec<u32>;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RandomDraws {
+    pub draws: Vec<ByteBuf>,
+    /// The number of `raw_rand` calls made for the draws
+    pub refills: u64,
+}
+
+pub type Response = Result<RandomDraws, String>;
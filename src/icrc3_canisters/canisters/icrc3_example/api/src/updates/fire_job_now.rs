@@ -0,0 +1,6 @@
+use bity_ic_icrc3::job_history::JobKind;
+
+/// The job to run now.
+pub type Args = JobKind;
+/// An error if the job was not started.
+pub type Response = Result<(), String>;
@@ -0,0 +1,5 @@
+use candid::Principal;
+
+/// The retired archive canister whose blocks are lost.
+pub type Args = Principal;
+pub type Response = Result<(), String>;
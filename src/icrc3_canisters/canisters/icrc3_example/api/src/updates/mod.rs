@@ -1,7 +1,30 @@
+pub mod add_archive_controller;
 pub mod add_created_transaction;
+pub mod add_icrc1_transaction;
 pub mod add_random_transaction;
+pub mod add_recorder;
 pub mod add_same_transactions;
 pub mod add_transactions_with_async;
+pub mod check_archive_funding;
 pub mod commit_prepared_transaction;
 pub mod create_transactions;
+pub mod draw_random_bytes;
+pub mod fire_job_now;
+pub mod mark_archive_unrecoverable;
 pub mod prepare_transaction;
+pub mod rebuild_from_archives;
+pub mod reconcile_archives;
+pub mod record_block_notifications;
+pub mod reinstall_archive;
+pub mod remove_archive_controller;
+pub mod remove_recorder;
+pub mod restore_archive_snapshot;
+pub mod retire_archive;
+pub mod run_verification_now;
+pub mod set_c2c_debug_capture;
+pub mod set_fault;
+pub mod subscribe;
+pub mod take_archive_snapshot;
+pub mod unretire_archive;
+pub mod unsubscribe;
+pub mod update_funding_config;
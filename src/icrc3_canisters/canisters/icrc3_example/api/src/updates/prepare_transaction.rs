@@ -1,4 +1,5 @@
 use crate::types::FakeTransaction;
+pub use bity_ic_icrc3::types::prepare_transaction::PreparedTransaction;
 
 pub type Args = FakeTransaction;
-pub type Response = Result<(Vec<u8>, u128), String>;
+pub type Response = Result<PreparedTransaction, String>;
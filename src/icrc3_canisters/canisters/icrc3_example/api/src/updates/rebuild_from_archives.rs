@@ -0,0 +1,5 @@
+use bity_ic_icrc3::rebuild::{RebuildError, RebuildReport};
+use candid::Principal;
+
+pub type Args = Vec<Principal>;
+pub type Response = Result<RebuildReport, RebuildError>;
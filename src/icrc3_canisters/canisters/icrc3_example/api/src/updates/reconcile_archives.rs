@@ -0,0 +1,10 @@
+use bity_ic_icrc3::blockchain::archive_canister_manager::ReconciliationReport;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Args {
+    pub auto_adopt: bool,
+}
+
+pub type Response = Result<ReconciliationReport, String>;
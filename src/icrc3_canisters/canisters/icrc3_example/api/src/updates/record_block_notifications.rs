@@ -0,0 +1,4 @@
+use bity_ic_icrc3::notifications::BlockNotification;
+
+pub type Args = Vec<BlockNotification>;
+pub type Response = ();
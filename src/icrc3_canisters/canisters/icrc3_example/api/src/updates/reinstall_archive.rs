@@ -0,0 +1,5 @@
+pub use bity_ic_icrc3::blockchain::archive_canister_manager::ReinstallConfirmation;
+
+/// The confirmation of the data loss for the archive canister to reinstall.
+pub type Args = ReinstallConfirmation;
+pub type Response = Result<(), String>;
@@ -0,0 +1,5 @@
+use candid::Principal;
+
+pub use super::add_archive_controller::Args;
+
+pub type Response = Result<Vec<Principal>, String>;
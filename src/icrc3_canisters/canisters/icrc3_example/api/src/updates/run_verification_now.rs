@@ -0,0 +1,4 @@
+/// The number of archived blocks to verify.
+pub type Args = u32;
+/// The number of verified blocks, or the blocks that failed verification.
+pub type Response = Result<u128, String>;
@@ -0,0 +1,3 @@
+/// Whether to capture the c2c responses that fail to deserialize.
+pub type Args = bool;
+pub type Response = ();
@@ -0,0 +1,23 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// A fault injected by `set_fault`, see `bity_ic_icrc3::testing_hooks`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum FaultKind {
+    /// The next call inserting blocks into an archive canister fails
+    RejectNextArchiveInsert,
+    /// Each insertion into an archive canister waits `ms` milliseconds first
+    SlowArchiveInsert { ms: u64 },
+    /// New transactions are throttled as if the window were full
+    ForceThrottle,
+    /// The next block is added with a wrong parent hash, breaking the chain
+    CorruptNextBlockHash,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Args {
+    pub fault: FaultKind,
+    pub enabled: bool,
+}
+
+pub type Response = Result<(), String>;
@@ -0,0 +1,13 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+/// The subscriber, the method called with the notifications, and the block
+/// types notified, or `None` for all of them.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Args {
+    pub canister_id: Principal,
+    pub method_name: String,
+    pub filter: Option<Vec<String>>,
+}
+
+pub type Response = Result<(), String>;
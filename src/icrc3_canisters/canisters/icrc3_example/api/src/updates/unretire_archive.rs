@@ -0,0 +1,5 @@
+use candid::Principal;
+
+/// The archive canister.
+pub type Args = Principal;
+pub type Response = Result<(), String>;
@@ -0,0 +1,6 @@
+use candid::Principal;
+
+/// The subscriber.
+pub type Args = Principal;
+/// Whether the canister was subscribed.
+pub type Response = bool;
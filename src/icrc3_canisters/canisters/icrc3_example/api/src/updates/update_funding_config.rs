@@ -0,0 +1,4 @@
+use bity_ic_icrc3::config::FundingConfig;
+
+pub type Args = FundingConfig;
+pub type Response = Result<(), String>;
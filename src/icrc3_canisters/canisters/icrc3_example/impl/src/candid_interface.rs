@@ -0,0 +1,81 @@
+// The Candid interface of the canister, as exported by `export_candid!()`.
+//
+// The endpoints are marked `hidden = true` so that the interface is the one
+// declared here from the `icrc3_example_api` types: a new endpoint must be
+// listed here too.
+
+use crate::icrc3_example_canister;
+
+bity_ic_candid_gen::candid_interface! {
+    canister = icrc3_example,
+    queries = [
+        c2c_debug_records,
+        create_transactions,
+        find_blocks_by_memo,
+        get_transactions,
+        http_request,
+        http_request_streaming_callback,
+        icrc10_supported_standards,
+        icrc3_block_schemas,
+        icrc3_chain_length,
+        icrc3_get_archive_stats,
+        icrc3_get_archives,
+        icrc3_get_blocks,
+        icrc3_get_properties,
+        icrc3_get_tip,
+        icrc3_get_tip_certificate,
+        icrc3_has_block,
+        icrc3_job_history,
+        icrc3_list_prepared_transactions,
+        icrc3_notification_metrics,
+        icrc3_prepared_transactions_metrics,
+        icrc3_supported_block_types,
+        icrc3_timers,
+        received_block_notifications,
+    ],
+    composite_queries = [find_block_by_thash],
+    updates = [
+        add_archive_controller,
+        add_created_transaction,
+        add_icrc1_transaction,
+        add_random_transaction,
+        add_recorder,
+        add_same_transactions,
+        add_transactions_with_async,
+        check_archive_funding,
+        commit_prepared_transaction,
+        draw_random_bytes,
+        fire_job_now,
+        mark_archive_unrecoverable,
+        prepare_transaction,
+        rebuild_from_archives,
+        reconcile_archives,
+        record_block_notifications,
+        reinstall_archive,
+        remove_archive_controller,
+        remove_recorder,
+        restore_archive_snapshot,
+        retire_archive,
+        run_verification_now,
+        set_c2c_debug_capture,
+        set_fault,
+        subscribe,
+        take_archive_snapshot,
+        unretire_archive,
+        unsubscribe,
+        update_funding_config,
+    ],
+}
+
+#[cfg(test)]
+mod tests {
+    use bity_ic_utils::did::service_methods;
+
+    #[test]
+    fn test_candid_interface_matches_did() {
+        assert_eq!(
+            service_methods(&crate::__export_service()),
+            service_methods(include_str!("../../api/can.did"))
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use ic_cdk::export_candid;
 
+#[allow(dead_code)]
+mod candid_interface;
 mod guards;
 mod lifecycle;
 mod memory;
@@ -9,7 +11,7 @@ pub mod updates;
 mod utils;
 
 use lifecycle::*;
-use queries::*;
-use updates::*;
+// `export_candid!()` resolves the types of the declared interface from here.
+use icrc3_example_api as icrc3_example_canister;
 
 export_candid!();
@@ -1,10 +1,13 @@
 use crate::lifecycle::init_canister;
-use crate::state::{init_icrc3, start_default_archive_job};
+use crate::state::{
+    icrc3_register_transaction_type, init_icrc3, start_default_archive_job, start_notification_job,
+};
 use crate::state::{Data, RuntimeState};
 use bity_ic_canister_tracing_macros::trace;
 use bity_ic_utils::env::{CanisterEnv, Environment};
 use ic_cdk_macros::init;
 pub use icrc3_example_api::lifecycle::Args;
+use icrc3_example_api::types::FakeTransaction;
 use tracing::info;
 
 #[init]
@@ -31,8 +34,10 @@ fn init(args: Args) {
 
             init_canister(runtime_state);
             init_icrc3(init_args.icrc3_config);
+            icrc3_register_transaction_type::<FakeTransaction>();
 
             start_default_archive_job();
+            start_notification_job(5 * bity_ic_canister_time::SECOND_IN_MS);
 
             info!("Init complete.")
         }
@@ -6,6 +6,9 @@ pub use init::*;
 
 use crate::state::{init_state, RuntimeState};
 
+/// Number of bytes between two progress lines when saving or restoring the state.
+const UPGRADE_PROGRESS_BYTES: u64 = 16 * 1024 * 1024;
+
 pub fn init_canister(runtime_state: RuntimeState) {
     init_state(runtime_state);
 }
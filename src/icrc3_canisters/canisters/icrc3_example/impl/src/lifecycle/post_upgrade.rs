@@ -1,16 +1,22 @@
-use crate::lifecycle::init_canister;
+use crate::lifecycle::{init_canister, UPGRADE_PROGRESS_BYTES};
 use crate::memory::get_upgrades_memory;
 // use crate::migrations::types::state::RuntimeStateV0;
-use crate::state::{replace_icrc3, start_default_archive_job, RuntimeState};
+use crate::state::{icrc3_mark_legacy_state, icrc3_post_upgrade, RuntimeState};
 
 use bity_ic_canister_logger::LogEntry;
 use bity_ic_canister_tracing_macros::trace;
 use bity_ic_icrc3::icrc3::ICRC3;
-use bity_ic_stable_memory::get_reader;
+use bity_ic_stable_memory::get_reader_with_progress;
 use ic_cdk_macros::post_upgrade;
 pub use icrc3_example_api::lifecycle::Args;
+use serde_bytes::ByteBuf;
 use tracing::info;
 
+/// The stable state saved by `pre_upgrade`, the ICRC3 state serialized by `icrc3_pre_upgrade`.
+type StableState = (RuntimeState, Vec<LogEntry>, Vec<LogEntry>, ByteBuf);
+/// The stable state saved before `icrc3_pre_upgrade` existed.
+type LegacyStableState = (RuntimeState, Vec<LogEntry>, Vec<LogEntry>, ICRC3);
+
 #[post_upgrade]
 #[trace]
 fn post_upgrade(args: Args) {
@@ -22,12 +28,32 @@ fn post_upgrade(args: Args) {
         Args::Upgrade(upgrade_args) => {
             info!("Post-upgrade starting with args: {:?}", upgrade_args);
             let memory = get_upgrades_memory();
-            let reader = get_reader(&memory);
+            let mut reader = get_reader_with_progress(&memory, UPGRADE_PROGRESS_BYTES, |stats| {
+                info!("Post-upgrade: restored {}", stats)
+            });
 
             // NOTE: uncomment these lines if you want to do a normal upgrade
-            let (mut state, logs, traces, icrc3): (RuntimeState, Vec<LogEntry>, Vec<LogEntry>, ICRC3) = bity_ic_serializer
-                ::deserialize(reader)
-                .unwrap();
+            let stable_state: Option<StableState> = bity_ic_serializer::deserialize(&mut reader).ok();
+            let (mut state, logs, traces, icrc3) = match stable_state {
+                Some(stable_state) => {
+                    info!("Post-upgrade: state restored, {}", reader.stats());
+                    stable_state
+                }
+                None => {
+                    // Saved before icrc3_pre_upgrade, with the ICRC3 state itself.
+                    let mut reader = get_reader_with_progress(&memory, UPGRADE_PROGRESS_BYTES, |stats| {
+                        info!("Post-upgrade: restored {}", stats)
+                    });
+                    let (state, logs, traces, mut icrc3): LegacyStableState = bity_ic_serializer
+                        ::deserialize(&mut reader)
+                        .unwrap();
+                    info!("Post-upgrade: legacy state restored, {}", reader.stats());
+                    // It records no job intervals, its archive and cleanup jobs were the default ones.
+                    icrc3_mark_legacy_state(&mut icrc3);
+                    let icrc3 = bity_ic_serializer::serialize_to_vec(icrc3).unwrap();
+                    (state, logs, traces, ByteBuf::from(icrc3))
+                }
+            };
 
             // NOTE: uncomment these lines if you want to do an upgrade with migration
             // let (runtime_state_v0, logs, traces): (
@@ -42,9 +68,8 @@ fn post_upgrade(args: Args) {
 
             bity_ic_canister_logger::init_with_logs(state.env.is_test_mode(), logs, traces);
             init_canister(state);
-            replace_icrc3(icrc3);
-
-            start_default_archive_job();
+            // Restores the certified data and the archive and cleanup jobs.
+            icrc3_post_upgrade(&icrc3, None);
 
             info!(version = %upgrade_args.version, "Post-upgrade complete");
         }
@@ -1,10 +1,12 @@
-use bity_ic_stable_memory::get_writer;
+use bity_ic_stable_memory::get_writer_with_progress;
 use ic_cdk_macros::pre_upgrade;
+use serde_bytes::ByteBuf;
 use tracing::info;
 
+use super::UPGRADE_PROGRESS_BYTES;
 use crate::{
     memory::get_upgrades_memory,
-    state::{take_icrc3, take_state},
+    state::{icrc3_pre_upgrade, take_state},
 };
 
 #[pre_upgrade]
@@ -13,15 +15,19 @@ fn pre_upgrade() {
 
     let runtime_state = take_state();
 
-    let icrc3 = take_icrc3();
+    let icrc3 = ByteBuf::from(icrc3_pre_upgrade());
 
-    let logs = bity_ic_canister_logger::export_logs();
-    let traces = bity_ic_canister_logger::export_traces();
+    let logs = bity_ic_canister_logger::export_all_logs();
+    let traces = bity_ic_canister_logger::export_all_traces();
 
     let stable_state = (runtime_state, logs, traces, icrc3);
 
     let mut memory = get_upgrades_memory();
-    let writer = get_writer(&mut memory);
+    let mut writer = get_writer_with_progress(&mut memory, UPGRADE_PROGRESS_BYTES, |stats| {
+        info!("Pre upgrade: saved {}", stats)
+    });
 
-    bity_ic_serializer::serialize(stable_state, writer).unwrap();
+    bity_ic_serializer::serialize(stable_state, &mut writer).unwrap();
+
+    info!("Pre upgrade: state saved, {}", writer.stats());
 }
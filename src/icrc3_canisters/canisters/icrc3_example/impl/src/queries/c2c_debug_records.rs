@@ -0,0 +1,11 @@
+use crate::guards::caller_is_authorized;
+
+use ic_cdk::query;
+pub use icrc3_example_api::c2c_debug_records::{
+    Args as C2cDebugRecordsArgs, Response as C2cDebugRecordsResponse,
+};
+
+#[query(guard = "caller_is_authorized", hidden = true)]
+fn c2c_debug_records(_: C2cDebugRecordsArgs) -> C2cDebugRecordsResponse {
+    bity_ic_canister_client::c2c_debug_records()
+}
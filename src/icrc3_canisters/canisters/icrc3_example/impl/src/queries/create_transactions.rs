@@ -6,7 +6,7 @@ pub use icrc3_example_api::updates::create_transactions::{
     Args as CreateTransactionsArgs, Response as CreateTransactionsResponse,
 };
 
-#[query]
+#[query(hidden = true)]
 fn create_transactions(_: CreateTransactionsArgs) -> CreateTransactionsResponse {
     trace("create_transactions");
     let transaction = read_state(|state| state.data.create_fake_transaction());
@@ -0,0 +1,11 @@
+use crate::state::icrc3_state;
+
+use ic_cdk::query;
+pub use icrc3_example_api::find_block_by_thash::{
+    Args as FindBlockByThashArgs, Response as FindBlockByThashResponse,
+};
+
+#[query(composite = true, hidden = true)]
+async fn find_block_by_thash(thash: FindBlockByThashArgs) -> FindBlockByThashResponse {
+    icrc3_state::find_block_by_thash(thash).await
+}
@@ -0,0 +1,11 @@
+use crate::state::icrc3_state;
+
+use ic_cdk::query;
+pub use icrc3_example_api::find_blocks_by_memo::{
+    Args as FindBlocksByMemoArgs, Response as FindBlocksByMemoResponse,
+};
+
+#[query(hidden = true)]
+fn find_blocks_by_memo(args: FindBlocksByMemoArgs) -> FindBlocksByMemoResponse {
+    icrc3_state::find_blocks_by_memo(args.memo, args.max)
+}
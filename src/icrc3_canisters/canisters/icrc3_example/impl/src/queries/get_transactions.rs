@@ -0,0 +1,11 @@
+use crate::state::icrc3_state;
+
+use ic_cdk::query;
+pub use icrc3_example_api::get_transactions::{
+    Args as GetTransactionsArgs, Response as GetTransactionsResponse,
+};
+
+#[query(hidden = true)]
+fn get_transactions(args: GetTransactionsArgs) -> GetTransactionsResponse {
+    icrc3_state::get_transactions(args)
+}
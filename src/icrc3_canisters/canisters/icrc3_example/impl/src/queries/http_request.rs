@@ -1,27 +1,52 @@
-// use bity_ic_types::{HttpRequest, HttpResponse, TimestampMillis};
-// use http_request::{build_json_response, encode_logs, extract_route, Route};
-// use ic_cdk_macros::query;
+use crate::state::{icrc3_block_schemas, icrc3_blocks_http_chunk, icrc3_chain_length};
+use bity_ic_canister_logger::{handle_logs_http_request, HttpResponse, StreamingStrategy};
+use bity_ic_icrc3::blocks_http::{BlocksRequest, BLOCKS_PATH, STREAMING_CALLBACK_METHOD};
+use bity_ic_icrc3::schema::block_schemas_json;
+use ic_cdk::query;
+pub use icrc3_example_api::http_request::{
+    Args as HttpRequestArgs, Response as HttpRequestResponse,
+};
 
-// use crate::state::{read_state, RuntimeState};
+/// Serves the `/logs` and `/traces` paths, the block schemas as JSON on
+/// `/block_schemas` and the local blocks as JSON on `/blocks?start=&length=`. The
+/// example canister does not restrict them, a canister can pass
+/// `require_header_token` as the guard instead.
+#[query(hidden = true)]
+fn http_request(request: HttpRequestArgs) -> HttpRequestResponse {
+    match request.path() {
+        "/block_schemas" => HttpResponse {
+            status_code: 200,
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: block_schemas_json(&icrc3_block_schemas()).into_bytes(),
+            streaming_strategy: None,
+        },
+        BLOCKS_PATH => blocks_response(request.query()),
+        _ => handle_logs_http_request(&request, |_| true),
+    }
+}
 
-// #[query(hidden = true)]
-// fn http_request(request: HttpRequest) -> HttpResponse {
-//     fn get_logs_impl(since: Option<TimestampMillis>) -> HttpResponse {
-//         encode_logs(canister_logger::export_logs(), since.unwrap_or(0))
-//     }
+/// Returns the first chunk of the blocks, with the token of the next one. Archived
+/// blocks are served by the `/blocks` path of their archive canister.
+fn blocks_response(query: &str) -> HttpResponse {
+    let chain_length = u64::try_from(icrc3_chain_length().0).unwrap_or(u64::MAX);
+    let request = match BlocksRequest::from_query(query, chain_length) {
+        Ok(request) => request,
+        Err(e) => return HttpResponse::text(400, e),
+    };
 
-//     fn get_traces_impl(since: Option<TimestampMillis>) -> HttpResponse {
-//         encode_logs(canister_logger::export_traces(), since.unwrap_or(0))
-//     }
-
-//     fn get_metrics_impl(state: &RuntimeState) -> HttpResponse {
-//         build_json_response(&state.metrics())
-//     }
-
-//     match extract_route(&request.url) {
-//         Route::Logs(since) => get_logs_impl(since),
-//         Route::Traces(since) => get_traces_impl(since),
-//         Route::Metrics => read_state(get_metrics_impl),
-//         _ => HttpResponse::not_found(),
-//     }
-// }
+    match icrc3_blocks_http_chunk(request.start, request.end, request.chunk_bytes, true) {
+        Ok(chunk) => HttpResponse {
+            status_code: 200,
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: chunk.body,
+            streaming_strategy: chunk.next.map(|token| {
+                StreamingStrategy::callback(
+                    ic_cdk::api::canister_self(),
+                    STREAMING_CALLBACK_METHOD,
+                    token.to_key(),
+                )
+            }),
+        },
+        Err(e) => HttpResponse::text(404, e),
+    }
+}
@@ -0,0 +1,24 @@
+use crate::state::icrc3_blocks_http_chunk;
+use bity_ic_canister_logger::StreamingToken;
+use bity_ic_icrc3::blocks_http::BlocksToken;
+use ic_cdk::query;
+pub use icrc3_example_api::http_request_streaming_callback::{
+    Args as HttpRequestStreamingCallbackArgs, Response as HttpRequestStreamingCallbackResponse,
+};
+
+/// Returns the next chunk of a `/blocks` response.
+#[query(hidden = true)]
+fn http_request_streaming_callback(
+    token: HttpRequestStreamingCallbackArgs,
+) -> HttpRequestStreamingCallbackResponse {
+    let token = BlocksToken::from_key(&token.key)
+        .unwrap_or_else(|e| ic_cdk::trap(format!("Invalid streaming token: {}", e)));
+    // The next blocks may have been archived since the previous chunk.
+    let chunk = icrc3_blocks_http_chunk(token.next, token.end, token.chunk_bytes, false)
+        .unwrap_or_else(|e| ic_cdk::trap(e));
+
+    HttpRequestStreamingCallbackResponse {
+        body: chunk.body,
+        token: chunk.next.map(|next| StreamingToken { key: next.to_key() }),
+    }
+}
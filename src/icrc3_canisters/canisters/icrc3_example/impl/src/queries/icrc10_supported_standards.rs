@@ -0,0 +1,11 @@
+use crate::state::icrc3_state;
+
+use ic_cdk::query;
+pub use icrc3_example_api::icrc10_supported_standards::{
+    Args as SupportedStandardsArgs, Response as SupportedStandardsResponse,
+};
+
+#[query(hidden = true)]
+fn icrc10_supported_standards(_: SupportedStandardsArgs) -> SupportedStandardsResponse {
+    icrc3_state::icrc10_supported_standards(&[])
+}
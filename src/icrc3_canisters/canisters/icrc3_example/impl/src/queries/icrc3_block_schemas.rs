@@ -0,0 +1,11 @@
+use crate::state::icrc3_state;
+
+use ic_cdk::query;
+pub use icrc3_example_api::icrc3_block_schemas::{
+    Args as GetBlockSchemasArgs, Response as GetBlockSchemasResponse,
+};
+
+#[query(hidden = true)]
+fn icrc3_block_schemas(_: GetBlockSchemasArgs) -> GetBlockSchemasResponse {
+    icrc3_state::icrc3_block_schemas()
+}
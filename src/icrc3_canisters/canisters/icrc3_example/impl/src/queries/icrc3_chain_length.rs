@@ -0,0 +1,11 @@
+use crate::state::icrc3_state;
+
+use ic_cdk::query;
+pub use icrc3_example_api::icrc3_chain_length::{
+    Args as ChainLengthArg, Response as ChainLengthResponse,
+};
+
+#[query(hidden = true)]
+fn icrc3_chain_length(_: ChainLengthArg) -> ChainLengthResponse {
+    icrc3_state::icrc3_chain_length()
+}
@@ -0,0 +1,11 @@
+use crate::state::icrc3_state;
+
+use ic_cdk::query;
+pub use icrc3_example_api::icrc3_get_archive_stats::{
+    Args as GetArchiveStatsArgs, Response as GetArchiveStatsResponse,
+};
+
+#[query(hidden = true)]
+fn icrc3_get_archive_stats(_: GetArchiveStatsArgs) -> GetArchiveStatsResponse {
+    icrc3_state::icrc3_get_archive_stats()
+}
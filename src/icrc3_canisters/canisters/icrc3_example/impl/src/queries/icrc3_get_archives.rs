@@ -1,11 +1,11 @@
-use crate::state::icrc3_get_archives as icrc3_get_archives_impl;
+use crate::state::icrc3_state;
 
 use ic_cdk::query;
 pub use icrc3_example_api::icrc3_get_archives::{
     Args as GetArchivesArg, Response as GetArchivesResponse,
 };
 
-#[query]
+#[query(hidden = true)]
 async fn icrc3_get_archives(_: GetArchivesArg) -> GetArchivesResponse {
-    icrc3_get_archives_impl()
+    icrc3_state::icrc3_get_archives()
 }
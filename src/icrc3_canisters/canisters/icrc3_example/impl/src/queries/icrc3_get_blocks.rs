@@ -1,12 +1,21 @@
-use crate::state::icrc3_get_blocks as icrc3_get_blocks_impl;
+use crate::state::icrc3_state;
 
+use bity_ic_canister_client::expose_msgpack_endpoint;
 use ic_cdk::query;
 pub use icrc3_example_api::queries::icrc3_get_blocks::{
     Args as GetBlocksArg, Response as GetBlocksResponse,
 };
 pub use icrc_ledger_types::icrc3::blocks::GetBlocksResult;
 
-#[query]
+#[query(hidden = true)]
 fn icrc3_get_blocks(args: GetBlocksArg) -> GetBlocksResult {
-    icrc3_get_blocks_impl(args)
+    icrc3_state::icrc3_get_blocks(args)
 }
+
+expose_msgpack_endpoint!(
+    query,
+    icrc3_get_blocks_msgpack,
+    GetBlocksArg,
+    GetBlocksResponse,
+    icrc3_state::icrc3_get_blocks
+);
@@ -1,11 +1,11 @@
-use crate::state::icrc3_get_properties as icrc3_get_properties_impl;
+use crate::state::icrc3_state;
 
 use ic_cdk::query;
 pub use icrc3_example_api::icrc3_get_properties::{
     Args as GetArchivePropsArg, Response as GetArchivePropsResponse,
 };
 
-#[query]
+#[query(hidden = true)]
 async fn icrc3_get_properties(_: GetArchivePropsArg) -> GetArchivePropsResponse {
-    icrc3_get_properties_impl()
+    icrc3_state::icrc3_get_properties()
 }
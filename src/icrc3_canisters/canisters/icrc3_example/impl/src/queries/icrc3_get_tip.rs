@@ -0,0 +1,9 @@
+use crate::state::icrc3_state;
+
+use ic_cdk::query;
+pub use icrc3_example_api::icrc3_get_tip::{Args as GetTipArg, Response as GetTipResponse};
+
+#[query(hidden = true)]
+fn icrc3_get_tip(_: GetTipArg) -> GetTipResponse {
+    icrc3_state::icrc3_get_tip()
+}
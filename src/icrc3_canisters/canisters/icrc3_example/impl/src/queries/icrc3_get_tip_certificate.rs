@@ -1,11 +1,11 @@
-use crate::state::icrc3_get_tip_certificate as icrc3_get_tip_certificate_impl;
+use crate::state::icrc3_state;
 
 use ic_cdk::query;
 pub use icrc3_example_api::icrc3_get_tip_certificate::{
     Args as GetTipCertificateArg, Response as GetTipCertificateResponse,
 };
 
-#[query]
+#[query(hidden = true)]
 async fn icrc3_get_tip_certificate(_: GetTipCertificateArg) -> GetTipCertificateResponse {
-    icrc3_get_tip_certificate_impl()
+    icrc3_state::icrc3_get_tip_certificate()
 }
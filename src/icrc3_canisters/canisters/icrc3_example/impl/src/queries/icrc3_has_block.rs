@@ -0,0 +1,9 @@
+use crate::state::icrc3_state;
+
+use ic_cdk::query;
+pub use icrc3_example_api::icrc3_has_block::{Args as HasBlockArg, Response as HasBlockResponse};
+
+#[query(hidden = true)]
+fn icrc3_has_block(index: HasBlockArg) -> HasBlockResponse {
+    icrc3_state::icrc3_has_block(index)
+}
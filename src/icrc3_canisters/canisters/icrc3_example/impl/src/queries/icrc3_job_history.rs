@@ -0,0 +1,11 @@
+use crate::state::icrc3_state;
+
+use ic_cdk::query;
+pub use icrc3_example_api::icrc3_job_history::{
+    Args as GetJobHistoryArgs, Response as GetJobHistoryResponse,
+};
+
+#[query(hidden = true)]
+fn icrc3_job_history(_: GetJobHistoryArgs) -> GetJobHistoryResponse {
+    icrc3_state::icrc3_job_history()
+}
@@ -0,0 +1,14 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_state;
+
+use ic_cdk::query;
+pub use icrc3_example_api::icrc3_list_prepared_transactions::{
+    Args as ListPreparedTransactionsArgs, Response as ListPreparedTransactionsResponse,
+};
+
+#[query(guard = "caller_is_authorized", hidden = true)]
+fn icrc3_list_prepared_transactions(
+    args: ListPreparedTransactionsArgs,
+) -> ListPreparedTransactionsResponse {
+    icrc3_state::icrc3_list_prepared_transactions(args)
+}
@@ -0,0 +1,11 @@
+use crate::state::icrc3_state;
+
+use ic_cdk::query;
+pub use icrc3_example_api::icrc3_notification_metrics::{
+    Args as GetNotificationMetricsArgs, Response as GetNotificationMetricsResponse,
+};
+
+#[query(hidden = true)]
+fn icrc3_notification_metrics(_: GetNotificationMetricsArgs) -> GetNotificationMetricsResponse {
+    icrc3_state::icrc3_notification_metrics()
+}
@@ -0,0 +1,13 @@
+use crate::state::icrc3_state;
+
+use ic_cdk::query;
+pub use icrc3_example_api::icrc3_prepared_transactions_metrics::{
+    Args as GetPreparedTransactionsMetricsArgs, Response as GetPreparedTransactionsMetricsResponse,
+};
+
+#[query(hidden = true)]
+fn icrc3_prepared_transactions_metrics(
+    _: GetPreparedTransactionsMetricsArgs,
+) -> GetPreparedTransactionsMetricsResponse {
+    icrc3_state::icrc3_prepared_transactions_metrics()
+}
@@ -1,13 +1,13 @@
-use crate::state::icrc3_supported_block_types as icrc3_supported_block_types_impl;
+use crate::state::icrc3_state;
 
 use ic_cdk::query;
 pub use icrc3_example_api::icrc3_supported_block_types::{
     Args as GetSupportedBlockTypesArg, Response as GetSupportedBlockTypesResponse,
 };
 
-#[query]
+#[query(hidden = true)]
 async fn icrc3_supported_block_types(
     _: GetSupportedBlockTypesArg,
 ) -> GetSupportedBlockTypesResponse {
-    icrc3_supported_block_types_impl()
+    icrc3_state::icrc3_supported_block_types()
 }
@@ -0,0 +1,9 @@
+use crate::state::icrc3_state;
+
+use ic_cdk::query;
+pub use icrc3_example_api::icrc3_timers::{Args as GetTimersArgs, Response as GetTimersResponse};
+
+#[query(hidden = true)]
+fn icrc3_timers(_: GetTimersArgs) -> GetTimersResponse {
+    icrc3_state::icrc3_timers()
+}
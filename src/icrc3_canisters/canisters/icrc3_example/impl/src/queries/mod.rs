@@ -1,13 +1,49 @@
+pub mod c2c_debug_records;
 pub mod create_transactions;
+pub mod find_block_by_thash;
+pub mod find_blocks_by_memo;
+pub mod get_transactions;
+pub mod http_request;
+pub mod http_request_streaming_callback;
+pub mod icrc10_supported_standards;
+pub mod icrc3_block_schemas;
+pub mod icrc3_chain_length;
+pub mod icrc3_get_archive_stats;
 pub mod icrc3_get_archives;
 pub mod icrc3_get_blocks;
 pub mod icrc3_get_properties;
+pub mod icrc3_get_tip;
 pub mod icrc3_get_tip_certificate;
+pub mod icrc3_has_block;
+pub mod icrc3_job_history;
+pub mod icrc3_list_prepared_transactions;
+pub mod icrc3_notification_metrics;
+pub mod icrc3_prepared_transactions_metrics;
 pub mod icrc3_supported_block_types;
+pub mod icrc3_timers;
+pub mod received_block_notifications;
 
+pub use c2c_debug_records::*;
 pub use create_transactions::*;
+pub use find_block_by_thash::*;
+pub use find_blocks_by_memo::*;
+pub use get_transactions::*;
+pub use http_request::*;
+pub use http_request_streaming_callback::*;
+pub use icrc10_supported_standards::*;
+pub use icrc3_block_schemas::*;
+pub use icrc3_chain_length::*;
+pub use icrc3_get_archive_stats::*;
 pub use icrc3_get_archives::*;
 pub use icrc3_get_blocks::*;
 pub use icrc3_get_properties::*;
+pub use icrc3_get_tip::*;
 pub use icrc3_get_tip_certificate::*;
+pub use icrc3_has_block::*;
+pub use icrc3_job_history::*;
+pub use icrc3_list_prepared_transactions::*;
+pub use icrc3_notification_metrics::*;
+pub use icrc3_prepared_transactions_metrics::*;
 pub use icrc3_supported_block_types::*;
+pub use icrc3_timers::*;
+pub use received_block_notifications::*;
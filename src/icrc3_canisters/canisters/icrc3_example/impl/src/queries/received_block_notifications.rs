@@ -0,0 +1,13 @@
+use crate::state::read_state;
+
+use ic_cdk::query;
+pub use icrc3_example_api::received_block_notifications::{
+    Args as ReceivedBlockNotificationsArgs, Response as ReceivedBlockNotificationsResponse,
+};
+
+#[query(hidden = true)]
+fn received_block_notifications(
+    _: ReceivedBlockNotificationsArgs,
+) -> ReceivedBlockNotificationsResponse {
+    read_state(|state| state.data.received_block_notifications.clone())
+}
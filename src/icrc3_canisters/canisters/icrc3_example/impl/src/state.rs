@@ -1,7 +1,16 @@
 use crate::utils::trace;
 
 use bity_ic_canister_state_macros::canister_state;
-use bity_ic_icrc3::transaction::TransactionType;
+use bity_ic_canister_time::TimerInfo;
+use bity_ic_icrc3::archive_stats::ArchiveStats;
+use bity_ic_icrc3::blockchain::archive_canister_manager::{ArchiveCanisterHistory, FundingAlert};
+use bity_ic_icrc3::config::FundingConfig;
+use bity_ic_icrc3::dedup_window::DedupWindowMetrics;
+use bity_ic_icrc3::job_history::JobHistoryMetrics;
+use bity_ic_icrc3::notifications::{BlockNotification, SubscriberMetrics};
+use bity_ic_icrc3::prepared::PreparedTransactionsMetrics;
+use bity_ic_icrc3::timestamp_ordering::TimestampOrderingMetrics;
+use bity_ic_icrc3::validation::ValidationMetrics;
 use bity_ic_icrc3_macros::icrc3_state;
 use bity_ic_types::{BuildVersion, Cycles, TimestampMillis};
 use bity_ic_utils::env::{CanisterEnv, Environment};
@@ -11,7 +20,56 @@ use icrc3_example_api::types::FakeTransaction;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 
-icrc3_state!();
+icrc3_state!(only(
+    icrc3_add_transaction,
+    icrc3_prepare_transaction,
+    icrc3_commit_prepared_transaction,
+    icrc3_get_archives,
+    icrc3_get_archive_stats,
+    icrc3_get_blocks,
+    icrc3_get_properties,
+    icrc3_get_tip_certificate,
+    icrc3_supported_block_types,
+    icrc3_block_schemas,
+    icrc3_register_transaction_type,
+    icrc10_supported_standards,
+    icrc3_get_tip,
+    icrc3_chain_length,
+    icrc3_has_block,
+    find_blocks_by_memo,
+    find_block_by_thash,
+    get_transactions,
+    icrc3_blocks_http_chunk,
+    icrc3_update_funding_config,
+    icrc3_funding_config,
+    icrc3_job_history,
+    icrc3_job_history_metrics,
+    icrc3_dedup_window_metrics,
+    icrc3_list_prepared_transactions,
+    icrc3_prepared_transactions_metrics,
+    icrc3_validation_metrics,
+    icrc3_timestamp_ordering_metrics,
+    icrc3_transactions_per_sec,
+    icrc3_timers,
+    icrc3_fire_job_now,
+    icrc3_archive_history,
+    icrc3_add_recorder,
+    icrc3_remove_recorder,
+    icrc3_retire_archive,
+    icrc3_unretire_archive,
+    icrc3_mark_archive_unrecoverable,
+    icrc3_reinstall_archive,
+    icrc3_add_archive_controller,
+    icrc3_remove_archive_controller,
+    icrc3_take_archive_snapshot,
+    icrc3_restore_archive_snapshot,
+    icrc3_rebuild_from_archives,
+    icrc3_reconcile_archives,
+    icrc3_archive_funding_alerts,
+    icrc3_subscribe,
+    icrc3_unsubscribe,
+    icrc3_notification_metrics,
+));
 canister_state!(RuntimeState);
 
 #[derive(Serialize, Deserialize)]
@@ -40,6 +98,18 @@ impl RuntimeState {
                 cycles_balance: self.env.cycles_balance(),
             },
             authorized_principals: self.data.authorized_principals.iter().cloned().collect(),
+            icrc3_funding_config: icrc3_funding_config(),
+            icrc3_jobs: icrc3_job_history_metrics(),
+            icrc3_dedup_window: icrc3_dedup_window_metrics(),
+            icrc3_prepared_transactions: icrc3_prepared_transactions_metrics(),
+            icrc3_validation: icrc3_validation_metrics(),
+            icrc3_timestamp_ordering: icrc3_timestamp_ordering_metrics(),
+            icrc3_transactions_per_sec: icrc3_transactions_per_sec(),
+            icrc3_archives: icrc3_archive_history(),
+            icrc3_archive_stats: icrc3_get_archive_stats(),
+            icrc3_timers: icrc3_timers(),
+            icrc3_funding_alerts: icrc3_archive_funding_alerts(),
+            icrc3_notifications: icrc3_notification_metrics(),
         }
     }
 }
@@ -47,6 +117,9 @@ impl RuntimeState {
 #[derive(Serialize, Deserialize)]
 pub struct Data {
     pub authorized_principals: HashSet<Principal>,
+    /// The notifications received when subscribed to another instance
+    #[serde(default)]
+    pub received_block_notifications: Vec<BlockNotification>,
 }
 
 impl Data {
@@ -54,6 +127,7 @@ impl Data {
     pub fn new(authorized_principals: Vec<Principal>) -> Self {
         Self {
             authorized_principals: authorized_principals.clone().into_iter().collect(),
+            received_block_notifications: Vec::new(),
         }
     }
 
@@ -69,6 +143,10 @@ impl Data {
         }
     }
 
+    pub fn record_block_notifications(&mut self, notifications: Vec<BlockNotification>) {
+        self.received_block_notifications.extend(notifications);
+    }
+
     pub fn create_fake_transaction(&self) -> FakeTransaction {
         trace("create_fake_transaction");
         FakeTransaction::random()
@@ -79,6 +157,18 @@ impl Data {
 pub struct Metrics {
     pub canister_info: CanisterInfo,
     pub authorized_principals: Vec<Principal>,
+    pub icrc3_funding_config: FundingConfig,
+    pub icrc3_jobs: JobHistoryMetrics,
+    pub icrc3_dedup_window: DedupWindowMetrics,
+    pub icrc3_prepared_transactions: PreparedTransactionsMetrics,
+    pub icrc3_validation: ValidationMetrics,
+    pub icrc3_timestamp_ordering: TimestampOrderingMetrics,
+    pub icrc3_transactions_per_sec: f64,
+    pub icrc3_archives: Vec<ArchiveCanisterHistory>,
+    pub icrc3_archive_stats: Vec<ArchiveStats>,
+    pub icrc3_timers: Vec<TimerInfo>,
+    pub icrc3_funding_alerts: Vec<FundingAlert>,
+    pub icrc3_notifications: Vec<SubscriberMetrics>,
 }
 
 #[derive(CandidType, Deserialize, Serialize)]
@@ -90,3 +180,38 @@ pub struct CanisterInfo {
     pub memory_used: MemorySize,
     pub cycles_balance: Cycles,
 }
+
+#[cfg(test)]
+mod tests {
+    // The jobs hold the state lock across await points, as in the canister state.
+    #[allow(clippy::await_holding_lock)]
+    mod selected {
+        use bity_ic_icrc3_macros::icrc3_state;
+
+        icrc3_state!(only(add_transaction, get_blocks, tip_certificate));
+
+        // Not generated, so free to define.
+        pub fn icrc3_get_archives() -> u64 {
+            1
+        }
+
+        // Generated, shadowed by the local function.
+        pub fn icrc3_get_blocks() -> u64 {
+            2
+        }
+    }
+
+    #[test]
+    fn test_icrc3_state_selection() {
+        assert!(!selected::is_initialized());
+        assert_eq!(selected::icrc3_get_archives(), 1);
+        assert_eq!(selected::icrc3_get_blocks(), 2);
+
+        let _: fn(
+            Vec<icrc_ledger_types::icrc3::blocks::GetBlocksRequest>,
+        ) -> icrc_ledger_types::icrc3::blocks::GetBlocksResult =
+            selected::icrc3_state::icrc3_get_blocks;
+        let _: fn() -> Option<icrc_ledger_types::icrc3::blocks::ICRC3DataCertificate> =
+            selected::icrc3_get_tip_certificate;
+    }
+}
@@ -0,0 +1,15 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_add_archive_controller;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::add_archive_controller::{
+    Args as AddArchiveControllerArgs, Response as AddArchiveControllerResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+async fn add_archive_controller(args: AddArchiveControllerArgs) -> AddArchiveControllerResponse {
+    trace(format!("add_archive_controller: {:?}", args));
+
+    icrc3_add_archive_controller(args.canister_id, args.controller).await
+}
@@ -6,7 +6,7 @@ pub use icrc3_example_api::updates::add_created_transaction::{
     Args as AddCreatedTransactionArgs, Response as AddCreatedTransactionResponse,
 };
 
-#[update]
+#[update(hidden = true)]
 fn add_created_transaction(
     transaction: AddCreatedTransactionArgs,
 ) -> AddCreatedTransactionResponse {
@@ -0,0 +1,17 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_add_transaction;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::add_icrc1_transaction::{
+    Args as AddIcrc1TransactionArgs, Response as AddIcrc1TransactionResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+fn add_icrc1_transaction(transaction: AddIcrc1TransactionArgs) -> AddIcrc1TransactionResponse {
+    trace(format!("add_icrc1_transaction: {}", transaction.btype));
+
+    icrc3_add_transaction(transaction)
+        .map(|_| ())
+        .map_err(|e| format!("Error adding transaction: {}", e))
+}
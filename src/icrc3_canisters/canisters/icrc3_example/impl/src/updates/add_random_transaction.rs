@@ -2,13 +2,26 @@ use crate::state::icrc3_add_transaction;
 use crate::state::read_state;
 use crate::utils::trace;
 
+use bity_ic_canister_client::expose_msgpack_endpoint;
 use ic_cdk_macros::update;
 pub use icrc3_example_api::updates::add_random_transaction::{
     Args as RandomTransactionArgs, Response as RandomTransactionResponse,
 };
 
-#[update]
-fn add_random_transaction(_: RandomTransactionArgs) -> RandomTransactionResponse {
+#[update(hidden = true)]
+fn add_random_transaction(args: RandomTransactionArgs) -> RandomTransactionResponse {
+    add_random_transaction_impl(args)
+}
+
+expose_msgpack_endpoint!(
+    update,
+    add_random_transaction_msgpack,
+    RandomTransactionArgs,
+    RandomTransactionResponse,
+    add_random_transaction_impl
+);
+
+fn add_random_transaction_impl(_: RandomTransactionArgs) -> RandomTransactionResponse {
     trace("add_random_transaction");
     let transaction = read_state(|state| state.data.create_fake_transaction());
 
@@ -0,0 +1,15 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_add_recorder;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::add_recorder::{
+    Args as AddRecorderArgs, Response as AddRecorderResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+fn add_recorder(recorder: AddRecorderArgs) -> AddRecorderResponse {
+    trace(format!("add_recorder: {}", recorder));
+
+    icrc3_add_recorder(recorder)
+}
@@ -7,7 +7,7 @@ pub use icrc3_example_api::updates::add_random_transaction::{
     Args as RandomTransactionArgs, Response as RandomTransactionResponse,
 };
 
-#[update]
+#[update(hidden = true)]
 fn add_same_transactions(_: RandomTransactionArgs) -> RandomTransactionResponse {
     trace("add_same_transactions");
     let transaction = read_state(|state| state.data.create_fake_transaction());
@@ -27,7 +27,7 @@ fn add_same_transactions(_: RandomTransactionArgs) -> RandomTransactionResponse
         }
         Err(e) => match e {
             bity_ic_icrc3::types::Icrc3Error::DuplicateTransaction { duplicate_of } => {
-                if duplicate_of == 0 {
+                if duplicate_of == 0u64 {
                     trace(format!("transaction already added: {}", duplicate_of));
                 } else {
                     ic_cdk::trap(format!(
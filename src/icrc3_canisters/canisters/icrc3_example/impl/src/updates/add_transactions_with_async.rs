@@ -6,7 +6,7 @@ pub use icrc3_example_api::updates::add_transactions_with_async::{
     Args as AddTransactionsWithAsyncArgs, Response as AddTransactionsWithAsyncResponse,
 };
 
-#[update]
+#[update(hidden = true)]
 async fn add_transactions_with_async(
     transaction: AddTransactionsWithAsyncArgs,
 ) -> AddTransactionsWithAsyncResponse {
@@ -0,0 +1,15 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_check_archive_funding;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::check_archive_funding::{
+    Args as CheckArchiveFundingArgs, Response as CheckArchiveFundingResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+async fn check_archive_funding(_: CheckArchiveFundingArgs) -> CheckArchiveFundingResponse {
+    trace("check_archive_funding");
+
+    icrc3_check_archive_funding().await
+}
@@ -6,7 +6,7 @@ pub use icrc3_example_api::updates::commit_prepared_transaction::{
     Args as CommitPreparedTransactionArgs, Response as CommitPreparedTransactionResponse,
 };
 
-#[update]
+#[update(hidden = true)]
 fn commit_prepared_transaction(
     args: CommitPreparedTransactionArgs,
 ) -> CommitPreparedTransactionResponse {
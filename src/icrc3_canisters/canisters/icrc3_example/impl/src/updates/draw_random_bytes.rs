@@ -0,0 +1,29 @@
+use crate::guards::caller_is_authorized;
+use crate::utils::trace;
+
+use bity_ic_canister_client::{get_random_bytes, random_pool_stats};
+use ic_cdk_macros::update;
+use icrc3_example_api::updates::draw_random_bytes::RandomDraws;
+pub use icrc3_example_api::updates::draw_random_bytes::{
+    Args as DrawRandomBytesArgs, Response as DrawRandomBytesResponse,
+};
+use serde_bytes::ByteBuf;
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+async fn draw_random_bytes(lengths: DrawRandomBytesArgs) -> DrawRandomBytesResponse {
+    trace(format!("draw_random_bytes: {:?}", lengths));
+
+    let refills_before = random_pool_stats().refills;
+    let mut draws = Vec::with_capacity(lengths.len());
+    for length in lengths {
+        let bytes = get_random_bytes(length as usize)
+            .await
+            .map_err(|e| format!("Failed to draw random bytes: {}", e))?;
+        draws.push(ByteBuf::from(bytes));
+    }
+
+    Ok(RandomDraws {
+        draws,
+        refills: random_pool_stats().refills - refills_before,
+    })
+}
@@ -0,0 +1,15 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_fire_job_now;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::fire_job_now::{
+    Args as FireJobNowArgs, Response as FireJobNowResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+fn fire_job_now(job: FireJobNowArgs) -> FireJobNowResponse {
+    trace(format!("fire_job_now: {:?}", job));
+
+    icrc3_fire_job_now(job)
+}
@@ -0,0 +1,17 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_mark_archive_unrecoverable;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::mark_archive_unrecoverable::{
+    Args as MarkArchiveUnrecoverableArgs, Response as MarkArchiveUnrecoverableResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+fn mark_archive_unrecoverable(
+    canister_id: MarkArchiveUnrecoverableArgs,
+) -> MarkArchiveUnrecoverableResponse {
+    trace(format!("mark_archive_unrecoverable: {}", canister_id));
+
+    icrc3_mark_archive_unrecoverable(canister_id)
+}
@@ -1,13 +1,59 @@
+pub mod add_archive_controller;
 pub mod add_created_transaction;
+pub mod add_icrc1_transaction;
 pub mod add_random_transaction;
+pub mod add_recorder;
 pub mod add_same_transactions;
 pub mod add_transactions_with_async;
+pub mod check_archive_funding;
 pub mod commit_prepared_transaction;
+pub mod draw_random_bytes;
+pub mod fire_job_now;
+pub mod mark_archive_unrecoverable;
 pub mod prepare_transaction;
+pub mod rebuild_from_archives;
+pub mod reconcile_archives;
+pub mod record_block_notifications;
+pub mod reinstall_archive;
+pub mod remove_archive_controller;
+pub mod remove_recorder;
+pub mod restore_archive_snapshot;
+pub mod retire_archive;
+pub mod run_verification_now;
+pub mod set_c2c_debug_capture;
+pub mod set_fault;
+pub mod subscribe;
+pub mod take_archive_snapshot;
+pub mod unretire_archive;
+pub mod unsubscribe;
+pub mod update_funding_config;
 
+pub use add_archive_controller::*;
 pub use add_created_transaction::*;
+pub use add_icrc1_transaction::*;
 pub use add_random_transaction::*;
+pub use add_recorder::*;
 // pub use add_same_transactions::*;
 pub use add_transactions_with_async::*;
+pub use check_archive_funding::*;
 pub use commit_prepared_transaction::*;
+pub use draw_random_bytes::*;
+pub use fire_job_now::*;
+pub use mark_archive_unrecoverable::*;
 pub use prepare_transaction::*;
+pub use rebuild_from_archives::*;
+pub use reconcile_archives::*;
+pub use record_block_notifications::*;
+pub use reinstall_archive::*;
+pub use remove_archive_controller::*;
+pub use remove_recorder::*;
+pub use restore_archive_snapshot::*;
+pub use retire_archive::*;
+pub use run_verification_now::*;
+pub use set_c2c_debug_capture::*;
+pub use set_fault::*;
+pub use subscribe::*;
+pub use take_archive_snapshot::*;
+pub use unretire_archive::*;
+pub use unsubscribe::*;
+pub use update_funding_config::*;
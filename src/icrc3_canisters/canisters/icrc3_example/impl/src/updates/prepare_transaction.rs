@@ -6,7 +6,7 @@ pub use icrc3_example_api::updates::prepare_transaction::{
     Args as PrepareTransactionArgs, Response as PrepareTransactionResponse,
 };
 
-#[update]
+#[update(hidden = true)]
 fn prepare_transaction(transaction: PrepareTransactionArgs) -> PrepareTransactionResponse {
     trace(format!("prepare_transaction: starting"));
 
@@ -31,5 +31,5 @@ fn prepare_transaction(transaction: PrepareTransactionArgs) -> PrepareTransactio
         "prepare_transaction: returning hash: {:?}",
         prepared_tx.transaction_hash
     ));
-    Ok((prepared_tx.transaction_hash, prepared_tx.timestamp))
+    Ok(prepared_tx)
 }
@@ -0,0 +1,15 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_rebuild_from_archives;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::rebuild_from_archives::{
+    Args as RebuildFromArchivesArgs, Response as RebuildFromArchivesResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+async fn rebuild_from_archives(archives: RebuildFromArchivesArgs) -> RebuildFromArchivesResponse {
+    trace(format!("rebuild_from_archives: {:?}", archives));
+
+    icrc3_rebuild_from_archives(archives).await
+}
@@ -0,0 +1,15 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_reconcile_archives;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::reconcile_archives::{
+    Args as ReconcileArchivesArgs, Response as ReconcileArchivesResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+async fn reconcile_archives(args: ReconcileArchivesArgs) -> ReconcileArchivesResponse {
+    trace(format!("reconcile_archives: {:?}", args));
+
+    icrc3_reconcile_archives(args.auto_adopt).await
+}
@@ -0,0 +1,20 @@
+use crate::state::mutate_state;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::record_block_notifications::{
+    Args as RecordBlockNotificationsArgs, Response as RecordBlockNotificationsResponse,
+};
+
+// Lets an instance of this canister subscribe to the blocks of another one.
+#[update(hidden = true)]
+fn record_block_notifications(
+    notifications: RecordBlockNotificationsArgs,
+) -> RecordBlockNotificationsResponse {
+    trace(format!(
+        "record_block_notifications: {} notifications",
+        notifications.len()
+    ));
+
+    mutate_state(|state| state.data.record_block_notifications(notifications))
+}
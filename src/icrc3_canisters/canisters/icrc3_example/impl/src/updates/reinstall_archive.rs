@@ -0,0 +1,15 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_reinstall_archive;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::reinstall_archive::{
+    Args as ReinstallArchiveArgs, Response as ReinstallArchiveResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+async fn reinstall_archive(confirmation: ReinstallArchiveArgs) -> ReinstallArchiveResponse {
+    trace(format!("reinstall_archive: {:?}", confirmation));
+
+    icrc3_reinstall_archive(confirmation).await
+}
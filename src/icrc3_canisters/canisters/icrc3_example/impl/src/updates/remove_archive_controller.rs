@@ -0,0 +1,17 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_remove_archive_controller;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::remove_archive_controller::{
+    Args as RemoveArchiveControllerArgs, Response as RemoveArchiveControllerResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+async fn remove_archive_controller(
+    args: RemoveArchiveControllerArgs,
+) -> RemoveArchiveControllerResponse {
+    trace(format!("remove_archive_controller: {:?}", args));
+
+    icrc3_remove_archive_controller(args.canister_id, args.controller).await
+}
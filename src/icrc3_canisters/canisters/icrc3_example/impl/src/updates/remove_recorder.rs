@@ -0,0 +1,15 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_remove_recorder;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::remove_recorder::{
+    Args as RemoveRecorderArgs, Response as RemoveRecorderResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+fn remove_recorder(recorder: RemoveRecorderArgs) -> RemoveRecorderResponse {
+    trace(format!("remove_recorder: {}", recorder));
+
+    icrc3_remove_recorder(recorder)
+}
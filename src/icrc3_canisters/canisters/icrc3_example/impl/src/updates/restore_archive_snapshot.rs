@@ -0,0 +1,17 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_restore_archive_snapshot;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::restore_archive_snapshot::{
+    Args as RestoreArchiveSnapshotArgs, Response as RestoreArchiveSnapshotResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+async fn restore_archive_snapshot(
+    args: RestoreArchiveSnapshotArgs,
+) -> RestoreArchiveSnapshotResponse {
+    trace(format!("restore_archive_snapshot: {:?}", args));
+
+    icrc3_restore_archive_snapshot(args.canister_id, args.snapshot_id).await
+}
@@ -0,0 +1,15 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_retire_archive;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::retire_archive::{
+    Args as RetireArchiveArgs, Response as RetireArchiveResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+fn retire_archive(canister_id: RetireArchiveArgs) -> RetireArchiveResponse {
+    trace(format!("retire_archive: {}", canister_id));
+
+    icrc3_retire_archive(canister_id)
+}
@@ -0,0 +1,15 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_run_verification_now;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::run_verification_now::{
+    Args as RunVerificationNowArgs, Response as RunVerificationNowResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+async fn run_verification_now(sample_size: RunVerificationNowArgs) -> RunVerificationNowResponse {
+    trace(format!("run_verification_now: {}", sample_size));
+
+    icrc3_run_verification_now(sample_size).await
+}
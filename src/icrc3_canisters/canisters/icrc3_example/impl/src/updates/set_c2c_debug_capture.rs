@@ -0,0 +1,14 @@
+use crate::guards::caller_is_authorized;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::set_c2c_debug_capture::{
+    Args as SetC2cDebugCaptureArgs, Response as SetC2cDebugCaptureResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+fn set_c2c_debug_capture(enabled: SetC2cDebugCaptureArgs) -> SetC2cDebugCaptureResponse {
+    trace(format!("set_c2c_debug_capture: {}", enabled));
+
+    bity_ic_canister_client::set_c2c_debug_capture(enabled)
+}
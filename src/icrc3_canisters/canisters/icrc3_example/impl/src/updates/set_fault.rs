@@ -0,0 +1,43 @@
+use crate::guards::caller_is_authorized;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::set_fault::{
+    Args as SetFaultArgs, FaultKind, Response as SetFaultResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+fn set_fault(args: SetFaultArgs) -> SetFaultResponse {
+    trace(format!("set_fault: {:?}", args));
+    inject(args)
+}
+
+/// Faults are only compiled in the wasm of the integration tests, see the
+/// `testing-hooks` feature.
+#[cfg(not(feature = "testing-hooks"))]
+fn inject(_: SetFaultArgs) -> SetFaultResponse {
+    Err("Fault injection is not compiled in this build".to_string())
+}
+
+#[cfg(feature = "testing-hooks")]
+fn inject(args: SetFaultArgs) -> SetFaultResponse {
+    use crate::state::read_state;
+    use bity_ic_icrc3::testing_hooks;
+
+    // Breaking the chain on purpose is only allowed on test deployments.
+    if args.fault == FaultKind::CorruptNextBlockHash
+        && args.enabled
+        && !read_state(|state| state.env.is_test_mode())
+    {
+        return Err("CorruptNextBlockHash is only available in test mode".to_string());
+    }
+
+    let fault = match args.fault {
+        FaultKind::RejectNextArchiveInsert => testing_hooks::FaultKind::RejectNextArchiveInsert,
+        FaultKind::SlowArchiveInsert { ms } => testing_hooks::FaultKind::SlowArchiveInsert { ms },
+        FaultKind::ForceThrottle => testing_hooks::FaultKind::ForceThrottle,
+        FaultKind::CorruptNextBlockHash => testing_hooks::FaultKind::CorruptNextBlockHash,
+    };
+    testing_hooks::set_fault(fault, args.enabled);
+    Ok(())
+}
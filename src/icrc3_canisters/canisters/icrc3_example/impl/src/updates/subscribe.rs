@@ -0,0 +1,15 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_subscribe;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::subscribe::{
+    Args as SubscribeArgs, Response as SubscribeResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+fn subscribe(args: SubscribeArgs) -> SubscribeResponse {
+    trace(format!("subscribe: {:?}", args));
+
+    icrc3_subscribe(args.canister_id, args.method_name, args.filter)
+}
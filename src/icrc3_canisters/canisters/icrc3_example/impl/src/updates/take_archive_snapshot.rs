@@ -0,0 +1,15 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_take_archive_snapshot;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::take_archive_snapshot::{
+    Args as TakeArchiveSnapshotArgs, Response as TakeArchiveSnapshotResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+async fn take_archive_snapshot(args: TakeArchiveSnapshotArgs) -> TakeArchiveSnapshotResponse {
+    trace(format!("take_archive_snapshot: {:?}", args));
+
+    icrc3_take_archive_snapshot(args.canister_id).await
+}
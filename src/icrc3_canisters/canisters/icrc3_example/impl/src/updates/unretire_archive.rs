@@ -0,0 +1,15 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_unretire_archive;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::unretire_archive::{
+    Args as UnretireArchiveArgs, Response as UnretireArchiveResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+fn unretire_archive(canister_id: UnretireArchiveArgs) -> UnretireArchiveResponse {
+    trace(format!("unretire_archive: {}", canister_id));
+
+    icrc3_unretire_archive(canister_id)
+}
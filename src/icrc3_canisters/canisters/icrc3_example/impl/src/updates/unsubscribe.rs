@@ -0,0 +1,15 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_unsubscribe;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::unsubscribe::{
+    Args as UnsubscribeArgs, Response as UnsubscribeResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+fn unsubscribe(canister_id: UnsubscribeArgs) -> UnsubscribeResponse {
+    trace(format!("unsubscribe: {}", canister_id));
+
+    icrc3_unsubscribe(canister_id)
+}
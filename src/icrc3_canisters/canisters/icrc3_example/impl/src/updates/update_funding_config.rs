@@ -0,0 +1,16 @@
+use crate::guards::caller_is_authorized;
+use crate::state::icrc3_update_funding_config;
+use crate::utils::trace;
+
+use ic_cdk_macros::update;
+pub use icrc3_example_api::updates::update_funding_config::{
+    Args as UpdateFundingConfigArgs, Response as UpdateFundingConfigResponse,
+};
+
+#[update(guard = "caller_is_authorized", hidden = true)]
+fn update_funding_config(funding_config: UpdateFundingConfigArgs) -> UpdateFundingConfigResponse {
+    trace(format!("update_funding_config: {:?}", funding_config));
+
+    icrc3_update_funding_config(funding_config)
+        .map_err(|e| format!("Error updating funding config: {}", e))
+}
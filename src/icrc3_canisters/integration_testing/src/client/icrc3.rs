@@ -1,23 +1,78 @@
 use crate::{generate_pocket_query_call, generate_pocket_update_call};
+use icrc3_example_api::add_archive_controller;
 use icrc3_example_api::add_created_transaction;
+use icrc3_example_api::add_icrc1_transaction;
 use icrc3_example_api::add_random_transaction;
+use icrc3_example_api::add_recorder;
 use icrc3_example_api::add_same_transactions;
 use icrc3_example_api::add_transactions_with_async;
+use icrc3_example_api::check_archive_funding;
 use icrc3_example_api::commit_prepared_transaction;
 use icrc3_example_api::create_transactions;
+use icrc3_example_api::draw_random_bytes;
+use icrc3_example_api::find_block_by_thash;
+use icrc3_example_api::find_blocks_by_memo;
+use icrc3_example_api::fire_job_now;
+use icrc3_example_api::get_transactions;
+use icrc3_example_api::http_request;
+use icrc3_example_api::http_request_streaming_callback;
+use icrc3_example_api::icrc10_supported_standards;
+use icrc3_example_api::icrc3_block_schemas;
+use icrc3_example_api::icrc3_chain_length;
+use icrc3_example_api::icrc3_get_archive_stats;
 use icrc3_example_api::icrc3_get_archives;
 use icrc3_example_api::icrc3_get_blocks;
 use icrc3_example_api::icrc3_get_properties;
+use icrc3_example_api::icrc3_get_tip;
 use icrc3_example_api::icrc3_get_tip_certificate;
+use icrc3_example_api::icrc3_has_block;
+use icrc3_example_api::icrc3_job_history;
+use icrc3_example_api::icrc3_list_prepared_transactions;
+use icrc3_example_api::icrc3_notification_metrics;
+use icrc3_example_api::icrc3_prepared_transactions_metrics;
 use icrc3_example_api::icrc3_supported_block_types;
+use icrc3_example_api::icrc3_timers;
+use icrc3_example_api::mark_archive_unrecoverable;
 use icrc3_example_api::prepare_transaction;
+use icrc3_example_api::rebuild_from_archives;
+use icrc3_example_api::received_block_notifications;
+use icrc3_example_api::reconcile_archives;
+use icrc3_example_api::reinstall_archive;
+use icrc3_example_api::remove_archive_controller;
+use icrc3_example_api::remove_recorder;
+use icrc3_example_api::restore_archive_snapshot;
+use icrc3_example_api::retire_archive;
+use icrc3_example_api::run_verification_now;
+use icrc3_example_api::set_fault;
+use icrc3_example_api::subscribe;
+use icrc3_example_api::take_archive_snapshot;
+use icrc3_example_api::unretire_archive;
+use icrc3_example_api::unsubscribe;
+use icrc3_example_api::update_funding_config;
 // // Queries
 generate_pocket_query_call!(icrc3_get_properties);
 generate_pocket_query_call!(icrc3_get_blocks);
 generate_pocket_query_call!(icrc3_get_tip_certificate);
 generate_pocket_query_call!(icrc3_supported_block_types);
 generate_pocket_query_call!(icrc3_get_archives);
+generate_pocket_query_call!(icrc3_get_archive_stats);
+generate_pocket_query_call!(icrc3_job_history);
+generate_pocket_query_call!(icrc3_timers);
+generate_pocket_query_call!(find_block_by_thash);
+generate_pocket_query_call!(find_blocks_by_memo);
+generate_pocket_query_call!(get_transactions);
+generate_pocket_query_call!(icrc3_block_schemas);
+generate_pocket_query_call!(icrc3_chain_length);
+generate_pocket_query_call!(icrc3_has_block);
+generate_pocket_query_call!(icrc3_get_tip);
 generate_pocket_query_call!(create_transactions);
+generate_pocket_query_call!(icrc10_supported_standards);
+generate_pocket_query_call!(http_request);
+generate_pocket_query_call!(http_request_streaming_callback);
+generate_pocket_query_call!(icrc3_notification_metrics);
+generate_pocket_query_call!(icrc3_list_prepared_transactions);
+generate_pocket_query_call!(icrc3_prepared_transactions_metrics);
+generate_pocket_query_call!(received_block_notifications);
 // Updates
 // generate_pocket_update_call!(add_authorized_principals);
 // generate_pocket_update_call!(add_batch_transactions);
@@ -25,6 +80,37 @@ generate_pocket_update_call!(add_random_transaction);
 generate_pocket_update_call!(add_same_transactions);
 // generate_pocket_update_call!(remove_authorized_principals);
 generate_pocket_update_call!(add_created_transaction);
+generate_pocket_update_call!(add_icrc1_transaction);
 generate_pocket_update_call!(add_transactions_with_async);
 generate_pocket_update_call!(prepare_transaction);
 generate_pocket_update_call!(commit_prepared_transaction);
+generate_pocket_update_call!(draw_random_bytes);
+generate_pocket_update_call!(fire_job_now);
+generate_pocket_update_call!(add_archive_controller);
+generate_pocket_update_call!(remove_archive_controller);
+generate_pocket_update_call!(take_archive_snapshot);
+generate_pocket_update_call!(restore_archive_snapshot);
+generate_pocket_update_call!(rebuild_from_archives);
+generate_pocket_update_call!(reconcile_archives);
+generate_pocket_update_call!(check_archive_funding);
+generate_pocket_update_call!(run_verification_now);
+generate_pocket_update_call!(set_fault);
+generate_pocket_update_call!(retire_archive);
+generate_pocket_update_call!(unretire_archive);
+generate_pocket_update_call!(mark_archive_unrecoverable);
+generate_pocket_update_call!(reinstall_archive);
+generate_pocket_update_call!(add_recorder);
+generate_pocket_update_call!(remove_recorder);
+generate_pocket_update_call!(update_funding_config);
+generate_pocket_update_call!(subscribe);
+generate_pocket_update_call!(unsubscribe);
+
+/// Clients of the `_msgpack` endpoint variants.
+pub mod msgpack {
+    use crate::{generate_pocket_query_call_msgpack, generate_pocket_update_call_msgpack};
+    use icrc3_example_api::add_random_transaction;
+    use icrc3_example_api::icrc3_get_blocks;
+
+    generate_pocket_query_call_msgpack!(icrc3_get_blocks);
+    generate_pocket_update_call_msgpack!(add_random_transaction);
+}
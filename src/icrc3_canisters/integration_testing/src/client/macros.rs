@@ -32,6 +32,52 @@ macro_rules! generate_pocket_update_call {
     };
 }
 
+#[macro_export]
+macro_rules! generate_pocket_query_call_msgpack {
+    ($method_name:ident) => {
+        #[allow(dead_code)]
+        pub fn $method_name(
+            pic: &pocket_ic::PocketIc,
+            sender: candid::Principal,
+            canister_id: candid::Principal,
+            args: &$method_name::Args,
+        ) -> $method_name::Response {
+            let method_name = concat!(stringify!($method_name), "_msgpack");
+
+            $crate::client::pocket::execute_query_msgpack(
+                pic,
+                sender,
+                canister_id,
+                method_name,
+                args,
+            )
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! generate_pocket_update_call_msgpack {
+    ($method_name:ident) => {
+        #[allow(dead_code)]
+        pub fn $method_name(
+            pic: &mut pocket_ic::PocketIc,
+            sender: candid::Principal,
+            canister_id: candid::Principal,
+            args: &$method_name::Args,
+        ) -> $method_name::Response {
+            let method_name = concat!(stringify!($method_name), "_msgpack");
+
+            $crate::client::pocket::execute_update_msgpack(
+                pic,
+                sender,
+                canister_id,
+                method_name,
+                args,
+            )
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! generate_update_call_encoded_args {
     ($method_name:ident) => {
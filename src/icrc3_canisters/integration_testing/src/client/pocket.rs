@@ -4,6 +4,7 @@ use bity_ic_types::CanisterId;
 use candid::{CandidType, Encode, Principal};
 use pocket_ic::{PocketIc, RejectResponse};
 use serde::de::DeserializeOwned;
+use serde::Serialize;
 
 use crate::utils::T;
 
@@ -48,6 +49,45 @@ pub fn unwrap_response<R: CandidType + DeserializeOwned>(
     }
 }
 
+pub fn execute_query_msgpack<P: Serialize, R: DeserializeOwned>(
+    pic: &PocketIc,
+    sender: Principal,
+    canister_id: CanisterId,
+    method_name: &str,
+    payload: &P,
+) -> R {
+    unwrap_msgpack_response(pic.query_call(
+        canister_id,
+        sender,
+        method_name,
+        bity_ic_canister_client::msgpack::serialize(payload).unwrap(),
+    ))
+}
+
+pub fn execute_update_msgpack<P: Serialize, R: DeserializeOwned>(
+    pic: &PocketIc,
+    sender: Principal,
+    canister_id: CanisterId,
+    method_name: &str,
+    payload: &P,
+) -> R {
+    unwrap_msgpack_response(pic.update_call(
+        canister_id,
+        sender,
+        method_name,
+        bity_ic_canister_client::msgpack::serialize(payload).unwrap(),
+    ))
+}
+
+pub fn unwrap_msgpack_response<R: DeserializeOwned>(
+    response: Result<Vec<u8>, RejectResponse>,
+) -> R {
+    match response {
+        Ok(response) => bity_ic_canister_client::msgpack::deserialize(&response).unwrap(),
+        Err(reject) => panic!("Reject response: {:?}", reject),
+    }
+}
+
 pub fn execute_update_encoded_args<R: CandidType + DeserializeOwned>(
     pic: &mut PocketIc,
     sender: Principal,
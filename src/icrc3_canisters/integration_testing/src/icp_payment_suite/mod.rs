@@ -0,0 +1,2 @@
+mod setup;
+mod tests;
@@ -0,0 +1,159 @@
+use crate::client::pocket::execute_update;
+use crate::utils::random_principal;
+use crate::wasms::ICP_LEDGER;
+
+use async_trait::async_trait;
+use bity_ic_ledger_utils::icp_payment::LegacyLedgerClient;
+use bity_ic_types::CanisterId;
+use candid::{CandidType, Principal};
+use ic_ledger_types::{
+    AccountIdentifier, BlockIndex, GetBlocksArgs, GetBlocksResult, Memo, QueryArchiveFn,
+    QueryBlocksResponse, Tokens, TransferArgs, TransferResult, DEFAULT_SUBACCOUNT,
+};
+use pocket_ic::{PocketIc, PocketIcBuilder};
+
+pub const TRANSFER_FEE: Tokens = Tokens::from_e8s(10_000);
+/// Blocks kept by the ledger before it archives `NUM_BLOCKS_TO_ARCHIVE` of them.
+pub const TRIGGER_THRESHOLD: u64 = 4;
+pub const NUM_BLOCKS_TO_ARCHIVE: u64 = 3;
+
+/// The subset of the ICP ledger init arguments used by the tests, the other
+/// optional fields are left unset.
+#[derive(CandidType)]
+enum LedgerCanisterPayload {
+    Init(LedgerInitArgs),
+}
+
+#[derive(CandidType)]
+struct LedgerInitArgs {
+    minting_account: String,
+    initial_values: Vec<(String, Tokens)>,
+    send_whitelist: Vec<Principal>,
+    transfer_fee: Option<Tokens>,
+    archive_options: Option<ArchiveOptions>,
+    token_symbol: Option<String>,
+    token_name: Option<String>,
+}
+
+#[derive(CandidType)]
+struct ArchiveOptions {
+    trigger_threshold: u64,
+    num_blocks_to_archive: u64,
+    controller_id: Principal,
+    cycles_for_archive_creation: Option<u64>,
+}
+
+pub struct IcpTestEnv {
+    pub pic: PocketIc,
+    pub ledger_id: CanisterId,
+    pub payer: Principal,
+    pub merchant: Principal,
+}
+
+impl IcpTestEnv {
+    pub fn account(principal: Principal) -> AccountIdentifier {
+        AccountIdentifier::new(&principal, &DEFAULT_SUBACCOUNT)
+    }
+
+    /// Transfers `amount` from the payer to the merchant, returning the block index.
+    pub fn pay(&self, amount: Tokens, memo: u64) -> BlockIndex {
+        let result: TransferResult = execute_update(
+            &self.pic,
+            self.payer,
+            self.ledger_id,
+            "transfer",
+            &TransferArgs {
+                memo: Memo(memo),
+                amount,
+                fee: TRANSFER_FEE,
+                from_subaccount: None,
+                to: Self::account(self.merchant),
+                created_at_time: None,
+            },
+        );
+        result.unwrap()
+    }
+}
+
+/// Installs the ICP ledger on a system subnet, where the ledger creates its
+/// archive canisters without cycles. Block 0 mints 100 ICP to the payer.
+pub fn icp_ledger_test_setup() -> IcpTestEnv {
+    let pic = PocketIcBuilder::new().with_nns_subnet().build();
+    let controller = random_principal();
+    let payer = random_principal();
+    let merchant = random_principal();
+    let minter = random_principal();
+
+    let ledger_id = pic.create_canister_with_settings(Some(controller), None);
+    let init_args = LedgerCanisterPayload::Init(LedgerInitArgs {
+        minting_account: IcpTestEnv::account(minter).to_hex(),
+        initial_values: vec![(
+            IcpTestEnv::account(payer).to_hex(),
+            Tokens::from_e8s(100 * Tokens::SUBDIVIDABLE_BY),
+        )],
+        send_whitelist: vec![],
+        transfer_fee: Some(TRANSFER_FEE),
+        archive_options: Some(ArchiveOptions {
+            trigger_threshold: TRIGGER_THRESHOLD,
+            num_blocks_to_archive: NUM_BLOCKS_TO_ARCHIVE,
+            controller_id: controller,
+            cycles_for_archive_creation: Some(0),
+        }),
+        token_symbol: Some("ICP".to_string()),
+        token_name: Some("Internet Computer".to_string()),
+    });
+    pic.install_canister(
+        ledger_id,
+        ICP_LEDGER.clone(),
+        candid::encode_one(init_args).unwrap(),
+        Some(controller),
+    );
+
+    IcpTestEnv {
+        pic,
+        ledger_id,
+        payer,
+        merchant,
+    }
+}
+
+/// Makes the legacy ledger calls of `bity_ic_ledger_utils` as PocketIC queries.
+pub struct PocketIcLedger<'a>(pub &'a PocketIc);
+
+#[async_trait]
+impl LegacyLedgerClient for PocketIcLedger<'_> {
+    async fn query_blocks(
+        &self,
+        ledger: CanisterId,
+        args: GetBlocksArgs,
+    ) -> Result<QueryBlocksResponse, String> {
+        let response = self
+            .0
+            .query_call(
+                ledger,
+                Principal::anonymous(),
+                "query_blocks",
+                candid::encode_one(args).unwrap(),
+            )
+            .map_err(|e| format!("{e:?}"))?;
+        candid::decode_one(&response).map_err(|e| format!("{e:?}"))
+    }
+
+    async fn query_archived_blocks(
+        &self,
+        callback: QueryArchiveFn,
+        args: GetBlocksArgs,
+    ) -> Result<GetBlocksResult, String> {
+        let callback = candid::Func::from(callback);
+        let response = self
+            .0
+            .query_call(
+                callback.principal,
+                Principal::anonymous(),
+                &callback.method,
+                candid::encode_one(args).unwrap(),
+            )
+            .map_err(|e| format!("{e:?}"))?;
+        candid::decode_one(&response).map_err(|e| format!("{e:?}"))
+    }
+}
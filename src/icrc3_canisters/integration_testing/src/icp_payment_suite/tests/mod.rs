@@ -0,0 +1 @@
+pub mod test_verify_icp_payment;
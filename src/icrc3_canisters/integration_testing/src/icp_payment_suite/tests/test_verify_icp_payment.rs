@@ -0,0 +1,125 @@
+use crate::icp_payment_suite::setup::{
+    icp_ledger_test_setup, IcpTestEnv, PocketIcLedger, NUM_BLOCKS_TO_ARCHIVE, TRANSFER_FEE,
+};
+use crate::utils::tick_n_blocks;
+
+use bity_ic_ledger_utils::icp_payment::{
+    get_icp_block_with, verify_icp_payment_with, ExpectedTransfer, LegacyLedgerClient,
+    PaymentVerifyError,
+};
+use futures::executor::block_on;
+use ic_ledger_types::{GetBlocksArgs, Operation, Tokens};
+
+fn expected(test_env: &IcpTestEnv, amount: Tokens, memo: u64) -> ExpectedTransfer {
+    ExpectedTransfer {
+        from: Some(IcpTestEnv::account(test_env.payer)),
+        to: IcpTestEnv::account(test_env.merchant),
+        amount,
+        memo: Some(memo),
+    }
+}
+
+#[test]
+fn test_matching_transfer() {
+    let test_env = icp_ledger_test_setup();
+    let amount = Tokens::from_e8s(150_000_000);
+    let block_index = test_env.pay(amount, 42);
+
+    let verified = block_on(verify_icp_payment_with(
+        &PocketIcLedger(&test_env.pic),
+        test_env.ledger_id,
+        block_index,
+        expected(&test_env, amount, 42),
+    ))
+    .unwrap();
+
+    assert_eq!(verified.block_index, block_index);
+    assert_eq!(verified.amount, amount);
+    assert_eq!(verified.fee, TRANSFER_FEE);
+    assert_eq!(verified.memo, 42);
+    assert!(verified.timestamp.timestamp_nanos > 0);
+
+    // The initial balance was minted in block 0.
+    let mint = block_on(verify_icp_payment_with(
+        &PocketIcLedger(&test_env.pic),
+        test_env.ledger_id,
+        0,
+        expected(&test_env, amount, 42),
+    ));
+    assert!(matches!(
+        mint,
+        Err(PaymentVerifyError::NotATransfer(Operation::Mint { .. }))
+    ));
+
+    let missing = block_on(verify_icp_payment_with(
+        &PocketIcLedger(&test_env.pic),
+        test_env.ledger_id,
+        block_index + 1,
+        expected(&test_env, amount, 42),
+    ));
+    assert_eq!(
+        missing,
+        Err(PaymentVerifyError::BlockNotFound(block_index + 1))
+    );
+}
+
+#[test]
+fn test_wrong_amount() {
+    let test_env = icp_ledger_test_setup();
+    let block_index = test_env.pay(Tokens::from_e8s(100_000_000), 1);
+
+    let result = block_on(verify_icp_payment_with(
+        &PocketIcLedger(&test_env.pic),
+        test_env.ledger_id,
+        block_index,
+        expected(&test_env, Tokens::from_e8s(200_000_000), 1),
+    ));
+
+    assert_eq!(
+        result,
+        Err(PaymentVerifyError::WrongAmount {
+            actual: Tokens::from_e8s(100_000_000)
+        })
+    );
+}
+
+#[test]
+fn test_archived_block() {
+    let test_env = icp_ledger_test_setup();
+    let amount = Tokens::from_e8s(10_000_000);
+    let first_payment = test_env.pay(amount, 7);
+    for memo in 0..5 {
+        test_env.pay(amount, memo);
+    }
+    tick_n_blocks(&test_env.pic, 10);
+
+    // The ledger no longer serves the block itself.
+    let response = block_on(PocketIcLedger(&test_env.pic).query_blocks(
+        test_env.ledger_id,
+        GetBlocksArgs {
+            start: first_payment,
+            length: 1,
+        },
+    ))
+    .unwrap();
+    assert!(response.blocks.is_empty());
+    assert!(response.first_block_index >= NUM_BLOCKS_TO_ARCHIVE);
+    assert_eq!(response.archived_blocks.len(), 1);
+
+    let block = block_on(get_icp_block_with(
+        &PocketIcLedger(&test_env.pic),
+        test_env.ledger_id,
+        first_payment,
+    ))
+    .unwrap();
+    assert_eq!(block.transaction.memo.0, 7);
+
+    let verified = block_on(verify_icp_payment_with(
+        &PocketIcLedger(&test_env.pic),
+        test_env.ledger_id,
+        first_payment,
+        expected(&test_env, amount, 7),
+    ))
+    .unwrap();
+    assert_eq!(verified.fee, TRANSFER_FEE);
+}
@@ -0,0 +1,247 @@
+//! Failure injection for the archive canisters.
+//!
+//! The helpers stop, start, uninstall or drain the cycles of an archive canister
+//! through PocketIC, acting as the ICRC3 canister which controls the archives.
+//! [`ChaosScenario`] interleaves those actions with transaction inserts and time
+//! advancement, the timing of the inserts being drawn from a seeded generator so
+//! a failing run can be replayed.
+
+use crate::client::icrc3::{add_random_transaction, icrc3_get_archives, icrc3_get_blocks};
+use crate::icrc3_suite::setup::setup::TestEnv;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_types::CanisterId;
+use candid::{Nat, Principal};
+use ic_management_canister_types::CanisterSettings;
+use icrc_ledger_types::icrc3::archive::ICRC3ArchiveInfo;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+use pocket_ic::PocketIc;
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
+use std::time::Duration;
+
+/// The default freezing threshold of a canister, in seconds.
+const DEFAULT_FREEZING_THRESHOLD: u64 = 2_592_000;
+
+/// Stops an archive canister.
+pub fn stop_archive(pic: &PocketIc, icrc3_id: CanisterId, archive_id: CanisterId) {
+    pic.stop_canister(archive_id, Some(icrc3_id))
+        .expect("failed to stop the archive");
+}
+
+/// Starts an archive canister.
+pub fn start_archive(pic: &PocketIc, icrc3_id: CanisterId, archive_id: CanisterId) {
+    pic.start_canister(archive_id, Some(icrc3_id))
+        .expect("failed to start the archive");
+}
+
+/// Uninstalls the code of an archive canister, losing the blocks it stores.
+pub fn uninstall_archive(pic: &PocketIc, icrc3_id: CanisterId, archive_id: CanisterId) {
+    pic.uninstall_canister(archive_id, Some(icrc3_id))
+        .expect("failed to uninstall the archive");
+}
+
+/// Burns the cycles of an archive canister until at most `remaining` are left.
+///
+/// The archive is given a compute allocation, which is charged every second, and
+/// time is advanced until its balance is low enough. The ICRC3 canister is stopped
+/// meanwhile so its fund manager does not top up the archive.
+pub fn drain_archive_cycles(
+    pic: &PocketIc,
+    controller: Principal,
+    icrc3_id: CanisterId,
+    archive_id: CanisterId,
+    remaining: u128,
+) {
+    pic.stop_canister(icrc3_id, Some(controller))
+        .expect("failed to stop the ICRC3 canister");
+    pic.update_canister_settings(
+        archive_id,
+        Some(icrc3_id),
+        CanisterSettings {
+            compute_allocation: Some(Nat::from(100u64)),
+            freezing_threshold: Some(Nat::from(0u64)),
+            ..Default::default()
+        },
+    )
+    .expect("failed to allocate compute to the archive");
+
+    for _ in 0..10_000 {
+        if pic.cycle_balance(archive_id) <= remaining {
+            break;
+        }
+        pic.advance_time(Duration::from_secs(10));
+        pic.tick();
+    }
+    assert!(
+        pic.cycle_balance(archive_id) <= remaining,
+        "failed to drain the archive cycles"
+    );
+
+    pic.update_canister_settings(
+        archive_id,
+        Some(icrc3_id),
+        CanisterSettings {
+            compute_allocation: Some(Nat::from(0u64)),
+            freezing_threshold: Some(Nat::from(DEFAULT_FREEZING_THRESHOLD)),
+            ..Default::default()
+        },
+    )
+    .expect("failed to reset the archive settings");
+    pic.start_canister(icrc3_id, Some(controller))
+        .expect("failed to start the ICRC3 canister");
+}
+
+/// Returns the archives of the ICRC3 canister, ordered by their first block.
+pub fn archives_by_start(test_env: &TestEnv) -> Vec<ICRC3ArchiveInfo> {
+    let mut archives =
+        icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    archives.sort_by(|a, b| a.start.cmp(&b.start));
+    archives
+}
+
+/// Asserts that `icrc3_get_blocks` returns every block of the chain exactly once,
+/// either locally or as an archived range.
+///
+/// # Returns
+/// The number of blocks served locally.
+pub fn assert_blocks_cover_chain(test_env: &TestEnv) -> u64 {
+    let probe = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &vec![],
+    );
+    let log_length: u64 = probe.log_length.0.try_into().unwrap();
+
+    let result = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &vec![GetBlocksRequest {
+            start: Nat::from(0u64),
+            length: Nat::from(log_length),
+        }],
+    );
+
+    let mut served = vec![0u32; log_length as usize];
+    for block in &result.blocks {
+        let id: u64 = block.id.0.clone().try_into().unwrap();
+        served[id as usize] += 1;
+    }
+    for archived in &result.archived_blocks {
+        for range in &archived.args {
+            let start: u64 = range.start.0.clone().try_into().unwrap();
+            let length: u64 = range.length.0.clone().try_into().unwrap();
+            for id in start..start + length {
+                served[id as usize] += 1;
+            }
+        }
+    }
+
+    for (id, count) in served.iter().enumerate() {
+        assert_eq!(*count, 1, "block {id} is served {count} times");
+    }
+
+    result.blocks.len() as u64
+}
+
+/// An action of a [`ChaosScenario`].
+///
+/// Archives are designated by their position in [`archives_by_start`], resolved
+/// when the action runs.
+#[derive(Clone, Debug)]
+pub enum ChaosAction {
+    /// Adds transactions, each followed by a short random pause
+    AddTransactions(u32),
+    /// Advances the time and lets the timers run
+    AdvanceTime(Duration),
+    /// Stops an archive
+    StopArchive(usize),
+    /// Starts an archive
+    StartArchive(usize),
+    /// Uninstalls an archive
+    UninstallArchive(usize),
+    /// Drains the cycles of an archive, see [`drain_archive_cycles`]
+    DrainArchiveCycles { archive: usize, remaining: u128 },
+}
+
+/// A script of [`ChaosAction`]s run against a [`TestEnv`].
+pub struct ChaosScenario {
+    rng: StdRng,
+    actions: Vec<ChaosAction>,
+}
+
+impl ChaosScenario {
+    /// Creates an empty scenario whose random pauses are drawn from `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            actions: vec![],
+        }
+    }
+
+    /// Appends an action to the scenario.
+    pub fn then(mut self, action: ChaosAction) -> Self {
+        self.actions.push(action);
+        self
+    }
+
+    /// Runs the actions in order.
+    pub fn run(mut self, test_env: &mut TestEnv) {
+        for action in std::mem::take(&mut self.actions) {
+            self.run_action(test_env, action);
+        }
+    }
+
+    fn run_action(&mut self, test_env: &mut TestEnv, action: ChaosAction) {
+        match action {
+            ChaosAction::AddTransactions(count) => {
+                for _ in 0..count {
+                    add_random_transaction(
+                        &mut test_env.pic,
+                        test_env.controller,
+                        test_env.icrc3_id,
+                        &(),
+                    );
+                    let pause = self.rng.random_range(1..=5);
+                    test_env.pic.advance_time(Duration::from_secs(pause));
+                    tick_n_blocks(&test_env.pic, self.rng.random_range(5..=15));
+                }
+            }
+            ChaosAction::AdvanceTime(duration) => {
+                test_env.pic.advance_time(duration);
+                tick_n_blocks(&test_env.pic, 50);
+            }
+            ChaosAction::StopArchive(archive) => {
+                let archive_id = archive_id(test_env, archive);
+                stop_archive(&test_env.pic, test_env.icrc3_id, archive_id);
+            }
+            ChaosAction::StartArchive(archive) => {
+                let archive_id = archive_id(test_env, archive);
+                start_archive(&test_env.pic, test_env.icrc3_id, archive_id);
+            }
+            ChaosAction::UninstallArchive(archive) => {
+                let archive_id = archive_id(test_env, archive);
+                uninstall_archive(&test_env.pic, test_env.icrc3_id, archive_id);
+            }
+            ChaosAction::DrainArchiveCycles { archive, remaining } => {
+                let archive_id = archive_id(test_env, archive);
+                drain_archive_cycles(
+                    &test_env.pic,
+                    test_env.controller,
+                    test_env.icrc3_id,
+                    archive_id,
+                    remaining,
+                );
+            }
+        }
+    }
+}
+
+fn archive_id(test_env: &TestEnv, archive: usize) -> CanisterId {
+    archives_by_start(test_env)
+        .get(archive)
+        .unwrap_or_else(|| panic!("no archive at position {archive}"))
+        .canister_id
+}
@@ -1,2 +1,3 @@
+mod chaos;
 mod setup;
 mod tests;
@@ -9,6 +9,18 @@ pub fn default_test_setup() -> TestEnv {
 }
 
 pub fn default_test_setup_with_archive() -> TestEnv {
+    test_env_builder_with_archive().build()
+}
+
+/// Same as [`default_test_setup_with_archive`], with the test-only endpoints of
+/// the archive canisters enabled.
+pub fn default_test_setup_with_archive_in_test_mode() -> TestEnv {
+    let mut test_env = test_env_builder_with_archive();
+    test_env.archive_test_mode = true;
+    test_env.build()
+}
+
+fn test_env_builder_with_archive() -> TestEnvBuilder {
     let mut test_env = TestEnvBuilder::new();
 
     let mut icrc3_constants = ICRC3Properties::default();
@@ -18,5 +30,5 @@ pub fn default_test_setup_with_archive() -> TestEnv {
 
     test_env.icrc3_constants = icrc3_constants;
 
-    test_env.build()
+    test_env
 }
@@ -1,6 +1,6 @@
-use crate::icrc3_suite::setup::setup_icrc3::setup_icrc3_canister;
+use crate::icrc3_suite::setup::setup_icrc3::{setup_external_archive, setup_icrc3_canister};
 use crate::utils::random_principal;
-use bity_ic_icrc3::config::{ICRC3Config, ICRC3Properties};
+use bity_ic_icrc3::config::{FundingConfig, ICRC3Config, ICRC3Properties};
 use bity_ic_types::{BuildVersion, CanisterId};
 use candid::Principal;
 use icrc3_example_api::Args;
@@ -10,9 +10,11 @@ use pocket_ic::{PocketIc, PocketIcBuilder};
 pub struct TestEnv {
     pub controller: Principal,
     pub icrc3_id: CanisterId,
+    pub external_archives: Vec<CanisterId>,
     pub pic: PocketIc,
 }
 
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fmt::Debug;
 use std::fmt::Formatter;
@@ -24,9 +26,16 @@ impl Debug for TestEnv {
     }
 }
 pub struct TestEnvBuilder {
-    controller: Principal,
+    pub controller: Principal,
     icrc3_id: CanisterId,
     pub icrc3_constants: ICRC3Properties,
+    pub icrc3_funding_config: Option<FundingConfig>,
+    /// Number of archive canisters installed beforehand and configured as external
+    pub external_archives: usize,
+    /// Block types supported by the ICRC3 canister
+    pub supported_block_types: Vec<String>,
+    /// Whether the archive canisters expose their test-only endpoints
+    pub archive_test_mode: bool,
 }
 
 impl Default for TestEnvBuilder {
@@ -35,6 +44,10 @@ impl Default for TestEnvBuilder {
             controller: random_principal(),
             icrc3_id: Principal::from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
             icrc3_constants: ICRC3Properties::default(),
+            icrc3_funding_config: None,
+            external_archives: 0,
+            supported_block_types: vec!["btype_test".to_string()],
+            archive_test_mode: false,
         }
     }
 }
@@ -49,19 +62,13 @@ impl TestEnvBuilder {
 
         self.icrc3_id = pic.create_canister_with_settings(Some(self.controller.clone()), None);
 
-        let icrc3_init_args = Args::Init(icrc3_example_api::init::InitArgs {
-            test_mode: true,
-            version: BuildVersion::min(),
-            commit_hash: "".to_string(),
-            authorized_principals: vec![self.controller],
-            icrc3_config: ICRC3Config {
-                supported_blocks: vec![SupportedBlockType {
-                    block_type: "btype_test".to_string(),
-                    url: "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-3/README.md#supported-block-types".to_string(),
-                }],
-                constants: self.icrc3_constants.clone(),
-            },
-        });
+        // Only the first external archive starts at block 0, the next ones are
+        // expected to be installed once it is full.
+        let external_archives: Vec<CanisterId> = (0..self.external_archives)
+            .map(|_| setup_external_archive(&mut pic, self.icrc3_id, 0, self.controller))
+            .collect();
+
+        let icrc3_init_args = self.icrc3_init_args(external_archives.clone());
 
         let icrc3_canister_id =
             setup_icrc3_canister(&mut pic, self.icrc3_id, icrc3_init_args, self.controller);
@@ -69,7 +76,38 @@ impl TestEnvBuilder {
         TestEnv {
             controller: self.controller,
             icrc3_id: icrc3_canister_id,
+            external_archives,
             pic,
         }
     }
+
+    /// Returns the init arguments the ICRC3 canister is installed with.
+    pub fn icrc3_init_args(&self, external_archives: Vec<CanisterId>) -> Args {
+        Args::Init(icrc3_example_api::init::InitArgs {
+            test_mode: true,
+            version: BuildVersion::min(),
+            commit_hash: "".to_string(),
+            authorized_principals: vec![self.controller],
+            icrc3_config: ICRC3Config {
+                supported_blocks: self
+                    .supported_block_types
+                    .iter()
+                    .map(|block_type| SupportedBlockType {
+                        block_type: block_type.clone(),
+                        url: "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-3/README.md#supported-block-types".to_string(),
+                    })
+                    .collect(),
+                constants: self.icrc3_constants.clone(),
+                funding_config: self.icrc3_funding_config.clone(),
+                block_transform: None,
+                commit_hash: None,
+                archive_test_mode: self.archive_test_mode,
+                custom_block_types: vec![],
+                external_archives,
+                genesis_parent_hash: None,
+                genesis_parent_ref: None,
+                validation_modes: BTreeMap::new(),
+            },
+        })
+    }
 }
@@ -1,3 +1,7 @@
+use bity_ic_icrc3::blockchain::archive_canister_manager::ARCHIVE_WASM;
+use bity_ic_icrc3_archive_api::archive_config::ArchiveConfig;
+use bity_ic_icrc3_archive_api::lifecycle::BlockType;
+use bity_ic_types::BuildVersion;
 use candid::encode_one;
 use candid::Principal;
 use pocket_ic::PocketIc;
@@ -26,6 +30,58 @@ pub fn setup_icrc3_canister(
     icrc3_id
 }
 
+/// Installs an archive canister the ICRC3 canister can archive into from
+/// `block_offset`, as governance would for an external archive.
+pub fn setup_external_archive(
+    pic: &mut PocketIc,
+    icrc3_id: Principal,
+    block_offset: u64,
+    controller: Principal,
+) -> Principal {
+    let archive_id = pic.create_canister_with_settings(Some(controller), None);
+    pic.add_cycles(archive_id, 100_000_000_000_000_000);
+
+    let args = bity_ic_icrc3_archive_api::Args::Init(bity_ic_icrc3_archive_api::init::InitArgs {
+        test_mode: true,
+        version: BuildVersion::min(),
+        commit_hash: "".to_string(),
+        authorized_principals: vec![icrc3_id],
+        archive_config: ArchiveConfig {
+            block_offset,
+            ..ArchiveConfig::default()
+        },
+        master_canister_id: icrc3_id,
+        block_type: BlockType::Default,
+    });
+    pic.install_canister(
+        archive_id,
+        ARCHIVE_WASM.to_vec(),
+        encode_one(args).unwrap(),
+        Some(controller),
+    );
+
+    println!("setup done : external archive_id: {:?}", archive_id);
+    archive_id
+}
+
+/// Reinstalls the ICRC3 canister, wiping its state as if it was lost.
+pub fn reinstall_icrc3_canister(
+    pic: &mut PocketIc,
+    icrc3_canister_id: Principal,
+    args: icrc3_example_api::Args,
+    controller: Principal,
+) {
+    let icrc3_wasm = include_bytes!("../../../../wasm/icrc3_example_canister.wasm.gz").to_vec();
+
+    pic.reinstall_canister(
+        icrc3_canister_id,
+        icrc3_wasm,
+        encode_one(args).unwrap(),
+        Some(controller),
+    )
+    .unwrap();
+}
+
 pub fn upgrade_icrc3_canister(
     pic: &mut PocketIc,
     icrc3_canister_id: Principal,
@@ -2,3 +2,50 @@ pub mod test_insert_transaction;
 pub mod test_migration;
 pub mod test_predefined_blocks;
 pub mod test_icrc3_hashing;
+pub mod test_archive_funding;
+pub mod test_job_history;
+pub mod test_msgpack_endpoints;
+pub mod test_archive_controllers;
+pub mod test_transaction_limits;
+pub mod test_archived_blocks_grouping;
+pub mod test_archive_insert_idempotency;
+pub mod test_duplicate_of_archived;
+pub mod test_archive_snapshot;
+pub mod test_chain_length_and_has_block;
+pub mod test_archive_chaos;
+pub mod test_get_tip;
+pub mod test_recorders;
+pub mod test_get_blocks_bounds;
+pub mod test_http_logs;
+pub mod test_archive_real_mode;
+pub mod test_created_at_time;
+pub mod test_upgrade_certificate;
+pub mod test_archive_verification;
+pub mod test_archive_retirement;
+pub mod test_block_schemas;
+pub mod test_memo_index;
+pub mod test_verifier;
+pub mod test_archive_reconciliation;
+pub mod test_fault_injection;
+pub mod test_random_pool;
+pub mod test_log_length;
+pub mod test_archive_lifecycle_args;
+pub mod test_admin_audit;
+pub mod test_archive_rollover;
+pub mod test_blocks_http;
+pub mod test_archive_reinstall;
+pub mod test_block_ids;
+pub mod test_find_block_by_thash;
+pub mod test_timers;
+pub mod test_external_archives;
+pub mod test_rebuild_from_archives;
+pub mod test_local_archive_full;
+pub mod test_notifications;
+pub mod test_legacy_transactions;
+pub mod test_prepared_transactions;
+pub mod test_adaptive_archive_batches;
+pub mod test_tip_certificate;
+pub mod test_archive_stats;
+pub mod test_archive_guards;
+pub mod test_timestamp_ordering;
+pub mod test_upgrade_from_baseline;
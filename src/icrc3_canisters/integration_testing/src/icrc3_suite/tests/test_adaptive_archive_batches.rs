@@ -0,0 +1,65 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::setup::TestEnvBuilder;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::MINUTE_IN_MS;
+use bity_ic_icrc3::config::ICRC3Properties;
+use bity_ic_icrc3::job_history::JobKind;
+use serde_bytes::ByteBuf;
+use std::time::Duration;
+
+const LARGE_MEMO_BYTES: usize = 400_000;
+
+#[test]
+fn test_mixed_block_sizes_are_archived_without_payload_failures() {
+    let mut test_env = TestEnvBuilder::new();
+    test_env.icrc3_constants = ICRC3Properties {
+        threshold_for_archiving_to_external_archive: Some(10),
+        max_transaction_size_bytes: 2 * LARGE_MEMO_BYTES as u128,
+        max_memo_size_bytes: Some(LARGE_MEMO_BYTES as u128),
+        ..ICRC3Properties::default()
+    };
+    let mut test_env = test_env.build();
+
+    // Small blocks, then blocks large enough that a few of them fill a batch.
+    for i in 0..60 {
+        let mut transaction =
+            create_transactions(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+        let memo_bytes = if i < 40 { 8 } else { LARGE_MEMO_BYTES };
+        transaction.tx.memo = Some(ByteBuf::from(vec![i as u8; memo_bytes]));
+        add_created_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &transaction,
+        )
+        .unwrap();
+        test_env.pic.advance_time(Duration::from_millis(200));
+    }
+
+    for _ in 0..6 {
+        test_env
+            .pic
+            .advance_time(Duration::from_millis(11 * MINUTE_IN_MS));
+        tick_n_blocks(&test_env.pic, 20);
+    }
+
+    let archive_runs: Vec<_> =
+        icrc3_job_history(&test_env.pic, test_env.controller, test_env.icrc3_id, &())
+            .into_iter()
+            .filter(|r| r.job == JobKind::Archive)
+            .collect();
+    assert!(!archive_runs.is_empty());
+    for run in &archive_runs {
+        assert!(run.outcome.is_ok(), "archive run failed: {:?}", run.outcome);
+        assert!(run.archive_batch_size.unwrap() >= 1);
+    }
+    let archived: u128 = archive_runs
+        .iter()
+        .map(|r| *r.outcome.as_ref().unwrap())
+        .sum();
+    assert!(archived > 40, "only {archived} blocks were archived");
+
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert!(!archives.is_empty());
+}
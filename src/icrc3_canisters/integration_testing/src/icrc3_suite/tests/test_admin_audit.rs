@@ -0,0 +1,98 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::setup::TestEnvBuilder;
+
+use bity_ic_icrc3::audit::{principal_value, ADMIN_BLOCK_TYPE};
+use bity_ic_icrc3::config::{FundingConfig, ICRC3Properties};
+use candid::Nat;
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+
+#[test]
+fn test_config_change_is_recorded_as_admin_block() {
+    let mut test_env = TestEnvBuilder::new();
+    test_env.icrc3_constants = ICRC3Properties {
+        audit_admin_actions: true,
+        ..ICRC3Properties::default()
+    };
+    let mut test_env = test_env.build();
+
+    assert!(icrc3_supported_block_types(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &()
+    )
+    .iter()
+    .any(|b| b.block_type == ADMIN_BLOCK_TYPE));
+    assert!(
+        icrc3_block_schemas(&test_env.pic, test_env.controller, test_env.icrc3_id, &())
+            .iter()
+            .any(|s| s.btype == ADMIN_BLOCK_TYPE && !s.fields.is_empty())
+    );
+
+    let funding_config = FundingConfig {
+        fund_cycles: 3_000_000_000_000,
+        ..FundingConfig::default()
+    };
+    let result = update_funding_config(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &funding_config,
+    );
+    assert_eq!(result, Ok(()));
+
+    let blocks = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &vec![GetBlocksRequest {
+            start: Nat::from(0u64),
+            length: Nat::from(10u64),
+        }],
+    )
+    .blocks;
+    assert_eq!(blocks.len(), 1);
+    let ICRC3Value::Map(block) = &blocks[0].block else {
+        panic!("a block is a map");
+    };
+    assert_eq!(
+        block.get("btype"),
+        Some(&ICRC3Value::Text(ADMIN_BLOCK_TYPE.to_string()))
+    );
+    let Some(ICRC3Value::Map(tx)) = block.get("tx") else {
+        panic!("an admin block has a tx map");
+    };
+    assert_eq!(
+        tx.get("action"),
+        Some(&ICRC3Value::Text("update_funding_config".to_string()))
+    );
+    assert_eq!(
+        tx.get("caller"),
+        Some(&principal_value(test_env.controller))
+    );
+    let Some(ICRC3Value::Map(details)) = tx.get("details") else {
+        panic!("an admin block has a details map");
+    };
+    assert_eq!(
+        details.get("fund_cycles"),
+        Some(&ICRC3Value::Nat(Nat::from(3_000_000_000_000u128)))
+    );
+}
+
+#[test]
+fn test_admin_actions_are_not_recorded_by_default() {
+    let mut test_env = TestEnvBuilder::new().build();
+
+    let result = update_funding_config(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &FundingConfig::default(),
+    );
+    assert_eq!(result, Ok(()));
+    assert_eq!(
+        icrc3_chain_length(&test_env.pic, test_env.controller, test_env.icrc3_id, &()),
+        Nat::from(0u64)
+    );
+}
@@ -0,0 +1,158 @@
+use crate::client::icrc3::*;
+use crate::client::icrc3_archive::total_transactions;
+use crate::icrc3_suite::chaos::{
+    archives_by_start, assert_blocks_cover_chain, ChaosAction, ChaosScenario,
+};
+use crate::icrc3_suite::setup::default_test_setup_with_archive;
+use crate::icrc3_suite::setup::setup::{TestEnv, TestEnvBuilder};
+use crate::utils::T;
+
+use bity_ic_canister_time::{DAY_IN_MS, MINUTE_IN_MS};
+use bity_ic_icrc3::config::{FundingConfig, ICRC3Properties};
+use bity_ic_icrc3::job_history::{JobKind, JobRunRecord};
+use candid::Nat;
+use std::time::Duration;
+
+/// Enough time for the archive job, which runs every 10 minutes.
+const ARCHIVE_JOB_INTERVAL: Duration = Duration::from_millis(11 * MINUTE_IN_MS);
+
+fn last_archive_run(test_env: &TestEnv) -> JobRunRecord {
+    icrc3_job_history(&test_env.pic, test_env.controller, test_env.icrc3_id, &())
+        .into_iter()
+        .rev()
+        .find(|r| r.job == JobKind::Archive)
+        .expect("no archive job run recorded")
+}
+
+fn chain_length(test_env: &TestEnv) -> u64 {
+    icrc3_chain_length(&test_env.pic, test_env.controller, test_env.icrc3_id, &())
+        .0
+        .try_into()
+        .unwrap()
+}
+
+/// Adds transactions until a first archive is created.
+fn with_first_archive(seed: u64) -> ChaosScenario {
+    ChaosScenario::new(seed)
+        .then(ChaosAction::AddTransactions(10))
+        .then(ChaosAction::AdvanceTime(Duration::from_millis(
+            DAY_IN_MS * 2,
+        )))
+}
+
+#[test]
+fn test_archive_stopped_during_archive_job() {
+    let mut test_env = default_test_setup_with_archive();
+
+    with_first_archive(1).run(&mut test_env);
+    assert_eq!(archives_by_start(&test_env).len(), 1);
+
+    ChaosScenario::new(2)
+        .then(ChaosAction::StopArchive(0))
+        .then(ChaosAction::AddTransactions(10))
+        .then(ChaosAction::AdvanceTime(ARCHIVE_JOB_INTERVAL))
+        .run(&mut test_env);
+    assert!(last_archive_run(&test_env).outcome.is_err());
+
+    ChaosScenario::new(3)
+        .then(ChaosAction::StartArchive(0))
+        .then(ChaosAction::AdvanceTime(ARCHIVE_JOB_INTERVAL))
+        .run(&mut test_env);
+    assert!(last_archive_run(&test_env).outcome.is_ok());
+
+    // Every block is stored once, either locally or in the archive.
+    let archives = archives_by_start(&test_env);
+    assert_eq!(archives.len(), 1);
+    let local = assert_blocks_cover_chain(&test_env);
+    let archived = total_transactions(
+        &test_env.pic,
+        test_env.icrc3_id,
+        archives[0].canister_id,
+        &(),
+    ) as u64;
+    assert_eq!(archived + local, chain_length(&test_env));
+}
+
+#[test]
+fn test_archive_out_of_cycles_is_topped_up() {
+    let mut test_env = TestEnvBuilder::new();
+    test_env.icrc3_constants = ICRC3Properties {
+        max_tx_local_stable_memory_size_bytes: Some(5000),
+        threshold_for_archiving_to_external_archive: Some(10),
+        max_transactions_in_window: 10_u64.into(),
+        ..ICRC3Properties::default()
+    };
+    test_env.icrc3_funding_config = Some(FundingConfig {
+        interval_secs: 60,
+        min_cycles: T as u128,
+        fund_cycles: T as u128,
+        initial_cycles: 3 * T as u128,
+        reserved_cycles: 3 * T as u128,
+        retired_min_cycles: None,
+    });
+    let mut test_env = test_env.build();
+
+    with_first_archive(4).run(&mut test_env);
+    let archive_id = archives_by_start(&test_env)[0].canister_id;
+    let archived_before = total_transactions(&test_env.pic, test_env.icrc3_id, archive_id, &());
+
+    ChaosScenario::new(5)
+        .then(ChaosAction::DrainArchiveCycles {
+            archive: 0,
+            remaining: T as u128 / 10,
+        })
+        .then(ChaosAction::AdvanceTime(Duration::from_secs(120)))
+        .run(&mut test_env);
+
+    // The fund manager topped the archive up.
+    assert!(test_env.pic.cycle_balance(archive_id) > T as u128);
+
+    ChaosScenario::new(6)
+        .then(ChaosAction::AddTransactions(10))
+        .then(ChaosAction::AdvanceTime(ARCHIVE_JOB_INTERVAL))
+        .run(&mut test_env);
+    assert!(last_archive_run(&test_env).outcome.is_ok());
+    assert!(
+        total_transactions(&test_env.pic, test_env.icrc3_id, archive_id, &()) > archived_before
+    );
+    assert_blocks_cover_chain(&test_env);
+}
+
+#[test]
+fn test_uninstalled_archive_is_replaced() {
+    let mut test_env = default_test_setup_with_archive();
+
+    with_first_archive(7).run(&mut test_env);
+    let lost_archive = archives_by_start(&test_env)[0].clone();
+
+    ChaosScenario::new(8)
+        .then(ChaosAction::UninstallArchive(0))
+        .then(ChaosAction::AddTransactions(10))
+        .then(ChaosAction::AdvanceTime(ARCHIVE_JOB_INTERVAL))
+        .run(&mut test_env);
+    assert!(last_archive_run(&test_env).outcome.is_ok());
+
+    // The replacement takes over right after the range of the lost archive.
+    let archives = archives_by_start(&test_env);
+    assert_eq!(archives.len(), 2);
+    assert_eq!(archives[0].canister_id, lost_archive.canister_id);
+    assert_ne!(archives[1].canister_id, lost_archive.canister_id);
+    assert_eq!(
+        archives[1].start,
+        lost_archive.end.clone() + Nat::from(1u64)
+    );
+
+    let local = assert_blocks_cover_chain(&test_env);
+    let replacement_blocks = total_transactions(
+        &test_env.pic,
+        test_env.icrc3_id,
+        archives[1].canister_id,
+        &(),
+    ) as u64;
+    let lost_start: u64 = lost_archive.start.0.try_into().unwrap();
+    let lost_end: u64 = lost_archive.end.0.try_into().unwrap();
+    assert_eq!(
+        (lost_end - lost_start + 1) + replacement_blocks + local,
+        chain_length(&test_env)
+    );
+}
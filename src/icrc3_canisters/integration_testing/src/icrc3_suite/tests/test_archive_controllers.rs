@@ -0,0 +1,77 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::default_test_setup_with_archive;
+use crate::utils::{random_principal, tick_n_blocks};
+
+use bity_ic_canister_time::DAY_IN_MS;
+use icrc3_example_api::add_archive_controller::Args as ArchiveControllerArgs;
+use std::time::Duration;
+
+#[test]
+fn test_archive_controller_management() {
+    let mut test_env = default_test_setup_with_archive();
+
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+    let archive_id = archives[0].canister_id;
+
+    let new_controller = random_principal();
+    let controllers = add_archive_controller(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &ArchiveControllerArgs {
+            canister_id: archive_id,
+            controller: new_controller,
+        },
+    )
+    .unwrap();
+    assert!(controllers.contains(&new_controller));
+
+    let status = test_env
+        .pic
+        .canister_status(archive_id, Some(test_env.icrc3_id))
+        .unwrap();
+    assert!(status.settings.controllers.contains(&new_controller));
+    assert!(status.settings.controllers.contains(&test_env.icrc3_id));
+
+    // The ICRC3 canister must not be able to remove itself.
+    let result = remove_archive_controller(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &ArchiveControllerArgs {
+            canister_id: archive_id,
+            controller: test_env.icrc3_id,
+        },
+    );
+    assert!(result.is_err());
+
+    let controllers = remove_archive_controller(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &ArchiveControllerArgs {
+            canister_id: archive_id,
+            controller: new_controller,
+        },
+    )
+    .unwrap();
+    assert!(!controllers.contains(&new_controller));
+    assert!(controllers.contains(&test_env.icrc3_id));
+}
@@ -0,0 +1,132 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::setup::TestEnvBuilder;
+use crate::utils::{tick_n_blocks, T};
+
+use bity_ic_canister_time::DAY_IN_MS;
+use bity_ic_icrc3::blockchain::archive_canister_manager::FundingAlertReason;
+use bity_ic_icrc3::config::{FundingConfig, ICRC3Properties};
+use std::time::Duration;
+
+#[test]
+fn test_archive_created_with_configured_initial_cycles() {
+    let mut test_env = TestEnvBuilder::new();
+
+    test_env.icrc3_constants = ICRC3Properties {
+        max_tx_local_stable_memory_size_bytes: Some(5000),
+        threshold_for_archiving_to_external_archive: Some(10),
+        max_transactions_in_window: 10_u64.into(),
+        ..ICRC3Properties::default()
+    };
+    test_env.icrc3_funding_config = Some(FundingConfig {
+        interval_secs: 60,
+        min_cycles: T as u128,
+        fund_cycles: T as u128,
+        initial_cycles: 3 * T as u128,
+        reserved_cycles: 3 * T as u128,
+        retired_min_cycles: None,
+    });
+
+    let mut test_env = test_env.build();
+
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 50);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &(),
+    );
+    assert_eq!(archives.len(), 1);
+
+    let status = test_env
+        .pic
+        .canister_status(archives[0].canister_id, Some(test_env.icrc3_id))
+        .expect("canister_status failed");
+
+    // The archive is created with the configured 3T cycles instead of the 5T default,
+    // minus what was burnt installing the wasm and storing the blocks.
+    let cycles: u128 = status.cycles.0.try_into().unwrap();
+    assert!(cycles <= 3 * T as u128);
+    assert!(cycles > 2 * T as u128);
+}
+
+#[test]
+fn test_archive_below_threshold_is_reported() {
+    let mut test_env = TestEnvBuilder::new();
+
+    test_env.icrc3_constants = ICRC3Properties {
+        max_tx_local_stable_memory_size_bytes: Some(5000),
+        threshold_for_archiving_to_external_archive: Some(10),
+        max_transactions_in_window: 10_u64.into(),
+        ..ICRC3Properties::default()
+    };
+    // The archive is created well below the threshold and is not topped up
+    // within the test.
+    test_env.icrc3_funding_config = Some(FundingConfig {
+        interval_secs: 365 * DAY_IN_MS / 1000,
+        min_cycles: 10 * T as u128,
+        fund_cycles: T as u128,
+        initial_cycles: 3 * T as u128,
+        reserved_cycles: 3 * T as u128,
+        retired_min_cycles: None,
+    });
+
+    let mut test_env = test_env.build();
+
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 50);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &(),
+    );
+    assert_eq!(archives.len(), 1);
+    let archive_id = archives[0].canister_id;
+
+    let alerts = check_archive_funding(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &(),
+    )
+    .unwrap();
+    let alert = alerts
+        .iter()
+        .find(|alert| alert.canister_id == archive_id)
+        .expect("no alert for the archive");
+    assert_eq!(alert.reason, FundingAlertReason::BelowThreshold);
+    assert_eq!(alert.min_cycles, Some(10 * T as u128));
+    assert!(alert.cycles.unwrap() < 3 * T as u128);
+    assert!(alert.master_cycles.is_some());
+}
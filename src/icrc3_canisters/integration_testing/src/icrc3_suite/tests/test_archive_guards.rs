@@ -0,0 +1,65 @@
+use crate::client::icrc3::*;
+use crate::client::icrc3_archive::total_transactions;
+use crate::icrc3_suite::setup::default_test_setup_with_archive;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_icrc3_archive_api::insert_blocks;
+use candid::Principal;
+use std::time::Duration;
+
+#[test]
+fn test_archive_rejects_unauthorized_callers() {
+    let mut test_env = default_test_setup_with_archive();
+
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2 * 60));
+        tick_n_blocks(&test_env.pic, 50);
+    }
+    test_env.pic.advance_time(Duration::from_secs(10 * 60));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    let archive_id = archives[0].canister_id;
+    let archived = total_transactions(&test_env.pic, test_env.controller, archive_id, &());
+    assert!(archived > 0);
+
+    // The main canister is the only authorized principal of its archives.
+    let intruder = Principal::from_slice(&[9, 9, 9]);
+    for (method, payload, expected_error) in [
+        (
+            "insert_blocks",
+            candid::encode_one(insert_blocks::Args {
+                first_block_id: archived as u64,
+                blocks: vec![],
+            })
+            .unwrap(),
+            format!("Caller {intruder} is not an authorized principal"),
+        ),
+        (
+            "corrupt_block",
+            candid::encode_one(0u64).unwrap(),
+            "Caller is not an admin principal".to_string(),
+        ),
+    ] {
+        let error = test_env
+            .pic
+            .update_call(archive_id, intruder, method, payload)
+            .expect_err("an unauthorized caller is rejected");
+        assert!(
+            error.reject_message.contains(&expected_error),
+            "{method}: {}",
+            error.reject_message
+        );
+    }
+
+    assert_eq!(
+        total_transactions(&test_env.pic, test_env.controller, archive_id, &()),
+        archived
+    );
+}
@@ -0,0 +1,135 @@
+use crate::client::icrc3::*;
+use crate::client::icrc3_archive::{insert_blocks, total_transactions};
+use crate::icrc3_suite::setup::default_test_setup_with_archive;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::DAY_IN_MS;
+use bity_ic_icrc3_archive_api::insert_blocks::{
+    Args as InsertBlocksArgs, InsertBlocksError, InsertBlocksSuccess,
+};
+use bity_ic_icrc3_archive_api::types::encoded_blocks::EncodedBlock;
+use std::time::Duration;
+
+fn blocks(count: u8) -> Vec<EncodedBlock> {
+    (0..count)
+        .map(|i| EncodedBlock {
+            block: vec![0xab, i],
+        })
+        .collect()
+}
+
+#[test]
+fn test_insert_blocks_replay_is_idempotent() {
+    let mut test_env = default_test_setup_with_archive();
+
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+    let archive_id = archives[0].canister_id;
+    let block_offset: u64 = archives[0].start.0.clone().try_into().unwrap();
+
+    let stored = total_transactions(&test_env.pic, test_env.icrc3_id, archive_id, &()) as u64;
+    let next_block_id = block_offset + stored;
+
+    let batch = InsertBlocksArgs {
+        first_block_id: next_block_id,
+        blocks: blocks(3),
+    };
+
+    let first = insert_blocks(&mut test_env.pic, test_env.icrc3_id, archive_id, &batch);
+    let remaining_capacity = first
+        .as_ref()
+        .ok()
+        .and_then(|success| success.remaining_capacity.clone())
+        .expect("the archive reports its remaining capacity");
+    assert_eq!(
+        first,
+        Ok(InsertBlocksSuccess {
+            already_present: false,
+            inserted: 3,
+            next_block_id: next_block_id + 3,
+            remaining_capacity: Some(remaining_capacity.clone()),
+        })
+    );
+    let total = total_transactions(&test_env.pic, test_env.icrc3_id, archive_id, &());
+    assert_eq!(total as u64, stored + 3);
+
+    // Replaying the same batch does not store it twice.
+    let second = insert_blocks(&mut test_env.pic, test_env.icrc3_id, archive_id, &batch);
+    assert_eq!(
+        second,
+        Ok(InsertBlocksSuccess {
+            already_present: true,
+            inserted: 0,
+            next_block_id: next_block_id + 3,
+            remaining_capacity: Some(remaining_capacity.clone()),
+        })
+    );
+    assert_eq!(
+        total_transactions(&test_env.pic, test_env.icrc3_id, archive_id, &()),
+        total
+    );
+
+    // A partially stored batch only appends the missing suffix.
+    let overlapping = insert_blocks(
+        &mut test_env.pic,
+        test_env.icrc3_id,
+        archive_id,
+        &InsertBlocksArgs {
+            first_block_id: next_block_id + 2,
+            blocks: blocks(3),
+        },
+    );
+    assert_eq!(
+        overlapping,
+        Ok(InsertBlocksSuccess {
+            already_present: false,
+            inserted: 2,
+            next_block_id: next_block_id + 5,
+            remaining_capacity: Some(
+                remaining_capacity
+                    - blocks(3)[1..]
+                        .iter()
+                        .map(|block| block.stored_size_bytes())
+                        .sum::<usize>()
+            ),
+        })
+    );
+
+    // A batch leaving a gap is rejected.
+    let gap = insert_blocks(
+        &mut test_env.pic,
+        test_env.icrc3_id,
+        archive_id,
+        &InsertBlocksArgs {
+            first_block_id: next_block_id + 10,
+            blocks: blocks(1),
+        },
+    );
+    assert_eq!(
+        gap,
+        Err(InsertBlocksError::NonContiguous {
+            expected: next_block_id + 5,
+            got: next_block_id + 10,
+        })
+    );
+    assert_eq!(
+        total_transactions(&test_env.pic, test_env.icrc3_id, archive_id, &()) as u64,
+        stored + 5
+    );
+}
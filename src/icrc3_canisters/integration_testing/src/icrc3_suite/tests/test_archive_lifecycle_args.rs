@@ -0,0 +1,175 @@
+use crate::client::icrc3_archive::get_version;
+use crate::icrc3_suite::setup::default_test_setup;
+
+use bity_ic_icrc3_archive_api::archive_config::ArchiveConfig;
+use bity_ic_icrc3_archive_api::init::InitArgs;
+use bity_ic_icrc3_archive_api::lifecycle::{Args, BlockType};
+use bity_ic_icrc3_archive_api::post_upgrade::UpgradeArgs;
+use bity_ic_types::BuildVersion;
+use candid::{encode_one, Principal};
+use pocket_ic::PocketIc;
+
+fn archive_wasm() -> Vec<u8> {
+    include_bytes!("../../../../wasm/icrc3_archive_canister.wasm.gz").to_vec()
+}
+
+fn create_archive_canister(pic: &PocketIc, controller: Principal) -> Principal {
+    let archive_id = pic.create_canister();
+    pic.add_cycles(archive_id, 100_000_000_000_000);
+    pic.set_controllers(archive_id, None, vec![controller])
+        .unwrap();
+    archive_id
+}
+
+fn init_args(controller: Principal) -> InitArgs {
+    InitArgs {
+        test_mode: true,
+        version: BuildVersion::min(),
+        commit_hash: "commit_hash".to_string(),
+        authorized_principals: vec![controller],
+        archive_config: ArchiveConfig::default(),
+        master_canister_id: controller,
+        block_type: BlockType::Default,
+    }
+}
+
+fn upgrade_args(version: BuildVersion, commit_hash: &str) -> Args {
+    Args::Upgrade(UpgradeArgs {
+        version,
+        commit_hash: commit_hash.to_string(),
+        block_type: BlockType::Default,
+    })
+}
+
+#[test]
+fn test_invalid_init_args_fail_the_install() {
+    let test_env = default_test_setup();
+    let pic = &test_env.pic;
+    let archive_id = create_archive_canister(pic, test_env.controller);
+
+    let invalid = [
+        (
+            Args::Init(InitArgs {
+                commit_hash: String::new(),
+                ..init_args(test_env.controller)
+            }),
+            "commit_hash is empty",
+        ),
+        (
+            Args::Init(InitArgs {
+                test_mode: false,
+                authorized_principals: vec![],
+                ..init_args(test_env.controller)
+            }),
+            "authorized_principals is empty",
+        ),
+        (
+            upgrade_args(BuildVersion::min(), "commit_hash"),
+            "Cannot initialize the canister with an Upgrade argument",
+        ),
+    ];
+    for (args, expected) in invalid {
+        // Reinstalling an empty canister installs it.
+        let error = pic
+            .reinstall_canister(
+                archive_id,
+                archive_wasm(),
+                encode_one(args).unwrap(),
+                Some(test_env.controller),
+            )
+            .expect_err("the install did not fail");
+        assert!(
+            error
+                .reject_message
+                .contains("icrc3_archive 0.4.0: invalid init arguments:"),
+            "{}",
+            error.reject_message
+        );
+        assert!(
+            error.reject_message.contains(expected),
+            "{}",
+            error.reject_message
+        );
+    }
+
+    // Nothing is left installed by the failed attempts.
+    assert!(pic
+        .canister_status(archive_id, Some(test_env.controller))
+        .unwrap()
+        .module_hash
+        .is_none());
+
+    pic.install_canister(
+        archive_id,
+        archive_wasm(),
+        encode_one(Args::Init(init_args(test_env.controller))).unwrap(),
+        Some(test_env.controller),
+    );
+    assert_eq!(
+        get_version(pic, test_env.controller, archive_id, &()),
+        BuildVersion::min()
+    );
+}
+
+#[test]
+fn test_invalid_upgrade_args_keep_the_archive() {
+    let test_env = default_test_setup();
+    let pic = &test_env.pic;
+    let archive_id = create_archive_canister(pic, test_env.controller);
+    pic.install_canister(
+        archive_id,
+        archive_wasm(),
+        encode_one(Args::Init(init_args(test_env.controller))).unwrap(),
+        Some(test_env.controller),
+    );
+
+    let invalid = [
+        (
+            Args::Init(init_args(test_env.controller)),
+            "Cannot upgrade the canister with an Init argument",
+        ),
+        (
+            upgrade_args(BuildVersion::new(0, 0, 2), ""),
+            "commit_hash is empty",
+        ),
+    ];
+    for (args, expected) in invalid {
+        let error = pic
+            .upgrade_canister(
+                archive_id,
+                archive_wasm(),
+                encode_one(args).unwrap(),
+                Some(test_env.controller),
+            )
+            .expect_err("the upgrade did not fail");
+        assert!(
+            error
+                .reject_message
+                .contains("icrc3_archive 0.4.0: invalid post_upgrade arguments:"),
+            "{}",
+            error.reject_message
+        );
+        assert!(
+            error.reject_message.contains(expected),
+            "{}",
+            error.reject_message
+        );
+        // The failed upgrade is rolled back.
+        assert_eq!(
+            get_version(pic, test_env.controller, archive_id, &()),
+            BuildVersion::min()
+        );
+    }
+
+    pic.upgrade_canister(
+        archive_id,
+        archive_wasm(),
+        encode_one(upgrade_args(BuildVersion::new(0, 0, 2), "commit_hash 2")).unwrap(),
+        Some(test_env.controller),
+    )
+    .unwrap();
+    assert_eq!(
+        get_version(pic, test_env.controller, archive_id, &()),
+        BuildVersion::new(0, 0, 2)
+    );
+}
@@ -0,0 +1,41 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::default_test_setup_with_archive;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::DAY_IN_MS;
+use std::time::Duration;
+
+/// The ICRC3 canister is not in test mode, so its archives are real canisters
+/// created and installed through the management canister.
+#[test]
+fn test_archives_are_real_canisters() {
+    let mut test_env = default_test_setup_with_archive();
+
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+    let archive_id = archives[0].canister_id;
+
+    assert!(test_env.pic.canister_exists(archive_id));
+    let status = test_env
+        .pic
+        .canister_status(archive_id, Some(test_env.icrc3_id))
+        .unwrap();
+    assert!(status.module_hash.is_some());
+    assert!(status.settings.controllers.contains(&test_env.icrc3_id));
+}
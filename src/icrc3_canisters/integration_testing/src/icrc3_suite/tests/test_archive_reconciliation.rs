@@ -0,0 +1,110 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::default_test_setup_with_archive;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::DAY_IN_MS;
+use bity_ic_icrc3::blockchain::archive_canister_manager::{DivergenceKind, SuggestedAction};
+use icrc3_example_api::reconcile_archives::Args as ReconcileArchivesArgs;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+// A valid wasm module exporting no methods.
+const EMPTY_WASM: &[u8] = b"\0asm\x01\0\0\0";
+
+#[test]
+fn test_reconcile_reports_and_adopts_out_of_band_upgrade() {
+    let mut test_env = default_test_setup_with_archive();
+
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+    let archive_id = archives[0].canister_id;
+
+    let report = reconcile_archives(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &ReconcileArchivesArgs { auto_adopt: false },
+    )
+    .unwrap();
+    assert_eq!(report.checked, 1);
+    assert!(report.divergences.is_empty(), "{:?}", report.divergences);
+
+    // Upgrade the archive behind the manager's back, as with dfx.
+    test_env
+        .pic
+        .upgrade_canister(
+            archive_id,
+            EMPTY_WASM.to_vec(),
+            vec![],
+            Some(test_env.icrc3_id),
+        )
+        .unwrap();
+    let manual_hash = Sha256::digest(EMPTY_WASM).to_vec();
+
+    let report = reconcile_archives(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &ReconcileArchivesArgs { auto_adopt: false },
+    )
+    .unwrap();
+    assert_eq!(report.divergences.len(), 1);
+    let divergence = &report.divergences[0];
+    assert_eq!(divergence.canister_id, archive_id);
+    assert_eq!(divergence.action, SuggestedAction::Investigate);
+    assert!(!divergence.adopted);
+    assert_eq!(
+        divergence.kind,
+        DivergenceKind::ModuleHash {
+            recorded: Some(report.wasm_hash.clone()),
+            actual: Some(manual_hash.clone()),
+        }
+    );
+
+    let report = reconcile_archives(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &ReconcileArchivesArgs { auto_adopt: true },
+    )
+    .unwrap();
+    assert_eq!(report.divergences.len(), 1);
+    assert!(report.divergences[0].adopted);
+
+    // The records now match the archive, which only remains to be upgraded.
+    let report = reconcile_archives(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &ReconcileArchivesArgs { auto_adopt: false },
+    )
+    .unwrap();
+    assert_eq!(report.divergences.len(), 1);
+    assert_eq!(
+        report.divergences[0].action,
+        SuggestedAction::ScheduleUpgrade
+    );
+    assert_eq!(
+        report.divergences[0].kind,
+        DivergenceKind::ModuleHash {
+            recorded: Some(manual_hash.clone()),
+            actual: Some(manual_hash),
+        }
+    );
+}
@@ -0,0 +1,125 @@
+use crate::client::icrc3::*;
+use crate::client::icrc3_archive::total_transactions;
+use crate::icrc3_suite::setup::default_test_setup_with_archive;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::DAY_IN_MS;
+use bity_ic_icrc3::blockchain::archive_canister_manager::ReinstallConfirmation;
+use candid::{CandidType, Principal};
+use std::time::Duration;
+
+/// A confirmation as an operator could forge it, with the wrong phrase.
+#[derive(CandidType)]
+struct ForgedConfirmation {
+    canister_id: Principal,
+    phrase: String,
+}
+
+#[test]
+fn test_reinstall_unrecoverable_archive() {
+    let mut test_env = default_test_setup_with_archive();
+
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+    let archive_id = archives[0].canister_id;
+    let archived = total_transactions(&test_env.pic, test_env.controller, archive_id, &());
+    assert!(archived > 0);
+    let confirmation = ReinstallConfirmation::confirm_data_loss(archive_id);
+
+    // The archive must be retired, then marked unrecoverable.
+    assert!(mark_archive_unrecoverable(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &archive_id
+    )
+    .is_err());
+    assert!(reinstall_archive(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &confirmation
+    )
+    .is_err());
+    assert_eq!(
+        retire_archive(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &archive_id
+        ),
+        Ok(())
+    );
+    assert!(reinstall_archive(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &confirmation
+    )
+    .is_err());
+    assert_eq!(
+        mark_archive_unrecoverable(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &archive_id
+        ),
+        Ok(())
+    );
+
+    // A confirmation without the exact phrase is rejected.
+    let response = test_env
+        .pic
+        .update_call(
+            test_env.icrc3_id,
+            test_env.controller,
+            "reinstall_archive",
+            candid::encode_one(ForgedConfirmation {
+                canister_id: archive_id,
+                phrase: "yes".to_string(),
+            })
+            .unwrap(),
+        )
+        .unwrap();
+    let response: Result<(), String> = candid::decode_one(&response).unwrap();
+    assert!(response.unwrap_err().contains("I-UNDERSTAND-DATA-LOSS"));
+    assert_eq!(
+        total_transactions(&test_env.pic, test_env.controller, archive_id, &()),
+        archived
+    );
+
+    assert_eq!(
+        reinstall_archive(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &confirmation
+        ),
+        Ok(())
+    );
+
+    // The archive is running again, empty.
+    assert_eq!(
+        total_transactions(&test_env.pic, test_env.controller, archive_id, &()),
+        0
+    );
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert!(archives
+        .iter()
+        .any(|archive| archive.canister_id == archive_id));
+}
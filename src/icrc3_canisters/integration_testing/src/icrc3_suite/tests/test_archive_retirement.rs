@@ -0,0 +1,100 @@
+use crate::client::icrc3::*;
+use crate::client::icrc3_archive::total_transactions;
+use crate::icrc3_suite::setup::default_test_setup_with_archive;
+use crate::icrc3_suite::setup::setup::TestEnv;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::DAY_IN_MS;
+use candid::Principal;
+use std::time::Duration;
+
+/// Adds transactions and waits for the archive job to move them to the archives.
+fn add_transactions_and_archive(test_env: &mut TestEnv) {
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+}
+
+#[test]
+fn test_retired_archive_stops_receiving_blocks() {
+    let mut test_env = default_test_setup_with_archive();
+
+    add_transactions_and_archive(&mut test_env);
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+    let archive_id = archives[0].canister_id;
+    let first_end = archives[0].end.clone();
+
+    // An unretired archive is considered for inserts again.
+    assert_eq!(
+        retire_archive(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &archive_id
+        ),
+        Ok(())
+    );
+    assert_eq!(
+        unretire_archive(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &archive_id
+        ),
+        Ok(())
+    );
+    add_transactions_and_archive(&mut test_env);
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+    assert!(archives[0].end > first_end);
+    let second_end = archives[0].end.clone();
+
+    // A retired archive is skipped even though it has space left.
+    assert_eq!(
+        retire_archive(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &archive_id
+        ),
+        Ok(())
+    );
+    add_transactions_and_archive(&mut test_env);
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 2);
+    let retired = archives
+        .iter()
+        .find(|archive| archive.canister_id == archive_id)
+        .expect("the retired archive is still listed");
+    let new_archive = archives
+        .iter()
+        .find(|archive| archive.canister_id != archive_id)
+        .unwrap();
+    assert_eq!(retired.end, second_end);
+    assert_eq!(new_archive.start, second_end);
+
+    // The retired archive keeps serving its blocks.
+    assert!(total_transactions(&test_env.pic, test_env.icrc3_id, archive_id, &()) > 0);
+
+    let unknown = Principal::from_slice(&[0xab]);
+    assert!(retire_archive(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &unknown
+    )
+    .is_err());
+}
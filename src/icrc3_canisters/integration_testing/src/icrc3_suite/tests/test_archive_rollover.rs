@@ -0,0 +1,73 @@
+use crate::client::icrc3::*;
+use crate::client::icrc3_archive::{get_insert_counters, remaining_capacity, total_transactions};
+use crate::icrc3_suite::setup::setup::{TestEnv, TestEnvBuilder};
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::DAY_IN_MS;
+use bity_ic_icrc3::config::ICRC3Properties;
+use candid::Nat;
+use std::time::Duration;
+
+const ARCHIVE_MAX_MEMORY_SIZE_BYTES: u128 = 4_000;
+
+fn add_transactions_and_archive(test_env: &mut TestEnv) {
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+}
+
+#[test]
+fn test_full_archive_rolls_over_without_failed_inserts() {
+    let mut test_env = TestEnvBuilder::new();
+    test_env.icrc3_constants = ICRC3Properties {
+        max_memory_size_bytes: ARCHIVE_MAX_MEMORY_SIZE_BYTES,
+        max_tx_local_stable_memory_size_bytes: Some(5000),
+        threshold_for_archiving_to_external_archive: Some(10),
+        max_transactions_in_window: 10_u64.into(),
+        ..ICRC3Properties::default()
+    };
+    test_env.archive_test_mode = true;
+    let mut test_env = test_env.build();
+
+    for _ in 0..4 {
+        add_transactions_and_archive(&mut test_env);
+    }
+
+    let mut archives =
+        icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    archives.sort_by(|a, b| a.start.cmp(&b.start));
+    assert!(archives.len() >= 2, "{archives:?}");
+
+    for (i, archive) in archives.iter().enumerate() {
+        // Each batch went straight to an archive with room for it.
+        let counters =
+            get_insert_counters(&test_env.pic, test_env.controller, archive.canister_id, &())
+                .unwrap();
+        assert!(counters.accepted > 0, "{counters:?}");
+        assert_eq!(counters.rejected, 0, "{counters:?}");
+
+        let capacity =
+            remaining_capacity(&test_env.pic, test_env.controller, archive.canister_id, &());
+        assert!(capacity <= ARCHIVE_MAX_MEMORY_SIZE_BYTES);
+
+        // The archives hold contiguous ranges of the chain.
+        let stored =
+            total_transactions(&test_env.pic, test_env.controller, archive.canister_id, &());
+        assert!(stored > 0);
+        if let Some(next) = archives.get(i + 1) {
+            assert_eq!(next.start, archive.start.clone() + Nat::from(stored));
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::client::icrc3::*;
+use crate::client::icrc3_archive::total_transactions;
+use crate::icrc3_suite::setup::default_test_setup_with_archive;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::DAY_IN_MS;
+use candid::encode_one;
+use icrc3_example_api::restore_archive_snapshot::Args as RestoreArchiveSnapshotArgs;
+use icrc3_example_api::take_archive_snapshot::Args as TakeArchiveSnapshotArgs;
+use std::time::Duration;
+
+// A valid wasm module exporting no methods.
+const EMPTY_WASM: &[u8] = b"\0asm\x01\0\0\0";
+
+#[test]
+fn test_archive_snapshot_restores_after_bad_upgrade() {
+    let mut test_env = default_test_setup_with_archive();
+
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+    let archive_id = archives[0].canister_id;
+
+    let archived = total_transactions(&test_env.pic, test_env.icrc3_id, archive_id, &());
+    assert!(archived > 0);
+
+    let snapshot_id = take_archive_snapshot(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &TakeArchiveSnapshotArgs {
+            canister_id: archive_id,
+        },
+    )
+    .unwrap();
+
+    // Break the archive with an upgrade to a module that exports nothing.
+    test_env
+        .pic
+        .upgrade_canister(
+            archive_id,
+            EMPTY_WASM.to_vec(),
+            vec![],
+            Some(test_env.icrc3_id),
+        )
+        .unwrap();
+    let broken = test_env.pic.query_call(
+        archive_id,
+        test_env.icrc3_id,
+        "total_transactions",
+        encode_one(()).unwrap(),
+    );
+    assert!(broken.is_err());
+
+    restore_archive_snapshot(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &RestoreArchiveSnapshotArgs {
+            canister_id: archive_id,
+            snapshot_id,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        total_transactions(&test_env.pic, test_env.icrc3_id, archive_id, &()),
+        archived
+    );
+
+    // Snapshot errors are reported, not swallowed.
+    let result = take_archive_snapshot(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &TakeArchiveSnapshotArgs {
+            canister_id: test_env.controller,
+        },
+    );
+    assert!(result.unwrap_err().contains("UnknownCanister"));
+}
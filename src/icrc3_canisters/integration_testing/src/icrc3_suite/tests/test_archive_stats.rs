@@ -0,0 +1,92 @@
+use crate::client::icrc3::*;
+use crate::client::icrc3_archive::stats;
+use crate::icrc3_suite::setup::default_test_setup_with_archive;
+use crate::icrc3_suite::setup::setup::TestEnv;
+use crate::icrc3_suite::setup::setup_icrc3::upgrade_icrc3_canister;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_types::BuildVersion;
+use icrc3_example_api::post_upgrade::UpgradeArgs;
+use std::time::Duration;
+
+fn add_transactions_and_archive(test_env: &mut TestEnv, count: usize) {
+    for _ in 0..count {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2 * 60));
+        tick_n_blocks(&test_env.pic, 50);
+    }
+    test_env.pic.advance_time(Duration::from_secs(10 * 60));
+    tick_n_blocks(&test_env.pic, 50);
+}
+
+#[test]
+fn test_archive_stats_advance_and_are_rebuilt_after_upgrade() {
+    let mut test_env = default_test_setup_with_archive();
+
+    add_transactions_and_archive(&mut test_env, 10);
+
+    let archive_stats =
+        icrc3_get_archive_stats(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archive_stats.len(), 1);
+    let archive_id = archive_stats[0].canister_id;
+    let first = archive_stats[0]
+        .stats
+        .clone()
+        .expect("fetched by the archive job");
+    let first_fetched_at = archive_stats[0].fetched_at.unwrap();
+    assert!(first.blocks_stored > 0);
+    assert!(first.total_bytes > 0);
+    assert!(first.last_insert_at.unwrap() <= first_fetched_at);
+    assert_eq!(
+        first,
+        stats(&test_env.pic, test_env.controller, archive_id, &())
+    );
+
+    // The next archived batch refreshes the stats.
+    add_transactions_and_archive(&mut test_env, 10);
+
+    let archive_stats =
+        icrc3_get_archive_stats(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    let second = archive_stats[0].stats.clone().unwrap();
+    assert!(second.blocks_stored > first.blocks_stored);
+    assert!(second.total_bytes > first.total_bytes);
+    assert!(second.last_insert_at > first.last_insert_at);
+    assert!(second.remaining_capacity < first.remaining_capacity);
+    assert!(archive_stats[0].fetched_at.unwrap() > first_fetched_at);
+
+    // The cache is not kept across upgrades, the next archive run rebuilds it.
+    upgrade_icrc3_canister(
+        &mut test_env.pic,
+        test_env.icrc3_id,
+        icrc3_example_api::Args::Upgrade(UpgradeArgs {
+            version: BuildVersion::min(),
+            commit_hash: "commit_hash 2".to_string(),
+        }),
+        test_env.controller,
+    );
+    let archive_stats =
+        icrc3_get_archive_stats(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archive_stats.len(), 1);
+    assert_eq!(archive_stats[0].stats, None);
+    assert_eq!(archive_stats[0].fetched_at, None);
+
+    test_env.pic.advance_time(Duration::from_secs(10 * 60));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archive_stats =
+        icrc3_get_archive_stats(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archive_stats[0].canister_id, archive_id);
+    assert_eq!(
+        archive_stats[0].stats,
+        Some(stats(&test_env.pic, test_env.controller, archive_id, &()))
+    );
+    assert_eq!(
+        archive_stats[0].stats.as_ref().unwrap().blocks_stored,
+        second.blocks_stored
+    );
+}
@@ -0,0 +1,95 @@
+use crate::client::icrc3::*;
+use crate::client::icrc3_archive::{corrupt_block, get_encoded_blocks, total_transactions};
+use crate::icrc3_suite::setup::default_test_setup_with_archive_in_test_mode;
+use crate::icrc3_suite::setup::setup::TestEnv;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::DAY_IN_MS;
+use bity_ic_icrc3::job_history::JobKind;
+use bity_ic_icrc3_archive_api::get_encoded_blocks::Args as GetEncodedBlocksArgs;
+use std::time::Duration;
+
+#[test]
+fn test_verification_reports_corrupted_archived_block() {
+    let mut test_env = default_test_setup_with_archive_in_test_mode();
+
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+    let archive_id = archives[0].canister_id;
+    let block_offset: u64 = archives[0].start.0.clone().try_into().unwrap();
+    let stored = total_transactions(&test_env.pic, test_env.icrc3_id, archive_id, &()) as u64;
+    assert!(stored >= 3);
+
+    let verified = run_verification_now(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &100,
+    );
+    assert_eq!(verified, Ok((block_offset + stored) as u128));
+
+    let corrupted_id = block_offset + 1;
+    let read_block = |test_env: &TestEnv| {
+        get_encoded_blocks(
+            &test_env.pic,
+            test_env.icrc3_id,
+            archive_id,
+            &GetEncodedBlocksArgs {
+                start: corrupted_id,
+                length: 1,
+            },
+        )
+    };
+    let original = read_block(&test_env);
+    assert_eq!(
+        corrupt_block(
+            &mut test_env.pic,
+            test_env.icrc3_id,
+            archive_id,
+            &corrupted_id
+        ),
+        Ok(())
+    );
+    let corrupted = read_block(&test_env);
+    assert_eq!(corrupted.len(), 1);
+    assert_eq!(corrupted[0].0, corrupted_id);
+    assert_ne!(corrupted, original);
+
+    let result = run_verification_now(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &100,
+    );
+    let error = result.expect_err("the corrupted block was not detected");
+    assert!(
+        error.contains(&format!("block {}:", corrupted_id)),
+        "unexpected error: {}",
+        error
+    );
+
+    let history = icrc3_job_history(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    let verifications: Vec<_> = history
+        .iter()
+        .filter(|r| r.job == JobKind::Verification)
+        .collect();
+    assert_eq!(verifications.len(), 2);
+    assert!(verifications[0].outcome.is_ok());
+    assert_eq!(verifications[1].outcome, Err(error));
+}
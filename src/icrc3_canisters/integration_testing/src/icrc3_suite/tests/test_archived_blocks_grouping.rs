@@ -0,0 +1,72 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::default_test_setup_with_archive;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::DAY_IN_MS;
+use candid::Nat;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+use std::time::Duration;
+
+fn range(start: u64, length: u64) -> GetBlocksRequest {
+    GetBlocksRequest {
+        start: Nat::from(start),
+        length: Nat::from(length),
+    }
+}
+
+#[test]
+fn test_interleaved_ranges_are_grouped_per_archive() {
+    let mut test_env = default_test_setup_with_archive();
+
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+    let archive_id = archives[0].canister_id;
+    assert!(archives[0].end >= 7u64);
+
+    let get_blocks_args = vec![range(0, 2), range(5, 2), range(2, 2)];
+    let get_blocks_result = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &get_blocks_args,
+    );
+
+    assert_eq!(get_blocks_result.blocks.len(), 0);
+    assert_eq!(get_blocks_result.archived_blocks.len(), 1);
+
+    let archived_block = get_blocks_result.archived_blocks[0].clone();
+    assert_eq!(archived_block.callback.canister_id, archive_id);
+    assert_eq!(archived_block.args, get_blocks_args);
+
+    let get_blocks_result_2 = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        archived_block.callback.canister_id,
+        &archived_block.args,
+    );
+
+    let ids: Vec<Nat> = get_blocks_result_2
+        .blocks
+        .iter()
+        .map(|block| block.id.clone())
+        .collect();
+    let expected: Vec<Nat> = [0u64, 1, 5, 6, 2, 3].into_iter().map(Nat::from).collect();
+    assert_eq!(ids, expected);
+    assert_eq!(get_blocks_result_2.archived_blocks.len(), 0);
+}
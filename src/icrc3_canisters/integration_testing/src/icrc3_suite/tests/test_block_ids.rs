@@ -0,0 +1,120 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::setup::TestEnv;
+use crate::icrc3_suite::setup::{
+    default_test_setup_with_archive, setup_icrc3::upgrade_icrc3_canister,
+};
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::DAY_IN_MS;
+use bity_ic_types::BuildVersion;
+use candid::Nat;
+use icrc3_example_api::post_upgrade::UpgradeArgs;
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+use std::time::Duration;
+
+/// Prepares and commits a new transaction, returning the id returned by the
+/// commit with the hash of the transaction.
+fn insert_transaction(test_env: &mut TestEnv) -> (u64, Vec<u8>) {
+    let transaction =
+        create_transactions(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    let prepared = prepare_transaction(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &transaction,
+    )
+    .unwrap();
+    let block_index = commit_prepared_transaction(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &(transaction, prepared.timestamp),
+    )
+    .unwrap();
+    test_env.pic.advance_time(Duration::from_secs(2));
+    tick_n_blocks(&test_env.pic, 10);
+    (block_index, prepared.transaction_hash)
+}
+
+/// Returns the block with the given id, following the archive callback if needed.
+fn get_block(test_env: &TestEnv, block_index: u64) -> ICRC3Value {
+    let args = vec![GetBlocksRequest {
+        start: Nat::from(block_index),
+        length: Nat::from(1u64),
+    }];
+    let mut result = icrc3_get_blocks(&test_env.pic, test_env.controller, test_env.icrc3_id, &args);
+    if result.blocks.is_empty() {
+        assert_eq!(result.archived_blocks.len(), 1, "block {block_index}");
+        let archived_blocks = result.archived_blocks[0].clone();
+        result = icrc3_get_blocks(
+            &test_env.pic,
+            test_env.controller,
+            archived_blocks.callback.canister_id,
+            &archived_blocks.args,
+        );
+    }
+    assert_eq!(result.blocks.len(), 1, "block {block_index}");
+    assert_eq!(result.blocks[0].id, block_index);
+    result.blocks[0].block.clone()
+}
+
+/// Returns the hash of the transaction recorded in a block, without the fields
+/// added when the block is created.
+fn transaction_hash(block: ICRC3Value) -> Vec<u8> {
+    let ICRC3Value::Map(mut block) = block else {
+        panic!("a block is a map");
+    };
+    for field in ["phash", "rec", "ver"] {
+        block.remove(field);
+    }
+    ICRC3Value::Map(block).hash().to_vec()
+}
+
+#[test]
+fn test_returned_ids_find_their_blocks_across_archives_and_upgrades() {
+    let mut test_env = default_test_setup_with_archive();
+
+    let mut inserted = vec![];
+    for _ in 0..10 {
+        inserted.push(insert_transaction(&mut test_env));
+    }
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+
+    upgrade_icrc3_canister(
+        &mut test_env.pic,
+        test_env.icrc3_id,
+        icrc3_example_api::Args::Upgrade(UpgradeArgs {
+            version: BuildVersion::min(),
+            commit_hash: "commit_hash 2".to_string(),
+        }),
+        test_env.controller,
+    );
+
+    for _ in 0..5 {
+        inserted.push(insert_transaction(&mut test_env));
+    }
+
+    // Block ids are 0-based and returned in sequence.
+    let ids: Vec<u64> = inserted
+        .iter()
+        .map(|(block_index, _)| *block_index)
+        .collect();
+    assert_eq!(ids, (0..15).collect::<Vec<u64>>());
+    let tip = icrc3_get_tip(&test_env.pic, test_env.controller, test_env.icrc3_id, &()).unwrap();
+    assert_eq!(tip.index, 14u64);
+
+    for (block_index, expected_hash) in inserted {
+        assert_eq!(
+            transaction_hash(get_block(&test_env, block_index)),
+            expected_hash,
+            "block {block_index}"
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::default_test_setup;
+
+use bity_ic_canister_logger::HttpRequest;
+use bity_ic_icrc3::schema::{BlockSchema, ValueKind};
+
+#[test]
+fn test_block_schemas_are_served_and_enforced() {
+    let mut test_env = default_test_setup();
+
+    let schemas = icrc3_block_schemas(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(schemas.len(), 1);
+    assert_eq!(schemas[0].btype, "btype_test");
+    let tx = schemas[0]
+        .fields
+        .iter()
+        .find(|field| field.name == "tx")
+        .expect("no tx field documented");
+    assert_eq!(tx.kind, ValueKind::Map);
+    assert!(tx
+        .fields
+        .iter()
+        .any(|field| field.name == "sender" && field.required));
+
+    let response = http_request(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &HttpRequest {
+            method: "GET".to_string(),
+            url: "/block_schemas".to_string(),
+            ..Default::default()
+        },
+    );
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.header("content-type"), Some("application/json"));
+    let served: Vec<BlockSchema> = serde_json::from_slice(&response.body).unwrap();
+    assert_eq!(served, schemas);
+
+    let transaction =
+        create_transactions(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    let result = add_created_transaction(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &transaction,
+    );
+    assert!(result.is_ok(), "{result:?}");
+}
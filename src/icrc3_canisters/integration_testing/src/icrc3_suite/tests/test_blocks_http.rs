@@ -0,0 +1,174 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::setup::TestEnv;
+use crate::icrc3_suite::setup::{default_test_setup, default_test_setup_with_archive};
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_logger::{HttpRequest, StreamingStrategy};
+use bity_ic_canister_time::DAY_IN_MS;
+use bity_ic_icrc3::blocks_http::{block_json, STREAMING_CALLBACK_METHOD};
+use bity_ic_types::CanisterId;
+use candid::Nat;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+use serde_json::Value;
+use std::time::Duration;
+
+fn get(test_env: &TestEnv, canister_id: CanisterId, url: &str) -> (u16, Vec<u8>, usize) {
+    let response = http_request(
+        &test_env.pic,
+        test_env.controller,
+        canister_id,
+        &HttpRequest {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            ..Default::default()
+        },
+    );
+    if response.status_code != 200 {
+        return (response.status_code, response.body, 0);
+    }
+    assert_eq!(response.header("content-type"), Some("application/json"));
+
+    // Follows the streaming callbacks as the HTTP gateway does.
+    let mut body = response.body;
+    let mut chunks = 1;
+    let mut token = response.streaming_strategy.map(|strategy| match strategy {
+        StreamingStrategy::Callback { callback, token } => {
+            assert_eq!(callback.0.principal, canister_id);
+            assert_eq!(callback.0.method, STREAMING_CALLBACK_METHOD);
+            token
+        }
+    });
+    while let Some(next) = token {
+        let chunk =
+            http_request_streaming_callback(&test_env.pic, test_env.controller, canister_id, &next);
+        body.extend(chunk.body);
+        chunks += 1;
+        token = chunk.token;
+    }
+    (200, body, chunks)
+}
+
+fn paged_blocks(test_env: &TestEnv, canister_id: CanisterId, start: u64, end: u64) -> Vec<Value> {
+    let mut blocks = vec![];
+    while start + (blocks.len() as u64) < end {
+        let result = icrc3_get_blocks(
+            &test_env.pic,
+            test_env.controller,
+            canister_id,
+            &vec![GetBlocksRequest {
+                start: Nat::from(start + blocks.len() as u64),
+                length: Nat::from(end - start - blocks.len() as u64),
+            }],
+        );
+        assert!(!result.blocks.is_empty());
+        for block in result.blocks {
+            let id = u64::try_from(block.id.0).unwrap();
+            blocks.push(block_json(id, &block.block));
+        }
+    }
+    blocks
+}
+
+#[test]
+fn test_blocks_are_streamed_in_chunks() {
+    let mut test_env = default_test_setup();
+
+    for i in 0..1000 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        if i % 100 == 99 {
+            test_env.pic.advance_time(Duration::from_secs(1));
+        }
+    }
+    let chain_length =
+        icrc3_chain_length(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(chain_length, 1000u64);
+
+    let (status, body, chunks) = get(
+        &test_env,
+        test_env.icrc3_id,
+        "/blocks?start=0&length=1000&chunk_bytes=20000",
+    );
+    assert_eq!(status, 200);
+    assert!(chunks > 5, "{chunks}");
+    let streamed: Vec<Value> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(streamed.len(), 1000);
+    assert_eq!(
+        streamed,
+        paged_blocks(&test_env, test_env.icrc3_id, 0, 1000)
+    );
+
+    // Lengths past the end of the chain are capped.
+    let (status, body, _) = get(&test_env, test_env.icrc3_id, "/blocks?start=990&length=50");
+    assert_eq!(status, 200);
+    let streamed: Vec<Value> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        streamed,
+        paged_blocks(&test_env, test_env.icrc3_id, 990, 1000)
+    );
+
+    let (status, _, _) = get(&test_env, test_env.icrc3_id, "/blocks?chunk_bytes=0");
+    assert_eq!(status, 400);
+
+    for key in ["", "blocks:v1:5", "blocks:v1:9:5:100", "blocks:v1:0:10:0"] {
+        let result = test_env.pic.query_call(
+            test_env.icrc3_id,
+            test_env.controller,
+            "http_request_streaming_callback",
+            candid::encode_one(bity_ic_canister_logger::StreamingToken {
+                key: key.to_string(),
+            })
+            .unwrap(),
+        );
+        let error = result.expect_err(key);
+        assert!(
+            error.reject_message.contains("Invalid streaming token"),
+            "{}",
+            error.reject_message
+        );
+    }
+}
+
+#[test]
+fn test_archived_blocks_are_streamed_by_their_archive() {
+    let mut test_env = default_test_setup_with_archive();
+
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+    let archive_id = archives[0].canister_id;
+    let archived =
+        icrc3_get_blocks(&test_env.pic, test_env.controller, archive_id, &vec![]).log_length;
+    let archived = u64::try_from(archived.0).unwrap();
+    assert!(archived > 0);
+
+    let (status, body, _) = get(&test_env, test_env.icrc3_id, "/blocks?start=0");
+    assert_eq!(status, 404);
+    assert!(String::from_utf8(body)
+        .unwrap()
+        .contains(&archive_id.to_text()));
+
+    let (status, body, chunks) = get(&test_env, archive_id, "/blocks?chunk_bytes=100");
+    assert_eq!(status, 200);
+    assert_eq!(chunks as u64, archived);
+    let streamed: Vec<Value> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(streamed, paged_blocks(&test_env, archive_id, 0, archived));
+}
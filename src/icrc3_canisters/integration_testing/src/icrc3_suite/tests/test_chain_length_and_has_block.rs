@@ -0,0 +1,68 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::default_test_setup_with_archive;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::DAY_IN_MS;
+use candid::Nat;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+use std::time::Duration;
+
+#[test]
+fn test_chain_length_and_has_block() {
+    let mut test_env = default_test_setup_with_archive();
+
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let chain_length =
+        icrc3_chain_length(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(chain_length, 10u64);
+
+    let blocks = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &vec![GetBlocksRequest {
+            start: Nat::from(0u64),
+            length: Nat::from(10u64),
+        }],
+    );
+    assert_eq!(blocks.log_length, chain_length);
+    assert!(!blocks.archived_blocks.is_empty());
+    assert!(!blocks.blocks.is_empty());
+
+    // Archived and local blocks both exist.
+    let archived_index = blocks.archived_blocks[0].args[0].start.clone();
+    let local_index = blocks.blocks[0].id.clone();
+    for index in [archived_index, local_index] {
+        assert!(icrc3_has_block(
+            &test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &index
+        ));
+    }
+
+    // Nothing exists beyond the tip.
+    for index in [10u64, 11, u64::MAX] {
+        assert!(!icrc3_has_block(
+            &test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &Nat::from(index)
+        ));
+    }
+}
@@ -0,0 +1,57 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::default_test_setup;
+
+use bity_ic_icrc3::config::ICRC3Properties;
+use bity_ic_icrc3::icrc3::PERMITTED_DRIFT;
+use candid::Nat;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+
+/// Mirrors the ICRC-1 reference ledger: a transaction is accepted from
+/// `now - tx_window` to `now + permitted_drift` and rejected outside of it.
+///
+/// The time of the canister moves between calls, so the cases are a millisecond
+/// away from the bounds, which are tested to the nanosecond in the unit tests.
+#[test]
+fn test_created_at_time_window() {
+    let mut test_env = default_test_setup();
+    let now = test_env.pic.get_time().as_nanos_since_unix_epoch();
+    let window = ICRC3Properties::default().tx_window.as_nanos() as u64;
+    let drift = PERMITTED_DRIFT.as_nanos() as u64;
+    let margin = 1_000_000;
+
+    let cases = [
+        (now - window + margin, None),
+        (now - window - margin, Some("TooOld")),
+        (now + drift - margin, None),
+        (now + drift + margin, Some("CreatedInFuture")),
+    ];
+
+    for (i, (created_at_time, expected_error)) in cases.into_iter().enumerate() {
+        let mut transaction =
+            create_transactions(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+        transaction.tx.memo = Some(serde_bytes::ByteBuf::from(vec![i as u8]));
+        transaction.tx.created_at_time = Some(created_at_time);
+
+        let result = add_created_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &transaction,
+        );
+        match expected_error {
+            None => assert!(result.is_ok(), "case {i}: {result:?}"),
+            Some(error) => assert!(result.unwrap_err().contains(error), "case {i}"),
+        }
+    }
+
+    let get_blocks_result = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &vec![GetBlocksRequest {
+            start: Nat::from(0u64),
+            length: Nat::from(10u64),
+        }],
+    );
+    assert_eq!(get_blocks_result.log_length, Nat::from(2u64));
+}
@@ -0,0 +1,111 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::setup::TestEnvBuilder;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::DAY_IN_MS;
+use bity_ic_icrc3::config::ICRC3Properties;
+use bity_ic_icrc3::types::Icrc3Error;
+use candid::Nat;
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+use std::time::Duration;
+
+#[test]
+fn test_duplicate_of_resolves_to_archived_original() {
+    let mut test_env = TestEnvBuilder::new();
+
+    test_env.icrc3_constants = ICRC3Properties {
+        max_tx_local_stable_memory_size_bytes: Some(5000),
+        threshold_for_archiving_to_external_archive: Some(10),
+        max_transactions_in_window: 1000_u64.into(),
+        // Keep every transaction in the deduplication window while the chain is archived.
+        tx_window: Duration::from_millis(DAY_IN_MS * 3),
+        ..ICRC3Properties::default()
+    };
+
+    let mut test_env = test_env.build();
+
+    let original_index = 2u64;
+    let mut original = None;
+
+    for i in 0..10 {
+        if i == original_index {
+            let transaction =
+                create_transactions(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+            add_created_transaction(
+                &mut test_env.pic,
+                test_env.controller,
+                test_env.icrc3_id,
+                &transaction,
+            )
+            .unwrap();
+            original = Some(transaction);
+        } else {
+            add_random_transaction(
+                &mut test_env.pic,
+                test_env.controller,
+                test_env.icrc3_id,
+                &(),
+            );
+        }
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+    let original = original.unwrap();
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+    assert!(archives[0].end >= original_index);
+
+    let result = add_created_transaction(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &original,
+    );
+    assert_eq!(
+        result,
+        Err(format!(
+            "Error adding transaction: {}",
+            Icrc3Error::DuplicateTransaction {
+                duplicate_of: Nat::from(original_index),
+            }
+        ))
+    );
+
+    let get_blocks_args = vec![GetBlocksRequest {
+        start: Nat::from(original_index),
+        length: Nat::from(1u64),
+    }];
+    let mut get_blocks_result = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &get_blocks_args,
+    );
+    if get_blocks_result.blocks.is_empty() {
+        assert_eq!(get_blocks_result.archived_blocks.len(), 1);
+        let archived_block = get_blocks_result.archived_blocks[0].clone();
+        get_blocks_result = icrc3_get_blocks(
+            &test_env.pic,
+            test_env.controller,
+            archived_block.callback.canister_id,
+            &archived_block.args,
+        );
+    }
+
+    assert_eq!(get_blocks_result.blocks.len(), 1);
+    let block = get_blocks_result.blocks[0].clone();
+    assert_eq!(block.id, original_index);
+
+    let mut transaction = block.block;
+    if let ICRC3Value::Map(ref mut map) = transaction {
+        map.remove("phash");
+    }
+    assert_eq!(transaction.hash(), ICRC3Value::from(original).hash());
+}
@@ -0,0 +1,72 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::setup::TestEnvBuilder;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::DAY_IN_MS;
+use bity_ic_icrc3::config::ICRC3Properties;
+use candid::Nat;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+use std::time::Duration;
+
+#[test]
+fn test_blocks_are_archived_into_external_archive() {
+    let mut test_env = TestEnvBuilder::new();
+
+    test_env.icrc3_constants = ICRC3Properties {
+        max_tx_local_stable_memory_size_bytes: Some(5000),
+        threshold_for_archiving_to_external_archive: Some(10),
+        max_transactions_in_window: 10_u64.into(),
+        ..ICRC3Properties::default()
+    };
+    test_env.external_archives = 1;
+
+    let mut test_env = test_env.build();
+    let external_archive = test_env.external_archives[0];
+
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 50);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    // No archive canister is created, the blocks land in the external one
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+    assert_eq!(archives[0].canister_id, external_archive);
+    assert_eq!(archives[0].start, Nat::from(0u64));
+    assert!(archives[0].end > 0u64);
+
+    let get_blocks_result = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &vec![GetBlocksRequest {
+            start: Nat::from(0u64),
+            length: Nat::from(1u64),
+        }],
+    );
+    assert!(get_blocks_result.blocks.is_empty());
+    assert_eq!(get_blocks_result.archived_blocks.len(), 1);
+    let archived_blocks = get_blocks_result.archived_blocks[0].clone();
+    assert_eq!(archived_blocks.callback.canister_id, external_archive);
+
+    let archived_result = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        external_archive,
+        &archived_blocks.args,
+    );
+    assert_eq!(archived_result.blocks.len(), 1);
+    assert_eq!(archived_result.blocks[0].id, Nat::from(0u64));
+}
@@ -0,0 +1,155 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::setup::TestEnv;
+use crate::icrc3_suite::setup::{default_test_setup, default_test_setup_with_archive};
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::{DAY_IN_MS, MINUTE_IN_MS};
+use bity_ic_icrc3::job_history::{JobKind, JobRunRecord};
+use candid::Nat;
+use icrc3_example_api::set_fault::{Args as SetFaultArgs, FaultKind};
+use std::time::Duration;
+
+fn set(test_env: &mut TestEnv, fault: FaultKind, enabled: bool) {
+    let result = set_fault(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &SetFaultArgs { fault, enabled },
+    );
+    assert_eq!(result, Ok(()));
+}
+
+fn add_transactions(test_env: &mut TestEnv, count: usize) {
+    for _ in 0..count {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+}
+
+fn archive_runs(test_env: &TestEnv) -> Vec<JobRunRecord> {
+    icrc3_job_history(&test_env.pic, test_env.controller, test_env.icrc3_id, &())
+        .into_iter()
+        .filter(|r| r.job == JobKind::Archive)
+        .collect()
+}
+
+#[test]
+fn test_forced_throttling_rejects_transactions() {
+    let mut test_env = default_test_setup();
+
+    set(&mut test_env, FaultKind::ForceThrottle, true);
+    let transaction =
+        create_transactions(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    let result = add_created_transaction(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &transaction,
+    );
+    assert!(
+        result
+            .as_ref()
+            .unwrap_err()
+            .contains("Transaction throttled"),
+        "{:?}",
+        result
+    );
+    assert_eq!(
+        icrc3_chain_length(&test_env.pic, test_env.controller, test_env.icrc3_id, &()),
+        Nat::from(0u64)
+    );
+
+    set(&mut test_env, FaultKind::ForceThrottle, false);
+    let result = add_created_transaction(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &transaction,
+    );
+    assert_eq!(result, Ok(()));
+    assert_eq!(
+        icrc3_chain_length(&test_env.pic, test_env.controller, test_env.icrc3_id, &()),
+        Nat::from(1u64)
+    );
+}
+
+#[test]
+fn test_rejected_archive_insert_is_retried() {
+    let mut test_env = default_test_setup_with_archive();
+
+    add_transactions(&mut test_env, 10);
+    set(&mut test_env, FaultKind::RejectNextArchiveInsert, true);
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let runs = archive_runs(&test_env);
+    let error = runs
+        .last()
+        .expect("no archive job run recorded")
+        .outcome
+        .clone()
+        .expect_err("the insert was not rejected");
+    assert!(
+        error.contains("Injected fault"),
+        "unexpected error: {}",
+        error
+    );
+    assert!(
+        icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &()).is_empty()
+    );
+
+    // The fault only applies once, the next run archives the same blocks.
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(11 * MINUTE_IN_MS));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let runs = archive_runs(&test_env);
+    assert!(runs.last().unwrap().outcome.is_ok());
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+    assert_eq!(archives[0].start, Nat::from(0u64));
+}
+
+#[test]
+fn test_verification_reports_injected_chain_break() {
+    let mut test_env = default_test_setup_with_archive();
+
+    add_transactions(&mut test_env, 3);
+    set(&mut test_env, FaultKind::CorruptNextBlockHash, true);
+    add_transactions(&mut test_env, 7);
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+
+    let result = run_verification_now(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &100,
+    );
+    let error = result.expect_err("the chain break was not detected");
+    assert!(error.contains("block 3:"), "unexpected error: {}", error);
+
+    let history = icrc3_job_history(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    let verification = history
+        .iter()
+        .rev()
+        .find(|r| r.job == JobKind::Verification)
+        .expect("no verification run recorded");
+    assert_eq!(verification.outcome, Err(error));
+}
@@ -0,0 +1,140 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::setup::{TestEnv, TestEnvBuilder};
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::DAY_IN_MS;
+use bity_ic_icrc3::config::ICRC3Properties;
+use candid::Nat;
+use serde_bytes::ByteBuf;
+use std::time::Duration;
+
+/// Prepares and commits a new transaction, returning its block id and hash.
+fn insert_transaction(test_env: &mut TestEnv) -> (u64, Vec<u8>) {
+    let transaction =
+        create_transactions(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    let prepared = prepare_transaction(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &transaction,
+    )
+    .unwrap();
+    let block_index = commit_prepared_transaction(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &(transaction, prepared.timestamp),
+    )
+    .unwrap();
+    test_env.pic.advance_time(Duration::from_secs(2));
+    tick_n_blocks(&test_env.pic, 10);
+    (block_index, prepared.transaction_hash)
+}
+
+#[test]
+fn test_find_block_by_thash_in_archives_and_local_blocks() {
+    let mut test_env = TestEnvBuilder::new();
+
+    test_env.icrc3_constants = ICRC3Properties {
+        max_tx_local_stable_memory_size_bytes: Some(5000),
+        threshold_for_archiving_to_external_archive: Some(10),
+        max_transactions_in_window: 1000_u64.into(),
+        index_thashes: true,
+        ..ICRC3Properties::default()
+    };
+
+    let mut test_env = test_env.build();
+
+    let mut inserted = vec![];
+    for _ in 0..10 {
+        inserted.push(insert_transaction(&mut test_env));
+    }
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+
+    for _ in 0..3 {
+        inserted.push(insert_transaction(&mut test_env));
+    }
+
+    for (block_index, transaction_hash) in inserted {
+        let block = find_block_by_thash(
+            &test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &ByteBuf::from(transaction_hash),
+        )
+        .unwrap()
+        .unwrap_or_else(|| panic!("block {block_index} not found"));
+        assert_eq!(block.id, Nat::from(block_index));
+    }
+
+    assert_eq!(
+        find_block_by_thash(
+            &test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &ByteBuf::from(vec![0; 32]),
+        ),
+        Ok(None)
+    );
+}
+
+#[test]
+fn test_find_block_by_thash_fans_out_to_three_archives() {
+    let mut test_env = TestEnvBuilder::new();
+
+    test_env.icrc3_constants = ICRC3Properties {
+        max_memory_size_bytes: 4_000,
+        max_tx_local_stable_memory_size_bytes: Some(5000),
+        threshold_for_archiving_to_external_archive: Some(10),
+        max_transactions_in_window: 1000_u64.into(),
+        index_thashes: true,
+        ..ICRC3Properties::default()
+    };
+
+    let mut test_env = test_env.build();
+
+    // Full archives roll over, until the blocks are spread over three of them.
+    let mut inserted = vec![];
+    let mut archives = vec![];
+    for _ in 0..8 {
+        for _ in 0..10 {
+            inserted.push(insert_transaction(&mut test_env));
+        }
+        test_env
+            .pic
+            .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+        tick_n_blocks(&test_env.pic, 50);
+
+        archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+        if archives.len() >= 3 {
+            break;
+        }
+    }
+    assert!(archives.len() >= 3, "{archives:?}");
+
+    // The first and last block of each archive are found through the fan-out.
+    archives.sort_by(|a, b| a.start.cmp(&b.start));
+    for archive in &archives {
+        for block_index in [archive.start.clone(), archive.end.clone()] {
+            let (_, transaction_hash) = inserted
+                .iter()
+                .find(|(id, _)| block_index == *id)
+                .unwrap_or_else(|| panic!("block {block_index} was not inserted"));
+            let block = find_block_by_thash(
+                &test_env.pic,
+                test_env.controller,
+                test_env.icrc3_id,
+                &ByteBuf::from(transaction_hash.clone()),
+            )
+            .unwrap()
+            .unwrap_or_else(|| panic!("block {block_index} not found"));
+            assert_eq!(block.id, block_index);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::default_test_setup_with_archive;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::DAY_IN_MS;
+use candid::Nat;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+use std::time::Duration;
+
+fn pathological_requests() -> Vec<GetBlocksRequest> {
+    let huge = Nat::from(1u128 << 80);
+    vec![
+        GetBlocksRequest {
+            start: huge.clone(),
+            length: huge.clone(),
+        },
+        GetBlocksRequest {
+            start: Nat::from(u64::MAX),
+            length: Nat::from(u64::MAX),
+        },
+        GetBlocksRequest {
+            start: Nat::from(0u64),
+            length: Nat::from(0u64),
+        },
+        GetBlocksRequest {
+            start: Nat::from(0u64),
+            length: huge,
+        },
+    ]
+}
+
+#[test]
+fn test_get_blocks_does_not_trap_on_pathological_requests() {
+    let mut test_env = default_test_setup_with_archive();
+
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+
+    let result = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &pathological_requests(),
+    );
+    assert_eq!(result.log_length, 10u64);
+    assert_eq!(result.blocks.len(), 10);
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+
+    // The archive canister bounds the same requests instead of trapping.
+    let result = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        archives[0].canister_id,
+        &pathological_requests(),
+    );
+    assert!(!result.blocks.is_empty());
+    for block in &result.blocks {
+        assert!(block.id < 10u64);
+    }
+    assert!(result.archived_blocks.is_empty());
+}
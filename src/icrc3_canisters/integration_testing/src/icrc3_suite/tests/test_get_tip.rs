@@ -0,0 +1,36 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::default_test_setup;
+use crate::utils::tick_n_blocks;
+
+use candid::Nat;
+use std::time::Duration;
+
+#[test]
+fn test_get_tip() {
+    let mut test_env = default_test_setup();
+
+    let tip = icrc3_get_tip(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(tip, None);
+
+    let mut previous_hash = None;
+    for i in 0..3u64 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+
+        let tip = icrc3_get_tip(&test_env.pic, test_env.controller, test_env.icrc3_id, &())
+            .expect("the chain is not empty");
+        let chain_length =
+            icrc3_chain_length(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+        assert_eq!(tip.index, i);
+        assert_eq!(tip.index.clone() + Nat::from(1u64), chain_length);
+        assert_eq!(tip.block_hash.len(), 32);
+        assert_ne!(Some(tip.block_hash.clone()), previous_hash);
+        previous_hash = Some(tip.block_hash);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::setup::TestEnv;
+use crate::icrc3_suite::setup::{default_test_setup, setup_icrc3::upgrade_icrc3_canister};
+
+use bity_ic_canister_logger::{HttpRequest, HttpResponse, LogEntry};
+use bity_ic_types::BuildVersion;
+use icrc3_example_api::post_upgrade::UpgradeArgs;
+use std::time::Duration;
+
+fn get(test_env: &TestEnv, url: &str) -> HttpResponse {
+    let response = http_request(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &HttpRequest {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            ..Default::default()
+        },
+    );
+    assert_eq!(response.status_code, 200, "GET {url}");
+    response
+}
+
+fn get_json(test_env: &TestEnv, url: &str) -> Vec<LogEntry> {
+    serde_json::from_slice(&get(test_env, url).body).unwrap()
+}
+
+fn get_text(test_env: &TestEnv, url: &str) -> String {
+    String::from_utf8(get(test_env, url).body).unwrap()
+}
+
+#[test]
+fn test_http_logs_formats_and_filters() {
+    let mut test_env = default_test_setup();
+
+    let logs = get_json(&test_env, "/logs?format=json");
+    assert!(logs.iter().any(|l| l.message.contains("Init complete.")));
+
+    let text = get_text(&test_env, "/logs");
+    assert!(text
+        .lines()
+        .any(|l| l.contains("INFO") && l.contains("Init complete.")));
+
+    test_env.pic.advance_time(Duration::from_secs(10));
+    let since_ms = test_env.pic.get_time().as_nanos_since_unix_epoch() / 1_000_000;
+
+    upgrade_icrc3_canister(
+        &mut test_env.pic,
+        test_env.icrc3_id,
+        icrc3_example_api::Args::Upgrade(UpgradeArgs {
+            version: BuildVersion::min(),
+            commit_hash: "commit_hash 2".to_string(),
+        }),
+        test_env.controller,
+    );
+
+    // The logs are kept across the upgrade.
+    let all_logs = get_json(&test_env, "/logs?format=json");
+    assert!(all_logs.len() > logs.len());
+
+    let recent = get_json(&test_env, &format!("/logs?format=json&since_ms={since_ms}"));
+    assert!(!recent.is_empty());
+    assert!(recent.iter().all(|l| l.timestamp >= since_ms));
+    assert!(recent.iter().all(|l| !l.message.contains("Init complete.")));
+    assert!(recent.iter().any(|l| l.message.contains("Post-upgrade")));
+
+    let tail = get_text(&test_env, "/logs?tail=1");
+    assert_eq!(tail.lines().count(), 1);
+    assert!(tail.contains(&all_logs.last().unwrap().timestamp.to_string()));
+
+    assert!(get_json(&test_env, "/logs?format=json&level=error").is_empty());
+    get_json(&test_env, "/traces?format=json&tail=10");
+
+    let response = http_request(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &HttpRequest {
+            method: "GET".to_string(),
+            url: "/logs?format=xml".to_string(),
+            ..Default::default()
+        },
+    );
+    assert_eq!(response.status_code, 400);
+}
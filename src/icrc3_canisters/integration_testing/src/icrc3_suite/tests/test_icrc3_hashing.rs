@@ -3,7 +3,9 @@ use icrc_ledger_types::icrc::generic_value::ICRC3Value;
 use icrc_ledger_types::icrc1::account::Account;
 use std::str::FromStr;
 
-use bity_ic_icrc3::transaction::{ICRC1Transaction, ICRC1TransactionData};
+use bity_ic_icrc3::transaction::{
+    ICRC1Transaction, ICRC1TransactionData, ICRC7Transaction, ICRC7TransactionData,
+};
 
 #[test]
 fn test_icrc3_hashing_nat() {
@@ -298,3 +300,38 @@ fn test_different_blocks_different_hashes() {
 
     assert_ne!(hash1, hash2);
 }
+
+#[test]
+fn test_icrc7_transfer_block_fee_hash() {
+    let transfer_tx = ICRC7Transaction::new(
+        "7xfer".to_string(),
+        1699218263,
+        ICRC7TransactionData {
+            op: "7xfer".to_string(),
+            tid: Some(Nat::from(1u64)),
+            from: Some(Account {
+                owner: candid::Principal::from_str("aaaaa-aa").unwrap(),
+                subaccount: None,
+            }),
+            to: Some(Account {
+                owner: candid::Principal::from_str("2vxsx-fae").unwrap(),
+                subaccount: None,
+            }),
+            meta: None,
+            memo: None,
+            created_at_time: Some(Nat::from(1699218263u64)),
+        },
+    );
+
+    let without_fee: ICRC3Value = transfer_tx.clone().into();
+    let with_fee: ICRC3Value = transfer_tx.with_fee(Nat::from(1000u64)).into();
+
+    // The fee is hashed as a top-level field of the block.
+    let ICRC3Value::Map(mut expected) = without_fee.clone() else {
+        panic!("block is not a map");
+    };
+    expected.insert("fee".to_string(), ICRC3Value::Nat(Nat::from(1000u64)));
+
+    assert_eq!(with_fee.clone().hash(), ICRC3Value::Map(expected).hash());
+    assert_ne!(with_fee.hash(), without_fee.hash());
+}
@@ -6,6 +6,7 @@ use crate::utils::tick_n_blocks;
 
 use bity_ic_canister_time::DAY_IN_MS;
 use bity_ic_icrc3::config::ICRC3Properties;
+use bity_ic_icrc3::prepared::ListPreparedTransactionsArgs;
 use candid::Nat;
 use icrc_ledger_types::icrc::generic_value::ICRC3Value;
 use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
@@ -572,7 +573,13 @@ fn test_prepare_transaction_duplicate_immediate() {
 
 #[test]
 fn test_prepare_transaction_cleanup_after_long_delay() {
-    let mut test_env = default_test_setup();
+    let ttl = Duration::from_secs(2 * 60 * 60);
+    let mut test_env = TestEnvBuilder::new();
+    test_env.icrc3_constants = ICRC3Properties {
+        prepared_transaction_ttl: ttl,
+        ..ICRC3Properties::default()
+    };
+    let mut test_env = test_env.build();
 
     // Create a transaction
     let transaction = create_transactions(
@@ -584,6 +591,10 @@ fn test_prepare_transaction_cleanup_after_long_delay() {
 
     println!("transaction: {:?}", transaction);
 
+    // The transaction is prepared well after its timestamp
+    test_env.pic.advance_time(ttl / 2);
+    let prepared_at = test_env.pic.get_time().as_nanos_since_unix_epoch() as u128;
+
     // First prepare should succeed
     let result1 = prepare_transaction(
         &mut test_env.pic,
@@ -594,12 +605,48 @@ fn test_prepare_transaction_cleanup_after_long_delay() {
 
     assert!(result1.is_ok());
     println!("First prepare result: {:?}", result1);
+    let prepared = result1.unwrap();
+
+    // It expires a TTL after it was prepared, not after its timestamp
+    assert!(prepared.timestamp < prepared_at);
+    assert!(prepared.expires_at_ns as u128 >= prepared_at + ttl.as_nanos());
+    assert!((prepared.expires_at_ns as u128) < prepared_at + ttl.as_nanos() + 1_000_000_000);
+    test_env.pic.advance_time(ttl.mul_f64(0.6));
+    tick_n_blocks(&test_env.pic, 50);
+    assert_eq!(
+        icrc3_list_prepared_transactions(
+            &test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &ListPreparedTransactionsArgs {
+                limit: 10,
+                offset: 0,
+            },
+        )
+        .len(),
+        1
+    );
 
-    // Advance time by 1.1 days (26.4 hours) to trigger cleanup
-    // 1.1 days = 1.1 * 24 * 60 * 60 = 95,040 seconds
-    test_env.pic.advance_time(Duration::from_secs(95_040));
+    // Advance time by 1.1 TTL to trigger cleanup
+    test_env.pic.advance_time(ttl.mul_f64(1.1));
     tick_n_blocks(&mut test_env.pic, 50);
 
+    // Committing now reports the expiry rather than a missing transaction
+    let commit_result = commit_prepared_transaction(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &(transaction.clone(), prepared.timestamp),
+    );
+    let error_msg = commit_result.unwrap_err();
+    assert!(
+        error_msg.contains(&format!(
+            "PreparedTransactionExpired {{ expired_at: {} }}",
+            prepared.expires_at_ns
+        )),
+        "{error_msg}"
+    );
+
     // Second prepare with same transaction should now succeed because the first one was cleaned up
     let result2 = prepare_transaction(
         &mut test_env.pic,
@@ -612,9 +659,9 @@ fn test_prepare_transaction_cleanup_after_long_delay() {
     println!("Second prepare result after cleanup: {:?}", result2);
 
     // Verify that both prepares returned the same hash (same transaction)
-    let hash1 = result1.unwrap();
-    let hash2 = result2.unwrap();
-    assert_eq!(hash1, hash2);
+    let second = result2.unwrap();
+    assert_eq!(prepared.transaction_hash, second.transaction_hash);
+    assert_eq!(prepared.timestamp, second.timestamp);
 }
 
 #[test]
@@ -648,7 +695,7 @@ fn test_prepare_and_commit_workflow() {
         &mut test_env.pic,
         test_env.controller,
         test_env.icrc3_id,
-        &(transaction, transaction_result.1.clone()),
+        &(transaction, transaction_result.timestamp),
     );
 
     assert!(commit_result.is_ok());
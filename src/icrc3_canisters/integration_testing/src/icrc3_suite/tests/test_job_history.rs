@@ -0,0 +1,79 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::default_test_setup_with_archive;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::{DAY_IN_MS, MINUTE_IN_MS};
+use bity_ic_icrc3::job_history::{JobKind, JobRunRecord};
+use std::time::Duration;
+
+fn last_archive_run(records: &[JobRunRecord]) -> JobRunRecord {
+    records
+        .iter()
+        .rev()
+        .find(|r| r.job == JobKind::Archive)
+        .cloned()
+        .expect("no archive job run recorded")
+}
+
+#[test]
+fn test_archive_job_failures_are_recorded() {
+    let mut test_env = default_test_setup_with_archive();
+
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+    let archive_id = archives[0].canister_id;
+
+    // Make the archive unreachable and give the job new blocks to archive.
+    test_env
+        .pic
+        .stop_canister(archive_id, Some(test_env.icrc3_id))
+        .unwrap();
+
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(11 * MINUTE_IN_MS));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let history = icrc3_job_history(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert!(last_archive_run(&history).outcome.is_err());
+
+    test_env
+        .pic
+        .start_canister(archive_id, Some(test_env.icrc3_id))
+        .unwrap();
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(11 * MINUTE_IN_MS));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let history = icrc3_job_history(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert!(last_archive_run(&history).outcome.is_ok());
+}
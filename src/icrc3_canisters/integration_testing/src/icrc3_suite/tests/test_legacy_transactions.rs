@@ -0,0 +1,218 @@
+use crate::client::icrc3::{
+    add_created_transaction, add_icrc1_transaction, create_transactions, get_transactions,
+    icrc3_get_blocks,
+};
+use crate::client::pocket::execute_query;
+use crate::icrc3_suite::setup::setup::TestEnvBuilder;
+use crate::utils::{random_principal, tick_n_blocks};
+
+use bity_ic_canister_time::DAY_IN_MS;
+use bity_ic_icrc3::config::ICRC3Properties;
+use bity_ic_icrc3::transaction::{ICRC1Transaction, ICRC1TransactionData};
+use candid::Nat;
+use icrc_ledger_types::icrc1::account::Account;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+use icrc_ledger_types::icrc3::transactions::{GetTransactionsRequest, TransactionRange};
+use std::time::Duration;
+
+#[test]
+fn test_legacy_transactions_match_the_blocks() {
+    let mut test_env = TestEnvBuilder::new();
+    test_env.icrc3_constants = ICRC3Properties {
+        legacy_transactions: true,
+        ..ICRC3Properties::default()
+    };
+    test_env.supported_block_types = vec![
+        "btype_test".to_string(),
+        "1mint".to_string(),
+        "1xfer".to_string(),
+    ];
+    let mut test_env = test_env.build();
+
+    let alice = Account::from(random_principal());
+    let bob = Account::from(random_principal());
+    let icrc1_transaction = |btype: &str, from: Option<Account>, to: Account, amount: u64| {
+        ICRC1Transaction::new(
+            btype.to_string(),
+            test_env.pic.get_time().as_nanos_since_unix_epoch(),
+            Nat::from(10u64),
+            ICRC1TransactionData {
+                op: None,
+                amount: Nat::from(amount),
+                from,
+                to: Some(to),
+                memo: None,
+                created_at_time: None,
+                fee: Some(Nat::from(10u64)),
+            },
+        )
+    };
+
+    let mint = icrc1_transaction("1mint", None, alice, 1_000);
+    let transfer = icrc1_transaction("1xfer", Some(alice), bob, 400);
+    for transaction in [mint, transfer] {
+        assert_eq!(
+            add_icrc1_transaction(
+                &mut test_env.pic,
+                test_env.controller,
+                test_env.icrc3_id,
+                &transaction,
+            ),
+            Ok(())
+        );
+        test_env.pic.advance_time(Duration::from_secs(1));
+    }
+    let transaction =
+        create_transactions(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(
+        add_created_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &transaction,
+        ),
+        Ok(())
+    );
+
+    let request = GetBlocksRequest {
+        start: Nat::from(0u64),
+        length: Nat::from(10u64),
+    };
+    let blocks = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &vec![request.clone()],
+    );
+    let response = get_transactions(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &request,
+    );
+
+    assert_eq!(response.log_length, blocks.log_length);
+    assert_eq!(response.first_index, Nat::from(0u64));
+    assert!(response.archived_transactions.is_empty());
+    assert_eq!(response.transactions.len(), blocks.blocks.len());
+
+    let kinds: Vec<&str> = response
+        .transactions
+        .iter()
+        .map(|transaction| transaction.kind.as_str())
+        .collect();
+    assert_eq!(kinds, vec!["mint", "transfer", "btype_test"]);
+
+    let mint = response.transactions[0].mint.clone().unwrap();
+    assert_eq!(mint.amount, Nat::from(1_000u64));
+    assert_eq!(mint.to, alice);
+    assert_eq!(mint.fee, Some(Nat::from(10u64)));
+
+    let transfer = response.transactions[1].transfer.clone().unwrap();
+    assert_eq!(transfer.amount, Nat::from(400u64));
+    assert_eq!((transfer.from, transfer.to), (alice, bob));
+    assert!(response.transactions[1].timestamp > response.transactions[0].timestamp);
+
+    // The other blocks keep their position, without an operation.
+    assert!(response.transactions[2].mint.is_none());
+    assert!(response.transactions[2].transfer.is_none());
+}
+
+#[test]
+fn test_legacy_transactions_of_archived_blocks() {
+    let mut test_env = TestEnvBuilder::new();
+    test_env.icrc3_constants = ICRC3Properties {
+        legacy_transactions: true,
+        max_tx_local_stable_memory_size_bytes: Some(5000),
+        threshold_for_archiving_to_external_archive: Some(10),
+        max_transactions_in_window: 1000_u64.into(),
+        tx_window: Duration::from_millis(DAY_IN_MS * 3),
+        ..ICRC3Properties::default()
+    };
+    test_env.supported_block_types = vec!["btype_test".to_string(), "1mint".to_string()];
+    let mut test_env = test_env.build();
+
+    // Each mint records its block index as its amount.
+    let alice = Account::from(random_principal());
+    let num_blocks = 10u64;
+    for amount in 0..num_blocks {
+        let mint = ICRC1Transaction::new(
+            "1mint".to_string(),
+            test_env.pic.get_time().as_nanos_since_unix_epoch(),
+            Nat::from(10u64),
+            ICRC1TransactionData {
+                op: None,
+                amount: Nat::from(amount),
+                from: None,
+                to: Some(alice),
+                memo: None,
+                created_at_time: None,
+                fee: None,
+            },
+        );
+        assert_eq!(
+            add_icrc1_transaction(
+                &mut test_env.pic,
+                test_env.controller,
+                test_env.icrc3_id,
+                &mint,
+            ),
+            Ok(())
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+
+    let request = GetTransactionsRequest {
+        start: Nat::from(0u64),
+        length: Nat::from(num_blocks),
+    };
+    let response = get_transactions(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &request,
+    );
+    assert_eq!(response.log_length, Nat::from(num_blocks));
+    assert!(!response.archived_transactions.is_empty());
+
+    // The archived ranges cover the blocks before the local ones, in order.
+    let mut next_index = Nat::from(0u64);
+    let mut amounts = vec![];
+    for archived in &response.archived_transactions {
+        assert_eq!(archived.start, next_index);
+        assert_eq!(archived.callback.method, "get_transactions");
+        let range: TransactionRange = execute_query(
+            &test_env.pic,
+            test_env.controller,
+            archived.callback.canister_id,
+            &archived.callback.method,
+            &GetTransactionsRequest {
+                start: archived.start.clone(),
+                length: archived.length.clone(),
+            },
+        );
+        assert_eq!(Nat::from(range.transactions.len()), archived.length);
+        amounts.extend(range.transactions);
+        next_index = archived.start.clone() + archived.length.clone();
+    }
+    assert_eq!(response.first_index, next_index);
+    amounts.extend(response.transactions);
+
+    let amounts: Vec<Nat> = amounts
+        .into_iter()
+        .map(|transaction| {
+            assert_eq!(transaction.kind, "mint");
+            let mint = transaction.mint.unwrap();
+            assert_eq!(mint.to, alice);
+            mint.amount
+        })
+        .collect();
+    let expected: Vec<Nat> = (0..num_blocks).map(Nat::from).collect();
+    assert_eq!(amounts, expected);
+}
@@ -0,0 +1,66 @@
+use crate::client::icrc3::{add_created_transaction, create_transactions, icrc3_get_archives};
+use crate::icrc3_suite::setup::setup::TestEnvBuilder;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_icrc3::config::ICRC3Properties;
+use bity_ic_icrc3::icrc3::LOCAL_ARCHIVE_FULL_RETRY_AFTER;
+use std::time::Duration;
+
+#[test]
+fn test_full_local_archive_triggers_archiving() {
+    let mut test_env = TestEnvBuilder::new();
+    test_env.icrc3_constants = ICRC3Properties {
+        max_tx_local_stable_memory_size_bytes: Some(2_000),
+        // Only a rejected block triggers an archive run, not the archive job
+        threshold_for_archiving_to_external_archive: Some(100_000),
+        local_archive_low_water_mark_percent: 0,
+        ..ICRC3Properties::default()
+    };
+    let mut test_env = test_env.build();
+
+    let mut rejected = None;
+    for _ in 0..100 {
+        let transaction =
+            create_transactions(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+        match add_created_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &transaction,
+        ) {
+            Ok(()) => test_env.pic.advance_time(Duration::from_secs(1)),
+            Err(error) => {
+                rejected = Some((transaction, error));
+                break;
+            }
+        }
+    }
+    let (transaction, error) = rejected.expect("the local archive fills up");
+    assert!(
+        error.contains(&format!(
+            "LocalArchiveFull {{ retry_after_hint_ms: {} }}",
+            LOCAL_ARCHIVE_FULL_RETRY_AFTER.as_millis()
+        )),
+        "{error}"
+    );
+    assert!(
+        icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &()).is_empty()
+    );
+
+    // The archive run requested by the rejection frees the local archive.
+    test_env.pic.advance_time(LOCAL_ARCHIVE_FULL_RETRY_AFTER);
+    tick_n_blocks(&test_env.pic, 20);
+    assert!(
+        !icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &()).is_empty()
+    );
+
+    assert_eq!(
+        add_created_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &transaction,
+        ),
+        Ok(())
+    );
+}
@@ -0,0 +1,148 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::setup::TestEnv;
+use crate::icrc3_suite::setup::{
+    default_test_setup_with_archive, setup_icrc3::upgrade_icrc3_canister,
+};
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::DAY_IN_MS;
+use bity_ic_types::BuildVersion;
+use candid::Nat;
+use icrc3_example_api::post_upgrade::UpgradeArgs;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+fn add_transactions_and_archive(test_env: &mut TestEnv) {
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+}
+
+/// Pages through the chain as a client would, from block 0 up to `log_length`,
+/// following the archived ranges to the archives, and asserts that the distinct
+/// block ids served are exactly `0..log_length`.
+fn assert_log_length_matches_pagination(test_env: &TestEnv) {
+    let log_length = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &vec![],
+    )
+    .log_length;
+    assert_eq!(
+        icrc3_chain_length(&test_env.pic, test_env.controller, test_env.icrc3_id, &()),
+        log_length
+    );
+    let log_length: u64 = log_length.0.try_into().unwrap();
+
+    let mut ids = BTreeSet::new();
+    let mut start = 0u64;
+    while start < log_length {
+        let page = icrc3_get_blocks(
+            &test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &vec![GetBlocksRequest {
+                start: Nat::from(start),
+                length: Nat::from(log_length - start),
+            }],
+        );
+        assert_eq!(page.log_length, log_length);
+
+        let mut page_ids: Vec<u64> = page
+            .blocks
+            .iter()
+            .map(|block| block.id.0.clone().try_into().unwrap())
+            .collect();
+        for archived in &page.archived_blocks {
+            let archived_page = icrc3_get_blocks(
+                &test_env.pic,
+                test_env.controller,
+                archived.callback.canister_id,
+                &archived.args,
+            );
+            let archived_ids: Vec<u64> = archived_page
+                .blocks
+                .iter()
+                .map(|block| block.id.0.clone().try_into().unwrap())
+                .collect();
+            // An archive reports the length of the chain up to its last block.
+            let archive_log_length: u64 = archived_page.log_length.0.try_into().unwrap();
+            assert!(archived_ids.iter().all(|id| *id < archive_log_length));
+            assert!(archive_log_length <= log_length);
+            page_ids.extend(archived_ids);
+        }
+
+        let next = page_ids.iter().max().map(|id| id + 1).unwrap_or(start);
+        assert!(next > start, "no block served from {start}");
+        ids.extend(page_ids);
+        start = next;
+    }
+
+    assert_eq!(ids, (0..log_length).collect::<BTreeSet<u64>>());
+}
+
+#[test]
+fn test_log_length_matches_pagination() {
+    let mut test_env = default_test_setup_with_archive();
+    assert_log_length_matches_pagination(&test_env);
+
+    add_transactions_and_archive(&mut test_env);
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 1);
+    assert_log_length_matches_pagination(&test_env);
+
+    // A pending prepared transaction is not a block yet.
+    let transaction =
+        create_transactions(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert!(prepare_transaction(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &transaction,
+    )
+    .is_ok());
+    assert_log_length_matches_pagination(&test_env);
+
+    // The next blocks go to a second archive, starting past the first block.
+    assert_eq!(
+        retire_archive(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &archives[0].canister_id
+        ),
+        Ok(())
+    );
+    add_transactions_and_archive(&mut test_env);
+    let archives = icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(archives.len(), 2);
+    assert_log_length_matches_pagination(&test_env);
+
+    upgrade_icrc3_canister(
+        &mut test_env.pic,
+        test_env.icrc3_id,
+        icrc3_example_api::Args::Upgrade(UpgradeArgs {
+            version: BuildVersion::min(),
+            commit_hash: "commit_hash 2".to_string(),
+        }),
+        test_env.controller,
+    );
+    assert_log_length_matches_pagination(&test_env);
+
+    add_transactions_and_archive(&mut test_env);
+    assert_log_length_matches_pagination(&test_env);
+}
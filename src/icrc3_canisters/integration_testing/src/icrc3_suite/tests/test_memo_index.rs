@@ -0,0 +1,48 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::setup::TestEnvBuilder;
+
+use bity_ic_icrc3::config::ICRC3Properties;
+use candid::Nat;
+use icrc3_example_api::find_blocks_by_memo::Args as FindBlocksByMemoArgs;
+use serde_bytes::ByteBuf;
+use std::time::Duration;
+
+#[test]
+fn test_find_blocks_by_memo() {
+    let mut test_env = TestEnvBuilder::new();
+    test_env.icrc3_constants = ICRC3Properties {
+        index_memos: true,
+        ..ICRC3Properties::default()
+    };
+    let mut test_env = test_env.build();
+
+    for memo in [b"a", b"b", b"a"] {
+        let mut transaction =
+            create_transactions(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+        transaction.tx.memo = Some(ByteBuf::from(memo.to_vec()));
+        let result = add_created_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &transaction,
+        );
+        assert!(result.is_ok(), "{result:?}");
+        test_env.pic.advance_time(Duration::from_secs(1));
+    }
+
+    let find = |memo: &[u8], max| {
+        find_blocks_by_memo(
+            &test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &FindBlocksByMemoArgs {
+                memo: ByteBuf::from(memo.to_vec()),
+                max,
+            },
+        )
+    };
+    assert_eq!(find(b"a", 10), vec![Nat::from(0u64), Nat::from(2u64)]);
+    assert_eq!(find(b"a", 1), vec![Nat::from(0u64)]);
+    assert_eq!(find(b"b", 10), vec![Nat::from(1u64)]);
+    assert!(find(b"c", 10).is_empty());
+}
@@ -0,0 +1,45 @@
+use crate::client::icrc3::{self, msgpack};
+use crate::icrc3_suite::setup::default_test_setup;
+use crate::utils::tick_n_blocks;
+
+use candid::Nat;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+use std::time::Duration;
+
+#[test]
+fn test_msgpack_endpoints_round_trip() {
+    let mut test_env = default_test_setup();
+
+    for _ in 0..3 {
+        msgpack::add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 5);
+    }
+
+    let get_blocks_args = vec![GetBlocksRequest {
+        start: Nat::from(0u64),
+        length: Nat::from(10u64),
+    }];
+
+    let msgpack_result = msgpack::icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &get_blocks_args,
+    );
+    let candid_result = icrc3::icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &get_blocks_args,
+    );
+
+    assert_eq!(msgpack_result.log_length, Nat::from(3u64));
+    assert_eq!(msgpack_result.blocks.len(), 3);
+    assert_eq!(msgpack_result, candid_result);
+}
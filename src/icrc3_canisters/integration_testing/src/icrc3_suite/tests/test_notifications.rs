@@ -0,0 +1,134 @@
+use crate::client::icrc3::{
+    add_created_transaction, create_transactions, find_block_by_thash, icrc3_notification_metrics,
+    received_block_notifications, subscribe, unsubscribe,
+};
+use crate::client::pocket::{start_canister, stop_canister};
+use crate::icrc3_suite::setup::setup::{TestEnv, TestEnvBuilder};
+use crate::icrc3_suite::setup::setup_icrc3::setup_icrc3_canister;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_icrc3::notifications::{
+    SubscriberMetrics, MAX_BACKOFF, MAX_CONSECUTIVE_FAILURES, SUSPENDED_RETRY_INTERVAL,
+};
+use candid::Nat;
+use icrc3_example_api::subscribe::Args as SubscribeArgs;
+use std::time::Duration;
+
+fn add_transactions(test_env: &mut TestEnv, count: usize) {
+    for _ in 0..count {
+        let transaction =
+            create_transactions(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+        assert_eq!(
+            add_created_transaction(
+                &mut test_env.pic,
+                test_env.controller,
+                test_env.icrc3_id,
+                &transaction,
+            ),
+            Ok(())
+        );
+        test_env.pic.advance_time(Duration::from_secs(1));
+    }
+}
+
+fn subscriber_metrics(test_env: &TestEnv) -> SubscriberMetrics {
+    let metrics =
+        icrc3_notification_metrics(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(metrics.len(), 1);
+    metrics[0].clone()
+}
+
+#[test]
+fn test_subscriber_is_notified_suspended_and_resumed() {
+    let mut test_env = TestEnvBuilder::new().build();
+
+    // Another instance of the example canister records the notifications.
+    let subscriber_id = test_env
+        .pic
+        .create_canister_with_settings(Some(test_env.controller), None);
+    setup_icrc3_canister(
+        &mut test_env.pic,
+        subscriber_id,
+        TestEnvBuilder::new().icrc3_init_args(vec![]),
+        test_env.controller,
+    );
+
+    assert_eq!(
+        subscribe(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &SubscribeArgs {
+                canister_id: subscriber_id,
+                method_name: "record_block_notifications".to_string(),
+                filter: Some(vec!["btype_test".to_string()]),
+            },
+        ),
+        Ok(())
+    );
+
+    add_transactions(&mut test_env, 3);
+    test_env.pic.advance_time(Duration::from_secs(5));
+    tick_n_blocks(&test_env.pic, 10);
+
+    let received =
+        received_block_notifications(&test_env.pic, test_env.controller, subscriber_id, &());
+    assert_eq!(
+        received.iter().map(|n| n.index).collect::<Vec<_>>(),
+        vec![0, 1, 2]
+    );
+    for notification in &received {
+        assert_eq!(notification.btype, "btype_test");
+        let block = find_block_by_thash(
+            &test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &notification.thash,
+        )
+        .unwrap()
+        .expect("the thash identifies the block");
+        assert_eq!(block.id, Nat::from(notification.index));
+    }
+    assert_eq!(subscriber_metrics(&test_env).delivered, 3);
+
+    // The subscriber is stopped: the deliveries fail until it is suspended,
+    // while the block is still added.
+    stop_canister(&mut test_env.pic, test_env.controller, subscriber_id);
+    add_transactions(&mut test_env, 1);
+    for _ in 0..MAX_CONSECUTIVE_FAILURES {
+        test_env.pic.advance_time(MAX_BACKOFF);
+        tick_n_blocks(&test_env.pic, 10);
+    }
+    let metrics = subscriber_metrics(&test_env);
+    assert!(metrics.suspended);
+    assert_eq!(metrics.consecutive_failures, MAX_CONSECUTIVE_FAILURES);
+    assert_eq!(metrics.queued, 1);
+    assert!(metrics.last_error.is_some());
+
+    // Once restarted, the subscriber is probed again and resumes.
+    start_canister(&mut test_env.pic, test_env.controller, subscriber_id);
+    test_env.pic.advance_time(SUSPENDED_RETRY_INTERVAL);
+    tick_n_blocks(&test_env.pic, 10);
+
+    let metrics = subscriber_metrics(&test_env);
+    assert!(!metrics.suspended);
+    assert_eq!(metrics.consecutive_failures, 0);
+    assert_eq!(metrics.queued, 0);
+    assert_eq!(metrics.delivered, 4);
+    assert_eq!(
+        received_block_notifications(&test_env.pic, test_env.controller, subscriber_id, &()).len(),
+        4
+    );
+
+    // Unsubscribing drops the subscriber and its metrics.
+    assert!(unsubscribe(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &subscriber_id,
+    ));
+    assert!(
+        icrc3_notification_metrics(&test_env.pic, test_env.controller, test_env.icrc3_id, &())
+            .is_empty()
+    );
+}
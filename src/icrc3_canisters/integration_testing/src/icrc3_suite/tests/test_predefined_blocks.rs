@@ -97,6 +97,7 @@ fn test_icrc2_transaction_validation() {
             memo: None,
             expected_allowance: None,
             expires_at: None,
+            created_at_time: None,
         },
     );
     assert!(transfer_tx.validate_transaction_fields().is_ok());
@@ -121,6 +122,7 @@ fn test_icrc2_transaction_validation() {
             memo: None,
             expected_allowance: None,
             expires_at: None,
+            created_at_time: None,
         },
     );
     assert!(approve_tx.validate_transaction_fields().is_ok());
@@ -415,6 +417,7 @@ fn test_invalid_transactions() {
             memo: None,
             expected_allowance: None,
             expires_at: None,
+            created_at_time: None,
         },
     );
     assert!(invalid_approve_tx.validate_transaction_fields().is_err());
@@ -436,6 +439,7 @@ fn test_invalid_transactions() {
             memo: None,
             expected_allowance: None,
             expires_at: None,
+            created_at_time: None,
         },
     );
     assert!(invalid_approve_tx_no_spender
@@ -459,6 +463,7 @@ fn test_invalid_transactions() {
             memo: None,
             expected_allowance: None,
             expires_at: None,
+            created_at_time: None,
         },
     );
     assert!(invalid_transfer_tx_no_from
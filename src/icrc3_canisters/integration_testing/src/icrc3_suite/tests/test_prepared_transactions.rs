@@ -0,0 +1,108 @@
+use crate::client::icrc3::{
+    commit_prepared_transaction, create_transactions, icrc3_list_prepared_transactions,
+    icrc3_prepared_transactions_metrics, prepare_transaction,
+};
+use crate::icrc3_suite::setup::setup::TestEnvBuilder;
+use crate::utils::random_principal;
+
+use bity_ic_icrc3::config::ICRC3Properties;
+use bity_ic_icrc3::prepared::ListPreparedTransactionsArgs;
+use candid::encode_one;
+use std::time::Duration;
+
+#[test]
+fn test_prepared_transactions_are_listed_until_committed() {
+    let mut test_env = TestEnvBuilder::new();
+    test_env.icrc3_constants = ICRC3Properties {
+        prepared_transactions_warning_threshold: Some(2),
+        ..ICRC3Properties::default()
+    };
+    let mut test_env = test_env.build();
+
+    let mut prepared = vec![];
+    for _ in 0..3 {
+        let transaction =
+            create_transactions(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+        let prepared_transaction = prepare_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &transaction,
+        )
+        .unwrap();
+        prepared.push((transaction, prepared_transaction));
+        test_env.pic.advance_time(Duration::from_millis(200));
+    }
+
+    let all = ListPreparedTransactionsArgs {
+        limit: 10,
+        offset: 0,
+    };
+    let listed = icrc3_list_prepared_transactions(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &all,
+    );
+    let hashes: Vec<String> = listed.iter().map(|info| info.hash_hex.clone()).collect();
+    let expected: Vec<String> = prepared
+        .iter()
+        .map(|(_, prepared)| hex::encode(&prepared.transaction_hash))
+        .collect();
+    assert_eq!(hashes, expected);
+    assert_eq!(listed[0].expires_at_ns, prepared[0].1.expires_at_ns);
+
+    let page = icrc3_list_prepared_transactions(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &ListPreparedTransactionsArgs {
+            limit: 1,
+            offset: 2,
+        },
+    );
+    assert_eq!(page, listed[2..].to_vec());
+
+    let metrics = icrc3_prepared_transactions_metrics(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &(),
+    );
+    assert_eq!(metrics.count, 3);
+    assert!(metrics.oldest_age_ns.unwrap() >= 600_000_000);
+    assert_eq!(metrics.warning_threshold, Some(2));
+
+    let (transaction, prepared_transaction) = prepared.remove(0);
+    commit_prepared_transaction(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &(transaction, prepared_transaction.timestamp),
+    )
+    .unwrap();
+
+    let listed_after_commit = icrc3_list_prepared_transactions(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &all,
+    );
+    assert_eq!(listed_after_commit, listed[1..].to_vec());
+    let metrics = icrc3_prepared_transactions_metrics(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &(),
+    );
+    assert_eq!(metrics.count, 2);
+
+    // The listing is only served to the authorized principals.
+    let unauthorized = test_env.pic.query_call(
+        test_env.icrc3_id,
+        random_principal(),
+        "icrc3_list_prepared_transactions",
+        encode_one(all).unwrap(),
+    );
+    assert!(unauthorized.is_err());
+}
@@ -0,0 +1,40 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::default_test_setup;
+
+#[test]
+fn test_draws_in_one_update_share_the_pool() {
+    let mut test_env = default_test_setup();
+
+    // Two small draws fit in the 32 bytes of a single raw_rand call.
+    let result = draw_random_bytes(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &vec![8, 8],
+    )
+    .unwrap();
+    assert_eq!(result.refills, 1);
+    assert_eq!(result.draws.len(), 2);
+    assert!(result.draws.iter().all(|draw| draw.len() == 8));
+    assert_ne!(result.draws[0], result.draws[1]);
+
+    // The 16 bytes left are used by the next update.
+    let result = draw_random_bytes(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &vec![16],
+    )
+    .unwrap();
+    assert_eq!(result.refills, 0);
+
+    let result = draw_random_bytes(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &vec![40],
+    )
+    .unwrap();
+    assert_eq!(result.refills, 2);
+    assert_eq!(result.draws[0].len(), 40);
+}
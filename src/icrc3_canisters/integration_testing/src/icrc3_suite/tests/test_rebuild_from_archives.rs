@@ -0,0 +1,140 @@
+use crate::client::icrc3::*;
+use crate::client::icrc3_archive::total_transactions;
+use crate::icrc3_suite::setup::setup::{TestEnv, TestEnvBuilder};
+use crate::icrc3_suite::setup::setup_icrc3::reinstall_icrc3_canister;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::DAY_IN_MS;
+use bity_ic_icrc3::config::ICRC3Properties;
+use bity_ic_icrc3::rebuild::RebuildError;
+use candid::Nat;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+use std::time::Duration;
+
+const ARCHIVE_MAX_MEMORY_SIZE_BYTES: u128 = 4_000;
+
+fn add_transactions_and_archive(test_env: &mut TestEnv) {
+    for _ in 0..10 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 10);
+    }
+
+    test_env
+        .pic
+        .advance_time(Duration::from_millis(DAY_IN_MS * 2));
+    tick_n_blocks(&test_env.pic, 50);
+}
+
+/// Returns the number of blocks stored in the archive canisters.
+fn archived_blocks(test_env: &TestEnv) -> u64 {
+    icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &())
+        .iter()
+        .map(|archive| {
+            total_transactions(&test_env.pic, test_env.controller, archive.canister_id, &()) as u64
+        })
+        .sum()
+}
+
+fn log_length(test_env: &TestEnv) -> Nat {
+    icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &vec![GetBlocksRequest {
+            start: Nat::from(0u64),
+            length: Nat::from(1u64),
+        }],
+    )
+    .log_length
+}
+
+#[test]
+fn test_chain_is_rebuilt_from_archives_after_reinstall() {
+    let mut builder = TestEnvBuilder::new();
+    builder.icrc3_constants = ICRC3Properties {
+        max_memory_size_bytes: ARCHIVE_MAX_MEMORY_SIZE_BYTES,
+        max_tx_local_stable_memory_size_bytes: Some(5000),
+        threshold_for_archiving_to_external_archive: Some(10),
+        max_transactions_in_window: 10_u64.into(),
+        ..ICRC3Properties::default()
+    };
+    let mut test_env = builder.build();
+
+    for _ in 0..4 {
+        add_transactions_and_archive(&mut test_env);
+    }
+
+    let mut archives =
+        icrc3_get_archives(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    archives.sort_by(|a, b| a.start.cmp(&b.start));
+    assert!(archives.len() >= 2, "{archives:?}");
+    let archive_ids: Vec<_> = archives.iter().map(|a| a.canister_id).collect();
+
+    // Only the archived blocks survive the loss of the state, the local ones are lost.
+    let archived_chain_length = archived_blocks(&test_env);
+    assert!(log_length(&test_env) > archived_chain_length);
+
+    reinstall_icrc3_canister(
+        &mut test_env.pic,
+        test_env.icrc3_id,
+        builder.icrc3_init_args(vec![]),
+        test_env.controller,
+    );
+    assert_eq!(log_length(&test_env), Nat::from(0u64));
+
+    // The archives are given in any order.
+    let mut reversed = archive_ids.clone();
+    reversed.reverse();
+    let report = rebuild_from_archives(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &reversed,
+    )
+    .unwrap();
+    assert_eq!(
+        report
+            .archives
+            .iter()
+            .map(|a| a.canister_id)
+            .collect::<Vec<_>>(),
+        archive_ids
+    );
+    assert_eq!(report.archived_chain_length, archived_chain_length);
+    assert_eq!(report.last_index, archived_chain_length - 1);
+    assert_eq!(log_length(&test_env), Nat::from(archived_chain_length));
+
+    // The chain is no longer empty.
+    assert_eq!(
+        rebuild_from_archives(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &archive_ids,
+        ),
+        Err(RebuildError::ChainNotEmpty {
+            chain_length: archived_chain_length,
+        })
+    );
+
+    // New blocks extend the rebuilt chain, and the whole chain verifies once
+    // they are archived.
+    add_transactions_and_archive(&mut test_env);
+    assert_eq!(log_length(&test_env), Nat::from(archived_chain_length + 10));
+    let archived = archived_blocks(&test_env);
+    assert!(archived > archived_chain_length);
+
+    let verified = run_verification_now(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &100_000,
+    );
+    assert_eq!(verified, Ok(archived as u128));
+}
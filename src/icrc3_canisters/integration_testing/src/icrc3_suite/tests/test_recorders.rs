@@ -0,0 +1,100 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::setup::TestEnvBuilder;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_icrc3::config::ICRC3Properties;
+use candid::Nat;
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+use serde_bytes::ByteBuf;
+use std::time::Duration;
+
+#[test]
+fn test_satellite_records_only_while_it_is_a_recorder() {
+    let mut test_env = TestEnvBuilder::new();
+    test_env.icrc3_constants = ICRC3Properties {
+        recorders: Some(vec![]),
+        record_recorder: true,
+        ..ICRC3Properties::default()
+    };
+    let mut test_env = test_env.build();
+
+    // A canister recording its transactions into the ICRC3 canister.
+    let satellite = test_env.pic.create_canister();
+
+    let transaction =
+        create_transactions(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    let result = add_created_transaction(
+        &mut test_env.pic,
+        satellite,
+        test_env.icrc3_id,
+        &transaction,
+    );
+    assert!(result.unwrap_err().contains("Unauthorized"));
+
+    add_recorder(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &satellite,
+    );
+    let properties =
+        icrc3_get_properties(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(properties.recorders, Some(vec![satellite]));
+
+    let result = add_created_transaction(
+        &mut test_env.pic,
+        satellite,
+        test_env.icrc3_id,
+        &transaction,
+    );
+    assert!(result.is_ok());
+
+    let blocks = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &vec![GetBlocksRequest {
+            start: Nat::from(0u64),
+            length: Nat::from(1u64),
+        }],
+    )
+    .blocks;
+    let ICRC3Value::Map(block) = &blocks[0].block else {
+        panic!("block is not a map");
+    };
+    assert_eq!(
+        block.get("rec"),
+        Some(&ICRC3Value::Blob(ByteBuf::from(
+            satellite.as_slice().to_vec()
+        )))
+    );
+
+    remove_recorder(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &satellite,
+    );
+    test_env.pic.advance_time(Duration::from_secs(2));
+    tick_n_blocks(&test_env.pic, 5);
+
+    let transaction =
+        create_transactions(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    let result = add_created_transaction(
+        &mut test_env.pic,
+        satellite,
+        test_env.icrc3_id,
+        &transaction,
+    );
+    assert!(result.unwrap_err().contains("Unauthorized"));
+
+    // The controller may still record.
+    let result = add_created_transaction(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &transaction,
+    );
+    assert!(result.is_ok());
+}
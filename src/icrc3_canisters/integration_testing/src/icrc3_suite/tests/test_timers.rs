@@ -0,0 +1,83 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::default_test_setup;
+use crate::icrc3_suite::setup::setup::TestEnv;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_canister_time::{TimerInfo, TimerKind, HOUR_IN_MS, MINUTE_IN_MS};
+use bity_ic_icrc3::job_history::JobKind;
+use std::time::Duration;
+
+fn job_timer(test_env: &TestEnv, job: JobKind) -> TimerInfo {
+    icrc3_timers(&test_env.pic, test_env.controller, test_env.icrc3_id, &())
+        .into_iter()
+        .find(|timer| timer.name == job.timer_name())
+        .unwrap_or_else(|| panic!("no timer for {job:?}"))
+}
+
+fn advance(test_env: &TestEnv, millis: u64) {
+    test_env.pic.advance_time(Duration::from_millis(millis));
+    tick_n_blocks(&test_env.pic, 5);
+}
+
+#[test]
+fn test_job_timers_report_their_fires() {
+    let mut test_env = default_test_setup();
+
+    let timers = icrc3_timers(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(timers.len(), 2);
+    let archive = job_timer(&test_env, JobKind::Archive);
+    assert_eq!(archive.kind, TimerKind::Interval);
+    assert_eq!(archive.interval_ms, 10 * MINUTE_IN_MS);
+    assert_eq!(archive.fire_count, 0);
+    assert_eq!(archive.last_fired_at, None);
+    assert_eq!(
+        archive.next_fire_at,
+        Some(archive.created_at + 10 * MINUTE_IN_MS)
+    );
+    let cleanup = job_timer(&test_env, JobKind::Cleanup);
+    assert_eq!(cleanup.interval_ms, HOUR_IN_MS);
+
+    advance(&test_env, 10 * MINUTE_IN_MS);
+    let archive = job_timer(&test_env, JobKind::Archive);
+    assert_eq!(archive.fire_count, 1);
+    let first_fire = archive.last_fired_at.unwrap();
+    assert!(first_fire >= archive.created_at + 10 * MINUTE_IN_MS);
+    assert_eq!(archive.next_fire_at, Some(first_fire + 10 * MINUTE_IN_MS));
+    assert_eq!(job_timer(&test_env, JobKind::Cleanup).fire_count, 0);
+
+    for _ in 0..5 {
+        advance(&test_env, 10 * MINUTE_IN_MS);
+    }
+    let archive = job_timer(&test_env, JobKind::Archive);
+    assert_eq!(archive.fire_count, 6);
+    assert!(archive.last_fired_at.unwrap() > first_fire);
+    let cleanup = job_timer(&test_env, JobKind::Cleanup);
+    assert_eq!(cleanup.fire_count, 1);
+
+    // Firing a job now runs it without moving its schedule.
+    assert_eq!(
+        fire_job_now(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &JobKind::Cleanup
+        ),
+        Ok(())
+    );
+    let fired = job_timer(&test_env, JobKind::Cleanup);
+    assert_eq!(fired.fire_count, 2);
+    assert!(fired.last_fired_at >= cleanup.last_fired_at);
+    assert_eq!(fired.next_fire_at, cleanup.next_fire_at);
+
+    advance(&test_env, HOUR_IN_MS);
+    assert_eq!(job_timer(&test_env, JobKind::Cleanup).fire_count, 3);
+
+    // The verification job was never started.
+    assert!(fire_job_now(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &JobKind::Verification
+    )
+    .is_err());
+}
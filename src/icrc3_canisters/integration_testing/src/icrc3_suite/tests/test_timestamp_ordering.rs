@@ -0,0 +1,101 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::setup::{TestEnv, TestEnvBuilder};
+
+use bity_ic_icrc3::config::ICRC3Properties;
+use bity_ic_icrc3::timestamp_ordering::TimestampOrdering;
+use candid::{Nat, Principal};
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+use std::time::Duration;
+
+const CLOCK_SKEW: Duration = Duration::from_secs(3);
+
+/// Two satellites recording into the ICRC3 canister, the clock of the first one
+/// `CLOCK_SKEW` ahead of the second one.
+fn setup_recorders(timestamp_ordering: TimestampOrdering) -> (TestEnv, Principal, Principal) {
+    let mut test_env = TestEnvBuilder::new();
+    test_env.icrc3_constants = ICRC3Properties {
+        recorders: Some(vec![]),
+        timestamp_ordering,
+        ..ICRC3Properties::default()
+    };
+    let mut test_env = test_env.build();
+
+    let ahead = test_env.pic.create_canister();
+    let behind = test_env.pic.create_canister();
+    for satellite in [ahead, behind] {
+        add_recorder(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &satellite,
+        );
+    }
+    (test_env, ahead, behind)
+}
+
+/// Records a transaction stamped `skew` ahead of the ICRC3 canister's clock.
+fn record(test_env: &mut TestEnv, satellite: Principal, skew: Duration) -> Result<(), String> {
+    let mut transaction =
+        create_transactions(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    transaction.timestamp += skew.as_nanos() as u64;
+    add_created_transaction(
+        &mut test_env.pic,
+        satellite,
+        test_env.icrc3_id,
+        &transaction,
+    )
+}
+
+#[test]
+fn test_recorders_with_skewed_clocks_succeed_when_clamped_to_tip() {
+    let (mut test_env, ahead, behind) = setup_recorders(TimestampOrdering::ClampToTip);
+
+    let properties =
+        icrc3_get_properties(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert_eq!(properties.timestamp_ordering, TimestampOrdering::ClampToTip);
+
+    assert!(record(&mut test_env, ahead, CLOCK_SKEW).is_ok());
+    for _ in 0..3 {
+        test_env.pic.advance_time(Duration::from_millis(200));
+        assert!(record(&mut test_env, behind, Duration::ZERO).is_ok());
+    }
+
+    let blocks = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &vec![GetBlocksRequest {
+            start: Nat::from(0u64),
+            length: Nat::from(4u64),
+        }],
+    )
+    .blocks;
+    assert_eq!(blocks.len(), 4);
+    let ICRC3Value::Map(first) = &blocks[0].block else {
+        panic!("block is not a map");
+    };
+    assert_eq!(first.get("orig_ts"), None);
+    let Some(ICRC3Value::Nat(ahead_timestamp)) = first.get("timestamp") else {
+        panic!("a block has a timestamp");
+    };
+    for block in &blocks[1..] {
+        let ICRC3Value::Map(block) = &block.block else {
+            panic!("block is not a map");
+        };
+        let Some(ICRC3Value::Nat(orig_ts)) = block.get("orig_ts") else {
+            panic!("a clamped block records its original timestamp");
+        };
+        assert!(orig_ts < ahead_timestamp);
+    }
+}
+
+#[test]
+fn test_recorders_with_skewed_clocks_are_rejected_when_strict() {
+    let (mut test_env, ahead, behind) = setup_recorders(TimestampOrdering::Strict);
+
+    assert!(record(&mut test_env, ahead, CLOCK_SKEW).is_ok());
+    test_env.pic.advance_time(Duration::from_millis(200));
+    let result = record(&mut test_env, behind, Duration::ZERO);
+    assert!(result.unwrap_err().contains("older than the previous tip"));
+}
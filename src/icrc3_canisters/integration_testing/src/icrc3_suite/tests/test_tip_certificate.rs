@@ -0,0 +1,35 @@
+use crate::client::icrc3::*;
+use crate::client::pocket::execute_update;
+use crate::icrc3_suite::setup::default_test_setup;
+use crate::utils::tick_n_blocks;
+
+use icrc_ledger_types::icrc3::blocks::ICRC3DataCertificate;
+use std::time::Duration;
+
+#[test]
+fn test_tip_certificate_is_only_available_in_a_query() {
+    let mut test_env = default_test_setup();
+
+    add_random_transaction(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &(),
+    );
+    test_env.pic.advance_time(Duration::from_secs(2));
+    tick_n_blocks(&test_env.pic, 5);
+
+    let tip_certificate =
+        icrc3_get_tip_certificate(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    assert!(tip_certificate.is_some());
+
+    // Called as an update, the endpoint has no data certificate and does not trap.
+    let tip_certificate: Option<ICRC3DataCertificate> = execute_update(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        "icrc3_get_tip_certificate",
+        &(),
+    );
+    assert!(tip_certificate.is_none());
+}
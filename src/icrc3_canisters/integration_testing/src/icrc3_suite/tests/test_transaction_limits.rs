@@ -0,0 +1,61 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::setup::TestEnvBuilder;
+
+use bity_ic_icrc3::config::ICRC3Properties;
+use candid::Nat;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+use serde_bytes::ByteBuf;
+
+#[test]
+fn test_oversized_memo_is_rejected() {
+    let max_memo_size = 32;
+    let mut test_env = TestEnvBuilder::new();
+    test_env.icrc3_constants = ICRC3Properties {
+        max_memo_size_bytes: Some(max_memo_size as u128),
+        ..ICRC3Properties::default()
+    };
+    let mut test_env = test_env.build();
+
+    let get_blocks_args = vec![GetBlocksRequest {
+        start: Nat::from(0u64),
+        length: Nat::from(10u64),
+    }];
+
+    let mut transaction =
+        create_transactions(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+    transaction.tx.memo = Some(ByteBuf::from(vec![1u8; max_memo_size + 1]));
+
+    let result = add_created_transaction(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &transaction,
+    );
+    assert!(result.unwrap_err().contains("MemoTooLarge"));
+
+    let get_blocks_result = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &get_blocks_args,
+    );
+    assert_eq!(get_blocks_result.log_length, Nat::from(0u64));
+
+    transaction.tx.memo = Some(ByteBuf::from(vec![1u8; max_memo_size]));
+
+    let result = add_created_transaction(
+        &mut test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &transaction,
+    );
+    assert!(result.is_ok());
+
+    let get_blocks_result = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &get_blocks_args,
+    );
+    assert_eq!(get_blocks_result.log_length, Nat::from(1u64));
+}
@@ -0,0 +1,58 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::{default_test_setup, setup_icrc3::upgrade_icrc3_canister};
+use crate::utils::tick_n_blocks;
+
+use bity_ic_types::BuildVersion;
+use ic_certification::{Certificate, HashTree, LookupResult};
+use icrc3_example_api::post_upgrade::UpgradeArgs;
+use std::time::Duration;
+
+#[test]
+fn test_tip_certificate_is_valid_right_after_upgrade() {
+    let mut test_env = default_test_setup();
+
+    for _ in 0..3 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 5);
+    }
+    let tip = icrc3_get_tip(&test_env.pic, test_env.controller, test_env.icrc3_id, &());
+
+    upgrade_icrc3_canister(
+        &mut test_env.pic,
+        test_env.icrc3_id,
+        icrc3_example_api::Args::Upgrade(UpgradeArgs {
+            version: BuildVersion::min(),
+            commit_hash: "commit_hash 2".to_string(),
+        }),
+        test_env.controller,
+    );
+
+    // No transaction is added after the upgrade.
+    let tip_certificate =
+        icrc3_get_tip_certificate(&test_env.pic, test_env.controller, test_env.icrc3_id, &())
+            .expect("a query has a data certificate");
+    let certificate: Certificate =
+        serde_cbor::from_slice(tip_certificate.certificate.as_slice()).unwrap();
+    let certified_data = certificate.tree.lookup_path([
+        b"canister".as_slice(),
+        test_env.icrc3_id.as_slice(),
+        b"certified_data".as_slice(),
+    ]);
+    let hash_tree: HashTree = serde_cbor::from_slice(tip_certificate.hash_tree.as_slice()).unwrap();
+    assert_eq!(
+        certified_data,
+        LookupResult::Found(hash_tree.digest().as_slice())
+    );
+
+    // The tip itself is unchanged.
+    assert_eq!(
+        icrc3_get_tip(&test_env.pic, test_env.controller, test_env.icrc3_id, &(),),
+        tip
+    );
+}
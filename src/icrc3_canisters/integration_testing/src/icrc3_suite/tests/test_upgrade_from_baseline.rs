@@ -0,0 +1,73 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::setup::TestEnvBuilder;
+use crate::icrc3_suite::setup::setup_icrc3::upgrade_icrc3_canister;
+use crate::utils::tick_n_blocks;
+use crate::wasms::ICRC3_BASELINE;
+
+use bity_ic_canister_time::{HOUR_IN_MS, MINUTE_IN_MS};
+use bity_ic_icrc3::job_history::JobKind;
+use bity_ic_types::BuildVersion;
+use candid::{encode_one, Nat};
+use icrc3_example_api::post_upgrade::UpgradeArgs;
+use icrc_ledger_types::icrc3::blocks::GetBlocksRequest;
+use pocket_ic::PocketIcBuilder;
+use std::time::Duration;
+
+/// The canister of the previous version saved the ICRC3 state itself rather
+/// than its `icrc3_pre_upgrade` bytes, and recorded no job intervals.
+#[test]
+fn test_upgrade_from_baseline_keeps_the_blocks_and_the_jobs() {
+    let test_env_builder = TestEnvBuilder::new();
+    let controller = test_env_builder.controller;
+    let mut pic = PocketIcBuilder::new().with_application_subnet().build();
+    let icrc3_id = pic.create_canister_with_settings(Some(controller), None);
+    pic.add_cycles(icrc3_id, 100_000_000_000_000_000_000);
+    pic.install_canister(
+        icrc3_id,
+        ICRC3_BASELINE.clone(),
+        encode_one(test_env_builder.icrc3_init_args(vec![])).unwrap(),
+        Some(controller),
+    );
+
+    for _ in 0..3 {
+        add_random_transaction(&mut pic, controller, icrc3_id, &());
+        pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&pic, 5);
+    }
+    let get_blocks_args = vec![GetBlocksRequest {
+        start: Nat::from(0u64),
+        length: Nat::from(10u64),
+    }];
+    let blocks = icrc3_get_blocks(&pic, controller, icrc3_id, &get_blocks_args);
+    assert_eq!(blocks.log_length, Nat::from(3u64));
+
+    upgrade_icrc3_canister(
+        &mut pic,
+        icrc3_id,
+        icrc3_example_api::Args::Upgrade(UpgradeArgs {
+            version: BuildVersion::min(),
+            commit_hash: "commit_hash 2".to_string(),
+        }),
+        controller,
+    );
+
+    assert_eq!(
+        icrc3_get_blocks(&pic, controller, icrc3_id, &get_blocks_args),
+        blocks
+    );
+
+    let timers = icrc3_timers(&pic, controller, icrc3_id, &());
+    let interval_ms = |job: JobKind| {
+        timers
+            .iter()
+            .find(|timer| timer.name == job.timer_name())
+            .unwrap_or_else(|| panic!("no timer for {job:?}"))
+            .interval_ms
+    };
+    assert_eq!(interval_ms(JobKind::Archive), 10 * MINUTE_IN_MS);
+    assert_eq!(interval_ms(JobKind::Cleanup), HOUR_IN_MS);
+
+    add_random_transaction(&mut pic, controller, icrc3_id, &());
+    let blocks = icrc3_get_blocks(&pic, controller, icrc3_id, &get_blocks_args);
+    assert_eq!(blocks.log_length, Nat::from(4u64));
+}
@@ -0,0 +1,97 @@
+use crate::client::icrc3::*;
+use crate::icrc3_suite::setup::default_test_setup;
+use crate::utils::tick_n_blocks;
+
+use bity_ic_icrc3::utils::get_timestamp;
+use bity_ic_icrc3_verifier::hashing::tip_hash_tree;
+use bity_ic_icrc3_verifier::{
+    verify_block_witness, verify_tip_certificate, BlockWitness, VerifyError,
+};
+use candid::Nat;
+use ic_certification::AsHashTree;
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use icrc_ledger_types::icrc3::blocks::{GetBlocksRequest, ICRC3DataCertificate};
+use serde_bytes::ByteBuf;
+use std::time::Duration;
+
+#[test]
+fn test_verify_tip_certificate_and_blocks() {
+    let mut test_env = default_test_setup();
+    let root_key = test_env.pic.root_key().expect("PocketIC has a root key");
+
+    for _ in 0..3 {
+        add_random_transaction(
+            &mut test_env.pic,
+            test_env.controller,
+            test_env.icrc3_id,
+            &(),
+        );
+        test_env.pic.advance_time(Duration::from_secs(2));
+        tick_n_blocks(&test_env.pic, 5);
+    }
+
+    let tip_certificate =
+        icrc3_get_tip_certificate(&test_env.pic, test_env.controller, test_env.icrc3_id, &())
+            .expect("a query has a data certificate");
+    // The certificate is signed by PocketIC with its root key, as by the IC.
+    let claim = verify_tip_certificate(&tip_certificate, &root_key, test_env.icrc3_id).unwrap();
+    let tip = icrc3_get_tip(&test_env.pic, test_env.controller, test_env.icrc3_id, &()).unwrap();
+    assert_eq!(tip.index, claim.last_block_index);
+    assert_eq!(tip.block_hash.as_slice(), claim.last_block_hash);
+
+    let blocks = icrc3_get_blocks(
+        &test_env.pic,
+        test_env.controller,
+        test_env.icrc3_id,
+        &vec![GetBlocksRequest {
+            start: Nat::from(0u64),
+            length: Nat::from(3u64),
+        }],
+    );
+    let witness: Vec<BlockWitness> = blocks
+        .blocks
+        .into_iter()
+        .map(|block| BlockWitness {
+            timestamp: u128::try_from(get_timestamp(&block.block).unwrap().0).unwrap(),
+            block,
+        })
+        .collect();
+    assert_eq!(verify_block_witness(&claim, &witness), Ok(()));
+
+    // A tampered block is rejected.
+    let mut tampered = witness.clone();
+    if let ICRC3Value::Map(map) = &mut tampered[1].block.block {
+        map.insert(
+            "btype".to_string(),
+            ICRC3Value::Text("tampered".to_string()),
+        );
+    }
+    assert_eq!(
+        verify_block_witness(&claim, &tampered),
+        Err(VerifyError::BlockHashMismatch { index: 1 })
+    );
+
+    // So is a tree that is not the certified one.
+    let other_tree = tip_hash_tree(Some((claim.last_block_index + 1, claim.last_block_hash)));
+    let tampered = ICRC3DataCertificate {
+        certificate: tip_certificate.certificate.clone(),
+        hash_tree: ByteBuf::from(serde_cbor::to_vec(&other_tree.as_hash_tree()).unwrap()),
+    };
+    assert_eq!(
+        verify_tip_certificate(&tampered, &root_key, test_env.icrc3_id),
+        Err(VerifyError::CertifiedDataMismatch)
+    );
+
+    // And a certificate whose signature was altered.
+    let mut certificate: ic_certification::Certificate =
+        serde_cbor::from_slice(&tip_certificate.certificate).unwrap();
+    certificate.signature[0] ^= 1;
+    let tampered = ICRC3DataCertificate {
+        certificate: ByteBuf::from(serde_cbor::to_vec(&certificate).unwrap()),
+        hash_tree: tip_certificate.hash_tree,
+    };
+    assert!(matches!(
+        verify_tip_certificate(&tampered, &root_key, test_env.icrc3_id),
+        Err(VerifyError::InvalidSignature(_))
+    ));
+}
@@ -1,6 +1,7 @@
 #![cfg(test)]
 
 mod client;
+pub mod icp_payment_suite;
 pub mod icrc3_suite;
 mod utils;
 mod wasms;
@@ -5,6 +5,8 @@ use std::io::Read;
 
 lazy_static! {
     pub static ref ICRC3: CanisterWasm = get_canister_wasm_from_bin("icrc3");
+    pub static ref ICP_LEDGER: CanisterWasm = get_canister_wasm_from_bin("icp_ledger");
+    pub static ref ICRC3_BASELINE: CanisterWasm = get_canister_wasm_from_bin("icrc3_baseline");
 }
 
 fn get_canister_wasm_from_bin(canister_name: &str) -> CanisterWasm {
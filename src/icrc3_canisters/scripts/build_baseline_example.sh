@@ -0,0 +1,19 @@
+#!/bin/bash
+# Builds the example canister of a previous version, which the upgrade integration
+# tests upgrade from.
+# Usage: ./scripts/build_baseline_example.sh <git-ref>
+
+BASELINE_REF=$1
+if [ -z "$BASELINE_REF" ]; then
+    echo "Usage: $0 <git-ref>"
+    exit 1
+fi
+
+worktree=$(mktemp -d)
+git worktree add --detach "$worktree" "$BASELINE_REF" &&
+(cd "$worktree/src/icrc3_canisters" && ./scripts/build_example.sh) &&
+mkdir -p ./integration_testing/wasm &&
+cp "$worktree/src/icrc3_canisters/wasm/icrc3_example_canister.wasm.gz" ./integration_testing/wasm/icrc3_baseline_canister.wasm.gz
+status=$?
+git worktree remove --force "$worktree"
+exit $status
@@ -1,6 +1,8 @@
 #!/bin/bash
+# Usage: ./scripts/build_example.sh [features], e.g. `testing-hooks` for the
+# wasm of the integration tests.
 
-cargo rustc --crate-type=cdylib --target wasm32-unknown-unknown --target-dir "./canisters/icrc3_example/target" --release --locked -p icrc3-example &&
+cargo rustc --crate-type=cdylib --target wasm32-unknown-unknown --target-dir "./canisters/icrc3_example/target" --release --locked -p icrc3-example ${1:+--features "$1"} &&
 ic-wasm "./canisters/icrc3_example/target/wasm32-unknown-unknown/release/icrc3_example.wasm" -o "./canisters/icrc3_example/target/wasm32-unknown-unknown/release/icrc3_example.wasm" shrink &&
 ic-wasm "./canisters/icrc3_example/target/wasm32-unknown-unknown/release/icrc3_example.wasm" -o "./canisters/icrc3_example/target/wasm32-unknown-unknown/release/icrc3_example.wasm" optimize --inline-functions-with-loops O3 &&
 gzip --no-name -9 -v -c "./canisters/icrc3_example/target/wasm32-unknown-unknown/release/icrc3_example.wasm" > "./canisters/icrc3_example/target/wasm32-unknown-unknown/release/icrc3_example_canister.wasm.gz" &&
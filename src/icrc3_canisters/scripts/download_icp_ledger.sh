@@ -0,0 +1,13 @@
+#!/bin/bash
+# Downloads the ICP ledger wasm used by the ICP payment integration tests.
+# Usage: ./scripts/download_icp_ledger.sh <ic-commit>
+
+IC_COMMIT=$1
+if [ -z "$IC_COMMIT" ]; then
+    echo "Usage: $0 <ic-commit>"
+    exit 1
+fi
+
+mkdir -p ./integration_testing/wasm &&
+curl -fsSL -o ./integration_testing/wasm/icp_ledger_canister.wasm.gz "https://download.dfinity.systems/ic/$IC_COMMIT/canisters/ledger-canister.wasm.gz" &&
+gzip -v -t ./integration_testing/wasm/icp_ledger_canister.wasm.gz
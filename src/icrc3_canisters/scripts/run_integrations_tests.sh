@@ -1,4 +1,9 @@
 ./scripts/build_archive.sh
-./scripts/build_example.sh
+./scripts/build_example.sh testing-hooks
+./scripts/build_baseline_example.sh "${BASELINE_REF:-main}"
+# The ICP payment tests run against the ICP ledger of the IC commit IC_COMMIT.
+if [ ! -f ./integration_testing/wasm/icp_ledger_canister.wasm.gz ]; then
+    ./scripts/download_icp_ledger.sh "$IC_COMMIT" || exit 1
+fi
 
-cargo test -p integration_testing
\ No newline at end of file
+cargo test -p integration_testing
@@ -10,56 +10,246 @@
 ///
 /// # Generated Functions
 /// * `init_icrc3()` - Initializes the ICRC3 state
+/// * `icrc3_pre_upgrade() -> Vec<u8>` - Takes the ICRC3 state and serializes it with `bity_ic_serializer`
+/// * `icrc3_post_upgrade(bytes: &[u8], config_override: Option<ICRC3Config>)` - Restores the serialized state,
+///   applies the config override, indexes the local blocks by transaction hash if needed, sets the certified data
+///   and restarts the jobs that were running, with their previous intervals
+/// * `icrc3_mark_legacy_state(icrc3: &mut ICRC3)` - Marks a state saved before the job intervals were recorded,
+///   so that `icrc3_post_upgrade` restarts the archive and cleanup jobs such a canister always ran with the default intervals
+/// * `start_archive_job(interval_ms: u64)`, `start_cleanup_job(interval_ms: u64)` and
+///   `start_default_archive_job()` - Periodically archive the local blocks and clean them up
+/// * `schedule_archive_job_now()` - Runs the archive job once, right away. `icrc3_add_transaction`
+///   and `icrc3_commit_prepared_transaction` call it when the local archive capacity falls below
+///   `local_archive_low_water_mark_percent`, or is too low for a block, which is then rejected with
+///   `Icrc3Error::LocalArchiveFull`
+/// * `icrc3_run_verification_now(sample_size: u32) -> Result<u128, String>` - Verifies a random sample of archived blocks,
+///   or fails if a previous run has not ended yet
+/// * `start_verification_job(interval_ms: u64, sample_size: u32)` - Periodically verifies a random sample of archived blocks
+/// * `icrc3_check_archive_funding() -> Result<Vec<FundingAlert>, String>` - Samples the cycle balances and reports the archive canisters trending towards freezing
+/// * `start_funding_health_job(interval_ms: u64)` - Periodically checks the funding health of the archive canisters
+/// * `icrc3_flush_notifications()` - Delivers the queued block notifications due now to the subscribers
+/// * `start_notification_job(interval_ms: u64)` - Periodically delivers the queued block notifications
+///
+/// The functions above are always generated, the jobs and the upgrade hooks rely on them.
+/// The wrappers below are generated unless the selection leaves them out:
+///
 /// * `add_transaction(transaction: T) -> Result<u64, Icrc3Error>` - Adds a new transaction
+///   and returns the 0-based id of its block
 /// * `icrc3_get_archives() -> Vec<ICRC3ArchiveInfo>` - Gets information about archives
+/// * `icrc3_get_archive_stats() -> Vec<ArchiveStats>` - Gets the blocks, bytes and remaining capacity of each archive, as last fetched by the archive job and timestamped
 /// * `icrc3_get_blocks(args: Vec<GetBlocksRequest>) -> Response` - Gets blocks
 /// * `icrc3_get_properties() -> Response` - Gets blockchain properties
-/// * `icrc3_get_tip_certificate() -> ICRC3DataCertificate` - Gets the tip certificate
+/// * `icrc3_get_tip_certificate() -> Option<ICRC3DataCertificate>` - Gets the tip certificate, `None` outside a query
 /// * `icrc3_supported_block_types() -> Vec<SupportedBlockType>` - Gets supported block types
+/// * `icrc10_supported_standards(extra: &[(&str, &str)]) -> Vec<StandardRecord>` - Gets the standards derived from the block types, ICRC-10 and the `(name, url)` of `extra`
+/// * `icrc3_block_schemas() -> Vec<BlockSchema>` - Gets the fields of each supported block type
+/// * `icrc3_register_block_schema(schema: TransactionSchema)` - Documents and validates a custom block type
+/// * `icrc3_register_transaction_type::<T>()` - Registers the block schemas of a transaction type
+/// * `icrc3_get_tip() -> Option<TipInfo>` - Gets the index, hash and timestamp of the last block
+/// * `icrc3_chain_length() -> Nat` - Gets the number of blocks in the chain
+/// * `icrc3_has_block(index: Nat) -> bool` - Checks whether a block exists, locally or in an archive
+/// * `find_blocks_by_memo(memo: ByteBuf, max: u32) -> Vec<Nat>` - Finds the local blocks with a memo when `index_memos` is set, archived blocks are not searched
+/// * `find_block_by_thash(thash: ByteBuf) -> Result<Option<BlockWithId>, String>` - Finds the block recording a transaction by its hash, locally then in the archives indexing them with `index_thashes`
+/// * `get_transactions(request: GetTransactionsRequest) -> GetTransactionsResponse` - Gets the ICRC-1 and ICRC-2 blocks in the legacy format of the ICRC-1 ledgers, traps unless `legacy_transactions` is set
+/// * `icrc3_update_funding_config(funding_config: FundingConfig) -> Result<(), Icrc3Error>` - Updates the archive funding config
+/// * `icrc3_funding_config() -> FundingConfig` - Gets the active archive funding config
+/// * `icrc3_job_history() -> Vec<JobRunRecord>` - Gets the most recent archive, cleanup and verification job runs
+/// * `icrc3_job_history_metrics() -> JobHistoryMetrics` - Gets the last success/failure of each job and the current archive batch size
+/// * `icrc3_dedup_window_metrics() -> DedupWindowMetrics` - Gets the size of the deduplication window, in bytes and entries, and its number of entries inside the throttling window
+/// * `icrc3_list_prepared_transactions(args: ListPreparedTransactionsArgs) -> Vec<PreparedTransactionInfo>` - Lists the pending prepared transactions, oldest first.
+///   It exposes the hashes of uncommitted transactions, so canisters should only serve it behind an admin guard
+/// * `icrc3_prepared_transactions_metrics() -> PreparedTransactionsMetrics` - Gets the number of pending prepared transactions and the age of the oldest one
+/// * `icrc3_validation_metrics() -> ValidationMetrics` - Gets the number of transactions of each block type accepted despite violations in lenient validation mode
+/// * `icrc3_timestamp_ordering_metrics() -> TimestampOrderingMetrics` - Gets the number of blocks whose timestamp was raised to the tip's
+/// * `icrc3_transactions_per_sec() -> f64` - Gets the number of blocks added per second over the last 5 minutes
+/// * `icrc3_timers() -> Vec<TimerInfo>` - Gets the timers of the archive, cleanup, verification, funding health and notification jobs that were started
+/// * `icrc3_fire_job_now(job: JobKind) -> Result<(), String>` - Runs a started job now, without moving its schedule
+/// * `icrc3_archive_history() -> Vec<ArchiveCanisterHistory>` - Gets when each archive canister was created and upgraded
+/// * `icrc3_add_recorder(recorder: Principal)` - Allows a principal to record transactions
+/// * `icrc3_remove_recorder(recorder: Principal)` - Stops a principal from recording transactions
+/// * `icrc3_retire_archive(canister_id: Principal) -> Result<(), String>` - Stops inserting blocks into an archive canister, which stays funded and queryable
+/// * `icrc3_unretire_archive(canister_id: Principal) -> Result<(), String>` - Puts a retired archive canister back into service, unless it is marked unrecoverable
+/// * `icrc3_mark_archive_unrecoverable(canister_id: Principal) -> Result<(), String>` - Marks the blocks of a retired archive canister as lost
+/// * `icrc3_reinstall_archive(confirmation: ReinstallConfirmation) -> Result<(), String>` - Reinstalls a retired archive canister marked unrecoverable, wiping its state
+/// * `icrc3_add_archive_controller(canister_id: Principal, controller: Principal) -> Result<Vec<Principal>, String>` - Adds a controller to an archive canister
+/// * `icrc3_remove_archive_controller(canister_id: Principal, controller: Principal) -> Result<Vec<Principal>, String>` - Removes a controller from an archive canister
+/// * `icrc3_take_archive_snapshot(canister_id: Principal) -> Result<SnapshotId, String>` - Takes a snapshot of an archive canister
+/// * `icrc3_list_archive_snapshots(canister_id: Principal) -> Result<Vec<Snapshot>, String>` - Lists the snapshots of an archive canister
+/// * `icrc3_restore_archive_snapshot(canister_id: Principal, snapshot_id: SnapshotId) -> Result<(), String>` - Restores an archive canister from a snapshot
+/// * `icrc3_delete_archive_snapshot(canister_id: Principal, snapshot_id: SnapshotId) -> Result<(), String>` - Deletes a snapshot of an archive canister
+/// * `icrc3_reconcile_archives(auto_adopt: bool) -> Result<ReconciliationReport, String>` - Reports the archive canisters whose module or status diverges from the records
+/// * `icrc3_rebuild_from_archives(archives: Vec<Principal>) -> Result<RebuildReport, RebuildError>` - Rebuilds an empty chain from its archive canisters, after the state was lost
+/// * `icrc3_subscribe(canister_id: Principal, method_name: String, filter: Option<Vec<String>>) -> Result<(), String>` - Subscribes a canister to the notifications of the appended blocks, of every type or of the types in `filter`
+/// * `icrc3_unsubscribe(canister_id: Principal) -> bool` - Removes a subscriber and its queued notifications
+/// * `icrc3_notification_metrics() -> Vec<SubscriberMetrics>` - Gets the queued, delivered and dropped notifications of each subscriber, and whether it is suspended
+/// * `icrc3_archive_funding_alerts() -> Vec<FundingAlert>` - Gets the funding alerts of the last check
 /// * `upgrade_archive_wasm(wasm_module: Vec<u8>)` - Upgrades the archive canister WASM
 ///
+/// `icrc3_add_transaction`, `icrc3_prepare_transaction` and `icrc3_commit_prepared_transaction`
+/// return `Icrc3Error::Unauthorized` when recorders are configured and the caller is neither
+/// a recorder, a controller nor the canister itself.
+///
+/// When `audit_admin_actions` is set, `icrc3_post_upgrade` and the endpoints changing the
+/// recorders, the subscribers, the funding config, the archive canisters or their snapshots record the
+/// action and its caller as an `admin` block once it succeeded, see `bity_ic_icrc3::audit`.
+///
+/// # Layout
+///
+/// The items are generated in a `pub mod icrc3_state` with fully qualified paths, no `use`
+/// statement is added at the call site, and they are re-exported with a glob. A function of
+/// the calling module with the name of a generated one shadows it, the generated one staying
+/// reachable as `icrc3_state::name`.
+///
+/// # Selection
+///
+/// `icrc3_state!(only(...))` generates the listed wrappers only, `icrc3_state!(except(...))`
+/// all but the listed ones. A wrapper is listed by its name, or by its name without its
+/// `icrc3_`, `icrc3_get_` or `icrc10_` prefix. Listing an unknown name, or a name twice, is
+/// a compile error.
+///
 /// # Example
-/// ```
-/// use icrc3_library::icrc3_macros::icrc3_state;
+/// ```ignore
+/// use bity_ic_icrc3_macros::icrc3_state;
 ///
-/// icrc3_state!();
+/// icrc3_state!(only(add_transaction, get_blocks, tip_certificate));
 ///
 /// fn add_transaction(transaction: MyTransaction) -> Result<u64, Icrc3Error> {
-///     add_transaction(transaction)
+///     icrc3_add_transaction(transaction)
 /// }
 ///
-/// fn get_archives() -> Vec<ICRC3ArchiveInfo> {
-///     icrc3_get_archives()
+/// // Shadows the wrapper, still reachable as `icrc3_state::icrc3_get_blocks`.
+/// fn icrc3_get_blocks(args: Vec<GetBlocksRequest>) -> GetBlocksResult {
+///     icrc3_state::icrc3_get_blocks(args)
 /// }
 /// ```
-///
-///
-// icrc3_macros/src/lib.rs
 extern crate proc_macro;
 
 use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
 use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, Ident, Token};
 
 #[proc_macro]
-pub fn icrc3_state(_input: TokenStream) -> TokenStream {
+pub fn icrc3_state(input: TokenStream) -> TokenStream {
+    let selection = parse_macro_input!(input as Selection);
+    let wrappers = match selection.select(wrapper_items()) {
+        Ok(wrappers) => wrappers,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let core = core_items();
+
     let expanded = quote! {
-        use lazy_static::lazy_static;
-        use std::sync::{Arc, RwLock};
-        use icrc_ledger_types::icrc3::blocks::{GetBlocksResult, GetBlocksRequest, ICRC3DataCertificate, SupportedBlockType};
-        use icrc_ledger_types::icrc3::archive::ICRC3ArchiveInfo;
-        use bity_ic_icrc3::{config::{ICRC3Config, ICRC3Properties}, icrc3::ICRC3, interface::ICRC3Interface, types::Icrc3Error};
-        use bity_ic_canister_time::{run_interval, MINUTE_IN_MS, HOUR_IN_MS};
-        use std::time::Duration;
+        pub mod icrc3_state {
+            #core
+
+            #(#wrappers)*
+        }
+
+        pub use self::icrc3_state::*;
+    };
+
+    expanded.into()
+}
+
+/// The wrappers to generate.
+enum Selection {
+    /// All of them
+    All,
+    /// The ones listed with `only(...)`
+    Only(Vec<Ident>),
+    /// All but the ones listed with `except(...)`
+    Except(Vec<Ident>),
+}
+
+impl Parse for Selection {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.is_empty() {
+            return Ok(Selection::All);
+        }
+
+        let keyword: Ident = input.parse()?;
+        if keyword != "only" && keyword != "except" {
+            return Err(syn::Error::new(
+                keyword.span(),
+                "expected `only(...)` or `except(...)` listing the wrappers to generate or to leave out",
+            ));
+        }
+        let content;
+        syn::parenthesized!(content in input);
+        let names = Punctuated::<Ident, Token![,]>::parse_terminated(&content)?
+            .into_iter()
+            .collect();
+        if !input.is_empty() {
+            return Err(input.error(format!("unexpected tokens after `{}(...)`", keyword)));
+        }
+        if keyword == "only" {
+            Ok(Selection::Only(names))
+        } else {
+            Ok(Selection::Except(names))
+        }
+    }
+}
+
+impl Selection {
+    /// Keeps the selected wrappers, in their generation order.
+    fn select(
+        &self,
+        wrappers: Vec<(&'static str, TokenStream2)>,
+    ) -> syn::Result<Vec<TokenStream2>> {
+        let (names, keep_listed) = match self {
+            Selection::All => return Ok(wrappers.into_iter().map(|(_, items)| items).collect()),
+            Selection::Only(names) => (names, true),
+            Selection::Except(names) => (names, false),
+        };
+
+        let available: Vec<&'static str> = wrappers.iter().map(|(name, _)| *name).collect();
+        let mut selected = vec![];
+        for name in names {
+            let wrapper = resolve_wrapper(&name.to_string(), &available).ok_or_else(|| {
+                syn::Error::new(name.span(), format!("unknown ICRC3 wrapper `{}`", name))
+            })?;
+            if selected.contains(&wrapper) {
+                return Err(syn::Error::new(
+                    name.span(),
+                    format!("the ICRC3 wrapper `{}` is listed twice", wrapper),
+                ));
+            }
+            selected.push(wrapper);
+        }
+        Ok(wrappers
+            .into_iter()
+            .filter(|(name, _)| selected.contains(name) == keep_listed)
+            .map(|(_, items)| items)
+            .collect())
+    }
+}
+
+/// Finds the wrapper listed as `name`, either by its name or without its prefix.
+fn resolve_wrapper(name: &str, wrappers: &[&'static str]) -> Option<&'static str> {
+    wrappers.iter().copied().find(|wrapper| {
+        *wrapper == name
+            || ["icrc3_get_", "icrc3_", "icrc10_"]
+                .iter()
+                .any(|prefix| wrapper.strip_prefix(prefix) == Some(name))
+    })
+}
 
-        lazy_static! {
-            pub static ref ICRC3_INSTANCE: Arc<RwLock<Option<ICRC3>>> = Arc::new(RwLock::new(None));
+/// The items generated whatever the selection: the state, the lifecycle functions and the jobs.
+fn core_items() -> TokenStream2 {
+    quote! {
+        ::lazy_static::lazy_static! {
+            pub static ref ICRC3_INSTANCE: ::std::sync::Arc<::std::sync::RwLock<Option<::bity_ic_icrc3::icrc3::ICRC3>>> = ::std::sync::Arc::new(::std::sync::RwLock::new(None));
         }
 
         const __ICRC3_NOT_INITIALIZED: &str = "ICRC3 state has not been initialized";
+        const __ICRC3_DEFAULT_ARCHIVE_JOB_INTERVAL_MS: u64 = 10 * ::bity_ic_canister_time::MINUTE_IN_MS;
+        const __ICRC3_DEFAULT_CLEANUP_JOB_INTERVAL_MS: u64 = ::bity_ic_canister_time::HOUR_IN_MS;
 
-        pub fn init_icrc3(config: ICRC3Config) {
+        pub fn init_icrc3(config: ::bity_ic_icrc3::config::ICRC3Config) {
             let mut lock = ICRC3_INSTANCE.write().unwrap();
-            *lock = Some(ICRC3::new(config));
+            *lock = Some(::bity_ic_icrc3::icrc3::ICRC3::new(config));
         }
 
         pub fn is_initialized() -> bool {
@@ -67,125 +257,1041 @@ pub fn icrc3_state(_input: TokenStream) -> TokenStream {
             lock.is_some()
         }
 
-        pub fn take_icrc3() -> Option<ICRC3> {
+        pub fn take_icrc3() -> Option<::bity_ic_icrc3::icrc3::ICRC3> {
             let mut lock = ICRC3_INSTANCE.write().unwrap();
             lock.take()
         }
 
-        pub fn replace_icrc3(icrc3: ICRC3) {
+        pub fn replace_icrc3(mut icrc3: ::bity_ic_icrc3::icrc3::ICRC3) {
+            icrc3.recompute_dedup_window_bytes();
             let mut lock = ICRC3_INSTANCE.write().unwrap();
             *lock = Some(icrc3);
         }
 
-        pub fn icrc3_add_transaction<T: TransactionType>(
-            transaction: T,
-        ) -> Result<u64, Icrc3Error> {
-            let mut lock = ICRC3_INSTANCE.write().unwrap();
-            let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
-            <ICRC3 as ICRC3Interface>::add_transaction(icrc3, transaction)
+        pub fn icrc3_pre_upgrade() -> Vec<u8> {
+            let icrc3 = take_icrc3().expect(__ICRC3_NOT_INITIALIZED);
+            ::bity_ic_serializer::serialize_to_vec(icrc3).expect("Failed to serialize the ICRC3 state")
         }
 
-        pub fn icrc3_prepare_transaction<T: TransactionType>(
-            transaction: T,
-        ) -> Result<bity_ic_icrc3::types::prepare_transaction::PreparedTransaction, Icrc3Error> {
-            let mut lock = ICRC3_INSTANCE.write().unwrap();
-            let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
-            <ICRC3 as ICRC3Interface>::prepare_transaction(icrc3, transaction)
+        pub fn icrc3_mark_legacy_state(icrc3: &mut ::bity_ic_icrc3::icrc3::ICRC3) {
+            icrc3.archive_job_interval_ms.get_or_insert(__ICRC3_DEFAULT_ARCHIVE_JOB_INTERVAL_MS);
+            icrc3.cleanup_job_interval_ms.get_or_insert(__ICRC3_DEFAULT_CLEANUP_JOB_INTERVAL_MS);
         }
 
-        pub fn icrc3_commit_prepared_transaction<T: TransactionType>(
-            transaction: T,
-            timestamp: u128,
-        ) -> Result<u64, Icrc3Error> {
-            let mut lock = ICRC3_INSTANCE.write().unwrap();
-            let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
-            <ICRC3 as ICRC3Interface>::commit_prepared_transaction(icrc3, transaction, timestamp)
-        }
+        pub fn icrc3_post_upgrade(bytes: &[u8], config_override: Option<::bity_ic_icrc3::config::ICRC3Config>) {
+            let mut icrc3: ::bity_ic_icrc3::icrc3::ICRC3 = ::bity_ic_serializer::deserialize_from_slice(bytes)
+                .expect("Failed to deserialize the ICRC3 state");
+            let mut details = vec![("version", ::icrc_ledger_types::icrc::generic_value::ICRC3Value::Text(env!("CARGO_PKG_VERSION").to_string()))];
+            if let Some(icrc3_config) = config_override {
+                if let Err(e) = icrc3.apply_config(icrc3_config) {
+                    ::ic_cdk::trap(e);
+                }
+                details.push(("config_override", ::icrc_ledger_types::icrc::generic_value::ICRC3Value::Text("applied".to_string())));
+            }
+            icrc3.rebuild_thash_index();
+            icrc3.refresh_certified_data();
+            ::bity_ic_icrc3::audit::record_admin_action(&mut icrc3, "upgrade", details);
 
-        pub fn icrc3_get_archives() -> Vec<ICRC3ArchiveInfo> {
-            let lock = ICRC3_INSTANCE.read().unwrap();
-            let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
-            <ICRC3 as ICRC3Interface>::icrc3_get_archives(icrc3)
-        }
+            let archive_job_interval_ms = icrc3.archive_job_interval_ms;
+            let cleanup_job_interval_ms = icrc3.cleanup_job_interval_ms;
+            let verification_job = icrc3.verification_job;
+            let funding_health_job_interval_ms = icrc3.funding_health_job_interval_ms;
+            let notification_job_interval_ms = icrc3.notification_job_interval_ms;
+            replace_icrc3(icrc3);
 
-        pub fn icrc3_get_blocks(
-            args: Vec<GetBlocksRequest>,
-        ) -> GetBlocksResult {
-            let lock = ICRC3_INSTANCE.read().unwrap();
-            let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
-            <ICRC3 as ICRC3Interface>::icrc3_get_blocks(icrc3, args)
-        }
-
-        pub fn icrc3_get_properties() -> ICRC3Properties {
-            let lock = ICRC3_INSTANCE.read().unwrap();
-            let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
-            <ICRC3 as ICRC3Interface>::icrc3_get_properties(icrc3)
+            if let Some(interval_ms) = archive_job_interval_ms {
+                start_archive_job(interval_ms);
+            }
+            if let Some(interval_ms) = cleanup_job_interval_ms {
+                start_cleanup_job(interval_ms);
+            }
+            if let Some(job) = verification_job {
+                start_verification_job(job.interval_ms, job.sample_size);
+            }
+            if let Some(interval_ms) = funding_health_job_interval_ms {
+                start_funding_health_job(interval_ms);
+            }
+            if let Some(interval_ms) = notification_job_interval_ms {
+                start_notification_job(interval_ms);
+            }
         }
 
-        pub fn icrc3_get_tip_certificate() -> ICRC3DataCertificate {
-            let lock = ICRC3_INSTANCE.read().unwrap();
-            let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
-            <ICRC3 as ICRC3Interface>::icrc3_get_tip_certificate(icrc3)
+        pub fn start_archive_job(interval_ms: u64) {
+            if let Some(icrc3) = ICRC3_INSTANCE.write().unwrap().as_mut() {
+                icrc3.archive_job_interval_ms = Some(interval_ms);
+            }
+            ::bity_ic_canister_time::run_interval_named(::bity_ic_icrc3::job_history::JobKind::Archive.timer_name(), ::std::time::Duration::from_millis(interval_ms), __icrc3_spawn_archive_job);
         }
 
-        pub fn icrc3_supported_block_types() -> Vec<SupportedBlockType> {
-            let lock = ICRC3_INSTANCE.read().unwrap();
-            let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
-            <ICRC3 as ICRC3Interface>::icrc3_supported_block_types(icrc3)
+        // Runs the archive job once, as soon as possible, when the add path requested it.
+        pub fn schedule_archive_job_now() {
+            ::bity_ic_canister_time::run_once_named("icrc3_archive_job_now", ::std::time::Duration::ZERO, __icrc3_spawn_archive_job);
         }
 
-        pub fn start_archive_job(interval_ms: u64) {
-            run_interval(Duration::from_millis(interval_ms), || {
-                ic_cdk::futures::spawn(async {
-                    match ICRC3_INSTANCE.write() {
-                        Ok(mut lock) => {
-                            if let Some(icrc3) = lock.as_mut() {
-                                if let Err(e) = icrc3.archive_job().await {
-                                    bity_ic_icrc3::utils::trace(format!("Archive job failed: {}", e));
-                                } else {
-                                    bity_ic_icrc3::utils::trace(format!("Archive job completed successfully"));
-                                }
+        fn __icrc3_spawn_archive_job() {
+            ::ic_cdk::futures::spawn(async {
+                match ICRC3_INSTANCE.write() {
+                    Ok(mut lock) => {
+                        if let Some(icrc3) = lock.as_mut() {
+                            if let Err(e) = icrc3.archive_job().await {
+                                ::bity_ic_icrc3::utils::trace(format!("Archive job failed: {}", e));
                             } else {
-                                bity_ic_icrc3::utils::trace("ICRC3 instance not initialized");
+                                ::bity_ic_icrc3::utils::trace(format!("Archive job completed successfully"));
                             }
-                        },
-                        Err(e) => {
-                            bity_ic_icrc3::utils::trace(format!("Failed to acquire ICRC3 lock: {}", e));
+                        } else {
+                            ::bity_ic_icrc3::utils::trace("ICRC3 instance not initialized");
+                        }
+                    },
+                    Err(e) => {
+                        let error = format!("Failed to acquire ICRC3 lock: {}", e);
+                        ::bity_ic_icrc3::utils::trace(error.clone());
+                        if let Some(icrc3) = e.into_inner().as_mut() {
+                            let now = ::ic_cdk::api::time();
+                            icrc3.archive_in_progress = false;
+                            icrc3.job_history.record(::bity_ic_icrc3::job_history::JobKind::Archive, now, now, Err(error));
                         }
                     }
-                });
+                }
             });
         }
 
         pub fn start_cleanup_job(interval_ms: u64) {
-            run_interval(Duration::from_millis(interval_ms), || {
-                ic_cdk::futures::spawn(async {
+            if let Some(icrc3) = ICRC3_INSTANCE.write().unwrap().as_mut() {
+                icrc3.cleanup_job_interval_ms = Some(interval_ms);
+            }
+            ::bity_ic_canister_time::run_interval_named(::bity_ic_icrc3::job_history::JobKind::Cleanup.timer_name(), ::std::time::Duration::from_millis(interval_ms), || {
+                ::ic_cdk::futures::spawn(async {
                     match ICRC3_INSTANCE.write() {
                         Ok(mut lock) => {
                             if let Some(icrc3) = lock.as_mut() {
                                 if let Err(e) = icrc3.cleanup_job() {
-                                    bity_ic_icrc3::utils::trace(format!("Cleanup job failed: {}", e));
+                                    ::bity_ic_icrc3::utils::trace(format!("Cleanup job failed: {}", e));
                                 } else {
-                                    bity_ic_icrc3::utils::trace(format!("Cleanup job completed successfully"));
+                                    ::bity_ic_icrc3::utils::trace(format!("Cleanup job completed successfully"));
                                 }
                             } else {
-                                bity_ic_icrc3::utils::trace("ICRC3 instance not initialized");
+                                ::bity_ic_icrc3::utils::trace("ICRC3 instance not initialized");
                             }
                         },
                         Err(e) => {
-                            bity_ic_icrc3::utils::trace(format!("Failed to acquire ICRC3 lock: {}", e));
+                            let error = format!("Failed to acquire ICRC3 lock: {}", e);
+                            ::bity_ic_icrc3::utils::trace(error.clone());
+                            if let Some(icrc3) = e.into_inner().as_mut() {
+                                let now = ::ic_cdk::api::time();
+                                icrc3.job_history.record(::bity_ic_icrc3::job_history::JobKind::Cleanup, now, now, Err(error));
+                            }
                         }
                     }
                 });
             });
         }
 
+        pub async fn icrc3_run_verification_now(sample_size: u32) -> Result<u128, String> {
+            let started_at = ::ic_cdk::api::time();
+            let plan = {
+                let mut lock = ICRC3_INSTANCE.write().unwrap();
+                lock.as_mut().expect(__ICRC3_NOT_INITIALIZED).verification_plan()?
+            };
+
+            // The archives are called without holding the lock.
+            let result = plan.run(sample_size).await;
+
+            let mut lock = ICRC3_INSTANCE.write().unwrap();
+            if let Some(icrc3) = lock.as_mut() {
+                icrc3.record_verification(started_at, result.clone());
+            }
+            result
+        }
+
+        pub fn start_verification_job(interval_ms: u64, sample_size: u32) {
+            if let Some(icrc3) = ICRC3_INSTANCE.write().unwrap().as_mut() {
+                icrc3.verification_job = Some(::bity_ic_icrc3::verification::VerificationJobConfig {
+                    interval_ms,
+                    sample_size,
+                });
+            }
+            ::bity_ic_canister_time::run_interval_named(::bity_ic_icrc3::job_history::JobKind::Verification.timer_name(), ::std::time::Duration::from_millis(interval_ms), || {
+                ::ic_cdk::futures::spawn(async {
+                    let sample_size = ICRC3_INSTANCE
+                        .read()
+                        .ok()
+                        .and_then(|lock| lock.as_ref().and_then(|icrc3| icrc3.verification_job))
+                        .map(|job| job.sample_size);
+                    if let Some(sample_size) = sample_size {
+                        if let Err(e) = icrc3_run_verification_now(sample_size).await {
+                            ::bity_ic_icrc3::utils::trace(format!("Verification job failed: {}", e));
+                        }
+                    }
+                });
+            });
+        }
+
+        pub async fn icrc3_check_archive_funding(
+        ) -> Result<Vec<::bity_ic_icrc3::blockchain::archive_canister_manager::FundingAlert>, String> {
+            let started_at = ::ic_cdk::api::time();
+            let calls = {
+                let mut lock = ICRC3_INSTANCE.write().unwrap();
+                lock.as_mut().expect(__ICRC3_NOT_INITIALIZED).archive_funding_check_calls()?
+            };
+
+            // The archives are sampled without holding the lock.
+            let sample = calls.sample_cycles().await;
+
+            let mut lock = ICRC3_INSTANCE.write().unwrap();
+            let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+            icrc3.record_archive_funding_check(started_at, sample)
+        }
+
+        pub fn start_funding_health_job(interval_ms: u64) {
+            if let Some(icrc3) = ICRC3_INSTANCE.write().unwrap().as_mut() {
+                icrc3.funding_health_job_interval_ms = Some(interval_ms);
+            }
+            ::bity_ic_canister_time::run_interval_named(::bity_ic_icrc3::job_history::JobKind::FundingHealth.timer_name(), ::std::time::Duration::from_millis(interval_ms), || {
+                ::ic_cdk::futures::spawn(async {
+                    if let Err(e) = icrc3_check_archive_funding().await {
+                        ::bity_ic_icrc3::utils::trace(format!("Funding health job failed: {}", e));
+                    }
+                });
+            });
+        }
+
+        pub async fn icrc3_flush_notifications() {
+            let started_at = ::ic_cdk::api::time();
+            let deliveries = {
+                let mut lock = ICRC3_INSTANCE.write().unwrap();
+                match lock.as_mut() {
+                    Some(icrc3) => icrc3.take_notification_deliveries(),
+                    None => return,
+                }
+            };
+            if deliveries.is_empty() {
+                return;
+            }
+
+            // The subscribers are called without holding the lock.
+            let results = ::bity_ic_icrc3::notifications::deliver(deliveries).await;
+
+            let mut lock = ICRC3_INSTANCE.write().unwrap();
+            if let Some(icrc3) = lock.as_mut() {
+                icrc3.record_notification_deliveries(started_at, results);
+            }
+        }
+
+        pub fn start_notification_job(interval_ms: u64) {
+            if let Some(icrc3) = ICRC3_INSTANCE.write().unwrap().as_mut() {
+                icrc3.notification_job_interval_ms = Some(interval_ms);
+            }
+            ::bity_ic_canister_time::run_interval_named(::bity_ic_icrc3::job_history::JobKind::Notifications.timer_name(), ::std::time::Duration::from_millis(interval_ms), || {
+                ::ic_cdk::futures::spawn(icrc3_flush_notifications());
+            });
+        }
+
         // by default you can use this method, to run archive 10mins
         pub fn start_default_archive_job() {
-            start_archive_job(10 * MINUTE_IN_MS);
-            start_cleanup_job(1 * HOUR_IN_MS);
+            start_archive_job(__ICRC3_DEFAULT_ARCHIVE_JOB_INTERVAL_MS);
+            start_cleanup_job(__ICRC3_DEFAULT_CLEANUP_JOB_INTERVAL_MS);
         }
-    };
+    }
+}
 
-    expanded.into()
+/// The wrappers which can be selected with `only(...)` or `except(...)`, by name.
+fn wrapper_items() -> Vec<(&'static str, TokenStream2)> {
+    vec![
+        (
+            "icrc3_add_transaction",
+            quote! {
+                pub fn icrc3_add_transaction<T: ::bity_ic_icrc3::transaction::TransactionType>(
+                    transaction: T,
+                ) -> Result<u64, ::bity_ic_icrc3::types::Icrc3Error> {
+                    let (result, archive_now) = {
+                        let mut lock = ICRC3_INSTANCE.write().unwrap();
+                        let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                        icrc3.authorize_recorder()?;
+                        let result = ::bity_ic_icrc3::interface::ICRC3Interface::add_transaction(icrc3, transaction);
+                        (result, icrc3.take_archive_request())
+                    };
+                    if archive_now {
+                        schedule_archive_job_now();
+                    }
+                    result
+                }
+            },
+        ),
+        (
+            "icrc3_prepare_transaction",
+            quote! {
+                pub fn icrc3_prepare_transaction<T: ::bity_ic_icrc3::transaction::TransactionType>(
+                    transaction: T,
+                ) -> Result<::bity_ic_icrc3::types::prepare_transaction::PreparedTransaction, ::bity_ic_icrc3::types::Icrc3Error> {
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.authorize_recorder()?;
+                    ::bity_ic_icrc3::interface::ICRC3Interface::prepare_transaction(icrc3, transaction)
+                }
+            },
+        ),
+        (
+            "icrc3_commit_prepared_transaction",
+            quote! {
+                pub fn icrc3_commit_prepared_transaction<T: ::bity_ic_icrc3::transaction::TransactionType>(
+                    transaction: T,
+                    timestamp: u128,
+                ) -> Result<u64, ::bity_ic_icrc3::types::Icrc3Error> {
+                    let (result, archive_now) = {
+                        let mut lock = ICRC3_INSTANCE.write().unwrap();
+                        let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                        icrc3.authorize_recorder()?;
+                        let result = ::bity_ic_icrc3::interface::ICRC3Interface::commit_prepared_transaction(icrc3, transaction, timestamp);
+                        (result, icrc3.take_archive_request())
+                    };
+                    if archive_now {
+                        schedule_archive_job_now();
+                    }
+                    result
+                }
+            },
+        ),
+        (
+            "icrc3_get_archives",
+            quote! {
+                pub fn icrc3_get_archives() -> Vec<::icrc_ledger_types::icrc3::archive::ICRC3ArchiveInfo> {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    ::bity_ic_icrc3::interface::ICRC3Interface::icrc3_get_archives(icrc3)
+                }
+            },
+        ),
+        (
+            "icrc3_get_archive_stats",
+            quote! {
+                pub fn icrc3_get_archive_stats() -> Vec<::bity_ic_icrc3::archive_stats::ArchiveStats> {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.archive_stats()
+                }
+            },
+        ),
+        (
+            "icrc3_get_blocks",
+            quote! {
+                pub fn icrc3_get_blocks(
+                    args: Vec<::icrc_ledger_types::icrc3::blocks::GetBlocksRequest>,
+                ) -> ::icrc_ledger_types::icrc3::blocks::GetBlocksResult {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    ::bity_ic_icrc3::interface::ICRC3Interface::icrc3_get_blocks(icrc3, args)
+                }
+            },
+        ),
+        (
+            "icrc3_get_properties",
+            quote! {
+                pub fn icrc3_get_properties() -> ::bity_ic_icrc3::config::ICRC3Properties {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    ::bity_ic_icrc3::interface::ICRC3Interface::icrc3_get_properties(icrc3)
+                }
+            },
+        ),
+        (
+            "icrc3_get_tip_certificate",
+            quote! {
+                pub fn icrc3_get_tip_certificate() -> Option<::icrc_ledger_types::icrc3::blocks::ICRC3DataCertificate> {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    ::bity_ic_icrc3::interface::ICRC3Interface::icrc3_get_tip_certificate(icrc3)
+                }
+            },
+        ),
+        (
+            "icrc3_supported_block_types",
+            quote! {
+                pub fn icrc3_supported_block_types() -> Vec<::icrc_ledger_types::icrc3::blocks::SupportedBlockType> {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    ::bity_ic_icrc3::interface::ICRC3Interface::icrc3_supported_block_types(icrc3)
+                }
+            },
+        ),
+        (
+            "icrc3_block_schemas",
+            quote! {
+                pub fn icrc3_block_schemas() -> Vec<::bity_ic_icrc3::schema::BlockSchema> {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.block_schemas()
+                }
+            },
+        ),
+        (
+            "icrc3_register_block_schema",
+            quote! {
+                pub fn icrc3_register_block_schema(schema: ::bity_ic_icrc3::schema::TransactionSchema) {
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.register_block_schema(schema)
+                }
+            },
+        ),
+        (
+            "icrc3_register_transaction_type",
+            quote! {
+                pub fn icrc3_register_transaction_type<T: ::bity_ic_icrc3::transaction::TransactionType>() {
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.register_transaction_type::<T>()
+                }
+            },
+        ),
+        (
+            "icrc10_supported_standards",
+            quote! {
+                pub fn icrc10_supported_standards(
+                    extra: &[(&str, &str)],
+                ) -> Vec<::bity_ic_icrc3::standards::StandardRecord> {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    let mut derived = ::bity_ic_icrc3::standards::icrc3_standard_records(&icrc3.icrc3_config);
+                    derived.push(::bity_ic_icrc3::standards::icrc10_standard_record());
+                    ::bity_ic_icrc3::standards::merge_standard_records(derived, extra)
+                }
+            },
+        ),
+        (
+            "icrc3_get_tip",
+            quote! {
+                pub fn icrc3_get_tip() -> Option<::bity_ic_icrc3::types::icrc3_get_tip::TipInfo> {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    ::bity_ic_icrc3::interface::ICRC3Interface::icrc3_get_tip(icrc3)
+                }
+            },
+        ),
+        (
+            "icrc3_chain_length",
+            quote! {
+                pub fn icrc3_chain_length() -> ::candid::Nat {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    ::bity_ic_icrc3::interface::ICRC3Interface::icrc3_chain_length(icrc3)
+                }
+            },
+        ),
+        (
+            "icrc3_has_block",
+            quote! {
+                pub fn icrc3_has_block(index: ::candid::Nat) -> bool {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    ::bity_ic_icrc3::interface::ICRC3Interface::icrc3_has_block(icrc3, index)
+                }
+            },
+        ),
+        (
+            "find_blocks_by_memo",
+            quote! {
+                pub fn find_blocks_by_memo(memo: ::serde_bytes::ByteBuf, max: u32) -> Vec<::candid::Nat> {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.find_blocks_by_memo(&memo, max)
+                }
+            },
+        ),
+        (
+            "get_transactions",
+            quote! {
+                pub fn get_transactions(
+                    request: ::icrc_ledger_types::icrc3::transactions::GetTransactionsRequest,
+                ) -> ::icrc_ledger_types::icrc3::transactions::GetTransactionsResponse {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    // Legacy clients expect the response type of the ICRC-1 ledgers.
+                    ::bity_ic_icrc3::legacy::get_transactions(icrc3, request)
+                        .unwrap_or_else(|e| ::ic_cdk::trap(&e))
+                }
+            },
+        ),
+        (
+            "find_block_by_thash",
+            quote! {
+                pub async fn find_block_by_thash(
+                    thash: ::serde_bytes::ByteBuf,
+                ) -> Result<Option<::icrc_ledger_types::icrc3::blocks::BlockWithId>, String> {
+                    let lookup = {
+                        let lock = ICRC3_INSTANCE.read().unwrap();
+                        lock.as_ref().expect(__ICRC3_NOT_INITIALIZED).thash_lookup(thash)
+                    };
+
+                    // The archives are called without holding the lock.
+                    lookup.run().await
+                }
+            },
+        ),
+        (
+            "icrc3_blocks_http_chunk",
+            quote! {
+                pub fn icrc3_blocks_http_chunk(
+                    start: u64,
+                    end: u64,
+                    chunk_bytes: usize,
+                    first: bool,
+                ) -> Result<::bity_ic_icrc3::blocks_http::BlocksChunk, String> {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.blocks_http_chunk(start, end, chunk_bytes, first)
+                }
+            },
+        ),
+        (
+            "icrc3_update_funding_config",
+            quote! {
+                pub fn icrc3_update_funding_config(
+                    funding_config: ::bity_ic_icrc3::config::FundingConfig,
+                ) -> Result<(), ::bity_ic_icrc3::types::Icrc3Error> {
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    ::bity_ic_icrc3::interface::ICRC3Interface::update_funding_config(icrc3, funding_config.clone())?;
+                    ::bity_ic_icrc3::audit::record_admin_action(
+                        icrc3,
+                        "update_funding_config",
+                        vec![
+                            ("interval_secs", ::icrc_ledger_types::icrc::generic_value::ICRC3Value::Nat(funding_config.interval_secs.into())),
+                            ("min_cycles", ::icrc_ledger_types::icrc::generic_value::ICRC3Value::Nat(funding_config.min_cycles.into())),
+                            ("fund_cycles", ::icrc_ledger_types::icrc::generic_value::ICRC3Value::Nat(funding_config.fund_cycles.into())),
+                            ("initial_cycles", ::icrc_ledger_types::icrc::generic_value::ICRC3Value::Nat(funding_config.initial_cycles.into())),
+                            ("reserved_cycles", ::icrc_ledger_types::icrc::generic_value::ICRC3Value::Nat(funding_config.reserved_cycles.into())),
+                        ],
+                    );
+                    Ok(())
+                }
+            },
+        ),
+        (
+            "icrc3_funding_config",
+            quote! {
+                pub fn icrc3_funding_config() -> ::bity_ic_icrc3::config::FundingConfig {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.icrc3_config.funding_config()
+                }
+            },
+        ),
+        (
+            "icrc3_job_history",
+            quote! {
+                pub fn icrc3_job_history() -> Vec<::bity_ic_icrc3::job_history::JobRunRecord> {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.job_history.records()
+                }
+            },
+        ),
+        (
+            "icrc3_job_history_metrics",
+            quote! {
+                pub fn icrc3_job_history_metrics() -> ::bity_ic_icrc3::job_history::JobHistoryMetrics {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.job_history_metrics()
+                }
+            },
+        ),
+        (
+            "icrc3_dedup_window_metrics",
+            quote! {
+                pub fn icrc3_dedup_window_metrics() -> ::bity_ic_icrc3::dedup_window::DedupWindowMetrics {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.dedup_window_metrics()
+                }
+            },
+        ),
+        (
+            "icrc3_list_prepared_transactions",
+            quote! {
+                pub fn icrc3_list_prepared_transactions(
+                    args: ::bity_ic_icrc3::prepared::ListPreparedTransactionsArgs,
+                ) -> Vec<::bity_ic_icrc3::prepared::PreparedTransactionInfo> {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    ::bity_ic_icrc3::interface::ICRC3Interface::list_prepared_transactions(icrc3, args)
+                }
+            },
+        ),
+        (
+            "icrc3_prepared_transactions_metrics",
+            quote! {
+                pub fn icrc3_prepared_transactions_metrics() -> ::bity_ic_icrc3::prepared::PreparedTransactionsMetrics {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.prepared_transactions_metrics()
+                }
+            },
+        ),
+        (
+            "icrc3_validation_metrics",
+            quote! {
+                pub fn icrc3_validation_metrics() -> ::bity_ic_icrc3::validation::ValidationMetrics {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.validation_metrics()
+                }
+            },
+        ),
+        (
+            "icrc3_timestamp_ordering_metrics",
+            quote! {
+                pub fn icrc3_timestamp_ordering_metrics() -> ::bity_ic_icrc3::timestamp_ordering::TimestampOrderingMetrics {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.timestamp_ordering_metrics()
+                }
+            },
+        ),
+        (
+            "icrc3_transactions_per_sec",
+            quote! {
+                pub fn icrc3_transactions_per_sec() -> f64 {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.transactions_per_sec()
+                }
+            },
+        ),
+        (
+            "icrc3_timers",
+            quote! {
+                pub fn icrc3_timers() -> Vec<::bity_ic_canister_time::TimerInfo> {
+                    ::bity_ic_canister_time::list_timers()
+                        .into_iter()
+                        .filter(|timer| ::bity_ic_icrc3::job_history::JobKind::ALL.iter().any(|job| job.timer_name() == timer.name))
+                        .collect()
+                }
+            },
+        ),
+        (
+            "icrc3_fire_job_now",
+            quote! {
+                pub fn icrc3_fire_job_now(job: ::bity_ic_icrc3::job_history::JobKind) -> Result<(), String> {
+                    ::bity_ic_canister_time::fire_now(job.timer_name())
+                }
+            },
+        ),
+        (
+            "icrc3_archive_history",
+            quote! {
+                pub fn icrc3_archive_history() -> Vec<::bity_ic_icrc3::blockchain::archive_canister_manager::ArchiveCanisterHistory> {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.archive_canister_histories()
+                }
+            },
+        ),
+        (
+            "icrc3_add_recorder",
+            quote! {
+                pub fn icrc3_add_recorder(recorder: ::candid::Principal) {
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.add_recorder(recorder);
+                    ::bity_ic_icrc3::audit::record_admin_action(icrc3, "add_recorder", vec![("recorder", ::bity_ic_icrc3::audit::principal_value(recorder))]);
+                }
+            },
+        ),
+        (
+            "icrc3_remove_recorder",
+            quote! {
+                pub fn icrc3_remove_recorder(recorder: ::candid::Principal) {
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.remove_recorder(recorder);
+                    ::bity_ic_icrc3::audit::record_admin_action(icrc3, "remove_recorder", vec![("recorder", ::bity_ic_icrc3::audit::principal_value(recorder))]);
+                }
+            },
+        ),
+        (
+            "icrc3_retire_archive",
+            quote! {
+                pub fn icrc3_retire_archive(canister_id: ::candid::Principal) -> Result<(), String> {
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.retire_archive(canister_id)?;
+                    ::bity_ic_icrc3::audit::record_admin_action(icrc3, "retire_archive", vec![("canister_id", ::bity_ic_icrc3::audit::principal_value(canister_id))]);
+                    Ok(())
+                }
+            },
+        ),
+        (
+            "icrc3_unretire_archive",
+            quote! {
+                pub fn icrc3_unretire_archive(canister_id: ::candid::Principal) -> Result<(), String> {
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.unretire_archive(canister_id)?;
+                    ::bity_ic_icrc3::audit::record_admin_action(icrc3, "unretire_archive", vec![("canister_id", ::bity_ic_icrc3::audit::principal_value(canister_id))]);
+                    Ok(())
+                }
+            },
+        ),
+        (
+            "icrc3_mark_archive_unrecoverable",
+            quote! {
+                pub fn icrc3_mark_archive_unrecoverable(canister_id: ::candid::Principal) -> Result<(), String> {
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.mark_archive_unrecoverable(canister_id)?;
+                    ::bity_ic_icrc3::audit::record_admin_action(icrc3, "mark_archive_unrecoverable", vec![("canister_id", ::bity_ic_icrc3::audit::principal_value(canister_id))]);
+                    Ok(())
+                }
+            },
+        ),
+        (
+            "icrc3_reinstall_archive",
+            quote! {
+                pub async fn icrc3_reinstall_archive(
+                    confirmation: ::bity_ic_icrc3::blockchain::archive_canister_manager::ReinstallConfirmation,
+                ) -> Result<(), String> {
+                    let canister_id = confirmation.canister_id();
+                    let reinstall = {
+                        let lock = ICRC3_INSTANCE.read().unwrap();
+                        lock.as_ref().expect(__ICRC3_NOT_INITIALIZED).archive_reinstall(&confirmation)?
+                    };
+                    // The archive is reinstalled without holding the lock.
+                    let result = reinstall.run().await;
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.record_archive_reinstall(reinstall, result)?;
+                    ::bity_ic_icrc3::audit::record_admin_action(icrc3, "reinstall_archive", vec![("canister_id", ::bity_ic_icrc3::audit::principal_value(canister_id))]);
+                    Ok(())
+                }
+            },
+        ),
+        (
+            "icrc3_add_archive_controller",
+            quote! {
+                pub async fn icrc3_add_archive_controller(
+                    canister_id: ::candid::Principal,
+                    controller: ::candid::Principal,
+                ) -> Result<Vec<::candid::Principal>, String> {
+                    let calls = {
+                        let lock = ICRC3_INSTANCE.read().unwrap();
+                        lock.as_ref().expect(__ICRC3_NOT_INITIALIZED).archive_calls()?
+                    };
+                    // The archive is called without holding the lock.
+                    let result = calls.add_controller(canister_id, controller).await;
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.record_archive_controllers(canister_id, &result)?;
+                    let controllers = result.map_err(|e| format!("Failed to add archive controller: {e:?}"))?;
+                    ::bity_ic_icrc3::audit::record_admin_action(
+                        icrc3,
+                        "add_archive_controller",
+                        vec![
+                            ("canister_id", ::bity_ic_icrc3::audit::principal_value(canister_id)),
+                            ("controller", ::bity_ic_icrc3::audit::principal_value(controller)),
+                        ],
+                    );
+                    Ok(controllers)
+                }
+            },
+        ),
+        (
+            "icrc3_remove_archive_controller",
+            quote! {
+                pub async fn icrc3_remove_archive_controller(
+                    canister_id: ::candid::Principal,
+                    controller: ::candid::Principal,
+                ) -> Result<Vec<::candid::Principal>, String> {
+                    let calls = {
+                        let lock = ICRC3_INSTANCE.read().unwrap();
+                        lock.as_ref().expect(__ICRC3_NOT_INITIALIZED).archive_calls()?
+                    };
+                    // The archive is called without holding the lock.
+                    let result = calls.remove_controller(canister_id, controller).await;
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.record_archive_controllers(canister_id, &result)?;
+                    let controllers = result.map_err(|e| format!("Failed to remove archive controller: {e:?}"))?;
+                    ::bity_ic_icrc3::audit::record_admin_action(
+                        icrc3,
+                        "remove_archive_controller",
+                        vec![
+                            ("canister_id", ::bity_ic_icrc3::audit::principal_value(canister_id)),
+                            ("controller", ::bity_ic_icrc3::audit::principal_value(controller)),
+                        ],
+                    );
+                    Ok(controllers)
+                }
+            },
+        ),
+        (
+            "icrc3_take_archive_snapshot",
+            quote! {
+                pub async fn icrc3_take_archive_snapshot(
+                    canister_id: ::candid::Principal,
+                ) -> Result<Vec<u8>, String> {
+                    let calls = {
+                        let lock = ICRC3_INSTANCE.read().unwrap();
+                        lock.as_ref().expect(__ICRC3_NOT_INITIALIZED).archive_calls()?
+                    };
+                    // The archive is called without holding the lock.
+                    let snapshot_id = calls
+                        .take_snapshot(canister_id)
+                        .await
+                        .map_err(|e| format!("Failed to take archive snapshot: {e:?}"))?;
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    ::bity_ic_icrc3::audit::record_admin_action(
+                        icrc3,
+                        "take_archive_snapshot",
+                        vec![
+                            ("canister_id", ::bity_ic_icrc3::audit::principal_value(canister_id)),
+                            ("snapshot_id", ::icrc_ledger_types::icrc::generic_value::ICRC3Value::Blob(::serde_bytes::ByteBuf::from(snapshot_id.clone()))),
+                        ],
+                    );
+                    Ok(snapshot_id)
+                }
+            },
+        ),
+        (
+            "icrc3_list_archive_snapshots",
+            quote! {
+                pub async fn icrc3_list_archive_snapshots(
+                    canister_id: ::candid::Principal,
+                ) -> Result<Vec<::ic_cdk::management_canister::Snapshot>, String> {
+                    let calls = {
+                        let lock = ICRC3_INSTANCE.read().unwrap();
+                        lock.as_ref().expect(__ICRC3_NOT_INITIALIZED).archive_calls()?
+                    };
+                    // The archive is called without holding the lock.
+                    calls
+                        .list_snapshots(canister_id)
+                        .await
+                        .map_err(|e| format!("Failed to list archive snapshots: {e:?}"))
+                }
+            },
+        ),
+        (
+            "icrc3_restore_archive_snapshot",
+            quote! {
+                pub async fn icrc3_restore_archive_snapshot(
+                    canister_id: ::candid::Principal,
+                    snapshot_id: Vec<u8>,
+                ) -> Result<(), String> {
+                    let calls = {
+                        let lock = ICRC3_INSTANCE.read().unwrap();
+                        lock.as_ref().expect(__ICRC3_NOT_INITIALIZED).archive_calls()?
+                    };
+                    // The archive is called without holding the lock.
+                    let result = calls.restore_snapshot(canister_id, snapshot_id.clone()).await;
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.record_archive_snapshot_restore(canister_id, &result)?;
+                    result.map_err(|e| format!("Failed to restore archive snapshot: {e:?}"))?;
+                    ::bity_ic_icrc3::audit::record_admin_action(
+                        icrc3,
+                        "restore_archive_snapshot",
+                        vec![
+                            ("canister_id", ::bity_ic_icrc3::audit::principal_value(canister_id)),
+                            ("snapshot_id", ::icrc_ledger_types::icrc::generic_value::ICRC3Value::Blob(::serde_bytes::ByteBuf::from(snapshot_id))),
+                        ],
+                    );
+                    Ok(())
+                }
+            },
+        ),
+        (
+            "icrc3_delete_archive_snapshot",
+            quote! {
+                pub async fn icrc3_delete_archive_snapshot(
+                    canister_id: ::candid::Principal,
+                    snapshot_id: Vec<u8>,
+                ) -> Result<(), String> {
+                    let calls = {
+                        let lock = ICRC3_INSTANCE.read().unwrap();
+                        lock.as_ref().expect(__ICRC3_NOT_INITIALIZED).archive_calls()?
+                    };
+                    // The archive is called without holding the lock.
+                    calls
+                        .delete_snapshot(canister_id, snapshot_id.clone())
+                        .await
+                        .map_err(|e| format!("Failed to delete archive snapshot: {e:?}"))?;
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    ::bity_ic_icrc3::audit::record_admin_action(
+                        icrc3,
+                        "delete_archive_snapshot",
+                        vec![
+                            ("canister_id", ::bity_ic_icrc3::audit::principal_value(canister_id)),
+                            ("snapshot_id", ::icrc_ledger_types::icrc::generic_value::ICRC3Value::Blob(::serde_bytes::ByteBuf::from(snapshot_id))),
+                        ],
+                    );
+                    Ok(())
+                }
+            },
+        ),
+        (
+            "icrc3_rebuild_from_archives",
+            quote! {
+                pub async fn icrc3_rebuild_from_archives(
+                    archives: Vec<::candid::Principal>,
+                ) -> Result<::bity_ic_icrc3::rebuild::RebuildReport, ::bity_ic_icrc3::rebuild::RebuildError> {
+                    let plan = {
+                        let lock = ICRC3_INSTANCE.read().unwrap();
+                        lock.as_ref().expect(__ICRC3_NOT_INITIALIZED).rebuild_plan(archives.clone())?
+                    };
+                    // The archives are read without holding the lock.
+                    let ends = plan.read_archive_ends().await?;
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    let report = icrc3.apply_rebuild(ends)?;
+                    ::bity_ic_icrc3::audit::record_admin_action(
+                        icrc3,
+                        "rebuild_from_archives",
+                        vec![(
+                            "archives",
+                            ::icrc_ledger_types::icrc::generic_value::ICRC3Value::Array(archives.into_iter().map(::bity_ic_icrc3::audit::principal_value).collect()),
+                        )],
+                    );
+                    Ok(report)
+                }
+            },
+        ),
+        (
+            "icrc3_reconcile_archives",
+            quote! {
+                pub async fn icrc3_reconcile_archives(
+                    auto_adopt: bool,
+                ) -> Result<::bity_ic_icrc3::blockchain::archive_canister_manager::ReconciliationReport, String> {
+                    let calls = {
+                        let lock = ICRC3_INSTANCE.read().unwrap();
+                        lock.as_ref().expect(__ICRC3_NOT_INITIALIZED).archive_calls()?
+                    };
+                    // The archives are called without holding the lock.
+                    let statuses = calls.canister_statuses().await;
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    let report = icrc3.apply_archive_reconciliation(statuses, auto_adopt)?;
+                    // Only adopting archives changes the records.
+                    if auto_adopt {
+                        ::bity_ic_icrc3::audit::record_admin_action(icrc3, "reconcile_archives", vec![]);
+                    }
+                    Ok(report)
+                }
+            },
+        ),
+        (
+            "icrc3_subscribe",
+            quote! {
+                pub fn icrc3_subscribe(
+                    canister_id: ::candid::Principal,
+                    method_name: String,
+                    filter: Option<Vec<String>>,
+                ) -> Result<(), String> {
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.notifications.subscribe(canister_id, method_name.clone(), filter)?;
+                    ::bity_ic_icrc3::audit::record_admin_action(icrc3, "subscribe", vec![
+                        ("canister_id", ::bity_ic_icrc3::audit::principal_value(canister_id)),
+                        ("method_name", ::icrc_ledger_types::icrc::generic_value::ICRC3Value::Text(method_name)),
+                    ]);
+                    Ok(())
+                }
+            },
+        ),
+        (
+            "icrc3_unsubscribe",
+            quote! {
+                pub fn icrc3_unsubscribe(canister_id: ::candid::Principal) -> bool {
+                    let mut lock = ICRC3_INSTANCE.write().unwrap();
+                    let icrc3 = lock.as_mut().expect(__ICRC3_NOT_INITIALIZED);
+                    let removed = icrc3.notifications.unsubscribe(canister_id);
+                    if removed {
+                        ::bity_ic_icrc3::audit::record_admin_action(icrc3, "unsubscribe", vec![("canister_id", ::bity_ic_icrc3::audit::principal_value(canister_id))]);
+                    }
+                    removed
+                }
+            },
+        ),
+        (
+            "icrc3_notification_metrics",
+            quote! {
+                pub fn icrc3_notification_metrics() -> Vec<::bity_ic_icrc3::notifications::SubscriberMetrics> {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.notifications.metrics()
+                }
+            },
+        ),
+        (
+            "icrc3_archive_funding_alerts",
+            quote! {
+                pub fn icrc3_archive_funding_alerts(
+                ) -> Vec<::bity_ic_icrc3::blockchain::archive_canister_manager::FundingAlert> {
+                    let lock = ICRC3_INSTANCE.read().unwrap();
+                    let icrc3 = lock.as_ref().expect(__ICRC3_NOT_INITIALIZED);
+                    icrc3.archive_funding_alerts()
+                }
+            },
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selected(input: TokenStream2) -> syn::Result<Vec<String>> {
+        let wrappers = wrapper_items();
+        let generated = syn::parse2::<Selection>(input)?.select(wrappers.clone())?;
+        Ok(wrappers
+            .into_iter()
+            .filter(|(_, items)| generated.iter().any(|g| g.to_string() == items.to_string()))
+            .map(|(name, _)| name.to_string())
+            .collect())
+    }
+
+    #[test]
+    fn test_selection() {
+        assert_eq!(selected(quote! {}).unwrap().len(), wrapper_items().len());
+        assert_eq!(
+            selected(quote! { only(add_transaction, get_blocks, tip_certificate) }).unwrap(),
+            vec![
+                "icrc3_add_transaction",
+                "icrc3_get_blocks",
+                "icrc3_get_tip_certificate"
+            ]
+        );
+        assert_eq!(
+            selected(quote! { only(icrc10_supported_standards, find_blocks_by_memo,) }).unwrap(),
+            vec!["icrc10_supported_standards", "find_blocks_by_memo"]
+        );
+        assert!(selected(quote! { only() }).unwrap().is_empty());
+        let all_but_get_blocks = selected(quote! { except(get_blocks) }).unwrap();
+        assert_eq!(all_but_get_blocks.len(), wrapper_items().len() - 1);
+        assert!(!all_but_get_blocks.contains(&"icrc3_get_blocks".to_string()));
+        assert_eq!(
+            selected(quote! { except() }).unwrap().len(),
+            wrapper_items().len()
+        );
+
+        assert!(selected(quote! { only(get_block) }).is_err());
+        assert!(selected(quote! { except(get_block) }).is_err());
+        assert!(selected(quote! { only(get_blocks, icrc3_get_blocks) }).is_err());
+        assert!(selected(quote! { select(get_blocks) }).is_err());
+        assert!(selected(quote! { only(get_blocks) extra }).is_err());
+    }
+
+    #[test]
+    fn test_short_names_are_unambiguous() {
+        let wrappers = wrapper_items();
+        let names: Vec<&'static str> = wrappers.iter().map(|(name, _)| *name).collect();
+        for name in &names {
+            for prefix in ["icrc3_get_", "icrc3_", "icrc10_"] {
+                if let Some(short) = name.strip_prefix(prefix) {
+                    assert_eq!(resolve_wrapper(short, &names), Some(*name));
+                }
+            }
+        }
+    }
 }
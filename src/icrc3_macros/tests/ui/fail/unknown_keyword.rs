@@ -0,0 +1,4 @@
+// A selection that is neither `only(...)` nor `except(...)`.
+bity_ic_icrc3_macros::icrc3_state!(select(get_blocks));
+
+fn main() {}
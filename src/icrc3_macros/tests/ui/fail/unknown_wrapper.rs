@@ -0,0 +1,4 @@
+// A name that is not a wrapper.
+bity_ic_icrc3_macros::icrc3_state!(only(add_transaction, get_block));
+
+fn main() {}
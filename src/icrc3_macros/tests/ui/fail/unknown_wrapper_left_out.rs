@@ -0,0 +1,4 @@
+// A name that is not a wrapper, in the wrappers left out.
+bity_ic_icrc3_macros::icrc3_state!(except(get_tips));
+
+fn main() {}
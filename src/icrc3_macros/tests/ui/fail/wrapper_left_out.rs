@@ -0,0 +1,6 @@
+// A wrapper left out by the selection is not generated.
+bity_ic_icrc3_macros::icrc3_state!(only(get_blocks));
+
+fn main() {
+    let _ = icrc3_get_tip_certificate;
+}
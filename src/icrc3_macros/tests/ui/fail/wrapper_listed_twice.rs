@@ -0,0 +1,4 @@
+// The same wrapper under its two names.
+bity_ic_icrc3_macros::icrc3_state!(only(get_blocks, icrc3_get_blocks));
+
+fn main() {}
@@ -0,0 +1,7 @@
+// All the wrappers but the listed ones are generated.
+bity_ic_icrc3_macros::icrc3_state!(except(prepare_transaction, icrc3_commit_prepared_transaction));
+
+fn main() {
+    let _ = icrc3_get_blocks;
+    let _ = icrc3_add_transaction::<bity_ic_icrc3::transaction::ICRC1Transaction>;
+}
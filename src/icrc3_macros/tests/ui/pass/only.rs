@@ -0,0 +1,9 @@
+// Only the listed wrappers are generated, whether listed with their prefix or not.
+bity_ic_icrc3_macros::icrc3_state!(only(add_transaction, icrc3_get_blocks, tip_certificate));
+
+fn main() {
+    let _ = icrc3_get_blocks;
+    let _ = icrc3_get_tip_certificate;
+    let _ = icrc3_state::icrc3_add_transaction::<bity_ic_icrc3::transaction::ICRC1Transaction>;
+    let _ = init_icrc3;
+}
@@ -0,0 +1,12 @@
+// A function of the calling module with the name of a generated one shadows it.
+use icrc_ledger_types::icrc3::blocks::{GetBlocksRequest, GetBlocksResult};
+
+bity_ic_icrc3_macros::icrc3_state!();
+
+pub fn icrc3_get_blocks(args: Vec<GetBlocksRequest>) -> GetBlocksResult {
+    icrc3_state::icrc3_get_blocks(args)
+}
+
+fn main() {
+    let _: fn(Vec<GetBlocksRequest>) -> GetBlocksResult = icrc3_get_blocks;
+}
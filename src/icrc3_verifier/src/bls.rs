@@ -0,0 +1,133 @@
+//! Verification of the BLS12-381 signatures of IC certificates.
+//!
+//! The IC signs with the "minimal signature size" variant: signatures are G1 points,
+//! public keys G2 points, and messages are hashed to G1 with the ciphersuite of
+//! [`DOMAIN_SEPARATOR`].
+
+use bls12_381::hash_to_curve::{ExpandMsgXmd, HashToCurve};
+use bls12_381::{multi_miller_loop, G1Affine, G1Projective, G2Affine, G2Prepared, Gt};
+use std::ops::Neg;
+
+/// The ciphersuite of the signatures, hashing messages to G1 with SHA-256.
+const DOMAIN_SEPARATOR: &[u8] = b"BLS_SIG_BLS12381G1_XMD:SHA-256_SSWU_RO_NUL_";
+
+/// The length of a compressed signature.
+const SIGNATURE_LEN: usize = 48;
+
+/// The length of a compressed public key.
+pub const PUBLIC_KEY_LEN: usize = 96;
+
+/// Decodes a compressed public key.
+///
+/// # Returns
+///
+/// The key, or `None` if `bytes` is not a point of G2 other than the identity.
+pub fn decode_public_key(bytes: &[u8]) -> Option<G2Affine> {
+    let bytes: &[u8; PUBLIC_KEY_LEN] = bytes.try_into().ok()?;
+    Option::from(G2Affine::from_compressed(bytes))
+        .filter(|key: &G2Affine| !bool::from(key.is_identity()))
+}
+
+/// Verifies a compressed signature of `message` under `public_key`.
+///
+/// # Returns
+///
+/// * `Ok(())` if the signature is valid
+/// * `Err(String)` describing why it is not
+pub fn verify_signature(
+    signature: &[u8],
+    message: &[u8],
+    public_key: &G2Affine,
+) -> Result<(), String> {
+    let signature: &[u8; SIGNATURE_LEN] = signature
+        .try_into()
+        .map_err(|_| format!("the signature is not {} bytes long", SIGNATURE_LEN))?;
+    let signature: G1Affine = Option::from(G1Affine::from_compressed(signature))
+        .ok_or_else(|| "the signature is not a point of G1".to_string())?;
+
+    // e(signature, -g2) * e(H(message), public_key) is the identity for a valid signature.
+    let minus_generator = G2Prepared::from(G2Affine::generator().neg());
+    let public_key = G2Prepared::from(*public_key);
+    let product = multi_miller_loop(&[
+        (&signature, &minus_generator),
+        (&hash_to_g1(message), &public_key),
+    ])
+    .final_exponentiation();
+    if product == Gt::identity() {
+        Ok(())
+    } else {
+        Err("the signature does not match the message".to_string())
+    }
+}
+
+/// Hashes a message to G1, as signed.
+pub(crate) fn hash_to_g1(message: &[u8]) -> G1Affine {
+    G1Affine::from(<G1Projective as HashToCurve<
+        ExpandMsgXmd<sha2_0_9::Sha256>,
+    >>::hash_to_curve(message, DOMAIN_SEPARATOR))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The root key of the IC mainnet.
+    const MAINNET_ROOT_KEY: &str = "814c0e6ec71fab583b08bd81373c255c3c371b2e84863c98a4f1e08b74235d14fb5d9c0cd546d9685f913a0c0b2cc5341583bf4b4392e467db96d65b9bb4cb717112f8472e0d5a4d14505ffd7484b01291091c5f87b98883463f98091a0baaae";
+
+    /// A signature of the root hash of a state tree by the IC mainnet, with the
+    /// signed message.
+    const MAINNET_SIGNATURE: &str = "ace9fcdd9bc977e05d6328f889dc4e7c99114c737a494653cb27a1f55c06f4555e0f160980af5ead098acc195010b2f7";
+    const MAINNET_MESSAGE: &str =
+        "0d69632d73746174652d726f6f74e6c01e909b4923345ce5970962bcfe3004bfd8474a21dae28f50692502f46d90";
+
+    /// A signature by another key, with the signed message and the key.
+    const OTHER_SIGNATURE: &str = "89a2be21b5fa8ac9fab1527e041327ce899d7da971436a1f2165393947b4d942365bfe5488710e61a619ba48388a21b1";
+    const OTHER_MESSAGE: &str =
+        "0d69632d73746174652d726f6f74b294b418b11ebe5dd7dd1dcb099e4e0372b9a42aef7a7a37fb4f25667d705ea9";
+    const OTHER_KEY: &str = "9933e1f89e8a3c4d7fdcccdbd518089e2bd4d8180a261f18d9c247a52768ebce98dc7328a39814a8f911086a1dd50cbe015e2a53b7bf78b55288893daa15c346640e8831d72a12bdedd979d28470c34823b8d1c3f4795d9c3984a247132e94fe";
+
+    fn verify(signature: &str, message: &str, key: &str) -> Result<(), String> {
+        let key = decode_public_key(&hex::decode(key).unwrap()).unwrap();
+        verify_signature(
+            &hex::decode(signature).unwrap(),
+            &hex::decode(message).unwrap(),
+            &key,
+        )
+    }
+
+    #[test]
+    fn test_signatures_of_the_ic_are_valid() {
+        assert_eq!(
+            verify(MAINNET_SIGNATURE, MAINNET_MESSAGE, MAINNET_ROOT_KEY),
+            Ok(())
+        );
+        assert_eq!(verify(OTHER_SIGNATURE, OTHER_MESSAGE, OTHER_KEY), Ok(()));
+    }
+
+    #[test]
+    fn test_mismatched_signatures_are_rejected() {
+        assert!(verify(OTHER_SIGNATURE, MAINNET_MESSAGE, MAINNET_ROOT_KEY).is_err());
+        assert!(verify(MAINNET_SIGNATURE, OTHER_MESSAGE, OTHER_KEY).is_err());
+        assert!(verify(MAINNET_SIGNATURE, OTHER_MESSAGE, MAINNET_ROOT_KEY).is_err());
+    }
+
+    #[test]
+    fn test_invalid_points_are_rejected() {
+        let mut signature = hex::decode(MAINNET_SIGNATURE).unwrap();
+        *signature.last_mut().unwrap() ^= 1;
+        let key = decode_public_key(&hex::decode(MAINNET_ROOT_KEY).unwrap()).unwrap();
+        assert!(
+            verify_signature(&signature, &hex::decode(MAINNET_MESSAGE).unwrap(), &key).is_err()
+        );
+        assert!(verify_signature(&signature[1..], &[], &key).is_err());
+
+        let mut key = hex::decode(MAINNET_ROOT_KEY).unwrap();
+        *key.last_mut().unwrap() ^= 1;
+        assert_eq!(decode_public_key(&key), None);
+        assert_eq!(decode_public_key(&key[1..]), None);
+        assert_eq!(
+            decode_public_key(&G2Affine::identity().to_compressed()),
+            None
+        );
+    }
+}
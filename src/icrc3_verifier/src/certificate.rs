@@ -0,0 +1,154 @@
+//! Verification of IC certificates, as described in the interface specification.
+//!
+//! A certificate is signed either by the root key, or by the key of a subnet whose
+//! own certificate, the delegation, is signed by the root key and lists the
+//! canister in the ranges of the subnet.
+
+use crate::{bls, VerifyError};
+use candid::Principal;
+use ic_certification::{Certificate, Delegation, LookupResult};
+
+/// The domain separator of the signed root hash of a state tree.
+const IC_STATE_ROOT_DOMAIN_SEPARATOR: &[u8] = b"\x0Dic-state-root";
+
+/// The DER prefix of a BLS12-381 public key, as used for the root and subnet keys.
+const BLS_DER_PREFIX: &[u8] = &[
+    0x30, 0x81, 0x82, 0x30, 0x1d, 0x06, 0x0d, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xdc, 0x7c, 0x05,
+    0x03, 0x01, 0x02, 0x01, 0x06, 0x0c, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xdc, 0x7c, 0x05, 0x03,
+    0x02, 0x01, 0x03, 0x61, 0x00,
+];
+
+/// Decodes a CBOR encoded certificate.
+pub fn decode_certificate(bytes: &[u8]) -> Result<Certificate, VerifyError> {
+    serde_cbor::from_slice(bytes).map_err(|e| VerifyError::MalformedCertificate(e.to_string()))
+}
+
+/// Verifies the signature of a certificate, and its delegation if any.
+///
+/// # Arguments
+///
+/// * `certificate` - The decoded certificate
+/// * `root_key` - The DER encoded root key of the IC
+/// * `canister_id` - The canister the certificate must be valid for
+pub fn verify_certificate(
+    certificate: &Certificate,
+    root_key: &[u8],
+    canister_id: Principal,
+) -> Result<(), VerifyError> {
+    let key = match &certificate.delegation {
+        None => root_key.to_vec(),
+        Some(delegation) => verify_delegation(delegation, root_key, canister_id)?,
+    };
+    verify_signature(certificate, &key)
+}
+
+/// Verifies a delegation and returns the DER encoded key of its subnet.
+fn verify_delegation(
+    delegation: &Delegation,
+    root_key: &[u8],
+    canister_id: Principal,
+) -> Result<Vec<u8>, VerifyError> {
+    let certificate = decode_certificate(&delegation.certificate)?;
+    if certificate.delegation.is_some() {
+        return Err(VerifyError::MalformedCertificate(
+            "the delegation has a delegation".to_string(),
+        ));
+    }
+    verify_signature(&certificate, root_key)?;
+
+    let subnet_id = delegation.subnet_id.as_slice();
+    let ranges =
+        match certificate
+            .tree
+            .lookup_path([b"subnet".as_slice(), subnet_id, b"canister_ranges"])
+        {
+            LookupResult::Found(ranges) => ranges,
+            _ => return Err(missing("the canister ranges of the subnet")),
+        };
+    let ranges: Vec<(Principal, Principal)> = serde_cbor::from_slice(ranges)
+        .map_err(|e| VerifyError::MalformedCertificate(e.to_string()))?;
+    let canister_id = canister_id.as_slice();
+    if !ranges
+        .iter()
+        .any(|(low, high)| low.as_slice() <= canister_id && canister_id <= high.as_slice())
+    {
+        return Err(VerifyError::CanisterNotInSubnet);
+    }
+
+    match certificate
+        .tree
+        .lookup_path([b"subnet".as_slice(), subnet_id, b"public_key"])
+    {
+        LookupResult::Found(key) => Ok(key.to_vec()),
+        _ => Err(missing("the public key of the subnet")),
+    }
+}
+
+/// Verifies the signature of the root hash of a certificate.
+fn verify_signature(certificate: &Certificate, der_key: &[u8]) -> Result<(), VerifyError> {
+    let key = der_key
+        .strip_prefix(BLS_DER_PREFIX)
+        .and_then(bls::decode_public_key)
+        .ok_or(VerifyError::MalformedKey)?;
+    let mut message = IC_STATE_ROOT_DOMAIN_SEPARATOR.to_vec();
+    message.extend_from_slice(&certificate.tree.digest());
+    bls::verify_signature(&certificate.signature, &message, &key)
+        .map_err(VerifyError::InvalidSignature)
+}
+
+/// Decodes the time of a certificate, in nanoseconds since the epoch.
+///
+/// The time is an unsigned LEB128 number.
+///
+/// # Returns
+///
+/// The time, or `None` if `bytes` is not exactly one LEB128 number that fits in
+/// a `u64`.
+pub fn decode_time(bytes: &[u8]) -> Option<u64> {
+    let mut time: u64 = 0;
+    for (position, byte) in bytes.iter().enumerate() {
+        let shift = 7 * position as u32;
+        let digit = u64::from(byte & 0x7f);
+        if shift >= u64::BITS || (digit << shift) >> shift != digit {
+            return None;
+        }
+        time |= digit << shift;
+        if byte & 0x80 == 0 {
+            return (position == bytes.len() - 1).then_some(time);
+        }
+    }
+    None
+}
+
+fn missing(what: &str) -> VerifyError {
+    VerifyError::MalformedCertificate(format!("{} is not in the certificate", what))
+}
+
+/// Builds a DER encoded key from a raw BLS12-381 public key.
+#[cfg(test)]
+pub(crate) fn der_encode_key(key: &[u8]) -> Vec<u8> {
+    [BLS_DER_PREFIX, key].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::encode_block_index;
+
+    #[test]
+    fn test_decode_time() {
+        for time in [0, 1, 127, 128, 1_700_000_000_000_000_000, u64::MAX] {
+            assert_eq!(decode_time(&encode_block_index(time)), Some(time));
+        }
+        assert_eq!(decode_time(&[0xe5, 0x8e, 0x26]), Some(624_485));
+
+        // Empty, truncated, with trailing bytes, or beyond 64 bits.
+        assert_eq!(decode_time(&[]), None);
+        assert_eq!(decode_time(&[0x80]), None);
+        assert_eq!(decode_time(&[0x05, 0x00]), None);
+        let mut beyond_u64 = encode_block_index(u64::MAX);
+        *beyond_u64.last_mut().unwrap() = 0x02;
+        assert_eq!(decode_time(&beyond_u64), None);
+        assert_eq!(decode_time(&[0xff; 11]), None);
+    }
+}
@@ -0,0 +1,594 @@
+//! Off-chain verification of the tip certificate and of the blocks of an ICRC3 ledger.
+//!
+//! A client fetching `icrc3_get_tip_certificate` and `icrc3_get_blocks` from a ledger
+//! built on `bity-ic-icrc3` can check, without trusting the replica that answered:
+//!
+//! 1. that the certificate is signed by the IC for the ledger canister,
+//! 2. that the certified data of the ledger is the root hash of the returned hash tree,
+//! 3. that blocks hash, from one to the next, to the last block hash of the tree.
+//!
+//! [`verify_tip_certificate`] covers the first two points and returns the certified
+//! [`TipClaim`], [`verify_block_witness`] the last one. The hash rules are in
+//! [`hashing`], shared with the ledger.
+//!
+//! The BLS12-381 signatures are checked in [`bls`], as the IC signs them.
+//!
+//! # Example
+//! ```ignore
+//! let claim = verify_tip_certificate(&certificate, &root_key, ledger_id)?;
+//! verify_block_witness(&claim, &blocks)?;
+//! ```
+
+use candid::Principal;
+use ic_certification::{Hash, HashTree, LookupResult};
+use icrc_ledger_types::icrc3::blocks::{BlockWithId, ICRC3DataCertificate};
+use std::fmt;
+
+pub mod bls;
+pub mod certificate;
+pub use bity_ic_icrc3_archive_api::types::hashing;
+
+use hashing::{LAST_BLOCK_HASH_LABEL, LAST_BLOCK_INDEX_LABEL};
+
+/// The tip of the chain, as certified by the ledger.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TipClaim {
+    /// The index of the last block
+    pub last_block_index: u64,
+    /// The hash of the last block
+    pub last_block_hash: Hash,
+    /// The time of the certificate in nanoseconds, for the caller to check its freshness
+    pub certified_at_ns: u64,
+}
+
+/// A block returned by `icrc3_get_blocks`, with the timestamp it was hashed with.
+///
+/// The ledger hashes each block with the `timestamp` of its transaction, which
+/// is not part of the returned block unless the transaction type includes it.
+#[derive(Clone, Debug)]
+pub struct BlockWitness {
+    pub block: BlockWithId,
+    pub timestamp: u128,
+}
+
+/// Errors returned by [`verify_tip_certificate`] and [`verify_block_witness`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The certificate, or its delegation, cannot be decoded or lacks a path
+    MalformedCertificate(String),
+    /// The root key or the key of the subnet is not a DER encoded BLS12-381 key
+    MalformedKey,
+    /// The signature of the certificate, or of its delegation, is invalid
+    InvalidSignature(String),
+    /// The delegation is for a subnet that does not host the canister
+    CanisterNotInSubnet,
+    /// The certificate has no certified data for the canister
+    MissingCertifiedData,
+    /// The hash tree cannot be decoded or has unexpected contents
+    MalformedHashTree(String),
+    /// The root hash of the hash tree is not the certified data
+    CertifiedDataMismatch,
+    /// The hash tree certifies an empty chain
+    EmptyChain,
+    /// No block was given
+    EmptyWitness,
+    /// The blocks are not consecutive or do not end with the last block
+    UnexpectedBlockIndex { expected: u64, actual: String },
+    /// A block cannot be hashed as the ledger does
+    MalformedBlock { index: u64, reason: String },
+    /// A block does not hash to the value committed by the next block or the tip
+    BlockHashMismatch { index: u64 },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::MalformedCertificate(e) => write!(f, "Malformed certificate: {}", e),
+            VerifyError::MalformedKey => write!(f, "Malformed BLS public key"),
+            VerifyError::InvalidSignature(e) => write!(f, "Invalid signature: {}", e),
+            VerifyError::CanisterNotInSubnet => {
+                write!(
+                    f,
+                    "The canister is not in the ranges of the delegated subnet"
+                )
+            }
+            VerifyError::MissingCertifiedData => {
+                write!(f, "No certified data for the canister")
+            }
+            VerifyError::MalformedHashTree(e) => write!(f, "Malformed hash tree: {}", e),
+            VerifyError::CertifiedDataMismatch => {
+                write!(f, "The hash tree does not match the certified data")
+            }
+            VerifyError::EmptyChain => write!(f, "The certified chain is empty"),
+            VerifyError::EmptyWitness => write!(f, "No block to verify"),
+            VerifyError::UnexpectedBlockIndex { expected, actual } => {
+                write!(f, "Expected block {}, got block {}", expected, actual)
+            }
+            VerifyError::MalformedBlock { index, reason } => {
+                write!(f, "Malformed block {}: {}", index, reason)
+            }
+            VerifyError::BlockHashMismatch { index } => {
+                write!(f, "Block {} does not match its certified hash", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Verifies a tip certificate returned by `icrc3_get_tip_certificate`.
+///
+/// # Arguments
+///
+/// * `cert` - The tip certificate
+/// * `root_key` - The DER encoded root key of the IC
+/// * `canister_id` - The ledger canister
+///
+/// # Returns
+///
+/// * `Ok(TipClaim)` containing the certified tip
+/// * `Err(VerifyError)` describing the first failed check
+pub fn verify_tip_certificate(
+    cert: &ICRC3DataCertificate,
+    root_key: &[u8],
+    canister_id: Principal,
+) -> Result<TipClaim, VerifyError> {
+    let certificate = certificate::decode_certificate(&cert.certificate)?;
+    certificate::verify_certificate(&certificate, root_key, canister_id)?;
+
+    let certified_data = match certificate.tree.lookup_path([
+        b"canister".as_slice(),
+        canister_id.as_slice(),
+        b"certified_data",
+    ]) {
+        LookupResult::Found(certified_data) => certified_data,
+        _ => return Err(VerifyError::MissingCertifiedData),
+    };
+    let certified_at_ns = match certificate.tree.lookup_path([b"time"]) {
+        LookupResult::Found(time) => certificate::decode_time(time)
+            .ok_or_else(|| VerifyError::MalformedCertificate("invalid time".to_string()))?,
+        _ => {
+            return Err(VerifyError::MalformedCertificate(
+                "the time is not in the certificate".to_string(),
+            ))
+        }
+    };
+
+    let hash_tree: HashTree = serde_cbor::from_slice(&cert.hash_tree)
+        .map_err(|e| VerifyError::MalformedHashTree(e.to_string()))?;
+    if hash_tree.digest().as_slice() != certified_data {
+        return Err(VerifyError::CertifiedDataMismatch);
+    }
+
+    let last_block_index = hash_tree.lookup_path([LAST_BLOCK_INDEX_LABEL.as_bytes()]);
+    let last_block_hash = hash_tree.lookup_path([LAST_BLOCK_HASH_LABEL.as_bytes()]);
+    match (last_block_index, last_block_hash) {
+        (LookupResult::Found(index), LookupResult::Found(hash)) => Ok(TipClaim {
+            last_block_index: hashing::decode_block_index(index).ok_or_else(|| {
+                VerifyError::MalformedHashTree("invalid last block index".to_string())
+            })?,
+            last_block_hash: hash.try_into().map_err(|_| {
+                VerifyError::MalformedHashTree("invalid last block hash".to_string())
+            })?,
+            certified_at_ns,
+        }),
+        (LookupResult::Absent, LookupResult::Absent) => Err(VerifyError::EmptyChain),
+        _ => Err(VerifyError::MalformedHashTree(
+            "the tip is not fully revealed".to_string(),
+        )),
+    }
+}
+
+/// Verifies that blocks are part of the chain certified by a [`TipClaim`].
+///
+/// The blocks must be consecutive and end with the last block. The last block
+/// must hash to the certified hash, and every other block to the `phash` of the
+/// block following it.
+///
+/// # Arguments
+///
+/// * `claim` - The tip returned by [`verify_tip_certificate`]
+/// * `blocks` - The blocks to verify, in increasing order
+///
+/// # Returns
+///
+/// * `Ok(())` if every block is part of the certified chain
+/// * `Err(VerifyError)` describing the first failed check, from the tip down
+pub fn verify_block_witness(claim: &TipClaim, blocks: &[BlockWitness]) -> Result<(), VerifyError> {
+    verify_anchored_block_witness(claim, blocks, None)
+}
+
+/// Verifies that blocks are part of the chain certified by a [`TipClaim`], for a
+/// chain anchored to a parent chain with a `genesis_parent_hash`.
+///
+/// As [`verify_block_witness`], except that block 0 must have the genesis parent
+/// hash as `phash` and is hashed with it as parent hash.
+///
+/// # Arguments
+///
+/// * `claim` - The tip returned by [`verify_tip_certificate`]
+/// * `blocks` - The blocks to verify, in increasing order
+/// * `genesis_parent_hash` - The parent hash of block 0, `None` if the chain is not anchored
+///
+/// # Returns
+///
+/// * `Ok(())` if every block is part of the certified chain
+/// * `Err(VerifyError)` describing the first failed check, from the tip down
+pub fn verify_anchored_block_witness(
+    claim: &TipClaim,
+    blocks: &[BlockWitness],
+    genesis_parent_hash: Option<Hash>,
+) -> Result<(), VerifyError> {
+    if blocks.is_empty() {
+        return Err(VerifyError::EmptyWitness);
+    }
+    let first_index = claim
+        .last_block_index
+        .checked_sub(blocks.len() as u64 - 1)
+        .ok_or(VerifyError::UnexpectedBlockIndex {
+            expected: 0,
+            actual: "a block before block 0".to_string(),
+        })?;
+
+    let mut expected_hash = claim.last_block_hash;
+    for (offset, witness) in blocks.iter().enumerate().rev() {
+        let index = first_index + offset as u64;
+        if witness.block.id != index {
+            return Err(VerifyError::UnexpectedBlockIndex {
+                expected: index,
+                actual: witness.block.id.to_string(),
+            });
+        }
+
+        let parent_hash =
+            hashing::parent_hash(&witness.block.block).ok_or(VerifyError::MalformedBlock {
+                index,
+                reason: "no 32 bytes phash".to_string(),
+            })?;
+        if index == 0 && parent_hash != genesis_parent_hash.unwrap_or([0; 32]) {
+            return Err(VerifyError::MalformedBlock {
+                index,
+                reason: match genesis_parent_hash {
+                    None => "the first block has a parent".to_string(),
+                    Some(_) => "the first block is not anchored to the genesis parent".to_string(),
+                },
+            });
+        }
+        let block_hash = hashing::block_hash(
+            if index > 0 {
+                Some(parent_hash)
+            } else {
+                genesis_parent_hash
+            },
+            witness.timestamp,
+            &witness.block.block,
+        )
+        .ok_or(VerifyError::MalformedBlock {
+            index,
+            reason: "a number does not fit in 64 bits".to_string(),
+        })?;
+        if block_hash != expected_hash {
+            return Err(VerifyError::BlockHashMismatch { index });
+        }
+        expected_hash = parent_hash;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bls12_381::{G1Affine, G2Affine, Scalar};
+    use candid::Nat;
+    use ic_certification::{fork, labeled, leaf, AsHashTree, Certificate, Delegation};
+    use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+    use serde_bytes::ByteBuf;
+    use std::collections::BTreeMap;
+
+    const TIME_NS: u64 = 1_700_000_000_000_000_000;
+
+    /// A BLS12-381 key signing as the IC does.
+    struct SigningKey(Scalar);
+
+    impl SigningKey {
+        const ROOT: SigningKey = SigningKey(Scalar::from_raw([1, 2, 3, 4]));
+        const SUBNET: SigningKey = SigningKey(Scalar::from_raw([5, 6, 7, 8]));
+
+        fn der_public_key(&self) -> Vec<u8> {
+            let key = G2Affine::from(G2Affine::generator() * self.0);
+            certificate::der_encode_key(&key.to_compressed())
+        }
+
+        fn sign_tree(&self, tree: &HashTree) -> Vec<u8> {
+            let message = [b"\x0Dic-state-root".as_slice(), &tree.digest()].concat();
+            G1Affine::from(bls::hash_to_g1(&message) * self.0)
+                .to_compressed()
+                .to_vec()
+        }
+    }
+
+    fn root_key() -> Vec<u8> {
+        SigningKey::ROOT.der_public_key()
+    }
+
+    fn subnet_key() -> Vec<u8> {
+        SigningKey::SUBNET.der_public_key()
+    }
+
+    fn ledger_id() -> Principal {
+        Principal::from_slice(&[0, 0, 0, 0, 0, 0, 0, 5, 1, 1])
+    }
+
+    fn subnet_id() -> Principal {
+        Principal::from_slice(&[9; 29])
+    }
+
+    fn state_tree(canister_id: Principal, certified_data: &[u8]) -> HashTree {
+        fork(
+            labeled(
+                "canister",
+                labeled(
+                    canister_id.as_slice(),
+                    labeled("certified_data", leaf(certified_data)),
+                ),
+            ),
+            labeled("time", leaf(hashing::encode_block_index(TIME_NS))),
+        )
+    }
+
+    fn delegation(ranges: Vec<(Principal, Principal)>) -> Delegation {
+        let tree = labeled(
+            "subnet",
+            labeled(
+                subnet_id().as_slice(),
+                fork(
+                    labeled(
+                        "canister_ranges",
+                        leaf(serde_cbor::to_vec(&ranges).unwrap()),
+                    ),
+                    labeled("public_key", leaf(subnet_key())),
+                ),
+            ),
+        );
+        let certificate = Certificate {
+            signature: SigningKey::ROOT.sign_tree(&tree),
+            tree,
+            delegation: None,
+        };
+        Delegation {
+            subnet_id: subnet_id().as_slice().to_vec(),
+            certificate: serde_cbor::to_vec(&certificate).unwrap(),
+        }
+    }
+
+    /// A tip certificate as returned by a ledger on a subnet hosting `ledger_id`.
+    fn tip_certificate(tip: Option<(u64, Hash)>) -> ICRC3DataCertificate {
+        let hash_tree = hashing::tip_hash_tree(tip);
+        let tree = state_tree(ledger_id(), &hash_tree.root_hash());
+        let certificate = Certificate {
+            signature: SigningKey::SUBNET.sign_tree(&tree),
+            tree,
+            delegation: Some(delegation(vec![(ledger_id(), ledger_id())])),
+        };
+        ICRC3DataCertificate {
+            certificate: ByteBuf::from(serde_cbor::to_vec(&certificate).unwrap()),
+            hash_tree: ByteBuf::from(serde_cbor::to_vec(&hash_tree.as_hash_tree()).unwrap()),
+        }
+    }
+
+    /// A chain as built by the ledger, with the timestamps of its blocks.
+    fn chain(length: u64) -> Vec<BlockWitness> {
+        anchored_chain(length, None)
+    }
+
+    /// A chain whose block 0 has `genesis_parent_hash` as parent.
+    fn anchored_chain(length: u64, genesis_parent_hash: Option<Hash>) -> Vec<BlockWitness> {
+        let mut blocks: Vec<BlockWitness> = vec![];
+        let mut parent_hash = genesis_parent_hash;
+        for index in 0..length {
+            let transaction = ICRC3Value::Map(BTreeMap::from([
+                (
+                    "phash".to_string(),
+                    ICRC3Value::Blob(ByteBuf::from(parent_hash.unwrap_or([0; 32]).to_vec())),
+                ),
+                ("amount".to_string(), ICRC3Value::Nat(Nat::from(index))),
+            ]));
+            let timestamp = TIME_NS as u128 + index as u128;
+            parent_hash = hashing::block_hash(parent_hash, timestamp, &transaction);
+            blocks.push(BlockWitness {
+                block: BlockWithId {
+                    id: Nat::from(index),
+                    block: transaction,
+                },
+                timestamp,
+            });
+        }
+        blocks
+    }
+
+    fn tip_of(blocks: &[BlockWitness]) -> (u64, Hash) {
+        let last = blocks.last().unwrap();
+        let parent_hash = hashing::parent_hash(&last.block.block).unwrap();
+        let index = blocks.len() as u64 - 1;
+        let hash = hashing::block_hash(
+            (index > 0).then_some(parent_hash),
+            last.timestamp,
+            &last.block.block,
+        )
+        .unwrap();
+        (index, hash)
+    }
+
+    #[test]
+    fn test_valid_certificate_and_witness() {
+        let blocks = chain(5);
+        let tip = tip_of(&blocks);
+        let claim =
+            verify_tip_certificate(&tip_certificate(Some(tip)), &root_key(), ledger_id()).unwrap();
+        assert_eq!(
+            claim,
+            TipClaim {
+                last_block_index: 4,
+                last_block_hash: tip.1,
+                certified_at_ns: TIME_NS,
+            }
+        );
+
+        assert_eq!(verify_block_witness(&claim, &blocks), Ok(()));
+        assert_eq!(verify_block_witness(&claim, &blocks[2..]), Ok(()));
+        assert_eq!(
+            verify_block_witness(&claim, &[]),
+            Err(VerifyError::EmptyWitness)
+        );
+    }
+
+    #[test]
+    fn test_empty_chain() {
+        assert_eq!(
+            verify_tip_certificate(&tip_certificate(None), &root_key(), ledger_id()),
+            Err(VerifyError::EmptyChain)
+        );
+    }
+
+    #[test]
+    fn test_tampered_hash_tree_is_rejected() {
+        let tip = tip_of(&chain(3));
+        let mut cert = tip_certificate(Some(tip));
+
+        // A tree claiming another tip no longer matches the certified data.
+        let other = hashing::tip_hash_tree(Some((tip.0 + 1, tip.1))).as_hash_tree();
+        cert.hash_tree = ByteBuf::from(serde_cbor::to_vec(&other).unwrap());
+        assert_eq!(
+            verify_tip_certificate(&cert, &root_key(), ledger_id()),
+            Err(VerifyError::CertifiedDataMismatch)
+        );
+
+        // A pruned tree matches the certified data but does not reveal the tip.
+        let pruned = ic_certification::hash_tree::pruned::<Vec<u8>, _>(
+            hashing::tip_hash_tree(Some(tip)).root_hash(),
+        );
+        cert.hash_tree = ByteBuf::from(serde_cbor::to_vec(&pruned).unwrap());
+        assert!(matches!(
+            verify_tip_certificate(&cert, &root_key(), ledger_id()),
+            Err(VerifyError::MalformedHashTree(_))
+        ));
+
+        cert.hash_tree = ByteBuf::from(vec![1, 2, 3]);
+        assert!(matches!(
+            verify_tip_certificate(&cert, &root_key(), ledger_id()),
+            Err(VerifyError::MalformedHashTree(_))
+        ));
+    }
+
+    #[test]
+    fn test_tampered_certificate_is_rejected() {
+        let tip = tip_of(&chain(3));
+        let cert = tip_certificate(Some(tip));
+
+        // Another root key does not sign the delegation.
+        assert!(matches!(
+            verify_tip_certificate(
+                &cert,
+                &SigningKey(Scalar::from(3)).der_public_key(),
+                ledger_id(),
+            ),
+            Err(VerifyError::InvalidSignature(_))
+        ));
+        assert_eq!(
+            verify_tip_certificate(&cert, &[1; 96], ledger_id()),
+            Err(VerifyError::MalformedKey)
+        );
+        assert_eq!(
+            verify_tip_certificate(&cert, &certificate::der_encode_key(&[1; 96]), ledger_id()),
+            Err(VerifyError::MalformedKey)
+        );
+
+        // Another canister is not in the ranges of the subnet.
+        let other_id = Principal::from_slice(&[0, 0, 0, 0, 0, 0, 0, 6, 1, 1]);
+        assert_eq!(
+            verify_tip_certificate(&cert, &root_key(), other_id),
+            Err(VerifyError::CanisterNotInSubnet)
+        );
+
+        // A state tree with other certified data no longer matches its signature.
+        let mut certificate = certificate::decode_certificate(&cert.certificate).unwrap();
+        certificate.tree = state_tree(ledger_id(), &[0; 32]);
+        let tampered = ICRC3DataCertificate {
+            certificate: ByteBuf::from(serde_cbor::to_vec(&certificate).unwrap()),
+            hash_tree: cert.hash_tree.clone(),
+        };
+        assert!(matches!(
+            verify_tip_certificate(&tampered, &root_key(), ledger_id()),
+            Err(VerifyError::InvalidSignature(_))
+        ));
+    }
+
+    #[test]
+    fn test_tampered_blocks_are_rejected() {
+        let blocks = chain(4);
+        let claim = TipClaim {
+            last_block_index: 3,
+            last_block_hash: tip_of(&blocks).1,
+            certified_at_ns: TIME_NS,
+        };
+
+        let mut tampered = blocks.clone();
+        if let ICRC3Value::Map(map) = &mut tampered[1].block.block {
+            map.insert("amount".to_string(), ICRC3Value::Nat(Nat::from(100u64)));
+        }
+        assert_eq!(
+            verify_block_witness(&claim, &tampered),
+            Err(VerifyError::BlockHashMismatch { index: 1 })
+        );
+
+        let mut tampered = blocks.clone();
+        tampered[3].timestamp += 1;
+        assert_eq!(
+            verify_block_witness(&claim, &tampered),
+            Err(VerifyError::BlockHashMismatch { index: 3 })
+        );
+
+        // Blocks that do not end with the last block.
+        assert_eq!(
+            verify_block_witness(&claim, &blocks[..3]),
+            Err(VerifyError::UnexpectedBlockIndex {
+                expected: 3,
+                actual: "2".to_string()
+            })
+        );
+        assert!(matches!(
+            verify_block_witness(&claim, &chain(5)),
+            Err(VerifyError::UnexpectedBlockIndex { .. })
+        ));
+    }
+
+    #[test]
+    fn test_anchored_chain_is_verified_from_its_anchor() {
+        let anchor = [7; 32];
+        let blocks = anchored_chain(3, Some(anchor));
+        let claim = TipClaim {
+            last_block_index: 2,
+            last_block_hash: tip_of(&blocks).1,
+            certified_at_ns: TIME_NS,
+        };
+
+        assert_eq!(
+            verify_anchored_block_witness(&claim, &blocks, Some(anchor)),
+            Ok(())
+        );
+        assert_eq!(
+            verify_block_witness(&claim, &blocks),
+            Err(VerifyError::MalformedBlock {
+                index: 0,
+                reason: "the first block has a parent".to_string()
+            })
+        );
+        assert_eq!(
+            verify_anchored_block_witness(&claim, &blocks, Some([8; 32])),
+            Err(VerifyError::MalformedBlock {
+                index: 0,
+                reason: "the first block is not anchored to the genesis parent".to_string()
+            })
+        );
+    }
+}
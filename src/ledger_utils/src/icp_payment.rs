@@ -0,0 +1,431 @@
+//! Verification of ICP payments recorded by the legacy ICP ledger.
+//!
+//! A canister selling something for ICP receives the index of the block of the
+//! payment and must check that this block is a transfer to its account, of the
+//! expected amount. [`verify_icp_payment`] fetches the block with the legacy
+//! `query_blocks` method, following the archive callbacks when the block was
+//! archived, and matches its operation against an [`ExpectedTransfer`].
+//!
+//! The ledger calls go through the [`LegacyLedgerClient`] trait: on-chain,
+//! [`IcLegacyLedger`] forwards them to `ic_cdk`, and tests may provide their own
+//! client to the `_with` variants of the functions.
+
+use crate::account_id_eq_ct;
+use async_trait::async_trait;
+use bity_ic_types::CanisterId;
+use candid::CandidType;
+use ic_ledger_types::{
+    AccountIdentifier, Block, BlockIndex, GetBlocksArgs, GetBlocksResult, Operation,
+    QueryArchiveFn, QueryBlocksResponse, Timestamp, Tokens,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The legacy ledger calls used to fetch blocks.
+///
+/// Errors are returned as strings, formatted the same way as the `ic_cdk` call errors.
+#[async_trait]
+pub trait LegacyLedgerClient: Send + Sync {
+    /// Calls `query_blocks` on the ledger.
+    async fn query_blocks(
+        &self,
+        ledger: CanisterId,
+        args: GetBlocksArgs,
+    ) -> Result<QueryBlocksResponse, String>;
+
+    /// Calls the archive callback returned by `query_blocks`.
+    async fn query_archived_blocks(
+        &self,
+        callback: QueryArchiveFn,
+        args: GetBlocksArgs,
+    ) -> Result<GetBlocksResult, String>;
+}
+
+/// Client forwarding the ledger calls through `ic_cdk`.
+pub struct IcLegacyLedger;
+
+#[async_trait]
+impl LegacyLedgerClient for IcLegacyLedger {
+    async fn query_blocks(
+        &self,
+        ledger: CanisterId,
+        args: GetBlocksArgs,
+    ) -> Result<QueryBlocksResponse, String> {
+        ic_ledger_types::query_blocks(ledger, &args)
+            .await
+            .map_err(|e| format!("{e:?}"))
+    }
+
+    async fn query_archived_blocks(
+        &self,
+        callback: QueryArchiveFn,
+        args: GetBlocksArgs,
+    ) -> Result<GetBlocksResult, String> {
+        ic_ledger_types::query_archived_blocks(&callback, &args)
+            .await
+            .map_err(|e| format!("{e:?}"))
+    }
+}
+
+/// The transfer a payment is expected to be.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ExpectedTransfer {
+    /// The account the payment must come from, any account if `None`
+    pub from: Option<AccountIdentifier>,
+    /// The account the payment must be made to
+    pub to: AccountIdentifier,
+    /// The exact amount of the payment, fee excluded
+    pub amount: Tokens,
+    /// The memo of the payment, any memo if `None`
+    pub memo: Option<u64>,
+}
+
+/// A transfer that matched an [`ExpectedTransfer`].
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct VerifiedTransfer {
+    /// The index of the block of the transfer
+    pub block_index: BlockIndex,
+    /// The account the tokens were transferred from
+    pub from: AccountIdentifier,
+    /// The amount that was transferred
+    pub amount: Tokens,
+    /// The fee paid by the sender
+    pub fee: Tokens,
+    /// The memo of the transfer
+    pub memo: u64,
+    /// The time at which the ledger created the block
+    pub timestamp: Timestamp,
+}
+
+/// Errors returned by [`verify_icp_payment`] and [`get_icp_block`].
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum PaymentVerifyError {
+    /// A call to the ledger or to one of its archives failed
+    CallFailed(String),
+    /// The ledger or the archive rejected the block request
+    GetBlocksFailed(String),
+    /// The block does not exist, or was not returned by the ledger or its archives
+    BlockNotFound(BlockIndex),
+    /// The block has no operation
+    MissingOperation,
+    /// The block is a mint, burn or approval rather than a transfer
+    NotATransfer(Operation),
+    /// The tokens were transferred from another account
+    WrongSender { actual: AccountIdentifier },
+    /// The tokens were transferred to another account
+    WrongRecipient { actual: AccountIdentifier },
+    /// Another amount was transferred
+    WrongAmount { actual: Tokens },
+    /// The transfer has another memo
+    WrongMemo { actual: u64 },
+}
+
+impl fmt::Display for PaymentVerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaymentVerifyError::CallFailed(e) => write!(f, "Ledger call failed: {}", e),
+            PaymentVerifyError::GetBlocksFailed(e) => write!(f, "Failed to get block: {}", e),
+            PaymentVerifyError::BlockNotFound(index) => write!(f, "Block {} not found", index),
+            PaymentVerifyError::MissingOperation => write!(f, "Block has no operation"),
+            PaymentVerifyError::NotATransfer(operation) => {
+                write!(f, "Block is not a transfer: {:?}", operation)
+            }
+            PaymentVerifyError::WrongSender { actual } => {
+                write!(f, "Transfer is from {}", actual)
+            }
+            PaymentVerifyError::WrongRecipient { actual } => {
+                write!(f, "Transfer is to {}", actual)
+            }
+            PaymentVerifyError::WrongAmount { actual } => {
+                write!(f, "Transfer amount is {}", actual)
+            }
+            PaymentVerifyError::WrongMemo { actual } => write!(f, "Transfer memo is {}", actual),
+        }
+    }
+}
+
+impl std::error::Error for PaymentVerifyError {}
+
+/// Fetches a block of the legacy ICP ledger, from its archives if needed.
+///
+/// # Arguments
+/// * `ledger` - The ICP ledger canister
+/// * `block_index` - The index of the block
+///
+/// # Returns
+/// The block, or a `PaymentVerifyError` if it could not be fetched
+pub async fn get_icp_block(
+    ledger: CanisterId,
+    block_index: BlockIndex,
+) -> Result<Block, PaymentVerifyError> {
+    get_icp_block_with(&IcLegacyLedger, ledger, block_index).await
+}
+
+/// Same as [`get_icp_block`], making the ledger calls with `client`.
+pub async fn get_icp_block_with(
+    client: &dyn LegacyLedgerClient,
+    ledger: CanisterId,
+    block_index: BlockIndex,
+) -> Result<Block, PaymentVerifyError> {
+    let args = GetBlocksArgs {
+        start: block_index,
+        length: 1,
+    };
+    let response = client
+        .query_blocks(ledger, args.clone())
+        .await
+        .map_err(PaymentVerifyError::CallFailed)?;
+
+    if block_index >= response.chain_length {
+        return Err(PaymentVerifyError::BlockNotFound(block_index));
+    }
+    if response.first_block_index == block_index {
+        if let Some(block) = response.blocks.into_iter().next() {
+            return Ok(block);
+        }
+    }
+
+    let callback = response
+        .archived_blocks
+        .into_iter()
+        .find(|range| range.start <= block_index && block_index - range.start < range.length)
+        .map(|range| range.callback)
+        .ok_or(PaymentVerifyError::BlockNotFound(block_index))?;
+
+    client
+        .query_archived_blocks(callback, args)
+        .await
+        .map_err(PaymentVerifyError::CallFailed)?
+        .map_err(|e| PaymentVerifyError::GetBlocksFailed(e.to_string()))?
+        .blocks
+        .into_iter()
+        .next()
+        .ok_or(PaymentVerifyError::BlockNotFound(block_index))
+}
+
+/// Verifies that a block of the legacy ICP ledger is the expected payment.
+///
+/// The block must be a transfer, or a transfer made by an approved spender, of
+/// exactly the expected amount to the expected account.
+///
+/// # Arguments
+/// * `ledger` - The ICP ledger canister
+/// * `block_index` - The index of the block of the payment
+/// * `expected` - The transfer the payment is expected to be
+///
+/// # Returns
+/// The verified transfer, with its fee and timestamp, or the first mismatch found
+pub async fn verify_icp_payment(
+    ledger: CanisterId,
+    block_index: BlockIndex,
+    expected: ExpectedTransfer,
+) -> Result<VerifiedTransfer, PaymentVerifyError> {
+    verify_icp_payment_with(&IcLegacyLedger, ledger, block_index, expected).await
+}
+
+/// Same as [`verify_icp_payment`], making the ledger calls with `client`.
+pub async fn verify_icp_payment_with(
+    client: &dyn LegacyLedgerClient,
+    ledger: CanisterId,
+    block_index: BlockIndex,
+    expected: ExpectedTransfer,
+) -> Result<VerifiedTransfer, PaymentVerifyError> {
+    let block = get_icp_block_with(client, ledger, block_index).await?;
+    match_icp_transfer(block_index, &block, &expected)
+}
+
+/// Matches the operation of a block against an expected transfer.
+///
+/// # Arguments
+/// * `block_index` - The index of `block`
+/// * `block` - The block of the payment
+/// * `expected` - The transfer the payment is expected to be
+///
+/// # Returns
+/// The verified transfer, or the first mismatch found
+pub fn match_icp_transfer(
+    block_index: BlockIndex,
+    block: &Block,
+    expected: &ExpectedTransfer,
+) -> Result<VerifiedTransfer, PaymentVerifyError> {
+    let operation = block
+        .transaction
+        .operation
+        .as_ref()
+        .ok_or(PaymentVerifyError::MissingOperation)?;
+    let (from, to, amount, fee) = match operation {
+        Operation::Transfer {
+            from,
+            to,
+            amount,
+            fee,
+        }
+        | Operation::TransferFrom {
+            from,
+            to,
+            amount,
+            fee,
+            ..
+        } => (*from, *to, *amount, *fee),
+        Operation::Mint { .. } | Operation::Burn { .. } | Operation::Approve { .. } => {
+            return Err(PaymentVerifyError::NotATransfer(operation.clone()));
+        }
+    };
+
+    if expected
+        .from
+        .is_some_and(|expected_from| !account_id_eq_ct(&expected_from, &from))
+    {
+        return Err(PaymentVerifyError::WrongSender { actual: from });
+    }
+    if !account_id_eq_ct(&to, &expected.to) {
+        return Err(PaymentVerifyError::WrongRecipient { actual: to });
+    }
+    if amount != expected.amount {
+        return Err(PaymentVerifyError::WrongAmount { actual: amount });
+    }
+    let memo = block.transaction.memo.0;
+    if expected
+        .memo
+        .is_some_and(|expected_memo| expected_memo != memo)
+    {
+        return Err(PaymentVerifyError::WrongMemo { actual: memo });
+    }
+
+    Ok(VerifiedTransfer {
+        block_index,
+        from,
+        amount,
+        fee,
+        memo,
+        timestamp: block.timestamp,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+    use ic_ledger_types::{Memo, Transaction, DEFAULT_SUBACCOUNT};
+
+    fn account(id: u8) -> AccountIdentifier {
+        AccountIdentifier::new(&Principal::from_slice(&[id]), &DEFAULT_SUBACCOUNT)
+    }
+
+    fn block(operation: Operation, memo: u64) -> Block {
+        Block {
+            parent_hash: None,
+            transaction: Transaction {
+                memo: Memo(memo),
+                operation: Some(operation),
+                created_at_time: Timestamp { timestamp_nanos: 1 },
+                icrc1_memo: None,
+            },
+            timestamp: Timestamp { timestamp_nanos: 2 },
+        }
+    }
+
+    fn transfer(amount: u64) -> Operation {
+        Operation::Transfer {
+            from: account(1),
+            to: account(2),
+            amount: Tokens::from_e8s(amount),
+            fee: Tokens::from_e8s(10_000),
+        }
+    }
+
+    fn expected() -> ExpectedTransfer {
+        ExpectedTransfer {
+            from: Some(account(1)),
+            to: account(2),
+            amount: Tokens::from_e8s(100),
+            memo: Some(7),
+        }
+    }
+
+    #[test]
+    fn test_matching_transfer() {
+        let verified = match_icp_transfer(5, &block(transfer(100), 7), &expected()).unwrap();
+        assert_eq!(
+            verified,
+            VerifiedTransfer {
+                block_index: 5,
+                from: account(1),
+                amount: Tokens::from_e8s(100),
+                fee: Tokens::from_e8s(10_000),
+                memo: 7,
+                timestamp: Timestamp { timestamp_nanos: 2 },
+            }
+        );
+
+        let any_sender_or_memo = ExpectedTransfer {
+            from: None,
+            memo: None,
+            ..expected()
+        };
+        assert!(match_icp_transfer(5, &block(transfer(100), 8), &any_sender_or_memo).is_ok());
+    }
+
+    #[test]
+    fn test_mismatches() {
+        assert_eq!(
+            match_icp_transfer(5, &block(transfer(99), 7), &expected()),
+            Err(PaymentVerifyError::WrongAmount {
+                actual: Tokens::from_e8s(99)
+            })
+        );
+        assert_eq!(
+            match_icp_transfer(5, &block(transfer(100), 8), &expected()),
+            Err(PaymentVerifyError::WrongMemo { actual: 8 })
+        );
+
+        let to_other = ExpectedTransfer {
+            to: account(3),
+            ..expected()
+        };
+        assert_eq!(
+            match_icp_transfer(5, &block(transfer(100), 7), &to_other),
+            Err(PaymentVerifyError::WrongRecipient { actual: account(2) })
+        );
+        let from_other = ExpectedTransfer {
+            from: Some(account(3)),
+            ..expected()
+        };
+        assert_eq!(
+            match_icp_transfer(5, &block(transfer(100), 7), &from_other),
+            Err(PaymentVerifyError::WrongSender { actual: account(1) })
+        );
+    }
+
+    #[test]
+    fn test_only_transfers_are_payments() {
+        let mint = Operation::Mint {
+            to: account(2),
+            amount: Tokens::from_e8s(100),
+        };
+        assert_eq!(
+            match_icp_transfer(0, &block(mint.clone(), 7), &expected()),
+            Err(PaymentVerifyError::NotATransfer(mint))
+        );
+
+        let approve = Operation::Approve {
+            from: account(1),
+            spender: account(2),
+            expires_at: None,
+            fee: Tokens::from_e8s(10_000),
+        };
+        assert!(matches!(
+            match_icp_transfer(0, &block(approve, 7), &expected()),
+            Err(PaymentVerifyError::NotATransfer(_))
+        ));
+
+        let transfer_from = Operation::TransferFrom {
+            from: account(1),
+            to: account(2),
+            spender: account(3),
+            amount: Tokens::from_e8s(100),
+            fee: Tokens::from_e8s(10_000),
+        };
+        assert!(match_icp_transfer(0, &block(transfer_from, 7), &expected()).is_ok());
+    }
+}
@@ -1,8 +1,9 @@
 //! Module for handling Internet Computer ledger operations and account management.
 //!
 //! This module provides utilities for working with the Internet Computer's ledger system,
-//! including account identifier computation, subaccount management, and conversion between
-//! different account formats.
+//! including account identifier computation, subaccount management, parsing of user-provided
+//! account strings, and conversion between different account formats. The [`icp_payment`]
+//! module verifies ICP payments recorded by the legacy ICP ledger.
 //!
 //! # Example
 //! ```
@@ -17,6 +18,14 @@ use candid::Principal;
 use ic_ledger_types::{AccountIdentifier, Subaccount, DEFAULT_SUBACCOUNT};
 use icrc_ledger_types::icrc1::account::Account;
 use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+pub mod icp_payment;
+
+pub use icp_payment::{
+    get_icp_block, verify_icp_payment, ExpectedTransfer, PaymentVerifyError, VerifiedTransfer,
+};
 
 /// Computes a neuron staking subaccount using SHA-256 hashing.
 ///
@@ -69,6 +78,21 @@ pub fn icrc_account_to_legacy_account_id(icrc_account: Account) -> AccountIdenti
 ///
 /// # Returns
 /// The corresponding legacy account identifier
+///
+/// # Example
+/// ```
+/// use bity_ic_ledger_utils::{
+///     account_id_eq_ct, account_id_from_hex, account_id_to_hex, principal_to_legacy_account_id,
+/// };
+/// use candid::Principal;
+///
+/// let principal = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+/// let account_id = principal_to_legacy_account_id(principal, None);
+///
+/// let hex = account_id_to_hex(&account_id);
+/// let parsed = account_id_from_hex(&format!(" {} ", hex.to_uppercase())).unwrap();
+/// assert!(account_id_eq_ct(&parsed, &account_id));
+/// ```
 pub fn principal_to_legacy_account_id(
     principal: Principal,
     subaccount: Option<Subaccount>,
@@ -76,12 +100,164 @@ pub fn principal_to_legacy_account_id(
     AccountIdentifier::new(&principal, &subaccount.unwrap_or(DEFAULT_SUBACCOUNT))
 }
 
+/// Parses a legacy account identifier from its hex encoding.
+///
+/// Surrounding whitespace is trimmed and both cases are accepted. The input must be the
+/// full 64-character encoding, whose embedded CRC32 checksum is verified.
+///
+/// # Arguments
+/// * `s` - The hex encoded account identifier
+///
+/// # Returns
+/// The account identifier, or an `AccountParseError` describing why the input is invalid
+pub fn account_id_from_hex(s: &str) -> Result<AccountIdentifier, AccountParseError> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err(AccountParseError::Empty);
+    }
+    if s.len() != 64 {
+        return Err(AccountParseError::InvalidLegacyAccountId(format!(
+            "expected 64 hex characters, got {}",
+            s.len()
+        )));
+    }
+
+    AccountIdentifier::from_hex(s).map_err(AccountParseError::InvalidLegacyAccountId)
+}
+
+/// Formats a legacy account identifier in its canonical lowercase hex encoding.
+///
+/// # Arguments
+/// * `account_id` - The account identifier to format
+///
+/// # Returns
+/// The 64-character lowercase hex encoding, checksum included
+pub fn account_id_to_hex(account_id: &AccountIdentifier) -> String {
+    account_id.to_hex()
+}
+
+/// Compares two legacy account identifiers in constant time.
+///
+/// Every byte is compared whatever the position of the first difference, so that
+/// matching a payment against a user-supplied account does not leak timing information.
+///
+/// # Arguments
+/// * `a` - The first account identifier
+/// * `b` - The second account identifier
+///
+/// # Returns
+/// `true` if both account identifiers are equal
+pub fn account_id_eq_ct(a: &AccountIdentifier, b: &AccountIdentifier) -> bool {
+    let diff = a
+        .as_bytes()
+        .iter()
+        .zip(b.as_bytes())
+        .fold(0u8, |diff, (x, y)| diff | (x ^ y));
+    std::hint::black_box(diff) == 0
+}
+
+/// An account parsed from user input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParsedAccount {
+    /// A legacy 64-character hex account identifier
+    Legacy(AccountIdentifier),
+    /// An ICRC-1 account in its textual encoding
+    Icrc(Account),
+}
+
+/// Errors returned by [`parse_account_input`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccountParseError {
+    /// The input is empty
+    Empty,
+    /// The input looks like a legacy account identifier but is invalid (e.g. bad checksum)
+    InvalidLegacyAccountId(String),
+    /// The input is not a valid ICRC-1 textual encoding
+    InvalidIcrcAccount(String),
+    /// The input decodes to an ICRC-1 account but is not in its canonical form
+    NonCanonicalIcrcAccount { expected: String },
+}
+
+impl fmt::Display for AccountParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountParseError::Empty => write!(f, "Account input is empty"),
+            AccountParseError::InvalidLegacyAccountId(e) => {
+                write!(f, "Invalid legacy account identifier: {}", e)
+            }
+            AccountParseError::InvalidIcrcAccount(e) => write!(f, "Invalid ICRC-1 account: {}", e),
+            AccountParseError::NonCanonicalIcrcAccount { expected } => {
+                write!(
+                    f,
+                    "ICRC-1 account is not in canonical form, expected {}",
+                    expected
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for AccountParseError {}
+
+/// Parses an account provided by a user.
+///
+/// Accepts either a legacy account identifier (64 hex characters, upper or lower case,
+/// with its CRC32 checksum verified) or the ICRC-1 textual encoding of an account
+/// (including the checksum of non-default subaccounts). ICRC-1 accounts must be in
+/// their canonical form, as produced by [`format_icrc_account`].
+///
+/// # Arguments
+/// * `s` - The account string to parse
+///
+/// # Returns
+/// The parsed account, or an `AccountParseError` describing why the input is invalid
+pub fn parse_account_input(s: &str) -> Result<ParsedAccount, AccountParseError> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err(AccountParseError::Empty);
+    }
+
+    if s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit()) {
+        return account_id_from_hex(s).map(ParsedAccount::Legacy);
+    }
+
+    let account =
+        Account::from_str(s).map_err(|e| AccountParseError::InvalidIcrcAccount(e.to_string()))?;
+    let canonical = format_icrc_account(&account);
+    if canonical != s {
+        return Err(AccountParseError::NonCanonicalIcrcAccount {
+            expected: canonical,
+        });
+    }
+
+    Ok(ParsedAccount::Icrc(account))
+}
+
+/// Formats an ICRC-1 account in its canonical textual encoding.
+///
+/// The default subaccount is omitted; other subaccounts are appended with their
+/// checksum and without leading zeroes.
+///
+/// # Arguments
+/// * `account` - The account to format
+///
+/// # Returns
+/// The canonical textual representation of the account
+pub fn format_icrc_account(account: &Account) -> String {
+    account.to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use candid::Principal;
+    use ic_ledger_types::Subaccount;
     use icrc_ledger_types::icrc1::account::Account;
 
-    use crate::icrc_account_to_legacy_account_id;
+    use crate::{
+        account_id_eq_ct, account_id_from_hex, account_id_to_hex, format_icrc_account,
+        icrc_account_to_legacy_account_id, parse_account_input, principal_to_legacy_account_id,
+        AccountParseError, ParsedAccount,
+    };
 
     #[test]
     fn convert_icrc_account_to_legacy_account_id() {
@@ -119,4 +295,138 @@ mod tests {
 
         assert_eq!(result.to_hex(), expected_result)
     }
+
+    const SPEC_OWNER: &str = "k2t6j-2nvnp-4zjm3-25dtz-6xhaa-c7boj-5gayf-oj3xs-i43lp-teztq-6ae";
+
+    fn spec_account(subaccount: Option<[u8; 32]>) -> Account {
+        Account {
+            owner: Principal::from_text(SPEC_OWNER).unwrap(),
+            subaccount,
+        }
+    }
+
+    #[test]
+    fn parse_icrc_account_spec_vectors() {
+        let mut one = [0u8; 32];
+        one[31] = 1;
+        let mut sequence = [0u8; 32];
+        for (i, byte) in sequence.iter_mut().enumerate() {
+            *byte = i as u8 + 1;
+        }
+
+        let vectors = [
+            (spec_account(None), SPEC_OWNER.to_string()),
+            (spec_account(Some(one)), format!("{}-6cc627i.1", SPEC_OWNER)),
+            (
+                spec_account(Some(sequence)),
+                format!(
+                    "{}-dfxgiyy.102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20",
+                    SPEC_OWNER
+                ),
+            ),
+        ];
+
+        for (account, text) in vectors {
+            assert_eq!(format_icrc_account(&account), text);
+            assert_eq!(parse_account_input(&text), Ok(ParsedAccount::Icrc(account)));
+        }
+    }
+
+    #[test]
+    fn parse_icrc_account_rejects_malformed_inputs() {
+        let invalid = [
+            // bad checksum
+            format!("{}-6cc627j.1", SPEC_OWNER),
+            // missing checksum
+            format!("{}.1", SPEC_OWNER),
+            // leading zeroes in subaccount
+            format!("{}-6cc627i.01", SPEC_OWNER),
+            // explicit default subaccount
+            format!("{}.0", SPEC_OWNER),
+            // truncated principal
+            "k2t6j-2nvnp-4zjm3".to_string(),
+        ];
+        for input in invalid {
+            assert!(parse_account_input(&input).is_err(), "{}", input);
+        }
+
+        assert_eq!(
+            parse_account_input(&SPEC_OWNER.to_uppercase()),
+            Err(AccountParseError::NonCanonicalIcrcAccount {
+                expected: SPEC_OWNER.to_string()
+            })
+        );
+        assert_eq!(parse_account_input("  "), Err(AccountParseError::Empty));
+    }
+
+    #[test]
+    fn parse_legacy_account_id() {
+        let hex = "aacba041bbce2b03c66307a68ca2d5a704a1f87397694a1292d89ce757136f11";
+
+        let parsed = parse_account_input(hex).unwrap();
+        assert!(matches!(parsed, ParsedAccount::Legacy(ref id) if id.to_hex() == hex));
+        assert_eq!(parse_account_input(&hex.to_uppercase()), Ok(parsed));
+
+        // bad checksum
+        assert!(matches!(
+            parse_account_input("bacba041bbce2b03c66307a68ca2d5a704a1f87397694a1292d89ce757136f11"),
+            Err(AccountParseError::InvalidLegacyAccountId(_))
+        ));
+        // wrong length
+        assert!(parse_account_input(&hex[..62]).is_err());
+    }
+
+    #[test]
+    fn account_id_hex_fixtures() {
+        let hex = "aacba041bbce2b03c66307a68ca2d5a704a1f87397694a1292d89ce757136f11";
+        let account_id = principal_to_legacy_account_id(
+            Principal::from_text("465sx-szz6o-idcax-nrjhv-hprrp-qqx5e-7mqwr-wadib-uo7ap-lofbe-dae")
+                .unwrap(),
+            None,
+        );
+
+        assert_eq!(account_id_to_hex(&account_id), hex);
+        for input in [
+            hex.to_string(),
+            hex.to_uppercase(),
+            format!("  {}\n", hex),
+            format!("\t{} ", hex.to_uppercase()),
+        ] {
+            let parsed = account_id_from_hex(&input).unwrap();
+            assert!(account_id_eq_ct(&parsed, &account_id), "{:?}", input);
+            assert_eq!(account_id_to_hex(&parsed), hex);
+        }
+
+        // corrupted checksum
+        assert!(matches!(
+            account_id_from_hex("bacba041bbce2b03c66307a68ca2d5a704a1f87397694a1292d89ce757136f11"),
+            Err(AccountParseError::InvalidLegacyAccountId(_))
+        ));
+        // wrong length, including the 56-character hash without its checksum
+        for input in [&hex[..62], &hex[8..], &format!("{}00", hex)] {
+            assert!(matches!(
+                account_id_from_hex(input),
+                Err(AccountParseError::InvalidLegacyAccountId(_))
+            ));
+        }
+        // not hex
+        assert!(matches!(
+            account_id_from_hex(&format!("{}zz", &hex[..62])),
+            Err(AccountParseError::InvalidLegacyAccountId(_))
+        ));
+        assert_eq!(account_id_from_hex(" "), Err(AccountParseError::Empty));
+    }
+
+    #[test]
+    fn account_id_eq_ct_compares_every_byte() {
+        let principal = Principal::from_text(SPEC_OWNER).unwrap();
+        let mut one = [0u8; 32];
+        one[31] = 1;
+        let default = principal_to_legacy_account_id(principal, None);
+        let other = principal_to_legacy_account_id(principal, Some(Subaccount(one)));
+
+        assert!(account_id_eq_ct(&default, &default.clone()));
+        assert!(!account_id_eq_ct(&default, &other));
+        assert_eq!(account_id_eq_ct(&default, &other), default == other);
+    }
 }
@@ -26,8 +26,12 @@
 //! let deserialized: Person = deserialize(Cursor::new(&buffer)).unwrap();
 //! assert_eq!(person, deserialized);
 //! ```
+//!
+//! When the whole input is already in memory, [`deserialize_from_slice`] lets
+//! types with `#[serde(borrow)]` fields point into the input instead of copying
+//! large byte buffers.
 
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::error::Error;
 use std::io::{Read, Write};
 
@@ -71,3 +75,124 @@ where
     let mut deserializer = rmp_serde::Deserializer::new(reader);
     T::deserialize(&mut deserializer)
 }
+
+/// Serializes a value into a MessagePack byte vector.
+///
+/// Uses the same struct-map encoding as [`serialize`], without going through a writer.
+///
+/// # Arguments
+/// * `value` - The value to serialize
+///
+/// # Returns
+/// A `Result` containing either the serialized bytes on success or an error on failure
+///
+/// # Type Parameters
+/// * `T` - The type of the value to serialize (must implement `Serialize`)
+pub fn serialize_to_vec<T>(value: T) -> Result<Vec<u8>, impl Error>
+where
+    T: Serialize,
+{
+    rmp_serde::to_vec_named(&value)
+}
+
+/// Deserializes a value from a MessagePack byte slice.
+///
+/// Unlike [`deserialize`], the result may borrow from `bytes`, so fields marked
+/// `#[serde(borrow)]` (e.g. `&'a serde_bytes::Bytes` or `&'a str`) are not copied.
+///
+/// # Arguments
+/// * `bytes` - The serialized data
+///
+/// # Returns
+/// A `Result` containing either the deserialized value on success or an error on failure
+///
+/// # Type Parameters
+/// * `T` - The type of the value to deserialize (must implement `Deserialize<'a>`)
+pub fn deserialize_from_slice<'a, T>(bytes: &'a [u8]) -> Result<T, impl Error>
+where
+    T: Deserialize<'a>,
+{
+    rmp_serde::from_slice(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_bytes::{ByteBuf, Bytes};
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATED.try_with(|a| a.set(a.get() + layout.size()));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAllocator = CountingAllocator;
+
+    fn allocated_during<R>(f: impl FnOnce() -> R) -> (R, usize) {
+        let before = ALLOCATED.with(|a| a.get());
+        let result = f();
+        (result, ALLOCATED.with(|a| a.get()) - before)
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Owned {
+        name: String,
+        data: ByteBuf,
+    }
+
+    #[derive(Deserialize)]
+    struct Borrowed<'a> {
+        #[serde(borrow)]
+        name: &'a str,
+        #[serde(borrow)]
+        data: &'a Bytes,
+    }
+
+    #[test]
+    fn test_serialize_to_vec_matches_serialize() {
+        let value = Owned {
+            name: "blob".to_string(),
+            data: ByteBuf::from(vec![1, 2, 3]),
+        };
+
+        let mut buffer = Vec::new();
+        serialize(&value, &mut buffer).unwrap();
+
+        assert_eq!(serialize_to_vec(&value).unwrap(), buffer);
+    }
+
+    #[test]
+    fn test_deserialize_from_slice_borrows_large_blob() {
+        const SIZE: usize = 1024 * 1024;
+        let bytes = serialize_to_vec(Owned {
+            name: "blob".to_string(),
+            data: ByteBuf::from(vec![7u8; SIZE]),
+        })
+        .unwrap();
+
+        let (borrowed, borrowed_alloc) =
+            allocated_during(|| deserialize_from_slice::<Borrowed>(&bytes).unwrap());
+        assert_eq!(borrowed.name, "blob");
+        assert_eq!(borrowed.data.len(), SIZE);
+        assert!(borrowed_alloc < 1024);
+
+        let (owned, owned_alloc) =
+            allocated_during(|| deserialize_from_slice::<Owned>(&bytes).unwrap());
+        assert_eq!(owned.data.len(), SIZE);
+        assert!(owned_alloc >= SIZE);
+    }
+}
@@ -1,7 +1,11 @@
 //! Module for managing stable memory in the Internet Computer context.
 //!
 //! This module provides utilities for efficiently reading and writing to stable memory
-//! using buffers, and allows tracking memory usage.
+//! using buffers, and allows tracking memory usage and the progress of large transfers.
+//!
+//! A state saved with [`save_state`] is preceded by a header, so that
+//! [`restore_state`] can tell a memory that was never written, where
+//! [`get_reader`] would happily read zeros, from a saved state.
 //!
 //! # Example
 //! ```
@@ -18,12 +22,21 @@ use ic_stable_structures::reader::{BufferedReader, Reader};
 use ic_stable_structures::writer::{BufferedWriter, Writer};
 use ic_stable_structures::Memory;
 use std::cmp::min;
-use std::io::{Read, Write};
+use std::fmt;
+use std::io::{self, Read, Write};
 
 const MAX_READER_WRITER_BUFFER_SIZE: usize = 1024 * 1024; // 1MB
 
+/// Magic bytes and format version at the start of a state saved with [`save_state`].
+const STATE_HEADER: [u8; 8] = *b"BITYST\x00\x01";
+/// Number of bytes of the header, the state itself starts right after it.
+pub const STATE_HEADER_LEN: u64 = STATE_HEADER.len() as u64;
+
 /// Creates a new buffered reader for stable memory.
 ///
+/// The memory is read as is: a memory that was never written reads as zeros. Use
+/// [`restore_state`] to tell it from a saved state.
+///
 /// # Arguments
 /// * `memory` - The stable memory to use
 ///
@@ -44,6 +57,318 @@ pub fn get_writer<M: Memory>(memory: &mut M) -> impl Write + '_ {
     BufferedWriter::new(MAX_READER_WRITER_BUFFER_SIZE, Writer::new(memory, 0))
 }
 
+/// The result of [`restore_state`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RestoreOutcome<T> {
+    /// A saved state was found and deserialized
+    Restored(T),
+    /// No valid header was found: the memory was never written, or was wiped
+    FreshInstall,
+}
+
+/// Returns whether the memory holds a state saved with [`save_state`].
+///
+/// # Arguments
+/// * `memory` - The stable memory to check
+///
+/// # Returns
+/// `true` if the memory starts with a valid state header
+pub fn state_exists<M: Memory>(memory: &M) -> bool {
+    if memory.size() == 0 {
+        return false;
+    }
+    let mut header = [0; STATE_HEADER.len()];
+    memory.read(0, &mut header);
+    header == STATE_HEADER
+}
+
+/// Saves a state to stable memory, after a header marking it as valid.
+///
+/// The header is written once `serialize` succeeded and the state was flushed,
+/// so a failed save leaves no state behind rather than a partial one.
+///
+/// # Arguments
+/// * `memory` - The stable memory to write to
+/// * `serialize` - Writes the state to the given writer
+///
+/// # Returns
+/// The error of `serialize`, if any
+///
+/// # Example
+/// ```ignore
+/// save_state(&mut memory, |writer| bity_ic_serializer::serialize(state, writer))?;
+/// ```
+pub fn save_state<M: Memory, E: From<io::Error>, F>(memory: &mut M, serialize: F) -> Result<(), E>
+where
+    F: FnOnce(&mut dyn Write) -> Result<(), E>,
+{
+    wipe_state(memory);
+    let mut writer = BufferedWriter::new(
+        MAX_READER_WRITER_BUFFER_SIZE,
+        Writer::new(memory, STATE_HEADER_LEN),
+    );
+    serialize(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    memory.write(0, &STATE_HEADER);
+    Ok(())
+}
+
+/// Restores a state saved with [`save_state`].
+///
+/// # Arguments
+/// * `memory` - The stable memory to read from
+/// * `deserialize` - Reads the state from the given reader
+///
+/// # Returns
+/// [`RestoreOutcome::FreshInstall`] without calling `deserialize` if there is no
+/// saved state, so that the caller decides between initializing a default state
+/// and failing loudly, or the result of `deserialize`
+///
+/// # Example
+/// ```ignore
+/// match restore_state(&memory, |reader| bity_ic_serializer::deserialize(reader))? {
+///     RestoreOutcome::Restored(state) => state,
+///     RestoreOutcome::FreshInstall => panic!("No state to restore"),
+/// }
+/// ```
+pub fn restore_state<M: Memory, T, E, F>(memory: &M, deserialize: F) -> Result<RestoreOutcome<T>, E>
+where
+    F: FnOnce(&mut dyn Read) -> Result<T, E>,
+{
+    if !state_exists(memory) {
+        return Ok(RestoreOutcome::FreshInstall);
+    }
+    let mut reader =
+        BufferedReader::new(buffer_size(memory), Reader::new(memory, STATE_HEADER_LEN));
+    deserialize(&mut reader).map(RestoreOutcome::Restored)
+}
+
+/// Reads a state saved with [`save_state`] into a buffer, without deserializing it.
+///
+/// The buffer holds everything after the header, up to the end of the memory: the
+/// state is followed by the bytes left over in its last pages.
+///
+/// # Arguments
+/// * `memory` - The stable memory to read from
+///
+/// # Returns
+/// [`RestoreOutcome::FreshInstall`] if there is no saved state, the bytes otherwise
+///
+/// # Example
+/// Large states can be deserialized with `bity_ic_serializer::deserialize_from_slice`,
+/// so that fields marked `#[serde(borrow)]` point into the buffer instead of being copied:
+/// ```ignore
+/// let RestoreOutcome::Restored(bytes) = read_state(&memory) else {
+///     panic!("No state to restore");
+/// };
+/// let state: StateView<'_> = bity_ic_serializer::deserialize_from_slice(&bytes).unwrap();
+/// ```
+pub fn read_state<M: Memory>(memory: &M) -> RestoreOutcome<Vec<u8>> {
+    if !state_exists(memory) {
+        return RestoreOutcome::FreshInstall;
+    }
+    let memory_size = memory.size() * WASM_PAGE_SIZE_IN_BYTES;
+    let mut bytes = vec![0; (memory_size - STATE_HEADER_LEN) as usize];
+    memory.read(STATE_HEADER_LEN, &mut bytes);
+    RestoreOutcome::Restored(bytes)
+}
+
+/// Invalidates a state saved with [`save_state`] by clearing its header only, the
+/// state bytes are left as they are.
+///
+/// # Arguments
+/// * `memory` - The stable memory to wipe
+pub fn wipe_state<M: Memory>(memory: &mut M) {
+    if state_exists(memory) {
+        memory.write(0, &[0; STATE_HEADER.len()]);
+    }
+}
+
+/// Creates a new buffered reader for stable memory reporting its progress.
+///
+/// # Arguments
+/// * `memory` - The stable memory to use
+/// * `every_bytes` - The number of bytes read between two calls to `callback`
+/// * `callback` - Called with the transfer stats so far every `every_bytes` bytes
+///
+/// # Returns
+/// A [`ProgressReader`] reading data from stable memory
+///
+/// # Panics
+/// Panics if `every_bytes` is 0
+pub fn get_reader_with_progress<M: Memory, F: FnMut(TransferStats)>(
+    memory: &M,
+    every_bytes: u64,
+    callback: F,
+) -> ProgressReader<impl Read + '_, F> {
+    ProgressReader {
+        inner: get_reader(memory),
+        progress: Progress::new(every_bytes, callback),
+    }
+}
+
+/// Creates a new buffered writer for stable memory reporting its progress.
+///
+/// # Arguments
+/// * `memory` - The stable memory to use
+/// * `every_bytes` - The number of bytes written between two calls to `callback`
+/// * `callback` - Called with the transfer stats so far every `every_bytes` bytes
+///
+/// # Returns
+/// A [`ProgressWriter`] writing data to stable memory
+///
+/// # Panics
+/// Panics if `every_bytes` is 0
+pub fn get_writer_with_progress<M: Memory, F: FnMut(TransferStats)>(
+    memory: &mut M,
+    every_bytes: u64,
+    callback: F,
+) -> ProgressWriter<impl Write + '_, F> {
+    ProgressWriter {
+        inner: get_writer(memory),
+        progress: Progress::new(every_bytes, callback),
+    }
+}
+
+/// Copies all the data of a reader into a writer, then flushes the writer.
+///
+/// # Arguments
+/// * `reader` - The reader to copy from
+/// * `writer` - The writer to copy to
+///
+/// # Returns
+/// The number of bytes copied and the instructions it took
+pub fn copy_to_writer<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+) -> io::Result<TransferStats> {
+    let start_instructions = instruction_counter();
+    let bytes = io::copy(&mut reader, &mut writer)?;
+    writer.flush()?;
+
+    Ok(TransferStats {
+        bytes,
+        instructions: instruction_counter() - start_instructions,
+    })
+}
+
+/// Summary of a transfer to or from stable memory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TransferStats {
+    /// Number of bytes transferred
+    pub bytes: u64,
+    /// Number of instructions executed during the transfer, always 0 off-chain
+    pub instructions: u64,
+}
+
+impl fmt::Display for TransferStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes in {} instructions",
+            self.bytes, self.instructions
+        )
+    }
+}
+
+/// A reader counting the bytes read, see [`get_reader_with_progress`].
+pub struct ProgressReader<R, F> {
+    inner: R,
+    progress: Progress<F>,
+}
+
+impl<R, F: FnMut(TransferStats)> ProgressReader<R, F> {
+    /// Returns the stats of the bytes read so far.
+    pub fn stats(&self) -> TransferStats {
+        self.progress.stats()
+    }
+}
+
+impl<R: Read, F: FnMut(TransferStats)> Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.progress.record(read);
+        Ok(read)
+    }
+}
+
+/// A writer counting the bytes written, see [`get_writer_with_progress`].
+pub struct ProgressWriter<W, F> {
+    inner: W,
+    progress: Progress<F>,
+}
+
+impl<W, F: FnMut(TransferStats)> ProgressWriter<W, F> {
+    /// Returns the stats of the bytes written so far.
+    pub fn stats(&self) -> TransferStats {
+        self.progress.stats()
+    }
+}
+
+impl<W: Write, F: FnMut(TransferStats)> Write for ProgressWriter<W, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.progress.record(written);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Progress of a transfer, reported to a callback every `every_bytes` bytes.
+struct Progress<F> {
+    every_bytes: u64,
+    next_report: u64,
+    bytes: u64,
+    start_instructions: u64,
+    callback: F,
+}
+
+impl<F: FnMut(TransferStats)> Progress<F> {
+    fn new(every_bytes: u64, callback: F) -> Self {
+        assert!(every_bytes > 0, "every_bytes must be greater than 0");
+        Progress {
+            every_bytes,
+            next_report: every_bytes,
+            bytes: 0,
+            start_instructions: instruction_counter(),
+            callback,
+        }
+    }
+
+    fn stats(&self) -> TransferStats {
+        TransferStats {
+            bytes: self.bytes,
+            instructions: instruction_counter() - self.start_instructions,
+        }
+    }
+
+    /// Counts transferred bytes, calling the callback once if a report is due.
+    fn record(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+        if self.bytes >= self.next_report {
+            let stats = self.stats();
+            (self.callback)(stats);
+            self.next_report = (self.bytes / self.every_bytes + 1) * self.every_bytes;
+        }
+    }
+}
+
+/// Returns the number of instructions executed since the start of the message.
+#[cfg(target_arch = "wasm32")]
+fn instruction_counter() -> u64 {
+    ic_cdk::api::instruction_counter()
+}
+
+/// Returns 0, as there is no instruction counter off-chain.
+#[cfg(not(target_arch = "wasm32"))]
+fn instruction_counter() -> u64 {
+    0
+}
+
 /// Calculates the optimal buffer size based on memory size.
 ///
 /// # Arguments
@@ -67,3 +392,149 @@ fn buffer_size<M: Memory>(memory: &M) -> usize {
 pub fn used() -> u64 {
     (stable_size() as u64) * (WASM_PAGE_SIZE_IN_BYTES as u64)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_stable_structures::DefaultMemoryImpl;
+
+    #[test]
+    fn test_progress_callbacks_fire_every_n_bytes() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+        let mut memory = DefaultMemoryImpl::default();
+
+        let mut reports = Vec::new();
+        let mut writer =
+            get_writer_with_progress(&mut memory, 1000, |stats| reports.push(stats.bytes));
+        for chunk in data.chunks(100) {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.flush().unwrap();
+        assert_eq!(writer.stats().bytes, data.len() as u64);
+        drop(writer);
+        assert_eq!(reports, (1..=10).map(|i| i * 1000).collect::<Vec<u64>>());
+
+        // A write crossing several steps is reported once.
+        let mut reports = Vec::new();
+        let mut writer =
+            get_writer_with_progress(&mut memory, 1000, |stats| reports.push(stats.bytes));
+        writer.write_all(&data[..2500]).unwrap();
+        writer.write_all(&data[2500..3000]).unwrap();
+        drop(writer);
+        assert_eq!(reports, vec![2500, 3000]);
+
+        let mut reports = Vec::new();
+        let mut reader = get_reader_with_progress(&memory, 4096, |stats| reports.push(stats.bytes));
+        let mut read = vec![0; data.len()];
+        for chunk in read.chunks_mut(512) {
+            reader.read_exact(chunk).unwrap();
+        }
+        assert_eq!(reader.stats().bytes, data.len() as u64);
+        drop(reader);
+        assert_eq!(reports, vec![4096, 8192]);
+        assert_eq!(&read[3000..], &data[3000..]);
+    }
+
+    #[test]
+    fn test_copy_to_writer_counts_bytes_written() {
+        let data = vec![7u8; 300_000];
+        let mut memory = DefaultMemoryImpl::default();
+
+        let stats = copy_to_writer(&data[..], get_writer(&mut memory)).unwrap();
+        assert_eq!(
+            stats,
+            TransferStats {
+                bytes: data.len() as u64,
+                instructions: 0,
+            }
+        );
+
+        let mut read = Vec::new();
+        let stats = copy_to_writer(get_reader(&memory).take(data.len() as u64), &mut read).unwrap();
+        assert_eq!(stats.bytes, data.len() as u64);
+        assert_eq!(read, data);
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct State(Vec<u8>);
+
+    fn save(memory: &mut DefaultMemoryImpl, state: &State) -> io::Result<()> {
+        save_state(memory, |writer| {
+            writer.write_all(&(state.0.len() as u64).to_le_bytes())?;
+            writer.write_all(&state.0)
+        })
+    }
+
+    fn restore(memory: &DefaultMemoryImpl) -> io::Result<RestoreOutcome<State>> {
+        restore_state(memory, |reader| {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len)?;
+            let mut bytes = vec![0; u64::from_le_bytes(len) as usize];
+            reader.read_exact(&mut bytes)?;
+            Ok(State(bytes))
+        })
+    }
+
+    #[test]
+    fn test_fresh_memory_has_no_state() {
+        let mut memory = DefaultMemoryImpl::default();
+        assert!(!state_exists(&memory));
+        assert_eq!(restore(&memory).unwrap(), RestoreOutcome::FreshInstall);
+
+        // Zeros, or data written without the header, are not a state either.
+        memory.grow(1);
+        assert!(!state_exists(&memory));
+        get_writer(&mut memory).write_all(&[0x80; 64]).unwrap();
+        assert!(!state_exists(&memory));
+        assert_eq!(restore(&memory).unwrap(), RestoreOutcome::FreshInstall);
+        assert_eq!(read_state(&memory), RestoreOutcome::FreshInstall);
+    }
+
+    #[test]
+    fn test_saved_state_is_restored() {
+        let mut memory = DefaultMemoryImpl::default();
+        let state = State((0..5_000u32).map(|i| i as u8).collect());
+        save(&mut memory, &state).unwrap();
+        assert!(state_exists(&memory));
+        assert_eq!(
+            restore(&memory).unwrap(),
+            RestoreOutcome::Restored(state.clone())
+        );
+
+        let RestoreOutcome::Restored(bytes) = read_state(&memory) else {
+            panic!("no state read");
+        };
+        assert_eq!(bytes[..8], (state.0.len() as u64).to_le_bytes());
+        assert_eq!(bytes[8..8 + state.0.len()], state.0[..]);
+
+        // A failed save leaves no state behind.
+        let result: io::Result<()> = save_state(&mut memory, |_| {
+            Err(io::Error::other("serialization failed"))
+        });
+        assert!(result.is_err());
+        assert!(!state_exists(&memory));
+    }
+
+    #[test]
+    fn test_wiped_state_is_a_fresh_install() {
+        let mut memory = DefaultMemoryImpl::default();
+        let state = State(vec![7; 100]);
+        save(&mut memory, &state).unwrap();
+
+        wipe_state(&mut memory);
+        assert!(!state_exists(&memory));
+        assert_eq!(restore(&memory).unwrap(), RestoreOutcome::FreshInstall);
+        // Only the header was cleared.
+        let mut bytes = [0; 8];
+        memory.read(STATE_HEADER_LEN + 8, &mut bytes);
+        assert_eq!(bytes, [7; 8]);
+
+        // Wiping a fresh memory does not grow it.
+        let mut memory = DefaultMemoryImpl::default();
+        wipe_state(&mut memory);
+        assert_eq!(memory.size(), 0);
+
+        save(&mut memory, &state).unwrap();
+        assert_eq!(restore(&memory).unwrap(), RestoreOutcome::Restored(state));
+    }
+}
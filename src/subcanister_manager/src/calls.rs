@@ -0,0 +1,305 @@
+//! Management canister calls on the sub-canisters, made without borrowing the
+//! manager.
+//!
+//! A manager kept behind a lock takes a [`SubCanisterCalls`] handle with
+//! [`SubCanisterManager::calls`](crate::SubCanisterManager::calls), releases the
+//! lock while the calls await, and records their outcome once it holds the lock
+//! again. Other messages can use the manager in the meantime instead of trapping
+//! on the lock.
+
+use crate::management::{CanisterStatusSummary, ManagementCanisterClient};
+use crate::{ControllerError, ReinstallError, SnapshotError};
+use bity_ic_utils::retry_async::retry_async;
+use candid::Principal;
+use ic_cdk::management_canister::{
+    CanisterSettings, InstallCodeArgs, Snapshot, SnapshotId, UpdateSettingsArgs,
+};
+use std::sync::Arc;
+
+/// The cycle balances sampled by [`SubCanisterCalls::sample_cycles`], recorded
+/// with [`SubCanisterManager::record_cycles_sample`](crate::SubCanisterManager::record_cycles_sample).
+///
+/// # Fields
+///
+/// * `balances` - The balance of each sub-canister, or why it could not be sampled
+/// * `master_cycles` - The balance of the master canister
+#[derive(Clone, Debug, PartialEq)]
+pub struct CyclesSample {
+    pub balances: Vec<(Principal, Result<u128, String>)>,
+    pub master_cycles: u128,
+}
+
+/// The management canister client of a manager, with the master canister and the
+/// sub-canisters when the handle was taken.
+#[derive(Clone)]
+pub struct SubCanisterCalls {
+    management: Arc<dyn ManagementCanisterClient>,
+    master_canister_id: Principal,
+    canister_ids: Vec<Principal>,
+}
+
+impl SubCanisterCalls {
+    pub(crate) fn new(
+        management: Arc<dyn ManagementCanisterClient>,
+        master_canister_id: Principal,
+        mut canister_ids: Vec<Principal>,
+    ) -> Self {
+        canister_ids.sort();
+        Self {
+            management,
+            master_canister_id,
+            canister_ids,
+        }
+    }
+
+    /// Samples the cycle balance of every sub-canister and of the master canister.
+    pub async fn sample_cycles(&self) -> CyclesSample {
+        let mut balances = Vec::with_capacity(self.canister_ids.len());
+        for &canister_id in &self.canister_ids {
+            let balance = self
+                .management
+                .canister_status_summary(canister_id)
+                .await
+                .map(|status| status.cycles);
+            balances.push((canister_id, balance));
+        }
+        CyclesSample {
+            balances,
+            master_cycles: self.management.own_cycle_balance(),
+        }
+    }
+
+    /// Fetches the status of every sub-canister, to be compared with the records by
+    /// [`SubCanisterManager::apply_reconciliation`](crate::SubCanisterManager::apply_reconciliation).
+    pub async fn canister_statuses(
+        &self,
+    ) -> Vec<(Principal, Result<CanisterStatusSummary, String>)> {
+        let mut statuses = Vec::with_capacity(self.canister_ids.len());
+        for &canister_id in &self.canister_ids {
+            let status = retry_async(
+                async || self.management.canister_status_summary(canister_id).await,
+                3,
+            )
+            .await;
+            statuses.push((canister_id, status));
+        }
+        statuses
+    }
+
+    /// Stops a sub-canister, reinstalls it and starts it again.
+    ///
+    /// The arguments are prepared with [`SubCanisterManager::reinstall_args`](crate::SubCanisterManager::reinstall_args),
+    /// and the reinstall is recorded with [`SubCanisterManager::record_reinstall`](crate::SubCanisterManager::record_reinstall).
+    pub async fn reinstall(&self, install_args: InstallCodeArgs) -> Result<(), ReinstallError> {
+        let canister_id = install_args.canister_id;
+        retry_async(async || self.management.stop_canister(canister_id).await, 3)
+            .await
+            .map_err(ReinstallError::StopCanisterError)?;
+
+        retry_async(
+            async || self.management.install_code(install_args.clone()).await,
+            3,
+        )
+        .await
+        .map_err(ReinstallError::InstallCodeError)?;
+
+        retry_async(
+            async || self.management.start_canister(canister_id).await,
+            3,
+        )
+        .await
+        .map_err(ReinstallError::StartCanisterError)
+    }
+
+    /// Adds a controller to a sub-canister.
+    ///
+    /// # Returns
+    /// The controllers of the sub-canister, as verified via `canister_status`, to
+    /// be cached with [`SubCanisterManager::record_controllers`](crate::SubCanisterManager::record_controllers).
+    pub async fn add_controller(
+        &self,
+        canister_id: Principal,
+        controller: Principal,
+    ) -> Result<Vec<Principal>, ControllerError> {
+        let mut controllers = self.fetch_controllers(canister_id).await?;
+        if controllers.contains(&controller) {
+            return Ok(controllers);
+        }
+
+        controllers.push(controller);
+        self.set_controllers(canister_id, controllers).await
+    }
+
+    /// Removes a controller from a sub-canister, which cannot be the master canister.
+    ///
+    /// # Returns
+    /// The controllers of the sub-canister, as verified via `canister_status`, to
+    /// be cached with [`SubCanisterManager::record_controllers`](crate::SubCanisterManager::record_controllers).
+    pub async fn remove_controller(
+        &self,
+        canister_id: Principal,
+        controller: Principal,
+    ) -> Result<Vec<Principal>, ControllerError> {
+        if controller == self.master_canister_id {
+            return Err(ControllerError::CannotRemoveMasterCanister);
+        }
+
+        let mut controllers = self.fetch_controllers(canister_id).await?;
+        controllers.retain(|c| *c != controller);
+        self.set_controllers(canister_id, controllers).await
+    }
+
+    /// Replaces the controllers of a sub-canister and checks them via `canister_status`.
+    pub async fn set_controllers(
+        &self,
+        canister_id: Principal,
+        controllers: Vec<Principal>,
+    ) -> Result<Vec<Principal>, ControllerError> {
+        if self.canister_ids.binary_search(&canister_id).is_err() {
+            return Err(ControllerError::UnknownCanister(canister_id));
+        }
+        let args = UpdateSettingsArgs {
+            canister_id,
+            settings: CanisterSettings {
+                controllers: Some(controllers.clone()),
+                ..Default::default()
+            },
+        };
+
+        retry_async(
+            async || self.management.update_settings(args.clone()).await,
+            3,
+        )
+        .await
+        .map_err(ControllerError::UpdateSettingsError)?;
+
+        let actual = self.fetch_controllers(canister_id).await?;
+        let mut expected_sorted = controllers.clone();
+        let mut actual_sorted = actual.clone();
+        expected_sorted.sort();
+        actual_sorted.sort();
+        if expected_sorted != actual_sorted {
+            return Err(ControllerError::ControllersMismatch {
+                expected: controllers,
+                actual,
+            });
+        }
+
+        Ok(actual)
+    }
+
+    async fn fetch_controllers(
+        &self,
+        canister_id: Principal,
+    ) -> Result<Vec<Principal>, ControllerError> {
+        if self.canister_ids.binary_search(&canister_id).is_err() {
+            return Err(ControllerError::UnknownCanister(canister_id));
+        }
+
+        retry_async(
+            async || self.management.canister_controllers(canister_id).await,
+            3,
+        )
+        .await
+        .map_err(ControllerError::CanisterStatusError)
+    }
+
+    /// Takes a snapshot of a sub-canister, replacing its most recent snapshot,
+    /// see [`SubCanisterManager::take_snapshot`](crate::SubCanisterManager::take_snapshot).
+    pub async fn take_snapshot(&self, canister_id: Principal) -> Result<SnapshotId, SnapshotError> {
+        let replace_snapshot = self
+            .list_snapshots(canister_id)
+            .await?
+            .into_iter()
+            .max_by_key(|snapshot| snapshot.taken_at_timestamp)
+            .map(|snapshot| snapshot.id);
+
+        retry_async(
+            async || {
+                self.management
+                    .take_canister_snapshot(canister_id, replace_snapshot.clone())
+                    .await
+            },
+            3,
+        )
+        .await
+        .map(|snapshot| snapshot.id)
+        .map_err(SnapshotError::TakeSnapshotError)
+    }
+
+    /// Lists the snapshots of a sub-canister.
+    pub async fn list_snapshots(
+        &self,
+        canister_id: Principal,
+    ) -> Result<Vec<Snapshot>, SnapshotError> {
+        self.check_known_canister(canister_id)?;
+
+        retry_async(
+            async || self.management.list_canister_snapshots(canister_id).await,
+            3,
+        )
+        .await
+        .map_err(SnapshotError::ListSnapshotsError)
+    }
+
+    /// Stops a sub-canister, loads one of its snapshots and starts it again.
+    ///
+    /// The new state of the sub-canister is recorded with
+    /// [`SubCanisterManager::record_snapshot_restore`](crate::SubCanisterManager::record_snapshot_restore).
+    pub async fn restore_snapshot(
+        &self,
+        canister_id: Principal,
+        snapshot_id: SnapshotId,
+    ) -> Result<(), SnapshotError> {
+        self.check_known_canister(canister_id)?;
+
+        retry_async(async || self.management.stop_canister(canister_id).await, 3)
+            .await
+            .map_err(SnapshotError::StopCanisterError)?;
+
+        retry_async(
+            async || {
+                self.management
+                    .load_canister_snapshot(canister_id, snapshot_id.clone())
+                    .await
+            },
+            3,
+        )
+        .await
+        .map_err(SnapshotError::LoadSnapshotError)?;
+
+        retry_async(
+            async || self.management.start_canister(canister_id).await,
+            3,
+        )
+        .await
+        .map_err(SnapshotError::StartCanisterError)
+    }
+
+    /// Deletes a snapshot of a sub-canister.
+    pub async fn delete_snapshot(
+        &self,
+        canister_id: Principal,
+        snapshot_id: SnapshotId,
+    ) -> Result<(), SnapshotError> {
+        self.check_known_canister(canister_id)?;
+
+        retry_async(
+            async || {
+                self.management
+                    .delete_canister_snapshot(canister_id, snapshot_id.clone())
+                    .await
+            },
+            3,
+        )
+        .await
+        .map_err(SnapshotError::DeleteSnapshotError)
+    }
+
+    fn check_known_canister(&self, canister_id: Principal) -> Result<(), SnapshotError> {
+        if self.canister_ids.binary_search(&canister_id).is_err() {
+            return Err(SnapshotError::UnknownCanister(canister_id));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+//! Funding health of the sub-canisters.
+//!
+//! The fund manager tops up the sub-canisters in the background and keeps its
+//! failures to itself. [`SubCanisterManager::sample_cycles`](crate::SubCanisterManager::sample_cycles)
+//! samples the cycle balance of each sub-canister and of the master canister,
+//! and [`SubCanisterManager::check_funding_health`](crate::SubCanisterManager::check_funding_health)
+//! turns the samples into [`FundingAlert`]s before a canister freezes.
+
+use bity_ic_utils::rate::Ewma;
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+/// Number of nanoseconds in a day.
+const DAY_IN_NANOS: f64 = 86_400_000_000_000.0;
+
+/// Weight of the latest sample in the burn rate.
+pub const BURN_RATE_ALPHA: f64 = 0.3;
+
+/// A canister whose balance runs out within this number of days at the observed
+/// burn rate is reported.
+pub const FUNDING_ALERT_HORIZON_DAYS: f64 = 7.0;
+
+/// Why a canister is reported by [`SubCanisterManager::check_funding_health`](crate::SubCanisterManager::check_funding_health).
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum FundingAlertReason {
+    /// The balance could not be sampled, e.g. the canister was deleted
+    SampleFailed(String),
+    /// The fund manager failed to top up the canister
+    TopUpFailed(String),
+    /// The balance is below the funding threshold, top-ups are not keeping up
+    BelowThreshold,
+    /// The balance runs out within [`FUNDING_ALERT_HORIZON_DAYS`] at the observed burn rate
+    RunningOut,
+}
+
+/// A canister trending towards freezing.
+///
+/// # Fields
+///
+/// * `canister_id` - The sub-canister, or the master canister
+/// * `reason` - Why the canister is reported
+/// * `cycles` - The last sampled balance
+/// * `min_cycles` - The funding threshold of the canister, if it has one
+/// * `burn_per_day` - The observed burn rate, in cycles per day
+/// * `days_remaining` - The number of days until the balance runs out at that rate
+/// * `master_cycles` - The last sampled balance of the master canister, which funds the others
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FundingAlert {
+    pub canister_id: Principal,
+    pub reason: FundingAlertReason,
+    pub cycles: Option<u128>,
+    pub min_cycles: Option<u128>,
+    pub burn_per_day: Option<f64>,
+    pub days_remaining: Option<f64>,
+    pub master_cycles: Option<u128>,
+}
+
+/// The cycle balance samples of a canister.
+///
+/// The burn rate is averaged over the samples where the balance went down: a
+/// balance that went up was topped up in between, which hides the burn.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CyclesSamples {
+    /// The last sampled balance
+    pub cycles: Option<u128>,
+    /// When the balance was last sampled
+    pub sampled_at: Option<u64>,
+    /// The burn rate, in cycles per day
+    pub burn_per_day: Ewma,
+    /// Why the last sample failed, cleared by the next successful one
+    pub error: Option<String>,
+}
+
+impl Default for CyclesSamples {
+    fn default() -> Self {
+        Self {
+            cycles: None,
+            sampled_at: None,
+            burn_per_day: Ewma::new(BURN_RATE_ALPHA),
+            error: None,
+        }
+    }
+}
+
+impl CyclesSamples {
+    /// Records the balance of the canister at `now`.
+    pub fn record(&mut self, cycles: u128, now: u64) {
+        if let (Some(previous), Some(sampled_at)) = (self.cycles, self.sampled_at) {
+            if now > sampled_at && cycles <= previous {
+                let burn = (previous - cycles) as f64 * DAY_IN_NANOS / (now - sampled_at) as f64;
+                self.burn_per_day.update(burn, now);
+            }
+        }
+        self.cycles = Some(cycles);
+        self.sampled_at = Some(now);
+        self.error = None;
+    }
+
+    /// Records that the balance could not be sampled.
+    pub fn record_error(&mut self, error: String) {
+        self.error = Some(error);
+    }
+
+    /// Returns the number of days until the balance runs out at the burn rate.
+    pub fn days_remaining(&self) -> Option<f64> {
+        let burn_per_day = self.burn_per_day.value().filter(|burn| *burn > 0.0)?;
+        Some(self.cycles? as f64 / burn_per_day)
+    }
+
+    /// Returns whether the balance runs out within [`FUNDING_ALERT_HORIZON_DAYS`].
+    pub fn is_running_out(&self) -> bool {
+        self.days_remaining()
+            .is_some_and(|days| days < FUNDING_ALERT_HORIZON_DAYS)
+    }
+
+    /// Returns an alert about the canister with these samples.
+    pub(crate) fn alert(
+        &self,
+        canister_id: Principal,
+        reason: FundingAlertReason,
+        min_cycles: Option<u128>,
+        master_cycles: Option<u128>,
+    ) -> FundingAlert {
+        FundingAlert {
+            canister_id,
+            reason,
+            cycles: self.cycles,
+            min_cycles,
+            burn_per_day: self.burn_per_day.value(),
+            days_remaining: self.days_remaining(),
+            master_cycles,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 86_400_000_000_000;
+
+    #[test]
+    fn test_burn_rate_ignores_top_ups() {
+        let mut samples = CyclesSamples::default();
+        samples.record(1_000, 0);
+        assert_eq!(samples.days_remaining(), None);
+
+        samples.record(900, DAY);
+        assert_eq!(samples.burn_per_day.value(), Some(100.0));
+        assert_eq!(samples.days_remaining(), Some(9.0));
+        assert!(!samples.is_running_out());
+
+        // A top-up hides the burn of the period.
+        samples.record(2_000, 2 * DAY);
+        assert_eq!(samples.burn_per_day.value(), Some(100.0));
+
+        samples.record(1_600, 2 * DAY + DAY / 2);
+        assert_eq!(samples.burn_per_day.value(), Some(310.0));
+        assert!(samples.is_running_out());
+
+        samples.record_error("canister not found".to_string());
+        assert_eq!(samples.cycles, Some(1_600));
+        samples.record(1_600, 3 * DAY);
+        assert_eq!(samples.error, None);
+    }
+}
@@ -8,8 +8,17 @@
 //!
 //! - Create and manage sub-canisters
 //! - Handle canister lifecycle (create, install, update, stop)
-//! - Manage canister controllers and permissions
-//! - Handle cycles allocation and management
+//! - Manage canister controllers and permissions, including on existing sub-canisters
+//! - Snapshot sub-canisters, optionally around each upgrade, and restore them
+//! - Record when each sub-canister was created and upgraded, and to which commit
+//! - Reconcile the records with the actual module and status of the sub-canisters
+//! - Retire sub-canisters so they stop receiving new work while staying funded
+//! - Reinstall a sub-canister, wiping its state, with an explicit confirmation
+//! - Handle cycles allocation and management, retrying failed fund manager registrations
+//! - Alert on sub-canisters trending towards freezing, e.g. when top-ups fail
+//! - List many sub-canisters page by page, with or without their cycle balances
+//! - Simulate the management canister in test mode, without creating real canisters
+//! - Mock the management canister in `cargo test` with the `host-test` feature
 //!
 //! # Example
 //!
@@ -34,21 +43,46 @@
 //!
 //! This project is licensed under the MIT License.
 
+use bity_ic_canister_client::fan_out_calls;
+use bity_ic_canister_time::Nanos;
 use bity_ic_utils::retry_async::retry_async;
 use candid::{CandidType, Encode, Nat, Principal};
 use canfund::{
-    manager::{options::FundManagerOptions, RegisterOpts},
+    manager::{
+        options::{FundManagerOptions, FundStrategy},
+        RegisterOpts,
+    },
     operations::fetch::FetchCyclesBalanceFromCanisterStatus,
     FundManager,
 };
-use ic_cdk::management_canister::create_canister_with_extra_cycles;
 use ic_cdk::management_canister::{
-    canister_status, install_code, start_canister, stop_canister, CanisterIdRecord,
-    CanisterInstallMode, CanisterSettings, CreateCanisterArgs, InstallCodeArgs, LogVisibility,
+    CanisterInstallMode, CanisterSettings, CanisterStatusType, InstallCodeArgs, LogVisibility,
 };
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::sync::Arc;
-use std::{any::Any, collections::HashMap, fmt::Debug};
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Debug,
+};
+
+pub mod calls;
+pub mod funding_health;
+pub mod management;
+pub mod simulated;
+
+pub use calls::{CyclesSample, SubCanisterCalls};
+
+pub use funding_health::{CyclesSamples, FundingAlert, FundingAlertReason};
+pub use ic_cdk::management_canister::{Snapshot, SnapshotId};
+pub use management::{
+    management_canister, CanisterStatusSummary, IcManagementCanister, ManagementCanisterClient,
+};
+pub use simulated::{SimulatedManagementCanister, SimulatedOperation};
+
+#[cfg(feature = "host-test")]
+pub use management::set_management_canister_client;
 
 /// Error types for storage operations
 #[derive(Debug)]
@@ -79,6 +113,115 @@ pub enum CanisterError {
     CantFindControllers(String),
 }
 
+/// Error types for controller management operations
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControllerError {
+    /// The canister is not managed by this manager
+    UnknownCanister(Principal),
+    /// The master canister cannot be removed from the controllers
+    CannotRemoveMasterCanister,
+    /// Error when fetching the canister status
+    CanisterStatusError(String),
+    /// Error when updating the canister settings
+    UpdateSettingsError(String),
+    /// The controllers reported by `canister_status` differ from the requested ones
+    ControllersMismatch {
+        expected: Vec<Principal>,
+        actual: Vec<Principal>,
+    },
+}
+
+/// Error types for canister snapshot operations
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotError {
+    /// The canister is not managed by this manager
+    UnknownCanister(Principal),
+    /// Error when taking the snapshot
+    TakeSnapshotError(String),
+    /// Error when listing the snapshots
+    ListSnapshotsError(String),
+    /// Error when loading the snapshot into the canister
+    LoadSnapshotError(String),
+    /// Error when deleting the snapshot
+    DeleteSnapshotError(String),
+    /// Error when stopping the canister before loading the snapshot
+    StopCanisterError(String),
+    /// Error when starting the canister after loading the snapshot
+    StartCanisterError(String),
+}
+
+/// Error types for retiring sub-canisters
+#[derive(Debug, Clone, PartialEq)]
+pub enum RetireError {
+    /// The canister is not managed by this manager
+    UnknownCanister(Principal),
+}
+
+/// Error types for reinstalling sub-canisters
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReinstallError {
+    /// The canister is not managed by this manager
+    UnknownCanister(Principal),
+    /// The confirmation is not the one of this canister
+    InvalidConfirmation(String),
+    /// Error when serializing initialization arguments
+    FailedToSerializeInitArgs(String),
+    /// Error when stopping the canister before reinstalling it
+    StopCanisterError(String),
+    /// Error when reinstalling the code, the canister is left stopped
+    InstallCodeError(String),
+    /// Error when starting the canister after reinstalling it
+    StartCanisterError(String),
+}
+
+/// The phrase a [`ReinstallConfirmation`] must contain.
+pub const REINSTALL_CONFIRMATION_PHRASE: &str = "I-UNDERSTAND-DATA-LOSS";
+
+/// Confirmation required by [`SubCanisterManager::reinstall_canister`], which
+/// wipes the state of the sub-canister.
+///
+/// It names the sub-canister to reinstall and contains
+/// [`REINSTALL_CONFIRMATION_PHRASE`]. It is created with
+/// [`confirm_data_loss`](Self::confirm_data_loss), or received from an operator,
+/// and is checked again before reinstalling.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ReinstallConfirmation {
+    canister_id: Principal,
+    phrase: String,
+}
+
+impl ReinstallConfirmation {
+    /// Confirms that the state of `canister_id` may be wiped.
+    pub fn confirm_data_loss(canister_id: Principal) -> Self {
+        Self {
+            canister_id,
+            phrase: REINSTALL_CONFIRMATION_PHRASE.to_string(),
+        }
+    }
+
+    /// Returns the sub-canister this confirmation is for.
+    pub fn canister_id(&self) -> Principal {
+        self.canister_id
+    }
+
+    /// Checks that this confirmation allows reinstalling `canister_id`.
+    pub fn validate(&self, canister_id: Principal) -> Result<(), ReinstallError> {
+        if self.canister_id != canister_id {
+            return Err(ReinstallError::InvalidConfirmation(format!(
+                "the confirmation is for canister {}, not {}",
+                self.canister_id, canister_id
+            )));
+        }
+        if self.phrase != REINSTALL_CONFIRMATION_PHRASE {
+            return Err(ReinstallError::InvalidConfirmation(format!(
+                "the confirmation must contain \"{}\"",
+                REINSTALL_CONFIRMATION_PHRASE
+            )));
+        }
+        Ok(())
+    }
+}
+
 /// Represents the current state of a canister
 #[derive(CandidType, Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub enum CanisterState {
@@ -90,6 +233,192 @@ pub enum CanisterState {
     Stopped,
 }
 
+/// Lifecycle history of a sub-canister, as recorded by the manager
+///
+/// Timestamps are in nanoseconds. Sub-canisters created before the history was
+/// recorded only have the fields updated since.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CanisterHistory {
+    /// When the canister was created
+    pub created_at: Option<u64>,
+    /// When the code was first installed
+    pub installed_at: Option<u64>,
+    /// When the code was last upgraded
+    pub last_upgrade_at: Option<u64>,
+    /// Number of successful upgrades
+    pub upgrade_count: u64,
+    /// Commit hash of the code last installed or upgraded
+    pub last_commit_hash: Option<String>,
+    /// SHA-256 hash of the module last installed or upgraded
+    #[serde(default)]
+    pub module_hash: Option<Vec<u8>>,
+    /// When the code was last reinstalled, wiping the state
+    #[serde(default)]
+    pub last_reinstall_at: Option<u64>,
+    /// Number of successful reinstalls
+    #[serde(default)]
+    pub reinstall_count: u64,
+}
+
+/// Maximum number of lifecycle events kept by a manager, the oldest are dropped.
+pub const MAX_LIFECYCLE_EVENTS: usize = 100;
+
+/// Kind of a [`LifecycleEvent`]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum LifecycleEventKind {
+    /// The code was installed on a new sub-canister
+    Installed,
+    /// The code was upgraded, keeping the state
+    Upgraded,
+    /// The code was reinstalled, wiping the state
+    Reinstalled,
+    /// The sub-canister could not be registered with the fund manager, it is
+    /// retried from [`SubCanisterManager::pending_funding`]
+    FundingRegistrationFailed(String),
+}
+
+/// An installation of code on a sub-canister, or a warning about it, as recorded
+/// by the manager
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LifecycleEvent {
+    /// ID of the sub-canister
+    pub canister_id: Principal,
+    /// What was done
+    pub kind: LifecycleEventKind,
+    /// When it was done, in nanoseconds
+    pub timestamp: u64,
+    /// Whether the state of the sub-canister was wiped
+    pub destructive: bool,
+}
+
+/// Action suggested for a divergence found by [`SubCanisterManager::reconcile`]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum SuggestedAction {
+    /// The records are stale, align them with the sub-canister
+    ResyncState,
+    /// The sub-canister runs an older module than the manager's wasm, upgrade it
+    ScheduleUpgrade,
+    /// The sub-canister runs a module the manager did not install
+    Investigate,
+}
+
+/// What differs between a sub-canister and the manager's records
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DivergenceKind {
+    /// The installed module is not the recorded one, or not the manager's wasm
+    ModuleHash {
+        recorded: Option<Vec<u8>>,
+        actual: Option<Vec<u8>>,
+    },
+    /// The running status does not match the recorded state
+    State {
+        recorded: CanisterState,
+        actual: CanisterState,
+    },
+}
+
+/// A divergence between a sub-canister and the manager's records
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Divergence {
+    /// ID of the sub-canister
+    pub canister_id: Principal,
+    /// What differs
+    pub kind: DivergenceKind,
+    /// What should be done about it
+    pub action: SuggestedAction,
+    /// Whether the records were updated to match the sub-canister
+    pub adopted: bool,
+}
+
+/// Result of [`SubCanisterManager::reconcile`]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ReconciliationReport {
+    /// SHA-256 hash of the manager's wasm
+    pub wasm_hash: Vec<u8>,
+    /// Number of sub-canisters whose status was fetched
+    pub checked: u64,
+    /// Divergences found, ordered by canister ID
+    pub divergences: Vec<Divergence>,
+    /// Sub-canisters whose status could not be fetched, with the error
+    pub failed: Vec<(Principal, String)>,
+}
+
+/// Status of a sub-canister, as reported by [`SubCanisterManager::status`]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SubCanisterStatus {
+    /// ID of the sub-canister
+    pub canister_id: Principal,
+    /// Current state of the sub-canister
+    pub state: CanisterState,
+    /// Whether the sub-canister is retired
+    pub retired: bool,
+    /// Creation and upgrade history of the sub-canister
+    pub history: CanisterHistory,
+    /// Funding alerts about the sub-canister, see [`SubCanisterManager::check_funding_health`]
+    #[serde(default)]
+    pub funding_alerts: Vec<FundingAlert>,
+}
+
+/// Maximum number of sub-canisters in a page of
+/// [`SubCanisterManager::list_canisters_page`] or
+/// [`SubCanisterManager::get_canisters_status_page`], a larger limit is lowered to it
+pub const MAX_CANISTERS_PAGE_SIZE: u16 = 500;
+
+/// Maximum number of `canister_status` calls in flight when a status page
+/// includes the cycle balances
+pub const STATUS_PAGE_MAX_CONCURRENCY: usize = 10;
+
+/// A sub-canister as recorded by the manager, listed by
+/// [`SubCanisterManager::list_canisters_page`]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CanisterSummary {
+    /// ID of the sub-canister
+    pub canister_id: Principal,
+    /// Recorded state of the sub-canister
+    pub state: CanisterState,
+    /// Whether the sub-canister is retired
+    pub retired: bool,
+}
+
+/// A page of [`SubCanisterManager::list_canisters_page`]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CanisterListPage {
+    /// Sub-canisters of the page, ordered by canister ID
+    pub canisters: Vec<CanisterSummary>,
+    /// Cursor of the next page, `None` on the last page
+    pub next: Option<Principal>,
+}
+
+/// Arguments of [`SubCanisterManager::get_canisters_status_page`]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct StatusPageArgs {
+    /// Lists the sub-canisters after this one, from the first one when `None`
+    pub start_after: Option<Principal>,
+    /// Maximum number of sub-canisters in the page, see [`MAX_CANISTERS_PAGE_SIZE`]
+    pub limit: u16,
+    /// Whether to fetch the cycle balance of each sub-canister with `canister_status`
+    pub include_cycles: bool,
+}
+
+/// Status of a sub-canister in a [`StatusPage`]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StatusPageEntry {
+    /// Status of the sub-canister
+    pub status: SubCanisterStatus,
+    /// Cycle balance of the sub-canister, or why it could not be fetched. `None`
+    /// unless requested with `include_cycles`
+    pub cycles: Option<Result<u128, String>>,
+}
+
+/// A page of [`SubCanisterManager::get_canisters_status_page`]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StatusPage {
+    /// Sub-canisters of the page, ordered by canister ID
+    pub entries: Vec<StatusPageEntry>,
+    /// Cursor of the next page, `None` on the last page
+    pub next: Option<Principal>,
+}
+
 /// Trait that must be implemented by canister types
 pub trait Canister {
     /// Type of parameters used for canister initialization
@@ -111,6 +440,9 @@ pub trait Canister {
     fn as_any(&self) -> &dyn Any;
 
     /// Retrieves the controllers of the canister
+    ///
+    /// This always calls the management canister, including for the canisters of
+    /// a manager in test mode, use [`SubCanisterManager::cached_controllers`] there.
     fn get_canister_controllers(
         &self,
     ) -> impl std::future::Future<Output = Result<Vec<Principal>, CanisterError>> + Send
@@ -118,19 +450,15 @@ pub trait Canister {
         Self: Sync + Send,
     {
         async {
+            let client = management_canister();
             match retry_async(
-                async || {
-                    canister_status(&CanisterIdRecord {
-                        canister_id: self.canister_id(),
-                    })
-                    .await
-                },
+                async || client.canister_controllers(self.canister_id()).await,
                 3,
             )
             .await
             {
-                Ok(res) => Ok(res.settings.controllers),
-                Err(e) => Err(CanisterError::CantFindControllers(format!("{e:?}"))),
+                Ok(controllers) => Ok(controllers),
+                Err(e) => Err(CanisterError::CantFindControllers(e)),
             }
         }
     }
@@ -154,7 +482,8 @@ where
     pub initial_cycles: u128,
     /// Reserved cycles for canisters
     pub reserved_cycles: u128,
-    /// Whether the manager is in test mode
+    /// Whether the manager is in test mode, where the management canister calls
+    /// are simulated by [`simulated`](Self::simulated)
     pub test_mode: bool,
     /// Commit hash of the current version
     pub commit_hash: String,
@@ -166,6 +495,39 @@ where
     /// Funding config
     #[serde(skip)]
     pub funding_config: FundManagerOptions,
+    /// Controllers of each sub-canister, as last verified via `canister_status`
+    #[serde(default)]
+    pub canister_controllers: HashMap<Principal, Vec<Principal>>,
+    /// Whether `update_canisters` snapshots each sub-canister before upgrading it
+    #[serde(default)]
+    pub snapshot_before_upgrade: bool,
+    /// Creation and upgrade history of each sub-canister
+    #[serde(default)]
+    pub canister_history: HashMap<Principal, CanisterHistory>,
+    /// Simulated canister registry used in test mode
+    #[serde(default)]
+    pub simulated: SimulatedManagementCanister,
+    /// Sub-canisters that no longer receive new work, see [`retire`](Self::retire)
+    #[serde(default)]
+    pub retired: HashSet<Principal>,
+    /// Funding strategy of the retired sub-canisters, the strategy of
+    /// `funding_config` applies when unset
+    #[serde(skip)]
+    pub retired_fund_strategy: Option<FundStrategy>,
+    /// Most recent installs, upgrades, reinstalls and funding registration
+    /// failures, oldest first, see [`MAX_LIFECYCLE_EVENTS`]
+    #[serde(default)]
+    pub lifecycle_events: VecDeque<LifecycleEvent>,
+    /// Sub-canisters whose registration with the fund manager failed, retried by
+    /// [`run_funding_health_check`](Self::run_funding_health_check)
+    #[serde(default)]
+    pub pending_funding: Vec<Principal>,
+    /// Cycle balance samples of each sub-canister, see [`sample_cycles`](Self::sample_cycles)
+    #[serde(default)]
+    pub cycles_samples: HashMap<Principal, CyclesSamples>,
+    /// Cycle balance samples of the master canister
+    #[serde(default)]
+    pub master_cycles_samples: CyclesSamples,
 }
 
 impl<T> SubCanisterManager<T>
@@ -199,9 +561,135 @@ where
             wasm,
             fund_manager: FundManager::new(),
             funding_config: funding_config,
+            canister_controllers: HashMap::new(),
+            snapshot_before_upgrade: false,
+            canister_history: HashMap::new(),
+            simulated: SimulatedManagementCanister::default(),
+            retired: HashSet::new(),
+            retired_fund_strategy: None,
+            lifecycle_events: VecDeque::new(),
+            pending_funding: vec![],
+            cycles_samples: HashMap::new(),
+            master_cycles_samples: CyclesSamples::default(),
+        }
+    }
+
+    /// Returns the client for management canister calls: the simulated registry
+    /// in test mode, the management canister otherwise.
+    fn management(&self) -> Arc<dyn ManagementCanisterClient> {
+        if self.test_mode {
+            Arc::new(self.simulated.shared())
+        } else {
+            management_canister()
+        }
+    }
+
+    /// Returns a handle making the management canister calls on the current
+    /// sub-canisters without borrowing the manager, see [`calls`].
+    pub fn calls(&self) -> SubCanisterCalls {
+        SubCanisterCalls::new(
+            self.management(),
+            self.master_canister_id,
+            self.list_canisters_ids(),
+        )
+    }
+
+    /// Registers canisters with the fund manager, which is only recorded in test
+    /// mode as there is nothing to fund.
+    fn register_funding(&mut self, canister_ids: Vec<Principal>) {
+        let failures = if self.test_mode {
+            simulated_registration_failures(&self.simulated, &canister_ids)
+        } else {
+            let failures = add_canisters_to_fund_manager(
+                &mut self.fund_manager,
+                self.funding_config.clone(),
+                canister_ids.clone(),
+            );
+            if let Some(strategy) = &self.retired_fund_strategy {
+                register_retired_canisters(
+                    &mut self.fund_manager,
+                    strategy,
+                    canister_ids
+                        .iter()
+                        .copied()
+                        .filter(|canister_id| self.retired.contains(canister_id)),
+                );
+            }
+            failures
+        };
+        self.record_funding_registrations(&canister_ids, failures);
+    }
+
+    /// Registers one canister with the fund manager without restarting it, see
+    /// [`register_one`].
+    fn register_canister_funding(&mut self, canister_id: Principal) {
+        let failures = if self.test_mode {
+            simulated_registration_failures(&self.simulated, &[canister_id])
+        } else {
+            let strategy = self
+                .retired_fund_strategy
+                .clone()
+                .filter(|_| self.retired.contains(&canister_id));
+            register_one(
+                &mut self.fund_manager,
+                &self.funding_config,
+                canister_id,
+                strategy,
+            )
+            .err()
+            .map(|error| (canister_id, error))
+            .into_iter()
+            .collect()
+        };
+        self.record_funding_registrations(&[canister_id], failures);
+    }
+
+    /// Keeps the canisters whose registration failed in [`pending_funding`](Self::pending_funding),
+    /// with a warning in the lifecycle events, and removes the registered ones.
+    fn record_funding_registrations(
+        &mut self,
+        canister_ids: &[Principal],
+        failures: Vec<(Principal, String)>,
+    ) {
+        self.pending_funding.retain(|canister_id| {
+            !canister_ids.contains(canister_id)
+                || failures.iter().any(|(failed, _)| failed == canister_id)
+        });
+        for (canister_id, error) in failures {
+            tracing::warn!(
+                canister_id = %canister_id,
+                error = %error,
+                "Failed to register the canister with the fund manager"
+            );
+            if !self.pending_funding.contains(&canister_id) {
+                self.pending_funding.push(canister_id);
+            }
+            self.record_lifecycle_event(
+                canister_id,
+                LifecycleEventKind::FundingRegistrationFailed(error),
+            );
+        }
+    }
+
+    /// Registers the canisters of [`pending_funding`](Self::pending_funding) with
+    /// the fund manager again, one by one. Those failing again stay pending.
+    pub fn retry_pending_funding(&mut self) {
+        for canister_id in self.pending_funding.clone() {
+            if self.sub_canisters.contains_key(&canister_id) {
+                self.register_canister_funding(canister_id);
+            } else {
+                self.pending_funding
+                    .retain(|pending| *pending != canister_id);
+            }
         }
     }
 
+    /// Returns the management canister operations simulated in test mode, oldest
+    /// first. The log is empty outside of test mode.
+    pub fn simulated_operations_log(&self) -> Vec<SimulatedOperation> {
+        self.simulated.operations()
+    }
+
     pub async fn create_canister(
         &mut self,
         init_args: <T as Canister>::ParamType,
@@ -209,7 +697,7 @@ where
         let mut canister_id = Principal::anonymous();
 
         for (_canister_id, canister) in self.sub_canisters.iter() {
-            if canister.state() == CanisterState::Created {
+            if canister.state() == CanisterState::Created && !self.retired.contains(_canister_id) {
                 canister_id = *_canister_id;
                 break;
             }
@@ -230,29 +718,27 @@ where
 
             canister_id = match retry_async(
                 async || {
-                    create_canister_with_extra_cycles(
-                        &CreateCanisterArgs {
-                            settings: Some(settings.clone()),
-                        },
-                        self.initial_cycles,
-                    )
-                    .await
+                    self.management()
+                        .create_canister(settings.clone(), self.initial_cycles)
+                        .await
                 },
                 3,
             )
             .await
             {
-                Ok(canister) => canister.canister_id,
+                Ok(canister_id) => canister_id,
                 Err(e) => {
-                    return Err(NewCanisterError::CreateCanisterError(format!("{e:?}")));
+                    return Err(NewCanisterError::CreateCanisterError(e));
                 }
             };
 
-            add_canisters_to_fund_manager(
-                &mut self.fund_manager,
-                self.funding_config.clone(),
-                vec![canister_id],
-            );
+            // A failed registration is retried later, the canister exists either way.
+            self.register_canister_funding(canister_id);
+
+            self.canister_history
+                .entry(canister_id)
+                .or_default()
+                .created_at = Some(Nanos::from(bity_ic_canister_time::timestamp_nanos()).get());
 
             self.sub_canisters.insert(
                 canister_id,
@@ -278,13 +764,20 @@ where
             arg: encoded_init_args.clone(),
         };
 
-        match install_code(&install_args).await {
+        match self.management().install_code(install_args).await {
             Ok(_) => {}
             Err(e) => {
-                return Err(NewCanisterError::InstallCodeError(format!("{:?}", e)));
+                return Err(NewCanisterError::InstallCodeError(e));
             }
         }
 
+        let module_hash = self.wasm_hash();
+        let history = self.canister_history.entry(canister_id).or_default();
+        history.installed_at = Some(Nanos::from(bity_ic_canister_time::timestamp_nanos()).get());
+        history.last_commit_hash = Some(self.commit_hash.clone());
+        history.module_hash = Some(module_hash);
+        self.record_lifecycle_event(canister_id, LifecycleEventKind::Installed);
+
         let canister = Box::new(T::new(
             canister_id,
             CanisterState::Installed,
@@ -317,12 +810,7 @@ where
 
         for (canister_id, _canister) in self.sub_canisters.clone().iter() {
             match retry_async(
-                async || {
-                    stop_canister(&CanisterIdRecord {
-                        canister_id: *canister_id,
-                    })
-                    .await
-                },
+                async || self.management().stop_canister(*canister_id).await,
                 3,
             )
             .await
@@ -339,13 +827,52 @@ where
                 }
                 Err(e) => {
                     canister_upgrade_errors.push(format!(
-                            "ERROR: storage upgrade :: storage with principal : {} failed to stop with error {:?}",
+                            "ERROR: storage upgrade :: storage with principal : {} failed to stop with error {}",
                             *canister_id, e
                         ));
                     continue;
                 }
             }
 
+            // The upgrade is not attempted without the snapshot to roll back to.
+            let snapshot_id = if self.snapshot_before_upgrade {
+                match self.take_snapshot(*canister_id).await {
+                    Ok(snapshot_id) => Some(snapshot_id),
+                    Err(e) => {
+                        canister_upgrade_errors.push(format!(
+                            "ERROR: storage upgrade :: storage with principal : {} failed to take a snapshot, upgrade skipped {:?}",
+                            *canister_id, e
+                        ));
+                        match retry_async(
+                            async || self.management().start_canister(*canister_id).await,
+                            3,
+                        )
+                        .await
+                        {
+                            Ok(_) => {
+                                self.sub_canisters.insert(
+                                    *canister_id,
+                                    Box::new(T::new(
+                                        *canister_id,
+                                        CanisterState::Installed,
+                                        update_args.clone(),
+                                    )),
+                                );
+                            }
+                            Err(e) => {
+                                canister_upgrade_errors.push(format!(
+                                    "ERROR: storage upgrade :: storage with principal : {} failed to start with error {}",
+                                    *canister_id, e
+                                ));
+                            }
+                        }
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+
             let result = {
                 let init_args = init_args.clone();
                 let wasm_module = self.wasm.clone();
@@ -356,18 +883,26 @@ where
                     wasm_module,
                     arg: init_args,
                 };
-                retry_async(|| install_code(&install_args), 3).await
+                retry_async(
+                    async || self.management().install_code(install_args.clone()).await,
+                    3,
+                )
+                .await
             };
 
             match result {
                 Ok(_) => {
+                    let module_hash = self.wasm_hash();
+                    let history = self.canister_history.entry(*canister_id).or_default();
+                    history.last_upgrade_at =
+                        Some(Nanos::from(bity_ic_canister_time::timestamp_nanos()).get());
+                    history.upgrade_count += 1;
+                    history.last_commit_hash = Some(self.commit_hash.clone());
+                    history.module_hash = Some(module_hash);
+                    self.record_lifecycle_event(*canister_id, LifecycleEventKind::Upgraded);
+
                     match retry_async(
-                        async || {
-                            start_canister(&CanisterIdRecord {
-                                canister_id: *canister_id,
-                            })
-                            .await
-                        },
+                        async || self.management().start_canister(*canister_id).await,
                         3,
                     )
                     .await
@@ -381,10 +916,21 @@ where
                                     update_args.clone(),
                                 )),
                             );
+
+                            if let Some(snapshot_id) = snapshot_id {
+                                if let Err(e) =
+                                    self.delete_snapshot(*canister_id, snapshot_id).await
+                                {
+                                    canister_upgrade_errors.push(format!(
+                                        "ERROR: storage upgrade :: storage with principal : {} upgraded but failed to delete its snapshot {:?}",
+                                        *canister_id, e
+                                    ));
+                                }
+                            }
                         }
                         Err(e) => {
                             canister_upgrade_errors.push(format!(
-                                    "ERROR: storage upgrade :: storage with principal : {} failed to start with error {:?}",
+                                    "ERROR: storage upgrade :: storage with principal : {} failed to start with error {}",
                                     *canister_id, e
                                 ));
                         }
@@ -392,8 +938,12 @@ where
                 }
                 Err(e) => {
                     canister_upgrade_errors.push(format!(
-                            "ERROR: storage upgrade :: storage with principal : {} failed to install upgrade {:?}",
-                            *canister_id, e
+                            "ERROR: storage upgrade :: storage with principal : {} failed to install upgrade {}{}",
+                            *canister_id,
+                            e,
+                            snapshot_id
+                                .map(|snapshot_id| format!(", snapshot {} kept", hex::encode(snapshot_id)))
+                                .unwrap_or_default()
                         ));
                 }
             }
@@ -406,60 +956,1977 @@ where
         }
     }
 
-    pub fn list_canisters(&self) -> Vec<Box<impl Canister>> {
-        self.sub_canisters.values().cloned().collect()
+    /// Reinstalls the manager's wasm on a sub-canister, wiping its state, e.g. to
+    /// recover a sub-canister whose state is corrupted beyond repair.
+    ///
+    /// The sub-canister is stopped, reinstalled and started again, and the
+    /// reinstall is recorded in its history and as a destructive lifecycle event.
+    ///
+    /// # Arguments
+    /// * `canister_id` - The sub-canister to reinstall
+    /// * `init_args` - The arguments of the new installation
+    /// * `confirmation` - The confirmation of the data loss for this sub-canister,
+    ///   see [`ReinstallConfirmation::confirm_data_loss`]
+    ///
+    /// # Errors
+    /// Nothing is done if the confirmation is not valid for `canister_id`. A
+    /// sub-canister that failed to be reinstalled is left stopped.
+    pub async fn reinstall_canister(
+        &mut self,
+        canister_id: impl Into<Principal>,
+        init_args: <T as Canister>::ParamType,
+        confirmation: ReinstallConfirmation,
+    ) -> Result<(), ReinstallError> {
+        let canister_id = canister_id.into();
+        let install_args = self.reinstall_args(canister_id, &init_args, &confirmation)?;
+        let result = self.calls().reinstall(install_args).await;
+        self.record_reinstall(canister_id, init_args, &result);
+        result
     }
 
-    pub fn list_canisters_ids(&self) -> Vec<Principal> {
-        self.sub_canisters.clone().into_keys().collect()
+    /// Checks the confirmation of a reinstall and returns the arguments to
+    /// reinstall the sub-canister with [`SubCanisterCalls::reinstall`].
+    ///
+    /// # Errors
+    /// Returns an error if the confirmation is not valid for `canister_id`.
+    pub fn reinstall_args(
+        &self,
+        canister_id: Principal,
+        init_args: &<T as Canister>::ParamType,
+        confirmation: &ReinstallConfirmation,
+    ) -> Result<InstallCodeArgs, ReinstallError> {
+        confirmation.validate(canister_id)?;
+        if !self.sub_canisters.contains_key(&canister_id) {
+            return Err(ReinstallError::UnknownCanister(canister_id));
+        }
+        let encoded_init_args = Encode!(init_args)
+            .map_err(|e| ReinstallError::FailedToSerializeInitArgs(format!("{e}")))?;
+
+        Ok(InstallCodeArgs {
+            mode: CanisterInstallMode::Reinstall,
+            canister_id,
+            wasm_module: self.wasm.clone(),
+            arg: encoded_init_args,
+        })
     }
-}
 
-impl<T> Clone for SubCanisterManager<T>
-where
-    T: Canister + Clone + Send,
-{
-    fn clone(&self) -> Self {
-        let mut fund_manager = FundManager::new();
+    /// Records the outcome of [`SubCanisterCalls::reinstall`]. Once the code is
+    /// reinstalled, the reinstall is recorded in the history of the sub-canister
+    /// and as a lifecycle event. A sub-canister left stopped is recorded as such.
+    ///
+    /// # Arguments
+    /// * `canister_id` - The reinstalled sub-canister
+    /// * `init_args` - The arguments of the new installation
+    /// * `result` - The outcome of the reinstall
+    pub fn record_reinstall(
+        &mut self,
+        canister_id: Principal,
+        init_args: <T as Canister>::ParamType,
+        result: &Result<(), ReinstallError>,
+    ) {
+        if !self.sub_canisters.contains_key(&canister_id) {
+            return;
+        }
+        let state = match result {
+            Ok(()) => CanisterState::Installed,
+            Err(ReinstallError::StartCanisterError(_)) => CanisterState::Stopped,
+            Err(ReinstallError::InstallCodeError(_)) => {
+                self.set_canister_state(canister_id, CanisterState::Stopped);
+                return;
+            }
+            Err(_) => return,
+        };
 
-        add_canisters_to_fund_manager(
-            &mut fund_manager,
-            self.funding_config.clone(),
-            self.sub_canisters.clone().into_keys().collect(),
-        );
+        let module_hash = self.wasm_hash();
+        let history = self.canister_history.entry(canister_id).or_default();
+        history.last_reinstall_at =
+            Some(Nanos::from(bity_ic_canister_time::timestamp_nanos()).get());
+        history.reinstall_count += 1;
+        history.last_commit_hash = Some(self.commit_hash.clone());
+        history.module_hash = Some(module_hash);
+        self.record_lifecycle_event(canister_id, LifecycleEventKind::Reinstalled);
+        self.sub_canisters
+            .insert(canister_id, Box::new(T::new(canister_id, state, init_args)));
+    }
 
-        Self {
-            master_canister_id: self.master_canister_id,
-            sub_canisters: self.sub_canisters.clone(),
-            controllers: self.controllers.clone(),
-            authorized_principal: self.authorized_principal.clone(),
-            initial_cycles: self.initial_cycles,
-            reserved_cycles: self.reserved_cycles,
-            test_mode: self.test_mode,
-            commit_hash: self.commit_hash.clone(),
-            wasm: self.wasm.clone(),
-            fund_manager: fund_manager,
-            funding_config: self.funding_config.clone(),
+    /// Returns the most recent installs, upgrades, reinstalls and funding
+    /// registration failures of the sub-canisters, oldest first.
+    pub fn lifecycle_events(&self) -> Vec<LifecycleEvent> {
+        self.lifecycle_events.iter().cloned().collect()
+    }
+
+    fn record_lifecycle_event(&mut self, canister_id: Principal, kind: LifecycleEventKind) {
+        if self.lifecycle_events.len() >= MAX_LIFECYCLE_EVENTS {
+            self.lifecycle_events.pop_front();
         }
+        self.lifecycle_events.push_back(LifecycleEvent {
+            canister_id,
+            destructive: kind == LifecycleEventKind::Reinstalled,
+            kind,
+            timestamp: Nanos::from(bity_ic_canister_time::timestamp_nanos()).get(),
+        });
     }
-}
 
-pub fn add_canisters_to_fund_manager(
-    fund_manager: &mut FundManager,
-    funding_config: FundManagerOptions,
-    canister_id_lst: Vec<Principal>,
-) {
-    fund_manager.stop();
+    /// Replaces the funding configuration and re-registers every sub-canister
+    /// with the fund manager so the new thresholds apply immediately.
+    ///
+    /// # Arguments
+    /// * `funding_config` - The new fund manager options
+    pub fn set_funding_config(&mut self, funding_config: FundManagerOptions) {
+        self.funding_config = funding_config;
 
-    fund_manager.with_options(funding_config);
+        let canister_ids = self.list_canisters_ids();
+        self.register_funding(canister_ids);
+    }
 
-    for canister_id in canister_id_lst {
-        fund_manager.register(
-            canister_id,
-            RegisterOpts::new()
-                .with_cycles_fetcher(Arc::new(FetchCyclesBalanceFromCanisterStatus::new())),
-        );
+    /// Sets the funding strategy of the retired sub-canisters and re-registers
+    /// them with the fund manager.
+    ///
+    /// # Arguments
+    /// * `strategy` - The strategy of the retired sub-canisters, e.g. a lower
+    ///   threshold, or `None` to fund them like the other sub-canisters
+    pub fn set_retired_fund_strategy(&mut self, strategy: Option<FundStrategy>) {
+        self.retired_fund_strategy = strategy;
+
+        let canister_ids = self.retired.iter().copied().collect();
+        self.register_funding(canister_ids);
     }
 
-    fund_manager.start();
+    /// Retires a sub-canister: it is no longer offered for new work by
+    /// [`active_canisters`](Self::active_canisters), but stays installed, funded
+    /// and queryable. Retiring a retired sub-canister does nothing.
+    ///
+    /// # Arguments
+    /// * `canister_id` - The sub-canister to retire
+    pub fn retire(&mut self, canister_id: impl Into<Principal>) -> Result<(), RetireError> {
+        let canister_id = canister_id.into();
+        if !self.sub_canisters.contains_key(&canister_id) {
+            return Err(RetireError::UnknownCanister(canister_id));
+        }
+
+        if self.retired.insert(canister_id) && self.retired_fund_strategy.is_some() {
+            self.register_funding(vec![canister_id]);
+        }
+        Ok(())
+    }
+
+    /// Puts a retired sub-canister back into service.
+    ///
+    /// # Arguments
+    /// * `canister_id` - The sub-canister to put back into service
+    pub fn unretire(&mut self, canister_id: impl Into<Principal>) -> Result<(), RetireError> {
+        let canister_id = canister_id.into();
+        if !self.sub_canisters.contains_key(&canister_id) {
+            return Err(RetireError::UnknownCanister(canister_id));
+        }
+
+        if self.retired.remove(&canister_id) && self.retired_fund_strategy.is_some() {
+            self.register_funding(vec![canister_id]);
+        }
+        Ok(())
+    }
+
+    /// Returns whether a sub-canister is retired.
+    pub fn is_retired(&self, canister_id: &Principal) -> bool {
+        self.retired.contains(canister_id)
+    }
+
+    /// Replaces the controllers given to sub-canisters created from now on.
+    ///
+    /// The master canister is always kept as a controller. Existing sub-canisters
+    /// are not affected until [`sync_controllers`](Self::sync_controllers) is called.
+    ///
+    /// # Arguments
+    /// * `controllers` - The new default controllers
+    pub fn set_default_controllers(&mut self, mut controllers: Vec<Principal>) {
+        if !controllers.contains(&self.master_canister_id) {
+            controllers.push(self.master_canister_id);
+        }
+        self.controllers = controllers;
+    }
+
+    /// Adds a controller to an existing sub-canister.
+    ///
+    /// # Arguments
+    /// * `canister_id` - The sub-canister to update
+    /// * `controller` - The principal to add as a controller
+    ///
+    /// # Returns
+    /// The controllers of the sub-canister, as verified via `canister_status`.
+    pub async fn add_controller(
+        &mut self,
+        canister_id: impl Into<Principal>,
+        controller: Principal,
+    ) -> Result<Vec<Principal>, ControllerError> {
+        let canister_id = canister_id.into();
+        let result = self.calls().add_controller(canister_id, controller).await;
+        self.record_controllers(canister_id, &result);
+        result
+    }
+
+    /// Removes a controller from an existing sub-canister.
+    ///
+    /// # Arguments
+    /// * `canister_id` - The sub-canister to update
+    /// * `controller` - The principal to remove from the controllers
+    ///
+    /// # Returns
+    /// The controllers of the sub-canister, as verified via `canister_status`.
+    ///
+    /// # Errors
+    /// Returns `ControllerError::CannotRemoveMasterCanister` if `controller` is the
+    /// master canister, as the manager would lose control over the sub-canister.
+    pub async fn remove_controller(
+        &mut self,
+        canister_id: impl Into<Principal>,
+        controller: Principal,
+    ) -> Result<Vec<Principal>, ControllerError> {
+        let canister_id = canister_id.into();
+        let result = self
+            .calls()
+            .remove_controller(canister_id, controller)
+            .await;
+        self.record_controllers(canister_id, &result);
+        result
+    }
+
+    /// Replaces the controllers of an existing sub-canister with the manager's
+    /// current controller list.
+    ///
+    /// # Arguments
+    /// * `canister_id` - The sub-canister to update
+    ///
+    /// # Returns
+    /// The controllers of the sub-canister, as verified via `canister_status`.
+    pub async fn sync_controllers(
+        &mut self,
+        canister_id: impl Into<Principal>,
+    ) -> Result<Vec<Principal>, ControllerError> {
+        let canister_id = canister_id.into();
+        let result = self
+            .calls()
+            .set_controllers(canister_id, self.controllers.clone())
+            .await;
+        self.record_controllers(canister_id, &result);
+        result
+    }
+
+    /// Caches the controllers of a sub-canister verified by a
+    /// [`SubCanisterCalls`] controller update, see [`cached_controllers`](Self::cached_controllers).
+    pub fn record_controllers(
+        &mut self,
+        canister_id: Principal,
+        result: &Result<Vec<Principal>, ControllerError>,
+    ) {
+        if let Ok(controllers) = result {
+            if self.sub_canisters.contains_key(&canister_id) {
+                self.canister_controllers
+                    .insert(canister_id, controllers.clone());
+            }
+        }
+    }
+
+    /// Returns the controllers of a sub-canister as last verified by the manager.
+    pub fn cached_controllers(&self, canister_id: &Principal) -> Option<&Vec<Principal>> {
+        self.canister_controllers.get(canister_id)
+    }
+
+    /// Returns the creation and upgrade history of a sub-canister.
+    pub fn canister_history(&self, canister_id: &Principal) -> Option<CanisterHistory> {
+        self.canister_history.get(canister_id).cloned()
+    }
+
+    /// Returns the SHA-256 hash of the manager's wasm, as reported in the
+    /// `module_hash` of the sub-canisters running it.
+    pub fn wasm_hash(&self) -> Vec<u8> {
+        Sha256::digest(&self.wasm).to_vec()
+    }
+
+    /// Compares each sub-canister with the manager's records.
+    ///
+    /// The module hash reported by `canister_status` is checked against the
+    /// manager's wasm and the recorded history, and the running status against
+    /// the recorded [`CanisterState`], so that a sub-canister upgraded or stopped
+    /// behind the manager's back is noticed.
+    ///
+    /// # Arguments
+    /// * `auto_adopt` - Whether to update the records to match the sub-canisters.
+    ///   A module the manager did not install is recorded without a commit hash.
+    pub async fn reconcile(&mut self, auto_adopt: bool) -> ReconciliationReport {
+        let statuses = self.calls().canister_statuses().await;
+        self.apply_reconciliation(statuses, auto_adopt)
+    }
+
+    /// Compares the statuses fetched by [`SubCanisterCalls::canister_statuses`]
+    /// with the records, see [`reconcile`](Self::reconcile).
+    ///
+    /// A sub-canister removed since its status was fetched is skipped.
+    pub fn apply_reconciliation(
+        &mut self,
+        statuses: Vec<(Principal, Result<CanisterStatusSummary, String>)>,
+        auto_adopt: bool,
+    ) -> ReconciliationReport {
+        let wasm_hash = self.wasm_hash();
+        let mut report = ReconciliationReport {
+            wasm_hash: wasm_hash.clone(),
+            checked: 0,
+            divergences: vec![],
+            failed: vec![],
+        };
+
+        for (canister_id, status) in statuses {
+            if !self.sub_canisters.contains_key(&canister_id) {
+                continue;
+            }
+            let summary = match status {
+                Ok(summary) => summary,
+                Err(e) => {
+                    report.failed.push((canister_id, e));
+                    continue;
+                }
+            };
+            report.checked += 1;
+
+            let recorded = self
+                .canister_history
+                .get(&canister_id)
+                .and_then(|history| history.module_hash.clone());
+            let module_action = if summary.module_hash.as_ref() == Some(&wasm_hash) {
+                (recorded.as_ref() != Some(&wasm_hash)).then_some(SuggestedAction::ResyncState)
+            } else if summary.module_hash == recorded {
+                summary
+                    .module_hash
+                    .is_some()
+                    .then_some(SuggestedAction::ScheduleUpgrade)
+            } else {
+                Some(SuggestedAction::Investigate)
+            };
+            if let Some(action) = module_action {
+                let adopted = auto_adopt && action != SuggestedAction::ScheduleUpgrade;
+                if adopted {
+                    let history = self.canister_history.entry(canister_id).or_default();
+                    history.last_commit_hash =
+                        (action == SuggestedAction::ResyncState).then(|| self.commit_hash.clone());
+                    history.module_hash = summary.module_hash.clone();
+                }
+                report.divergences.push(Divergence {
+                    canister_id,
+                    kind: DivergenceKind::ModuleHash {
+                        recorded,
+                        actual: summary.module_hash.clone(),
+                    },
+                    action,
+                    adopted,
+                });
+            }
+
+            let actual_state = match (&summary.module_hash, summary.status) {
+                (None, _) => CanisterState::Created,
+                (Some(_), CanisterStatusType::Running) => CanisterState::Installed,
+                (Some(_), CanisterStatusType::Stopping | CanisterStatusType::Stopped) => {
+                    CanisterState::Stopped
+                }
+            };
+            let recorded_state = self.sub_canisters[&canister_id].state();
+            if recorded_state != actual_state {
+                if auto_adopt {
+                    self.set_canister_state(canister_id, actual_state.clone());
+                }
+                report.divergences.push(Divergence {
+                    canister_id,
+                    kind: DivergenceKind::State {
+                        recorded: recorded_state,
+                        actual: actual_state,
+                    },
+                    action: SuggestedAction::ResyncState,
+                    adopted: auto_adopt,
+                });
+            }
+        }
+
+        report
+    }
+
+    /// Sets whether [`update_canisters`](Self::update_canisters) snapshots each
+    /// sub-canister before upgrading it.
+    ///
+    /// The snapshot is deleted once the upgrade succeeded and kept otherwise, so
+    /// the sub-canister can be restored with [`restore_snapshot`](Self::restore_snapshot).
+    /// A sub-canister that cannot be snapshotted is not upgraded.
+    pub fn set_snapshot_before_upgrade(&mut self, snapshot_before_upgrade: bool) {
+        self.snapshot_before_upgrade = snapshot_before_upgrade;
+    }
+
+    /// Takes a snapshot of a sub-canister.
+    ///
+    /// The most recent snapshot of the sub-canister, if any, is replaced, so that
+    /// repeated snapshots don't hit the limit of snapshots per canister.
+    ///
+    /// # Arguments
+    /// * `canister_id` - The sub-canister to snapshot
+    ///
+    /// # Returns
+    /// The id of the snapshot that was taken.
+    pub async fn take_snapshot(
+        &mut self,
+        canister_id: impl Into<Principal>,
+    ) -> Result<SnapshotId, SnapshotError> {
+        self.calls().take_snapshot(canister_id.into()).await
+    }
+
+    /// Lists the snapshots of a sub-canister.
+    pub async fn list_snapshots(
+        &self,
+        canister_id: impl Into<Principal>,
+    ) -> Result<Vec<Snapshot>, SnapshotError> {
+        self.calls().list_snapshots(canister_id.into()).await
+    }
+
+    /// Restores a sub-canister from one of its snapshots.
+    ///
+    /// The sub-canister is stopped while the snapshot is loaded and started again
+    /// afterwards.
+    ///
+    /// # Arguments
+    /// * `canister_id` - The sub-canister to restore
+    /// * `snapshot_id` - The snapshot to load
+    pub async fn restore_snapshot(
+        &mut self,
+        canister_id: impl Into<Principal>,
+        snapshot_id: SnapshotId,
+    ) -> Result<(), SnapshotError> {
+        let canister_id = canister_id.into();
+        let result = self
+            .calls()
+            .restore_snapshot(canister_id, snapshot_id)
+            .await;
+        self.record_snapshot_restore(canister_id, &result);
+        result
+    }
+
+    /// Records the state of a sub-canister after
+    /// [`SubCanisterCalls::restore_snapshot`]: running once restored, stopped if
+    /// the restore failed after stopping it.
+    pub fn record_snapshot_restore(
+        &mut self,
+        canister_id: Principal,
+        result: &Result<(), SnapshotError>,
+    ) {
+        match result {
+            Ok(()) => self.set_canister_state(canister_id, CanisterState::Installed),
+            Err(SnapshotError::LoadSnapshotError(_) | SnapshotError::StartCanisterError(_)) => {
+                self.set_canister_state(canister_id, CanisterState::Stopped)
+            }
+            Err(_) => {}
+        }
+    }
+
+    /// Deletes a snapshot of a sub-canister.
+    pub async fn delete_snapshot(
+        &mut self,
+        canister_id: impl Into<Principal>,
+        snapshot_id: SnapshotId,
+    ) -> Result<(), SnapshotError> {
+        self.calls()
+            .delete_snapshot(canister_id.into(), snapshot_id)
+            .await
+    }
+
+    fn set_canister_state(&mut self, canister_id: Principal, state: CanisterState) {
+        if let Some(canister) = self.sub_canisters.get(&canister_id) {
+            let canister_param = canister.canister_param();
+            self.sub_canisters.insert(
+                canister_id,
+                Box::new(T::new(canister_id, state, canister_param)),
+            );
+        }
+    }
+
+    pub fn list_canisters(&self) -> Vec<Box<impl Canister>> {
+        self.sub_canisters.values().cloned().collect()
+    }
+
+    pub fn list_canisters_ids(&self) -> Vec<Principal> {
+        self.sub_canisters.clone().into_keys().collect()
+    }
+
+    /// Returns the sub-canisters that are not retired, i.e. the ones new work
+    /// can be given to. [`list_canisters`](Self::list_canisters) returns them all.
+    pub fn active_canisters(&self) -> Vec<Box<T>> {
+        self.sub_canisters
+            .iter()
+            .filter(|(canister_id, _)| !self.retired.contains(canister_id))
+            .map(|(_, canister)| canister.clone())
+            .collect()
+    }
+
+    /// Returns the status of every sub-canister, ordered by canister ID.
+    ///
+    /// With many sub-canisters, prefer [`get_canisters_status_page`](Self::get_canisters_status_page)
+    /// whose pages fit in a response.
+    pub fn status(&self) -> Vec<SubCanisterStatus> {
+        let mut funding_alerts = self.check_funding_health();
+        let mut status: Vec<SubCanisterStatus> = self
+            .sub_canisters
+            .keys()
+            .map(|canister_id| self.canister_status(*canister_id, &mut funding_alerts))
+            .collect();
+        status.sort_by_key(|s| s.canister_id);
+        status
+    }
+
+    /// Returns the status of a sub-canister, taking its alerts out of `funding_alerts`.
+    fn canister_status(
+        &self,
+        canister_id: Principal,
+        funding_alerts: &mut Vec<FundingAlert>,
+    ) -> SubCanisterStatus {
+        SubCanisterStatus {
+            canister_id,
+            state: self.sub_canisters[&canister_id].state(),
+            retired: self.retired.contains(&canister_id),
+            history: self
+                .canister_history
+                .get(&canister_id)
+                .cloned()
+                .unwrap_or_default(),
+            funding_alerts: funding_alerts
+                .extract_if(.., |alert| alert.canister_id == canister_id)
+                .collect(),
+        }
+    }
+
+    /// Returns the IDs of a page of sub-canisters ordered by canister ID, with the
+    /// cursor of the next page.
+    fn page_ids(
+        &self,
+        start_after: Option<Principal>,
+        limit: u16,
+    ) -> (Vec<Principal>, Option<Principal>) {
+        let limit = limit.clamp(1, MAX_CANISTERS_PAGE_SIZE) as usize;
+        let mut canister_ids: Vec<Principal> = self
+            .sub_canisters
+            .keys()
+            .filter(|canister_id| start_after.is_none_or(|start_after| **canister_id > start_after))
+            .copied()
+            .collect();
+        canister_ids.sort();
+
+        let next = (canister_ids.len() > limit).then(|| canister_ids[limit - 1]);
+        canister_ids.truncate(limit);
+        (canister_ids, next)
+    }
+
+    /// Lists a page of sub-canisters with their recorded state, without calling
+    /// any canister.
+    ///
+    /// # Arguments
+    /// * `start_after` - Lists the sub-canisters after this one, from the first one when `None`
+    /// * `limit` - Maximum number of sub-canisters in the page, see [`MAX_CANISTERS_PAGE_SIZE`]
+    ///
+    /// # Returns
+    /// The page, ordered by canister ID, whose `next` cursor is the `start_after`
+    /// of the next page
+    pub fn list_canisters_page(
+        &self,
+        start_after: Option<Principal>,
+        limit: u16,
+    ) -> CanisterListPage {
+        let (canister_ids, next) = self.page_ids(start_after, limit);
+        CanisterListPage {
+            canisters: canister_ids
+                .into_iter()
+                .map(|canister_id| CanisterSummary {
+                    canister_id,
+                    state: self.sub_canisters[&canister_id].state(),
+                    retired: self.retired.contains(&canister_id),
+                })
+                .collect(),
+            next,
+        }
+    }
+
+    /// Returns the status of a page of sub-canisters, as [`status`](Self::status)
+    /// does for all of them.
+    ///
+    /// The cycle balances are only fetched when requested, with at most
+    /// [`STATUS_PAGE_MAX_CONCURRENCY`] `canister_status` calls in flight. A failed
+    /// call is reported in the entry of its sub-canister.
+    ///
+    /// # Returns
+    /// The page, ordered by canister ID, whose `next` cursor is the `start_after`
+    /// of the next page
+    pub async fn get_canisters_status_page(&self, args: StatusPageArgs) -> StatusPage {
+        let (canister_ids, next) = self.page_ids(args.start_after, args.limit);
+
+        let mut cycles: HashMap<Principal, Result<u128, String>> = HashMap::new();
+        if args.include_cycles {
+            let management = self.management();
+            cycles = fan_out_calls(
+                canister_ids.clone(),
+                STATUS_PAGE_MAX_CONCURRENCY,
+                |canister_id| {
+                    let management = &management;
+                    async move {
+                        management
+                            .canister_status_summary(canister_id)
+                            .await
+                            .map(|status| status.cycles)
+                    }
+                },
+            )
+            .await
+            .into_iter()
+            .collect();
+        }
+
+        let mut funding_alerts = self.check_funding_health();
+        StatusPage {
+            entries: canister_ids
+                .into_iter()
+                .map(|canister_id| StatusPageEntry {
+                    status: self.canister_status(canister_id, &mut funding_alerts),
+                    cycles: cycles.remove(&canister_id),
+                })
+                .collect(),
+            next,
+        }
+    }
+
+    /// Returns the funding threshold of a sub-canister, `None` when it is funded
+    /// at a fixed interval whatever its balance.
+    fn min_cycles(&self, canister_id: &Principal) -> Option<u128> {
+        let strategy = match &self.retired_fund_strategy {
+            Some(strategy) if self.retired.contains(canister_id) => strategy,
+            _ => self.funding_config.strategy(),
+        };
+        match strategy {
+            FundStrategy::BelowThreshold(threshold) => Some(threshold.min_cycles()),
+            FundStrategy::BelowEstimatedRuntime(runtime) => Some(runtime.fallback_min_cycles()),
+            FundStrategy::Always(_) => None,
+        }
+    }
+
+    /// Samples the cycle balance of every sub-canister and of the master canister,
+    /// updating their burn rates.
+    ///
+    /// A failed sample is kept until the next successful one and reported by
+    /// [`check_funding_health`](Self::check_funding_health).
+    pub async fn sample_cycles(&mut self) {
+        let sample = self.calls().sample_cycles().await;
+        self.record_cycles_sample(sample);
+    }
+
+    /// Updates the burn rates with cycle balances sampled by
+    /// [`SubCanisterCalls::sample_cycles`]. The samples of the sub-canisters
+    /// removed since are dropped.
+    pub fn record_cycles_sample(&mut self, sample: CyclesSample) {
+        let now = Nanos::from(bity_ic_canister_time::timestamp_nanos()).get();
+        self.cycles_samples
+            .retain(|canister_id, _| self.sub_canisters.contains_key(canister_id));
+        for (canister_id, balance) in sample.balances {
+            if !self.sub_canisters.contains_key(&canister_id) {
+                continue;
+            }
+            let samples = self.cycles_samples.entry(canister_id).or_default();
+            match balance {
+                Ok(cycles) => samples.record(cycles, now),
+                Err(e) => samples.record_error(e),
+            }
+        }
+        self.master_cycles_samples.record(sample.master_cycles, now);
+    }
+
+    /// Returns the canisters trending towards freezing according to the last
+    /// samples, ordered by canister ID with the master canister last.
+    ///
+    /// A sub-canister is reported when its last sample failed, its last top-up
+    /// failed, its balance is below its funding threshold or, otherwise, it runs
+    /// out of cycles within [`funding_health::FUNDING_ALERT_HORIZON_DAYS`] at the
+    /// observed burn rate. The master canister is reported when it runs out of
+    /// cycles within that horizon.
+    pub fn check_funding_health(&self) -> Vec<FundingAlert> {
+        let master_cycles = self.master_cycles_samples.cycles;
+        let mut canister_ids = self.list_canisters_ids();
+        canister_ids.sort();
+
+        let mut alerts = vec![];
+        for canister_id in canister_ids {
+            let Some(samples) = self.cycles_samples.get(&canister_id) else {
+                continue;
+            };
+            let min_cycles = self.min_cycles(&canister_id);
+            let alert = |reason| samples.alert(canister_id, reason, min_cycles, master_cycles);
+
+            if let Some(error) = &samples.error {
+                alerts.push(alert(FundingAlertReason::SampleFailed(error.clone())));
+            }
+            if let Some(failure) = self
+                .fund_manager
+                .get_canister(canister_id)
+                .and_then(|record| record.get_funding_failure().cloned())
+            {
+                alerts.push(alert(FundingAlertReason::TopUpFailed(
+                    failure.error_code.message(),
+                )));
+            }
+            let below_threshold = samples
+                .cycles
+                .zip(min_cycles)
+                .is_some_and(|(cycles, min_cycles)| cycles < min_cycles);
+            if below_threshold {
+                alerts.push(alert(FundingAlertReason::BelowThreshold));
+            } else if samples.is_running_out() {
+                alerts.push(alert(FundingAlertReason::RunningOut));
+            }
+        }
+
+        if self.master_cycles_samples.is_running_out() {
+            alerts.push(self.master_cycles_samples.alert(
+                self.master_canister_id,
+                FundingAlertReason::RunningOut,
+                None,
+                master_cycles,
+            ));
+        }
+        alerts
+    }
+
+    /// Retries the pending fund manager registrations and samples the cycle
+    /// balances, then returns the funding alerts and logs each of them as a warning.
+    ///
+    /// Meant to be called from a timer, see [`retry_pending_funding`](Self::retry_pending_funding),
+    /// [`sample_cycles`](Self::sample_cycles) and [`check_funding_health`](Self::check_funding_health).
+    pub async fn run_funding_health_check(&mut self) -> Vec<FundingAlert> {
+        self.retry_pending_funding();
+        self.sample_cycles().await;
+        self.report_funding_health()
+    }
+
+    /// Returns the funding alerts according to the last samples and logs each of
+    /// them as a warning, see [`check_funding_health`](Self::check_funding_health).
+    pub fn report_funding_health(&self) -> Vec<FundingAlert> {
+        let alerts = self.check_funding_health();
+        for alert in &alerts {
+            tracing::warn!(
+                canister_id = %alert.canister_id,
+                reason = ?alert.reason,
+                cycles = ?alert.cycles,
+                min_cycles = ?alert.min_cycles,
+                days_remaining = ?alert.days_remaining,
+                master_cycles = ?alert.master_cycles,
+                "Canister trending towards freezing"
+            );
+        }
+        alerts
+    }
+}
+
+impl<T> Clone for SubCanisterManager<T>
+where
+    T: Canister + Clone + Send,
+{
+    fn clone(&self) -> Self {
+        let mut fund_manager = FundManager::new();
+
+        if !self.test_mode {
+            add_canisters_to_fund_manager(
+                &mut fund_manager,
+                self.funding_config.clone(),
+                self.sub_canisters.clone().into_keys().collect(),
+            );
+            if let Some(strategy) = &self.retired_fund_strategy {
+                register_retired_canisters(
+                    &mut fund_manager,
+                    strategy,
+                    self.retired.iter().copied(),
+                );
+            }
+        }
+
+        Self {
+            master_canister_id: self.master_canister_id,
+            sub_canisters: self.sub_canisters.clone(),
+            controllers: self.controllers.clone(),
+            authorized_principal: self.authorized_principal.clone(),
+            initial_cycles: self.initial_cycles,
+            reserved_cycles: self.reserved_cycles,
+            test_mode: self.test_mode,
+            commit_hash: self.commit_hash.clone(),
+            wasm: self.wasm.clone(),
+            fund_manager: fund_manager,
+            funding_config: self.funding_config.clone(),
+            canister_controllers: self.canister_controllers.clone(),
+            snapshot_before_upgrade: self.snapshot_before_upgrade,
+            canister_history: self.canister_history.clone(),
+            simulated: self.simulated.clone(),
+            retired: self.retired.clone(),
+            retired_fund_strategy: self.retired_fund_strategy.clone(),
+            lifecycle_events: self.lifecycle_events.clone(),
+            pending_funding: self.pending_funding.clone(),
+            cycles_samples: self.cycles_samples.clone(),
+            master_cycles_samples: self.master_cycles_samples.clone(),
+        }
+    }
+}
+
+/// Applies the funding config and registers canisters with the fund manager,
+/// restarting it.
+///
+/// # Returns
+///
+/// The canisters that could not be registered, with the reason, e.g. all of them
+/// when the config is invalid, in which case the fund manager is left untouched
+pub fn add_canisters_to_fund_manager(
+    fund_manager: &mut FundManager,
+    funding_config: FundManagerOptions,
+    canister_id_lst: Vec<Principal>,
+) -> Vec<(Principal, String)> {
+    if let Err(error) = validate_funding_options(&funding_config, None) {
+        return canister_id_lst
+            .into_iter()
+            .map(|canister_id| (canister_id, error.clone()))
+            .collect();
+    }
+
+    fund_manager.stop();
+
+    fund_manager.with_options(funding_config);
+
+    for canister_id in canister_id_lst {
+        fund_manager.register(
+            canister_id,
+            RegisterOpts::new()
+                .with_cycles_fetcher(Arc::new(FetchCyclesBalanceFromCanisterStatus::new())),
+        );
+    }
+
+    // The fund manager runs on canister timers, which are not available off-chain.
+    #[cfg(not(feature = "host-test"))]
+    fund_manager.start();
+
+    vec![]
+}
+
+/// Registers one canister with the fund manager, with `strategy` instead of the
+/// strategy of the config if set.
+///
+/// Unlike [`add_canisters_to_fund_manager`], a running fund manager is neither
+/// stopped nor restarted, and keeps its options. A stopped one gets the options
+/// of `funding_config` and is started.
+///
+/// # Errors
+///
+/// Returns an error message if the options are invalid, see [`validate_funding_options`]
+pub fn register_one(
+    fund_manager: &mut FundManager,
+    funding_config: &FundManagerOptions,
+    canister_id: Principal,
+    strategy: Option<FundStrategy>,
+) -> Result<(), String> {
+    let running = fund_manager.is_running();
+    if running {
+        validate_funding_options(&fund_manager.get_options(), strategy.as_ref())?;
+    } else {
+        validate_funding_options(funding_config, strategy.as_ref())?;
+        fund_manager.with_options(funding_config.clone());
+    }
+
+    let mut opts = RegisterOpts::new()
+        .with_cycles_fetcher(Arc::new(FetchCyclesBalanceFromCanisterStatus::new()));
+    if let Some(strategy) = strategy {
+        opts = opts.with_strategy(strategy);
+    }
+    fund_manager.register(canister_id, opts);
+
+    #[cfg(not(feature = "host-test"))]
+    if !running {
+        fund_manager.start();
+    }
+    Ok(())
+}
+
+/// Checks that canisters can be registered with these options.
+///
+/// The fund manager sizes the balance history of the `BelowEstimatedRuntime`
+/// strategy by dividing by the interval, and traps on a zero interval, which
+/// would leave a canister created in the same call unfunded.
+///
+/// # Arguments
+/// * `funding_config` - The options of the fund manager
+/// * `strategy` - The strategy of the canister, if not the one of the options
+pub fn validate_funding_options(
+    funding_config: &FundManagerOptions,
+    strategy: Option<&FundStrategy>,
+) -> Result<(), String> {
+    let strategy = strategy.unwrap_or(funding_config.strategy());
+    if matches!(strategy, FundStrategy::BelowEstimatedRuntime(_))
+        && funding_config.interval_secs() == 0
+    {
+        return Err("the BelowEstimatedRuntime strategy requires a positive interval".to_string());
+    }
+    Ok(())
+}
+
+/// Registers canisters with the simulated fund manager, returning the failures.
+fn simulated_registration_failures(
+    simulated: &SimulatedManagementCanister,
+    canister_ids: &[Principal],
+) -> Vec<(Principal, String)> {
+    match simulated.register_funding(canister_ids.to_vec()) {
+        Ok(()) => vec![],
+        Err(error) => canister_ids
+            .iter()
+            .map(|canister_id| (*canister_id, error.clone()))
+            .collect(),
+    }
+}
+
+/// Registers retired canisters again with their own funding strategy, replacing
+/// the registration made by [`add_canisters_to_fund_manager`].
+fn register_retired_canisters(
+    fund_manager: &mut FundManager,
+    strategy: &FundStrategy,
+    canister_ids: impl IntoIterator<Item = Principal>,
+) {
+    for canister_id in canister_ids {
+        fund_manager.register(
+            canister_id,
+            RegisterOpts::new()
+                .with_cycles_fetcher(Arc::new(FetchCyclesBalanceFromCanisterStatus::new()))
+                .with_strategy(strategy.clone()),
+        );
+    }
+}
+
+#[cfg(all(test, feature = "host-test"))]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use canfund::manager::options::CyclesThreshold;
+    use futures::executor::block_on;
+    use ic_cdk::management_canister::UpdateSettingsArgs;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MockManagementCanister {
+        next_id: Mutex<u64>,
+        controllers: Mutex<HashMap<Principal, Vec<Principal>>>,
+        installs: Mutex<Vec<(Principal, CanisterInstallMode)>>,
+        modules: Mutex<HashMap<Principal, Vec<u8>>>,
+        stopped: Mutex<Vec<Principal>>,
+        snapshots: Mutex<HashMap<Principal, Vec<Snapshot>>>,
+        loaded_snapshots: Mutex<Vec<(Principal, SnapshotId)>>,
+        snapshots_unsupported: bool,
+        cycles: Mutex<HashMap<Principal, u128>>,
+        own_cycles: Mutex<u128>,
+    }
+
+    #[async_trait]
+    impl ManagementCanisterClient for MockManagementCanister {
+        async fn create_canister(
+            &self,
+            settings: CanisterSettings,
+            _cycles: u128,
+        ) -> Result<Principal, String> {
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id += 1;
+            let canister_id = Principal::from_slice(&next_id.to_be_bytes());
+            self.controllers
+                .lock()
+                .unwrap()
+                .insert(canister_id, settings.controllers.unwrap_or_default());
+            Ok(canister_id)
+        }
+
+        async fn install_code(&self, args: InstallCodeArgs) -> Result<(), String> {
+            self.modules
+                .lock()
+                .unwrap()
+                .insert(args.canister_id, Sha256::digest(&args.wasm_module).to_vec());
+            self.installs
+                .lock()
+                .unwrap()
+                .push((args.canister_id, args.mode));
+            Ok(())
+        }
+
+        async fn canister_controllers(
+            &self,
+            canister_id: Principal,
+        ) -> Result<Vec<Principal>, String> {
+            self.controllers
+                .lock()
+                .unwrap()
+                .get(&canister_id)
+                .cloned()
+                .ok_or_else(|| format!("canister {canister_id} not found"))
+        }
+
+        async fn canister_status_summary(
+            &self,
+            canister_id: Principal,
+        ) -> Result<CanisterStatusSummary, String> {
+            if !self.controllers.lock().unwrap().contains_key(&canister_id) {
+                return Err(format!("canister {canister_id} not found"));
+            }
+            Ok(CanisterStatusSummary {
+                status: if self.stopped.lock().unwrap().contains(&canister_id) {
+                    CanisterStatusType::Stopped
+                } else {
+                    CanisterStatusType::Running
+                },
+                module_hash: self.modules.lock().unwrap().get(&canister_id).cloned(),
+                cycles: self
+                    .cycles
+                    .lock()
+                    .unwrap()
+                    .get(&canister_id)
+                    .copied()
+                    .unwrap_or_default(),
+            })
+        }
+
+        fn own_cycle_balance(&self) -> u128 {
+            *self.own_cycles.lock().unwrap()
+        }
+
+        async fn update_settings(&self, args: UpdateSettingsArgs) -> Result<(), String> {
+            if let Some(controllers) = args.settings.controllers {
+                self.controllers
+                    .lock()
+                    .unwrap()
+                    .insert(args.canister_id, controllers);
+            }
+            Ok(())
+        }
+
+        async fn start_canister(&self, canister_id: Principal) -> Result<(), String> {
+            self.stopped.lock().unwrap().retain(|c| *c != canister_id);
+            Ok(())
+        }
+
+        async fn stop_canister(&self, canister_id: Principal) -> Result<(), String> {
+            self.stopped.lock().unwrap().push(canister_id);
+            Ok(())
+        }
+
+        async fn take_canister_snapshot(
+            &self,
+            canister_id: Principal,
+            replace_snapshot: Option<SnapshotId>,
+        ) -> Result<Snapshot, String> {
+            if self.snapshots_unsupported {
+                return Err("canister snapshots are not supported".to_string());
+            }
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id += 1;
+            let mut snapshots = self.snapshots.lock().unwrap();
+            let canister_snapshots = snapshots.entry(canister_id).or_default();
+            if let Some(replace_snapshot) = replace_snapshot {
+                canister_snapshots.retain(|snapshot| snapshot.id != replace_snapshot);
+            }
+            let snapshot = Snapshot {
+                id: next_id.to_be_bytes().to_vec(),
+                taken_at_timestamp: *next_id,
+                total_size: 0,
+            };
+            canister_snapshots.push(snapshot.clone());
+            Ok(snapshot)
+        }
+
+        async fn list_canister_snapshots(
+            &self,
+            canister_id: Principal,
+        ) -> Result<Vec<Snapshot>, String> {
+            Ok(self
+                .snapshots
+                .lock()
+                .unwrap()
+                .get(&canister_id)
+                .cloned()
+                .unwrap_or_default())
+        }
+
+        async fn load_canister_snapshot(
+            &self,
+            canister_id: Principal,
+            snapshot_id: SnapshotId,
+        ) -> Result<(), String> {
+            if !self.stopped.lock().unwrap().contains(&canister_id) {
+                return Err("canister must be stopped".to_string());
+            }
+            self.loaded_snapshots
+                .lock()
+                .unwrap()
+                .push((canister_id, snapshot_id));
+            Ok(())
+        }
+
+        async fn delete_canister_snapshot(
+            &self,
+            canister_id: Principal,
+            snapshot_id: SnapshotId,
+        ) -> Result<(), String> {
+            if let Some(snapshots) = self.snapshots.lock().unwrap().get_mut(&canister_id) {
+                snapshots.retain(|snapshot| snapshot.id != snapshot_id);
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    struct TestCanister {
+        canister_id: Principal,
+        state: CanisterState,
+        param: u64,
+    }
+
+    impl Canister for TestCanister {
+        type ParamType = u64;
+
+        fn new(canister_id: Principal, state: CanisterState, canister_param: u64) -> Self {
+            Self {
+                canister_id,
+                state,
+                param: canister_param,
+            }
+        }
+
+        fn canister_param(&self) -> u64 {
+            self.param
+        }
+
+        fn canister_id(&self) -> Principal {
+            self.canister_id
+        }
+
+        fn state(&self) -> CanisterState {
+            self.state.clone()
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    fn setup() -> (
+        Arc<MockManagementCanister>,
+        SubCanisterManager<TestCanister>,
+    ) {
+        setup_with_client(MockManagementCanister::default())
+    }
+
+    fn setup_with_client(
+        client: MockManagementCanister,
+    ) -> (
+        Arc<MockManagementCanister>,
+        SubCanisterManager<TestCanister>,
+    ) {
+        let client = Arc::new(client);
+        set_management_canister_client(client.clone());
+
+        let manager = SubCanisterManager::new(
+            Principal::from_slice(&[0xff]),
+            HashMap::new(),
+            vec![],
+            vec![],
+            1_000_000_000_000,
+            0,
+            false,
+            "commit_hash".to_string(),
+            vec![0u8; 8],
+            FundManagerOptions::new(),
+        );
+
+        (client, manager)
+    }
+
+    fn setup_test_mode() -> SubCanisterManager<TestCanister> {
+        SubCanisterManager::new(
+            Principal::from_slice(&[0xff]),
+            HashMap::new(),
+            vec![],
+            vec![],
+            1_000_000_000_000,
+            0,
+            true,
+            "commit_hash".to_string(),
+            vec![0u8; 8],
+            FundManagerOptions::new(),
+        )
+    }
+
+    #[test]
+    fn test_create_and_upgrade_sub_canisters() {
+        let (client, mut manager) = setup();
+
+        let canister = block_on(manager.create_canister(1)).unwrap();
+        assert_eq!(canister.state(), CanisterState::Installed);
+        assert_eq!(
+            block_on(canister.get_canister_controllers()).ok(),
+            Some(vec![manager.master_canister_id])
+        );
+
+        block_on(manager.update_canisters(2)).unwrap();
+        let upgraded = &manager.sub_canisters[&canister.canister_id()];
+        assert_eq!(upgraded.state(), CanisterState::Installed);
+        assert_eq!(upgraded.canister_param(), 2);
+
+        let installs = client.installs.lock().unwrap().clone();
+        assert_eq!(
+            installs,
+            vec![
+                (canister.canister_id(), CanisterInstallMode::Install),
+                (canister.canister_id(), CanisterInstallMode::Upgrade(None)),
+            ]
+        );
+        assert!(client.stopped.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_canister_history() {
+        let (_client, mut manager) = setup();
+        let start = 1_700_000_000_000_000_000;
+        bity_ic_canister_time::set_mock_time(start);
+
+        let canister_id = block_on(manager.create_canister(1)).unwrap().canister_id();
+        assert_eq!(
+            manager.canister_history(&canister_id),
+            Some(CanisterHistory {
+                created_at: Some(start),
+                installed_at: Some(start),
+                last_upgrade_at: None,
+                upgrade_count: 0,
+                last_commit_hash: Some("commit_hash".to_string()),
+                module_hash: Some(manager.wasm_hash()),
+                last_reinstall_at: None,
+                reinstall_count: 0,
+            })
+        );
+
+        bity_ic_canister_time::set_mock_time(start + 10);
+        block_on(manager.update_canisters(2)).unwrap();
+        manager.commit_hash = "next_commit_hash".to_string();
+        bity_ic_canister_time::set_mock_time(start + 20);
+        block_on(manager.update_canisters(3)).unwrap();
+
+        let expected = CanisterHistory {
+            created_at: Some(start),
+            installed_at: Some(start),
+            last_upgrade_at: Some(start + 20),
+            upgrade_count: 2,
+            last_commit_hash: Some("next_commit_hash".to_string()),
+            module_hash: Some(manager.wasm_hash()),
+            last_reinstall_at: None,
+            reinstall_count: 0,
+        };
+        assert_eq!(
+            manager.canister_history(&canister_id),
+            Some(expected.clone())
+        );
+        assert_eq!(manager.canister_history(&Principal::from_slice(&[2])), None);
+
+        // The history is stored with the manager.
+        let bytes = rmp_serde::to_vec_named(&manager).unwrap();
+        let restored: SubCanisterManager<TestCanister> = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(restored.canister_history(&canister_id), Some(expected));
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let (client, mut manager) = setup();
+        let canister_id = block_on(manager.create_canister(1)).unwrap().canister_id();
+        let wasm_hash = manager.wasm_hash();
+
+        let report = block_on(manager.reconcile(false));
+        assert_eq!(report.checked, 1);
+        assert!(report.divergences.is_empty());
+
+        // The canister is upgraded and stopped out of band.
+        let manual_hash = vec![7; 32];
+        client
+            .modules
+            .lock()
+            .unwrap()
+            .insert(canister_id, manual_hash.clone());
+        client.stopped.lock().unwrap().push(canister_id);
+
+        let module_divergence = |action, adopted| Divergence {
+            canister_id,
+            kind: DivergenceKind::ModuleHash {
+                recorded: Some(wasm_hash.clone()),
+                actual: Some(manual_hash.clone()),
+            },
+            action,
+            adopted,
+        };
+        let state_divergence = |adopted| Divergence {
+            canister_id,
+            kind: DivergenceKind::State {
+                recorded: CanisterState::Installed,
+                actual: CanisterState::Stopped,
+            },
+            action: SuggestedAction::ResyncState,
+            adopted,
+        };
+        let report = block_on(manager.reconcile(false));
+        assert_eq!(
+            report.divergences,
+            vec![
+                module_divergence(SuggestedAction::Investigate, false),
+                state_divergence(false),
+            ]
+        );
+        assert_eq!(
+            manager.canister_history(&canister_id).unwrap().module_hash,
+            Some(wasm_hash.clone())
+        );
+
+        let report = block_on(manager.reconcile(true));
+        assert_eq!(
+            report.divergences,
+            vec![
+                module_divergence(SuggestedAction::Investigate, true),
+                state_divergence(true),
+            ]
+        );
+        let history = manager.canister_history(&canister_id).unwrap();
+        assert_eq!(history.module_hash, Some(manual_hash.clone()));
+        assert_eq!(history.last_commit_hash, None);
+        assert_eq!(
+            manager.sub_canisters[&canister_id].state(),
+            CanisterState::Stopped
+        );
+
+        // The adopted module is now known, only the upgrade remains to be done.
+        let report = block_on(manager.reconcile(true));
+        assert_eq!(
+            report.divergences,
+            vec![Divergence {
+                canister_id,
+                kind: DivergenceKind::ModuleHash {
+                    recorded: Some(manual_hash.clone()),
+                    actual: Some(manual_hash.clone()),
+                },
+                action: SuggestedAction::ScheduleUpgrade,
+                adopted: false,
+            }]
+        );
+
+        block_on(manager.update_canisters(2)).unwrap();
+        assert!(block_on(manager.reconcile(false)).divergences.is_empty());
+        assert_eq!(
+            manager
+                .canister_history(&canister_id)
+                .unwrap()
+                .last_commit_hash,
+            Some("commit_hash".to_string())
+        );
+
+        // A sub-canister removed while its status was fetched is not reported.
+        let statuses = block_on(manager.calls().canister_statuses());
+        manager.sub_canisters.remove(&canister_id);
+        let report = manager.apply_reconciliation(statuses, true);
+        assert_eq!(report.checked, 0);
+        assert!(report.divergences.is_empty());
+    }
+
+    #[test]
+    fn test_reconcile_in_test_mode() {
+        let mut manager = setup_test_mode();
+        let canister_id = block_on(manager.create_canister(1)).unwrap().canister_id();
+        assert!(block_on(manager.reconcile(false)).divergences.is_empty());
+
+        // Records predating the module hash are filled in.
+        manager
+            .canister_history
+            .get_mut(&canister_id)
+            .unwrap()
+            .module_hash = None;
+        let report = block_on(manager.reconcile(true));
+        assert_eq!(
+            report
+                .divergences
+                .iter()
+                .map(|d| &d.action)
+                .collect::<Vec<_>>(),
+            vec![&SuggestedAction::ResyncState]
+        );
+        assert_eq!(
+            manager.canister_history(&canister_id).unwrap().module_hash,
+            Some(manager.wasm_hash())
+        );
+    }
+
+    #[test]
+    fn test_controller_management() {
+        let (_client, mut manager) = setup();
+        let canister_id = block_on(manager.create_canister(1)).unwrap().canister_id();
+        let master = manager.master_canister_id;
+        let user = Principal::from_slice(&[1]);
+
+        let controllers = block_on(manager.add_controller(canister_id, user)).unwrap();
+        assert_eq!(controllers, vec![master, user]);
+        assert_eq!(manager.cached_controllers(&canister_id), Some(&controllers));
+
+        assert_eq!(
+            block_on(manager.remove_controller(canister_id, master)),
+            Err(ControllerError::CannotRemoveMasterCanister)
+        );
+
+        let controllers = block_on(manager.remove_controller(canister_id, user)).unwrap();
+        assert_eq!(controllers, vec![master]);
+
+        let unknown = Principal::from_slice(&[2]);
+        assert_eq!(
+            block_on(manager.sync_controllers(unknown)),
+            Err(ControllerError::UnknownCanister(unknown))
+        );
+    }
+
+    #[test]
+    fn test_snapshot_around_upgrade() {
+        let (client, mut manager) = setup();
+        manager.set_snapshot_before_upgrade(true);
+        let canister_id = block_on(manager.create_canister(1)).unwrap().canister_id();
+
+        block_on(manager.update_canisters(2)).unwrap();
+
+        // The snapshot was taken and pruned once the upgrade succeeded.
+        assert_eq!(block_on(manager.list_snapshots(canister_id)), Ok(vec![]));
+        assert_eq!(client.installs.lock().unwrap().len(), 2);
+
+        let snapshot_id = block_on(manager.take_snapshot(canister_id)).unwrap();
+        block_on(manager.restore_snapshot(canister_id, snapshot_id.clone())).unwrap();
+        assert_eq!(
+            client.loaded_snapshots.lock().unwrap().clone(),
+            vec![(canister_id, snapshot_id)]
+        );
+        assert_eq!(
+            manager.sub_canisters[&canister_id].state(),
+            CanisterState::Installed
+        );
+        assert!(client.stopped.lock().unwrap().is_empty());
+
+        // A restore that failed after stopping the sub-canister leaves it stopped.
+        manager.record_snapshot_restore(
+            canister_id,
+            &Err(SnapshotError::LoadSnapshotError("rejected".to_string())),
+        );
+        assert_eq!(
+            manager.sub_canisters[&canister_id].state(),
+            CanisterState::Stopped
+        );
+    }
+
+    #[test]
+    fn test_repeated_snapshots_replace_the_latest_one() {
+        let (_, mut manager) = setup();
+        let canister_id = block_on(manager.create_canister(1)).unwrap().canister_id();
+
+        let first = block_on(manager.take_snapshot(canister_id)).unwrap();
+        let second = block_on(manager.take_snapshot(canister_id)).unwrap();
+        assert_ne!(first, second);
+        let snapshots = block_on(manager.list_snapshots(canister_id)).unwrap();
+        assert_eq!(
+            snapshots.into_iter().map(|s| s.id).collect::<Vec<_>>(),
+            vec![second]
+        );
+    }
+
+    #[test]
+    fn test_upgrade_is_skipped_when_snapshot_fails() {
+        let (client, mut manager) = setup_with_client(MockManagementCanister {
+            snapshots_unsupported: true,
+            ..Default::default()
+        });
+        manager.set_snapshot_before_upgrade(true);
+        let canister_id = block_on(manager.create_canister(1)).unwrap().canister_id();
+
+        let errors = block_on(manager.update_canisters(2)).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("canister snapshots are not supported"));
+
+        // Only the initial install happened and the canister was started again.
+        assert_eq!(client.installs.lock().unwrap().len(), 1);
+        assert!(client.stopped.lock().unwrap().is_empty());
+        assert_eq!(
+            manager.sub_canisters[&canister_id].state(),
+            CanisterState::Installed
+        );
+
+        assert_eq!(
+            block_on(manager.take_snapshot(canister_id)),
+            Err(SnapshotError::TakeSnapshotError(
+                "canister snapshots are not supported".to_string()
+            ))
+        );
+        let unknown = Principal::from_slice(&[2]);
+        assert_eq!(
+            block_on(manager.take_snapshot(unknown)),
+            Err(SnapshotError::UnknownCanister(unknown))
+        );
+    }
+
+    #[test]
+    fn test_failed_funding_registration_is_retried() {
+        let mut manager = setup_test_mode();
+        manager.simulated.fail_next_registrations(1);
+
+        let canister_id = block_on(manager.create_canister(1)).unwrap().canister_id();
+        assert_eq!(manager.list_canisters_ids(), vec![canister_id]);
+        assert_eq!(manager.pending_funding, vec![canister_id]);
+        assert_eq!(
+            manager
+                .lifecycle_events()
+                .iter()
+                .map(|event| event.kind.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                LifecycleEventKind::FundingRegistrationFailed(
+                    "simulated registration failure".to_string()
+                ),
+                LifecycleEventKind::Installed,
+            ]
+        );
+        assert!(!manager
+            .simulated_operations_log()
+            .contains(&SimulatedOperation::RegisterFunding(vec![canister_id])));
+
+        // As done by the next run_funding_health_check.
+        manager.retry_pending_funding();
+        assert!(manager.pending_funding.is_empty());
+        assert!(manager
+            .simulated_operations_log()
+            .contains(&SimulatedOperation::RegisterFunding(vec![canister_id])));
+    }
+
+    #[test]
+    fn test_invalid_funding_options_are_reported() {
+        let invalid = FundManagerOptions::new()
+            .with_interval_secs(0)
+            .with_strategy(FundStrategy::BelowEstimatedRuntime(
+                canfund::manager::options::EstimatedRuntime::new(),
+            ));
+        assert!(validate_funding_options(&invalid, None).is_err());
+        assert_eq!(
+            validate_funding_options(&invalid, Some(&FundStrategy::Always(1))),
+            Ok(())
+        );
+
+        let canister_id = Principal::from_slice(&[1]);
+        let mut fund_manager = FundManager::new();
+        assert!(register_one(&mut fund_manager, &invalid, canister_id, None).is_err());
+        assert_eq!(
+            add_canisters_to_fund_manager(&mut fund_manager, invalid, vec![canister_id]).len(),
+            1
+        );
+        assert!(fund_manager.get_canister(canister_id).is_none());
+
+        let valid = FundManagerOptions::new().with_interval_secs(60);
+        assert_eq!(
+            register_one(&mut fund_manager, &valid, canister_id, None),
+            Ok(())
+        );
+        assert!(fund_manager.get_canister(canister_id).is_some());
+    }
+
+    #[test]
+    fn test_test_mode_simulates_management_calls() {
+        // No client is installed on this thread, so any real call would panic.
+        let mut manager = setup_test_mode();
+        let master = manager.master_canister_id;
+        manager.set_snapshot_before_upgrade(true);
+
+        let first = block_on(manager.create_canister(1)).unwrap().canister_id();
+        let second = block_on(manager.create_canister(1)).unwrap().canister_id();
+        assert_eq!(first, SimulatedManagementCanister::canister_id(0));
+        assert_eq!(second, SimulatedManagementCanister::canister_id(1));
+
+        block_on(manager.update_canisters(2)).unwrap();
+        assert_eq!(manager.sub_canisters[&first].canister_param(), 2);
+        assert_eq!(
+            manager.canister_history(&first).map(|h| h.upgrade_count),
+            Some(1)
+        );
+        // The snapshots taken around the upgrade were deleted.
+        assert_eq!(block_on(manager.list_snapshots(first)), Ok(vec![]));
+
+        let user = Principal::from_slice(&[1]);
+        assert_eq!(
+            block_on(manager.add_controller(first, user)),
+            Ok(vec![master, user])
+        );
+
+        let log = manager.simulated_operations_log();
+        assert_eq!(
+            log[..4],
+            [
+                SimulatedOperation::CreateCanister {
+                    canister_id: first,
+                    controllers: vec![master],
+                    cycles: 1_000_000_000_000,
+                },
+                SimulatedOperation::RegisterFunding(vec![first]),
+                SimulatedOperation::InstallCode {
+                    canister_id: first,
+                    mode: CanisterInstallMode::Install,
+                    wasm_module_size: 8,
+                },
+                SimulatedOperation::CreateCanister {
+                    canister_id: second,
+                    controllers: vec![master],
+                    cycles: 1_000_000_000_000,
+                },
+            ]
+        );
+        let upgrades = log
+            .iter()
+            .filter(|op| {
+                matches!(op, SimulatedOperation::InstallCode { mode, .. }
+                    if *mode == CanisterInstallMode::Upgrade(None))
+            })
+            .count();
+        assert_eq!(upgrades, 2);
+        assert_eq!(
+            log.iter()
+                .filter(|op| matches!(op, SimulatedOperation::DeleteSnapshot { .. }))
+                .count(),
+            2
+        );
+        assert_eq!(
+            log.last(),
+            Some(&SimulatedOperation::UpdateSettings {
+                canister_id: first,
+                controllers: Some(vec![master, user]),
+            })
+        );
+    }
+
+    #[test]
+    fn test_canister_pages_cover_every_canister_once() {
+        let mut manager = setup_test_mode();
+        for _ in 0..300 {
+            block_on(manager.create_canister(1)).unwrap();
+        }
+        let retired = SimulatedManagementCanister::canister_id(42);
+        manager.retire(retired).unwrap();
+        let mut all_ids = manager.list_canisters_ids();
+        all_ids.sort();
+
+        let mut listed = vec![];
+        let mut start_after = None;
+        loop {
+            let page = manager.list_canisters_page(start_after, 64);
+            assert!(page.canisters.len() <= 64);
+            assert!(page
+                .canisters
+                .iter()
+                .all(|canister| canister.state == CanisterState::Installed
+                    && canister.retired == (canister.canister_id == retired)));
+            listed.extend(page.canisters.iter().map(|canister| canister.canister_id));
+            match page.next {
+                Some(next) => {
+                    assert_eq!(page.canisters.len(), 64);
+                    assert_eq!(Some(&next), listed.last());
+                    start_after = Some(next);
+                }
+                None => break,
+            }
+        }
+        assert_eq!(listed, all_ids);
+
+        let mut statuses = vec![];
+        let mut start_after = None;
+        loop {
+            let page = block_on(manager.get_canisters_status_page(StatusPageArgs {
+                start_after,
+                limit: 100,
+                include_cycles: statuses.is_empty(),
+            }));
+            assert!(page.entries.len() <= 100);
+            for entry in &page.entries {
+                assert_eq!(entry.cycles.is_some(), statuses.is_empty());
+            }
+            statuses.extend(page.entries);
+            match page.next {
+                Some(next) => start_after = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(
+            statuses
+                .iter()
+                .map(|entry| entry.status.clone())
+                .collect::<Vec<_>>(),
+            manager.status()
+        );
+        assert_eq!(statuses[0].cycles, Some(Ok(1_000_000_000_000)));
+
+        // The limit is kept within bounds, the last page has no cursor.
+        assert_eq!(manager.list_canisters_page(None, 0).canisters.len(), 1);
+        assert_eq!(
+            manager.list_canisters_page(None, u16::MAX).canisters.len(),
+            300
+        );
+        assert_eq!(
+            manager
+                .list_canisters_page(all_ids.last().copied(), 10)
+                .next,
+            None
+        );
+        assert!(manager
+            .list_canisters_page(all_ids.last().copied(), 10)
+            .canisters
+            .is_empty());
+    }
+
+    #[test]
+    fn test_retired_canisters() {
+        let mut manager = setup_test_mode();
+        let first = block_on(manager.create_canister(1)).unwrap().canister_id();
+        let second = block_on(manager.create_canister(1)).unwrap().canister_id();
+
+        assert_eq!(manager.retire(first), Ok(()));
+        assert!(manager.is_retired(&first));
+        let active: Vec<Principal> = manager
+            .active_canisters()
+            .iter()
+            .map(|c| c.canister_id())
+            .collect();
+        assert_eq!(active, vec![second]);
+        assert_eq!(manager.list_canisters().len(), 2);
+
+        let unknown = Principal::from_slice(&[2]);
+        assert_eq!(
+            manager.retire(unknown),
+            Err(RetireError::UnknownCanister(unknown))
+        );
+
+        // Only the retired canisters are registered again with their strategy.
+        manager.set_retired_fund_strategy(Some(FundStrategy::Always(1)));
+        assert_eq!(
+            manager.simulated_operations_log().last(),
+            Some(&SimulatedOperation::RegisterFunding(vec![first]))
+        );
+
+        // The retired set is stored with the manager.
+        let bytes = rmp_serde::to_vec_named(&manager).unwrap();
+        let mut restored: SubCanisterManager<TestCanister> = rmp_serde::from_slice(&bytes).unwrap();
+        let status = restored.status();
+        assert_eq!(status.len(), 2);
+        assert!(status
+            .iter()
+            .all(|s| s.retired == (s.canister_id == first) && s.state == CanisterState::Installed));
+
+        assert_eq!(restored.unretire(first), Ok(()));
+        assert!(!restored.is_retired(&first));
+        assert_eq!(restored.active_canisters().len(), 2);
+    }
+
+    #[test]
+    fn test_funding_health_alerts() {
+        let (client, mut manager) = setup();
+        manager.funding_config = FundManagerOptions::new().with_strategy(
+            FundStrategy::BelowThreshold(CyclesThreshold::new().with_min_cycles(1_000)),
+        );
+        let first = block_on(manager.create_canister(1)).unwrap().canister_id();
+        let second = block_on(manager.create_canister(1)).unwrap().canister_id();
+        let master = manager.master_canister_id;
+        let day = 86_400_000_000_000;
+        let start = 1_700_000_000_000_000_000;
+
+        let set_cycles = |first_cycles, second_cycles, master_cycles| {
+            let mut cycles = client.cycles.lock().unwrap();
+            cycles.insert(first, first_cycles);
+            cycles.insert(second, second_cycles);
+            *client.own_cycles.lock().unwrap() = master_cycles;
+        };
+
+        bity_ic_canister_time::set_mock_time(start);
+        set_cycles(100_000, 500, 1_000_000);
+        block_on(manager.sample_cycles());
+        let alerts = manager.check_funding_health();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].canister_id, second);
+        assert_eq!(alerts[0].reason, FundingAlertReason::BelowThreshold);
+        assert_eq!(alerts[0].min_cycles, Some(1_000));
+        assert_eq!(alerts[0].master_cycles, Some(1_000_000));
+
+        // The first canister burns a third of its balance a day, the master
+        // canister a tenth.
+        bity_ic_canister_time::set_mock_time(start + day);
+        set_cycles(66_000, 2_000, 900_000);
+        let alerts = block_on(manager.run_funding_health_check());
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].canister_id, first);
+        assert_eq!(alerts[0].reason, FundingAlertReason::RunningOut);
+        assert_eq!(alerts[0].burn_per_day, Some(34_000.0));
+
+        bity_ic_canister_time::set_mock_time(start + 2 * day);
+        set_cycles(66_000, 2_000, 100_000);
+        client.controllers.lock().unwrap().remove(&second);
+        let alerts = block_on(manager.run_funding_health_check());
+        let reasons: Vec<(Principal, FundingAlertReason)> = alerts
+            .into_iter()
+            .map(|alert| (alert.canister_id, alert.reason))
+            .collect();
+        let mut expected = vec![
+            (first, FundingAlertReason::RunningOut),
+            (
+                second,
+                FundingAlertReason::SampleFailed(format!("canister {second} not found")),
+            ),
+            (master, FundingAlertReason::RunningOut),
+        ];
+        if second < first {
+            expected.swap(0, 1);
+        }
+        assert_eq!(reasons, expected);
+
+        let status = manager.status();
+        assert!(status.iter().all(|s| s.funding_alerts.len() == 1));
+    }
+
+    #[test]
+    fn test_cycles_are_sampled_without_borrowing_the_manager() {
+        let (client, mut manager) = setup();
+        let first = block_on(manager.create_canister(1)).unwrap().canister_id();
+        let second = block_on(manager.create_canister(1)).unwrap().canister_id();
+        client.cycles.lock().unwrap().insert(first, 5_000);
+        client.cycles.lock().unwrap().insert(second, 7_000);
+        *client.own_cycles.lock().unwrap() = 9_000;
+
+        let calls = manager.calls();
+        let sample = block_on(calls.sample_cycles());
+        assert_eq!(sample.balances.len(), 2);
+        assert_eq!(sample.master_cycles, 9_000);
+
+        // A sub-canister removed while the calls were awaited is not recorded.
+        manager.sub_canisters.remove(&second);
+        manager.record_cycles_sample(sample);
+        assert_eq!(manager.cycles_samples[&first].cycles, Some(5_000));
+        assert!(!manager.cycles_samples.contains_key(&second));
+        assert_eq!(manager.master_cycles_samples.cycles, Some(9_000));
+    }
+
+    #[test]
+    fn test_reinstall_requires_a_valid_confirmation() {
+        let (client, mut manager) = setup();
+        let canister_id = block_on(manager.create_canister(1)).unwrap().canister_id();
+        let other = Principal::from_slice(&[2]);
+
+        let confirmation = ReinstallConfirmation::confirm_data_loss(canister_id);
+        assert_eq!(confirmation.validate(canister_id), Ok(()));
+        assert!(matches!(
+            block_on(manager.reinstall_canister(
+                canister_id,
+                2,
+                ReinstallConfirmation::confirm_data_loss(other)
+            )),
+            Err(ReinstallError::InvalidConfirmation(_))
+        ));
+        let forged = ReinstallConfirmation {
+            canister_id,
+            phrase: "i-understand-data-loss".to_string(),
+        };
+        assert!(matches!(
+            block_on(manager.reinstall_canister(canister_id, 2, forged)),
+            Err(ReinstallError::InvalidConfirmation(_))
+        ));
+        assert_eq!(
+            block_on(manager.reinstall_canister(
+                other,
+                2,
+                ReinstallConfirmation::confirm_data_loss(other)
+            )),
+            Err(ReinstallError::UnknownCanister(other))
+        );
+
+        // Nothing was done to the canister.
+        assert_eq!(client.installs.lock().unwrap().len(), 1);
+        assert!(client.stopped.lock().unwrap().is_empty());
+        assert_eq!(
+            manager
+                .canister_history(&canister_id)
+                .unwrap()
+                .reinstall_count,
+            0
+        );
+    }
+
+    #[test]
+    fn test_reinstall_canister() {
+        let (client, mut manager) = setup();
+        let start = 1_700_000_000_000_000_000;
+        bity_ic_canister_time::set_mock_time(start);
+        let canister_id = block_on(manager.create_canister(1)).unwrap().canister_id();
+
+        bity_ic_canister_time::set_mock_time(start + 10);
+        block_on(manager.reinstall_canister(
+            canister_id,
+            2,
+            ReinstallConfirmation::confirm_data_loss(canister_id),
+        ))
+        .unwrap();
+
+        assert_eq!(
+            client.installs.lock().unwrap().last(),
+            Some(&(canister_id, CanisterInstallMode::Reinstall))
+        );
+        assert!(client.stopped.lock().unwrap().is_empty());
+        let canister = &manager.sub_canisters[&canister_id];
+        assert_eq!(canister.state(), CanisterState::Installed);
+        assert_eq!(canister.canister_param(), 2);
+
+        let history = manager.canister_history(&canister_id).unwrap();
+        assert_eq!(history.last_reinstall_at, Some(start + 10));
+        assert_eq!(history.reinstall_count, 1);
+        assert_eq!(history.upgrade_count, 0);
+
+        let events = manager.lifecycle_events();
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| (event.kind.clone(), event.destructive))
+                .collect::<Vec<_>>(),
+            vec![
+                (LifecycleEventKind::Installed, false),
+                (LifecycleEventKind::Reinstalled, true),
+            ]
+        );
+        assert_eq!(events[1].canister_id, canister_id);
+        assert_eq!(events[1].timestamp, start + 10);
+
+        // The events are stored with the manager.
+        let bytes = rmp_serde::to_vec_named(&manager).unwrap();
+        let restored: SubCanisterManager<TestCanister> = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(restored.lifecycle_events(), events);
+    }
+
+    #[test]
+    fn test_test_mode_registry_survives_serialization() {
+        let mut manager = setup_test_mode();
+        let canister_id = block_on(manager.create_canister(1)).unwrap().canister_id();
+        let snapshot_id = block_on(manager.take_snapshot(canister_id)).unwrap();
+
+        let bytes = rmp_serde::to_vec_named(&manager).unwrap();
+        let mut restored: SubCanisterManager<TestCanister> = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(
+            restored.simulated_operations_log(),
+            manager.simulated_operations_log()
+        );
+
+        // Ids keep being allocated after the restored ones.
+        let next = block_on(restored.create_canister(1)).unwrap().canister_id();
+        assert_eq!(next, SimulatedManagementCanister::canister_id(1));
+
+        block_on(restored.restore_snapshot(canister_id, snapshot_id.clone())).unwrap();
+        block_on(restored.delete_snapshot(canister_id, snapshot_id.clone())).unwrap();
+        assert!(matches!(
+            block_on(restored.delete_snapshot(canister_id, snapshot_id)),
+            Err(SnapshotError::DeleteSnapshotError(_))
+        ));
+    }
 }
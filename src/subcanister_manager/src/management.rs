@@ -0,0 +1,256 @@
+//! Access to the management canister used by the sub-canister manager.
+//!
+//! All management canister calls made by [`SubCanisterManager`](crate::SubCanisterManager)
+//! go through the [`ManagementCanisterClient`] trait. On-chain, [`IcManagementCanister`]
+//! forwards them to `ic_cdk`. With the `host-test` feature, a mock client is installed
+//! with [`set_management_canister_client`] so the manager can be tested with `cargo test`.
+
+use async_trait::async_trait;
+use candid::Principal;
+use ic_cdk::management_canister::{
+    CanisterSettings, CanisterStatusType, InstallCodeArgs, Snapshot, SnapshotId, UpdateSettingsArgs,
+};
+use std::sync::Arc;
+
+/// The part of `canister_status` used to reconcile the manager's records and
+/// sample the cycle balances.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CanisterStatusSummary {
+    /// Whether the canister is running, stopping or stopped
+    pub status: CanisterStatusType,
+    /// SHA-256 hash of the installed module, `None` when the canister is empty
+    pub module_hash: Option<Vec<u8>>,
+    /// Cycle balance of the canister
+    pub cycles: u128,
+}
+
+/// Management canister calls made by the sub-canister manager.
+///
+/// Errors are returned as strings, formatted the same way as the `ic_cdk` call errors.
+#[async_trait]
+pub trait ManagementCanisterClient: Send + Sync {
+    /// Creates a canister with the given settings and cycles, returning its id.
+    async fn create_canister(
+        &self,
+        settings: CanisterSettings,
+        cycles: u128,
+    ) -> Result<Principal, String>;
+
+    /// Installs or upgrades the code of a canister.
+    async fn install_code(&self, args: InstallCodeArgs) -> Result<(), String>;
+
+    /// Returns the controllers of a canister, as reported by `canister_status`.
+    async fn canister_controllers(&self, canister_id: Principal) -> Result<Vec<Principal>, String>;
+
+    /// Returns the running status and module hash of a canister, as reported by
+    /// `canister_status`.
+    async fn canister_status_summary(
+        &self,
+        canister_id: Principal,
+    ) -> Result<CanisterStatusSummary, String>;
+
+    /// Returns the cycle balance of the calling canister.
+    fn own_cycle_balance(&self) -> u128 {
+        ic_cdk::api::canister_cycle_balance()
+    }
+
+    /// Updates the settings of a canister.
+    async fn update_settings(&self, args: UpdateSettingsArgs) -> Result<(), String>;
+
+    /// Starts a canister.
+    async fn start_canister(&self, canister_id: Principal) -> Result<(), String>;
+
+    /// Stops a canister.
+    async fn stop_canister(&self, canister_id: Principal) -> Result<(), String>;
+
+    /// Takes a snapshot of a canister, replacing `replace_snapshot` if given.
+    async fn take_canister_snapshot(
+        &self,
+        canister_id: Principal,
+        replace_snapshot: Option<SnapshotId>,
+    ) -> Result<Snapshot, String>;
+
+    /// Lists the snapshots of a canister.
+    async fn list_canister_snapshots(
+        &self,
+        canister_id: Principal,
+    ) -> Result<Vec<Snapshot>, String>;
+
+    /// Loads a snapshot into a canister.
+    async fn load_canister_snapshot(
+        &self,
+        canister_id: Principal,
+        snapshot_id: SnapshotId,
+    ) -> Result<(), String>;
+
+    /// Deletes a snapshot of a canister.
+    async fn delete_canister_snapshot(
+        &self,
+        canister_id: Principal,
+        snapshot_id: SnapshotId,
+    ) -> Result<(), String>;
+}
+
+/// Client forwarding the calls to the management canister through `ic_cdk`.
+pub struct IcManagementCanister;
+
+#[async_trait]
+impl ManagementCanisterClient for IcManagementCanister {
+    async fn create_canister(
+        &self,
+        settings: CanisterSettings,
+        cycles: u128,
+    ) -> Result<Principal, String> {
+        ic_cdk::management_canister::create_canister_with_extra_cycles(
+            &ic_cdk::management_canister::CreateCanisterArgs {
+                settings: Some(settings),
+            },
+            cycles,
+        )
+        .await
+        .map(|canister| canister.canister_id)
+        .map_err(|e| format!("{e:?}"))
+    }
+
+    async fn install_code(&self, args: InstallCodeArgs) -> Result<(), String> {
+        ic_cdk::management_canister::install_code(&args)
+            .await
+            .map_err(|e| format!("{e:?}"))
+    }
+
+    async fn canister_controllers(&self, canister_id: Principal) -> Result<Vec<Principal>, String> {
+        ic_cdk::management_canister::canister_status(
+            &ic_cdk::management_canister::CanisterIdRecord { canister_id },
+        )
+        .await
+        .map(|status| status.settings.controllers)
+        .map_err(|e| format!("{e:?}"))
+    }
+
+    async fn canister_status_summary(
+        &self,
+        canister_id: Principal,
+    ) -> Result<CanisterStatusSummary, String> {
+        ic_cdk::management_canister::canister_status(
+            &ic_cdk::management_canister::CanisterIdRecord { canister_id },
+        )
+        .await
+        .map(|status| CanisterStatusSummary {
+            status: status.status,
+            module_hash: status.module_hash,
+            cycles: status.cycles.0.try_into().unwrap_or(u128::MAX),
+        })
+        .map_err(|e| format!("{e:?}"))
+    }
+
+    async fn update_settings(&self, args: UpdateSettingsArgs) -> Result<(), String> {
+        ic_cdk::management_canister::update_settings(&args)
+            .await
+            .map_err(|e| format!("{e:?}"))
+    }
+
+    async fn start_canister(&self, canister_id: Principal) -> Result<(), String> {
+        ic_cdk::management_canister::start_canister(
+            &ic_cdk::management_canister::CanisterIdRecord { canister_id },
+        )
+        .await
+        .map_err(|e| format!("{e:?}"))
+    }
+
+    async fn stop_canister(&self, canister_id: Principal) -> Result<(), String> {
+        ic_cdk::management_canister::stop_canister(&ic_cdk::management_canister::CanisterIdRecord {
+            canister_id,
+        })
+        .await
+        .map_err(|e| format!("{e:?}"))
+    }
+
+    async fn take_canister_snapshot(
+        &self,
+        canister_id: Principal,
+        replace_snapshot: Option<SnapshotId>,
+    ) -> Result<Snapshot, String> {
+        ic_cdk::management_canister::take_canister_snapshot(
+            &ic_cdk::management_canister::TakeCanisterSnapshotArgs {
+                canister_id,
+                replace_snapshot,
+            },
+        )
+        .await
+        .map_err(|e| format!("{e:?}"))
+    }
+
+    async fn list_canister_snapshots(
+        &self,
+        canister_id: Principal,
+    ) -> Result<Vec<Snapshot>, String> {
+        ic_cdk::management_canister::list_canister_snapshots(
+            &ic_cdk::management_canister::CanisterIdRecord { canister_id },
+        )
+        .await
+        .map_err(|e| format!("{e:?}"))
+    }
+
+    async fn load_canister_snapshot(
+        &self,
+        canister_id: Principal,
+        snapshot_id: SnapshotId,
+    ) -> Result<(), String> {
+        ic_cdk::management_canister::load_canister_snapshot(
+            &ic_cdk::management_canister::LoadCanisterSnapshotArgs {
+                canister_id,
+                snapshot_id,
+            },
+        )
+        .await
+        .map_err(|e| format!("{e:?}"))
+    }
+
+    async fn delete_canister_snapshot(
+        &self,
+        canister_id: Principal,
+        snapshot_id: SnapshotId,
+    ) -> Result<(), String> {
+        ic_cdk::management_canister::delete_canister_snapshot(
+            &ic_cdk::management_canister::DeleteCanisterSnapshotArgs {
+                canister_id,
+                snapshot_id,
+            },
+        )
+        .await
+        .map_err(|e| format!("{e:?}"))
+    }
+}
+
+#[cfg(feature = "host-test")]
+thread_local! {
+    static CLIENT: std::cell::RefCell<Option<Arc<dyn ManagementCanisterClient>>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// Installs the client used for management canister calls on the current thread.
+#[cfg(feature = "host-test")]
+pub fn set_management_canister_client(client: Arc<dyn ManagementCanisterClient>) {
+    CLIENT.with(|c| *c.borrow_mut() = Some(client));
+}
+
+/// Returns the client used for management canister calls.
+///
+/// # Panics
+///
+/// With the `host-test` feature, panics if no client was installed with
+/// [`set_management_canister_client`].
+pub fn management_canister() -> Arc<dyn ManagementCanisterClient> {
+    #[cfg(feature = "host-test")]
+    {
+        CLIENT.with(|c| {
+            c.borrow()
+                .clone()
+                .expect("no management canister client installed for host tests")
+        })
+    }
+    #[cfg(not(feature = "host-test"))]
+    {
+        Arc::new(IcManagementCanister)
+    }
+}
@@ -0,0 +1,330 @@
+//! Simulated management canister used by the sub-canister manager in test mode.
+//!
+//! When [`SubCanisterManager::test_mode`](crate::SubCanisterManager::test_mode) is
+//! set, the manager makes no management canister call. [`SimulatedManagementCanister`]
+//! answers them instead: it allocates deterministic canister ids from a counter,
+//! keeps the controllers, modules, running status and snapshots of the simulated
+//! canisters, and records each operation that would have been made in a log.
+
+use crate::management::{CanisterStatusSummary, ManagementCanisterClient};
+use async_trait::async_trait;
+use bity_ic_canister_time::Nanos;
+use candid::{CandidType, Principal};
+use ic_cdk::management_canister::{
+    CanisterInstallMode, CanisterSettings, CanisterStatusType, InstallCodeArgs, Snapshot,
+    SnapshotId, UpdateSettingsArgs,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+/// An operation the manager would have made in real mode.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum SimulatedOperation {
+    /// A canister was created with these controllers and cycles
+    CreateCanister {
+        canister_id: Principal,
+        controllers: Vec<Principal>,
+        cycles: u128,
+    },
+    /// Code was installed or upgraded
+    InstallCode {
+        canister_id: Principal,
+        mode: CanisterInstallMode,
+        wasm_module_size: usize,
+    },
+    /// The settings of a canister were updated
+    UpdateSettings {
+        canister_id: Principal,
+        controllers: Option<Vec<Principal>>,
+    },
+    /// A canister was started
+    StartCanister(Principal),
+    /// A canister was stopped
+    StopCanister(Principal),
+    /// A snapshot was taken
+    TakeSnapshot {
+        canister_id: Principal,
+        snapshot_id: SnapshotId,
+    },
+    /// A snapshot was loaded
+    LoadSnapshot {
+        canister_id: Principal,
+        snapshot_id: SnapshotId,
+    },
+    /// A snapshot was deleted
+    DeleteSnapshot {
+        canister_id: Principal,
+        snapshot_id: SnapshotId,
+    },
+    /// Canisters were registered with the fund manager
+    RegisterFunding(Vec<Principal>),
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+struct SimulatedState {
+    /// Number of canisters created so far
+    created: u64,
+    controllers: HashMap<Principal, Vec<Principal>>,
+    snapshots: HashMap<Principal, Vec<Snapshot>>,
+    operations: Vec<SimulatedOperation>,
+    /// Hash of the module installed on each canister
+    #[serde(default)]
+    modules: HashMap<Principal, Vec<u8>>,
+    /// Canisters stopped and not started since
+    #[serde(default)]
+    stopped: HashSet<Principal>,
+    /// Cycles each canister was created with
+    #[serde(default)]
+    cycles: HashMap<Principal, u128>,
+    /// Number of upcoming fund manager registrations to fail
+    #[serde(default)]
+    failing_registrations: u32,
+}
+
+/// The simulated canister registry of a manager in test mode.
+///
+/// Cloning copies the registry, while [`shared`](Self::shared) handles update the
+/// same one.
+#[derive(Serialize, Deserialize, Default)]
+pub struct SimulatedManagementCanister {
+    state: Arc<Mutex<SimulatedState>>,
+}
+
+impl Clone for SimulatedManagementCanister {
+    fn clone(&self) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(self.state.lock().unwrap().clone())),
+        }
+    }
+}
+
+impl SimulatedManagementCanister {
+    /// Returns a handle on the same registry, to make calls without borrowing the
+    /// manager, see [`SubCanisterCalls`](crate::calls::SubCanisterCalls).
+    pub(crate) fn shared(&self) -> Self {
+        Self {
+            state: Arc::clone(&self.state),
+        }
+    }
+
+    /// Returns the operations simulated so far, oldest first.
+    pub fn operations(&self) -> Vec<SimulatedOperation> {
+        self.state.lock().unwrap().operations.clone()
+    }
+
+    /// Records an operation that has no management canister call.
+    pub fn record(&self, operation: SimulatedOperation) {
+        self.state.lock().unwrap().operations.push(operation);
+    }
+
+    /// Makes the next `count` fund manager registrations fail, to exercise the
+    /// retry of [`SubCanisterManager::pending_funding`](crate::SubCanisterManager::pending_funding).
+    pub fn fail_next_registrations(&self, count: u32) {
+        self.state.lock().unwrap().failing_registrations = count;
+    }
+
+    /// Registers canisters with the simulated fund manager, recording the
+    /// registration unless it was made to fail.
+    pub fn register_funding(&self, canister_ids: Vec<Principal>) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        if state.failing_registrations > 0 {
+            state.failing_registrations -= 1;
+            return Err("simulated registration failure".to_string());
+        }
+        state
+            .operations
+            .push(SimulatedOperation::RegisterFunding(canister_ids));
+        Ok(())
+    }
+
+    /// Returns the id of the `index`-th simulated canister, an opaque id of the
+    /// same form as the ids assigned by the subnets.
+    pub fn canister_id(index: u64) -> Principal {
+        let mut bytes = [0x01; 10];
+        bytes[..8].copy_from_slice(&index.to_be_bytes());
+        Principal::from_slice(&bytes)
+    }
+
+    fn check_exists(state: &SimulatedState, canister_id: Principal) -> Result<(), String> {
+        if state.controllers.contains_key(&canister_id) {
+            Ok(())
+        } else {
+            Err(format!("canister {canister_id} not found"))
+        }
+    }
+}
+
+#[async_trait]
+impl ManagementCanisterClient for SimulatedManagementCanister {
+    async fn create_canister(
+        &self,
+        settings: CanisterSettings,
+        cycles: u128,
+    ) -> Result<Principal, String> {
+        let mut state = self.state.lock().unwrap();
+        let canister_id = Self::canister_id(state.created);
+        state.created += 1;
+
+        let controllers = settings.controllers.unwrap_or_default();
+        state.controllers.insert(canister_id, controllers.clone());
+        state.cycles.insert(canister_id, cycles);
+        state.operations.push(SimulatedOperation::CreateCanister {
+            canister_id,
+            controllers,
+            cycles,
+        });
+        Ok(canister_id)
+    }
+
+    async fn install_code(&self, args: InstallCodeArgs) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        Self::check_exists(&state, args.canister_id)?;
+        state
+            .modules
+            .insert(args.canister_id, Sha256::digest(&args.wasm_module).to_vec());
+        state.operations.push(SimulatedOperation::InstallCode {
+            canister_id: args.canister_id,
+            mode: args.mode,
+            wasm_module_size: args.wasm_module.len(),
+        });
+        Ok(())
+    }
+
+    async fn canister_controllers(&self, canister_id: Principal) -> Result<Vec<Principal>, String> {
+        let state = self.state.lock().unwrap();
+        Self::check_exists(&state, canister_id)?;
+        Ok(state.controllers[&canister_id].clone())
+    }
+
+    async fn canister_status_summary(
+        &self,
+        canister_id: Principal,
+    ) -> Result<CanisterStatusSummary, String> {
+        let state = self.state.lock().unwrap();
+        Self::check_exists(&state, canister_id)?;
+        Ok(CanisterStatusSummary {
+            status: if state.stopped.contains(&canister_id) {
+                CanisterStatusType::Stopped
+            } else {
+                CanisterStatusType::Running
+            },
+            module_hash: state.modules.get(&canister_id).cloned(),
+            cycles: state.cycles.get(&canister_id).copied().unwrap_or_default(),
+        })
+    }
+
+    async fn update_settings(&self, args: UpdateSettingsArgs) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        Self::check_exists(&state, args.canister_id)?;
+        if let Some(controllers) = &args.settings.controllers {
+            state
+                .controllers
+                .insert(args.canister_id, controllers.clone());
+        }
+        state.operations.push(SimulatedOperation::UpdateSettings {
+            canister_id: args.canister_id,
+            controllers: args.settings.controllers,
+        });
+        Ok(())
+    }
+
+    async fn start_canister(&self, canister_id: Principal) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        Self::check_exists(&state, canister_id)?;
+        state.stopped.remove(&canister_id);
+        state
+            .operations
+            .push(SimulatedOperation::StartCanister(canister_id));
+        Ok(())
+    }
+
+    async fn stop_canister(&self, canister_id: Principal) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        Self::check_exists(&state, canister_id)?;
+        state.stopped.insert(canister_id);
+        state
+            .operations
+            .push(SimulatedOperation::StopCanister(canister_id));
+        Ok(())
+    }
+
+    async fn take_canister_snapshot(
+        &self,
+        canister_id: Principal,
+        replace_snapshot: Option<SnapshotId>,
+    ) -> Result<Snapshot, String> {
+        let mut state = self.state.lock().unwrap();
+        Self::check_exists(&state, canister_id)?;
+        let operation_index = state.operations.len() as u64;
+        let snapshots = state.snapshots.entry(canister_id).or_default();
+        if let Some(replace_snapshot) = replace_snapshot {
+            snapshots.retain(|snapshot| snapshot.id != replace_snapshot);
+        }
+
+        let snapshot = Snapshot {
+            id: operation_index.to_be_bytes().to_vec(),
+            taken_at_timestamp: Nanos::from(bity_ic_canister_time::timestamp_nanos()).get(),
+            total_size: 0,
+        };
+        snapshots.push(snapshot.clone());
+        state.operations.push(SimulatedOperation::TakeSnapshot {
+            canister_id,
+            snapshot_id: snapshot.id.clone(),
+        });
+        Ok(snapshot)
+    }
+
+    async fn list_canister_snapshots(
+        &self,
+        canister_id: Principal,
+    ) -> Result<Vec<Snapshot>, String> {
+        let state = self.state.lock().unwrap();
+        Self::check_exists(&state, canister_id)?;
+        Ok(state
+            .snapshots
+            .get(&canister_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn load_canister_snapshot(
+        &self,
+        canister_id: Principal,
+        snapshot_id: SnapshotId,
+    ) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let exists = state
+            .snapshots
+            .get(&canister_id)
+            .is_some_and(|snapshots| snapshots.iter().any(|s| s.id == snapshot_id));
+        if !exists {
+            return Err(format!("snapshot not found for canister {canister_id}"));
+        }
+        state.operations.push(SimulatedOperation::LoadSnapshot {
+            canister_id,
+            snapshot_id,
+        });
+        Ok(())
+    }
+
+    async fn delete_canister_snapshot(
+        &self,
+        canister_id: Principal,
+        snapshot_id: SnapshotId,
+    ) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let snapshots = state.snapshots.entry(canister_id).or_default();
+        let len = snapshots.len();
+        snapshots.retain(|snapshot| snapshot.id != snapshot_id);
+        if snapshots.len() == len {
+            return Err(format!("snapshot not found for canister {canister_id}"));
+        }
+        state.operations.push(SimulatedOperation::DeleteSnapshot {
+            canister_id,
+            snapshot_id,
+        });
+        Ok(())
+    }
+}
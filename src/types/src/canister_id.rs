@@ -0,0 +1,309 @@
+//! A canister id that is validated to be one.
+//!
+//! [`CanisterId`](crate::CanisterId) is an alias of `Principal`, so nothing prevents
+//! a user principal from being used where a canister id is expected. The
+//! [`CanisterIdStrict`] newtype only holds principals of the canister id form:
+//! the management canister (`aaaaa-aa`) or an opaque id of 10 bytes ending with
+//! `0x01`, as assigned to canisters by the subnets.
+
+use candid::types::{Serializer, Type};
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The length of the ids assigned to canisters.
+const CANISTER_ID_LENGTH: usize = 10;
+/// The last byte of the opaque ids, which includes canister ids.
+const OPAQUE_ID_TAG: u8 = 0x01;
+
+/// Returns whether `bytes` have the form of a canister id.
+const fn is_canister_id(bytes: &[u8]) -> bool {
+    bytes.is_empty()
+        || (bytes.len() == CANISTER_ID_LENGTH && bytes[bytes.len() - 1] == OPAQUE_ID_TAG)
+}
+
+/// Error returned when a principal does not have the form of a canister id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NotACanisterId(pub Principal);
+
+impl Display for NotACanisterId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is not a canister id", self.0)
+    }
+}
+
+impl std::error::Error for NotACanisterId {}
+
+/// A principal validated to have the form of a canister id.
+///
+/// # Examples
+///
+/// ```
+/// use bity_ic_types::{canister_id, CanisterIdStrict};
+/// use candid::Principal;
+///
+/// const LEDGER: CanisterIdStrict = canister_id!("ryjl3-tyaaa-aaaaa-aaaba-cai");
+///
+/// let principal = Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap();
+/// assert_eq!(CanisterIdStrict::from_principal(principal), Ok(LEDGER));
+/// assert!(CanisterIdStrict::from_principal(Principal::anonymous()).is_err());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CanisterIdStrict(Principal);
+
+impl CanisterIdStrict {
+    /// The id of the management canister, `aaaaa-aa`.
+    pub const MANAGEMENT_CANISTER: CanisterIdStrict =
+        CanisterIdStrict(Principal::management_canister());
+
+    /// Validates that `principal` has the form of a canister id.
+    pub fn from_principal(principal: Principal) -> Result<Self, NotACanisterId> {
+        if is_canister_id(principal.as_slice()) {
+            Ok(CanisterIdStrict(principal))
+        } else {
+            Err(NotACanisterId(principal))
+        }
+    }
+
+    /// Returns the underlying principal.
+    pub const fn as_principal(&self) -> &Principal {
+        &self.0
+    }
+
+    /// Parses the textual form of a canister id in a const context.
+    ///
+    /// This is used by [`canister_id!`](crate::canister_id), prefer
+    /// [`FromStr`] at runtime.
+    ///
+    /// # Panics
+    /// Panics if `text` is not a valid principal or not a canister id, which
+    /// fails the compilation when evaluated in a constant.
+    pub const fn from_text_or_panic(text: &str) -> Self {
+        let mut bytes = [0u8; 4 + 29];
+        let len = decode_principal_text(text.as_bytes(), &mut bytes);
+        let (checksummed, _) = bytes.split_at(len);
+        let (checksum, id) = checksummed.split_at(4);
+
+        let crc = crc32(id);
+        if checksum[0] != (crc >> 24) as u8
+            || checksum[1] != (crc >> 16) as u8
+            || checksum[2] != (crc >> 8) as u8
+            || checksum[3] != crc as u8
+        {
+            panic!("invalid principal checksum");
+        }
+        if !is_canister_id(id) {
+            panic!("not a canister id");
+        }
+
+        CanisterIdStrict(Principal::from_slice(id))
+    }
+}
+
+/// Decodes the base32 of a textual principal into `out`, returning the number
+/// of bytes written. Dashes are only accepted after every group of 5 characters.
+const fn decode_principal_text(text: &[u8], out: &mut [u8; 33]) -> usize {
+    let mut len = 0;
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    let mut i = 0;
+
+    while i < text.len() {
+        let c = text[i];
+        if i % 6 == 5 {
+            if c != b'-' || i == text.len() - 1 {
+                panic!("invalid principal text");
+            }
+            i += 1;
+            continue;
+        }
+
+        let value = match c {
+            b'a'..=b'z' => c - b'a',
+            b'2'..=b'7' => c - b'2' + 26,
+            _ => panic!("invalid principal text"),
+        };
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            if len == out.len() {
+                panic!("principal text is too long");
+            }
+            out[len] = (buffer >> bits) as u8;
+            len += 1;
+            buffer &= (1 << bits) - 1;
+        }
+        i += 1;
+    }
+
+    if buffer != 0 || len < 4 {
+        panic!("invalid principal text");
+    }
+    len
+}
+
+/// The CRC-32 (IEEE) checksum prefixed to the bytes of a textual principal.
+const fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    let mut i = 0;
+    while i < bytes.len() {
+        crc ^= bytes[i] as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        i += 1;
+    }
+    !crc
+}
+
+/// Creates a [`CanisterIdStrict`] constant from its textual form, validated at
+/// compile time.
+///
+/// # Examples
+///
+/// ```
+/// use bity_ic_types::{canister_id, CanisterIdStrict};
+///
+/// const MANAGEMENT: CanisterIdStrict = canister_id!("aaaaa-aa");
+/// assert_eq!(MANAGEMENT, CanisterIdStrict::MANAGEMENT_CANISTER);
+/// ```
+#[macro_export]
+macro_rules! canister_id {
+    ($text:literal) => {{
+        const ID: $crate::CanisterIdStrict = $crate::CanisterIdStrict::from_text_or_panic($text);
+        ID
+    }};
+}
+
+impl Display for CanisterIdStrict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl FromStr for CanisterIdStrict {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let principal = Principal::from_text(s).map_err(|e| e.to_string())?;
+        CanisterIdStrict::from_principal(principal).map_err(|e| e.to_string())
+    }
+}
+
+impl TryFrom<Principal> for CanisterIdStrict {
+    type Error = NotACanisterId;
+
+    fn try_from(principal: Principal) -> Result<Self, Self::Error> {
+        CanisterIdStrict::from_principal(principal)
+    }
+}
+
+impl From<CanisterIdStrict> for Principal {
+    fn from(canister_id: CanisterIdStrict) -> Self {
+        canister_id.0
+    }
+}
+
+impl AsRef<Principal> for CanisterIdStrict {
+    fn as_ref(&self) -> &Principal {
+        &self.0
+    }
+}
+
+impl CandidType for CanisterIdStrict {
+    fn _ty() -> Type {
+        Principal::ty()
+    }
+
+    fn idl_serialize<S: Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+        self.0.idl_serialize(serializer)
+    }
+}
+
+impl Serialize for CanisterIdStrict {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CanisterIdStrict {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let principal = Principal::deserialize(deserializer)?;
+        CanisterIdStrict::from_principal(principal).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CANISTER_IDS: [&str; 4] = [
+        "aaaaa-aa",
+        "rrkah-fqaaa-aaaaa-aaaaq-cai",
+        "ryjl3-tyaaa-aaaaa-aaaba-cai",
+        "mxzaz-hqaaa-aaaar-qaada-cai",
+    ];
+
+    fn user_principals() -> Vec<Principal> {
+        vec![
+            Principal::anonymous(),
+            Principal::self_authenticating([7u8; 44]),
+            // An opaque id of the wrong length.
+            Principal::from_slice(&[0, 0, 0, 0, 0, 0, 0, 1, 1]),
+        ]
+    }
+
+    #[test]
+    fn test_canister_ids_are_accepted() {
+        for text in CANISTER_IDS {
+            let principal = Principal::from_text(text).unwrap();
+            let canister_id = CanisterIdStrict::from_principal(principal).unwrap();
+            assert_eq!(canister_id.as_principal(), &principal);
+            assert_eq!(canister_id.to_string(), text);
+            assert_eq!(text.parse::<CanisterIdStrict>(), Ok(canister_id));
+            assert_eq!(CanisterIdStrict::from_text_or_panic(text), canister_id);
+        }
+    }
+
+    #[test]
+    fn test_user_principals_are_rejected() {
+        for principal in user_principals() {
+            let text = principal.to_text();
+            assert_eq!(
+                CanisterIdStrict::from_principal(principal),
+                Err(NotACanisterId(principal))
+            );
+            assert!(text.parse::<CanisterIdStrict>().is_err());
+        }
+        assert!("not-a-principal".parse::<CanisterIdStrict>().is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid principal checksum")]
+    fn test_const_parse_checks_the_checksum() {
+        CanisterIdStrict::from_text_or_panic("rrkah-fqaaa-aaaaa-aaaab-cai");
+    }
+
+    #[test]
+    fn test_candid_encoding_is_a_principal() {
+        const LEDGER: CanisterIdStrict = canister_id!("ryjl3-tyaaa-aaaaa-aaaba-cai");
+
+        let bytes = candid::encode_one(LEDGER).unwrap();
+        assert_eq!(bytes, candid::encode_one(*LEDGER.as_principal()).unwrap());
+        assert_eq!(
+            candid::decode_one::<CanisterIdStrict>(&bytes).unwrap(),
+            LEDGER
+        );
+
+        let user = candid::encode_one(Principal::anonymous()).unwrap();
+        assert!(candid::decode_one::<CanisterIdStrict>(&user).is_err());
+    }
+}
@@ -7,14 +7,20 @@ use candid::{CandidType, Principal};
 use serde::{Deserialize, Serialize};
 
 mod build_version;
+mod canister_id;
+mod time_units;
 
 pub use build_version::*;
+pub use canister_id::*;
+pub use time_units::*;
 
 /// Represents an empty type, useful for functions that don't need to return data
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Empty {}
 
 /// Type alias for an Internet Computer canister ID
+///
+/// See [`CanisterIdStrict`] for a canister ID validated to be one.
 pub type CanisterId = Principal;
 /// Type alias for WebAssembly binary data representing a canister
 pub type CanisterWasm = Vec<u8>;
@@ -33,10 +39,16 @@ pub type ProposalId = u64;
 /// Type alias for Service Nervous System neuron IDs
 pub type SnsNeuronId = [u8; 32];
 /// Type alias for Unix timestamps in seconds
+///
+/// See [`Seconds`] for a newtype that can't be mixed with other units.
 pub type TimestampSeconds = u64;
 /// Type alias for Unix timestamps in milliseconds
+///
+/// See [`Millis`] for a newtype that can't be mixed with other units.
 pub type TimestampMillis = u64;
 /// Type alias for Unix timestamps in nanoseconds
+///
+/// See [`Nanos`] for a newtype that can't be mixed with other units.
 pub type TimestampNanos = u64;
 /// Type alias for seconds
 pub type Second = u64;
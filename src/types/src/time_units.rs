@@ -0,0 +1,245 @@
+//! Time units that can't be mixed up.
+//!
+//! [`TimestampSeconds`](crate::TimestampSeconds), [`TimestampMillis`](crate::TimestampMillis)
+//! and [`TimestampNanos`](crate::TimestampNanos) are all aliases of `u64`, so
+//! nothing stops a timestamp in milliseconds from being compared to one in
+//! nanoseconds. The [`Seconds`], [`Millis`] and [`Nanos`] newtypes only add up,
+//! subtract and compare within the same unit, and convert between units
+//! explicitly: to a coarser unit by flooring, to a finer unit with an overflow
+//! check.
+//!
+//! They are opt-in: each converts from and to `u64`, so the aliases can be
+//! replaced one call site at a time, and they are encoded as plain `nat64` in
+//! Candid and as plain numbers with serde, so the wire format does not change.
+
+use candid::types::{Serializer, Type};
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+
+const NANOS_PER_MILLI: u64 = 1_000_000;
+const MILLIS_PER_SECOND: u64 = 1_000;
+const NANOS_PER_SECOND: u64 = NANOS_PER_MILLI * MILLIS_PER_SECOND;
+
+macro_rules! time_unit {
+    ($(#[$meta:meta])* $name:ident, $suffix:literal) => {
+        $(#[$meta])*
+        #[derive(
+            Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+        )]
+        #[serde(transparent)]
+        pub struct $name(pub u64);
+
+        impl $name {
+            /// The zero value of the unit.
+            pub const ZERO: $name = $name(0);
+
+            /// Returns the number of units.
+            pub const fn get(self) -> u64 {
+                self.0
+            }
+
+            /// Adds `rhs`, returning `None` on overflow.
+            pub const fn checked_add(self, rhs: $name) -> Option<$name> {
+                match self.0.checked_add(rhs.0) {
+                    Some(value) => Some($name(value)),
+                    None => None,
+                }
+            }
+
+            /// Subtracts `rhs`, returning `None` if it is larger.
+            pub const fn checked_sub(self, rhs: $name) -> Option<$name> {
+                match self.0.checked_sub(rhs.0) {
+                    Some(value) => Some($name(value)),
+                    None => None,
+                }
+            }
+
+            /// Adds `rhs`, saturating at `u64::MAX`.
+            pub const fn saturating_add(self, rhs: $name) -> $name {
+                $name(self.0.saturating_add(rhs.0))
+            }
+
+            /// Subtracts `rhs`, saturating at zero.
+            pub const fn saturating_sub(self, rhs: $name) -> $name {
+                $name(self.0.saturating_sub(rhs.0))
+            }
+        }
+
+        impl From<u64> for $name {
+            fn from(value: u64) -> Self {
+                $name(value)
+            }
+        }
+
+        impl From<$name> for u64 {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl Add for $name {
+            type Output = $name;
+
+            fn add(self, rhs: $name) -> $name {
+                $name(self.0 + rhs.0)
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, rhs: $name) {
+                self.0 += rhs.0;
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $name;
+
+            fn sub(self, rhs: $name) -> $name {
+                $name(self.0 - rhs.0)
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, rhs: $name) {
+                self.0 -= rhs.0;
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                write!(f, concat!("{}", $suffix), self.0)
+            }
+        }
+
+        impl CandidType for $name {
+            fn _ty() -> Type {
+                u64::ty()
+            }
+
+            fn idl_serialize<S: Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+                self.0.idl_serialize(serializer)
+            }
+        }
+    };
+}
+
+time_unit!(
+    /// A number of seconds, or a Unix timestamp in seconds.
+    Seconds,
+    "s"
+);
+time_unit!(
+    /// A number of milliseconds, or a Unix timestamp in milliseconds.
+    Millis,
+    "ms"
+);
+time_unit!(
+    /// A number of nanoseconds, or a Unix timestamp in nanoseconds.
+    Nanos,
+    "ns"
+);
+
+impl Seconds {
+    /// Converts to milliseconds, returning `None` on overflow.
+    pub const fn to_millis(self) -> Option<Millis> {
+        match self.0.checked_mul(MILLIS_PER_SECOND) {
+            Some(millis) => Some(Millis(millis)),
+            None => None,
+        }
+    }
+
+    /// Converts to nanoseconds, returning `None` on overflow.
+    pub const fn to_nanos(self) -> Option<Nanos> {
+        match self.0.checked_mul(NANOS_PER_SECOND) {
+            Some(nanos) => Some(Nanos(nanos)),
+            None => None,
+        }
+    }
+}
+
+impl Millis {
+    /// Converts to seconds, rounding down.
+    pub const fn to_seconds(self) -> Seconds {
+        Seconds(self.0 / MILLIS_PER_SECOND)
+    }
+
+    /// Converts to nanoseconds, returning `None` on overflow.
+    pub const fn to_nanos(self) -> Option<Nanos> {
+        match self.0.checked_mul(NANOS_PER_MILLI) {
+            Some(nanos) => Some(Nanos(nanos)),
+            None => None,
+        }
+    }
+}
+
+impl Nanos {
+    /// Converts to seconds, rounding down.
+    pub const fn to_seconds(self) -> Seconds {
+        Seconds(self.0 / NANOS_PER_SECOND)
+    }
+
+    /// Converts to milliseconds, rounding down.
+    pub const fn to_millis(self) -> Millis {
+        Millis(self.0 / NANOS_PER_MILLI)
+    }
+
+    /// Returns the duration of a number of nanoseconds.
+    pub const fn as_duration(self) -> std::time::Duration {
+        std::time::Duration::from_nanos(self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions_floor_to_coarser_units() {
+        assert_eq!(Nanos(1_999_999).to_millis(), Millis(1));
+        assert_eq!(Nanos(2_999_999_999).to_seconds(), Seconds(2));
+        assert_eq!(Millis(1_999).to_seconds(), Seconds(1));
+        assert_eq!(Nanos(999_999).to_millis(), Millis::ZERO);
+
+        assert_eq!(Millis(1_500).to_nanos(), Some(Nanos(1_500_000_000)));
+        assert_eq!(Seconds(2).to_millis(), Some(Millis(2_000)));
+        assert_eq!(Seconds(2).to_nanos(), Some(Nanos(2_000_000_000)));
+        assert_eq!(Millis(1_500).to_nanos().unwrap().to_millis(), Millis(1_500));
+    }
+
+    #[test]
+    fn test_conversions_to_finer_units_check_overflow() {
+        let max_millis = Millis(u64::MAX / NANOS_PER_MILLI);
+        assert_eq!(
+            max_millis.to_nanos(),
+            Some(Nanos(max_millis.get() * NANOS_PER_MILLI))
+        );
+        assert_eq!(Millis(max_millis.get() + 1).to_nanos(), None);
+        assert_eq!(Seconds(u64::MAX / NANOS_PER_SECOND + 1).to_nanos(), None);
+        assert_eq!(Seconds(u64::MAX).to_millis(), None);
+
+        assert_eq!(Nanos(u64::MAX).checked_add(Nanos(1)), None);
+        assert_eq!(Nanos(1).checked_sub(Nanos(2)), None);
+        assert_eq!(Nanos(1).saturating_sub(Nanos(2)), Nanos::ZERO);
+        assert_eq!(Millis(1) + Millis(2), Millis(3));
+        assert_eq!(Millis(3) - Millis(2), Millis(1));
+    }
+
+    #[test]
+    fn test_wire_format_is_a_plain_number() {
+        let nanos = Nanos(1_700_000_000_000_000_000);
+        assert_eq!(
+            candid::encode_one(nanos).unwrap(),
+            candid::encode_one(nanos.get()).unwrap()
+        );
+        assert_eq!(
+            candid::decode_one::<Nanos>(&candid::encode_one(42u64).unwrap()).unwrap(),
+            Nanos(42)
+        );
+
+        assert_eq!(serde_json::to_string(&Millis(1_500)).unwrap(), "1500");
+        assert_eq!(serde_json::from_str::<Seconds>("60").unwrap(), Seconds(60));
+        assert_eq!(Millis(1_500).to_string(), "1500ms");
+    }
+}
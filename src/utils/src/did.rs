@@ -0,0 +1,61 @@
+// Utilities for checking a canister's exported Candid interface against its .did file.
+
+/// Returns the name of each method of the service of a .did file, and whether
+/// it is a query, sorted by name.
+///
+/// Type names are left out as they depend on the declaration order, so the
+/// methods of an exported interface can be compared with a hand-written .did file.
+///
+/// # Arguments
+///
+/// * `did` - The text of the .did file
+pub fn service_methods(did: &str) -> Vec<(String, bool)> {
+    let mut methods: Vec<String> = vec![];
+    for line in did
+        .lines()
+        .skip_while(|line| !line.starts_with("service"))
+        .skip(1)
+        .take_while(|line| !line.starts_with('}'))
+    {
+        // Long signatures are wrapped on more indented lines.
+        match methods.last_mut() {
+            Some(method) if line.starts_with("    ") => method.push_str(line.trim()),
+            _ => methods.push(line.to_string()),
+        }
+    }
+    let mut methods: Vec<(String, bool)> = methods
+        .iter()
+        .filter_map(|method| {
+            let (name, _) = method.split_once(':')?;
+            Some((name.trim().to_string(), method.ends_with("query;")))
+        })
+        .collect();
+    methods.sort();
+    methods
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_methods() {
+        let did = "type Args = record { start : nat };
+service : (Args) -> {
+  get_blocks : (Args) -> (vec nat) query;
+  find_block : (nat) -> (opt nat) composite_query;
+  insert_blocks : (
+      vec nat,
+    ) -> ();
+}
+";
+        assert_eq!(
+            service_methods(did),
+            vec![
+                ("find_block".to_string(), true),
+                ("get_blocks".to_string(), true),
+                ("insert_blocks".to_string(), false),
+            ]
+        );
+    }
+}
@@ -1,6 +1,8 @@
 pub mod canister;
+pub mod did;
 pub mod env;
 pub mod memory;
 pub mod principal;
 pub mod rand;
+pub mod rate;
 pub mod retry_async;
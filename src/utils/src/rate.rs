@@ -0,0 +1,268 @@
+use bity_ic_types::TimestampNanos;
+use serde::{Deserialize, Serialize};
+
+// Provides rolling rate trackers for canister metrics.
+//
+// Neither type reads the time: callers pass timestamps, in nanoseconds, from
+// `bity_ic_canister_time` or `ic_cdk::api::time`.
+
+const NANOS_PER_SECOND: f64 = 1_000_000_000.0;
+
+/// Counts events over a sliding horizon, in buckets of a fixed width.
+///
+/// The horizon is rounded up to a whole number of buckets. Events are dropped
+/// once their bucket falls out of the horizon, so the count covers between
+/// `horizon - bucket_width` and `horizon` of history.
+///
+/// # Example
+///
+/// ```
+/// use bity_ic_utils::rate::RateTracker;
+///
+/// const SECOND: u64 = 1_000_000_000;
+///
+/// let mut tracker = RateTracker::new(10 * SECOND, 60 * SECOND);
+/// tracker.record(0, 30);
+/// tracker.record(20 * SECOND, 30);
+/// assert_eq!(tracker.count(30 * SECOND), 60);
+/// assert_eq!(tracker.rate_per_sec(30 * SECOND), 1.0);
+/// assert_eq!(tracker.count(65 * SECOND), 30);
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RateTracker {
+    bucket_width_nanos: u64,
+    buckets: Vec<u64>,
+    // Number of the most recent bucket, counted in bucket widths since time 0.
+    head: u64,
+    total: u64,
+}
+
+impl RateTracker {
+    /// Creates a tracker with buckets of `bucket_width_nanos` over `horizon_nanos`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bucket_width_nanos` is zero or greater than `horizon_nanos`.
+    pub fn new(bucket_width_nanos: u64, horizon_nanos: u64) -> Self {
+        assert!(
+            bucket_width_nanos > 0 && bucket_width_nanos <= horizon_nanos,
+            "RateTracker: the bucket width must be positive and at most the horizon"
+        );
+        let bucket_count = horizon_nanos.div_ceil(bucket_width_nanos);
+        Self {
+            bucket_width_nanos,
+            buckets: vec![0; bucket_count as usize],
+            head: 0,
+            total: 0,
+        }
+    }
+
+    /// Returns the width of a bucket, in nanoseconds.
+    pub fn bucket_width_nanos(&self) -> u64 {
+        self.bucket_width_nanos
+    }
+
+    /// Returns the horizon covered by the buckets, in nanoseconds.
+    pub fn horizon_nanos(&self) -> u64 {
+        self.bucket_width_nanos * self.buckets.len() as u64
+    }
+
+    /// Records `count` events at `now`.
+    ///
+    /// Events older than the most recent record are added to their bucket, or
+    /// dropped when it is already out of the horizon.
+    pub fn record(&mut self, now: TimestampNanos, count: u64) {
+        let bucket = now / self.bucket_width_nanos;
+        if bucket > self.head {
+            self.expire(bucket);
+        } else if self.head - bucket >= self.buckets.len() as u64 {
+            return;
+        }
+        let slot = self.slot(bucket);
+        self.buckets[slot] += count;
+        self.total += count;
+    }
+
+    /// Returns the number of events recorded over the horizon ending at `now`.
+    pub fn count(&self, now: TimestampNanos) -> u64 {
+        let bucket = now / self.bucket_width_nanos;
+        let expired = bucket.saturating_sub(self.head);
+        if expired >= self.buckets.len() as u64 {
+            return 0;
+        }
+        // The buckets that `now` pushes out of the horizon are the oldest ones.
+        (1..=expired).fold(self.total, |total, i| {
+            total - self.buckets[self.slot(self.head + i)]
+        })
+    }
+
+    /// Returns the number of events per second over the horizon ending at `now`.
+    pub fn rate_per_sec(&self, now: TimestampNanos) -> f64 {
+        self.count(now) as f64 * NANOS_PER_SECOND / self.horizon_nanos() as f64
+    }
+
+    /// Moves the head to `bucket`, clearing the buckets that leave the horizon.
+    fn expire(&mut self, bucket: u64) {
+        let expired = bucket - self.head;
+        if expired >= self.buckets.len() as u64 {
+            self.buckets.iter_mut().for_each(|count| *count = 0);
+            self.total = 0;
+        } else {
+            for i in 1..=expired {
+                let slot = self.slot(self.head + i);
+                self.total -= self.buckets[slot];
+                self.buckets[slot] = 0;
+            }
+        }
+        self.head = bucket;
+    }
+
+    fn slot(&self, bucket: u64) -> usize {
+        (bucket % self.buckets.len() as u64) as usize
+    }
+}
+
+/// An exponentially weighted moving average.
+///
+/// Each update moves the average towards the new value by `alpha`:
+/// `average = alpha * value + (1 - alpha) * average`. The first update sets it.
+///
+/// # Example
+///
+/// ```
+/// use bity_ic_utils::rate::Ewma;
+///
+/// let mut ewma = Ewma::new(0.5);
+/// ewma.update(10.0, 1);
+/// ewma.update(20.0, 2);
+/// assert_eq!(ewma.value(), Some(15.0));
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Ewma {
+    alpha: f64,
+    value: Option<f64>,
+    last_update: Option<TimestampNanos>,
+}
+
+impl Ewma {
+    /// Creates an empty average with the weight `alpha` given to new values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `alpha` is not in `(0, 1]`.
+    pub fn new(alpha: f64) -> Self {
+        assert!(alpha > 0.0 && alpha <= 1.0, "Ewma: alpha must be in (0, 1]");
+        Self {
+            alpha,
+            value: None,
+            last_update: None,
+        }
+    }
+
+    /// Folds `value`, observed at `now`, into the average.
+    ///
+    /// Values observed before the last update are ignored.
+    pub fn update(&mut self, value: f64, now: TimestampNanos) {
+        if self
+            .last_update
+            .is_some_and(|last_update| now < last_update)
+        {
+            return;
+        }
+        self.value = Some(match self.value {
+            Some(average) => self.alpha * value + (1.0 - self.alpha) * average,
+            None => value,
+        });
+        self.last_update = Some(now);
+    }
+
+    /// Returns the average, or `None` before the first update.
+    pub fn value(&self) -> Option<f64> {
+        self.value
+    }
+
+    /// Returns when the average was last updated.
+    pub fn last_update(&self) -> Option<TimestampNanos> {
+        self.last_update
+    }
+
+    /// Returns the weight given to new values.
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    #[test]
+    fn test_rate_tracker_rolls_over_buckets() {
+        let mut tracker = RateTracker::new(10 * SECOND, 30 * SECOND);
+        assert_eq!(tracker.horizon_nanos(), 30 * SECOND);
+
+        tracker.record(0, 1);
+        tracker.record(5 * SECOND, 2);
+        tracker.record(10 * SECOND, 4);
+        tracker.record(25 * SECOND, 8);
+        assert_eq!(tracker.count(29 * SECOND), 15);
+        assert_eq!(tracker.rate_per_sec(29 * SECOND), 0.5);
+
+        // The bucket of [0, 10) leaves the horizon, then the one of [10, 20).
+        assert_eq!(tracker.count(30 * SECOND), 12);
+        tracker.record(41 * SECOND, 16);
+        assert_eq!(tracker.count(41 * SECOND), 24);
+
+        // A late event lands in its bucket while it is in the horizon.
+        tracker.record(22 * SECOND, 32);
+        assert_eq!(tracker.count(41 * SECOND), 56);
+        tracker.record(5 * SECOND, 64);
+        assert_eq!(tracker.count(41 * SECOND), 56);
+    }
+
+    #[test]
+    fn test_rate_tracker_expires_after_the_horizon() {
+        let mut tracker = RateTracker::new(10 * SECOND, 25 * SECOND);
+        assert_eq!(tracker.horizon_nanos(), 30 * SECOND);
+
+        tracker.record(0, 3);
+        tracker.record(20 * SECOND, 5);
+        assert_eq!(tracker.count(29 * SECOND), 8);
+        assert_eq!(tracker.count(59 * SECOND), 0);
+        assert_eq!(tracker.count(1_000 * SECOND), 0);
+
+        tracker.record(1_000 * SECOND, 7);
+        assert_eq!(tracker.count(1_000 * SECOND), 7);
+        assert_eq!(tracker.count(1_029 * SECOND), 7);
+        assert_eq!(tracker.count(1_030 * SECOND), 0);
+    }
+
+    #[test]
+    fn test_ewma_converges() {
+        let mut ewma = Ewma::new(0.25);
+        assert_eq!(ewma.value(), None);
+
+        ewma.update(8.0, 1);
+        assert_eq!(ewma.value(), Some(8.0));
+        ewma.update(0.0, 2);
+        assert_eq!(ewma.value(), Some(6.0));
+        ewma.update(0.0, 3);
+        assert_eq!(ewma.value(), Some(4.5));
+        ewma.update(12.0, 4);
+        assert_eq!(ewma.value(), Some(6.375));
+
+        // A stale value is ignored.
+        ewma.update(100.0, 3);
+        assert_eq!(ewma.value(), Some(6.375));
+        assert_eq!(ewma.last_update(), Some(4));
+
+        // A constant input is approached geometrically: the gap shrinks by 3/4.
+        for t in 5..105 {
+            ewma.update(2.0, t);
+        }
+        let gap = (6.375 - 2.0) * 0.75f64.powi(100);
+        assert!((ewma.value().unwrap() - 2.0 - gap).abs() < 1e-12);
+    }
+}